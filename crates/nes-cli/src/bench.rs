@@ -0,0 +1,62 @@
+//! `bench` subcommand - headless performance benchmark
+//!
+//! Runs a fixed number of frames from power-on twice: once untimed to measure
+//! raw throughput (frames/s and CPU cycles/s), then again with
+//! `nes_core::metrics` enabled to break the time down per subsystem. Per-step
+//! timing adds overhead, so the breakdown pass is slower than the first.
+
+use clap::Args;
+use nes_core::system::NesSystem;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Arguments for the `bench` subcommand
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Path to the iNES ROM file
+    #[arg(short, long)]
+    rom: PathBuf,
+
+    /// Number of frames in the workload
+    #[arg(short, long, default_value = "3000")]
+    frames: u64,
+}
+
+/// Run the `bench` subcommand
+pub fn run(args: &BenchArgs) {
+    let rom_data = crate::read_rom(&args.rom);
+
+    let mut system = crate::load_system(&rom_data);
+    let elapsed = run_workload(&mut system, args.frames);
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+
+    println!("nes-cli {} benchmark: {}", env!("CARGO_PKG_VERSION"), args.rom.display());
+    println!("{:<16}{}", "Frames:", args.frames);
+    println!("{:<16}{:.3} s", "Time:", secs);
+    println!("{:<16}{:.1}", "Frames/sec:", args.frames as f64 / secs);
+    println!("{:<16}{:.0}", "CPU cycles/sec:", system.cpu_cycles() as f64 / secs);
+    println!("{:<16}{:.2}x realtime (60 fps)", "Speed:", args.frames as f64 / secs / 60.0);
+
+    let mut system = crate::load_system(&rom_data);
+    system.set_metrics_enabled(true);
+    let timed = run_workload(&mut system, args.frames);
+    let Some(metrics) = system.metrics() else {
+        return;
+    };
+    println!("\nSubsystem breakdown (timed pass, {:.3} s):", timed.as_secs_f64());
+    for (subsystem, time, share) in metrics.breakdown() {
+        println!("  {:<7} {:>9.3} ms  {:>5.1}%", subsystem.name(), time.as_secs_f64() * 1000.0, share * 100.0);
+    }
+    let other = timed.saturating_sub(metrics.total());
+    println!("  {:<7} {:>9.3} ms  (frame bookkeeping and timer overhead)", "Other", other.as_secs_f64() * 1000.0);
+}
+
+/// Run the frames, exiting on emulation errors, and return the elapsed wall time
+fn run_workload(system: &mut NesSystem, frames: u64) -> Duration {
+    let start = Instant::now();
+    if let Err(e) = system.run_frames(frames) {
+        eprintln!("Error running system: {}", e);
+        std::process::exit(1);
+    }
+    start.elapsed()
+}
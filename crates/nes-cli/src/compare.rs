@@ -0,0 +1,184 @@
+//! `compare` subcommand - render frames and diff them against baseline PNGs
+//!
+//! Baselines are stored as `frame_NNNNN.png` (1-based frame number). Mismatching
+//! frames produce `actual_NNNNN.png` and `diff_NNNNN.png` in the output directory,
+//! together with a `report.txt` summary suitable for attaching to bug reports.
+
+use clap::Args;
+use nes_core::ppu::{FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::png_io;
+
+/// Arguments for the `compare` subcommand
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Path to the iNES ROM file
+    #[arg(short, long)]
+    rom: PathBuf,
+
+    /// Number of frames to run
+    #[arg(short, long, default_value = "600")]
+    frames: u64,
+
+    /// Directory containing baseline frame PNGs
+    #[arg(short, long)]
+    baseline: PathBuf,
+
+    /// Only compare every Nth frame
+    #[arg(long, default_value = "1")]
+    every: u64,
+
+    /// Directory for the report and difference images
+    #[arg(short, long, default_value = "compare-output")]
+    output: PathBuf,
+
+    /// Write the rendered frames as the new baseline instead of comparing
+    #[arg(long)]
+    update: bool,
+}
+
+/// Result of comparing one frame
+enum FrameResult {
+    Match,
+    Mismatch { pixels: usize },
+    MissingBaseline,
+    Error(String),
+}
+
+/// Run the `compare` subcommand
+pub fn run(args: &CompareArgs) {
+    let rom_data = crate::read_rom(&args.rom);
+    let mut system = crate::load_system(&rom_data);
+    let every = args.every.max(1);
+
+    let out_dir = if args.update { &args.baseline } else { &args.output };
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        eprintln!("Failed to create {}: {}", out_dir.display(), e);
+        std::process::exit(1);
+    }
+
+    let mut framebuffer = vec![0u8; FRAME_RGB_SIZE];
+    let mut results = Vec::new();
+
+    for frame in 1..=args.frames {
+        if let Err(e) = system.run_frames(1) {
+            eprintln!("Error running system at frame {}: {}", frame, e);
+            std::process::exit(1);
+        }
+        if frame % every != 0 {
+            continue;
+        }
+        system.ppu().render_frame(&mut framebuffer);
+
+        let baseline_path = args.baseline.join(frame_name("frame", frame));
+        if args.update {
+            if let Err(e) = png_io::write_rgb(&baseline_path, FRAME_WIDTH, FRAME_HEIGHT, &framebuffer) {
+                eprintln!("Failed to write baseline: {}", e);
+                std::process::exit(1);
+            }
+            continue;
+        }
+
+        let result = compare_frame(&baseline_path, &framebuffer, &args.output, frame);
+        results.push((frame, result));
+    }
+
+    if args.update {
+        println!("Wrote baseline frames to {}", args.baseline.display());
+        return;
+    }
+
+    let report = build_report(args, &results);
+    let report_path = args.output.join("report.txt");
+    if let Err(e) = fs::write(&report_path, &report) {
+        eprintln!("Failed to write {}: {}", report_path.display(), e);
+        std::process::exit(1);
+    }
+    print!("{}", report);
+
+    let failed = results.iter().any(|(_, r)| !matches!(r, FrameResult::Match));
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Compare a rendered frame against its baseline, writing artifacts on mismatch
+fn compare_frame(baseline_path: &Path, actual: &[u8], out_dir: &Path, frame: u64) -> FrameResult {
+    if !baseline_path.exists() {
+        return FrameResult::MissingBaseline;
+    }
+    let (width, height, expected) = match png_io::read_rgb(baseline_path) {
+        Ok(image) => image,
+        Err(e) => return FrameResult::Error(e),
+    };
+    if width != FRAME_WIDTH || height != FRAME_HEIGHT {
+        return FrameResult::Error(format!("baseline is {}x{}, expected {}x{}", width, height, FRAME_WIDTH, FRAME_HEIGHT));
+    }
+
+    let (diff, pixels) = diff_image(&expected, actual);
+    if pixels == 0 {
+        return FrameResult::Match;
+    }
+
+    let actual_path = out_dir.join(frame_name("actual", frame));
+    let diff_path = out_dir.join(frame_name("diff", frame));
+    if let Err(e) = png_io::write_rgb(&actual_path, FRAME_WIDTH, FRAME_HEIGHT, actual)
+        .and_then(|_| png_io::write_rgb(&diff_path, FRAME_WIDTH, FRAME_HEIGHT, &diff))
+    {
+        return FrameResult::Error(e);
+    }
+    FrameResult::Mismatch { pixels }
+}
+
+/// Build a difference image: mismatching pixels in red over a dimmed copy of the expected frame
+fn diff_image(expected: &[u8], actual: &[u8]) -> (Vec<u8>, usize) {
+    let mut diff = Vec::with_capacity(expected.len());
+    let mut pixels = 0;
+    for (e, a) in expected.chunks_exact(3).zip(actual.chunks_exact(3)) {
+        if e == a {
+            diff.extend(e.iter().map(|&c| c / 4));
+        } else {
+            pixels += 1;
+            diff.extend_from_slice(&[0xFF, 0x00, 0x00]);
+        }
+    }
+    (diff, pixels)
+}
+
+fn frame_name(prefix: &str, frame: u64) -> String {
+    format!("{}_{:05}.png", prefix, frame)
+}
+
+fn build_report(args: &CompareArgs, results: &[(u64, FrameResult)]) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "ROM: {}", args.rom.display());
+    let _ = writeln!(report, "Baseline: {}", args.baseline.display());
+    let _ = writeln!(report, "Frames run: {} (compared every {})", args.frames, args.every.max(1));
+
+    let mut matched = 0;
+    let mut failures = String::new();
+    for (frame, result) in results {
+        match result {
+            FrameResult::Match => matched += 1,
+            FrameResult::Mismatch { pixels } => {
+                let _ = writeln!(failures, "  frame {}: {} pixels differ ({})", frame, pixels, frame_name("diff", *frame));
+            }
+            FrameResult::MissingBaseline => {
+                let _ = writeln!(failures, "  frame {}: missing baseline", frame);
+            }
+            FrameResult::Error(e) => {
+                let _ = writeln!(failures, "  frame {}: error: {}", frame, e);
+            }
+        }
+    }
+
+    let _ = writeln!(report, "Matched: {}/{}", matched, results.len());
+    if !failures.is_empty() {
+        let _ = writeln!(report, "Mismatches:");
+        report.push_str(&failures);
+    }
+    report
+}
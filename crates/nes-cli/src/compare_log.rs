@@ -0,0 +1,50 @@
+//! `compare-log` subcommand - run a ROM against a reference emulator trace
+//!
+//! The log is a nestest.log-style trace (one line per instruction with the
+//! registers, PPU line/dot and CPU cycle count before it ran). The CPU starts
+//! in the state of the first line, then every line is checked; the first one
+//! that differs is printed with the lines before it, and the process exits
+//! with status 1.
+
+use clap::Args;
+use nes_core::reference_log::{compare_log, parse_log, start_from};
+use std::fs;
+use std::path::PathBuf;
+
+/// Arguments for the `compare-log` subcommand
+#[derive(Args, Debug)]
+pub struct CompareLogArgs {
+    /// Path to the iNES ROM file
+    #[arg(short, long)]
+    rom: PathBuf,
+
+    /// Reference trace in nestest.log format
+    #[arg(short, long)]
+    log: PathBuf,
+}
+
+/// Run the `compare-log` subcommand
+pub fn run(args: &CompareLogArgs) {
+    let text = match fs::read_to_string(&args.log) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read log {}: {}", args.log.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let entries = parse_log(&text);
+    let Some(first) = entries.first() else {
+        eprintln!("No trace lines found in {}", args.log.display());
+        std::process::exit(1);
+    };
+
+    let rom_data = crate::read_rom(&args.rom);
+    let mut system = crate::load_system(&rom_data);
+    start_from(&mut system, first);
+
+    let comparison = compare_log(&mut system, &entries);
+    println!("{}", comparison);
+    if !comparison.passed() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,88 @@
+//! Crash reporting - run frames defensively and export a diagnostic bundle
+//!
+//! When emulation fails (a CPU error such as an unknown opcode, or a panic inside
+//! the core), `write_bundle` zips up everything needed to reproduce the problem:
+//! - `report.txt`: error, ROM CRC32, frame, CPU and PPU registers
+//! - `config.txt`: the command line options used
+//! - `trace.txt`: the most recently executed instructions, oldest first
+//! - `ram.bin`, `vram.bin`, `palette.bin`, `oam.bin`: memory contents
+
+use nes_core::system::NesSystem;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Run one frame, turning both CPU errors and panics into an error message
+pub fn run_frame_guarded(system: &mut NesSystem) -> Result<(), String> {
+    run_guarded(system, |system| system.run_frames(1))
+}
+
+/// Run emulation with `run`, turning both CPU errors and panics into an error message
+pub fn run_guarded<T>(
+    system: &mut NesSystem,
+    run: impl FnOnce(&mut NesSystem) -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, String> {
+    match panic::catch_unwind(AssertUnwindSafe(|| run(system))) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(format!("internal panic: {}", message))
+        }
+    }
+}
+
+/// Write a zipped diagnostic bundle describing the system at the time of the error
+pub fn write_bundle(path: &Path, system: &NesSystem, error: &str, config: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+
+    let report = build_report(system, error);
+    let mut trace = Vec::new();
+    system.trace().dump(&mut trace).map_err(|e| e.to_string())?;
+    let files: [(&str, &[u8]); 7] = [
+        ("report.txt", report.as_bytes()),
+        ("config.txt", config.as_bytes()),
+        ("trace.txt", &trace),
+        ("ram.bin", system.ram()),
+        ("vram.bin", system.ppu().vram()),
+        ("palette.bin", system.ppu().palette_ram()),
+        ("oam.bin", system.ppu().oam()),
+    ];
+    for (name, data) in files {
+        zip.start_file(name, options)
+            .and_then(|_| zip.write_all(data).map_err(Into::into))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    zip.finish().map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(())
+}
+
+fn build_report(system: &NesSystem, error: &str) -> String {
+    let regs = system.cpu_registers();
+    let mut report = String::new();
+    let _ = writeln!(report, "nes-cli {} crash report", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "Error: {}", error);
+    let crc = system.rom_crc32().map_or("(none)".to_string(), |crc| format!("{:08X}", crc));
+    let _ = writeln!(report, "ROM CRC32: {}", crc);
+    let _ = writeln!(report, "Frame: {}", system.frame_count());
+    let _ = writeln!(report, "CPU cycles: {}", system.cpu_cycles());
+    let _ = writeln!(
+        report,
+        "CPU: PC=${:04X} A=${:02X} X=${:02X} Y=${:02X} P=${:02X} SP=${:02X}",
+        regs.pc, regs.a, regs.x, regs.y, regs.p, regs.sp
+    );
+    let _ = writeln!(report, "PPU: scanline={} dot={}", system.ppu().scanline(), system.ppu().dot());
+    let _ = writeln!(report, "Accuracy: {} (PPU alignment {})", system.accuracy(), system.ppu_alignment());
+    let _ = writeln!(report, "State hash: {:016x}", system.state_hash());
+    report
+}
@@ -0,0 +1,87 @@
+//! `library` subcommand - list a ROM directory with playtime and notes
+//!
+//! ROMs are found with `nes_core::loader::scan_roms` and matched to the play
+//! statistics store (see `nes_core::playstats`) by CRC32, so renamed files
+//! keep their history. `--notes` with `--rom` replaces one game's notes.
+
+use clap::Args;
+use nes_core::cartridge::Cartridge;
+use nes_core::loader::scan_roms;
+use nes_core::playstats::{self, PlayStats};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Arguments for the `library` subcommand
+#[derive(Args, Debug)]
+pub struct LibraryArgs {
+    /// Directory to scan for .nes files
+    #[arg(required_unless_present = "rom")]
+    dir: Option<PathBuf>,
+
+    /// Play statistics file (defaults to playstats.txt in the config directory)
+    #[arg(long, value_name = "FILE")]
+    stats: Option<PathBuf>,
+
+    /// ROM whose notes `--notes` replaces
+    #[arg(long, requires = "notes")]
+    rom: Option<PathBuf>,
+
+    /// New notes for `--rom` (an empty string clears them)
+    #[arg(long, value_name = "TEXT", requires = "rom")]
+    notes: Option<String>,
+}
+
+/// Run the `library` subcommand
+pub fn run(args: &LibraryArgs) {
+    let Some(path) = args.stats.clone().or_else(playstats::default_path) else {
+        eprintln!("No config directory found; pass --stats FILE");
+        std::process::exit(1);
+    };
+    let mut stats = match PlayStats::load(&path) {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let (Some(rom), Some(notes)) = (&args.rom, &args.notes) {
+        let Some(crc32) = rom_crc32(&crate::read_rom(rom)) else {
+            eprintln!("Failed to load cartridge: {}", rom.display());
+            std::process::exit(1);
+        };
+        stats.set_notes(crc32, notes.replace("\\n", "\n"));
+        if let Err(e) = stats.save(&path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Notes saved for {}", rom.display());
+    }
+
+    if let Some(dir) = &args.dir {
+        list(dir, &stats);
+    }
+}
+
+/// Print every ROM under `dir` with its playtime and notes
+fn list(dir: &Path, stats: &PlayStats) {
+    let mut entries = scan_roms(dir).wait();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    for entry in entries {
+        let name = entry.path.strip_prefix(dir).unwrap_or(&entry.path).display().to_string();
+        let game = fs::read(&entry.path).ok().and_then(|data| rom_crc32(&data)).and_then(|crc| stats.get(crc));
+        let Some(game) = game else {
+            println!("{:<40} {:>9}", name, "-");
+            continue;
+        };
+        println!("{:<40} {:>9}", name, playstats::format_playtime(game.playtime));
+        for line in game.notes.lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+/// CRC32 the play statistics are keyed by, if the file is a valid ROM
+fn rom_crc32(data: &[u8]) -> Option<u32> {
+    Cartridge::from_rom(data).ok().map(|cart| cart.crc32())
+}
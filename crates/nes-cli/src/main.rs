@@ -1,19 +1,51 @@
 //! NES CLI - Command line interface for NES emulator
 
-use clap::Parser;
+mod bench;
+mod compare;
+mod compare_log;
+mod crash;
+mod library;
+mod png_io;
+#[cfg(feature = "remote")]
+mod remote;
+mod state_diff;
+mod telemetry;
+#[cfg(feature = "tui")]
+mod tui;
+mod verify_hashes;
+mod verify_movie;
+
+use clap::{Parser, Subcommand};
+use nes_core::accuracy::{AccuracyProfile, PpuAlignment};
+use nes_core::achievements::AchievementSet;
 use nes_core::cartridge::Cartridge;
+use nes_core::frame_hashes::FrameHashes;
+use nes_core::gif::GifRecorder;
+use nes_core::idle::IdleDetector;
+use nes_core::input_schedule::InputSchedule;
+use nes_core::mappers::{self, SupportLevel};
+use nes_core::region::Region;
+use nes_core::sink::VideoSink;
 use nes_core::system::NesSystem;
+use nes_core::trace::DEFAULT_TRACE_CAPACITY;
+use nes_core::StatusFlags;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use png_io::PngSequenceSink;
+use telemetry::TelemetryWriter;
 
 /// NES Emulator CLI
 #[derive(Parser, Debug)]
 #[command(name = "nes-cli")]
 #[command(about = "A NES emulator CLI", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the iNES ROM file
-    #[arg(short, long)]
-    rom: PathBuf,
+    #[arg(short, long, required = true)]
+    rom: Option<PathBuf>,
 
     /// Number of frames to run
     #[arg(short, long, default_value = "60")]
@@ -26,19 +58,243 @@ struct Args {
     /// Dump PPU state after execution
     #[arg(short = 'p', long)]
     dump_ppu: bool,
+
+    /// Print the most recently executed instructions after execution
+    #[arg(long)]
+    dump_trace: bool,
+
+    /// Number of instructions kept in the trace ring (0 disables tracing)
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TRACE_CAPACITY)]
+    trace_size: usize,
+
+    /// Start executing here after reset instead of at the reset vector (hex, e.g. C000 for nestest automation)
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    entry_pc: Option<u16>,
+
+    /// Stop as soon as the CPU reaches this address (hex); exits with status 1 if it never does
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    stop_pc: Option<u16>,
+
+    /// Accuracy profile: 'compatible' or 'accurate' (adds the PPU warm-up period)
+    #[arg(long, value_name = "PROFILE", default_value = "compatible", value_parser = parse_accuracy)]
+    accuracy: AccuracyProfile,
+
+    /// CPU/PPU alignment at power-on: 0-2 (PPU dots ahead) or 'random' (the chosen value is printed)
+    #[arg(long, value_name = "ALIGNMENT", default_value = "0", value_parser = parse_alignment)]
+    ppu_alignment: PpuAlignment,
+
+    /// Console region: 'ntsc', 'pal' or 'dendy' (default: the one a NES 2.0 header names, else NTSC)
+    #[arg(long, value_name = "REGION", value_parser = parse_region)]
+    region: Option<Region>,
+
+    /// Seed for random features such as random alignment (chosen at random and printed otherwise)
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Write per-frame JSON telemetry to this file ('-' for stdout, which silences other output)
+    #[arg(long, value_name = "PATH")]
+    telemetry: Option<PathBuf>,
+
+    /// Play back a JSON or CSV input schedule (frame, buttons[, hold, port])
+    #[arg(long, value_name = "PATH")]
+    inputs: Option<PathBuf>,
+
+    /// Evaluate an offline achievement pack and print unlocks
+    #[arg(long, value_name = "PATH")]
+    achievements: Option<PathBuf>,
+
+    /// Record the run as an animated GIF
+    #[arg(long, value_name = "PATH")]
+    gif: Option<PathBuf>,
+
+    /// GIF scale factor (1 or 2, nearest neighbour)
+    #[arg(long, value_name = "N", default_value = "1", value_parser = clap::value_parser!(u8).range(1..=2))]
+    gif_scale: u8,
+
+    /// Write every frame as a numbered PNG into this directory
+    #[arg(long, value_name = "DIR")]
+    png_frames: Option<PathBuf>,
+
+    /// Write each frame's input and state hash here, so the GIF or PNG frames can be checked and re-rendered with verify-hashes
+    #[arg(long, value_name = "PATH")]
+    frame_hashes: Option<PathBuf>,
+
+    /// Write a zipped diagnostic bundle here if emulation crashes
+    #[arg(long, value_name = "PATH")]
+    crash_bundle: Option<PathBuf>,
+
+    /// Stop early once the game state (RAM, VRAM, palette, OAM) is unchanged for this many frames
+    #[arg(long, value_name = "FRAMES")]
+    stop_when_idle: Option<u32>,
+
+    /// Start from a savestate (written by --save-state for the same ROM) instead of power-on
+    #[arg(long, value_name = "PATH")]
+    load_state: Option<PathBuf>,
+
+    /// Write a savestate here when the run ends
+    #[arg(long, value_name = "PATH")]
+    save_state: Option<PathBuf>,
+
+    /// Don't load or write battery-backed save data (`<rom>.sav`)
+    #[arg(long)]
+    no_sav: bool,
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render frames and compare them against baseline PNGs
+    Compare(compare::CompareArgs),
+    /// Run a ROM against a reference trace log (nestest.log format) and report the first divergence
+    CompareLog(compare_log::CompareLogArgs),
+    /// Measure emulation speed with a per-subsystem time breakdown
+    Bench(bench::BenchArgs),
+    /// Play an FM2 movie headless and verify the final state
+    VerifyMovie(verify_movie::VerifyMovieArgs),
+    /// Replay a frame hash sidecar, checking every frame and optionally rendering it again
+    VerifyHashes(verify_hashes::VerifyHashesArgs),
+    /// List ROMs with their playtime and notes, or edit a game's notes
+    Library(library::LibraryArgs),
+    /// Compare the system state at two frames of a run, field by field
+    StateDiff(state_diff::StateDiffArgs),
+    /// Stream the emulator to browsers over WebSocket (remote viewing)
+    #[cfg(feature = "remote")]
+    Serve(remote::ServeArgs),
+    /// Pick a ROM and play it in the terminal, with live CPU/PPU stats
+    #[cfg(feature = "tui")]
+    Tui(tui::TuiArgs),
 }
 
 fn main() {
     let args = Args::parse();
 
-    // Load ROM file
-    let rom_data = match fs::read(&args.rom) {
+    match args.command {
+        Some(Command::Compare(compare_args)) => compare::run(&compare_args),
+        Some(Command::CompareLog(compare_log_args)) => compare_log::run(&compare_log_args),
+        Some(Command::Bench(bench_args)) => bench::run(&bench_args),
+        Some(Command::VerifyMovie(verify_args)) => verify_movie::run(&verify_args),
+        Some(Command::VerifyHashes(verify_args)) => verify_hashes::run(&verify_args),
+        Some(Command::Library(library_args)) => library::run(&library_args),
+        Some(Command::StateDiff(diff_args)) => state_diff::run(&diff_args),
+        #[cfg(feature = "remote")]
+        Some(Command::Serve(serve_args)) => remote::run(&serve_args),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui(tui_args)) => tui::run(&tui_args),
+        None => run(&args),
+    }
+}
+
+/// Read a ROM file, exiting with an error message on failure
+fn read_rom(path: &Path) -> Vec<u8> {
+    match fs::read(path) {
         Ok(data) => data,
         Err(e) => {
             eprintln!("Failed to read ROM file: {}", e);
             std::process::exit(1);
         }
+    }
+}
+
+/// Restore a savestate file, exiting with an error message on failure
+fn load_state(system: &mut NesSystem, path: &Path) {
+    let result = fs::read(path).map_err(|e| e.to_string()).and_then(|data| system.load_state(&data).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("Failed to load state {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// Restore battery-backed save data from `<rom>.sav` if the board has any
+fn load_battery(system: &mut NesSystem, rom: &Path, verbose: bool) {
+    if system.battery_data().is_none() {
+        return;
+    }
+    let path = rom.with_extension("sav");
+    match fs::read(&path) {
+        Ok(data) => {
+            system.load_battery_data(&data);
+            if verbose {
+                println!("Loaded save data from {}", path.display());
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to read save data {}: {}", path.display(), e),
+    }
+}
+
+/// Write battery-backed save data to `<rom>.sav` if the run changed it
+fn save_battery(system: &mut NesSystem, rom: &Path, verbose: bool) {
+    if !system.take_battery_dirty() {
+        return;
+    }
+    let Some(data) = system.battery_data() else {
+        return;
     };
+    let path = rom.with_extension("sav");
+    match fs::write(&path, data) {
+        Ok(()) if verbose => println!("Saved save data to {}", path.display()),
+        Ok(()) => {}
+        Err(e) => eprintln!("Failed to write save data {}: {}", path.display(), e),
+    }
+}
+
+/// Read an input schedule, exiting with an error message on failure
+fn read_inputs(path: &Path) -> InputSchedule {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read inputs {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    match InputSchedule::parse(&text) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            eprintln!("Failed to parse inputs {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read an achievement pack, exiting with an error message on failure
+fn read_achievements(path: &Path) -> AchievementSet {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read achievements {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    match AchievementSet::parse(&text) {
+        Ok(pack) => pack,
+        Err(e) => {
+            eprintln!("Failed to parse achievements {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Create a system with the given ROM loaded and reset, exiting on failure
+fn load_system(rom_data: &[u8]) -> NesSystem {
+    let mut system = NesSystem::new();
+    if let Err(e) = system.load_rom(rom_data) {
+        eprintln!("Failed to load ROM: {}", e);
+        std::process::exit(1);
+    }
+    system.reset();
+    system
+}
+
+fn run(args: &Args) {
+    let rom_path = args.rom.as_deref().expect("--rom is required without a subcommand");
+
+    // Telemetry on stdout must not be mixed with the human readable output
+    let verbose = !args.telemetry.as_deref().is_some_and(TelemetryWriter::is_stdout);
+    let mut telemetry = args.telemetry.as_deref().map(open_telemetry);
+
+    // Load ROM file
+    let rom_data = read_rom(rom_path);
+    let inputs = args.inputs.as_deref().map(read_inputs);
 
     // Load iNES cartridge
     let cartridge = match Cartridge::from_rom(&rom_data) {
@@ -49,43 +305,261 @@ fn main() {
         }
     };
 
-    println!("Loaded cartridge:");
-    println!("  PRG ROM: {} bytes", cartridge.prg_rom().len());
-    println!("  CHR ROM: {} bytes", cartridge.chr_rom().len());
-    println!("  Mapper: {:?}", cartridge.mapper());
+    let mapper = mappers::lookup(cartridge.header().mapper_number());
+    if verbose {
+        println!("Loaded cartridge:");
+        println!("  PRG ROM: {} bytes", cartridge.prg_rom().len());
+        println!("  CHR ROM: {} bytes", cartridge.chr_rom().len());
+        println!("  Mapper: {}", mapper);
+    }
+    if mapper.level != SupportLevel::Full {
+        eprintln!("Warning: {}", mapper);
+    }
 
     // Create and initialize system
-    let mut system = NesSystem::new();
-    if let Err(e) = system.load_rom(&rom_data) {
-        eprintln!("Failed to load ROM: {}", e);
-        std::process::exit(1);
+    let mut system = load_system(&rom_data);
+    system.set_trace_capacity(args.trace_size);
+    if let Some(region) = args.region {
+        system.set_region(region);
+    }
+    if let Some(seed) = args.seed {
+        system.set_rng_seed(seed);
+    }
+    // Power-cycle so the profile's power-on behaviour and the seed apply from the start
+    system.set_accuracy(AccuracyProfile {
+        ppu_alignment: args.ppu_alignment,
+        ..args.accuracy
+    });
+    system.power_cycle();
+    if !args.no_sav {
+        load_battery(&mut system, rom_path, verbose);
+    }
+    if let Some(pc) = args.entry_pc {
+        system.set_pc(pc);
+    }
+    if let Some(path) = &args.load_state {
+        load_state(&mut system, path);
+        if verbose {
+            println!("Loaded state from {} (frame {})", path.display(), system.frame_count());
+        }
+    }
+    for diagnostic in system.take_crash_diagnostics() {
+        eprintln!("Warning: {}", diagnostic);
+    }
+    system.set_achievements(args.achievements.as_deref().map(read_achievements));
+    system.set_idle_detector(args.stop_when_idle.map(IdleDetector::new));
+    if let Some(path) = &args.gif {
+        let max_frames = u32::try_from(args.frames).unwrap_or(u32::MAX);
+        system.start_gif_recording_with(GifRecorder::new(path, max_frames).with_scale(args.gif_scale as usize));
     }
-    system.reset();
 
-    println!("\nRunning {} frames...", args.frames);
+    let mut frame_hashes = args.frame_hashes.as_ref().map(|_| FrameHashes::begin(&system));
+    let mut png_frames = args.png_frames.as_deref().map(|dir| {
+        PngSequenceSink::new(dir).unwrap_or_else(|e| {
+            eprintln!("Failed to create frame directory {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    if verbose {
+        println!("PPU alignment: {} (reproduce with --ppu-alignment {})", args.ppu_alignment, system.ppu_alignment());
+        println!("RNG seed: {} (reproduce with --seed {})", system.rng_seed(), system.rng_seed());
+        println!("Region: {}", system.region());
+        println!("\nRunning {} frames...", args.frames);
+    }
 
     // Run for specified frames
-    if let Err(e) = system.run_frames(args.frames) {
-        eprintln!("Error running system: {}", e);
+    let mut stopped = false;
+    for _ in 0..args.frames {
+        if let Some(inputs) = &inputs {
+            inputs.apply(&mut system);
+        }
+        let result = match args.stop_pc {
+            Some(stop_pc) => crash::run_guarded(&mut system, |s| s.run_frame_until(|s| s.cpu_registers().pc == stop_pc)),
+            None => crash::run_frame_guarded(&mut system).map(|()| false),
+        };
+        match result {
+            Ok(true) => {
+                stopped = true;
+                break;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("Error running system: {}", e);
+                flush_telemetry(&mut telemetry);
+                report_crash(args, &system, &e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            write_telemetry(telemetry, &system);
+        }
+        if let Some(sink) = png_frames.as_mut() {
+            sink.on_frame(&system.ppu_mut().snapshot_frame());
+        }
+        if let Some(frame_hashes) = frame_hashes.as_mut() {
+            frame_hashes.record(&system);
+        }
+        for diagnostic in system.take_crash_diagnostics() {
+            eprintln!("Warning: {}", diagnostic);
+        }
+        for title in system.take_unlocked_achievements() {
+            if verbose {
+                println!("Frame {}: achievement unlocked: {}", system.frame_count(), title);
+            }
+        }
+        if system.is_idle() {
+            break;
+        }
+    }
+    flush_telemetry(&mut telemetry);
+    save_gif(&mut system, verbose);
+    if let (Some(frame_hashes), Some(path)) = (frame_hashes, &args.frame_hashes) {
+        match frame_hashes.save(path) {
+            Ok(()) if verbose => println!("Frame hashes written to {}", path.display()),
+            Ok(()) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    if let (Some(sink), Some(dir)) = (png_frames, &args.png_frames) {
+        match sink.finish() {
+            Ok(count) if verbose => println!("Wrote {} frames to {}", count, dir.display()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to write frames: {}", e),
+        }
+    }
+    if let Some(path) = &args.save_state {
+        match fs::write(path, system.save_state()) {
+            Ok(()) if verbose => println!("State written to {}", path.display()),
+            Ok(()) => {}
+            Err(e) => eprintln!("Failed to write state {}: {}", path.display(), e),
+        }
+    }
+    if !args.no_sav {
+        save_battery(&mut system, rom_path, verbose);
+    }
+    let missed_stop = args.stop_pc.is_some() && !stopped;
+
+    if verbose {
+        report(args, &system, stopped);
+    }
+    if missed_stop {
         std::process::exit(1);
     }
+}
 
+/// Print the run summary and any requested dumps
+fn report(args: &Args, system: &NesSystem, stopped: bool) {
+    if let Some(stop_pc) = args.stop_pc {
+        if stopped {
+            println!("Reached ${:04X} in frame {} after {} CPU cycles.", stop_pc, system.frame_count() + 1, system.cpu_cycles());
+        } else {
+            println!("Did not reach ${:04X} within {} frames.", stop_pc, args.frames);
+        }
+    }
+    if let Some(idle) = system.idle_detector().filter(|idle| idle.is_idle()) {
+        println!("Stopped early: game state unchanged for {} frames.", idle.unchanged_frames());
+    }
     println!("Completed {} frames.", system.frame_count());
+    println!("Input polls in last frame: {}", system.input_polls_last_frame());
+    if let Some(pack) = system.achievements() {
+        println!("Achievements unlocked: {}/{}", pack.unlocked_count(), pack.achievements().len());
+    }
 
     // Dump state if requested
     if args.dump_cpu {
-        dump_cpu_state(&system);
+        dump_cpu_state(system);
     }
 
     if args.dump_ppu {
-        dump_ppu_state(&system);
+        dump_ppu_state(system);
+    }
+
+    if args.dump_trace {
+        dump_trace(system);
+    }
+}
+
+fn parse_accuracy(name: &str) -> Result<AccuracyProfile, String> {
+    AccuracyProfile::from_name(name)
+        .ok_or_else(|| format!("unknown profile '{}' (expected one of: {})", name, AccuracyProfile::NAMES.join(", ")))
+}
+
+fn parse_region(name: &str) -> Result<Region, String> {
+    Region::from_name(name).ok_or_else(|| format!("unknown region '{}' (expected one of: {})", name, Region::NAMES.join(", ")))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|e| format!("invalid address '{}': {}", text, e))
+}
+
+fn parse_alignment(text: &str) -> Result<PpuAlignment, String> {
+    PpuAlignment::parse(text).ok_or_else(|| format!("invalid alignment '{}' (expected 0-2 or 'random')", text))
+}
+
+/// Export a crash bundle if requested, or explain how to get one
+fn report_crash(args: &Args, system: &NesSystem, error: &str) {
+    let Some(path) = &args.crash_bundle else {
+        eprintln!("Re-run with --crash-bundle <PATH> to export a diagnostic bundle for bug reports.");
+        return;
+    };
+    match crash::write_bundle(path, system, error, &format!("{:#?}\n", args)) {
+        Ok(()) => eprintln!("Diagnostic bundle written to {}", path.display()),
+        Err(e) => eprintln!("Failed to write diagnostic bundle: {}", e),
+    }
+}
+
+/// Write the GIF recording (if any), exiting on failure
+fn save_gif(system: &mut NesSystem, verbose: bool) {
+    match system.stop_gif_recording() {
+        Some(Ok(path)) if verbose => println!("GIF written to {}", path.display()),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        _ => {}
+    }
+}
+
+/// Open a telemetry stream, exiting on failure
+fn open_telemetry(path: &Path) -> TelemetryWriter {
+    match TelemetryWriter::open(path) {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Failed to open telemetry output {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Flush a telemetry stream (if any), exiting on failure
+fn flush_telemetry(telemetry: &mut Option<TelemetryWriter>) {
+    if let Some(Err(e)) = telemetry.as_mut().map(|t| t.flush()) {
+        eprintln!("Failed to write telemetry: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Write one telemetry record, exiting on failure
+fn write_telemetry(telemetry: &mut TelemetryWriter, system: &NesSystem) {
+    if let Err(e) = telemetry.write_frame(system) {
+        eprintln!("Failed to write telemetry: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn dump_trace(system: &NesSystem) {
+    println!("\nLast {} instructions:", system.trace().len());
+    let stdout = std::io::stdout();
+    if let Err(e) = system.trace().dump(&mut stdout.lock()) {
+        eprintln!("Failed to write trace: {}", e);
     }
 }
 
 fn dump_cpu_state(system: &NesSystem) {
-    let cpu = system.cpu();
-    let regs = cpu.registers();
-    let status = cpu.status();
+    let regs = system.cpu_registers();
+    let status = StatusFlags::new(regs.p);
 
     println!("\nCPU State:");
     println!("  A:    ${:02X}", regs.a);
@@ -94,7 +568,7 @@ fn dump_cpu_state(system: &NesSystem) {
     println!("  PC:   ${:04X}", regs.pc);
     println!("  SP:   ${:02X}", regs.sp);
     println!("  P:    {} ({})", status, status);
-    println!("  Cycles: {}", cpu.total_cycles());
+    println!("  Cycles: {}", system.cpu_cycles());
 }
 
 fn dump_ppu_state(system: &NesSystem) {
@@ -0,0 +1,81 @@
+//! PNG reading and writing for RGB framebuffers
+
+use nes_core::ppu::{FrameSnapshot, FRAME_HEIGHT, FRAME_WIDTH};
+use nes_core::sink::VideoSink;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Write an RGB image (3 bytes per pixel) as a PNG file
+pub fn write_rgb(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    writer
+        .write_image_data(rgb)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Read a PNG file as an RGB image, returning (width, height, pixels)
+pub fn read_rgb(path: &Path) -> Result<(usize, usize, Vec<u8>), String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    buf.truncate(info.buffer_size());
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let rgb = match info.color_type {
+        png::ColorType::Rgb => buf,
+        png::ColorType::Rgba => buf.chunks_exact(4).flat_map(|px| [px[0], px[1], px[2]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&v| [v, v, v]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|px| [px[0], px[0], px[0]]).collect(),
+        png::ColorType::Indexed => return Err(format!("{}: unexpanded indexed PNG", path.display())),
+    };
+    Ok((width, height, rgb))
+}
+
+/// Video sink writing every frame to `frame-NNNNNN.png` in a directory
+#[derive(Debug)]
+pub struct PngSequenceSink {
+    dir: PathBuf,
+    written: u64,
+    /// First write error; later frames are dropped
+    error: Option<String>,
+}
+
+impl PngSequenceSink {
+    /// Create the output directory if needed
+    pub fn new(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(Self { dir: dir.to_path_buf(), written: 0, error: None })
+    }
+
+    /// Number of frames written, or the first error
+    pub fn finish(self) -> Result<u64, String> {
+        self.error.map_or(Ok(self.written), Err)
+    }
+}
+
+impl VideoSink for PngSequenceSink {
+    fn on_frame(&mut self, frame: &FrameSnapshot) {
+        if self.error.is_some() {
+            return;
+        }
+        let path = self.dir.join(format!("frame-{:06}.png", self.written));
+        match write_rgb(&path, FRAME_WIDTH, FRAME_HEIGHT, frame.pixels()) {
+            Ok(()) => self.written += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! `serve` subcommand - remote viewing over WebSocket
+//!
+//! Runs the emulator at 60 frames per second and serves:
+//! - `GET /`: the reference browser client (`remote_client.html`)
+//! - WebSocket upgrades on any path: a stream of binary frame messages encoded
+//!   with `nes_core::framediff` (a keyframe on connect, then deltas)
+//!
+//! Clients send input as 2-byte binary messages `[port, buttons]`, with the
+//! button bits of `nes_core::controller::Buttons`.
+//!
+//! Only available with the `remote` cargo feature.
+
+use clap::Args;
+use nes_core::controller::Buttons;
+use nes_core::framediff;
+use nes_core::ppu::FRAME_RGB_SIZE;
+use nes_core::system::NesSystem;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{Message, WebSocket};
+
+/// Reference client served at `/`
+const CLIENT_HTML: &str = include_str!("remote_client.html");
+
+/// Target frame duration (NTSC)
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Arguments for the `serve` subcommand
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Path to the iNES ROM file
+    #[arg(short, long)]
+    rom: PathBuf,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+}
+
+/// A connected viewer
+struct Client {
+    socket: WebSocket<TcpStream>,
+    /// Last frame sent to this client (None until the keyframe is sent)
+    last_frame: Option<Vec<u8>>,
+}
+
+/// Run the `serve` subcommand
+pub fn run(args: &ServeArgs) {
+    let rom_data = crate::read_rom(&args.rom);
+    let mut system = crate::load_system(&rom_data);
+    system.initialize_ppu();
+
+    let listener = match TcpListener::bind(&args.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", args.listen, e);
+            std::process::exit(1);
+        }
+    };
+    println!("Serving {} on http://{}/", args.rom.display(), args.listen);
+
+    let clients = spawn_acceptor(listener);
+    let mut viewers: Vec<Client> = Vec::new();
+    let mut framebuffer = vec![0u8; FRAME_RGB_SIZE];
+    let mut next_frame = Instant::now();
+
+    loop {
+        loop {
+            match clients.try_recv() {
+                Ok(socket) => viewers.push(Client { socket, last_frame: None }),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        viewers.retain_mut(|client| read_input(client, &mut system));
+
+        if let Err(e) = system.run_frames(1) {
+            eprintln!("Error running system: {}", e);
+            std::process::exit(1);
+        }
+        system.ppu().render_frame(&mut framebuffer);
+
+        let frame_number = system.frame_count() as u32;
+        viewers.retain_mut(|client| send_frame(client, frame_number, &framebuffer));
+
+        next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            // Running behind: don't try to catch up with a burst of frames
+            next_frame = now;
+        }
+    }
+}
+
+/// Accept connections on a background thread, handing WebSocket clients to the main loop
+fn spawn_acceptor(listener: TcpListener) -> Receiver<WebSocket<TcpStream>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            match handle_connection(stream) {
+                Ok(Some(socket)) => {
+                    if sender.send(socket).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Connection failed: {}", e),
+            }
+        }
+    });
+    receiver
+}
+
+/// Upgrade WebSocket requests; answer anything else with the client page
+fn handle_connection(mut stream: TcpStream) -> io::Result<Option<WebSocket<TcpStream>>> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let request = peek_request_head(&stream)?;
+    if request.to_ascii_lowercase().contains("upgrade: websocket") {
+        let peer = stream.peer_addr()?;
+        let socket = tungstenite::accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+        socket.get_ref().set_nonblocking(true)?;
+        println!("Viewer connected: {}", peer);
+        return Ok(Some(socket));
+    }
+
+    // Plain HTTP: consume the request and serve the client
+    let mut head = vec![0; request.len()];
+    stream.read_exact(&mut head)?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        CLIENT_HTML.len(),
+        CLIENT_HTML
+    )?;
+    Ok(None)
+}
+
+/// Peek at the HTTP request head without consuming it (the WebSocket handshake reads it again)
+fn peek_request_head(stream: &TcpStream) -> io::Result<String> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = stream.peek(&mut buf)?;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+        }
+        let text = String::from_utf8_lossy(&buf[..len]);
+        if let Some(end) = text.find("\r\n\r\n") {
+            return Ok(text[..end + 4].to_string());
+        }
+        if len == buf.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too large"));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Apply pending input messages; returns false if the client disconnected
+fn read_input(client: &mut Client, system: &mut NesSystem) -> bool {
+    loop {
+        match client.socket.read() {
+            Ok(Message::Binary(data)) if data.len() == 2 && data[0] < 2 => {
+                system.set_buttons(data[0] as usize, Buttons::new(data[1]));
+            }
+            Ok(Message::Close(_)) => return false,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => return true,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Send the frame as a diff against what the client last received; returns false on disconnect
+fn send_frame(client: &mut Client, frame_number: u32, framebuffer: &[u8]) -> bool {
+    if client.last_frame.as_deref() == Some(framebuffer) {
+        return true;
+    }
+    let message = framediff::encode(frame_number, framebuffer, client.last_frame.as_deref());
+    match client.socket.send(Message::binary(message)) {
+        Ok(()) => {}
+        // The message is queued and flushed with the next send
+        Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+        Err(_) => return false,
+    }
+    match client.last_frame.as_mut() {
+        Some(last) => last.copy_from_slice(framebuffer),
+        None => client.last_frame = Some(framebuffer.to_vec()),
+    }
+    true
+}
@@ -0,0 +1,56 @@
+//! Per-frame telemetry as newline-delimited JSON
+//!
+//! Each completed frame produces one object:
+//!
+//! ```text
+//! {"frame":1,"cycles":29780,"pc":"C000","lag":false,"hash":"ab19079c93d03a31","input":[0,0]}
+//! ```
+//!
+//! `input` holds the button bitmask of each controller port (bit 0 = A ... bit 7 = Right).
+
+use nes_core::system::NesSystem;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes one JSON line per frame to a file or stdout
+pub struct TelemetryWriter {
+    out: Box<dyn Write>,
+}
+
+impl TelemetryWriter {
+    /// Open a telemetry stream; the path `-` means stdout
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let out: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(Self { out })
+    }
+
+    /// Check if the given telemetry path refers to stdout
+    pub fn is_stdout(path: &Path) -> bool {
+        path == Path::new("-")
+    }
+
+    /// Write the record for the frame that just completed
+    pub fn write_frame(&mut self, system: &NesSystem) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{{\"frame\":{},\"cycles\":{},\"pc\":\"{:04X}\",\"lag\":{},\"hash\":\"{:016x}\",\"input\":[{},{}]}}",
+            system.frame_count(),
+            system.cpu_cycles(),
+            system.cpu_registers().pc,
+            system.lag_frame(),
+            system.state_hash(),
+            system.buttons(0).bits(),
+            system.buttons(1).bits(),
+        )
+    }
+
+    /// Flush buffered records
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
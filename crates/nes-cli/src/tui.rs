@@ -0,0 +1,466 @@
+//! `tui` subcommand - play in the terminal
+//!
+//! A front end for headless servers and SSH sessions: pick a ROM from a
+//! directory (found with `nes_core::loader::scan_roms`), then play it with a
+//! framebuffer preview drawn in text cells next to live CPU/PPU stats. The
+//! preview uses half blocks (two colored pixels per cell) or, toggled with
+//! `b`, monochrome braille (2x4 dots per cell, for terminals without true
+//! color).
+//!
+//! Terminals only report key presses, so a pressed button is held for a few
+//! frames and released unless the key repeats (or the terminal reports the
+//! release).
+//!
+//! Only available with the `tui` cargo feature.
+
+use clap::Args;
+use nes_core::controller::Buttons;
+use nes_core::loader::{scan_roms, RomScan, RomScanEntry};
+use nes_core::ppu::{FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use nes_core::system::NesSystem;
+use nes_core::StatusFlags;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListState, Paragraph, Widget};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Target frame duration (NTSC)
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Frames a button stays pressed after its last key event
+const HOLD_FRAMES: u8 = 8;
+
+/// Width of the stats panel in cells
+const STATS_WIDTH: u16 = 30;
+
+/// Arguments for the `tui` subcommand
+#[derive(Args, Debug)]
+pub struct TuiArgs {
+    /// Directory to pick a ROM from
+    #[arg(default_value = ".")]
+    dir: PathBuf,
+
+    /// Start this ROM right away instead of showing the picker
+    #[arg(short, long)]
+    rom: Option<PathBuf>,
+}
+
+/// How the framebuffer is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PreviewMode {
+    /// '▀' cells with the top pixel as foreground and the bottom as background
+    HalfBlock,
+    /// Braille cells with a dot for each pixel brighter than the frame average
+    Braille,
+}
+
+/// ROM picker state
+struct Picker {
+    scan: RomScan,
+    entries: Vec<RomScanEntry>,
+    list: ListState,
+}
+
+/// A running game
+struct Game {
+    system: NesSystem,
+    name: String,
+    framebuffer: Vec<u8>,
+    paused: bool,
+    /// Frames left before each button (bit 0 = A ... bit 7 = Right) is released
+    held: [u8; 8],
+    /// Frames run and when counting started, for the FPS readout
+    fps_frames: u32,
+    fps_start: Instant,
+    fps: f64,
+}
+
+enum Screen {
+    Picker(Picker),
+    Game(Box<Game>),
+}
+
+struct App {
+    dir: PathBuf,
+    screen: Screen,
+    preview: PreviewMode,
+    /// Last error, shown in the footer
+    message: Option<String>,
+    quit: bool,
+}
+
+/// Run the `tui` subcommand
+pub fn run(args: &TuiArgs) {
+    let mut app = App {
+        dir: args.dir.clone(),
+        screen: Screen::Picker(Picker::new(&args.dir)),
+        preview: PreviewMode::HalfBlock,
+        message: None,
+        quit: false,
+    };
+    if let Some(rom) = &args.rom {
+        match Game::load(rom) {
+            Ok(game) => app.screen = Screen::Game(Box::new(game)),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut terminal = match ratatui::try_init() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            eprintln!("Failed to set up the terminal: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    if let Err(e) = result {
+        eprintln!("Terminal error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        let mut next_frame = Instant::now();
+        while !self.quit {
+            if let Screen::Picker(picker) = &mut self.screen {
+                picker.poll();
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            // Handle input until the next frame is due
+            loop {
+                let timeout = next_frame.saturating_duration_since(Instant::now());
+                if !event::poll(timeout)? {
+                    break;
+                }
+                if let Event::Key(key) = event::read()? {
+                    self.handle_key(key);
+                }
+                if self.quit {
+                    return Ok(());
+                }
+            }
+
+            if let Screen::Game(game) = &mut self.screen {
+                if let Err(e) = game.tick() {
+                    self.message = Some(format!("Emulation stopped: {}", e));
+                    game.paused = true;
+                }
+            }
+            next_frame += FRAME_DURATION;
+            let now = Instant::now();
+            if next_frame < now {
+                // Running behind (or idle in the picker): don't catch up with a burst of frames
+                next_frame = now;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let pressed = key.kind != KeyEventKind::Release;
+        match &mut self.screen {
+            Screen::Picker(picker) if pressed => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Up | KeyCode::Char('k') => picker.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => picker.list.select_next(),
+                KeyCode::PageUp => picker.list.scroll_up_by(10),
+                KeyCode::PageDown => picker.list.scroll_down_by(10),
+                KeyCode::Home => picker.list.select_first(),
+                KeyCode::End => picker.list.select_last(),
+                KeyCode::Enter => {
+                    let Some(entry) = picker.list.selected().and_then(|i| picker.entries.get(i)) else {
+                        return;
+                    };
+                    match Game::load(&entry.path) {
+                        Ok(game) => {
+                            self.screen = Screen::Game(Box::new(game));
+                            self.message = None;
+                        }
+                        Err(e) => self.message = Some(e),
+                    }
+                }
+                _ => {}
+            },
+            Screen::Picker(_) => {}
+            Screen::Game(game) => {
+                if let Some(button) = button_for_key(key.code) {
+                    game.press(button, pressed);
+                    return;
+                }
+                if !pressed {
+                    return;
+                }
+                match key.code {
+                    KeyCode::Char('q') => self.quit = true,
+                    KeyCode::Esc => {
+                        self.screen = Screen::Picker(Picker::new(&self.dir));
+                        self.message = None;
+                    }
+                    KeyCode::Char('p') | KeyCode::Char(' ') => game.paused = !game.paused,
+                    KeyCode::Char('n') if game.paused => {
+                        if let Err(e) = game.advance() {
+                            self.message = Some(format!("Emulation stopped: {}", e));
+                        }
+                    }
+                    KeyCode::Char('r') => {
+                        game.system.reset();
+                        self.message = None;
+                    }
+                    KeyCode::Char('b') => {
+                        self.preview = match self.preview {
+                            PreviewMode::HalfBlock => PreviewMode::Braille,
+                            PreviewMode::Braille => PreviewMode::HalfBlock,
+                        };
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let help = match &mut self.screen {
+            Screen::Picker(picker) => {
+                picker.draw(frame, main, &self.dir);
+                "↑/↓ select  Enter play  q quit"
+            }
+            Screen::Game(game) => {
+                game.draw(frame, main, self.preview);
+                "arrows D-pad  z B  x A  Enter Start  Tab Select  p pause  n step  r reset  b preview  Esc ROMs  q quit"
+            }
+        };
+        let footer_line = match &self.message {
+            Some(message) => Line::styled(message.as_str(), Style::new().fg(Color::Red)),
+            None => Line::styled(help, Style::new().add_modifier(Modifier::DIM)),
+        };
+        frame.render_widget(footer_line, footer);
+    }
+}
+
+impl Picker {
+    fn new(dir: &Path) -> Self {
+        Self { scan: scan_roms(dir), entries: Vec::new(), list: ListState::default() }
+    }
+
+    /// Add ROMs the scan found since the last poll, keeping the list sorted
+    fn poll(&mut self) {
+        let found = self.scan.poll();
+        if found.is_empty() {
+            return;
+        }
+        let selected = self.list.selected().and_then(|i| self.entries.get(i)).map(|entry| entry.path.clone());
+        self.entries.extend(found);
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let index = selected.and_then(|path| self.entries.iter().position(|entry| entry.path == path));
+        self.list.select(Some(index.unwrap_or(0)));
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect, dir: &Path) {
+        let items = self.entries.iter().map(|entry| {
+            let name = entry.path.strip_prefix(dir).unwrap_or(&entry.path).display().to_string();
+            match &entry.header {
+                Ok(header) => Line::raw(format!("{:<48} mapper {:>3}", name, header.mapper_number())),
+                Err(_) => Line::styled(format!("{:<48} unreadable", name), Style::new().add_modifier(Modifier::DIM)),
+            }
+        });
+        let status = if self.scan.is_finished() { "" } else { " (scanning...)" };
+        let title = format!(" {} - {} ROMs{} ", dir.display(), self.entries.len(), status);
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+}
+
+impl Game {
+    /// Load and reset a ROM, returning a message on failure
+    fn load(path: &Path) -> Result<Self, String> {
+        let rom_data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut system = NesSystem::new();
+        system.load_rom(&rom_data).map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+        system.reset();
+        system.initialize_ppu();
+        let name = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+        Ok(Self {
+            system,
+            name,
+            framebuffer: vec![0; FRAME_RGB_SIZE],
+            paused: false,
+            held: [0; 8],
+            fps_frames: 0,
+            fps_start: Instant::now(),
+            fps: 0.0,
+        })
+    }
+
+    /// Press or release a button (a `Buttons` bit)
+    fn press(&mut self, button: u8, pressed: bool) {
+        let index = button.trailing_zeros() as usize;
+        self.held[index] = if pressed { HOLD_FRAMES } else { 0 };
+    }
+
+    /// Run one frame unless paused, updating the FPS readout
+    fn tick(&mut self) -> Result<(), String> {
+        if !self.paused {
+            self.advance()?;
+            self.fps_frames += 1;
+        }
+        let elapsed = self.fps_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = self.fps_frames as f64 / elapsed.as_secs_f64();
+            self.fps_frames = 0;
+            self.fps_start = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Run one frame with the held buttons
+    fn advance(&mut self) -> Result<(), String> {
+        let mut buttons = Buttons::default();
+        for (bit, frames) in self.held.iter_mut().enumerate() {
+            if *frames > 0 {
+                buttons.set(1 << bit, true);
+                *frames -= 1;
+            }
+        }
+        self.system.set_buttons(0, buttons);
+        self.system.run_frames(1).map_err(|e| e.to_string())?;
+        self.system.ppu().render_frame(&mut self.framebuffer);
+        Ok(())
+    }
+
+    fn draw(&self, frame: &mut Frame, area: Rect, preview: PreviewMode) {
+        let [screen, stats] = Layout::horizontal([Constraint::Min(0), Constraint::Length(STATS_WIDTH)]).areas(area);
+        let title = if self.paused { format!(" {} (paused) ", self.name) } else { format!(" {} ", self.name) };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(screen);
+        frame.render_widget(block, screen);
+        frame.render_widget(FramePreview { framebuffer: &self.framebuffer, mode: preview }, inner);
+        frame.render_widget(Paragraph::new(self.stats()).block(Block::bordered().title(" Stats ")), stats);
+    }
+
+    fn stats(&self) -> Vec<Line<'static>> {
+        let regs = self.system.cpu_registers();
+        let ppu = self.system.ppu();
+        let buttons = self.held.iter().enumerate().filter(|(_, &frames)| frames > 0);
+        let buttons: String = buttons.map(|(bit, _)| "ABsSUDLR".as_bytes()[bit] as char).collect();
+        vec![
+            Line::raw(format!("Frame    {}", self.system.frame_count())),
+            Line::raw(format!("FPS      {:.1}", self.fps)),
+            Line::raw(""),
+            Line::styled("CPU", Style::new().add_modifier(Modifier::BOLD)),
+            Line::raw(format!("PC ${:04X}  SP ${:02X}", regs.pc, regs.sp)),
+            Line::raw(format!("A  ${:02X}  X ${:02X}  Y ${:02X}", regs.a, regs.x, regs.y)),
+            Line::raw(format!("P  {}", StatusFlags::new(regs.p))),
+            Line::raw(format!("Cycles   {}", self.system.cpu_cycles())),
+            Line::raw(""),
+            Line::styled("PPU", Style::new().add_modifier(Modifier::BOLD)),
+            Line::raw(format!("Scanline {}", ppu.scanline())),
+            Line::raw(format!("Dot      {}", ppu.dot())),
+            Line::raw(format!("VBLANK   {}", ppu.status().vblank())),
+            Line::raw(""),
+            Line::raw(format!("Input    {}", buttons)),
+        ]
+    }
+}
+
+/// Map a key to a controller button
+fn button_for_key(code: KeyCode) -> Option<u8> {
+    match code {
+        KeyCode::Up => Some(Buttons::UP),
+        KeyCode::Down => Some(Buttons::DOWN),
+        KeyCode::Left => Some(Buttons::LEFT),
+        KeyCode::Right => Some(Buttons::RIGHT),
+        KeyCode::Char('x') => Some(Buttons::A),
+        KeyCode::Char('z') => Some(Buttons::B),
+        KeyCode::Enter => Some(Buttons::START),
+        KeyCode::Tab => Some(Buttons::SELECT),
+        _ => None,
+    }
+}
+
+/// The framebuffer scaled (nearest neighbour) to fill an area of cells
+struct FramePreview<'a> {
+    framebuffer: &'a [u8],
+    mode: PreviewMode,
+}
+
+impl FramePreview<'_> {
+    /// Get the RGB color of a pixel
+    fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let offset = (y * FRAME_WIDTH + x) * 3;
+        (self.framebuffer[offset], self.framebuffer[offset + 1], self.framebuffer[offset + 2])
+    }
+
+    /// Get the frame pixel shown at a sub-cell position, with `columns` x `rows` samples in the area
+    fn sample(&self, column: u16, row: u16, columns: u16, rows: u16) -> (u8, u8, u8) {
+        let x = column as usize * FRAME_WIDTH / columns as usize;
+        let y = row as usize * FRAME_HEIGHT / rows as usize;
+        self.pixel(x, y)
+    }
+}
+
+/// Perceived brightness of a color (0-255)
+fn luma((r, g, b): (u8, u8, u8)) -> u32 {
+    (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000
+}
+
+impl Widget for FramePreview<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        match self.mode {
+            PreviewMode::HalfBlock => {
+                let rows = area.height * 2;
+                for y in 0..area.height {
+                    for x in 0..area.width {
+                        let (tr, tg, tb) = self.sample(x, y * 2, area.width, rows);
+                        let (br, bg, bb) = self.sample(x, y * 2 + 1, area.width, rows);
+                        if let Some(cell) = buf.cell_mut((area.x + x, area.y + y)) {
+                            cell.set_char('▀').set_fg(Color::Rgb(tr, tg, tb)).set_bg(Color::Rgb(br, bg, bb));
+                        }
+                    }
+                }
+            }
+            PreviewMode::Braille => {
+                let (columns, rows) = (area.width * 2, area.height * 4);
+                let pixels = self.framebuffer.chunks_exact(3).map(|rgb| luma((rgb[0], rgb[1], rgb[2])));
+                let threshold = pixels.sum::<u32>() / (FRAME_WIDTH * FRAME_HEIGHT) as u32;
+                // Dot bits of a braille cell, by row then column
+                const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                for y in 0..area.height {
+                    for x in 0..area.width {
+                        let mut bits = 0;
+                        for (dy, row) in DOTS.iter().enumerate() {
+                            for (dx, bit) in row.iter().enumerate() {
+                                let pixel = self.sample(x * 2 + dx as u16, y * 4 + dy as u16, columns, rows);
+                                if luma(pixel) > threshold {
+                                    bits |= bit;
+                                }
+                            }
+                        }
+                        let symbol = char::from_u32(0x2800 + bits).unwrap_or(' ');
+                        if let Some(cell) = buf.cell_mut((area.x + x, area.y + y)) {
+                            cell.set_char(symbol);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! `verify-hashes` subcommand - replay a frame hash sidecar
+//!
+//! The system is powered on the way the sidecar's header describes, then the
+//! recorded input is played back and the state hash is checked after every
+//! frame. With `--gif` or `--png-frames` the replayed frames are written out
+//! again, so a recording can be re-rendered (for example at 2x) from the ROM
+//! and its sidecar alone. Exits with status 1 on the first mismatch.
+
+use crate::png_io::PngSequenceSink;
+use clap::Args;
+use nes_core::accuracy::{AccuracyProfile, PpuAlignment};
+use nes_core::frame_hashes::FrameHashes;
+use nes_core::gif::GifRecorder;
+use nes_core::sink::VideoSink;
+use std::path::PathBuf;
+
+/// Arguments for the `verify-hashes` subcommand
+#[derive(Args, Debug)]
+pub struct VerifyHashesArgs {
+    /// Path to the iNES ROM file
+    #[arg(short, long)]
+    rom: PathBuf,
+
+    /// Path to the frame hash sidecar (as written by --frame-hashes)
+    #[arg(long, value_name = "PATH")]
+    hashes: PathBuf,
+
+    /// Render the replay as an animated GIF
+    #[arg(long, value_name = "PATH")]
+    gif: Option<PathBuf>,
+
+    /// GIF scale factor (1 or 2, nearest neighbour)
+    #[arg(long, value_name = "N", default_value = "1", value_parser = clap::value_parser!(u8).range(1..=2))]
+    gif_scale: u8,
+
+    /// Write every replayed frame as a numbered PNG into this directory
+    #[arg(long, value_name = "DIR")]
+    png_frames: Option<PathBuf>,
+}
+
+/// Run the `verify-hashes` subcommand
+pub fn run(args: &VerifyHashesArgs) {
+    let hashes = match FrameHashes::load(&args.hashes) {
+        Ok(hashes) => hashes,
+        Err(e) => {
+            eprintln!("Failed to read frame hashes: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if hashes.start_frame != 0 {
+        eprintln!("The recording starts at frame {}; only recordings from power-on can be replayed", hashes.start_frame);
+        std::process::exit(1);
+    }
+    let accuracy = AccuracyProfile::from_name(&hashes.accuracy).unwrap_or_else(|| {
+        eprintln!("Warning: unknown accuracy profile '{}', using compatible", hashes.accuracy);
+        AccuracyProfile::compatible()
+    });
+
+    let rom_data = crate::read_rom(&args.rom);
+    let mut system = crate::load_system(&rom_data);
+    system.set_rng_seed(hashes.rng_seed);
+    system.set_accuracy(AccuracyProfile {
+        ppu_alignment: PpuAlignment::Fixed(hashes.ppu_alignment),
+        ..accuracy
+    });
+    system.power_cycle();
+
+    if let Some(path) = &args.gif {
+        let max_frames = u32::try_from(hashes.len()).unwrap_or(u32::MAX);
+        system.start_gif_recording_with(GifRecorder::new(path, max_frames).with_scale(args.gif_scale as usize));
+    }
+    let mut png_frames = args.png_frames.as_deref().map(|dir| {
+        PngSequenceSink::new(dir).unwrap_or_else(|e| {
+            eprintln!("Failed to create frame directory {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    let result = hashes.replay(&mut system, |system| {
+        if let Some(sink) = png_frames.as_mut() {
+            sink.on_frame(&system.ppu_mut().snapshot_frame());
+        }
+    });
+    if let Err(e) = result {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+    println!("Frames verified: {}", hashes.len());
+
+    crate::save_gif(&mut system, true);
+    if let (Some(sink), Some(dir)) = (png_frames, &args.png_frames) {
+        match sink.finish() {
+            Ok(count) => println!("Wrote {} frames to {}", count, dir.display()),
+            Err(e) => eprintln!("Failed to write frames: {}", e),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! `verify-movie` subcommand - play an FM2 movie headless and check the result
+//!
+//! The movie is played at full speed from power-on. Afterwards the final state
+//! hash and any `--expect-ram` goal conditions are checked; the process exits
+//! with status 1 if any check fails.
+
+use clap::Args;
+use nes_core::movie::Movie;
+use std::fs;
+use std::path::PathBuf;
+
+/// Arguments for the `verify-movie` subcommand
+#[derive(Args, Debug)]
+pub struct VerifyMovieArgs {
+    /// Path to the iNES ROM file
+    #[arg(short, long)]
+    rom: PathBuf,
+
+    /// Path to the FM2 movie
+    #[arg(short, long)]
+    movie: PathBuf,
+
+    /// Expected final state hash (hex, as printed by this command)
+    #[arg(long, value_parser = parse_hex)]
+    expect_hash: Option<u64>,
+
+    /// Goal condition: RAM byte at ADDR must equal VALUE (hex, e.g. 0x0075=0x02)
+    #[arg(long, value_name = "ADDR=VALUE", value_parser = parse_ram_check)]
+    expect_ram: Vec<(u16, u8)>,
+
+    /// Write per-frame JSON telemetry to this file
+    #[arg(long, value_name = "PATH")]
+    telemetry: Option<PathBuf>,
+}
+
+/// Run the `verify-movie` subcommand
+pub fn run(args: &VerifyMovieArgs) {
+    let text = match fs::read_to_string(&args.movie) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read movie {}: {}", args.movie.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let movie = match Movie::parse_fm2(&text) {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("Failed to parse movie: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let rom_data = crate::read_rom(&args.rom);
+    let mut system = crate::load_system(&rom_data);
+    let mut telemetry = args.telemetry.as_deref().map(crate::open_telemetry);
+
+    for (frame, input) in movie.frames.iter().enumerate() {
+        input.apply(&mut system);
+        if let Err(e) = system.run_frames(1) {
+            eprintln!("Error running system at frame {}: {}", frame + 1, e);
+            std::process::exit(1);
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            crate::write_telemetry(telemetry, &system);
+        }
+    }
+    crate::flush_telemetry(&mut telemetry);
+
+    let hash = system.state_hash();
+    println!("Frames played: {}", movie.len());
+    println!("Final state hash: {:016x}", hash);
+
+    let mut failed = false;
+    if let Some(expected) = args.expect_hash {
+        if hash == expected {
+            println!("State hash: OK");
+        } else {
+            println!("State hash: MISMATCH (expected {:016x})", expected);
+            failed = true;
+        }
+    }
+    for &(address, expected) in &args.expect_ram {
+        let actual = system.read_memory(address);
+        if actual == expected {
+            println!("RAM ${:04X} = ${:02X}: OK", address, actual);
+        } else {
+            println!("RAM ${:04X} = ${:02X}: MISMATCH (expected ${:02X})", address, actual, expected);
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u64, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches('$');
+    u64::from_str_radix(digits, 16).map_err(|e| format!("invalid hex value '{}': {}", text, e))
+}
+
+fn parse_ram_check(text: &str) -> Result<(u16, u8), String> {
+    let (address, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDR=VALUE, got '{}'", text))?;
+    let address = u16::try_from(parse_hex(address)?).map_err(|_| format!("address out of range: {}", address))?;
+    let value = u8::try_from(parse_hex(value)?).map_err(|_| format!("value out of range: {}", value))?;
+    Ok((address, value))
+}
@@ -0,0 +1,75 @@
+//! Bus read/write dispatch cost, with and without mapped I/O regions
+//!
+//! Every CPU access goes through the bus (`NesSystem::read_memory`/`write_memory`
+//! here), so the page check for mapped regions has to stay cheap for the pages
+//! that don't have one.
+//!
+//! ```text
+//! cargo bench -p nes-core --bench bus_dispatch
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nes_core::io_map::IoHandler;
+use nes_core::NesSystem;
+
+/// Register file standing in for a mapper's $5000-$5FFF registers
+#[derive(Debug, Clone)]
+struct Registers([u8; 8]);
+
+impl IoHandler for Registers {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        Some(self.0[(address & 7) as usize])
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[(address & 7) as usize] = value;
+    }
+}
+
+/// 32KB NROM image filled with NOPs
+fn nrom() -> Vec<u8> {
+    let mut rom = b"NES\x1A\x02\x01".to_vec();
+    rom.resize(16, 0);
+    rom.extend(vec![0xEA; 32 * 1024]);
+    rom.extend(vec![0; 8 * 1024]);
+    rom
+}
+
+fn system(mapped: bool) -> NesSystem {
+    let mut system = NesSystem::new();
+    system.load_rom(&nrom()).unwrap();
+    if mapped {
+        system.map_io(0x5000..=0x5FFF, Registers([0; 8])).unwrap();
+    }
+    system
+}
+
+/// Read 256 consecutive addresses starting at `base`
+fn read_page(system: &mut NesSystem, base: u16) -> u8 {
+    let mut acc = 0u8;
+    for offset in 0..256u16 {
+        acc ^= system.read_memory(black_box(base.wrapping_add(offset)));
+    }
+    acc
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bus_dispatch");
+    for (label, mapped) in [("unmapped", false), ("mapped", true)] {
+        let mut system = system(mapped);
+        group.bench_function(format!("{}/ram_read", label), |b| b.iter(|| read_page(&mut system, 0x0200)));
+        group.bench_function(format!("{}/prg_read", label), |b| b.iter(|| read_page(&mut system, 0xC000)));
+        group.bench_function(format!("{}/expansion_read", label), |b| b.iter(|| read_page(&mut system, 0x5000)));
+        group.bench_function(format!("{}/ram_write", label), |b| {
+            b.iter(|| {
+                for offset in 0..256u16 {
+                    system.write_memory(black_box(0x0300 + offset), offset as u8);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dispatch);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Instruction decode and execute cost through `NesSystem::step`
+//!
+//! Each step also catches the PPU and APU up with the instruction's cycles,
+//! so the numbers show the per-instruction cost that headless and batch runs
+//! pay rather than the CPU's dispatch alone.
+//!
+//! ```text
+//! cargo bench -p nes-core --bench cpu_dispatch
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use nes_core::NesSystem;
+
+/// Instructions stepped per iteration
+const STEPS: u64 = 10_000;
+
+/// Loop at $8000 mixing loads, stores, ALU ops, read-modify-writes, a
+/// subroutine call and branches, the way game code does (a 32KB NROM image)
+fn mixed_program() -> Vec<u8> {
+    let mut prg = vec![0; 32 * 1024];
+    let code = [
+        0xA2, 0x00, // LDX #$00
+        0xBD, 0x00, 0x03, // loop: LDA $0300,X
+        0x69, 0x03, // ADC #$03
+        0x9D, 0x00, 0x03, // STA $0300,X
+        0xE6, 0x10, // INC $10
+        0x45, 0x10, // EOR $10
+        0x0A, // ASL A
+        0x20, 0x00, 0x90, // JSR $9000
+        0xE8, // INX
+        0xD0, 0xEE, // BNE loop
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+    prg[..code.len()].copy_from_slice(&code);
+    // $9000: LDY $10; CPY #$80; RTS
+    prg[0x1000..0x1005].copy_from_slice(&[0xA4, 0x10, 0xC0, 0x80, 0x60]);
+    // Reset vector: $8000
+    prg[0x7FFC..0x7FFE].copy_from_slice(&[0x00, 0x80]);
+
+    let mut rom = b"NES\x1A\x02\x01".to_vec();
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.extend(vec![0; 8 * 1024]);
+    rom
+}
+
+fn bench_step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu_dispatch");
+    group.throughput(Throughput::Elements(STEPS));
+    let mut system = NesSystem::new();
+    system.load_rom(&mixed_program()).unwrap();
+    system.reset();
+    system.set_pc(0x8000);
+    group.bench_function("mixed", |b| {
+        b.iter(|| {
+            for _ in 0..STEPS {
+                system.step().unwrap();
+            }
+            black_box(system.cpu_cycles())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_step);
+criterion_main!(benches);
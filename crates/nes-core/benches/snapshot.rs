@@ -0,0 +1,58 @@
+//! Save and restore cost of in-memory snapshots against full savestates
+//!
+//! Run-ahead and rollback save and restore at least once per frame, so a
+//! snapshot round trip has to stay well under 0.2ms. Each iteration runs a
+//! frame that writes a few RAM and VRAM pages, then snapshots and rewinds.
+//!
+//! ```text
+//! cargo bench -p nes-core --bench snapshot
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nes_core::snapshot::SnapshotRing;
+use nes_core::NesSystem;
+
+/// A 256KB PRG / 128KB CHR cartridge looping over RAM and VRAM writes
+fn system() -> NesSystem {
+    // INC $10; INC $0300; STA $2007; JMP $8000
+    let mut prg_rom = vec![0xEA; 256 * 1024];
+    prg_rom[..11].copy_from_slice(&[0xE6, 0x10, 0xEE, 0x00, 0x03, 0x8D, 0x07, 0x20, 0x4C, 0x00, 0x80]);
+    let mut rom = b"NES\x1A\x10\x10".to_vec();
+    rom.resize(16, 0);
+    rom.extend(prg_rom);
+    rom.extend(vec![0; 128 * 1024]);
+    let mut system = NesSystem::new();
+    system.load_rom(&rom).unwrap();
+    system.reset();
+    system.set_pc(0x8000);
+    system.run_frames(2).unwrap();
+    system
+}
+
+fn bench_round_trip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    let mut full = system();
+    let state = full.save_state();
+    group.bench_function("save_state", |b| {
+        b.iter(|| {
+            let saved = full.save_state();
+            full.load_state(black_box(&state)).unwrap();
+            saved
+        })
+    });
+    let mut fast = system();
+    let mut ring = SnapshotRing::new(8);
+    for _ in 0..ring.capacity() {
+        ring.push(&mut fast);
+    }
+    group.bench_function("ring", |b| {
+        b.iter(|| {
+            ring.push(&mut fast);
+            ring.rewind(&mut fast, 0).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_round_trip);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+//! Accuracy profile
+//!
+//! Hardware behaviours that some software depends on and other software breaks
+//! with are toggled here rather than always emulated, along with performance
+//! trade-offs such as frame skipping. Frontends pick a named preset and can
+//! flip individual options.
+
+use crate::frameskip::Frameskip;
+use crate::rng::RandomSource;
+use std::fmt;
+
+/// CPU cycles after power/reset during which the PPU ignores setup register writes
+pub const PPU_WARMUP_CYCLES: u32 = 29658;
+
+/// Number of distinct CPU/PPU alignments (PPU dot offsets within a CPU cycle)
+pub const PPU_ALIGNMENTS: u8 = 3;
+
+/// CPU/PPU clock alignment applied at power/reset
+///
+/// Real consoles start with the PPU at an arbitrary phase relative to the CPU.
+/// The core runs the PPU in whole dots, so an alignment is the number of dots
+/// (0-2) the PPU starts ahead of the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuAlignment {
+    /// Always use this dot offset
+    Fixed(u8),
+    /// Pick a new offset from the system RNG at every reset (the chosen one is reported by the system)
+    Random,
+}
+
+impl PpuAlignment {
+    /// Parse `0`-`2` or `random`
+    pub fn parse(text: &str) -> Option<Self> {
+        if text.eq_ignore_ascii_case("random") {
+            return Some(PpuAlignment::Random);
+        }
+        text.parse().ok().filter(|&n| n < PPU_ALIGNMENTS).map(PpuAlignment::Fixed)
+    }
+
+    /// Resolve to a concrete dot offset, drawing a random one if needed
+    pub fn resolve(self, rng: &mut impl RandomSource) -> u8 {
+        match self {
+            PpuAlignment::Fixed(offset) => offset % PPU_ALIGNMENTS,
+            PpuAlignment::Random => rng.below(PPU_ALIGNMENTS as u32) as u8,
+        }
+    }
+}
+
+impl Default for PpuAlignment {
+    fn default() -> Self {
+        PpuAlignment::Fixed(0)
+    }
+}
+
+impl fmt::Display for PpuAlignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PpuAlignment::Fixed(offset) => write!(f, "{}", offset),
+            PpuAlignment::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// Set of optional hardware behaviours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AccuracyProfile {
+    /// Ignore writes to $2000/$2001/$2005/$2006 for `PPU_WARMUP_CYCLES` after power/reset
+    pub ppu_warmup: bool,
+    /// CPU/PPU alignment used at power/reset
+    pub ppu_alignment: PpuAlignment,
+    /// Let DMC sample fetches corrupt controller reads (the DPCM glitch, see `dma`)
+    pub dmc_controller_glitch: bool,
+    /// Search for a ninth sprite the way hardware does, setting the sprite
+    /// overflow flag falsely or missing it (see `sprite_eval::SpriteEvaluator`)
+    pub sprite_overflow_bug: bool,
+    /// Frames frontends render (every frame is still emulated)
+    pub frameskip: Frameskip,
+}
+
+impl AccuracyProfile {
+    /// Names accepted by `from_name`
+    pub const NAMES: [&'static str; 2] = ["compatible", "accurate"];
+
+    /// Preset favouring software compatibility (the default)
+    pub fn compatible() -> Self {
+        Self::default()
+    }
+
+    /// Preset emulating every supported hardware quirk
+    pub fn accurate() -> Self {
+        Self {
+            ppu_warmup: true,
+            dmc_controller_glitch: true,
+            sprite_overflow_bug: true,
+            ..Self::default()
+        }
+    }
+
+    /// Look up a preset by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "compatible" => Some(Self::compatible()),
+            "accurate" => Some(Self::accurate()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AccuracyProfile {
+    /// Preset name, or the enabled options for custom combinations
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::compatible() {
+            write!(f, "compatible")
+        } else if *self == Self::accurate() {
+            write!(f, "accurate")
+        } else {
+            write!(
+                f,
+                "custom (ppu_warmup={}, ppu_alignment={}, dmc_controller_glitch={}, sprite_overflow_bug={}, frameskip={})",
+                self.ppu_warmup, self.ppu_alignment, self.dmc_controller_glitch, self.sprite_overflow_bug, self.frameskip
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn test_alignment_parse_and_resolve() {
+        assert_eq!(PpuAlignment::parse("2"), Some(PpuAlignment::Fixed(2)));
+        assert_eq!(PpuAlignment::parse("Random"), Some(PpuAlignment::Random));
+        assert_eq!(PpuAlignment::parse("3"), None);
+        let mut rng = Rng::new(3);
+        assert_eq!(PpuAlignment::Fixed(1).resolve(&mut rng), 1);
+        assert!((0..20).all(|_| PpuAlignment::Random.resolve(&mut rng) < PPU_ALIGNMENTS));
+    }
+}
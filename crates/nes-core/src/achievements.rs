@@ -0,0 +1,186 @@
+//! Offline achievement packs
+//!
+//! A pack is a text file with one achievement per line:
+//!
+//! ```text
+//! # Title: condition && condition [for FRAMES]
+//! Reached World 2: 0x075F == 1
+//! Coin hoarder: 0x075E >= 50 && 0x0770 == 1 for 60
+//! ```
+//!
+//! The title runs up to the last `:`. Conditions use the `condition` module's
+//! `ADDR OP VALUE` syntax and must all hold on the same frame; with
+//! `for FRAMES` they must hold for that many consecutive frames. The system
+//! evaluates the pack at the end of every frame and queues each achievement
+//! once, the first time it unlocks, for the frontend to announce.
+
+use crate::condition::{ConditionError, RamCondition};
+use std::fmt;
+
+/// One achievement from a pack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Achievement {
+    pub title: String,
+    /// Conditions that must all hold
+    pub conditions: Vec<RamCondition>,
+    /// Consecutive frames the conditions must hold (at least 1)
+    pub frames: u32,
+}
+
+impl Achievement {
+    /// Parse `Title: condition && condition [for FRAMES]`
+    fn parse(line: &str) -> Result<Self, String> {
+        let (title, rule) = line.rsplit_once(':').ok_or("expected 'Title: conditions'")?;
+        let title = title.trim();
+        if title.is_empty() {
+            return Err("missing title".to_string());
+        }
+        let (rule, frames) = match rule.rsplit_once(" for ") {
+            Some((rule, count)) => {
+                let frames = count.trim().parse::<u32>().ok().filter(|&f| f > 0);
+                (rule, frames.ok_or_else(|| format!("invalid frame count '{}'", count.trim()))?)
+            }
+            None => (rule, 1),
+        };
+        let conditions = rule
+            .split("&&")
+            .map(RamCondition::parse)
+            .collect::<Result<Vec<_>, ConditionError>>()
+            .map_err(|e| e.to_string())?;
+        Ok(Self { title: title.to_string(), conditions, frames })
+    }
+}
+
+/// Error parsing an achievement pack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AchievementError {
+    /// 1-based line number
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for AchievementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid achievement on line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for AchievementError {}
+
+/// A loaded pack with per-achievement progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AchievementSet {
+    achievements: Vec<Achievement>,
+    /// Consecutive frames each achievement's conditions have held
+    streaks: Vec<u32>,
+    unlocked: Vec<bool>,
+}
+
+impl AchievementSet {
+    /// Parse a pack
+    pub fn parse(text: &str) -> Result<Self, AchievementError> {
+        let achievements = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(index, line)| Achievement::parse(line).map_err(|reason| AchievementError { line: index + 1, reason }))
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = achievements.len();
+        Ok(Self { achievements, streaks: vec![0; count], unlocked: vec![false; count] })
+    }
+
+    /// Achievements in the pack
+    pub fn achievements(&self) -> &[Achievement] {
+        &self.achievements
+    }
+
+    /// Check if the achievement at `index` has unlocked
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        self.unlocked.get(index).copied().unwrap_or(false)
+    }
+
+    /// Number of unlocked achievements
+    pub fn unlocked_count(&self) -> usize {
+        self.unlocked.iter().filter(|&&u| u).count()
+    }
+
+    /// Evaluate one frame against the 2KB internal RAM, returning achievements unlocked by it
+    pub fn evaluate(&mut self, ram: &[u8]) -> Vec<&Achievement> {
+        let mut newly_unlocked = Vec::new();
+        for (index, achievement) in self.achievements.iter().enumerate() {
+            if self.unlocked[index] {
+                continue;
+            }
+            if achievement.conditions.iter().all(|c| c.matches(ram)) {
+                self.streaks[index] += 1;
+            } else {
+                self.streaks[index] = 0;
+            }
+            if self.streaks[index] >= achievement.frames {
+                self.unlocked[index] = true;
+                newly_unlocked.push(achievement);
+            }
+        }
+        newly_unlocked
+    }
+
+    /// Forget all progress and unlocks
+    pub fn reset(&mut self) {
+        self.streaks.fill(0);
+        self.unlocked.fill(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACK: &str = "# Test pack\n\
+                        World 1-2: Warp zone: 0x075F == 1\n\
+                        \n\
+                        Coin hoarder: 0x075E >= 50 && 0x0770 == 1 for 3\n";
+
+    fn titles(set: &mut AchievementSet, ram: &[u8]) -> Vec<String> {
+        set.evaluate(ram).into_iter().map(|a| a.title.clone()).collect()
+    }
+
+    #[test]
+    fn test_parse_pack() {
+        let set = AchievementSet::parse(PACK).unwrap();
+        assert_eq!(set.achievements().len(), 2);
+        assert_eq!(set.achievements()[0].title, "World 1-2: Warp zone");
+        assert_eq!(set.achievements()[0].frames, 1);
+        assert_eq!(set.achievements()[1].conditions.len(), 2);
+        assert_eq!(set.achievements()[1].frames, 3);
+
+        let error = AchievementSet::parse("Ok: 0x10 == 1\nBroken 0x10 == 1").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(AchievementSet::parse("Slow: 0x10 == 1 for 0").is_err());
+        assert!(AchievementSet::parse("Bad: 0x10 = 1").is_err());
+    }
+
+    #[test]
+    fn test_unlock_once_after_streak() {
+        let mut set = AchievementSet::parse(PACK).unwrap();
+        let mut ram = vec![0u8; 0x800];
+        assert!(titles(&mut set, &ram).is_empty());
+
+        ram[0x75F] = 1;
+        ram[0x75E] = 60;
+        ram[0x770] = 1;
+        assert_eq!(titles(&mut set, &ram), ["World 1-2: Warp zone"]);
+        assert!(titles(&mut set, &ram).is_empty());
+
+        // The streak restarts when a condition stops holding
+        ram[0x770] = 0;
+        assert!(titles(&mut set, &ram).is_empty());
+        ram[0x770] = 1;
+        assert!(titles(&mut set, &ram).is_empty());
+        assert!(titles(&mut set, &ram).is_empty());
+        assert_eq!(titles(&mut set, &ram), ["Coin hoarder"]);
+        assert_eq!(set.unlocked_count(), 2);
+
+        set.reset();
+        assert!(!set.is_unlocked(0));
+    }
+}
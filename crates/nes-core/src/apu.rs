@@ -1,73 +1,653 @@
 //! APU (Audio Processing Unit) implementation
 //!
 //! The NES APU has five channels:
-//! - Pulse 1 (4-bit pulse wave)
-//! - Pulse 2 (4-bit pulse wave)
-//! - Triangle (linear noise)
-//! - Noise (shift register)
+//! - Pulse 1 and 2 (square waves with duty, envelope, sweep and length counter)
+//! - Triangle (32-step wave gated by the linear and length counters)
+//! - Noise (15-bit shift register with envelope and length counter)
 //! - DMC (delta modulation channel)
 //!
-//! For now, this is a stub with timing hooks that can be expanded later.
+//! Everything is clocked per CPU cycle: the triangle, noise and DMC timers
+//! every cycle, the pulse timers every other one, and the frame sequencer's
+//! quarter and half frames (4- or 5-step mode from $4017) clock the
+//! envelopes, linear counter, length counters and sweeps. A $4017 write
+//! restarts the sequence 3 or 4 cycles later, depending on where it lands
+//! in the APU cycle. The 4-step sequence sets the frame IRQ flag on the
+//! three cycles around its last step unless $4017 bit 6 inhibits it;
+//! reading $4015 acknowledges it. `set_region` picks the step positions,
+//! noise periods and DMC rates of PAL consoles, whose CPU runs slower.
+//!
+//! The DMC's sample reader lives on the bus (see `dma`), which halts the CPU
+//! for its fetches; the bytes it reads are handed over with
+//! `push_dmc_sample` and played by the output unit here.
+//!
+//! Channel outputs are combined with `mixer::mix`, which uses integer math
+//! only so audio stays bit-identical across platforms. With audio output on
+//! (`set_audio_enabled`), the mixed level is averaged over the CPU cycles
+//! each output sample spans, a box filter that removes most of the aliasing,
+//! and collected for `take_samples`. Samples stay mixer levels
+//! (0..=`MIX_SCALE`, all channels at 0 is 0); `sink::FramePump` converts them
+//! to `f32` for output.
+
+use crate::dma::{dmc_rates, DMC_RATES};
+use crate::mixer::{self, ChannelLevels};
+use crate::region::Region;
+use crate::state::{StateError, StateReader, StateWriter};
 
 /// APU register map
 pub const APU_REGISTER_COUNT: usize = 24;
 
-/// APU registers
-#[derive(Debug, Clone, Copy)]
-pub enum ApuRegister {
-    /// $4000 - Pulse 1 control
-    Pulse1Ctrl,
-    /// $4001 - Pulse 1 sweep
-    Pulse1Sweep,
-    /// $4002 - Pulse 1 timer low
-    Pulse1TimerLow,
-    /// $4003 - Pulse 1 timer high
-    Pulse1TimerHigh,
-    /// $4004 - Pulse 2 control
-    Pulse2Ctrl,
-    /// $4005 - Pulse 2 sweep
-    Pulse2Sweep,
-    /// $4006 - Pulse 2 timer low
-    Pulse2TimerLow,
-    /// $4007 - Pulse 2 timer high
-    Pulse2TimerHigh,
-    /// $4008 - Triangle control
-    TriangleCtrl,
-    /// $400A - Triangle linear counter
-    TriangleLinear,
-    /// $400C - Triangle timer low
-    TriangleTimerLow,
-    /// $400D - Triangle timer high
-    TriangleTimerHigh,
-    /// $400E - Noise control
-    NoiseCtrl,
-    /// $4010 - Noise length table
-    NoiseLength,
-    /// $4012 - Noise timer low
-    NoiseTimerLow,
-    /// $4013 - Noise timer high
-    NoiseTimerHigh,
-    /// $4014 - DMC control
-    DmcCtrl,
-    /// $4015 - DMC timer
-    DmcTimer,
-    /// $4016 - Controller 1
-    Controller1,
-    /// $4017 - Controller 2
-    Controller2,
+/// Output sample rate used until `set_sample_rate` is called
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// Length counter loads, indexed by bits 3-7 of the fourth channel register
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28,
+    32, 30,
+];
+
+/// Pulse waveforms for the four duty settings (12.5%, 25%, 50%, 25% negated)
+const DUTY_TABLE: [[u8; 8]; 4] =
+    [[0, 1, 0, 0, 0, 0, 0, 0], [0, 1, 1, 0, 0, 0, 0, 0], [0, 1, 1, 1, 1, 0, 0, 0], [1, 0, 0, 1, 1, 1, 1, 1]];
+
+/// Triangle output for each of its 32 steps
+const TRIANGLE_SEQUENCE: [u8; 32] =
+    [15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// CPU cycles per noise shift for each $400E period index (NTSC)
+pub const NOISE_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+/// CPU cycles per noise shift for each $400E period index (PAL)
+pub const NOISE_PERIODS_PAL: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+/// Frame sequencer cycle of each step (NTSC); the 4-step sequence restarts
+/// after the fourth, the 5-step one after the fifth
+const FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+/// Frame sequencer cycle of each step (PAL)
+const FRAME_STEPS_PAL: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+/// Get the noise period table of a region (the Dendy uses NTSC's)
+fn noise_periods(region: Region) -> &'static [u16; 16] {
+    match region {
+        Region::Pal => &NOISE_PERIODS_PAL,
+        _ => &NOISE_PERIODS,
+    }
+}
+
+/// Get the frame sequencer steps of a region (the Dendy uses NTSC's)
+fn frame_steps(region: Region) -> &'static [u32; 5] {
+    match region {
+        Region::Pal => &FRAME_STEPS_PAL,
+        _ => &FRAME_STEPS,
+    }
+}
+
+/// DMC bytes the output unit holds on to when the reader runs ahead
+const DMC_QUEUE_LIMIT: usize = 4;
+
+/// Volume envelope shared by the pulse and noise channels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Envelope {
+    /// Restart on the next quarter frame
+    start: bool,
+    /// Loop the decay (also halts the length counter)
+    looping: bool,
+    /// Output `volume` instead of the decay level
+    constant: bool,
+    /// Constant volume, or the divider period
+    volume: u8,
+    divider: u8,
+    /// Decay level (15 down to 0)
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.volume = value & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider > 0 {
+            self.divider -= 1;
+        } else {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.start);
+        writer.write_bool(self.looping);
+        writer.write_bool(self.constant);
+        writer.write_bytes(&[self.volume, self.divider, self.decay]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.start = reader.read_bool()?;
+        self.looping = reader.read_bool()?;
+        self.constant = reader.read_bool()?;
+        self.volume = reader.read_u8()? & 0x0F;
+        self.divider = reader.read_u8()? & 0x0F;
+        self.decay = reader.read_u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+/// Length counter: silences its channel when it runs out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct LengthCounter {
+    value: u8,
+    halt: bool,
+    /// Channel enabled in $4015 (loads are ignored while disabled)
+    enabled: bool,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.value);
+        writer.write_bool(self.halt);
+        writer.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.value = reader.read_u8()?;
+        self.halt = reader.read_bool()?;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+/// Pulse channel ($4000-$4003 or $4004-$4007)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Pulse {
+    /// Pulse 1 negates its sweep in ones' complement (one lower than pulse 2)
+    ones_complement: bool,
+    duty: u8,
+    /// Position in the 8-step duty sequence
+    step: u8,
+    /// 11-bit timer period
+    period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    fn new(ones_complement: bool) -> Self {
+        Self { ones_complement, ..Self::default() }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocked every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Period the sweep unit would switch to
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.period.saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.period + change
+        }
+    }
+
+    /// The sweep unit mutes the channel at periods below 8 or targets above $7FF, even when disabled
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length.value == 0 || self.muted() || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.duty, self.step]);
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        self.envelope.save_state(writer);
+        self.length.save_state(writer);
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+        writer.write_bool(self.sweep_reload);
+        writer.write_u8(self.sweep_divider);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.duty = reader.read_u8()? & 0x03;
+        self.step = reader.read_u8()? & 0x07;
+        self.period = reader.read_u16()? & 0x07FF;
+        self.timer = reader.read_u16()? & 0x07FF;
+        self.envelope.load_state(reader)?;
+        self.length.load_state(reader)?;
+        self.sweep_enabled = reader.read_bool()?;
+        self.sweep_period = reader.read_u8()? & 0x07;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()? & 0x07;
+        self.sweep_reload = reader.read_bool()?;
+        self.sweep_divider = reader.read_u8()? & 0x07;
+        Ok(())
+    }
+}
+
+/// Triangle channel ($4008-$400B)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Triangle {
+    /// Linear counter control (also halts the length counter)
+    control: bool,
+    linear_reload_value: u8,
+    linear: u8,
+    linear_reload: bool,
+    period: u16,
+    timer: u16,
+    /// Position in the 32-step sequence
+    step: u8,
+    length: LengthCounter,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = value & 0x7F;
+            }
+            1 => {}
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle; the sequence holds its step while either counter is 0
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.value > 0 && self.linear > 0 {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_reload_value;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.step as usize]
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.control);
+        writer.write_bytes(&[self.linear_reload_value, self.linear]);
+        writer.write_bool(self.linear_reload);
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.step);
+        self.length.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.control = reader.read_bool()?;
+        self.linear_reload_value = reader.read_u8()? & 0x7F;
+        self.linear = reader.read_u8()? & 0x7F;
+        self.linear_reload = reader.read_bool()?;
+        self.period = reader.read_u16()? & 0x07FF;
+        self.timer = reader.read_u16()? & 0x07FF;
+        self.step = reader.read_u8()? & 0x1F;
+        self.length.load_state(reader)
+    }
+}
+
+/// Noise channel ($400C-$400F)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Noise {
+    /// Short mode: feedback from bit 6 instead of bit 1
+    short: bool,
+    /// Period table of the console's region
+    periods: &'static [u16; 16],
+    /// CPU cycles per shift
+    period: u16,
+    timer: u16,
+    /// 15-bit linear feedback shift register
+    shift: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            short: false,
+            periods: &NOISE_PERIODS,
+            period: NOISE_PERIODS[0],
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.short = value & 0x80 != 0;
+                self.period = self.periods[(value & 0x0F) as usize];
+            }
+            _ => {
+                self.length.load(value >> 3);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Switch to a region's period table, keeping the selected index
+    fn set_region(&mut self, region: Region) {
+        let index = self.periods.iter().position(|&period| period == self.period).unwrap_or(0);
+        self.periods = noise_periods(region);
+        self.period = self.periods[index];
+        self.timer = self.timer.min(self.period - 1);
+    }
+
+    /// Clocked every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period - 1;
+        let tap = if self.short { 6 } else { 1 };
+        let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+        self.shift = (self.shift >> 1) | (feedback << 14);
+    }
+
+    fn output(&self) -> u8 {
+        if self.length.value == 0 || self.shift & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.short);
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        writer.write_u16(self.shift);
+        self.envelope.save_state(writer);
+        self.length.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.short = reader.read_bool()?;
+        let period = reader.read_u16()?;
+        if !self.periods.contains(&period) {
+            return Err(StateError::InvalidData("noise period"));
+        }
+        self.period = period;
+        self.timer = reader.read_u16()?.min(period - 1);
+        self.shift = reader.read_u16()? & 0x7FFF;
+        self.envelope.load_state(reader)?;
+        self.length.load_state(reader)
+    }
+}
+
+/// DMC output unit ($4010-$4011); the sample reader is `dma::DmcDma`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dmc {
+    /// Rate table of the console's region
+    rates: &'static [u16; 16],
+    /// CPU cycles per output bit
+    period: u16,
+    timer: u16,
+    /// 7-bit output level
+    level: u8,
+    /// Bits of the current byte, played LSB first
+    shift: u8,
+    bits_remaining: u8,
+    /// No byte was available when the output cycle started
+    silence: bool,
+    /// Bytes fetched by the reader, oldest first
+    samples: Vec<u8>,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            rates: &DMC_RATES,
+            period: DMC_RATES[0],
+            timer: DMC_RATES[0],
+            level: 0,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl Dmc {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.period = self.rates[(value & 0x0F) as usize],
+            1 => self.level = value & 0x7F,
+            _ => {}
+        }
+    }
+
+    /// Switch to a region's rate table, keeping the selected index
+    fn set_region(&mut self, region: Region) {
+        let index = self.rates.iter().position(|&rate| rate == self.period).unwrap_or(0);
+        self.rates = dmc_rates(region);
+        self.period = self.rates[index];
+        self.timer = self.timer.min(self.period);
+    }
+
+    /// Clocked every CPU cycle; each bit moves the level up or down by 2
+    fn clock_timer(&mut self) {
+        self.timer -= 1;
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period;
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            self.silence = self.samples.is_empty();
+            if !self.silence {
+                self.shift = self.samples.remove(0);
+            }
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        writer.write_bytes(&[self.level, self.shift, self.bits_remaining]);
+        writer.write_bool(self.silence);
+        writer.write_u8(self.samples.len() as u8);
+        for &byte in &self.samples {
+            writer.write_u8(byte);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let period = reader.read_u16()?;
+        let timer = reader.read_u16()?;
+        if !self.rates.contains(&period) || timer == 0 {
+            return Err(StateError::InvalidData("DMC timer"));
+        }
+        (self.period, self.timer) = (period, timer);
+        self.level = reader.read_u8()? & 0x7F;
+        self.shift = reader.read_u8()?;
+        self.bits_remaining = reader.read_u8()?;
+        if !(1..=8).contains(&self.bits_remaining) {
+            return Err(StateError::InvalidData("DMC timer"));
+        }
+        self.silence = reader.read_bool()?;
+        let queued = reader.read_u8()? as usize;
+        if queued > DMC_QUEUE_LIMIT {
+            return Err(StateError::InvalidData("DMC sample queue"));
+        }
+        self.samples.clear();
+        for _ in 0..queued {
+            self.samples.push(reader.read_u8()?);
+        }
+        Ok(())
+    }
 }
 
 /// APU state
 #[derive(Debug, Clone)]
 pub struct Apu {
-    /// APU registers
+    /// APU registers (last value written)
     registers: [u8; APU_REGISTER_COUNT],
     /// Cycle counter for timing
     cycle_count: u64,
-    /// Frame counter state
-    frame_counter: u8,
-    /// Frame counter increment period
-    frame_period: u8,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    /// CPU cycles into the frame sequence
+    frame_cycle: u32,
+    /// 5-step sequence selected in $4017
+    five_step: bool,
+    /// CPU cycles until a $4017 write restarts the frame sequence (0 when none is pending)
+    frame_reset_delay: u8,
+    /// The frame IRQ is holding the line
+    frame_irq: bool,
+    /// Console region, which sets the CPU clock and the timing tables
+    region: Region,
+    /// Collect output samples
+    audio_enabled: bool,
+    /// Output sample rate in Hz
+    sample_rate: u32,
+    /// Progress towards the next output sample, in units of 1 / (CPU clock * sample rate) seconds
+    sample_phase: u32,
+    /// Sum of mixed levels since the last output sample, and the cycles it covers
+    sample_sum: u64,
+    sample_cycles: u32,
+    /// Output samples since the last take
+    samples: Vec<i16>,
 }
 
 impl Apu {
@@ -76,31 +656,152 @@ impl Apu {
         Self {
             registers: [0; APU_REGISTER_COUNT],
             cycle_count: 0,
-            frame_counter: 0,
-            frame_period: 0,
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_cycle: 0,
+            five_step: false,
+            frame_reset_delay: 0,
+            frame_irq: false,
+            region: Region::Ntsc,
+            audio_enabled: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_phase: 0,
+            sample_sum: 0,
+            sample_cycles: 0,
+            samples: Vec::new(),
         }
     }
 
-    /// Reset the APU
+    /// Reset the APU (the region and audio output settings are kept)
     pub fn reset(&mut self) {
-        self.registers = [0; APU_REGISTER_COUNT];
-        self.cycle_count = 0;
-        self.frame_counter = 0;
-        self.frame_period = 0;
+        let (region, audio_enabled, sample_rate) = (self.region, self.audio_enabled, self.sample_rate);
+        *self = Self::new();
+        self.set_region(region);
+        self.audio_enabled = audio_enabled;
+        self.sample_rate = sample_rate;
+    }
+
+    /// Get the console region
+    #[cfg(test)]
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Use a region's CPU clock, frame sequencer steps, noise periods and DMC rates
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+        self.sample_rate = self.sample_rate.min(region.cpu_clock());
     }
 
     /// Step the APU by the given number of cycles
-    pub fn step(&mut self, cycles: u8) {
-        self.cycle_count += cycles as u64;
-        self.frame_counter = self.frame_counter.wrapping_add(cycles);
+    pub fn step(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.clock();
+        }
+    }
+
+    fn clock(&mut self) {
+        self.cycle_count += 1;
+        self.clock_frame_sequencer();
+        if self.cycle_count & 1 == 0 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.audio_enabled {
+            self.sample_sum += mixer::mix(self.channel_levels()) as u64;
+            self.sample_cycles += 1;
+            self.sample_phase += self.sample_rate;
+            let cpu_clock = self.region.cpu_clock();
+            if self.sample_phase >= cpu_clock {
+                self.sample_phase -= cpu_clock;
+                self.samples.push((self.sample_sum / self.sample_cycles as u64) as i16);
+                self.sample_sum = 0;
+                self.sample_cycles = 0;
+            }
+        }
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                // Restart the sequence; entering 5-step mode clocks the units at once
+                self.five_step = self.registers[0x17] & 0x80 != 0;
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.half_frame();
+                }
+                return;
+            }
+        }
+        let steps = frame_steps(self.region);
+        self.frame_cycle += 1;
+        match self.frame_cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => self.quarter_frame(),
+            cycle if cycle == steps[1] || cycle == steps[4] => self.half_frame(),
+            cycle if cycle == steps[3] && !self.five_step => self.half_frame(),
+            _ => {}
+        }
+        // The 4-step sequence sets the IRQ flag on the cycles either side of
+        // its last step too, so acknowledging it on that step doesn't stick
+        if !self.five_step && self.frame_cycle.abs_diff(steps[3]) <= 1 {
+            self.frame_irq |= !self.frame_irq_inhibited();
+        }
+        let length = if self.five_step { steps[4] } else { steps[3] };
+        if self.frame_cycle > length {
+            self.frame_cycle = 0;
+        }
+    }
+
+    /// Check if $4017 bit 6 inhibits the frame IRQ
+    fn frame_irq_inhibited(&self) -> bool {
+        self.registers[0x17] & 0x40 != 0
+    }
+
+    /// Check if the frame IRQ is holding the line
+    pub fn frame_irq_pending(&self) -> bool {
+        self.frame_irq
+    }
+
+    /// Release the frame IRQ, as a $4015 read does
+    pub fn acknowledge_frame_irq(&mut self) {
+        self.frame_irq = false;
+    }
+
+    /// Envelopes and the triangle's linear counter
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    /// A quarter frame plus the length counters and sweeps
+    fn half_frame(&mut self) {
+        self.quarter_frame();
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
     }
 
     /// Get the current cycle count
+    #[cfg(test)]
     pub fn cycle_count(&self) -> u64 {
         self.cycle_count
     }
 
-    /// Read an APU register
+    /// Read back the last value written to an APU register
     pub fn read(&self, address: u16) -> u8 {
         let offset = (address - 0x4000) as usize;
         if offset < APU_REGISTER_COUNT {
@@ -113,21 +814,159 @@ impl Apu {
     /// Write to an APU register
     pub fn write(&mut self, address: u16, value: u8) {
         let offset = (address - 0x4000) as usize;
-        if offset < APU_REGISTER_COUNT {
-            self.registers[offset] = value;
+        if offset >= APU_REGISTER_COUNT {
+            return;
+        }
+        self.registers[offset] = value;
+        let register = address & 0x03;
+        match address {
+            0x4000..=0x4003 => self.pulse1.write(register, value),
+            0x4004..=0x4007 => self.pulse2.write(register, value),
+            0x4008..=0x400B => self.triangle.write(register, value),
+            0x400C..=0x400F => self.noise.write(register, value),
+            0x4010..=0x4013 => self.dmc.write(register, value),
+            0x4015 => {
+                self.pulse1.length.set_enabled(value & 0x01 != 0);
+                self.pulse2.length.set_enabled(value & 0x02 != 0);
+                self.triangle.length.set_enabled(value & 0x04 != 0);
+                self.noise.length.set_enabled(value & 0x08 != 0);
+            }
+            0x4017 => {
+                // The inhibit bit takes effect at once; the sequence restarts
+                // 3 CPU cycles later if the write lands on an APU cycle, 4 if
+                // it lands between two
+                self.frame_irq &= !self.frame_irq_inhibited();
+                self.frame_reset_delay = if self.cycle_count.is_multiple_of(2) { 3 } else { 4 };
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the APU's bits of $4015: bit 0-3 set while pulse 1, pulse 2,
+    /// triangle and noise are sounding, bit 6 while the frame IRQ is pending
+    /// (the DMC bits come from its reader)
+    pub fn status(&self) -> u8 {
+        [self.pulse1.length, self.pulse2.length, self.triangle.length, self.noise.length]
+            .iter()
+            .enumerate()
+            .fold((self.frame_irq as u8) << 6, |status, (bit, length)| status | ((length.value > 0) as u8) << bit)
+    }
+
+    /// Queue a sample byte fetched by the DMC reader for the output unit
+    pub fn push_dmc_sample(&mut self, byte: u8) {
+        if self.dmc.samples.len() == DMC_QUEUE_LIMIT {
+            self.dmc.samples.remove(0);
+        }
+        self.dmc.samples.push(byte);
+    }
+
+    /// Get the current output level of each channel
+    ///
+    /// `apu_script` records this per sample, so each channel's golden output
+    /// changes on its own.
+    pub fn channel_levels(&self) -> ChannelLevels {
+        ChannelLevels {
+            pulse1: self.pulse1.output(),
+            pulse2: self.pulse2.output(),
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.level,
         }
     }
 
-    /// Get the duration of a frame in CPU cycles
-    pub fn frame_duration(&self) -> u64 {
-        // NTSC: 29780 cycles per frame (59.94Hz)
-        // PAL: 33240 cycles per frame (50Hz)
-        29780
+    /// Turn output sample collection on or off (off by default)
+    pub fn set_audio_enabled(&mut self, enabled: bool) {
+        self.audio_enabled = enabled;
+        if !enabled {
+            self.samples.clear();
+            self.sample_sum = 0;
+            self.sample_cycles = 0;
+        }
+    }
+
+    /// Check if output samples are collected
+    #[cfg(test)]
+    pub fn audio_enabled(&self) -> bool {
+        self.audio_enabled
+    }
+
+    /// Set the output sample rate in Hz (clamped to 1 Hz up to the CPU clock)
+    ///
+    /// The resampler carries on from where it was, so frontends can nudge the
+    /// rate every frame to match their audio device's clock.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate.clamp(1, self.region.cpu_clock());
+    }
+
+    /// Get the output sample rate in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Take the mono samples (mixer levels) produced since the last call
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Serialize the APU state, including the timing counters that keep
+    /// channel phase, frame sequencing and the resampler continuous across
+    /// a reload
+    ///
+    /// Output samples not yet taken aren't included.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_u64(self.cycle_count);
+        self.save_channels(writer);
+        self.save_frame_irq(writer);
+        self.save_resampler(writer);
+        self.save_frame_reset(writer);
+    }
+
+    /// Serialize the resampler's progress towards the next output sample (the part savestate v4 added)
+    pub(crate) fn save_resampler(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.sample_phase);
+        writer.write_u64(self.sample_sum);
+        writer.write_u32(self.sample_cycles);
+    }
+
+    /// Serialize the pending $4017 restart (the part savestate v5 added)
+    pub(crate) fn save_frame_reset(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.frame_reset_delay);
+    }
+
+    /// Serialize the frame IRQ flag (the part savestate v3 added)
+    pub(crate) fn save_frame_irq(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.frame_irq);
+    }
+
+    /// Serialize the channels and frame sequencer (the part savestate v2 added)
+    pub(crate) fn save_channels(&self, writer: &mut StateWriter) {
+        self.pulse1.save_state(writer);
+        self.pulse2.save_state(writer);
+        self.triangle.save_state(writer);
+        self.noise.save_state(writer);
+        self.dmc.save_state(writer);
+        writer.write_u32(self.frame_cycle);
+        writer.write_bool(self.five_step);
     }
 
-    /// Get the duration of a half-frame
-    pub fn half_frame_duration(&self) -> u64 {
-        self.frame_duration() / 2
+    /// Restore state written by `save_state`
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.registers)?;
+        self.cycle_count = reader.read_u64()?;
+        self.pulse1.load_state(reader)?;
+        self.pulse2.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.dmc.load_state(reader)?;
+        self.frame_cycle = reader.read_u32()?.min(frame_steps(self.region)[4]);
+        self.five_step = reader.read_bool()?;
+        self.frame_irq = reader.read_bool()?;
+        self.sample_phase = reader.read_u32()?.min(self.region.cpu_clock() - 1);
+        self.sample_sum = reader.read_u64()?;
+        self.sample_cycles = reader.read_u32()?;
+        self.frame_reset_delay = reader.read_u8()?.min(4);
+        Ok(())
     }
 }
 
@@ -140,6 +979,7 @@ impl Default for Apu {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dma::DMC_RATES_PAL;
 
     #[test]
     fn test_apu_reset() {
@@ -161,4 +1001,259 @@ mod tests {
         apu.write(0x4000, 0x42);
         assert_eq!(apu.read(0x4000), 0x42);
     }
+
+    #[test]
+    fn test_apu_state_round_trip() {
+        let mut apu = Apu::new();
+        apu.write(0x4002, 0xAB);
+        apu.step(200);
+        apu.step(100);
+
+        let mut writer = StateWriter::new();
+        apu.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let mut restored = Apu::new();
+        restored.load_state(&mut StateReader::new(&bytes)).unwrap();
+        assert_eq!(restored.read(0x4002), 0xAB);
+        assert_eq!(restored.cycle_count(), 300);
+        assert_eq!(restored.frame_cycle, apu.frame_cycle);
+        assert_eq!(restored.pulse1, apu.pulse1);
+        assert_eq!(restored.channel_levels(), apu.channel_levels());
+
+        // Truncated data is rejected rather than partially applied silently
+        let mut short = Apu::new();
+        assert_eq!(short.load_state(&mut StateReader::new(&bytes[..10])), Err(StateError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_state_keeps_resampler_progress() {
+        let mut apu = Apu::new();
+        apu.set_audio_enabled(true);
+        apu.write(0x4015, 0x01);
+        apu.write(0x4000, 0xBF);
+        apu.write(0x4002, 0xFD);
+        apu.write(0x4003, 0x08);
+        // Stop partway through an output sample
+        apu.step(1000);
+        apu.take_samples();
+        assert!(apu.sample_cycles > 0);
+
+        let mut writer = StateWriter::new();
+        apu.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut restored = Apu::new();
+        restored.set_audio_enabled(true);
+        restored.load_state(&mut StateReader::new(&bytes)).unwrap();
+
+        apu.step(100);
+        restored.step(100);
+        let next = apu.take_samples();
+        assert!(!next.is_empty());
+        assert_eq!(restored.take_samples(), next);
+    }
+
+    #[test]
+    fn test_pulse_plays_duty_at_constant_volume() {
+        let mut apu = Apu::new();
+        apu.write(0x4015, 0x01);
+        // 50% duty, constant volume 9, period 100 (200 CPU cycles per step)
+        apu.write(0x4000, 0xB9);
+        apu.write(0x4002, 100);
+        apu.write(0x4003, 0x08);
+        let levels: Vec<u8> = (0..16)
+            .map(|_| {
+                apu.step(202);
+                apu.channel_levels().pulse1
+            })
+            .collect();
+        assert!(levels.contains(&9) && levels.contains(&0));
+        assert!(levels.iter().all(|&level| level == 0 || level == 9));
+
+        // Periods below 8 are muted by the sweep unit
+        apu.write(0x4002, 7);
+        apu.write(0x4003, 0x08);
+        assert!((0..64).all(|_| {
+            apu.step(3);
+            apu.channel_levels().pulse1 == 0
+        }));
+    }
+
+    #[test]
+    fn test_length_counters_and_status() {
+        let mut apu = Apu::new();
+        // Loads are ignored while the channel is disabled
+        apu.write(0x4003, 0x08);
+        assert_eq!(apu.status(), 0x00);
+
+        apu.write(0x4015, 0x0F);
+        apu.write(0x4003, 0x18); // length index 3: 2 half frames
+        apu.write(0x400F, 0x08); // length index 1: 254
+        assert_eq!(apu.status(), 0x09);
+
+        // The 4-step sequence also raised the frame IRQ
+        apu.step(FRAME_STEPS[3]);
+        assert_eq!(apu.status(), 0x48);
+        apu.acknowledge_frame_irq();
+
+        apu.write(0x4015, 0x00);
+        assert_eq!(apu.status(), 0x00);
+
+        // Setting $4017 bit 6 inhibits and clears it
+        apu.step(FRAME_STEPS[4]);
+        assert!(apu.frame_irq_pending());
+        apu.write(0x4017, 0x40);
+        assert!(!apu.frame_irq_pending());
+        apu.step(FRAME_STEPS[4]);
+        assert_eq!(apu.status(), 0x00);
+    }
+
+    #[test]
+    fn test_five_step_sequence_clocks_on_restart() {
+        let mut apu = Apu::new();
+        apu.write(0x4015, 0x01);
+        apu.write(0x4003, 0x18);
+        apu.write(0x4017, 0x80);
+        apu.step(3);
+        apu.write(0x4017, 0x80);
+        apu.step(4);
+        assert_eq!(apu.status(), 0x00);
+
+        // The first half frame of the 5-step sequence is at the same cycle as in the 4-step one
+        apu.write(0x4003, 0x18);
+        apu.step(FRAME_STEPS[1] - 1);
+        assert_eq!(apu.status(), 0x01);
+        apu.step(1);
+        assert_eq!(apu.status(), 0x01);
+        apu.step(FRAME_STEPS[4] - FRAME_STEPS[1]);
+        assert_eq!(apu.status(), 0x00);
+    }
+
+    #[test]
+    fn test_frame_irq_set_for_three_cycles() {
+        let mut apu = Apu::new();
+        apu.step(FRAME_STEPS[3] - 2);
+        assert!(!apu.frame_irq_pending());
+        apu.step(1);
+        assert!(apu.frame_irq_pending());
+
+        // Acknowledging on the last step doesn't stick: wrapping sets the flag again
+        apu.step(1);
+        apu.acknowledge_frame_irq();
+        apu.step(1);
+        assert!(apu.frame_irq_pending());
+        assert_eq!(apu.frame_cycle, 0);
+        apu.acknowledge_frame_irq();
+        apu.step(FRAME_STEPS[3] - 2);
+        assert!(!apu.frame_irq_pending());
+    }
+
+    #[test]
+    fn test_4017_write_restarts_sequence_after_delay() {
+        let mut apu = Apu::new();
+        apu.write(0x4015, 0x01);
+        apu.write(0x4003, 0x18);
+        apu.step(1001);
+
+        // Written between APU cycles (after an odd CPU cycle), the sequence restarts 4 cycles later
+        apu.write(0x4017, 0x80);
+        apu.step(3);
+        assert_eq!(apu.frame_cycle, 1004);
+        assert_eq!(apu.pulse1.length.value, 2);
+        apu.step(1);
+        assert_eq!(apu.frame_cycle, 0);
+        // Entering 5-step mode clocked the half frame units
+        assert_eq!(apu.pulse1.length.value, 1);
+
+        // On an APU cycle it takes 3
+        apu.step(1);
+        apu.write(0x4017, 0x00);
+        apu.step(2);
+        assert_eq!(apu.frame_cycle, 3);
+        apu.step(1);
+        assert_eq!(apu.frame_cycle, 0);
+        assert!(!apu.five_step);
+        assert_eq!(apu.pulse1.length.value, 1);
+
+        // The 5-step sequence never raises the frame IRQ
+        apu.write(0x4017, 0x80);
+        apu.step(FRAME_STEPS[4] * 2);
+        assert!(!apu.frame_irq_pending());
+
+        // A pending restart survives a savestate
+        apu.write(0x4017, 0x00);
+        let mut writer = StateWriter::new();
+        apu.save_state(&mut writer);
+        let mut restored = Apu::new();
+        restored.load_state(&mut StateReader::new(&writer.into_bytes())).unwrap();
+        restored.step(3);
+        assert!(restored.five_step);
+        restored.step(1);
+        assert_eq!(restored.frame_cycle, 0);
+        assert!(!restored.five_step);
+    }
+
+    #[test]
+    fn test_dmc_plays_pushed_samples() {
+        let mut apu = Apu::new();
+        apu.write(0x4011, 0x40);
+        apu.push_dmc_sample(0xFF);
+        // The output unit takes the byte when its current (silent) cycle ends
+        apu.step(DMC_RATES[0] as u32 * 8);
+        assert_eq!(apu.channel_levels().dmc, 0x40);
+        apu.step(DMC_RATES[0] as u32 * 8);
+        assert_eq!(apu.channel_levels().dmc, 0x40 + 16);
+    }
+
+    #[test]
+    fn test_samples_at_output_rate() {
+        let mut apu = Apu::new();
+        apu.step(1000);
+        assert!(apu.take_samples().is_empty());
+
+        apu.set_audio_enabled(true);
+        apu.write(0x4015, 0x01);
+        apu.write(0x4000, 0xBF);
+        apu.write(0x4002, 0xFD);
+        apu.write(0x4003, 0x08);
+        apu.step(Region::Ntsc.cpu_clock());
+        let samples = apu.take_samples();
+        assert_eq!(samples.len(), DEFAULT_SAMPLE_RATE as usize);
+        assert!(samples.iter().all(|&sample| (0..=mixer::MIX_SCALE as i16).contains(&sample)));
+        assert!(samples.iter().any(|&sample| sample > 0));
+
+        apu.set_sample_rate(48_000);
+        apu.step(Region::Ntsc.cpu_clock() / 10);
+        assert_eq!(apu.take_samples().len(), 4_799);
+
+        // Reset keeps the output settings
+        apu.reset();
+        assert!(apu.audio_enabled());
+        assert_eq!(apu.sample_rate(), 48_000);
+    }
+
+    #[test]
+    fn test_pal_timing_tables() {
+        let mut apu = Apu::new();
+        apu.set_region(Region::Pal);
+        apu.write(0x400E, 0x04);
+        apu.write(0x4010, 0x0F);
+        assert_eq!(apu.noise.period, NOISE_PERIODS_PAL[4]);
+        assert_eq!(apu.dmc.period, DMC_RATES_PAL[15]);
+
+        // The second half frame comes later than on NTSC
+        apu.write(0x4015, 0x01);
+        apu.write(0x4003, 0x18);
+        apu.step(FRAME_STEPS[3]);
+        assert_eq!(apu.status(), 0x01);
+        apu.step(FRAME_STEPS_PAL[3] - FRAME_STEPS[3]);
+        assert_eq!(apu.status(), 0x40);
+
+        // Reset keeps the region, and a second of PAL cycles is a second of samples
+        apu.reset();
+        assert_eq!(apu.region(), Region::Pal);
+        apu.set_audio_enabled(true);
+        apu.step(Region::Pal.cpu_clock());
+        assert_eq!(apu.take_samples().len(), DEFAULT_SAMPLE_RATE as usize);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,263 @@
+//! Scripted APU register tests with golden output
+//!
+//! A script writes APU registers at exact CPU cycles and samples every
+//! channel's output level at a fixed period, with no CPU involved, so a
+//! channel (sweep, envelope, length counter, DMC) can be checked on its own.
+//! The level stream is compared with golden data stored next to the script:
+//!
+//! ```text
+//! # Pulse 1 at a constant volume of 15
+//! period 40            # CPU cycles per sample
+//! length 29780         # CPU cycles to run
+//! 0    $4015 $01       # cycle, address, value
+//! 0    $4000 $BF
+//! 100  $4003 $08
+//! ```
+//!
+//! Numbers are decimal or `$` hex, `#` starts a comment, and writes must be
+//! in cycle order. Writes land before the sample taken on the same cycle.
+//!
+//! Golden data is run-length encoded, one line per run of identical samples:
+//! `count pulse1 pulse2 triangle noise dmc`. Keeping channels apart (rather
+//! than the mixed sample) means a change to one channel only touches that
+//! column and a mismatch names the channel. `tests/apu_scripts.rs` runs
+//! every script under `tests/apu/`; set `NES_BLESS=1` to rewrite the golden
+//! files after an intended change, and review the diff.
+
+use crate::apu::Apu;
+use crate::mixer::ChannelLevels;
+use std::fmt;
+
+/// Default CPU cycles per sample (about 44.7 kHz)
+pub const DEFAULT_SAMPLE_PERIOD: u32 = 40;
+
+/// Channel names, in golden column order
+pub const CHANNELS: [&str; 5] = ["pulse1", "pulse2", "triangle", "noise", "dmc"];
+
+/// A register write at a CPU cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptWrite {
+    pub cycle: u64,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Parsed register script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApuScript {
+    /// Writes in cycle order
+    pub writes: Vec<ScriptWrite>,
+    /// CPU cycles per sample
+    pub sample_period: u32,
+    /// CPU cycles to run
+    pub length: u64,
+}
+
+/// Error reading a script or golden file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScriptError {
+    /// A line (1-based) could not be parsed
+    InvalidLine { line: usize, reason: &'static str },
+    /// The script has no `length` line
+    MissingLength,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::InvalidLine { line, reason } => write!(f, "Invalid script line {}: {}", line, reason),
+            ScriptError::MissingLength => write!(f, "Script has no length"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// First difference between a golden stream and a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Mismatch {
+    /// The streams have different numbers of samples
+    Length { expected: usize, actual: usize },
+    /// A channel's level differs
+    Level { sample: usize, cycle: u64, channel: &'static str, expected: u8, actual: u8 },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Length { expected, actual } => write!(f, "{} samples, golden has {}", actual, expected),
+            Mismatch::Level { sample, cycle, channel, expected, actual } => {
+                write!(f, "sample {} (cycle {}): {} is {}, golden has {}", sample, cycle, channel, actual, expected)
+            }
+        }
+    }
+}
+
+impl ApuScript {
+    /// Parse a script
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut writes: Vec<ScriptWrite> = Vec::new();
+        let mut sample_period = DEFAULT_SAMPLE_PERIOD;
+        let mut length = None;
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason| ScriptError::InvalidLine { line: index + 1, reason };
+            let fields: Vec<&str> = line.split('#').next().unwrap_or_default().split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["period", value] => {
+                    sample_period = number(value).filter(|&n| (1..=u32::MAX as u64).contains(&n)).ok_or(invalid("bad period"))? as u32;
+                }
+                ["length", value] => length = Some(number(value).ok_or(invalid("bad length"))?),
+                [cycle, address, value] => {
+                    let cycle = number(cycle).ok_or(invalid("bad cycle"))?;
+                    let address = number(address).filter(|a| (0x4000..=0x4017).contains(a)).ok_or(invalid("not an APU register"))?;
+                    let value = number(value).filter(|&v| v <= 0xFF).ok_or(invalid("bad value"))?;
+                    if writes.last().is_some_and(|last| last.cycle > cycle) {
+                        return Err(invalid("writes out of cycle order"));
+                    }
+                    writes.push(ScriptWrite { cycle, address: address as u16, value: value as u8 });
+                }
+                _ => return Err(invalid("expected `cycle address value`, `period n` or `length n`")),
+            }
+        }
+        let length = length.ok_or(ScriptError::MissingLength)?;
+        Ok(Self { writes, sample_period, length })
+    }
+
+    /// Run the script on a fresh APU, returning a sample every `sample_period` cycles
+    pub fn run(&self) -> Vec<ChannelLevels> {
+        self.run_on(&mut Apu::new())
+    }
+
+    /// Run the script on the given APU
+    fn run_on(&self, apu: &mut Apu) -> Vec<ChannelLevels> {
+        let period = self.sample_period as u64;
+        let mut samples = Vec::with_capacity((self.length / period) as usize);
+        let mut writes = self.writes.iter().peekable();
+        let mut cycle = 0;
+        for sample in 0..self.length.div_ceil(period) {
+            let sample_cycle = sample * period;
+            while let Some(write) = writes.next_if(|write| write.cycle <= sample_cycle) {
+                step(apu, write.cycle - cycle);
+                cycle = write.cycle;
+                apu.write(write.address, write.value);
+            }
+            step(apu, sample_cycle - cycle);
+            cycle = sample_cycle;
+            samples.push(apu.channel_levels());
+        }
+        samples
+    }
+}
+
+/// Step the APU by any number of cycles
+fn step(apu: &mut Apu, cycles: u64) {
+    let mut left = cycles;
+    while left > 0 {
+        let chunk = left.min(u32::MAX as u64);
+        apu.step(chunk as u32);
+        left -= chunk;
+    }
+}
+
+/// Parse a decimal or `$` hex number
+fn number(text: &str) -> Option<u64> {
+    match text.strip_prefix('$') {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn columns(levels: &ChannelLevels) -> [u8; 5] {
+    [levels.pulse1, levels.pulse2, levels.triangle, levels.noise, levels.dmc]
+}
+
+/// Encode samples as golden data
+pub fn encode_golden(samples: &[ChannelLevels]) -> String {
+    let mut text = String::from("# count pulse1 pulse2 triangle noise dmc\n");
+    for run in samples.chunk_by(|a, b| a == b) {
+        let [p1, p2, tri, noise, dmc] = columns(&run[0]);
+        text.push_str(&format!("{} {} {} {} {} {}\n", run.len(), p1, p2, tri, noise, dmc));
+    }
+    text
+}
+
+/// Decode golden data
+pub fn decode_golden(text: &str) -> Result<Vec<ChannelLevels>, ScriptError> {
+    let mut samples = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split('#').next().unwrap_or_default().split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let invalid = ScriptError::InvalidLine { line: index + 1, reason: "expected `count pulse1 pulse2 triangle noise dmc`" };
+        let values: Vec<u64> = fields.iter().map(|field| field.parse().ok()).collect::<Option<_>>().ok_or(invalid.clone())?;
+        let [count, p1, p2, tri, noise, dmc] = values[..] else {
+            return Err(invalid);
+        };
+        if [p1, p2, tri, noise, dmc].iter().any(|&level| level > 0xFF) {
+            return Err(invalid);
+        }
+        let levels =
+            ChannelLevels { pulse1: p1 as u8, pulse2: p2 as u8, triangle: tri as u8, noise: noise as u8, dmc: dmc as u8 };
+        samples.extend(std::iter::repeat_n(levels, count as usize));
+    }
+    Ok(samples)
+}
+
+/// Find the first difference between golden and actual samples
+pub fn compare(expected: &[ChannelLevels], actual: &[ChannelLevels], sample_period: u32) -> Option<Mismatch> {
+    for (sample, (want, got)) in expected.iter().zip(actual).enumerate() {
+        let (want, got) = (columns(want), columns(got));
+        if let Some(channel) = (0..CHANNELS.len()).find(|&i| want[i] != got[i]) {
+            return Some(Mismatch::Level {
+                sample,
+                cycle: sample as u64 * sample_period as u64,
+                channel: CHANNELS[channel],
+                expected: want[channel],
+                actual: got[channel],
+            });
+        }
+    }
+    (expected.len() != actual.len()).then_some(Mismatch::Length { expected: expected.len(), actual: actual.len() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = ApuScript::parse("# test\nperiod $10\n0 $4015 $01\n16 16384 191 # pulse 1\nlength 100\n").unwrap();
+        assert_eq!(script.sample_period, 16);
+        assert_eq!(script.length, 100);
+        assert_eq!(script.writes[1], ScriptWrite { cycle: 16, address: 0x4000, value: 0xBF });
+        assert_eq!(script.run().len(), 7);
+
+        let error = |text| ApuScript::parse(text).unwrap_err();
+        assert_eq!(error("0 $4015 $01"), ScriptError::MissingLength);
+        assert_eq!(error("10 $4000 1\n5 $4000 2"), ScriptError::InvalidLine { line: 2, reason: "writes out of cycle order" });
+        assert_eq!(error("0 $2000 1"), ScriptError::InvalidLine { line: 1, reason: "not an APU register" });
+        assert_eq!(error("0 $4000 $100"), ScriptError::InvalidLine { line: 1, reason: "bad value" });
+    }
+
+    #[test]
+    fn test_golden_round_trip_and_compare() {
+        let quiet = ChannelLevels::default();
+        let loud = ChannelLevels { pulse2: 9, ..quiet };
+        let samples = [quiet, quiet, loud, quiet];
+        let golden = encode_golden(&samples);
+        assert_eq!(golden.lines().count(), 4);
+        assert_eq!(decode_golden(&golden).unwrap(), samples);
+
+        assert_eq!(compare(&samples, &samples, 40), None);
+        assert_eq!(
+            compare(&samples, &[quiet; 4], 40),
+            Some(Mismatch::Level { sample: 2, cycle: 80, channel: "pulse2", expected: 9, actual: 0 })
+        );
+        assert_eq!(compare(&samples, &samples[..3], 40), Some(Mismatch::Length { expected: 4, actual: 3 }));
+        assert!(decode_golden("2 0 0 0 0").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Pairing recorded frames with audio for WAV + frame sequence captures
+//!
+//! A capture that writes frames and samples separately drifts apart when the
+//! frontend's audio blocks don't line up with frames: a few samples too many
+//! or too few per frame add up to seconds over a long recording. `AvMux`
+//! buffers both streams and hands out each frame with exactly the number of
+//! samples that belongs to it.
+//!
+//! Frame `n` ends at sample `round(n * sample_rate / frame_rate)`, computed
+//! in integers, so rounding errors never accumulate (like `gif::frame_delay`).
+//! At NTSC's 60.0988 Hz and 44.1 kHz that is 734 or 733 samples per frame,
+//! about 733.8 on average; `with_frame_rate(60, 1)` gives a flat 735 for
+//! output that assumes 60 Hz. `stats` reports how far the audio fed in runs
+//! ahead of or behind the frames.
+
+use crate::ppu::FrameSnapshot;
+use std::collections::VecDeque;
+
+/// NTSC frame rate as a ratio: CPU clock / 29780.5 CPU cycles per frame
+pub const NTSC_FRAME_RATE_RATIO: (u64, u64) = (2 * 1_789_773, 59_561);
+
+/// Cadence of samples per frame for a sample rate and frame rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleCadence {
+    sample_rate: u32,
+    /// Frames per second as numerator / denominator
+    frame_rate: (u64, u64),
+}
+
+impl SampleCadence {
+    /// Create a cadence for `sample_rate` Hz at the NTSC frame rate
+    pub fn ntsc(sample_rate: u32) -> Self {
+        Self { sample_rate, frame_rate: NTSC_FRAME_RATE_RATIO }
+    }
+
+    /// Create a cadence for `sample_rate` Hz at `numerator / denominator` frames per second
+    pub fn new(sample_rate: u32, numerator: u64, denominator: u64) -> Self {
+        Self { sample_rate, frame_rate: (numerator.max(1), denominator.max(1)) }
+    }
+
+    /// Get the sample rate in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Get the sample that ends `frames` frames (rounded to the nearest sample)
+    pub fn end_sample(&self, frames: u64) -> u64 {
+        let (numerator, denominator) = self.frame_rate;
+        let scaled = frames as u128 * self.sample_rate as u128 * denominator as u128;
+        ((scaled * 2 + numerator as u128) / (numerator as u128 * 2)) as u64
+    }
+
+    /// Get the number of samples belonging to frame `index`
+    pub fn samples_for_frame(&self, index: u64) -> usize {
+        (self.end_sample(index + 1) - self.end_sample(index)) as usize
+    }
+}
+
+/// A frame with the samples that play during it
+#[derive(Debug, Clone)]
+pub struct MuxedFrame {
+    /// Index of the frame since the mux was created
+    pub index: u64,
+    /// Time of the frame's first sample, in seconds
+    pub timestamp: f64,
+    /// The frame
+    pub frame: FrameSnapshot,
+    /// Exactly `SampleCadence::samples_for_frame(index)` samples
+    pub samples: Vec<f32>,
+}
+
+/// How the audio fed in compares to the frames
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DriftStats {
+    /// Frames handed out
+    pub frames: u64,
+    /// Samples received from `push_samples`
+    pub samples_in: u64,
+    /// Samples handed out with frames (received plus padding)
+    pub samples_out: u64,
+    /// Silent samples added because the audio ran short at `finish`
+    pub padded: u64,
+    /// Received samples left over after the last frame at `finish`
+    pub dropped: u64,
+    /// Samples received minus samples due for the frames pushed so far
+    /// (positive when the audio runs ahead)
+    pub drift: i64,
+    /// Largest `drift` seen, either way
+    pub max_drift: i64,
+}
+
+impl DriftStats {
+    /// Get the current drift in seconds at `sample_rate`
+    pub fn drift_seconds(&self, sample_rate: u32) -> f64 {
+        self.drift as f64 / sample_rate.max(1) as f64
+    }
+}
+
+/// Pairs video frames with their share of the audio stream
+#[derive(Debug, Clone)]
+pub struct AvMux {
+    cadence: SampleCadence,
+    frames: VecDeque<FrameSnapshot>,
+    audio: VecDeque<f32>,
+    /// Frames pushed so far
+    pushed: u64,
+    stats: DriftStats,
+}
+
+impl AvMux {
+    /// Create a mux for mono audio at `sample_rate` Hz and the NTSC frame rate
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_cadence(SampleCadence::ntsc(sample_rate))
+    }
+
+    /// Create a mux with an explicit cadence
+    pub fn with_cadence(cadence: SampleCadence) -> Self {
+        Self { cadence, frames: VecDeque::new(), audio: VecDeque::new(), pushed: 0, stats: DriftStats::default() }
+    }
+
+    /// Use `numerator / denominator` frames per second instead of NTSC's rate
+    pub fn with_frame_rate(self, numerator: u64, denominator: u64) -> Self {
+        Self::with_cadence(SampleCadence::new(self.cadence.sample_rate, numerator, denominator))
+    }
+
+    /// Get the cadence
+    pub fn cadence(&self) -> SampleCadence {
+        self.cadence
+    }
+
+    /// Queue a rendered frame
+    pub fn push_frame(&mut self, frame: FrameSnapshot) {
+        self.frames.push_back(frame);
+        self.pushed += 1;
+        self.update_drift();
+    }
+
+    /// Queue a block of samples
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.audio.extend(samples);
+        self.stats.samples_in += samples.len() as u64;
+        self.update_drift();
+    }
+
+    /// Take the next frame once all of its samples have arrived
+    pub fn pop(&mut self) -> Option<MuxedFrame> {
+        let needed = self.cadence.samples_for_frame(self.stats.frames);
+        if self.frames.is_empty() || self.audio.len() < needed {
+            return None;
+        }
+        let samples = self.audio.drain(..needed).collect();
+        Some(self.emit(samples))
+    }
+
+    /// Take every queued frame, padding missing audio with silence, and drop leftover samples
+    pub fn finish(&mut self) -> Vec<MuxedFrame> {
+        let mut out = Vec::new();
+        while !self.frames.is_empty() {
+            let needed = self.cadence.samples_for_frame(self.stats.frames);
+            let available = needed.min(self.audio.len());
+            let mut samples: Vec<f32> = self.audio.drain(..available).collect();
+            samples.resize(needed, 0.0);
+            self.stats.padded += (needed - available) as u64;
+            out.push(self.emit(samples));
+        }
+        self.stats.dropped += self.audio.len() as u64;
+        self.audio.clear();
+        out
+    }
+
+    /// Get the drift statistics so far
+    pub fn stats(&self) -> DriftStats {
+        self.stats
+    }
+
+    fn emit(&mut self, samples: Vec<f32>) -> MuxedFrame {
+        let index = self.stats.frames;
+        let timestamp = self.cadence.end_sample(index) as f64 / self.cadence.sample_rate.max(1) as f64;
+        self.stats.frames += 1;
+        self.stats.samples_out += samples.len() as u64;
+        let frame = self.frames.pop_front().expect("a frame is queued");
+        MuxedFrame { index, timestamp, frame, samples }
+    }
+
+    fn update_drift(&mut self) {
+        let due = self.cadence.end_sample(self.pushed) as i64;
+        self.stats.drift = self.stats.samples_in as i64 - due;
+        if self.stats.drift.abs() > self.stats.max_drift.abs() {
+            self.stats.max_drift = self.stats.drift;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::Ppu;
+
+    #[test]
+    fn test_cadence() {
+        let ntsc = SampleCadence::ntsc(44_100);
+        let counts: Vec<usize> = (0..600).map(|i| ntsc.samples_for_frame(i)).collect();
+        assert!(counts.iter().all(|&n| n == 733 || n == 734));
+        // 600 frames at 60.0988 Hz are 9.98356 s
+        assert_eq!(ntsc.end_sample(600), 440_275);
+        // A full hour stays within half a sample of the exact rate
+        let hour = (3600.0 * 1_789_773.0 / 29_780.5) as u64;
+        assert_eq!(ntsc.end_sample(hour), (hour as f64 * 44_100.0 * 29_780.5 / 1_789_773.0).round() as u64);
+
+        let flat = SampleCadence::new(44_100, 60, 1);
+        assert!((0..120).all(|i| flat.samples_for_frame(i) == 735));
+    }
+
+    #[test]
+    fn test_mux_pairs_frames_with_audio() {
+        let frame = Ppu::new().snapshot_frame();
+        let mut mux = AvMux::new(44_100);
+        // Audio arrives in 512-sample blocks that don't line up with frames
+        let mut out = Vec::new();
+        for i in 0..10 {
+            mux.push_frame(frame.clone());
+            if i % 3 != 2 {
+                mux.push_samples(&[0.25; 1024]);
+            }
+            out.extend(std::iter::from_fn(|| mux.pop()));
+        }
+        assert!(out.len() < 10);
+        out.extend(mux.finish());
+
+        assert_eq!(out.len(), 10);
+        for (i, muxed) in out.iter().enumerate() {
+            assert_eq!(muxed.index, i as u64);
+            assert_eq!(muxed.samples.len(), mux.cadence().samples_for_frame(i as u64));
+        }
+        assert!((out[1].timestamp - 734.0 / 44_100.0).abs() < 1e-9);
+
+        let stats = mux.stats();
+        assert_eq!(stats.samples_in, 7 * 1024);
+        assert_eq!(stats.samples_out, mux.cadence().end_sample(10));
+        assert_eq!(stats.padded, stats.samples_out - stats.samples_in);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(stats.drift, 7 * 1024 - stats.samples_out as i64);
+        assert!(stats.max_drift.abs() >= stats.drift.abs());
+    }
+
+    #[test]
+    fn test_finish_drops_extra_audio() {
+        let mut mux = AvMux::new(48_000).with_frame_rate(60, 1);
+        mux.push_frame(Ppu::new().snapshot_frame());
+        mux.push_samples(&[0.0; 1000]);
+        let out = mux.finish();
+        assert_eq!(out[0].samples.len(), 800);
+        assert_eq!(mux.stats().dropped, 200);
+        // Behind by a frame until its audio arrived, then ahead by the extra
+        assert_eq!(mux.stats().max_drift, -800);
+        assert_eq!(mux.stats().drift, 200);
+    }
+}
@@ -9,8 +9,24 @@
 //! $4020-$5FFF - Cartridge expansion (NA, PRG ROM at $8000-$FFFF)
 //! $6000-$7FFF - Cartridge PRG RAM (if present)
 //! $8000-$FFFF - Cartridge PRG ROM
+//!
+//! Devices registered with `Bus::map` (see `io_map`) take priority over
+//! this map in the ranges they cover.
 
+use crate::cartridge::Mirroring;
+use crate::cheats::{self, Cheat};
+use crate::controller::{Buttons, Controller, DpadPolicy, InputEcho};
+use crate::dma::{DmcDma, DmcFetches};
+use crate::io_map::{IoHandler, IoMap, IoMapError, IoRegionId};
+use crate::vaus::Vaus;
+use crate::zapper::Zapper;
 use crate::cpu::Bus as CpuBus;
+use crate::mapper::{FlashOp, MapperState, FLASH_SECTOR};
+use crate::mapper_interface::MapperInterface;
+use crate::region::Region;
+use crate::rng::RandomSource;
+use crate::snapshot::PageStamps;
+use crate::state::{StateError, StateReader, StateWriter};
 
 /// RAM size in bytes
 pub const RAM_SIZE: usize = 2048; // 2KB
@@ -21,17 +37,85 @@ pub const PPU_REGISTER_COUNT: usize = 8;
 /// APU/IO register count
 pub const APU_REGISTER_COUNT: usize = 24;
 
+/// Power-on contents of the internal RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamInit {
+    /// All bytes $00
+    #[default]
+    Zeros,
+    /// All bytes set to the given value
+    Fill(u8),
+    /// Four $00 bytes followed by four $FF bytes, repeated (common on real consoles)
+    Alternating,
+    /// Bytes drawn from the system RNG (reproducible with the same seed)
+    Random,
+}
+
+impl RamInit {
+    /// Get the power-on value of the byte at the given RAM offset
+    pub fn byte_at(&self, offset: usize, rng: &mut impl RandomSource) -> u8 {
+        match self {
+            RamInit::Zeros => 0x00,
+            RamInit::Fill(value) => *value,
+            RamInit::Alternating => if offset & 0x04 == 0 { 0x00 } else { 0xFF },
+            RamInit::Random => rng.next_u8(),
+        }
+    }
+}
+
 /// Memory bus structure
 #[derive(Debug, Clone)]
 pub struct Bus {
     /// 2KB internal RAM (with mirroring)
     ram: [u8; RAM_SIZE],
+    /// Pages of RAM written, for snapshots
+    ram_pages: PageStamps,
     /// PPU registers (copy for read-back)
     ppu_registers: [u8; PPU_REGISTER_COUNT],
+    /// PPUCTRL, PPUSCROLL, PPUADDR and PPUDATA writes (register index, value)
+    /// the PPU hasn't seen yet; each one moves its scroll registers or VRAM
+    /// address, so they are applied once and in order rather than synced
+    /// from `ppu_registers`
+    ppu_writes: Vec<(usize, u8)>,
+    /// PPUSTATUS was read since the last take (the read clears the PPU's write toggle)
+    ppu_status_read: bool,
     /// APU/IO registers
     apu_registers: [u8; APU_REGISTER_COUNT],
+    /// APU register writes (address, value) the APU hasn't seen yet, so its
+    /// channels are updated once per write and in order
+    apu_writes: Vec<(u16, u8)>,
+    /// Length counter and frame IRQ bits of $4015, set by the system after each APU step
+    apu_status: u8,
+    /// $4015 was read, acknowledging the frame IRQ, since the last take
+    apu_status_read: bool,
+    /// Sample bytes fetched by the DMC reader that the APU hasn't played yet
+    dmc_samples: Vec<u8>,
     /// Cartridge (PRG and CHR memory)
     cartridge: Option<SimpleCartridge>,
+    /// Number of $4016 reads since the counter was last taken
+    input_polls: u32,
+    /// Number of PPU register reads and writes since the counter was last taken
+    ppu_accesses: u32,
+    /// Standard controllers on ports 1 and 2
+    controllers: [Controller; 2],
+    /// Zapper plugged into port 2 in place of the controller
+    zapper: Option<Zapper>,
+    /// Arkanoid paddle plugged into port 2 in place of the controller
+    vaus: Option<Vaus>,
+    /// PPU is warming up: writes to $2000/$2001/$2005/$2006 are dropped
+    ppu_warming_up: bool,
+    /// Registered memory-mapped I/O regions
+    io: IoMap,
+    /// DMC sample reader, whose fetches halt the CPU
+    dmc: DmcDma,
+    /// Page written to $4014, waiting for the system to run the OAM DMA
+    oam_dma: Option<u8>,
+    /// Controller port (0 or 1) read since the last take
+    controller_read: Option<usize>,
+    /// Controller port reads since the echo was last taken
+    input_echo: InputEcho,
+    /// Active cheats, applied to PRG ROM reads and once a frame to RAM
+    cheats: Vec<Cheat>,
 }
 
 impl Bus {
@@ -39,12 +123,71 @@ impl Bus {
     pub fn new() -> Self {
         Self {
             ram: [0; RAM_SIZE],
+            ram_pages: PageStamps::new(RAM_SIZE),
             ppu_registers: [0; PPU_REGISTER_COUNT],
+            ppu_writes: Vec::new(),
+            ppu_status_read: false,
             apu_registers: [0; APU_REGISTER_COUNT],
+            apu_writes: Vec::new(),
+            apu_status: 0,
+            apu_status_read: false,
+            dmc_samples: Vec::new(),
             cartridge: None,
+            input_polls: 0,
+            ppu_accesses: 0,
+            controllers: [Controller::new(), Controller::new()],
+            zapper: None,
+            vaus: None,
+            ppu_warming_up: false,
+            io: IoMap::new(),
+            dmc: DmcDma::new(),
+            oam_dma: None,
+            controller_read: None,
+            input_echo: InputEcho::new(),
+            cheats: Vec::new(),
+        }
+    }
+
+    /// Map a device's registers onto an address range, ahead of the built-in map
+    pub fn map(
+        &mut self,
+        range: std::ops::RangeInclusive<u16>,
+        handler: impl IoHandler + 'static,
+    ) -> Result<IoRegionId, IoMapError> {
+        self.io.map(range, Box::new(handler))
+    }
+
+    /// Remove a mapped region, returning its handler
+    pub fn unmap(&mut self, id: IoRegionId) -> Option<Box<dyn IoHandler>> {
+        self.io.unmap(id)
+    }
+
+    /// Set whether the PPU is in its post-reset warm-up period
+    pub fn set_ppu_warming_up(&mut self, warming_up: bool) {
+        self.ppu_warming_up = warming_up;
+    }
+
+    /// Store a PPU register write (index 0-7) unless the warm-up period drops it
+    fn write_ppu_register(&mut self, index: usize, value: u8) {
+        self.ppu_accesses = self.ppu_accesses.wrapping_add(1);
+        if self.ppu_warming_up && matches!(index, 0 | 1 | 5 | 6) {
+            return;
+        }
+        self.ppu_registers[index] = value;
+        if matches!(index, 0 | 5 | 6 | 7) {
+            self.ppu_writes.push((index, value));
         }
     }
 
+    /// Read a PPU register (index 0-7)
+    fn read_ppu_register(&mut self, index: usize) -> u8 {
+        self.ppu_accesses = self.ppu_accesses.wrapping_add(1);
+        if index == 2 {
+            self.ppu_status_read = true;
+        }
+        self.ppu_registers[index]
+    }
+
     /// Set the cartridge for this bus
     pub fn set_cartridge(&mut self, cartridge: SimpleCartridge) {
         self.cartridge = Some(cartridge);
@@ -55,15 +198,322 @@ impl Bus {
         self.cartridge.as_ref()
     }
 
+    /// Get a mutable reference to the cartridge, if present
+    pub fn cartridge_mut(&mut self) -> Option<&mut SimpleCartridge> {
+        self.cartridge.as_mut()
+    }
+
+    /// Serialize RAM, registers, controllers and the cartridge
+    ///
+    /// A Zapper or paddle is left out: its aim and buttons come from the host every frame.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        self.save_state_without_ram(writer);
+    }
+
+    /// Serialize everything `save_state` does but RAM
+    pub(crate) fn save_state_without_ram(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ppu_registers);
+        writer.write_bytes(&self.apu_registers);
+        writer.write_u32(self.input_polls);
+        writer.write_u32(self.ppu_accesses);
+        for controller in &self.controllers {
+            controller.save_state(writer);
+        }
+        writer.write_bool(self.ppu_warming_up);
+        writer.write_bool(self.cartridge.is_some());
+        if let Some(cart) = &self.cartridge {
+            cart.save_state(writer);
+        }
+        self.dmc.save_state(writer);
+        self.dmc.save_irq(writer);
+    }
+
+    /// Restore state written by `save_state` (with the same cartridge inserted)
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.ram)?;
+        self.ram_pages.mark_all();
+        self.load_state_without_ram(reader)
+    }
+
+    /// Restore state written by `save_state_without_ram`
+    pub(crate) fn load_state_without_ram(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.ppu_registers)?;
+        reader.read_into(&mut self.apu_registers)?;
+        self.input_polls = reader.read_u32()?;
+        self.ppu_accesses = reader.read_u32()?;
+        for controller in &mut self.controllers {
+            controller.load_state(reader)?;
+        }
+        self.ppu_warming_up = reader.read_bool()?;
+        // Reads made before the state was loaded didn't happen in it
+        self.input_echo.clear();
+        self.ppu_writes.clear();
+        self.ppu_status_read = false;
+        self.apu_writes.clear();
+        self.apu_status_read = false;
+        self.dmc_samples.clear();
+        match (reader.read_bool()?, self.cartridge.as_mut()) {
+            (true, Some(cart)) => cart.load_state(reader)?,
+            (false, None) => {}
+            _ => return Err(StateError::InvalidData("cartridge presence does not match")),
+        }
+        self.dmc.load_state(reader)?;
+        self.dmc.load_irq(reader)
+    }
+
+    /// Advance cartridge timers by the given number of CPU cycles
+    pub fn clock_cartridge(&mut self, cycles: u32) {
+        if let Some(cart) = self.cartridge.as_mut() {
+            cart.mapper.clock_cpu(cycles);
+        }
+    }
+
+    /// Fill internal RAM with its power-on pattern
+    pub fn power_on_ram(&mut self, init: RamInit, rng: &mut impl RandomSource) {
+        for (offset, byte) in self.ram.iter_mut().enumerate() {
+            *byte = init.byte_at(offset, rng);
+        }
+        self.ram_pages.mark_all();
+    }
+
+    /// Take the controller poll count, resetting it to zero
+    pub fn take_input_polls(&mut self) -> u32 {
+        std::mem::take(&mut self.input_polls)
+    }
+
+    /// Take the bytes read from $4016/$4017, leaving the echo empty
+    pub fn take_input_echo(&mut self) -> InputEcho {
+        std::mem::take(&mut self.input_echo)
+    }
+
+    /// Take the page of an OAM DMA requested by a $4014 write, if any
+    pub fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma.take()
+    }
+
+    /// Take the controller port last read through $4016/$4017, if any
+    pub fn take_controller_read(&mut self) -> Option<usize> {
+        self.controller_read.take()
+    }
+
+    /// Clock a controller's shift register as a discarded read would (a Zapper on port 2 has none)
+    pub fn clock_controller(&mut self, port: usize) {
+        if port == 1 && self.zapper.is_some() {
+            return;
+        }
+        if let (1, Some(vaus)) = (port, self.vaus.as_mut()) {
+            vaus.read();
+        } else if let Some(controller) = self.controllers.get_mut(port) {
+            controller.read();
+        }
+    }
+
+    /// Run the DMC sample reader for the given number of CPU cycles
+    ///
+    /// The fetched bytes are read from PRG ROM and kept for `take_dmc_samples`.
+    pub fn clock_dmc(&mut self, cycles: u32) -> DmcFetches {
+        let (cartridge, samples, cheats) = (&self.cartridge, &mut self.dmc_samples, &self.cheats);
+        self.dmc.clock_with(cycles, |address| {
+            samples.push(cartridge.as_ref().map_or(0xFF, |cart| cart.read_prg_patched(address, cheats)));
+        })
+    }
+
+    /// Take the sample bytes the DMC reader fetched since the last call, oldest first
+    pub fn take_dmc_samples(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.dmc_samples)
+    }
+
+    /// Take the APU register writes (address, value) made since the last call, oldest first
+    pub fn take_apu_writes(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.apu_writes)
+    }
+
+    /// Set the length counter (0-3) and frame IRQ (6) bits returned by $4015 reads
+    pub fn set_apu_status(&mut self, status: u8) {
+        self.apu_status = status & 0x4F;
+    }
+
+    /// Check if $4015 was read since the last take, resetting the flag
+    ///
+    /// The read acknowledges the APU's frame IRQ.
+    pub fn take_apu_status_read(&mut self) -> bool {
+        std::mem::take(&mut self.apu_status_read)
+    }
+
+    /// Check if the DMC IRQ is holding the line
+    pub fn dmc_irq_pending(&self) -> bool {
+        self.dmc.irq_pending()
+    }
+
+    /// Release the DMC IRQ, as a $4015 write does
+    pub fn acknowledge_dmc_irq(&mut self) {
+        self.dmc.acknowledge_irq();
+    }
+
+    /// Stop the DMC and drop pending DMA requests, as a reset does
+    pub fn reset_dma(&mut self) {
+        self.reset_dmc();
+        self.oam_dma = None;
+        self.controller_read = None;
+    }
+
+    /// Stop the DMC sample reader
+    pub fn reset_dmc(&mut self) {
+        self.dmc.write(0x4015, 0x00);
+        self.dmc_samples.clear();
+        self.apu_writes.clear();
+    }
+
+    /// Forget the PPU register values written through the bus
+    pub fn clear_ppu_registers(&mut self) {
+        self.ppu_registers = [0; PPU_REGISTER_COUNT];
+        self.ppu_writes.clear();
+        self.ppu_status_read = false;
+    }
+
+    /// Take the PPUCTRL, PPUSCROLL, PPUADDR and PPUDATA writes (register index, value) made since the last call, oldest first
+    pub fn take_ppu_writes(&mut self) -> Vec<(usize, u8)> {
+        std::mem::take(&mut self.ppu_writes)
+    }
+
+    /// Take whether PPUSTATUS was read since the last call
+    pub fn take_ppu_status_read(&mut self) -> bool {
+        std::mem::take(&mut self.ppu_status_read)
+    }
+
+    /// Use a region's DMC rate table
+    pub fn set_region(&mut self, region: Region) {
+        self.dmc.set_region(region);
+    }
+
+    /// Take the PPU register access count ($2000-$3FFF reads and writes), resetting it to zero
+    pub fn take_ppu_accesses(&mut self) -> u32 {
+        std::mem::take(&mut self.ppu_accesses)
+    }
+
+    /// Get the active cheats
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Replace the active cheats
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+    }
+
+    /// Write the RAM cheats whose compare byte (if any) matches
+    pub fn apply_ram_cheats(&mut self) {
+        for cheat in self.cheats.iter().filter(|cheat| !cheat.is_prg_patch()) {
+            match cheat.address {
+                0x0000..=0x1FFF => {
+                    let index = (cheat.address & 0x07FF) as usize;
+                    if cheat.compare.is_none_or(|compare| compare == self.ram[index]) {
+                        self.ram[index] = cheat.value;
+                        self.ram_pages.mark(index);
+                    }
+                }
+                _ => {
+                    if let Some(cart) = self.cartridge.as_mut() {
+                        if cheat.compare.is_none_or(|compare| compare == cart.read_prm_ram(cheat.address)) {
+                            cart.write_prm_ram(cheat.address, cheat.value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get internal RAM
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Get internal RAM with its page stamps, for snapshots
+    pub(crate) fn ram_pages(&mut self) -> (&mut [u8], &mut PageStamps) {
+        (&mut self.ram, &mut self.ram_pages)
+    }
+
+    /// Set the button state of the controller on the given port (0 or 1)
+    pub fn set_buttons(&mut self, port: usize, buttons: Buttons) {
+        if let Some(controller) = self.controllers.get_mut(port) {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    /// Get the button state of the controller on the given port (0 or 1)
+    pub fn buttons(&self, port: usize) -> Buttons {
+        self.controllers.get(port).map(|c| c.buttons()).unwrap_or_default()
+    }
+
+    /// Set how both controllers resolve opposing D-pad directions
+    pub fn set_dpad_policy(&mut self, policy: DpadPolicy) {
+        for controller in &mut self.controllers {
+            controller.set_dpad_policy(policy);
+        }
+    }
+
+    /// Get the opposing D-pad direction policy
+    pub fn dpad_policy(&self) -> DpadPolicy {
+        self.controllers[0].dpad_policy()
+    }
+
+    /// Plug a Zapper into port 2, unplugging a paddle (None plugs the controller back in)
+    pub fn set_zapper(&mut self, zapper: Option<Zapper>) {
+        if zapper.is_some() {
+            self.vaus = None;
+        }
+        self.zapper = zapper;
+    }
+
+    /// Get the Zapper on port 2, if plugged in
+    pub fn zapper(&self) -> Option<&Zapper> {
+        self.zapper.as_ref()
+    }
+
+    /// Get mutable access to the Zapper on port 2, if plugged in
+    pub fn zapper_mut(&mut self) -> Option<&mut Zapper> {
+        self.zapper.as_mut()
+    }
+
+    /// Plug an Arkanoid paddle into port 2, unplugging a Zapper (None plugs the controller back in)
+    pub fn set_vaus(&mut self, vaus: Option<Vaus>) {
+        if vaus.is_some() {
+            self.zapper = None;
+        }
+        self.vaus = vaus;
+    }
+
+    /// Get the paddle on port 2, if plugged in
+    pub fn vaus(&self) -> Option<&Vaus> {
+        self.vaus.as_ref()
+    }
+
+    /// Get mutable access to the paddle on port 2, if plugged in
+    pub fn vaus_mut(&mut self) -> Option<&mut Vaus> {
+        self.vaus.as_mut()
+    }
+
     /// Get a reference to the CHR ROM, if present
     pub fn chr_rom(&self) -> Option<&[u8]> {
         self.cartridge.as_ref().map(|c| c.chr_rom.as_slice())
     }
 }
 
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CpuBus for Bus {
     /// Read a byte from the given address
     fn read(&mut self, address: u16) -> u8 {
+        if self.io.covers_page(address) {
+            if let Some(value) = self.io.read(address) {
+                return value;
+            }
+        }
         match address {
             // $0000-$07FF - Internal RAM
             0x0000..=0x07FF => {
@@ -75,16 +525,39 @@ impl CpuBus for Bus {
                 self.ram[(address & 0x07FF) as usize]
             }
             // $2000-$2007 - PPU registers
-            0x2000..=0x2007 => {
-                self.ppu_registers[(address & 0x0007) as usize]
-            }
+            0x2000..=0x2007 => self.read_ppu_register((address & 0x0007) as usize),
             // $2008-$3FFF - PPU register mirroring (every 8 bytes)
-            0x2008..=0x3FFF => {
-                self.ppu_registers[((address - 0x2008) & 0x0007) as usize]
-            }
+            0x2008..=0x3FFF => self.read_ppu_register(((address - 0x2008) & 0x0007) as usize),
             // $4000-$4017 - APU and I/O registers
             0x4000..=0x4017 => {
-                self.apu_registers[(address - 0x4000) as usize]
+                match address {
+                    0x4016 => {
+                        self.input_polls = self.input_polls.wrapping_add(1);
+                        self.controller_read = Some(0);
+                        let value = self.controllers[0].read();
+                        self.input_echo.record(0, value);
+                        value
+                    }
+                    0x4017 => {
+                        self.controller_read = Some(1);
+                        let value = match (&self.zapper, self.vaus.as_mut()) {
+                            (Some(zapper), _) => zapper.read(),
+                            (None, Some(vaus)) => vaus.read(),
+                            (None, None) => self.controllers[1].read(),
+                        };
+                        self.input_echo.record(1, value);
+                        value
+                    }
+                    // Channel status: length counters, whether a DMC sample is
+                    // playing and both IRQ flags; the read acknowledges the frame IRQ
+                    0x4015 => {
+                        let status = self.apu_status | (self.dmc.is_active() as u8) << 4 | (self.dmc.irq_pending() as u8) << 7;
+                        self.apu_status &= !0x40;
+                        self.apu_status_read = true;
+                        status
+                    }
+                    _ => self.apu_registers[(address - 0x4000) as usize],
+                }
             }
             // $4020-$5FFF - Cartridge expansion (NA)
             0x4020..=0x5FFF => {
@@ -92,19 +565,13 @@ impl CpuBus for Bus {
                 0xFF
             }
             // $6000-$7FFF - Cartridge PRG RAM (if present)
-            0x6000..=0x7FFF => {
-                if let Some(ref cart) = self.cartridge {
-                    cart.read_prm_ram(address)
-                } else {
-                    0xFF
-                }
-            }
+            0x6000..=0x7FFF => self.cartridge.as_mut().map_or(0xFF, |cart| cart.read_low(address)),
             // $8000-$FFFF - Cartridge PRG ROM
             0x8000..=0xFFFF => {
                 if let Some(ref cart) = self.cartridge {
                     // Debug: print when reading from PRG ROM
                     //eprintln!("DEBUG: Reading PRG ROM at address ${:04X}", address);
-                    cart.read_prd_rom(address)
+                    cart.read_prg_patched(address, &self.cheats)
                 } else {
                     0xFF
                 }
@@ -115,40 +582,59 @@ impl CpuBus for Bus {
 
     /// Write a byte to the given address
     fn write(&mut self, address: u16, value: u8) {
+        if self.io.covers_page(address) && self.io.write(address, value) {
+            return;
+        }
         match address {
             // $0000-$07FF - Internal RAM
             0x0000..=0x07FF => {
                 self.ram[(address & 0x07FF) as usize] = value;
+                self.ram_pages.mark((address & 0x07FF) as usize);
             }
             // $0800-$1FFF - RAM mirroring
             0x0800..=0x1FFF => {
                 self.ram[(address & 0x07FF) as usize] = value;
+                self.ram_pages.mark((address & 0x07FF) as usize);
             }
             // $2000-$2007 - PPU registers
             0x2000..=0x2007 => {
-                self.ppu_registers[(address & 0x0007) as usize] = value;
+                self.write_ppu_register((address & 0x0007) as usize, value);
             }
             // $2008-$3FFF - PPU register mirroring
             0x2008..=0x3FFF => {
-                self.ppu_registers[((address - 0x2008) & 0x0007) as usize] = value;
+                self.write_ppu_register(((address - 0x2008) & 0x0007) as usize, value);
             }
             // $4000-$4017 - APU and I/O registers
             0x4000..=0x4017 => {
+                match address {
+                    0x4010..=0x4013 | 0x4015 => {
+                        self.dmc.write(address, value);
+                        self.apu_writes.push((address, value));
+                    }
+                    0x4014 => self.oam_dma = Some(value),
+                    0x4016 => {
+                        for controller in &mut self.controllers {
+                            controller.write_strobe(value);
+                        }
+                        if let Some(vaus) = self.vaus.as_mut() {
+                            vaus.write_strobe(value);
+                        }
+                    }
+                    _ => self.apu_writes.push((address, value)),
+                }
                 self.apu_registers[(address - 0x4000) as usize] = value;
             }
-            // $4020-$5FFF - Cartridge expansion (NA)
-            0x4020..=0x5FFF => {
-                // ExpansionROM write - ignore for now
-            }
-            // $6000-$7FFF - Cartridge PRG RAM (if present)
-            0x6000..=0x7FFF => {
+            // $4020-$7FFF - Cartridge expansion and PRG RAM
+            0x4020..=0x7FFF => {
                 if let Some(ref mut cart) = self.cartridge {
-                    cart.write_prm_ram(address, value);
+                    cart.write_low(address, value);
                 }
             }
-            // $8000-$FFFF - Cartridge PRG ROM (write-protected, ignore)
+            // $8000-$FFFF - Cartridge PRG ROM (writes go to mapper registers)
             0x8000..=0xFFFF => {
-                // PRG ROM is write-protected
+                if let Some(ref mut cart) = self.cartridge {
+                    cart.write_prg(address, value);
+                }
             }
             _ => {}
         }
@@ -156,6 +642,26 @@ impl CpuBus for Bus {
 }
 
 impl Bus {
+    /// Read a byte without side effects (no controller shifts or poll counting)
+    pub fn peek(&self, address: u16) -> u8 {
+        if self.io.covers_page(address) {
+            if let Some(value) = self.io.peek(address) {
+                return value;
+            }
+        }
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize],
+            0x2000..=0x3FFF => self.ppu_registers[(address & 0x0007) as usize],
+            0x4000..=0x4017 => self.apu_registers[(address - 0x4000) as usize],
+            0x6000..=0x7FFF => self
+                .cartridge
+                .as_ref()
+                .map_or(0xFF, |c| c.mapper.read(address).unwrap_or_else(|| c.read_prm_ram(address))),
+            0x8000..=0xFFFF => self.cartridge.as_ref().map_or(0xFF, |c| c.read_prd_rom(address)),
+            _ => 0xFF,
+        }
+    }
+
     /// Get PPU register value
     pub fn get_ppu_register(&self, index: usize) -> u8 {
         if index < PPU_REGISTER_COUNT {
@@ -175,6 +681,16 @@ pub struct SimpleCartridge {
     prg_ram: Option<Vec<u8>>,
     /// CHR ROM data
     chr_rom: Vec<u8>,
+    /// Bank-switching state
+    mapper: MapperState,
+    /// Bus conflict override (None uses the board default)
+    bus_conflicts: Option<bool>,
+    /// Soldered mirroring, used unless the mapper selects its own
+    mirroring: Mirroring,
+    /// PRG RAM is battery-backed (header flags 6 bit 1)
+    battery: bool,
+    /// Battery-backed data may have changed since the last take
+    battery_dirty: bool,
 }
 
 impl SimpleCartridge {
@@ -184,73 +700,252 @@ impl SimpleCartridge {
             prg_rom,
             prg_ram: Some(vec![0xFF; 8192]), // Default 8KB PRG RAM
             chr_rom,
+            mapper: MapperState::Fixed,
+            bus_conflicts: None,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            battery_dirty: false,
+        }
+    }
+
+    /// Use a PRG RAM of the given size in bytes (0 removes it)
+    pub fn with_prg_ram_size(mut self, bytes: usize) -> Self {
+        self.prg_ram = (bytes > 0).then(|| vec![0xFF; bytes]);
+        self
+    }
+
+    /// Use the given mapper for bank switching
+    pub fn with_mapper(mut self, mapper: MapperState) -> Self {
+        self.mapper = mapper;
+        self
+    }
+
+    /// Override whether register writes have bus conflicts (None uses the board default)
+    pub fn with_bus_conflicts(mut self, bus_conflicts: Option<bool>) -> Self {
+        self.bus_conflicts = bus_conflicts;
+        self
+    }
+
+    /// Use the given soldered mirroring (from the header; horizontal by default)
+    pub fn with_mirroring(mut self, mirroring: Mirroring) -> Self {
+        self.mirroring = mirroring;
+        self
+    }
+
+    /// Use battery-backed PRG RAM (from the header; off by default)
+    pub fn with_battery(mut self, battery: bool) -> Self {
+        self.battery = battery;
+        self
+    }
+
+    /// Get the nametable mirroring in effect: the mapper's, or the soldered one
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.current_mirroring().unwrap_or(self.mirroring)
+    }
+
+    /// Check if register writes are ANDed with the PRG ROM byte at the written address
+    pub fn bus_conflicts(&self) -> bool {
+        self.bus_conflicts.unwrap_or_else(|| self.mapper.bus_conflicts())
+    }
+
+    /// Get the mapper state
+    pub fn mapper(&self) -> &MapperState {
+        &self.mapper
+    }
+
+    /// Get mutable mapper state (e.g. to set board DIP switches)
+    pub fn mapper_mut(&mut self) -> &mut MapperState {
+        &mut self.mapper
+    }
+
+    /// Serialize PRG RAM, mapper state and, on flash boards, PRG
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        if let Some(prg_ram) = &self.prg_ram {
+            writer.write_bytes(prg_ram);
+        }
+        if self.mapper.flashable_prg() {
+            writer.write_bytes(&self.prg_rom);
+        }
+        self.mapper.save_state(writer);
+    }
+
+    /// Restore state written by `save_state` for the same ROM
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if let Some(prg_ram) = self.prg_ram.as_mut() {
+            reader.read_into(prg_ram)?;
+        }
+        if self.mapper.flashable_prg() {
+            reader.read_into(&mut self.prg_rom)?;
         }
+        self.mapper.load_state(reader)?;
+        self.battery_dirty = self.battery_data().is_some();
+        Ok(())
     }
 
     /// Read from PRG ROM
     pub fn read_prd_rom(&self, address: u16) -> u8 {
-        // Remove the $8000 offset
-        let offset = (address - 0x8000) as usize;
-        if offset < self.prg_rom.len() {
-            self.prg_rom[offset]
-        } else {
-            // For 16KB PRG ROM, $8000-$BFFF and $C000-$FFFF both map to same data (mirroring)
-            // Wrap the offset to the first 16KB
-            let prg_size = self.prg_rom.len();
-            let mirrored_offset = offset % prg_size;
-            self.prg_rom[mirrored_offset]
+        // For 16KB PRG ROM, $8000-$BFFF and $C000-$FFFF both map to same data (mirroring);
+        // the mapper wraps offsets beyond the end of the ROM
+        if self.prg_rom.is_empty() {
+            return 0xFF;
         }
+        self.prg_rom[self.mapper.prg_offset(address, self.prg_rom.len())]
     }
 
-    /// Read from PRG RAM
-    pub fn read_prm_ram(&self, _address: u16) -> u8 {
-        if let Some(ref prg_ram) = self.prg_ram {
-            // Simple direct mapping for now
-            prg_ram[0] // TODO: proper addressing
-        } else {
-            0xFF
+    /// Read from PRG ROM with cheat patches applied against the mapped bank
+    pub fn read_prg_patched(&self, address: u16, cheats: &[Cheat]) -> u8 {
+        if cheats.is_empty() || self.prg_rom.is_empty() {
+            return self.read_prd_rom(address);
         }
+        let offset = self.mapper.prg_offset(address, self.prg_rom.len());
+        cheats::patch_prg(cheats, address, offset, self.prg_rom[offset])
     }
 
-    /// Write to PRG RAM
-    pub fn write_prm_ram(&mut self, _address: u16, value: u8) {
-        if let Some(ref mut prg_ram) = self.prg_ram {
-            // Simple direct mapping for now
-            prg_ram[0] = value; // TODO: proper addressing
+    /// Write to $8000-$FFFF: mapper registers (with bus conflicts where the board has them), or the PRG flash on boards that have it
+    pub fn write_prg_rom(&mut self, address: u16, value: u8) {
+        let value = if self.bus_conflicts() { value & self.read_prd_rom(address) } else { value };
+        self.mapper.write(address, value);
+        // EEPROMs are clocked through mapper registers
+        if self.mapper.battery_data().is_some() {
+            self.battery_dirty = true;
+        }
+        let Some(op) = self.mapper.take_flash_op() else {
+            return;
+        };
+        if self.prg_rom.is_empty() {
+            return;
+        }
+        self.battery_dirty = true;
+        let len = self.prg_rom.len();
+        match op {
+            FlashOp::Program { offset, value } => self.prg_rom[offset % len] &= value,
+            FlashOp::EraseSector { offset } => {
+                let start = offset % len;
+                self.prg_rom[start..(start + FLASH_SECTOR).min(len)].fill(0xFF);
+            }
+            FlashOp::EraseChip => self.prg_rom.fill(0xFF),
         }
     }
 
-    /// Get PRG ROM size in bytes
-    pub fn prg_rom_size(&self) -> usize {
-        self.prg_rom.len()
+    /// Get the battery-backed save data: board storage such as an EEPROM, the
+    /// whole PRG on flash boards, or PRG RAM when it has a battery
+    pub fn battery_data(&self) -> Option<&[u8]> {
+        if self.mapper.flashable_prg() {
+            return Some(&self.prg_rom);
+        }
+        self.mapper
+            .battery_data()
+            .or_else(|| self.prg_ram.as_deref().filter(|_| self.battery))
     }
 
-    /// Get CHR ROM size in bytes
-    pub fn chr_rom_size(&self) -> usize {
-        self.chr_rom.len()
+    /// Restore battery-backed save data previously returned by `battery_data`
+    ///
+    /// Flash images of the wrong size are ignored rather than corrupting PRG;
+    /// PRG RAM and EEPROM data is copied as far as it fits.
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        self.battery_dirty = false;
+        if self.mapper.flashable_prg() {
+            if data.len() == self.prg_rom.len() {
+                self.prg_rom.copy_from_slice(data);
+            }
+            return;
+        }
+        if self.mapper.battery_data().is_some() {
+            self.mapper.load_battery_data(data);
+        } else if let Some(prg_ram) = self.prg_ram.as_mut().filter(|_| self.battery) {
+            let len = data.len().min(prg_ram.len());
+            prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+
+    /// Check if battery-backed data may have changed since the last take, resetting the flag
+    ///
+    /// Frontends use this to skip rewriting an unchanged save file.
+    pub fn take_battery_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.battery_dirty)
+    }
+
+    /// Read from PRG RAM ($6000-$7FFF, banked by the mapper)
+    pub fn read_prm_ram(&self, address: u16) -> u8 {
+        match self.prg_ram {
+            Some(ref prg_ram) => self
+                .mapper
+                .prg_ram_offset(address, prg_ram.len())
+                .map_or(0xFF, |offset| prg_ram[offset]),
+            None => 0xFF,
+        }
     }
-}
 
-/// Mapper types
-#[derive(Debug, Clone, Copy)]
-pub enum Mapper {
-    /// NROM - Simple mapper, no bank switching
-    NROM,
-    /// UxROM - Simple mapper with PRG bank switching
-    UXROM,
-    /// CNROM - Simple mapper with CHR bank switching
-    CNROM,
+    /// Write to PRG RAM ($6000-$7FFF, banked by the mapper)
+    pub fn write_prm_ram(&mut self, address: u16, value: u8) {
+        if let Some(ref mut prg_ram) = self.prg_ram {
+            if let Some(offset) = self.mapper.prg_ram_offset(address, prg_ram.len()) {
+                prg_ram[offset] = value;
+                self.battery_dirty |= self.battery;
+            }
+        }
+        // Bandai boards clock their EEPROM from $6000-$7FFF too
+        if self.mapper.battery_data().is_some() {
+            self.battery_dirty = true;
+        }
+    }
+
+    /// Get PRG RAM contents (None if the board has none)
+    pub fn prg_ram(&self) -> Option<&[u8]> {
+        self.prg_ram.as_deref()
+    }
 }
 
-impl Default for Mapper {
-    fn default() -> Self {
-        Mapper::NROM
+impl MapperInterface for SimpleCartridge {
+    fn reset(&mut self) {
+        self.mapper.reset();
+    }
+
+    /// A register the board decodes at $6000-$7FFF, else PRG RAM ($FF without any)
+    fn read_low(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => self.mapper.read(address).unwrap_or_else(|| self.read_prm_ram(address)),
+            _ => 0xFF,
+        }
+    }
+
+    /// Some boards decode registers here (Action 53 at $5000, Bandai FCG at $6000)
+    fn write_low(&mut self, address: u16, value: u8) {
+        self.mapper.write(address, value);
+        if address >= 0x6000 {
+            self.write_prm_ram(address, value);
+        }
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.read_prd_rom(address)
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        self.write_prg_rom(address, value);
+    }
+
+    /// The PPU keeps its own copy of CHR (and its CHR RAM), so this only reads
+    /// the ROM image the cartridge was created with
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.chr_rom.get(address as usize & 0x1FFF).copied().unwrap_or(0)
+    }
+
+    fn write_chr(&mut self, _address: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        SimpleCartridge::mirroring(self)
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Rng;
 
     #[test]
     fn test_bus_read_write() {
@@ -265,14 +960,167 @@ mod tests {
         assert_eq!(bus.read(0x0801), 0x43);
     }
 
+    #[test]
+    fn test_power_on_ram_patterns() {
+        let mut bus = Bus::new();
+
+        let mut rng = Rng::new(1);
+
+        bus.power_on_ram(RamInit::Fill(0xFF), &mut rng);
+        assert_eq!(bus.read(0x0000), 0xFF);
+        assert_eq!(bus.read(0x07FF), 0xFF);
+
+        bus.power_on_ram(RamInit::Alternating, &mut rng);
+        assert_eq!(bus.read(0x0003), 0x00);
+        assert_eq!(bus.read(0x0004), 0xFF);
+        assert_eq!(bus.read(0x0008), 0x00);
+
+        // Random contents depend only on the seed
+        bus.power_on_ram(RamInit::Random, &mut Rng::new(9));
+        let first = bus.ram().to_vec();
+        bus.power_on_ram(RamInit::Random, &mut Rng::new(9));
+        assert_eq!(bus.ram(), &first[..]);
+        assert!(first.iter().any(|&b| b != first[0]));
+    }
+
+    #[test]
+    fn test_bus_counts_input_polls() {
+        let mut bus = Bus::new();
+
+        bus.read(0x4016);
+        bus.read(0x4016);
+        bus.read(0x4017); // Port 2 reads are not counted
+        assert_eq!(bus.take_input_polls(), 2);
+        assert_eq!(bus.take_input_polls(), 0);
+    }
+
+    #[test]
+    fn test_bus_counts_ppu_accesses() {
+        let mut bus = Bus::new();
+        bus.read(0x2002);
+        bus.write(0x3FFE, 0x20);
+        bus.read(0x4016);
+        assert_eq!(bus.take_ppu_accesses(), 2);
+        assert_eq!(bus.take_ppu_accesses(), 0);
+
+        // Scroll register writes are queued in order; PPUMASK only latches
+        assert!(bus.take_ppu_status_read());
+        assert!(!bus.take_ppu_status_read());
+        bus.write(0x2005, 0x0B);
+        bus.write(0x2001, 0x1E);
+        bus.write(0x200D, 0x20);
+        bus.write(0x2007, 0x55);
+        assert_eq!(bus.take_ppu_writes(), [(6, 0x20), (5, 0x0B), (5, 0x20), (7, 0x55)]);
+        assert!(bus.take_ppu_writes().is_empty());
+    }
+
+    #[test]
+    fn test_mapped_io_takes_priority() {
+        #[derive(Debug, Clone)]
+        struct Expansion(u8);
+
+        impl IoHandler for Expansion {
+            fn read(&mut self, address: u16) -> Option<u8> {
+                (address == 0x5000).then_some(self.0)
+            }
+
+            fn peek(&self, address: u16) -> Option<u8> {
+                (address == 0x5000).then_some(self.0)
+            }
+
+            fn write(&mut self, _address: u16, value: u8) {
+                self.0 = value;
+            }
+        }
+
+        let mut bus = Bus::new();
+        let id = bus.map(0x5000..=0x5FFF, Expansion(0x12)).unwrap();
+        assert_eq!(bus.read(0x5000), 0x12);
+        assert_eq!(bus.peek(0x5000), 0x12);
+        // Declined reads fall through to the built-in map (open bus here)
+        assert_eq!(bus.read(0x5001), 0xFF);
+        bus.write(0x5FFF, 0x34);
+        assert_eq!(bus.read(0x5000), 0x34);
+        // Clones carry their own copy of the device
+        let mut copy = bus.clone();
+        copy.write(0x5000, 0x56);
+        assert_eq!(bus.read(0x5000), 0x34);
+
+        assert!(bus.unmap(id).is_some());
+        assert_eq!(bus.read(0x5000), 0xFF);
+    }
+
+    #[test]
+    fn test_controller_ports() {
+        let mut bus = Bus::new();
+        bus.set_buttons(0, Buttons::new(Buttons::A));
+        bus.set_buttons(1, Buttons::new(Buttons::B));
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+
+        assert_eq!(bus.read(0x4016) & 0x01, 1); // Port 1: A
+        assert_eq!(bus.read(0x4017) & 0x01, 0); // Port 2: A
+        assert_eq!(bus.read(0x4016) & 0x01, 0); // Port 1: B
+        assert_eq!(bus.read(0x4017) & 0x01, 1); // Port 2: B
+    }
+
+    #[test]
+    fn test_flash_prg_writes_and_battery_data() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[6] = 0xE2; // mapper 30 (low nibble), battery (flashable)
+        header[7] = 0x10;
+        let header = crate::cartridge::InesHeader::parse(&header).unwrap();
+        let mut cart = SimpleCartridge::new(vec![0xFF; 4 * 16384], Vec::new()).with_mapper(MapperState::for_header(&header));
+
+        // Program $5A into bank 2 at $8010: AA/$9555 (bank 1), 55/$AAAA (bank 0), A0/$9555 (bank 1)
+        for (bank, address, value) in [(1, 0x9555, 0xAA), (0, 0xAAAA, 0x55), (1, 0x9555, 0xA0), (2, 0x8010, 0x5A)] {
+            cart.write_prg_rom(0xC000, bank);
+            cart.write_prg_rom(address, value);
+        }
+        assert_eq!(cart.read_prd_rom(0x8010), 0x5A);
+        let saved = cart.battery_data().unwrap().to_vec();
+        assert_eq!(saved.len(), 4 * 16384);
+
+        let mut fresh = SimpleCartridge::new(vec![0xFF; 4 * 16384], Vec::new()).with_mapper(MapperState::for_header(&header));
+        fresh.load_battery_data(&saved[..100]);
+        assert_eq!(fresh.battery_data().unwrap()[2 * 16384 + 0x10], 0xFF);
+        fresh.load_battery_data(&saved);
+        fresh.write_prg_rom(0xC000, 2);
+        assert_eq!(fresh.read_prd_rom(0x8010), 0x5A);
+    }
+
+    #[test]
+    fn test_battery_prg_ram() {
+        let mut cart = SimpleCartridge::new(vec![0xFF; 16384], Vec::new());
+        cart.write_prm_ram(0x6000, 0x42);
+        assert!(cart.battery_data().is_none());
+        assert!(!cart.take_battery_dirty());
+
+        let mut cart = cart.with_battery(true);
+        cart.write_prg_rom(0x8000, 0x01);
+        assert!(!cart.take_battery_dirty());
+        cart.write_prm_ram(0x7FFF, 0x24);
+        assert!(cart.take_battery_dirty());
+        assert!(!cart.take_battery_dirty());
+        let saved = cart.battery_data().unwrap().to_vec();
+        assert_eq!((saved.len(), saved[0], saved[0x1FFF]), (8192, 0x42, 0x24));
+
+        let mut fresh = SimpleCartridge::new(vec![0xFF; 16384], Vec::new()).with_battery(true);
+        fresh.write_prm_ram(0x6000, 0x00);
+        fresh.load_battery_data(&saved);
+        assert!(!fresh.take_battery_dirty());
+        assert_eq!((fresh.read_prm_ram(0x6000), fresh.read_prm_ram(0x7FFF)), (0x42, 0x24));
+    }
+
     #[test]
     fn test_cartridge_creation() {
         let prg_rom = vec![0xFF; 16384]; // 16KB
         let chr_rom = vec![0x00; 8192];  // 8KB
         let cart = SimpleCartridge::new(prg_rom, chr_rom);
 
-        assert_eq!(cart.prg_rom_size(), 16384);
-        assert_eq!(cart.chr_rom_size(), 8192);
+        assert_eq!(cart.prg_rom.len(), 16384);
+        assert_eq!(cart.chr_rom.len(), 8192);
     }
 
     #[test]
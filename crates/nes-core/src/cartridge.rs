@@ -3,11 +3,25 @@
 //! This module handles cartridge ROM loading and mapper logic.
 //! Mappers are used to expand the addressable memory beyond the NES limitations.
 
-use crate::bus::Mapper;
+pub use crate::mapper_interface::Mirroring;
+use crate::region::Region;
+use crate::romdb::{crc32, crc32_update};
 
 /// iNES header size
 pub const HEADER_SIZE: usize = 16;
 
+/// Mapper types
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Mapper {
+    /// NROM - Simple mapper, no bank switching
+    #[default]
+    NROM,
+    /// UxROM - Simple mapper with PRG bank switching
+    UXROM,
+    /// CNROM - Simple mapper with CHR bank switching
+    CNROM,
+}
+
 /// iNES header structure
 #[derive(Debug, Clone)]
 pub struct InesHeader {
@@ -58,7 +72,7 @@ impl InesHeader {
 
     /// Get the mapper number from flags
     pub fn mapper_number(&self) -> u8 {
-        ((self.flags_6 >> 4) as u8) | (self.flags_7 as u8 & 0xF0)
+        (self.flags_6 >> 4) | (self.flags_7 & 0xF0)
     }
 
     /// Check if trainer is present
@@ -71,10 +85,63 @@ impl InesHeader {
         (self.flags_6 & 0x01) != 0
     }
 
+    /// Get the soldered mirroring (flags 6 bit 0, or four-screen with bit 3)
+    ///
+    /// Boards with a mirroring register override it at runtime; see
+    /// `MapperState::current_mirroring`.
+    pub fn mirroring(&self) -> Mirroring {
+        if self.flags_6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if self.flags_6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+
     /// Check if SRAM is present
     pub fn has_sram(&self) -> bool {
         (self.flags_6 & 0x02) != 0
     }
+
+    /// Check if the header uses the NES 2.0 format
+    pub fn is_nes2(&self) -> bool {
+        (self.flags_7 & 0x0C) == 0x08
+    }
+
+    /// Get the NES 2.0 submapper number (None for iNES 1.0 headers)
+    pub fn submapper(&self) -> Option<u8> {
+        self.is_nes2().then_some(self.prg_ram_size >> 4)
+    }
+
+    /// Get the console region declared by a NES 2.0 header (byte 12)
+    ///
+    /// None for iNES 1.0 headers and for multi-region games, which run on any console.
+    pub fn region(&self) -> Option<Region> {
+        if !self.is_nes2() {
+            return None;
+        }
+        match self.padding[1] & 0x03 {
+            0 => Some(Region::Ntsc),
+            1 => Some(Region::Pal),
+            3 => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    /// Get the PRG RAM size in bytes declared by the header, if any
+    ///
+    /// NES 2.0 headers give volatile and battery-backed sizes as shift counts
+    /// (64 << n bytes); iNES 1.0 headers give 8KB units, where 0 means unspecified.
+    pub fn prg_ram_bytes(&self) -> Option<usize> {
+        if self.is_nes2() {
+            let size = |shift: u8| if shift == 0 { 0 } else { 64usize << shift };
+            let total = size(self.flags_10 & 0x0F) + size(self.flags_10 >> 4);
+            (total > 0).then_some(total)
+        } else {
+            (self.prg_ram_size > 0).then(|| self.prg_ram_size as usize * 8 * 1024)
+        }
+    }
 }
 
 /// Cartridge structure
@@ -107,6 +174,9 @@ impl Cartridge {
 
         // Skip trainer if present
         let trainer = if header.has_trainer() {
+            if rom_data.len() < offset + 512 {
+                return Err(CartridgeError::InvalidData("Truncated trainer"));
+            }
             let trainer_data = rom_data[offset..offset + 512].to_vec();
             offset += 512;
             Some(trainer_data)
@@ -116,11 +186,17 @@ impl Cartridge {
 
         // PRG ROM
         let prg_rom_size = header.prg_rom_size as usize * 16 * 1024;
+        if rom_data.len() < offset + prg_rom_size {
+            return Err(CartridgeError::InvalidData("Truncated PRG ROM"));
+        }
         let prg_rom = rom_data[offset..offset + prg_rom_size].to_vec();
         offset += prg_rom_size;
 
         // CHR ROM
         let chr_rom_size = header.chr_rom_size as usize * 8 * 1024;
+        if rom_data.len() < offset + chr_rom_size {
+            return Err(CartridgeError::InvalidData("Truncated CHR ROM"));
+        }
         let chr_rom = rom_data[offset..offset + chr_rom_size].to_vec();
 
         // Determine mapper
@@ -156,6 +232,16 @@ impl Cartridge {
         &self.chr_rom
     }
 
+    /// Get the CRC32 of PRG ROM followed by CHR ROM (used as the ROM database key)
+    pub fn crc32(&self) -> u32 {
+        crc32_update(crc32(&self.prg_rom), &self.chr_rom)
+    }
+
+    /// Get trainer data, if present
+    pub fn trainer(&self) -> Option<&[u8]> {
+        self.trainer.as_deref()
+    }
+
     /// Read from PRG ROM with mapper addressing
     pub fn read_prd_rom(&self, address: u16) -> u8 {
         match self.mapper {
@@ -235,6 +321,7 @@ impl Cartridge {
 
 /// Cartridge error types
 #[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
 pub enum CartridgeError {
     InvalidHeader(&'static str),
     InvalidData(&'static str),
@@ -268,6 +355,40 @@ mod tests {
         assert_eq!(header.chr_rom_size, 1);
     }
 
+    #[test]
+    fn test_prg_ram_size_from_header() {
+        let mut header_data = [0u8; HEADER_SIZE];
+        header_data[0..4].copy_from_slice(b"NES\x1A");
+        assert_eq!(InesHeader::parse(&header_data).unwrap().prg_ram_bytes(), None);
+
+        // iNES 1.0: 8KB units
+        header_data[8] = 2;
+        assert_eq!(InesHeader::parse(&header_data).unwrap().prg_ram_bytes(), Some(16 * 1024));
+
+        // NES 2.0: 8KB volatile (64 << 7) plus 8KB battery-backed
+        header_data[7] = 0x08;
+        header_data[8] = 0;
+        header_data[10] = 0x77;
+        let header = InesHeader::parse(&header_data).unwrap();
+        assert!(header.is_nes2());
+        assert_eq!(header.prg_ram_bytes(), Some(16 * 1024));
+    }
+
+    #[test]
+    fn test_region_from_nes2_header() {
+        let mut header_data = [0u8; HEADER_SIZE];
+        header_data[0..4].copy_from_slice(b"NES\x1A");
+        header_data[12] = 1;
+        assert_eq!(InesHeader::parse(&header_data).unwrap().region(), None);
+
+        header_data[7] = 0x08;
+        let regions = [Some(Region::Ntsc), Some(Region::Pal), None, Some(Region::Dendy)];
+        for (timing, region) in regions.into_iter().enumerate() {
+            header_data[12] = timing as u8;
+            assert_eq!(InesHeader::parse(&header_data).unwrap().region(), region);
+        }
+    }
+
     #[test]
     fn test_cartridge_from_rom() {
         // Create a minimal iNES ROM
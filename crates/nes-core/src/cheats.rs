@@ -0,0 +1,361 @@
+//! Cheat codes
+//!
+//! Three cartridge cheat devices are decoded, plus a raw format:
+//!
+//! - Game Genie: 6 or 8 letters. Patches a PRG ROM byte; 8-letter codes add
+//!   a compare byte so the patch only applies while the original ROM byte
+//!   matches (which keeps it from hitting other banks mapped at the address).
+//! - Pro Action Replay: 8 hex digits `00AAAAVV`. Writes VV to RAM address
+//!   AAAA once a frame, freezing the value.
+//! - Pro Action Rocky: 8 hex digits, scrambled. Patches a PRG ROM byte with
+//!   a compare byte.
+//! - Raw: `ADDR:VALUE` or `ADDR?COMPARE:VALUE` in hex, with an optional
+//!   `@BANK` suffix that limits a PRG patch to one 8KB bank of PRG ROM.
+//!
+//! PRG patches ($8000-$FFFF) are applied as the CPU reads the byte, against
+//! the ROM byte the mapper currently selects. RAM cheats ($0000-$1FFF and
+//! $6000-$7FFF) are written at the end of every frame, and skipped while a
+//! compare byte doesn't match.
+
+use std::fmt;
+
+/// Game Genie letters in the order of their 4-bit values
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+/// Bank size used by bank-aware patches (the smallest PRG bank any mapper switches)
+pub const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Pro Action Rocky key, and the value it's stirred with after every set bit
+const ROCKY_KEY: u32 = 0x7E5E_E93A;
+const ROCKY_XOR: u32 = 0x5C18_4B91;
+
+/// Bit of the decoded Rocky code each scrambled bit lands on, last first
+/// (15 address bits, then 8 compare bits, then 8 value bits)
+const ROCKY_SHIFTS: [u32; 31] = [
+    3, 13, 14, 1, 6, 9, 5, 0, 12, 7, 2, 8, 10, 11, 4, // address
+    19, 21, 23, 22, 20, 17, 16, 18, // compare
+    29, 31, 24, 26, 25, 30, 27, 28, // value
+];
+
+/// Code format, which has to be given since Action Replay and Rocky codes look alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CheatFormat {
+    GameGenie,
+    ProActionReplay,
+    ProActionRocky,
+    Raw,
+}
+
+impl CheatFormat {
+    /// Names accepted by `from_name`
+    pub const NAMES: [&'static str; 4] = ["gg", "par", "rocky", "raw"];
+
+    /// Look up a format by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gg" | "gamegenie" => Some(CheatFormat::GameGenie),
+            "par" => Some(CheatFormat::ProActionReplay),
+            "rocky" => Some(CheatFormat::ProActionRocky),
+            "raw" => Some(CheatFormat::Raw),
+            _ => None,
+        }
+    }
+}
+
+/// Error decoding a cheat code
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CheatError {
+    /// Wrong number of letters or digits for the format
+    InvalidLength(String),
+    /// A character outside the format's alphabet
+    InvalidCharacter(char),
+    /// The code decodes to an address the format can't patch
+    InvalidAddress(u16),
+    /// A bank was given for a cheat that isn't a PRG patch
+    BankOutsidePrg(u16),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::InvalidLength(code) => write!(f, "Cheat code '{}' has the wrong length", code),
+            CheatError::InvalidCharacter(c) => write!(f, "Invalid character '{}' in cheat code", c),
+            CheatError::InvalidAddress(address) => write!(f, "Cheat address ${:04X} can't be patched", address),
+            CheatError::BankOutsidePrg(address) => {
+                write!(f, "Cheat at ${:04X} is not in PRG ROM and can't have a bank", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+/// A decoded cheat: a byte patched into PRG ROM or frozen in RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cheat {
+    /// CPU address
+    pub address: u16,
+    /// Byte to read (PRG) or write (RAM)
+    pub value: u8,
+    /// The cheat only applies while the original byte equals this
+    pub compare: Option<u8>,
+    /// 8KB PRG ROM bank a PRG patch is limited to (None patches any bank)
+    pub bank: Option<u16>,
+}
+
+impl Cheat {
+    /// Decode a code in the given format
+    pub fn decode(format: CheatFormat, code: &str) -> Result<Self, CheatError> {
+        let code = code.trim();
+        match format {
+            CheatFormat::GameGenie => Self::decode_game_genie(code),
+            CheatFormat::ProActionReplay => Self::decode_action_replay(code),
+            CheatFormat::ProActionRocky => Self::decode_rocky(code),
+            CheatFormat::Raw => Self::parse_raw(code),
+        }
+    }
+
+    /// Decode a 6- or 8-letter Game Genie code
+    pub fn decode_game_genie(code: &str) -> Result<Self, CheatError> {
+        if code.len() != 6 && code.len() != 8 {
+            return Err(CheatError::InvalidLength(code.to_string()));
+        }
+        let n = code
+            .chars()
+            .map(|c| {
+                let upper = c.to_ascii_uppercase();
+                GAME_GENIE_LETTERS
+                    .iter()
+                    .position(|&letter| char::from(letter) == upper)
+                    .map(|value| value as u16)
+                    .ok_or(CheatError::InvalidCharacter(c))
+            })
+            .collect::<Result<Vec<u16>, _>>()?;
+        let address = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[4] & 8) << 8)
+            | ((n[5] & 7) << 8)
+            | ((n[1] & 8) << 4)
+            | ((n[2] & 7) << 4)
+            | (n[3] & 8)
+            | (n[4] & 7);
+        let high = ((n[0] & 8) << 4) | ((n[1] & 7) << 4) | (n[0] & 7);
+        let (value, compare) = if n.len() == 8 {
+            let compare = ((n[6] & 8) << 4) | ((n[7] & 7) << 4) | (n[5] & 8) | (n[6] & 7);
+            (high | (n[7] & 8), Some(compare as u8))
+        } else {
+            (high | (n[5] & 8), None)
+        };
+        Ok(Self { address, value: value as u8, compare, bank: None })
+    }
+
+    /// Decode an 8-digit Pro Action Replay RAM code (`00AAAAVV`)
+    pub fn decode_action_replay(code: &str) -> Result<Self, CheatError> {
+        let raw = parse_hex_digits(code, 8)?;
+        let address = (raw >> 8) as u16;
+        if raw >> 24 != 0 || !is_ram_address(address) {
+            return Err(CheatError::InvalidAddress(address));
+        }
+        Ok(Self { address, value: raw as u8, compare: None, bank: None })
+    }
+
+    /// Decode an 8-digit Pro Action Rocky code
+    pub fn decode_rocky(code: &str) -> Result<Self, CheatError> {
+        // Bit 0 is unused
+        let mut scrambled = parse_hex_digits(code, 8)? >> 1;
+        let mut key = ROCKY_KEY;
+        let mut result = 0u32;
+        for &shift in ROCKY_SHIFTS.iter().rev() {
+            if ((key ^ scrambled) >> 30) & 1 != 0 {
+                result |= 1 << shift;
+                key ^= ROCKY_XOR;
+            }
+            scrambled <<= 1;
+            key <<= 1;
+        }
+        Ok(Self {
+            address: 0x8000 | (result & 0x7FFF) as u16,
+            value: (result >> 24) as u8,
+            compare: Some((result >> 16) as u8),
+            bank: None,
+        })
+    }
+
+    /// Parse `ADDR:VALUE` or `ADDR?COMPARE:VALUE`, optionally followed by `@BANK`
+    pub fn parse_raw(code: &str) -> Result<Self, CheatError> {
+        let (code, bank) = match code.split_once('@') {
+            Some((code, bank)) => (code, Some(parse_hex(bank)?)),
+            None => (code, None),
+        };
+        let (target, value) = code.split_once(':').ok_or_else(|| CheatError::InvalidLength(code.to_string()))?;
+        let (address, compare) = match target.split_once('?') {
+            Some((address, compare)) => (address, Some(parse_hex(compare)?)),
+            None => (target, None),
+        };
+        let address = parse_hex(address)?;
+        let to_byte = |value: u16| u8::try_from(value).map_err(|_| CheatError::InvalidLength(code.to_string()));
+        let cheat = Self {
+            address,
+            value: to_byte(parse_hex(value)?)?,
+            compare: compare.map(to_byte).transpose()?,
+            bank,
+        };
+        if !cheat.is_prg_patch() && !is_ram_address(address) {
+            return Err(CheatError::InvalidAddress(address));
+        }
+        if bank.is_some() && !cheat.is_prg_patch() {
+            return Err(CheatError::BankOutsidePrg(address));
+        }
+        Ok(cheat)
+    }
+
+    /// Limit a PRG patch to one 8KB bank of PRG ROM
+    pub fn in_bank(mut self, bank: u16) -> Self {
+        self.bank = Some(bank);
+        self
+    }
+
+    /// Check if the cheat patches PRG ROM reads rather than writing RAM
+    pub fn is_prg_patch(&self) -> bool {
+        self.address >= 0x8000
+    }
+
+    /// Get the byte read for a PRG patch, given the ROM offset the mapper selects and the byte there
+    pub fn patch_prg(&self, address: u16, prg_offset: usize, original: u8) -> Option<u8> {
+        let applies = self.is_prg_patch()
+            && self.address == address
+            && self.compare.is_none_or(|compare| compare == original)
+            && self.bank.is_none_or(|bank| usize::from(bank) == prg_offset / PRG_BANK_SIZE);
+        applies.then_some(self.value)
+    }
+}
+
+impl fmt::Display for Cheat {
+    /// Write the cheat in the raw format
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}", self.address)?;
+        if let Some(compare) = self.compare {
+            write!(f, "?{:02X}", compare)?;
+        }
+        write!(f, ":{:02X}", self.value)?;
+        if let Some(bank) = self.bank {
+            write!(f, "@{:X}", bank)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply every matching PRG patch to a byte read from PRG ROM
+pub fn patch_prg(cheats: &[Cheat], address: u16, prg_offset: usize, original: u8) -> u8 {
+    cheats
+        .iter()
+        .find_map(|cheat| cheat.patch_prg(address, prg_offset, original))
+        .unwrap_or(original)
+}
+
+/// Check if an address is CPU RAM or cartridge PRG RAM
+fn is_ram_address(address: u16) -> bool {
+    matches!(address, 0x0000..=0x1FFF | 0x6000..=0x7FFF)
+}
+
+/// Parse exactly `digits` hex digits
+fn parse_hex_digits(code: &str, digits: usize) -> Result<u32, CheatError> {
+    if code.len() != digits {
+        return Err(CheatError::InvalidLength(code.to_string()));
+    }
+    code.chars().try_fold(0u32, |value, c| {
+        c.to_digit(16).map(|digit| (value << 4) | digit).ok_or(CheatError::InvalidCharacter(c))
+    })
+}
+
+/// Parse a hex number of up to four digits
+fn parse_hex(text: &str) -> Result<u16, CheatError> {
+    let text = text.trim().trim_start_matches('$');
+    if text.is_empty() || text.len() > 4 {
+        return Err(CheatError::InvalidLength(text.to_string()));
+    }
+    parse_hex_digits(text, text.len()).map(|value| value as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scramble a decoded Rocky code (address bits, compare << 16, value << 24)
+    fn encode_rocky(decoded: u32) -> u32 {
+        let mut key = ROCKY_KEY;
+        let mut code = 0u32;
+        for (i, &shift) in ROCKY_SHIFTS.iter().enumerate().rev() {
+            let bit = (decoded >> shift) & 1;
+            code |= (bit ^ ((key >> 30) & 1)) << i;
+            if bit != 0 {
+                key ^= ROCKY_XOR;
+            }
+            key <<= 1;
+        }
+        code << 1
+    }
+
+    #[test]
+    fn test_game_genie() {
+        // Super Mario Bros. infinite lives: LDA replaces the DEC of the life counter
+        let cheat = Cheat::decode(CheatFormat::GameGenie, "SXIOPO").unwrap();
+        assert_eq!(cheat, Cheat { address: 0x91D9, value: 0xAD, compare: None, bank: None });
+        assert_eq!(Cheat::decode_game_genie("sxiopo"), Ok(cheat));
+
+        let cheat = Cheat::decode_game_genie("SXIOPOAP").unwrap();
+        assert_eq!(cheat.address, 0x91D9);
+        assert_eq!((cheat.value, cheat.compare), (0xA5, Some(0x18)));
+
+        assert_eq!(Cheat::decode_game_genie("SXIOP"), Err(CheatError::InvalidLength("SXIOP".to_string())));
+        assert_eq!(Cheat::decode_game_genie("SXIOPB"), Err(CheatError::InvalidCharacter('B')));
+    }
+
+    #[test]
+    fn test_action_replay() {
+        let cheat = Cheat::decode(CheatFormat::ProActionReplay, "00075A09").unwrap();
+        assert_eq!(cheat, Cheat { address: 0x075A, value: 0x09, compare: None, bank: None });
+        assert!(!cheat.is_prg_patch());
+        assert_eq!(Cheat::decode_action_replay("00C00001"), Err(CheatError::InvalidAddress(0xC000)));
+        assert_eq!(Cheat::decode_action_replay("0075A09"), Err(CheatError::InvalidLength("0075A09".to_string())));
+    }
+
+    #[test]
+    fn test_rocky() {
+        let decoded = 0xADCE_11D9;
+        let code = format!("{:08X}", encode_rocky(decoded));
+        let cheat = Cheat::decode(CheatFormat::ProActionRocky, &code).unwrap();
+        assert_eq!(cheat, Cheat { address: 0x91D9, value: 0xAD, compare: Some(0xCE), bank: None });
+        // The unused low bit doesn't change the code
+        let odd = format!("{:08X}", encode_rocky(decoded) | 1);
+        assert_eq!(Cheat::decode_rocky(&odd), Ok(cheat));
+        assert_eq!(Cheat::decode_rocky("0123456G"), Err(CheatError::InvalidCharacter('G')));
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        for text in ["91D9:AD", "91D9?CE:AD", "91D9?CE:AD@3", "075A:09", "6010:FF"] {
+            let cheat = Cheat::decode(CheatFormat::Raw, text).unwrap();
+            assert_eq!(cheat.to_string(), text);
+        }
+        assert_eq!(Cheat::parse_raw("$8000:EA@1").unwrap().bank, Some(1));
+        assert_eq!(Cheat::parse_raw("4016:01"), Err(CheatError::InvalidAddress(0x4016)));
+        assert_eq!(Cheat::parse_raw("075A:09@1"), Err(CheatError::BankOutsidePrg(0x075A)));
+        assert_eq!(Cheat::parse_raw("075A:109"), Err(CheatError::InvalidLength("075A:109".to_string())));
+    }
+
+    #[test]
+    fn test_patch_prg_compare_and_bank() {
+        let cheats = [Cheat::parse_raw("8000?CE:AD@1").unwrap(), Cheat::parse_raw("8001:EA").unwrap()];
+        // Bank 1 is mapped and the original byte matches
+        assert_eq!(patch_prg(&cheats, 0x8000, 0x2000, 0xCE), 0xAD);
+        // Wrong bank, or a different byte in the right bank
+        assert_eq!(patch_prg(&cheats, 0x8000, 0x4000, 0xCE), 0xCE);
+        assert_eq!(patch_prg(&cheats, 0x8000, 0x2000, 0x60), 0x60);
+        // Unconditional patch, and an untouched address
+        assert_eq!(patch_prg(&cheats, 0x8001, 0x7001, 0x00), 0xEA);
+        assert_eq!(patch_prg(&cheats, 0x8002, 0x2002, 0x42), 0x42);
+    }
+}
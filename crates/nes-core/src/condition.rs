@@ -0,0 +1,156 @@
+//! RAM conditions
+//!
+//! A condition compares one byte of CPU RAM against a constant, written as
+//! `ADDR OP VALUE` (for example `0x075F >= 2`). Addresses and values accept
+//! `0x`/`$` hex or decimal; addresses in $0000-$1FFF follow the RAM mirrors.
+//! Conditions are evaluated against `NesSystem::ram`, so they never trigger
+//! bus side effects. They are the building block for achievements and other
+//! memory watches.
+
+use std::fmt;
+
+/// Internal RAM range conditions can address (2KB mirrored four times)
+const RAM_MIRROR_END: u16 = 0x1FFF;
+
+/// Comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Operators in the order they're matched (two-character ones first)
+    const SYMBOLS: [(&'static str, CompareOp); 6] = [
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+
+    /// Operator as written in condition text
+    pub fn symbol(self) -> &'static str {
+        Self::SYMBOLS.iter().find(|(_, op)| *op == self).map(|(s, _)| *s).unwrap_or("==")
+    }
+
+    /// Apply the comparison
+    pub fn compare(self, left: u8, right: u8) -> bool {
+        match self {
+            CompareOp::Eq => left == right,
+            CompareOp::Ne => left != right,
+            CompareOp::Lt => left < right,
+            CompareOp::Le => left <= right,
+            CompareOp::Gt => left > right,
+            CompareOp::Ge => left >= right,
+        }
+    }
+}
+
+/// Error parsing a condition
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConditionError {
+    /// No comparison operator found
+    MissingOperator(String),
+    /// Address is not a number in the RAM range
+    InvalidAddress(String),
+    /// Value is not a byte
+    InvalidValue(String),
+}
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionError::MissingOperator(text) => write!(f, "Missing comparison operator in '{}'", text),
+            ConditionError::InvalidAddress(text) => write!(f, "Invalid RAM address '{}' (expected $0000-$1FFF)", text),
+            ConditionError::InvalidValue(text) => write!(f, "Invalid byte value '{}'", text),
+        }
+    }
+}
+
+impl std::error::Error for ConditionError {}
+
+/// `ADDR OP VALUE` comparison against CPU RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RamCondition {
+    pub address: u16,
+    pub op: CompareOp,
+    pub value: u8,
+}
+
+impl RamCondition {
+    /// Parse `ADDR OP VALUE`
+    pub fn parse(text: &str) -> Result<Self, ConditionError> {
+        let (position, symbol, op) = CompareOp::SYMBOLS
+            .iter()
+            .filter_map(|&(symbol, op)| text.find(symbol).map(|pos| (pos, symbol, op)))
+            .min_by_key(|&(pos, symbol, _)| (pos, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(|| ConditionError::MissingOperator(text.trim().to_string()))?;
+        let address_text = text[..position].trim();
+        let value_text = text[position + symbol.len()..].trim();
+        let address = parse_number(address_text)
+            .and_then(|a| u16::try_from(a).ok())
+            .filter(|&a| a <= RAM_MIRROR_END)
+            .ok_or_else(|| ConditionError::InvalidAddress(address_text.to_string()))?;
+        let value = parse_number(value_text)
+            .and_then(|v| u8::try_from(v).ok())
+            .ok_or_else(|| ConditionError::InvalidValue(value_text.to_string()))?;
+        Ok(Self { address, op, value })
+    }
+
+    /// Check the condition against the 2KB internal RAM
+    pub fn matches(&self, ram: &[u8]) -> bool {
+        let byte = ram.get(self.address as usize % ram.len().max(1)).copied().unwrap_or(0);
+        self.op.compare(byte, self.value)
+    }
+}
+
+impl fmt::Display for RamCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:04X} {} 0x{:02X}", self.address, self.op.symbol(), self.value)
+    }
+}
+
+/// Parse `0x`/`$` hex or decimal
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).or_else(|| text.strip_prefix('$')) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match() {
+        let mut ram = vec![0u8; 0x800];
+        ram[0x75F] = 2;
+
+        let condition = RamCondition::parse("0x075F >= 2").unwrap();
+        assert_eq!(condition, RamCondition { address: 0x075F, op: CompareOp::Ge, value: 2 });
+        assert!(condition.matches(&ram));
+        assert!(!RamCondition::parse("$75F<2").unwrap().matches(&ram));
+        assert!(RamCondition::parse("1887 != 0").unwrap().matches(&ram));
+        // Mirrors of $0000-$07FF
+        assert!(RamCondition::parse("0x0F5F == $02").unwrap().matches(&ram));
+        assert_eq!(condition.to_string(), "0x075F >= 0x02");
+        assert_eq!(RamCondition::parse(&condition.to_string()), Ok(condition));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(RamCondition::parse("0x10"), Err(ConditionError::MissingOperator("0x10".to_string())));
+        assert_eq!(RamCondition::parse("0x6000 == 1"), Err(ConditionError::InvalidAddress("0x6000".to_string())));
+        assert_eq!(RamCondition::parse("0x10 > 256"), Err(ConditionError::InvalidValue("256".to_string())));
+        assert_eq!(RamCondition::parse("lives == 1"), Err(ConditionError::InvalidAddress("lives".to_string())));
+    }
+}
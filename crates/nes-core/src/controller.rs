@@ -0,0 +1,296 @@
+//! Standard controller (joypad) emulation
+//!
+//! Writing bit 0 of $4016 sets the strobe, which continuously reloads each
+//! controller's shift register from the current button state. Once the strobe
+//! is cleared, every read of $4016 (port 1) or $4017 (port 2) returns the next
+//! button in the order A, B, Select, Start, Up, Down, Left, Right, followed by
+//! 1s once all eight have been shifted out.
+//!
+//! A real D-pad can't press opposing directions together, and some games
+//! glitch badly when they see Left+Right or Up+Down (easy to hit from a
+//! keyboard). `DpadPolicy` decides what the console sees in that case; the
+//! resolved state is what `buttons()` returns, so recorded input matches
+//! what the game read.
+//!
+//! `InputEcho` records the other side of that: every byte the game got back
+//! from $4016 and $4017, open-bus bits included, so TAS tools and tests can
+//! check what the game observed rather than what the frontend pressed.
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Upper bits returned with controller reads (open bus on most consoles)
+pub const OPEN_BUS_BITS: u8 = 0x40;
+
+/// Button state for one standard controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Buttons(u8);
+
+impl Buttons {
+    pub const A: u8 = 0x01;
+    pub const B: u8 = 0x02;
+    pub const SELECT: u8 = 0x04;
+    pub const START: u8 = 0x08;
+    pub const UP: u8 = 0x10;
+    pub const DOWN: u8 = 0x20;
+    pub const LEFT: u8 = 0x40;
+    pub const RIGHT: u8 = 0x80;
+
+    /// Lowercase button names with their bits, in bit order
+    pub const NAMES: [(&'static str, u8); 8] = [
+        ("a", Buttons::A),
+        ("b", Buttons::B),
+        ("select", Buttons::SELECT),
+        ("start", Buttons::START),
+        ("up", Buttons::UP),
+        ("down", Buttons::DOWN),
+        ("left", Buttons::LEFT),
+        ("right", Buttons::RIGHT),
+    ];
+
+    /// Look up a button's bit by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<u8> {
+        Self::NAMES.iter().find(|(button, _)| button.eq_ignore_ascii_case(name)).map(|&(_, bit)| bit)
+    }
+
+    /// Create button state from a bitmask (bit 0 = A ... bit 7 = Right)
+    pub fn new(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// Get the bitmask
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Check if all of the given buttons are pressed
+    pub fn contains(&self, buttons: u8) -> bool {
+        self.0 & buttons == buttons
+    }
+
+    /// Press or release the given buttons
+    pub fn set(&mut self, buttons: u8, pressed: bool) {
+        if pressed {
+            self.0 |= buttons;
+        } else {
+            self.0 &= !buttons;
+        }
+    }
+}
+
+/// How simultaneous opposing D-pad directions are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum DpadPolicy {
+    /// Pass both directions through, as input movies made for it expect
+    #[default]
+    Allow,
+    /// Release both directions
+    Block,
+    /// Keep the direction pressed last; if both go down together, neither
+    LastPressed,
+}
+
+/// Opposing direction pairs
+const DPAD_AXES: [u8; 2] = [Buttons::UP | Buttons::DOWN, Buttons::LEFT | Buttons::RIGHT];
+
+/// Bytes the game read from the controller ports
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputEcho {
+    /// Reads of $4016 and $4017, in order
+    reads: [Vec<u8>; 2],
+}
+
+impl InputEcho {
+    /// Create an empty echo
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a byte read from a port
+    pub(crate) fn record(&mut self, port: usize, value: u8) {
+        self.reads[port].push(value);
+    }
+
+    /// Get the bytes read from a port (0 or 1), in order
+    pub fn reads(&self, port: usize) -> &[u8] {
+        self.reads.get(port).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Get bit 0 of each byte read from a port: the serial button data a standard controller sends
+    pub fn bits(&self, port: usize) -> Vec<u8> {
+        self.reads(port).iter().map(|value| value & 0x01).collect()
+    }
+
+    /// Check if neither port was read
+    pub fn is_empty(&self) -> bool {
+        self.reads.iter().all(Vec::is_empty)
+    }
+
+    /// Forget all reads
+    pub fn clear(&mut self) {
+        self.reads.iter_mut().for_each(Vec::clear);
+    }
+}
+
+/// Standard controller shift register
+#[derive(Debug, Clone, Default)]
+pub struct Controller {
+    /// Resolved button state, as the console sees it
+    buttons: Buttons,
+    /// Button state last set, before resolving
+    held: Buttons,
+    dpad_policy: DpadPolicy,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    /// Create a controller with no buttons pressed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the current button state, with opposing directions resolved
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    /// Set the current button state
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        let resolved = self.resolve(buttons);
+        self.held = buttons;
+        self.buttons = resolved;
+        if self.strobe {
+            self.shift = resolved.bits();
+        }
+    }
+
+    /// Get the opposing direction policy
+    pub fn dpad_policy(&self) -> DpadPolicy {
+        self.dpad_policy
+    }
+
+    /// Set the opposing direction policy, applying it to the held buttons
+    pub fn set_dpad_policy(&mut self, policy: DpadPolicy) {
+        self.dpad_policy = policy;
+        self.set_buttons(self.held);
+    }
+
+    /// Apply the D-pad policy to newly set buttons
+    fn resolve(&self, buttons: Buttons) -> Buttons {
+        let mut bits = buttons.bits();
+        for axis in DPAD_AXES {
+            if bits & axis != axis {
+                continue;
+            }
+            let kept = match self.dpad_policy {
+                DpadPolicy::Allow => axis,
+                DpadPolicy::Block => 0,
+                DpadPolicy::LastPressed => {
+                    let pressed = axis & !self.held.bits();
+                    // With no single new press, the previous winner (if any) stays
+                    if pressed.count_ones() == 1 { pressed } else { self.buttons.bits() & axis }
+                }
+            };
+            bits = (bits & !axis) | kept;
+        }
+        Buttons::new(bits)
+    }
+
+    /// Handle a write to $4016 (bit 0 is the strobe)
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons.bits();
+        }
+    }
+
+    /// Serialize the buttons and shift register
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.buttons.bits());
+        writer.write_u8(self.shift);
+        writer.write_bool(self.strobe);
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.buttons = Buttons::new(reader.read_u8()?);
+        self.held = self.buttons;
+        self.shift = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+
+    /// Read the next bit from the shift register
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return (self.buttons.bits() & 0x01) | OPEN_BUS_BITS;
+        }
+        let bit = self.shift & 0x01;
+        // Official controllers shift in 1s after the eighth read
+        self.shift = (self.shift >> 1) | 0x80;
+        bit | OPEN_BUS_BITS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_order() {
+        let mut controller = Controller::new();
+        controller.set_buttons(Buttons::new(Buttons::A | Buttons::START | Buttons::RIGHT));
+        controller.write_strobe(1);
+        controller.write_strobe(0);
+
+        let bits: Vec<u8> = (0..10).map(|_| controller.read() & 0x01).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_strobe_high_returns_a() {
+        let mut controller = Controller::new();
+        controller.write_strobe(1);
+        controller.set_buttons(Buttons::new(Buttons::A));
+        assert_eq!(controller.read(), 0x41);
+        assert_eq!(controller.read(), 0x41);
+    }
+
+    #[test]
+    fn test_button_names() {
+        assert_eq!(Buttons::from_name("Start"), Some(Buttons::START));
+        assert_eq!(Buttons::from_name("right"), Some(Buttons::RIGHT));
+        assert_eq!(Buttons::from_name("turbo"), None);
+    }
+
+    #[test]
+    fn test_dpad_policy() {
+        let both = Buttons::new(Buttons::LEFT | Buttons::RIGHT | Buttons::A);
+        let mut controller = Controller::new();
+        controller.set_buttons(both);
+        assert_eq!(controller.buttons(), both);
+
+        // Blocking releases both directions but leaves the other buttons
+        controller.set_dpad_policy(DpadPolicy::Block);
+        assert_eq!(controller.buttons(), Buttons::new(Buttons::A));
+        controller.write_strobe(1);
+        controller.write_strobe(0);
+        let bits: Vec<u8> = (0..8).map(|_| controller.read() & 0x01).collect();
+        assert_eq!(bits, [1, 0, 0, 0, 0, 0, 0, 0]);
+
+        // The newer press wins and keeps winning while both are held
+        controller.set_dpad_policy(DpadPolicy::LastPressed);
+        controller.set_buttons(Buttons::new(Buttons::LEFT));
+        controller.set_buttons(Buttons::new(Buttons::LEFT | Buttons::RIGHT));
+        assert_eq!(controller.buttons(), Buttons::new(Buttons::RIGHT));
+        controller.set_buttons(Buttons::new(Buttons::LEFT | Buttons::RIGHT | Buttons::UP));
+        assert_eq!(controller.buttons(), Buttons::new(Buttons::RIGHT | Buttons::UP));
+        controller.set_buttons(Buttons::new(Buttons::LEFT));
+        assert_eq!(controller.buttons(), Buttons::new(Buttons::LEFT));
+        // Pressed on the same frame, neither wins
+        controller.set_buttons(Buttons::new(0));
+        controller.set_buttons(Buttons::new(Buttons::UP | Buttons::DOWN));
+        assert_eq!(controller.buttons(), Buttons::new(0));
+    }
+}
@@ -0,0 +1,253 @@
+//! Heuristic detection of crashed games
+//!
+//! A ROM that hits unsupported mapper behaviour usually doesn't fail loudly;
+//! the CPU wanders off and the screen stays black. The detector watches for
+//! the common signatures and reports each one once per reset:
+//! - an interrupt vector ($FFFA/$FFFC/$FFFE) that reads $0000
+//! - the CPU executing from RAM that is all zeros (BRK loop)
+//! - no PPU register access for `SILENT_PPU_FRAMES` frames
+//!
+//! Each report names the likely causes, including the mapper when it isn't
+//! one the core supports.
+
+use crate::mapper::MapperState;
+use std::fmt;
+
+/// Frames without PPU register access before the game counts as stuck (1s at 60Hz)
+pub const SILENT_PPU_FRAMES: u32 = 60;
+/// Zero bytes at PC that count as executing cleared RAM
+const ZEROED_RUN: usize = 8;
+
+/// CPU interrupt vector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vector {
+    Nmi,
+    Reset,
+    Irq,
+}
+
+impl Vector {
+    /// All vectors with their addresses
+    pub const ALL: [(Vector, u16); 3] = [(Vector::Nmi, 0xFFFA), (Vector::Reset, 0xFFFC), (Vector::Irq, 0xFFFE)];
+
+    /// Vector name
+    pub fn name(self) -> &'static str {
+        match self {
+            Vector::Nmi => "NMI",
+            Vector::Reset => "RESET",
+            Vector::Irq => "IRQ",
+        }
+    }
+}
+
+/// Failure signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CrashSignature {
+    /// An interrupt vector points at $0000
+    NullVector(Vector),
+    /// The CPU is executing RAM filled with zeros
+    ZeroedRamExecution,
+    /// No PPU register was read or written for this many frames
+    PpuSilent(u32),
+}
+
+impl CrashSignature {
+    /// Index into the reported flags
+    fn slot(self) -> usize {
+        match self {
+            CrashSignature::NullVector(_) => 0,
+            CrashSignature::ZeroedRamExecution => 1,
+            CrashSignature::PpuSilent(_) => 2,
+        }
+    }
+}
+
+impl fmt::Display for CrashSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrashSignature::NullVector(vector) => write!(f, "{} vector points at $0000", vector.name()),
+            CrashSignature::ZeroedRamExecution => write!(f, "executing zero-filled RAM"),
+            CrashSignature::PpuSilent(frames) => write!(f, "no PPU register access for {} frames", frames),
+        }
+    }
+}
+
+/// A detected failure with its context and likely causes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CrashDiagnostic {
+    pub signature: CrashSignature,
+    /// Frame the signature was seen on
+    pub frame: u64,
+    /// CPU program counter at the time
+    pub pc: u16,
+    /// iNES mapper number of the loaded ROM
+    pub mapper: Option<u8>,
+    /// Likely causes, most likely first
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for CrashDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Frame {}: {} (PC=${:04X})", self.frame, self.signature, self.pc)?;
+        if !self.suggestions.is_empty() {
+            write!(f, "; possible causes: {}", self.suggestions.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Watches a running system for crash signatures
+#[derive(Debug, Clone, Default)]
+pub struct CrashDetector {
+    mapper: Option<u8>,
+    silent_frames: u32,
+    /// Signatures already reported since the last reset
+    reported: [bool; 3],
+    diagnostics: Vec<CrashDiagnostic>,
+}
+
+impl CrashDetector {
+    /// Create a detector for a ROM with the given mapper
+    pub fn new(mapper: Option<u8>) -> Self {
+        Self { mapper, ..Self::default() }
+    }
+
+    /// Forget progress, reported signatures and undelivered diagnostics (on reset)
+    pub fn reset(&mut self) {
+        self.silent_frames = 0;
+        self.reported = [false; 3];
+        self.diagnostics.clear();
+    }
+
+    /// Check the interrupt vectors (as read from $FFFA-$FFFF)
+    pub fn check_vectors(&mut self, read: impl Fn(u16) -> u8, frame: u64, pc: u16) {
+        for (vector, address) in Vector::ALL {
+            let target = u16::from_le_bytes([read(address), read(address + 1)]);
+            if target == 0x0000 {
+                self.report(CrashSignature::NullVector(vector), frame, pc);
+            }
+        }
+    }
+
+    /// Check the instruction about to execute against the 2KB internal RAM
+    pub fn check_pc(&mut self, pc: u16, ram: &[u8], frame: u64) {
+        if pc >= 0x2000 || ram.is_empty() || self.reported[CrashSignature::ZeroedRamExecution.slot()] {
+            return;
+        }
+        let zeroed = (0..ZEROED_RUN).all(|i| ram[(pc as usize + i) % ram.len()] == 0);
+        if zeroed {
+            self.report(CrashSignature::ZeroedRamExecution, frame, pc);
+        }
+    }
+
+    /// Finish a frame with the number of PPU register accesses it made
+    pub fn end_frame(&mut self, ppu_accesses: u32, frame: u64, pc: u16) {
+        if ppu_accesses > 0 {
+            self.silent_frames = 0;
+            return;
+        }
+        self.silent_frames += 1;
+        if self.silent_frames >= SILENT_PPU_FRAMES {
+            self.report(CrashSignature::PpuSilent(self.silent_frames), frame, pc);
+        }
+    }
+
+    /// Take the diagnostics reported since the last call, oldest first
+    pub fn take_diagnostics(&mut self) -> Vec<CrashDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn report(&mut self, signature: CrashSignature, frame: u64, pc: u16) {
+        let slot = signature.slot();
+        if self.reported[slot] {
+            return;
+        }
+        self.reported[slot] = true;
+        let suggestions = self.suggestions(signature);
+        self.diagnostics.push(CrashDiagnostic { signature, frame, pc, mapper: self.mapper, suggestions });
+    }
+
+    fn suggestions(&self, signature: CrashSignature) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        match self.mapper {
+            Some(mapper) if !MapperState::supports(mapper) => {
+                suggestions.push(format!("mapper {} is not supported, so PRG banks are mapped as NROM", mapper))
+            }
+            Some(mapper) if mapper != 0 => suggestions.push(format!("mapper {} banking may select the wrong PRG bank", mapper)),
+            _ => {}
+        }
+        match signature {
+            CrashSignature::NullVector(_) => {
+                suggestions.push("the last PRG bank may not be mapped at $C000-$FFFF".to_string());
+                suggestions.push("the ROM dump or iNES header may be bad".to_string());
+            }
+            CrashSignature::ZeroedRamExecution => {
+                suggestions.push("a jump through an uninitialised pointer or a corrupted stack".to_string());
+                suggestions.push("the game may rely on a power-on RAM pattern (try another RAM init)".to_string());
+            }
+            CrashSignature::PpuSilent(_) => {
+                suggestions.push("the game may be waiting on an IRQ or sprite 0 hit that never happens".to_string());
+            }
+        }
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_null_vectors_reported_once() {
+        let mut detector = CrashDetector::new(Some(4));
+        // RESET and IRQ vectors are $0000, NMI is $8000
+        let read = |address: u16| if address == 0xFFFB { 0x80 } else { 0x00 };
+        detector.check_vectors(read, 0, 0xFFFC);
+        detector.check_vectors(read, 0, 0xFFFC);
+        let diagnostics = detector.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].signature, CrashSignature::NullVector(Vector::Reset));
+        assert!(diagnostics[0].suggestions[0].contains("mapper 4 is not supported"));
+        assert!(detector.take_diagnostics().is_empty());
+
+        detector.reset();
+        detector.check_vectors(read, 5, 0xFFFC);
+        assert_eq!(detector.take_diagnostics()[0].frame, 5);
+    }
+
+    #[test]
+    fn test_zeroed_ram_execution() {
+        let mut detector = CrashDetector::new(Some(0));
+        let mut ram = vec![0u8; 0x800];
+        ram[0x0300] = 0xEA;
+        detector.check_pc(0x02FC, &ram, 3);
+        detector.check_pc(0x8000, &ram, 3);
+        assert!(detector.take_diagnostics().is_empty());
+
+        // Mirrors of $0000-$07FF
+        detector.check_pc(0x0900, &ram, 4);
+        let diagnostics = detector.take_diagnostics();
+        assert_eq!(diagnostics[0].signature, CrashSignature::ZeroedRamExecution);
+        assert_eq!(diagnostics[0].pc, 0x0900);
+        assert!(diagnostics[0].to_string().starts_with("Frame 4: executing zero-filled RAM (PC=$0900); possible causes: "));
+    }
+
+    #[test]
+    fn test_silent_ppu() {
+        let mut detector = CrashDetector::new(Some(1));
+        for frame in 0..SILENT_PPU_FRAMES as u64 - 1 {
+            detector.end_frame(0, frame, 0x8000);
+        }
+        detector.end_frame(3, 59, 0x8000);
+        for frame in 0..SILENT_PPU_FRAMES as u64 - 1 {
+            detector.end_frame(0, frame, 0x8000);
+        }
+        assert!(detector.take_diagnostics().is_empty());
+        detector.end_frame(0, 200, 0x8123);
+        let diagnostics = detector.take_diagnostics();
+        assert_eq!(diagnostics[0].signature, CrashSignature::PpuSilent(SILENT_PPU_FRAMES));
+        assert_eq!(diagnostics[0].suggestions[0], "mapper 1 banking may select the wrong PRG bank");
+    }
+}
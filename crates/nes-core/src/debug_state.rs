@@ -0,0 +1,167 @@
+//! Register snapshots for debugger UIs
+//!
+//! `DebugState::capture` gathers the registers a live debugger shows (CPU
+//! registers, PPU raster position and scroll latches, mapper bank registers
+//! the APU channel enables and the devices holding the IRQ line) in one call,
+//! along with the sprite occupancy of the scanlines in OAM, and `to_json`
+//! hands them to a UI that can't link against the core, such as the web
+//! frontend.
+
+use crate::interrupt::IrqSource;
+use crate::sprite_eval::SpriteStats;
+use crate::system::NesSystem;
+use std::fmt::Write;
+
+/// Channel names for bits 0-4 of $4015
+const CHANNELS: [&str; 5] = ["pulse1", "pulse2", "triangle", "noise", "dmc"];
+
+/// Registers of every component at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DebugState {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub p: u8,
+    pub cycles: u64,
+    pub scanline: i16,
+    pub dot: u16,
+    pub ppu_ctrl: u8,
+    pub ppu_mask: u8,
+    pub ppu_status: u8,
+    /// Current VRAM address (loopy `v`)
+    pub v: u16,
+    /// Temporary VRAM address (loopy `t`)
+    pub t: u16,
+    /// Fine X scroll (loopy `x`)
+    pub fine_x: u8,
+    /// Write toggle (loopy `w`)
+    pub w: bool,
+    /// Mapper bank registers by name (empty without a cartridge or for NROM)
+    pub mapper: Vec<(String, u32)>,
+    /// Channel enable bits written to $4015 (pulse 1, pulse 2, triangle, noise, DMC)
+    pub apu_channels: u8,
+    /// Devices holding the IRQ line low
+    pub irq: Vec<IrqSource>,
+    /// Sprites per scanline for the current OAM
+    pub sprites: SpriteStats,
+}
+
+impl DebugState {
+    /// Take a snapshot of the system's registers
+    pub fn capture(system: &NesSystem) -> Self {
+        let cpu = system.cpu();
+        let regs = cpu.registers();
+        let ppu = system.ppu();
+        Self {
+            pc: regs.pc,
+            a: regs.a,
+            x: regs.x,
+            y: regs.y,
+            sp: regs.sp,
+            p: cpu.p_register(),
+            cycles: cpu.total_cycles(),
+            scanline: ppu.scanline(),
+            dot: ppu.dot(),
+            ppu_ctrl: ppu.control_value(),
+            ppu_mask: ppu.mask_value(),
+            ppu_status: ppu.status_value(),
+            v: ppu.video_address(),
+            t: ppu.temp_address(),
+            fine_x: ppu.fine_x(),
+            w: ppu.write_toggle(),
+            mapper: system.bus_cartridge().map(|cart| cart.mapper().registers()).unwrap_or_default(),
+            apu_channels: system.peek_memory(0x4015) & 0x1F,
+            irq: system.interrupts().pending(),
+            sprites: SpriteStats::from_lines(&ppu.evaluate_sprites()),
+        }
+    }
+
+    /// Encode as a JSON object with `cpu`, `ppu`, `mapper`, `apu`, `irq` and `sprites` members
+    ///
+    /// Values are plain numbers (booleans for flags) so UIs can format them
+    /// as they like. Mapper register names are identifiers and need no escaping.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            concat!(
+                r#"{{"cpu":{{"pc":{},"a":{},"x":{},"y":{},"sp":{},"p":{},"cycles":{}}},"#,
+                r#""ppu":{{"scanline":{},"dot":{},"ctrl":{},"mask":{},"status":{},"v":{},"t":{},"x":{},"w":{}}},"#,
+                r#""mapper":{{"#
+            ),
+            self.pc,
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            self.p,
+            self.cycles,
+            self.scanline,
+            self.dot,
+            self.ppu_ctrl,
+            self.ppu_mask,
+            self.ppu_status,
+            self.v,
+            self.t,
+            self.fine_x,
+            self.w,
+        );
+        for (i, (name, value)) in self.mapper.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let _ = write!(json, r#"{}"{}":{}"#, separator, name, value);
+        }
+        json.push_str(r#"},"apu":{"#);
+        for (bit, name) in CHANNELS.iter().enumerate() {
+            let separator = if bit == 0 { "" } else { "," };
+            let _ = write!(json, r#"{}"{}":{}"#, separator, name, self.apu_channels & (1 << bit) != 0);
+        }
+        let irq: Vec<String> = self.irq.iter().map(|source| format!(r#""{}""#, source.name())).collect();
+        let histogram: Vec<String> = self.sprites.histogram.iter().map(u16::to_string).collect();
+        let _ = write!(
+            json,
+            r#"}},"irq":[{}],"sprites":{{"max":{},"overflow_lines":{},"dropped":{},"histogram":[{}]}}}}"#,
+            irq.join(","),
+            self.sprites.max_per_line,
+            self.sprites.overflow_lines,
+            self.sprites.dropped,
+            histogram.join(","),
+        );
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+    use crate::mapper::MapperState;
+
+    #[test]
+    fn test_capture_to_json() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(
+            SimpleCartridge::new(vec![0xEA; 32768], vec![0x00; 8192]).with_mapper(MapperState::for_number(2)),
+        );
+        system.reset();
+        system.cpu_mut().registers_mut().a = 0x12;
+        system.write_memory(0x4015, 0x15);
+        system.ppu_mut().write(0x2005, 0x0D);
+
+        let state = DebugState::capture(&system);
+        assert_eq!((state.a, state.fine_x, state.w), (0x12, 5, true));
+        let json = state.to_json();
+        assert!(json.starts_with(r#"{"cpu":{"pc":"#), "{}", json);
+        assert!(json.contains(r#""a":18,"#), "{}", json);
+        assert!(json.contains(r#""x":5,"w":true}"#), "{}", json);
+        assert!(json.contains(r#""mapper":{"prg":0}"#), "{}", json);
+        assert!(
+            json.contains(r#""apu":{"pulse1":true,"pulse2":false,"triangle":true,"noise":false,"dmc":true},"irq":[],"#),
+            "{}",
+            json
+        );
+        // Power-on OAM is all zeros: 64 sprites on lines 1-8
+        assert!(json.contains(r#""sprites":{"max":64,"overflow_lines":8,"dropped":448,"histogram":[232,0,"#), "{}", json);
+        assert!(json.ends_with(",0,8]}}"), "{}", json);
+    }
+}
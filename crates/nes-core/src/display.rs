@@ -0,0 +1,84 @@
+//! Display sizing presets
+//!
+//! The PPU outputs 256x240 pixels, but NTSC televisions drew them with an
+//! 8:7 pixel aspect ratio and hid roughly 8 lines at the top and bottom
+//! behind the bezel. Frontends use these presets to size their output the
+//! same way instead of hard-coding dimensions.
+
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use std::ops::Range;
+
+/// Lines hidden at the top and at the bottom when overscan is cropped
+pub const OVERSCAN_LINES: usize = 8;
+/// NTSC pixel aspect ratio (width, height)
+pub const PIXEL_ASPECT: (usize, usize) = (8, 7);
+
+/// Width and height of a presented frame in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanvasSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl CanvasSize {
+    /// Native PPU output (256x240)
+    pub const fn native() -> Self {
+        Self { width: FRAME_WIDTH, height: FRAME_HEIGHT }
+    }
+
+    /// Frame size with or without the overscan lines
+    pub const fn visible(overscan: bool) -> Self {
+        if overscan {
+            Self::native()
+        } else {
+            Self { width: FRAME_WIDTH, height: FRAME_HEIGHT - 2 * OVERSCAN_LINES }
+        }
+    }
+
+    /// Same frame stretched horizontally to the pixel aspect ratio (rounded)
+    pub const fn aspect_corrected(self) -> Self {
+        let (num, den) = PIXEL_ASPECT;
+        Self { width: (self.width * num + den / 2) / den, height: self.height }
+    }
+}
+
+/// Framebuffer lines shown with or without overscan
+pub fn visible_lines(overscan: bool) -> Range<usize> {
+    if overscan {
+        0..FRAME_HEIGHT
+    } else {
+        OVERSCAN_LINES..FRAME_HEIGHT - OVERSCAN_LINES
+    }
+}
+
+/// Copy the visible lines of a 256x240 RGB framebuffer
+pub fn crop_frame(framebuffer: &[u8], overscan: bool) -> Vec<u8> {
+    let stride = FRAME_WIDTH * 3;
+    let lines = visible_lines(overscan);
+    framebuffer
+        .get(lines.start * stride..lines.end * stride)
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::FRAME_RGB_SIZE;
+
+    #[test]
+    fn test_presets_and_cropping() {
+        assert_eq!(CanvasSize::native(), CanvasSize { width: 256, height: 240 });
+        assert_eq!(CanvasSize::visible(false), CanvasSize { width: 256, height: 224 });
+        assert_eq!(CanvasSize::native().aspect_corrected(), CanvasSize { width: 293, height: 240 });
+        assert_eq!(CanvasSize::visible(false).aspect_corrected().height, 224);
+
+        let mut frame = vec![0; FRAME_RGB_SIZE];
+        frame[8 * FRAME_WIDTH * 3] = 0xAA;
+        let cropped = crop_frame(&frame, false);
+        assert_eq!(cropped.len(), 256 * 224 * 3);
+        assert_eq!(cropped[0], 0xAA);
+        assert_eq!(crop_frame(&frame, true), frame);
+        assert!(crop_frame(&frame[..100], false).is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+//! DMA units and how they interact with controller reads
+//!
+//! The 2A03 has two DMA units that halt the CPU while they use the bus:
+//!
+//! - OAM (sprite) DMA: a write of page `$XX` to $4014 copies $XX00-$XXFF
+//!   into OAM, halting the CPU for `OAM_DMA_CYCLES` cycles (one more when it
+//!   starts on an odd cycle). Controller reads are delayed by the halt, and a
+//!   copy from page $40 reads $4016/$4017 itself, clocking the controllers.
+//! - DMC DMA: while a delta modulation sample plays, the DMC's reader fetches
+//!   the next sample byte every 8 output bits, halting the CPU for 1 to
+//!   `DMC_FETCH_CYCLES` cycles. The reader halts the CPU, waits a dummy
+//!   cycle and, if needed, an alignment cycle so the fetch lands on an even
+//!   cycle. The CPU can't be halted while it writes, but the reader's own
+//!   cycles run alongside the writes, so a fetch that comes due during a
+//!   store, a read-modify-write or an interrupt's pushes halts it for less
+//!   (`dmc_fetch_stall`). Inside an OAM DMA a fetch usually adds
+//!   `DMC_FETCH_DURING_OAM_CYCLES` (`dmc_fetch_during_oam_stall`).
+//!
+//! A DMC fetch that lands on the cycle where the CPU reads $4016 or $4017
+//! makes the CPU read the port again, clocking the controller's shift
+//! register twice: the game sees the next button and one button is lost
+//! (the "DPCM controller glitch"). It is emulated when
+//! `AccuracyProfile::dmc_controller_glitch` is on. Games that play samples
+//! work around it in one of two ways, both of which hold up here:
+//!
+//! - read the controller repeatedly until two reads agree (the glitch can
+//!   hit at most one of two back-to-back reads of a sample period);
+//! - read it right after OAM DMA, which finishes far from the next fetch.
+//!
+//! The reader only tracks addresses and timing; the bus reads each fetched
+//! byte from PRG ROM and hands it to the APU's DMC output unit. When a
+//! sample that doesn't loop runs out with IRQs enabled ($4010 bit 7), it
+//! raises the DMC IRQ, which holds until $4015 is written.
+
+use crate::region::Region;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// CPU cycles an OAM DMA halts the CPU (plus one when it starts on an odd cycle)
+pub const OAM_DMA_CYCLES: u32 = 513;
+/// Most CPU cycles a DMC sample fetch halts the CPU
+pub const DMC_FETCH_CYCLES: u32 = 4;
+/// Extra cycles a DMC fetch usually adds to an OAM DMA it lands in
+pub const DMC_FETCH_DURING_OAM_CYCLES: u32 = 2;
+
+/// CPU cycles a DMC fetch coming due on CPU cycle `cycle` halts the CPU,
+/// given the CPU write cycles in a row starting there
+///
+/// The halt, dummy and alignment cycles take 3 cycles when the fetch falls
+/// on the next even cycle after them and 4 otherwise. Each write cycle
+/// overlaps one of them, down to the fetch itself.
+pub fn dmc_fetch_stall(cycle: u64, writes: u32) -> u32 {
+    let cycles: u32 = if cycle.is_multiple_of(2) { 3 } else { 4 };
+    cycles.saturating_sub(writes).max(1)
+}
+
+/// Extra CPU cycles a DMC fetch adds to an OAM DMA with `cycles_left` to go when it comes due
+///
+/// The OAM DMA pauses for the fetch and a realignment cycle. On its
+/// second-to-last cycle the fetch only takes 1, and on its last 3, as the
+/// reader has to halt the CPU itself.
+pub fn dmc_fetch_during_oam_stall(cycles_left: u32) -> u32 {
+    match cycles_left {
+        2 => 1,
+        1 => 3,
+        _ => DMC_FETCH_DURING_OAM_CYCLES,
+    }
+}
+
+/// CPU cycles per output bit for each $4010 rate index (NTSC)
+pub const DMC_RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+/// CPU cycles per output bit for each $4010 rate index (PAL)
+pub const DMC_RATES_PAL: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+/// Get the DMC rate table of a region (the Dendy uses NTSC's)
+pub fn dmc_rates(region: Region) -> &'static [u16; 16] {
+    match region {
+        Region::Pal => &DMC_RATES_PAL,
+        _ => &DMC_RATES,
+    }
+}
+
+/// DMC fetches during a run of CPU cycles
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DmcFetches {
+    /// Cycle of the run each sample byte was fetched on, from 0
+    pub cycles: Vec<u32>,
+    /// A fetch landed on the last cycle (where an instruction's operand read happens)
+    pub on_last_cycle: bool,
+}
+
+impl DmcFetches {
+    /// Number of sample bytes fetched
+    pub fn count(&self) -> u32 {
+        self.cycles.len() as u32
+    }
+}
+
+/// The DMC's sample reader: registers $4010-$4013, enable bit 4 of $4015
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmcDma {
+    /// Rate table of the console's region
+    rates: &'static [u16; 16],
+    /// CPU cycles per output bit
+    period: u16,
+    /// Restart the sample when it ends ($4010 bit 6)
+    looping: bool,
+    /// Sample start address ($4012)
+    sample_address: u16,
+    /// Sample length in bytes ($4013)
+    sample_length: u16,
+    /// Address of the next byte to fetch
+    current_address: u16,
+    /// Bytes left to fetch
+    bytes_remaining: u16,
+    /// The sample buffer holds a byte the output unit hasn't taken yet
+    buffer_full: bool,
+    /// Cycles until the output unit finishes the current bit
+    timer: u16,
+    /// Bits left in the current output cycle
+    bits_remaining: u8,
+    /// Raise an IRQ when a sample ends ($4010 bit 7)
+    irq_enabled: bool,
+    /// The IRQ is holding the line
+    irq_pending: bool,
+}
+
+impl DmcDma {
+    /// Create an idle reader
+    pub fn new() -> Self {
+        Self {
+            rates: &DMC_RATES,
+            period: DMC_RATES[0],
+            looping: false,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            buffer_full: false,
+            timer: DMC_RATES[0],
+            bits_remaining: 8,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    /// Handle a write to $4010-$4013 or $4015 (other addresses are ignored)
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4010 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.irq_pending &= self.irq_enabled;
+                self.looping = value & 0x40 != 0;
+                self.period = self.rates[(value & 0x0F) as usize];
+            }
+            0x4012 => self.sample_address = 0xC000 | (value as u16) << 6,
+            0x4013 => self.sample_length = ((value as u16) << 4) + 1,
+            0x4015 => {
+                self.irq_pending = false;
+                if value & 0x10 == 0 {
+                    self.bytes_remaining = 0;
+                } else if self.bytes_remaining == 0 {
+                    self.restart();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Switch to a region's rate table, keeping the selected rate index
+    pub fn set_region(&mut self, region: Region) {
+        let index = self.rates.iter().position(|&rate| rate == self.period).unwrap_or(0);
+        self.rates = dmc_rates(region);
+        self.period = self.rates[index];
+        self.timer = self.timer.min(self.period);
+    }
+
+    /// Check if a sample is still being fetched
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// Check if the DMC IRQ is holding the line
+    pub fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// Release the DMC IRQ, as a $4015 write does
+    pub fn acknowledge_irq(&mut self) {
+        self.irq_pending = false;
+    }
+
+    /// Get the number of sample bytes left to fetch
+    pub fn bytes_remaining(&self) -> u16 {
+        self.bytes_remaining
+    }
+
+    /// Run the reader for `cycles` CPU cycles, returning the fetches it made
+    pub fn clock(&mut self, cycles: u32) -> DmcFetches {
+        self.clock_with(cycles, |_| {})
+    }
+
+    /// Run the reader like `clock`, calling `on_fetch` with the address of each byte fetched
+    pub fn clock_with(&mut self, cycles: u32, mut on_fetch: impl FnMut(u16)) -> DmcFetches {
+        let mut fetches = DmcFetches::default();
+        if !self.is_active() {
+            return fetches;
+        }
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            if !self.buffer_full && self.bytes_remaining > 0 {
+                on_fetch(self.fetch());
+                fetches.cycles.push(elapsed);
+                fetches.on_last_cycle |= elapsed + 1 == cycles;
+            }
+            let left = cycles - elapsed;
+            if (self.timer as u32) > left {
+                self.timer -= left as u16;
+                break;
+            }
+            elapsed += self.timer as u32;
+            self.timer = self.period;
+            self.bits_remaining -= 1;
+            if self.bits_remaining == 0 {
+                // A new output cycle takes the buffered byte
+                self.bits_remaining = 8;
+                self.buffer_full = false;
+            }
+        }
+        fetches
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Fetch the next byte, returning its address
+    fn fetch(&mut self) -> u16 {
+        let address = self.current_address;
+        self.buffer_full = true;
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else {
+                self.irq_pending |= self.irq_enabled;
+            }
+        }
+        address
+    }
+
+    /// Serialize the reader
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.period);
+        writer.write_bool(self.looping);
+        writer.write_u16(self.sample_address);
+        writer.write_u16(self.sample_length);
+        writer.write_u16(self.current_address);
+        writer.write_u16(self.bytes_remaining);
+        writer.write_bool(self.buffer_full);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.bits_remaining);
+    }
+
+    /// Serialize the IRQ enable and flag (the part the v3 bus section added)
+    pub(crate) fn save_irq(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.irq_pending);
+    }
+
+    /// Restore state written by `save_irq`
+    pub(crate) fn load_irq(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enabled = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+        Ok(())
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let period = reader.read_u16()?;
+        if !self.rates.contains(&period) {
+            return Err(StateError::InvalidData("DMC rate"));
+        }
+        self.period = period;
+        self.looping = reader.read_bool()?;
+        self.sample_address = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+        self.current_address = reader.read_u16()?;
+        self.bytes_remaining = reader.read_u16()?;
+        self.buffer_full = reader.read_bool()?;
+        self.timer = reader.read_u16()?;
+        self.bits_remaining = reader.read_u8()?;
+        if self.timer == 0 || !(1..=8).contains(&self.bits_remaining) {
+            return Err(StateError::InvalidData("DMC timer"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for DmcDma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reader playing a 17-byte sample at the fastest rate
+    fn playing() -> DmcDma {
+        let mut dmc = DmcDma::new();
+        dmc.write(0x4010, 0x0F);
+        dmc.write(0x4013, 0x01);
+        dmc.write(0x4015, 0x10);
+        dmc
+    }
+
+    #[test]
+    fn test_fetch_every_eight_bits() {
+        let mut dmc = playing();
+        assert_eq!(dmc.bytes_remaining(), 17);
+        // The empty buffer is filled at once
+        assert_eq!(dmc.clock(1).count(), 1);
+        // The next fetch waits for the output unit to take that byte
+        while dmc.clock(1).count() == 0 {}
+        // From then on, one fetch per 8 bits of 54 cycles
+        assert_eq!(dmc.clock(8 * 54 - 1), DmcFetches { cycles: vec![], on_last_cycle: false });
+        assert_eq!(dmc.clock(1), DmcFetches { cycles: vec![0], on_last_cycle: true });
+        assert_eq!(dmc.bytes_remaining(), 14);
+        assert_eq!(dmc.clock(8 * 54 + 10).cycles, [8 * 54 - 1]);
+
+        assert_eq!(dmc.clock(100_000).count(), 13);
+        assert!(!dmc.is_active());
+        assert_eq!(dmc.clock(100_000).count(), 0);
+    }
+
+    #[test]
+    fn test_fetch_stall_lengths() {
+        // On a read cycle: 3 or 4 depending on alignment
+        assert_eq!(dmc_fetch_stall(100, 0), 3);
+        assert_eq!(dmc_fetch_stall(101, 0), 4);
+        // A store's write, the two of a read-modify-write, and the three pushes of BRK
+        assert_eq!(dmc_fetch_stall(100, 1), 2);
+        assert_eq!(dmc_fetch_stall(101, 1), 3);
+        assert_eq!(dmc_fetch_stall(101, 2), 2);
+        assert_eq!(dmc_fetch_stall(100, 2), 1);
+        assert_eq!(dmc_fetch_stall(100, 3), 1);
+        assert_eq!(dmc_fetch_stall(101, 3), 1);
+
+        assert_eq!(dmc_fetch_during_oam_stall(300), DMC_FETCH_DURING_OAM_CYCLES);
+        assert_eq!(dmc_fetch_during_oam_stall(2), 1);
+        assert_eq!(dmc_fetch_during_oam_stall(1), 3);
+    }
+
+    #[test]
+    fn test_looping_and_disable() {
+        let mut dmc = playing();
+        dmc.write(0x4010, 0x4F);
+        assert!(dmc.clock(100 * 8 * 54).count() > 17);
+        assert!(dmc.is_active());
+        dmc.write(0x4015, 0x00);
+        assert!(!dmc.is_active());
+    }
+}
@@ -0,0 +1,346 @@
+//! Serial EEPROMs used for game saves on Bandai boards
+//!
+//! The mapper drives the two-wire bus by writing SCL and SDA levels; the chip
+//! decodes start/stop conditions and shifts bits on SCL edges. Two variants exist:
+//! - 24C02 (256 bytes): I2C device address byte, word address, MSB-first data
+//! - X24C01 (128 bytes): no device address, 7-bit word address plus R/W bit, LSB-first
+//!
+//! `output` is the level the chip drives on SDA; the mapper returns it in bit 4
+//! of reads from $6000-$7FFF.
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// EEPROM chip variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EepromKind {
+    /// X24C01, 128 bytes (mapper 159)
+    C01,
+    /// 24C02, 256 bytes (mapper 16)
+    C02,
+}
+
+impl EepromKind {
+    /// Capacity in bytes
+    pub fn size(self) -> usize {
+        match self {
+            EepromKind::C01 => 128,
+            EepromKind::C02 => 256,
+        }
+    }
+}
+
+/// Bus state of the chip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Idle,
+    ChipAddress,
+    Address,
+    Read,
+    Write,
+    SendAck,
+    WaitAck,
+}
+
+/// Modes in the order of their savestate encoding
+const MODES: [Mode; 7] = [Mode::Idle, Mode::ChipAddress, Mode::Address, Mode::Read, Mode::Write, Mode::SendAck, Mode::WaitAck];
+
+/// Decode a mode saved as its index in `MODES`
+fn read_mode(reader: &mut StateReader) -> Result<Mode, StateError> {
+    MODES.get(reader.read_u8()? as usize).copied().ok_or(StateError::InvalidData("EEPROM mode"))
+}
+
+/// Serial EEPROM attached to a two-wire bus
+#[derive(Debug, Clone)]
+pub struct Eeprom {
+    kind: EepromKind,
+    data: Vec<u8>,
+    mode: Mode,
+    /// Mode entered after the current acknowledge
+    next_mode: Mode,
+    chip_address: u8,
+    address: u8,
+    /// Byte being shifted in or out
+    shift: u8,
+    /// Bits shifted in the current byte
+    counter: u8,
+    output: bool,
+    prev_scl: bool,
+    prev_sda: bool,
+}
+
+impl Eeprom {
+    /// Create a blank (erased, all $FF) chip
+    pub fn new(kind: EepromKind) -> Self {
+        Self {
+            kind,
+            data: vec![0xFF; kind.size()],
+            mode: Mode::Idle,
+            next_mode: Mode::Idle,
+            chip_address: 0,
+            address: 0,
+            shift: 0,
+            counter: 0,
+            output: true,
+            prev_scl: false,
+            prev_sda: false,
+        }
+    }
+
+    /// Get the stored bytes (for writing a save file)
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Restore stored bytes from a save file (extra bytes are ignored)
+    pub fn load_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// Serialize the stored bytes and the bus state
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        writer.write_u8(self.mode as u8);
+        writer.write_u8(self.next_mode as u8);
+        writer.write_bytes(&[self.chip_address, self.address, self.shift, self.counter]);
+        writer.write_bool(self.output);
+        writer.write_bool(self.prev_scl);
+        writer.write_bool(self.prev_sda);
+    }
+
+    /// Restore state written by `save_state` (from a chip of the same kind)
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.data)?;
+        self.mode = read_mode(reader)?;
+        self.next_mode = read_mode(reader)?;
+        self.chip_address = reader.read_u8()?;
+        self.address = reader.read_u8()?;
+        self.shift = reader.read_u8()?;
+        self.counter = reader.read_u8()?;
+        self.output = reader.read_bool()?;
+        self.prev_scl = reader.read_bool()?;
+        self.prev_sda = reader.read_bool()?;
+        Ok(())
+    }
+
+    /// Level the chip drives on SDA
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    /// Update the SCL and SDA levels driven by the mapper
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.prev_scl && scl && sda != self.prev_sda {
+            if !sda {
+                // Start condition: SDA falls while SCL is high
+                self.mode = match self.kind {
+                    EepromKind::C01 => Mode::Address,
+                    EepromKind::C02 => Mode::ChipAddress,
+                };
+                self.counter = 0;
+            } else {
+                // Stop condition: SDA rises while SCL is high
+                self.mode = Mode::Idle;
+            }
+            self.output = true;
+        } else if scl && !self.prev_scl {
+            self.rising_edge(sda);
+        } else if !scl && self.prev_scl {
+            self.falling_edge();
+        }
+        self.prev_scl = scl;
+        self.prev_sda = sda;
+    }
+
+    fn mask(&self) -> u8 {
+        (self.kind.size() - 1) as u8
+    }
+
+    /// Bit position within a byte for the current transfer order
+    fn bit(&self) -> u8 {
+        match self.kind {
+            EepromKind::C01 => self.counter,
+            EepromKind::C02 => 7 - self.counter,
+        }
+    }
+
+    fn shift_in(&mut self, target: u8, sda: bool) -> u8 {
+        let bit = self.bit();
+        self.counter += 1;
+        (target & !(1 << bit)) | ((sda as u8) << bit)
+    }
+
+    fn rising_edge(&mut self, sda: bool) {
+        match self.mode {
+            Mode::ChipAddress if self.counter < 8 => self.chip_address = self.shift_in(self.chip_address, sda),
+            Mode::Address if self.kind == EepromKind::C01 && self.counter == 7 => {
+                // X24C01: eighth bit selects read (1) or write (0)
+                self.counter = 8;
+                self.begin_transfer(sda);
+            }
+            Mode::Address if self.counter < 8 => self.address = self.shift_in(self.address, sda),
+            Mode::Write if self.counter < 8 => self.shift = self.shift_in(self.shift, sda),
+            Mode::Read if self.counter < 8 => {
+                self.output = self.shift & (1 << self.bit()) != 0;
+                self.counter += 1;
+            }
+            Mode::SendAck => self.output = false,
+            Mode::WaitAck => {
+                // The host acknowledges (SDA low) to continue a sequential read
+                self.next_mode = if sda { Mode::Idle } else { Mode::Read };
+                self.shift = self.data[self.address as usize];
+            }
+            _ => {}
+        }
+    }
+
+    fn falling_edge(&mut self) {
+        if self.counter < 8 && matches!(self.mode, Mode::ChipAddress | Mode::Address | Mode::Read | Mode::Write) {
+            return;
+        }
+        match self.mode {
+            Mode::ChipAddress => {
+                if self.chip_address & 0xF0 == 0xA0 {
+                    self.begin_transfer(self.chip_address & 1 != 0);
+                    self.mode = Mode::SendAck;
+                } else {
+                    self.mode = Mode::Idle;
+                }
+                self.output = true;
+            }
+            Mode::Address => {
+                self.mode = Mode::SendAck;
+                if self.kind == EepromKind::C02 {
+                    self.next_mode = Mode::Write;
+                }
+                self.output = true;
+            }
+            Mode::Read => {
+                self.mode = Mode::WaitAck;
+                self.address = self.address.wrapping_add(1) & self.mask();
+            }
+            Mode::Write => {
+                let index = (self.address & self.mask()) as usize;
+                self.data[index] = self.shift;
+                self.address = self.address.wrapping_add(1) & self.mask();
+                self.mode = Mode::SendAck;
+                self.next_mode = Mode::Write;
+                self.output = true;
+            }
+            Mode::SendAck | Mode::WaitAck => {
+                self.mode = self.next_mode;
+                self.counter = 0;
+                self.output = true;
+            }
+            Mode::Idle => {}
+        }
+    }
+
+    /// Choose the mode after the address phase: read the current address or accept data
+    fn begin_transfer(&mut self, read: bool) {
+        self.address &= self.mask();
+        if read {
+            self.next_mode = Mode::Read;
+            self.shift = self.data[self.address as usize];
+        } else {
+            self.next_mode = if self.kind == EepromKind::C01 { Mode::Write } else { Mode::Address };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal bus master for driving the chip in tests
+    struct Host<'a>(&'a mut Eeprom);
+
+    impl Host<'_> {
+        fn start(&mut self) {
+            self.0.write(false, true);
+            self.0.write(true, true);
+            self.0.write(true, false);
+            self.0.write(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.0.write(false, false);
+            self.0.write(true, false);
+            self.0.write(true, true);
+        }
+
+        fn clock(&mut self, sda: bool) -> bool {
+            self.0.write(false, sda);
+            self.0.write(true, sda);
+            let out = self.0.output();
+            self.0.write(false, sda);
+            out
+        }
+
+        fn send(&mut self, byte: u8, lsb_first: bool) -> bool {
+            for i in 0..8 {
+                let bit = if lsb_first { i } else { 7 - i };
+                self.clock(byte & (1 << bit) != 0);
+            }
+            // Acknowledge is the chip pulling SDA low
+            !self.clock(true)
+        }
+
+        fn receive(&mut self, lsb_first: bool, ack: bool) -> u8 {
+            let mut byte = 0;
+            for i in 0..8 {
+                let bit = if lsb_first { i } else { 7 - i };
+                byte |= (self.clock(true) as u8) << bit;
+            }
+            self.clock(!ack);
+            byte
+        }
+    }
+
+    #[test]
+    fn test_24c02_write_then_sequential_read() {
+        let mut chip = Eeprom::new(EepromKind::C02);
+        let mut host = Host(&mut chip);
+        host.start();
+        assert!(host.send(0xA0, false));
+        assert!(host.send(0x10, false));
+        assert!(host.send(0x12, false));
+        assert!(host.send(0x34, false));
+        host.stop();
+        assert_eq!(&chip.data()[0x10..0x12], &[0x12, 0x34]);
+
+        let mut host = Host(&mut chip);
+        host.start();
+        host.send(0xA0, false);
+        host.send(0x10, false);
+        host.start();
+        assert!(host.send(0xA1, false));
+        assert_eq!(host.receive(false, true), 0x12);
+        assert_eq!(host.receive(false, false), 0x34);
+        host.stop();
+    }
+
+    #[test]
+    fn test_24c01_lsb_first_protocol() {
+        let mut chip = Eeprom::new(EepromKind::C01);
+        let mut host = Host(&mut chip);
+        host.start();
+        // Address 5, write
+        assert!(host.send(0x05, true));
+        assert!(host.send(0xC3, true));
+        host.stop();
+        assert_eq!(chip.data()[5], 0xC3);
+
+        let mut host = Host(&mut chip);
+        host.start();
+        assert!(host.send(0x85, true));
+        assert_eq!(host.receive(true, false), 0xC3);
+        host.stop();
+
+        // Other devices on the bus are ignored by the 24C02
+        let mut chip = Eeprom::new(EepromKind::C02);
+        let mut host = Host(&mut chip);
+        host.start();
+        assert!(!host.send(0x50, false));
+    }
+}
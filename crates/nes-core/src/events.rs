@@ -0,0 +1,212 @@
+//! Polled queue of system events for frontends
+//!
+//! Frontends that can't take callbacks from the core (the WASM wrapper hands
+//! everything across the JS boundary as values) queue events here and drain
+//! them once per host frame. `EventQueue::observe` turns what the system did
+//! since the last call into events (a finished frame, a CPU jam, unlocked
+//! achievements); the frontend pushes the ones only it knows about (states
+//! saved or loaded, OSD messages). `to_json` encodes a drained batch as a
+//! JSON array of objects tagged with `type`:
+//!
+//! ```text
+//! [{"type":"frame_complete","frame":61},{"type":"message","text":"Reset"}]
+//! ```
+
+use crate::system::NesSystem;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Maximum number of queued events (the oldest are dropped first)
+pub const MAX_QUEUED: usize = 1024;
+
+/// Something that happened in the emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A frame finished; `frame` is the frame count after it
+    FrameComplete { frame: u64 },
+    /// The CPU hit a jamming opcode and stops until reset
+    CpuJammed { pc: u16, opcode: u8 },
+    /// A savestate was written
+    StateSaved,
+    /// A savestate was restored
+    StateLoaded,
+    /// An achievement from the loaded pack unlocked
+    AchievementUnlocked { title: String },
+    /// An on-screen display message was posted
+    Message { text: String },
+}
+
+impl Event {
+    /// Name used in the `type` member of the JSON encoding
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::FrameComplete { .. } => "frame_complete",
+            Event::CpuJammed { .. } => "cpu_jammed",
+            Event::StateSaved => "state_saved",
+            Event::StateLoaded => "state_loaded",
+            Event::AchievementUnlocked { .. } => "achievement_unlocked",
+            Event::Message { .. } => "message",
+        }
+    }
+
+    /// Encode as a JSON object
+    pub fn to_json(&self) -> String {
+        let mut json = format!(r#"{{"type":"{}""#, self.name());
+        let _ = match self {
+            Event::FrameComplete { frame } => write!(json, r#","frame":{}"#, frame),
+            Event::CpuJammed { pc, opcode } => write!(json, r#","pc":{},"opcode":{}"#, pc, opcode),
+            Event::StateSaved | Event::StateLoaded => Ok(()),
+            Event::AchievementUnlocked { title } => write!(json, r#","title":{}"#, json_string(title)),
+            Event::Message { text } => write!(json, r#","text":{}"#, json_string(text)),
+        };
+        json.push('}');
+        json
+    }
+}
+
+/// Encode a batch of events as a JSON array
+pub fn to_json(events: &[Event]) -> String {
+    let items: Vec<String> = events.iter().map(Event::to_json).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Quote and escape a string for JSON
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Events waiting for the frontend
+#[derive(Debug, Clone, Default)]
+pub struct EventQueue {
+    events: VecDeque<Event>,
+    /// Frame count at the last `observe`
+    last_frame: u64,
+    /// The jam was already reported
+    jammed: bool,
+}
+
+impl EventQueue {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue an event
+    pub fn push(&mut self, event: Event) {
+        if self.events.len() == MAX_QUEUED {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Queue events for what the system did since the last call
+    ///
+    /// Call it after running each frame. It takes the system's unlocked
+    /// achievements, so don't also use `NesSystem::take_unlocked_achievements`.
+    pub fn observe(&mut self, system: &mut NesSystem) {
+        let frame = system.frame_count();
+        if frame != self.last_frame {
+            self.last_frame = frame;
+            // A reset or loaded state moves the count back; that's not a finished frame
+            if frame > 0 {
+                self.push(Event::FrameComplete { frame });
+            }
+        }
+        for title in system.take_unlocked_achievements() {
+            self.push(Event::AchievementUnlocked { title });
+        }
+        match system.health().cpu_jammed {
+            Some((pc, opcode)) if !self.jammed => {
+                self.jammed = true;
+                self.push(Event::CpuJammed { pc, opcode });
+            }
+            Some(_) => {}
+            None => self.jammed = false,
+        }
+    }
+
+    /// Number of queued events
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Check if no events are queued
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Take all queued events, oldest first
+    pub fn drain(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+
+    #[test]
+    fn test_observe_frames_and_jam() {
+        let mut system = NesSystem::new();
+        // JMP $8000
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        let mut queue = EventQueue::new();
+        queue.observe(&mut system);
+        assert!(queue.is_empty());
+
+        system.run_frames(2).unwrap();
+        queue.observe(&mut system);
+        assert_eq!(queue.drain(), [Event::FrameComplete { frame: 2 }]);
+
+        // A KIL opcode is reported once, until a reset clears it
+        system.write_memory(0x0000, 0x02);
+        system.cpu_mut().registers_mut().pc = 0x0000;
+        assert!(system.run_frames(1).is_err());
+        queue.observe(&mut system);
+        queue.observe(&mut system);
+        assert_eq!(queue.drain(), [Event::CpuJammed { pc: 0x0000, opcode: 0x02 }]);
+
+        system.reset();
+        queue.observe(&mut system);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_json_encoding() {
+        let mut queue = EventQueue::new();
+        queue.push(Event::FrameComplete { frame: 3 });
+        queue.push(Event::StateSaved);
+        queue.push(Event::Message { text: "Say \"hi\"\n".into() });
+        assert_eq!(queue.len(), 3);
+        assert_eq!(
+            to_json(&queue.drain()),
+            r#"[{"type":"frame_complete","frame":3},{"type":"state_saved"},{"type":"message","text":"Say \"hi\"\n"}]"#
+        );
+        assert_eq!(to_json(&[]), "[]");
+
+        for frame in 0..MAX_QUEUED as u64 + 1 {
+            queue.push(Event::FrameComplete { frame });
+        }
+        assert_eq!(queue.drain()[0], Event::FrameComplete { frame: 1 });
+    }
+}
@@ -0,0 +1,322 @@
+//! Fast boot: skip a game's intro by restoring a cached title screen state
+//!
+//! On the first clean boot of a ROM (no buttons pressed), a
+//! `TitleScreenDetector` watches the frames until the game looks like it is
+//! waiting on its title screen: rendering is on and the game has read the
+//! controller every frame for `TITLE_POLL_FRAMES` frames. The frontend then
+//! stores a savestate in the `FastBootCache`, and later launches of the same
+//! ROM restore it instead of booting from reset.
+//!
+//! Cache files are named after the ROM's CRC32 and a fingerprint of the
+//! emulator version, the savestate format and the settings that shape the
+//! boot (region, accuracy profile, power-on RAM and D-pad policy). Changing any of those looks
+//! for a different file, so a stale state is never restored; storing a new
+//! state deletes the other states cached for the same ROM.
+//!
+//! Boards with battery-backed saves are skipped: a cached state would bring
+//! back the save data it was taken with.
+
+use crate::accuracy::PpuAlignment;
+use crate::bus::RamInit;
+use crate::controller::DpadPolicy;
+use crate::state::{fnv1a_update, StateError, FNV_OFFSET_BASIS, STATE_VERSION};
+use crate::system::NesSystem;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Consecutive frames of controller polling with rendering on that count as the title screen
+pub const TITLE_POLL_FRAMES: u64 = 30;
+/// Frames after which the detector stops looking (a minute at 60Hz)
+pub const TITLE_TIMEOUT_FRAMES: u64 = 60 * 60;
+/// Extension of cached state files
+pub const FAST_BOOT_EXTENSION: &str = "state";
+
+/// Error reading or writing the fast boot cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FastBootError {
+    /// No ROM is loaded, so there is nothing to key the cache on
+    NoRom,
+    /// The cached state could not be loaded (the file is removed)
+    State(StateError),
+    /// The cache could not be read or written
+    Io(String),
+}
+
+impl fmt::Display for FastBootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FastBootError::NoRom => write!(f, "No ROM loaded"),
+            FastBootError::State(e) => write!(f, "Cached boot state is unusable: {}", e),
+            FastBootError::Io(e) => write!(f, "Fast boot cache error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FastBootError {}
+
+impl From<io::Error> for FastBootError {
+    fn from(e: io::Error) -> Self {
+        FastBootError::Io(e.to_string())
+    }
+}
+
+/// Fingerprint of the emulator version and the settings a boot state depends on
+pub fn settings_fingerprint(system: &NesSystem) -> u64 {
+    let accuracy = system.accuracy();
+    let alignment = match accuracy.ppu_alignment {
+        PpuAlignment::Fixed(offset) => offset,
+        PpuAlignment::Random => 0xFF,
+    };
+    let ram_init = match system.effective_ram_init() {
+        RamInit::Zeros => [0, 0],
+        RamInit::Fill(value) => [1, value],
+        RamInit::Alternating => [2, 0],
+        RamInit::Random => [3, 0],
+    };
+    let dpad = match system.dpad_policy() {
+        DpadPolicy::Allow => 0,
+        DpadPolicy::Block => 1,
+        DpadPolicy::LastPressed => 2,
+    };
+    let mut hash = fnv1a_update(FNV_OFFSET_BASIS, env!("CARGO_PKG_VERSION").as_bytes());
+    hash = fnv1a_update(hash, &STATE_VERSION.to_le_bytes());
+    hash = fnv1a_update(hash, system.region().name().as_bytes());
+    hash = fnv1a_update(
+        hash,
+        &[accuracy.ppu_warmup as u8, alignment, accuracy.dmc_controller_glitch as u8, accuracy.sprite_overflow_bug as u8],
+    );
+    hash = fnv1a_update(hash, &ram_init);
+    fnv1a_update(hash, &[dpad])
+}
+
+/// Check if fast boot can be used with the loaded ROM
+pub fn supported(system: &NesSystem) -> bool {
+    system.rom_crc32().is_some() && system.battery_data().is_none()
+}
+
+/// Directory of cached boot states, one file per ROM and settings fingerprint
+#[derive(Debug, Clone)]
+pub struct FastBootCache {
+    dir: PathBuf,
+}
+
+impl FastBootCache {
+    /// Use the given cache directory (created when the first state is stored)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Default location: `<cache dir>/rustnes/fastboot`
+    ///
+    /// The cache dir is `$XDG_CACHE_HOME`, `~/.cache` or `%LOCALAPPDATA%`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let env_dir = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+        let cache = env_dir("XDG_CACHE_HOME")
+            .or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+            .or_else(|| env_dir("LOCALAPPDATA"))?;
+        Some(cache.join("rustnes").join("fastboot"))
+    }
+
+    /// Get the cache directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cache file for the loaded ROM with the current settings
+    pub fn path_for(&self, system: &NesSystem) -> Option<PathBuf> {
+        let crc = system.rom_crc32()?;
+        let name = format!("{:08x}-{:016x}.{}", crc, settings_fingerprint(system), FAST_BOOT_EXTENSION);
+        Some(self.dir.join(name))
+    }
+
+    /// Restore the cached boot state; false if there is none for this ROM and these settings
+    ///
+    /// A cached state that fails to load is deleted.
+    pub fn restore(&self, system: &mut NesSystem) -> Result<bool, FastBootError> {
+        let path = self.path_for(system).ok_or(FastBootError::NoRom)?;
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if let Err(e) = system.load_state(&data) {
+            let _ = fs::remove_file(&path);
+            return Err(FastBootError::State(e));
+        }
+        Ok(true)
+    }
+
+    /// Cache the current state as the boot state, replacing older states for the same ROM
+    pub fn store(&self, system: &NesSystem) -> Result<PathBuf, FastBootError> {
+        let path = self.path_for(system).ok_or(FastBootError::NoRom)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, system.save_state())?;
+        let prefix = format!("{:08x}-", system.rom_crc32().unwrap_or_default());
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            let stale = entry.path();
+            let name = entry.file_name();
+            if stale != path && name.to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(stale);
+            }
+        }
+        Ok(path)
+    }
+}
+
+/// What the title screen detector has seen so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleProgress {
+    /// Still booting
+    Booting,
+    /// The game is waiting for input on its title screen; store the state now
+    Reached,
+    /// The boot wasn't clean (buttons were pressed) or took too long; don't cache it
+    GaveUp,
+}
+
+/// Watches a boot from reset for the title screen
+#[derive(Debug, Clone, Default)]
+pub struct TitleScreenDetector {
+    /// Frame count at the last observation
+    last_frame: u64,
+    /// Frames in a row that polled the controller with rendering on
+    polling_frames: u64,
+}
+
+impl TitleScreenDetector {
+    /// Start watching (from reset)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the system after running one or more frames
+    ///
+    /// Frames run in one batch are judged together by the last of them, so
+    /// call this after every frame where possible. A reset starts over.
+    pub fn observe(&mut self, system: &NesSystem) -> TitleProgress {
+        let frame = system.frame_count();
+        if frame < self.last_frame {
+            *self = Self::new();
+        }
+        let elapsed = frame - self.last_frame;
+        self.last_frame = frame;
+
+        if system.buttons(0).bits() != 0 || system.buttons(1).bits() != 0 {
+            return TitleProgress::GaveUp;
+        }
+        let mask = system.ppu().mask();
+        if system.input_polls_last_frame() > 0 && (mask.render_background() || mask.render_sprites()) {
+            self.polling_frames += elapsed;
+        } else {
+            self.polling_frames = 0;
+        }
+        if self.polling_frames >= TITLE_POLL_FRAMES {
+            TitleProgress::Reached
+        } else if frame >= TITLE_TIMEOUT_FRAMES {
+            TitleProgress::GaveUp
+        } else {
+            TitleProgress::Booting
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accuracy::AccuracyProfile;
+    use crate::controller::Buttons;
+
+    /// iNES image that turns rendering on, then polls $4016 in a loop
+    fn title_rom() -> Vec<u8> {
+        let mut rom = b"NES\x1A\x01\x01".to_vec();
+        rom.resize(16, 0);
+        let mut prg = vec![0xEA; 16384];
+        // $8000: LDA #$08; STA $2001; loop: LDA $4016; JMP loop
+        prg[..11].copy_from_slice(&[0xA9, 0x08, 0x8D, 0x01, 0x20, 0xAD, 0x16, 0x40, 0x4C, 0x05, 0x80]);
+        rom.extend(prg);
+        rom.extend(vec![0; 8192]);
+        rom
+    }
+
+    fn booted_system() -> NesSystem {
+        let mut system = NesSystem::new();
+        system.load_rom(&title_rom()).unwrap();
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system
+    }
+
+    fn temp_cache(name: &str) -> FastBootCache {
+        let dir = std::env::temp_dir().join(format!("nes-core-fastboot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        FastBootCache::new(dir)
+    }
+
+    #[test]
+    fn test_fingerprint_covers_boot_settings() {
+        use crate::region::Region;
+
+        let base = settings_fingerprint(&booted_system());
+        let changed: [fn(&mut NesSystem); 4] = [
+            |s| s.set_region(Region::Pal),
+            |s| s.set_accuracy(AccuracyProfile { dmc_controller_glitch: true, ..s.accuracy() }),
+            |s| s.set_accuracy(AccuracyProfile { sprite_overflow_bug: true, ..s.accuracy() }),
+            |s| s.set_dpad_policy(DpadPolicy::Block),
+        ];
+        for change in changed {
+            let mut system = booted_system();
+            change(&mut system);
+            assert_ne!(settings_fingerprint(&system), base);
+        }
+    }
+
+    #[test]
+    fn test_detector_waits_for_polling_frames() {
+        let mut system = booted_system();
+        let mut detector = TitleScreenDetector::new();
+        for _ in 0..TITLE_POLL_FRAMES - 1 {
+            system.run_frames(1).unwrap();
+            assert_eq!(detector.observe(&system), TitleProgress::Booting);
+        }
+        system.run_frames(1).unwrap();
+        assert_eq!(detector.observe(&system), TitleProgress::Reached);
+
+        // Pressing a button makes the boot unclean
+        let mut system = booted_system();
+        let mut detector = TitleScreenDetector::new();
+        system.set_buttons(0, Buttons::new(Buttons::START));
+        system.run_frames(1).unwrap();
+        assert_eq!(detector.observe(&system), TitleProgress::GaveUp);
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_invalidation() {
+        let cache = temp_cache("round-trip");
+        let mut system = booted_system();
+        assert_eq!(cache.restore(&mut system), Ok(false));
+        system.run_frames(TITLE_POLL_FRAMES).unwrap();
+        let path = cache.store(&system).unwrap();
+        let hash = system.state_hash();
+
+        let mut next = booted_system();
+        assert_eq!(cache.restore(&mut next), Ok(true));
+        assert_eq!(next.state_hash(), hash);
+
+        // Different settings look for a different file; storing replaces the old one
+        next.set_accuracy(AccuracyProfile::accurate());
+        assert_ne!(cache.path_for(&next), Some(path.clone()));
+        assert_eq!(cache.restore(&mut next), Ok(false));
+        cache.store(&next).unwrap();
+        assert!(!path.exists());
+
+        // A corrupt state is reported and removed
+        let current = cache.path_for(&next).unwrap();
+        fs::write(&current, b"junk").unwrap();
+        assert_eq!(cache.restore(&mut next), Err(FastBootError::State(StateError::NotAState)));
+        assert!(!current.exists());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
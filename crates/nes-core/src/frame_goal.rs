@@ -0,0 +1,173 @@
+//! Framebuffer goals for automated playtesting
+//!
+//! `NesSystem::run_until_goal` renders every frame and hands the 256x240 RGB
+//! framebuffer to a `FrameGoal`, stopping once the goal reports it was
+//! reached. A goal can be any closure, or a user-supplied recognizer (OCR,
+//! a perceptual hash) behind the trait. `TemplateMatch` is a simple built-in
+//! one: it compares a rectangle of the frame with reference pixels, such as
+//! a title logo captured once from a known-good run.
+//!
+//! ```no_run
+//! use nes_core::frame_goal::{Region, TemplateMatch};
+//! use nes_core::NesSystem;
+//!
+//! let logo = std::fs::read("tests/title_logo.rgb")?;
+//! let mut title = TemplateMatch::new(Region { x: 64, y: 48, width: 128, height: 32 }, logo)
+//!     .expect("logo size matches the region")
+//!     .with_tolerance(8);
+//! let mut system = NesSystem::new();
+//! system.load_rom(&std::fs::read("homebrew.nes")?)?;
+//! system.reset();
+//! let frames = system.run_until_goal(600, &mut title)?;
+//! assert!(frames.is_some(), "title screen never appeared");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+/// Something to look for in the rendered frames
+pub trait FrameGoal {
+    /// Check a finished frame (256x240 RGB) after `frame` frames have run
+    fn reached(&mut self, framebuffer: &[u8], frame: u64) -> bool;
+}
+
+impl<F: FnMut(&[u8], u64) -> bool> FrameGoal for F {
+    fn reached(&mut self, framebuffer: &[u8], frame: u64) -> bool {
+        self(framebuffer, frame)
+    }
+}
+
+/// Rectangle of the frame in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    /// Check if the region lies inside the 256x240 frame
+    pub fn fits(&self) -> bool {
+        self.width > 0 && self.height > 0 && self.x + self.width <= FRAME_WIDTH && self.y + self.height <= FRAME_HEIGHT
+    }
+
+    /// RGB bytes of one region row in a framebuffer
+    fn row<'a>(&self, framebuffer: &'a [u8], row: usize) -> &'a [u8] {
+        let start = ((self.y + row) * FRAME_WIDTH + self.x) * 3;
+        &framebuffer[start..start + self.width * 3]
+    }
+}
+
+/// Reference pixels expected in a region of the frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateMatch {
+    region: Region,
+    /// Region pixels, RGB row by row
+    template: Vec<u8>,
+    /// Largest per-channel difference for a pixel to count as matching
+    tolerance: u8,
+    /// Smallest share of matching pixels (0.0-1.0) for the goal to be reached
+    min_score: f64,
+}
+
+impl TemplateMatch {
+    /// Match `template` (RGB, `width * height * 3` bytes) against `region`
+    ///
+    /// Returns None if the region doesn't fit the frame or the template has
+    /// the wrong size. Pixels must match exactly until `with_tolerance` and
+    /// `with_min_score` relax it.
+    pub fn new(region: Region, template: Vec<u8>) -> Option<Self> {
+        (region.fits() && template.len() == region.width * region.height * 3)
+            .then_some(Self { region, template, tolerance: 0, min_score: 1.0 })
+    }
+
+    /// Take the template from a region of a reference framebuffer
+    pub fn capture(framebuffer: &[u8], region: Region) -> Option<Self> {
+        if !region.fits() || framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+            return None;
+        }
+        let template = (0..region.height).flat_map(|row| region.row(framebuffer, row).iter().copied()).collect();
+        Self::new(region, template)
+    }
+
+    /// Allow each color channel to differ by up to `tolerance`
+    pub fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Accept a match once this share of pixels (0.0-1.0) matches, for regions
+    /// with a blinking cursor or animated detail
+    pub fn with_min_score(mut self, min_score: f64) -> Self {
+        self.min_score = min_score.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Get the compared region
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Share of region pixels (0.0-1.0) that match the template
+    pub fn score(&self, framebuffer: &[u8]) -> f64 {
+        if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+            return 0.0;
+        }
+        let row_bytes = self.region.width * 3;
+        let matching: usize = (0..self.region.height)
+            .map(|row| {
+                let expected = self.template[row * row_bytes..(row + 1) * row_bytes].chunks_exact(3);
+                let actual = self.region.row(framebuffer, row).chunks_exact(3);
+                expected
+                    .zip(actual)
+                    .filter(|(want, got)| want.iter().zip(got.iter()).all(|(&a, &b)| a.abs_diff(b) <= self.tolerance))
+                    .count()
+            })
+            .sum();
+        matching as f64 / (self.region.width * self.region.height) as f64
+    }
+
+    /// Check if the frame shows the template
+    pub fn matches(&self, framebuffer: &[u8]) -> bool {
+        self.score(framebuffer) >= self.min_score
+    }
+}
+
+impl FrameGoal for TemplateMatch {
+    fn reached(&mut self, framebuffer: &[u8], _frame: u64) -> bool {
+        self.matches(framebuffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::FRAME_RGB_SIZE;
+
+    #[test]
+    fn test_template_match() {
+        let mut frame = vec![0u8; FRAME_RGB_SIZE];
+        let region = Region { x: 10, y: 20, width: 4, height: 2 };
+        // A white bar in the region's first row
+        let start = (20 * FRAME_WIDTH + 10) * 3;
+        frame[start..start + 12].fill(0xFF);
+        let template = TemplateMatch::capture(&frame, region).unwrap();
+        assert!(template.matches(&frame));
+        assert!(!template.matches(&vec![0; FRAME_RGB_SIZE]));
+        assert_eq!(template.score(&vec![0; FRAME_RGB_SIZE]), 0.5);
+
+        // Near colors pass with a tolerance, and a partial match with a lower score
+        let mut dimmer = frame.clone();
+        dimmer[start..start + 12].fill(0xF8);
+        assert!(!template.matches(&dimmer));
+        let tolerant = template.clone().with_tolerance(8);
+        assert!(tolerant.matches(&dimmer));
+        dimmer[start..start + 3].fill(0);
+        assert!(!tolerant.matches(&dimmer));
+        assert!(tolerant.with_min_score(0.8).matches(&dimmer));
+
+        assert!(TemplateMatch::new(Region { x: 250, y: 0, width: 8, height: 1 }, vec![0; 24]).is_none());
+        assert!(TemplateMatch::new(region, vec![0; 3]).is_none());
+    }
+}
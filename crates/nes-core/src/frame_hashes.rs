@@ -0,0 +1,336 @@
+//! Frame hash sidecars for recordings
+//!
+//! A GIF or PNG sequence shows what a run looked like but can't prove it. A
+//! `FrameHashes` sidecar, written next to the recording, turns it into a
+//! reproducible artifact: it records how the system was started (ROM CRC,
+//! RNG seed, PPU alignment, accuracy profile and the state hash before the
+//! first frame) and, for every emulated frame, the controller input and the
+//! state hash afterwards. `replay` feeds the same input to a freshly started
+//! system, checks every hash, and hands each frame to a callback so the run
+//! can be rendered again, for example at a larger scale.
+//!
+//! The format is plain text: `key value` header lines, then `frames` and one
+//! `<frame> <port 1> <port 2> <hash>` line per frame (hex, frame numbers as
+//! counted by `NesSystem::frame_count`).
+//!
+//! ```text
+//! # nes-core frame hashes
+//! version 1
+//! rom 1a2b3c4d
+//! seed 42
+//! ppu_alignment 0
+//! accuracy compatible
+//! start_frame 0
+//! start_hash 8c5f0e1d9a2b3c4d
+//! frames
+//! 1 00 00 0f1e2d3c4b5a6978
+//! 2 08 00 77665544332211ff
+//! ```
+//!
+//! Only standard controller input is recorded; runs driven by the Zapper or
+//! the Arkanoid paddle will report a mismatch on replay.
+
+use crate::controller::Buttons;
+use crate::system::NesSystem;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format version written by `to_text`
+pub const FRAME_HASHES_VERSION: u32 = 1;
+/// Extension appended to a recording's file name for its sidecar
+pub const EXTENSION: &str = "hashes";
+
+/// Error reading, writing or replaying a frame hash sidecar
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameHashError {
+    /// Malformed line (1-based line number)
+    InvalidLine { line: usize, reason: String },
+    /// Written by a newer version of the format
+    UnsupportedVersion(u32),
+    /// The loaded ROM isn't the one that was recorded
+    RomMismatch { expected: Option<u32>, actual: Option<u32> },
+    /// The system wasn't started the way the recording was
+    StartMismatch { expected: u64, actual: u64 },
+    /// The state hash after a frame differs from the recorded one
+    Mismatch { frame: u64, expected: u64, actual: u64 },
+    /// Emulation failed during replay
+    Emulation(String),
+    /// File could not be read or written
+    Io(String),
+}
+
+impl fmt::Display for FrameHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let crc = |crc: &Option<u32>| crc.map_or_else(|| "none".to_string(), |crc| format!("{:08x}", crc));
+        match self {
+            FrameHashError::InvalidLine { line, reason } => write!(f, "Invalid frame hash line {}: {}", line, reason),
+            FrameHashError::UnsupportedVersion(version) => write!(f, "Unsupported frame hash version {}", version),
+            FrameHashError::RomMismatch { expected, actual } => {
+                write!(f, "Recorded with ROM {}, but ROM {} is loaded", crc(expected), crc(actual))
+            }
+            FrameHashError::StartMismatch { expected, actual } => {
+                write!(f, "Start state hash {:016x} doesn't match the recorded {:016x}", actual, expected)
+            }
+            FrameHashError::Mismatch { frame, expected, actual } => {
+                write!(f, "Frame {}: state hash {:016x} doesn't match the recorded {:016x}", frame, actual, expected)
+            }
+            FrameHashError::Emulation(e) => write!(f, "Emulation error during replay: {}", e),
+            FrameHashError::Io(e) => write!(f, "Frame hash I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameHashError {}
+
+/// Input and resulting state of one emulated frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRecord {
+    /// Frame number once the frame finished
+    pub frame: u64,
+    /// Controller state for ports 1 and 2
+    pub ports: [Buttons; 2],
+    /// `NesSystem::state_hash` after the frame
+    pub hash: u64,
+}
+
+/// Per-frame state hashes and input of a recording
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameHashes {
+    /// CRC-32 of the ROM (None for cartridges built in code)
+    pub rom_crc32: Option<u32>,
+    /// RNG seed the system was powered on with
+    pub rng_seed: u64,
+    /// CPU/PPU alignment the system was powered on with
+    pub ppu_alignment: u8,
+    /// Accuracy profile, as displayed by `AccuracyProfile`
+    pub accuracy: String,
+    /// Frame number before the first recorded frame
+    pub start_frame: u64,
+    /// `NesSystem::state_hash` before the first recorded frame
+    pub start_hash: u64,
+    /// Recorded frames, oldest first
+    pub frames: Vec<FrameRecord>,
+}
+
+impl FrameHashes {
+    /// Start recording from the system's current state
+    pub fn begin(system: &NesSystem) -> Self {
+        Self {
+            rom_crc32: system.rom_crc32(),
+            rng_seed: system.rng_seed(),
+            ppu_alignment: system.ppu_alignment(),
+            accuracy: system.accuracy().to_string(),
+            start_frame: system.frame_count(),
+            start_hash: system.state_hash(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Record the frame the system just finished
+    pub fn record(&mut self, system: &NesSystem) {
+        self.frames.push(FrameRecord {
+            frame: system.frame_count(),
+            ports: [system.buttons(0), system.buttons(1)],
+            hash: system.state_hash(),
+        });
+    }
+
+    /// Number of recorded frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check if no frames were recorded
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Run the recorded input on `system`, checking the state hash after every frame
+    ///
+    /// The system must have the recorded ROM loaded and be started the way
+    /// the header describes (seed, alignment and accuracy, then a power
+    /// cycle). `on_frame` runs after each frame once its hash has matched.
+    pub fn replay(&self, system: &mut NesSystem, mut on_frame: impl FnMut(&mut NesSystem)) -> Result<(), FrameHashError> {
+        if system.rom_crc32() != self.rom_crc32 {
+            return Err(FrameHashError::RomMismatch { expected: self.rom_crc32, actual: system.rom_crc32() });
+        }
+        let actual = system.state_hash();
+        if actual != self.start_hash {
+            return Err(FrameHashError::StartMismatch { expected: self.start_hash, actual });
+        }
+        for record in &self.frames {
+            for (port, &buttons) in record.ports.iter().enumerate() {
+                system.set_buttons(port, buttons);
+            }
+            system.run_frames(1).map_err(|e| FrameHashError::Emulation(e.to_string()))?;
+            let actual = system.state_hash();
+            if actual != record.hash {
+                return Err(FrameHashError::Mismatch { frame: record.frame, expected: record.hash, actual });
+            }
+            on_frame(system);
+        }
+        Ok(())
+    }
+
+    /// Serialize to the text format
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("# nes-core frame hashes\n");
+        out.push_str(&format!("version {}\n", FRAME_HASHES_VERSION));
+        match self.rom_crc32 {
+            Some(crc) => out.push_str(&format!("rom {:08x}\n", crc)),
+            None => out.push_str("rom none\n"),
+        }
+        out.push_str(&format!("seed {}\n", self.rng_seed));
+        out.push_str(&format!("ppu_alignment {}\n", self.ppu_alignment));
+        out.push_str(&format!("accuracy {}\n", self.accuracy));
+        out.push_str(&format!("start_frame {}\n", self.start_frame));
+        out.push_str(&format!("start_hash {:016x}\n", self.start_hash));
+        out.push_str("frames\n");
+        for record in &self.frames {
+            let [port1, port2] = record.ports;
+            out.push_str(&format!("{} {:02x} {:02x} {:016x}\n", record.frame, port1.bits(), port2.bits(), record.hash));
+        }
+        out
+    }
+
+    /// Parse the text format
+    ///
+    /// Unknown header keys are ignored, so later versions can add some.
+    pub fn parse(text: &str) -> Result<Self, FrameHashError> {
+        let mut hashes = FrameHashes::default();
+        let mut in_frames = false;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let invalid = |reason: String| FrameHashError::InvalidLine { line: line_number, reason };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if in_frames {
+                hashes.frames.push(parse_frame(line).map_err(invalid)?);
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "version" => {
+                    let version: u32 = value.parse().map_err(|_| invalid(format!("invalid version '{}'", value)))?;
+                    if version > FRAME_HASHES_VERSION {
+                        return Err(FrameHashError::UnsupportedVersion(version));
+                    }
+                }
+                "rom" if value == "none" => hashes.rom_crc32 = None,
+                "rom" => hashes.rom_crc32 = Some(parse_hex(value).map_err(invalid)? as u32),
+                "seed" => hashes.rng_seed = value.parse().map_err(|_| invalid(format!("invalid seed '{}'", value)))?,
+                "ppu_alignment" => {
+                    hashes.ppu_alignment = value.parse().map_err(|_| invalid(format!("invalid alignment '{}'", value)))?
+                }
+                "accuracy" => hashes.accuracy = value.to_string(),
+                "start_frame" => {
+                    hashes.start_frame = value.parse().map_err(|_| invalid(format!("invalid frame '{}'", value)))?
+                }
+                "start_hash" => hashes.start_hash = parse_hex(value).map_err(invalid)?,
+                "frames" => in_frames = true,
+                _ => {}
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Write the sidecar to `path`
+    pub fn save(&self, path: &Path) -> Result<(), FrameHashError> {
+        fs::write(path, self.to_text()).map_err(|e| FrameHashError::Io(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Read a sidecar from `path`
+    pub fn load(path: &Path) -> Result<Self, FrameHashError> {
+        let text = fs::read_to_string(path).map_err(|e| FrameHashError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&text)
+    }
+}
+
+/// Sidecar path for a recording (`clip.gif` gets `clip.gif.hashes`)
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    let mut name = recording.as_os_str().to_os_string();
+    name.push(".");
+    name.push(EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Parse a `<frame> <port 1> <port 2> <hash>` line
+fn parse_frame(line: &str) -> Result<FrameRecord, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [frame, port1, port2, hash] = fields[..] else {
+        return Err(format!("expected 4 fields, got {}", fields.len()));
+    };
+    let port = |text: &str| {
+        u8::from_str_radix(text, 16).map(Buttons::new).map_err(|_| format!("invalid buttons '{}'", text))
+    };
+    Ok(FrameRecord {
+        frame: frame.parse().map_err(|_| format!("invalid frame '{}'", frame))?,
+        ports: [port(port1)?, port(port2)?],
+        hash: parse_hex(hash)?,
+    })
+}
+
+fn parse_hex(text: &str) -> Result<u64, String> {
+    u64::from_str_radix(text, 16).map_err(|_| format!("invalid hex value '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+
+    /// System running a loop that stores the A button bit at $10
+    fn system() -> NesSystem {
+        let mut system = NesSystem::new();
+        // LDA #1; STA $4016; LDA #0; STA $4016; LDA $4016; STA $10; JMP $8000
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..18].copy_from_slice(&[
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x85, 0x10, 0x4C, 0x00, 0x80,
+        ]);
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut recorded = system();
+        let mut hashes = FrameHashes::begin(&recorded);
+        for frame in 0..4 {
+            recorded.set_buttons(0, Buttons::new(if frame == 2 { Buttons::A } else { 0 }));
+            recorded.run_frames(1).unwrap();
+            hashes.record(&recorded);
+        }
+        assert_eq!(hashes.len(), 4);
+        assert_eq!(hashes.frames[2].ports[0], Buttons::new(Buttons::A));
+
+        let parsed = FrameHashes::parse(&hashes.to_text()).unwrap();
+        assert_eq!(parsed, hashes);
+        let mut replayed = system();
+        let mut frames = Vec::new();
+        parsed.replay(&mut replayed, |system| frames.push(system.frame_count())).unwrap();
+        assert_eq!(frames, [1, 2, 3, 4]);
+
+        // Dropping the button press changes RAM, so the hash stops matching
+        let mut tampered = parsed.clone();
+        tampered.frames[2].ports[0] = Buttons::new(0);
+        assert!(matches!(
+            tampered.replay(&mut system(), |_| {}),
+            Err(FrameHashError::Mismatch { frame: 3, .. })
+        ));
+        assert!(matches!(parsed.replay(&mut replayed, |_| {}), Err(FrameHashError::StartMismatch { .. })));
+
+        assert_eq!(sidecar_path(Path::new("out/clip.gif")), Path::new("out/clip.gif.hashes"));
+        assert_eq!(FrameHashes::parse("version 2\n"), Err(FrameHashError::UnsupportedVersion(2)));
+        assert!(matches!(
+            FrameHashes::parse("frames\n1 00 00\n"),
+            Err(FrameHashError::InvalidLine { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+//! Run-length encoded frame diffs for remote viewing
+//!
+//! A message encodes one 256x240 RGB frame relative to the previous frame the
+//! receiver holds. The body is a list of runs, each one
+//! `skip (varint), count (varint), r, g, b`: leave `skip` pixels unchanged, then
+//! paint `count` pixels with the color. Keyframes are encoded against nothing,
+//! so every pixel is covered by a run.
+//!
+//! Message layout (all integers little endian):
+//!
+//! ```text
+//! u8  kind    (1 = keyframe, 2 = delta)
+//! u32 frame   frame number
+//! ... runs
+//! ```
+//!
+//! Varints are unsigned LEB128.
+
+use crate::ppu::{FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use std::fmt;
+
+/// Message kind: full frame
+pub const KIND_KEYFRAME: u8 = 1;
+/// Message kind: changes since the previous frame
+pub const KIND_DELTA: u8 = 2;
+
+const PIXELS: usize = FRAME_WIDTH * FRAME_HEIGHT;
+
+/// Encode `frame` as a diff against `previous` (a keyframe when `None`)
+pub fn encode(frame_number: u32, frame: &[u8], previous: Option<&[u8]>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64);
+    out.push(if previous.is_some() { KIND_DELTA } else { KIND_KEYFRAME });
+    out.extend_from_slice(&frame_number.to_le_bytes());
+
+    let pixel = |buf: &[u8], i: usize| [buf[i * 3], buf[i * 3 + 1], buf[i * 3 + 2]];
+    let changed = |i: usize| previous.is_none_or(|prev| pixel(prev, i) != pixel(frame, i));
+
+    let mut i = 0;
+    let mut skip = 0usize;
+    while i < PIXELS {
+        if !changed(i) {
+            skip += 1;
+            i += 1;
+            continue;
+        }
+        let color = pixel(frame, i);
+        let start = i;
+        while i < PIXELS && changed(i) && pixel(frame, i) == color {
+            i += 1;
+        }
+        write_varint(&mut out, skip as u32);
+        write_varint(&mut out, (i - start) as u32);
+        out.extend_from_slice(&color);
+        skip = 0;
+    }
+    out
+}
+
+/// Apply an encoded message to `frame` (a 256x240 RGB buffer), returning its frame number
+pub fn decode(message: &[u8], frame: &mut [u8]) -> Result<u32, FrameDiffError> {
+    if frame.len() < FRAME_RGB_SIZE {
+        return Err(FrameDiffError::BufferTooSmall);
+    }
+    if message.len() < 5 {
+        return Err(FrameDiffError::Truncated);
+    }
+    if message[0] != KIND_KEYFRAME && message[0] != KIND_DELTA {
+        return Err(FrameDiffError::UnknownKind(message[0]));
+    }
+    let frame_number = u32::from_le_bytes([message[1], message[2], message[3], message[4]]);
+
+    let mut pos = 5;
+    let mut pixel = 0usize;
+    while pos < message.len() {
+        pixel += read_varint(message, &mut pos)? as usize;
+        let count = read_varint(message, &mut pos)? as usize;
+        let color = message.get(pos..pos + 3).ok_or(FrameDiffError::Truncated)?;
+        pos += 3;
+        if pixel + count > PIXELS {
+            return Err(FrameDiffError::OutOfBounds);
+        }
+        for p in pixel..pixel + count {
+            frame[p * 3..p * 3 + 3].copy_from_slice(color);
+        }
+        pixel += count;
+    }
+    Ok(frame_number)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u32, FrameDiffError> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *data.get(*pos).ok_or(FrameDiffError::Truncated)?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(FrameDiffError::Truncated)
+}
+
+/// Frame diff decoding error types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameDiffError {
+    /// The message ended in the middle of a field
+    Truncated,
+    /// The message kind byte is not recognized
+    UnknownKind(u8),
+    /// A run extends past the end of the frame
+    OutOfBounds,
+    /// The destination buffer is smaller than a frame
+    BufferTooSmall,
+}
+
+impl fmt::Display for FrameDiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameDiffError::Truncated => write!(f, "Truncated frame diff"),
+            FrameDiffError::UnknownKind(kind) => write!(f, "Unknown frame diff kind: {}", kind),
+            FrameDiffError::OutOfBounds => write!(f, "Frame diff run out of bounds"),
+            FrameDiffError::BufferTooSmall => write!(f, "Frame buffer too small"),
+        }
+    }
+}
+
+impl std::error::Error for FrameDiffError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframe_and_delta_round_trip() {
+        let mut first = vec![0x10; FRAME_RGB_SIZE];
+        first[..3 * 100].fill(0x80);
+        let keyframe = encode(1, &first, None);
+        // Two runs: 100 grey pixels, then the rest of the frame
+        assert_eq!(keyframe[0], KIND_KEYFRAME);
+        assert!(keyframe.len() < 20);
+
+        let mut second = first.clone();
+        second[3 * 500..3 * 510].fill(0xFF);
+        let delta = encode(2, &second, Some(&first));
+        assert_eq!(delta[0], KIND_DELTA);
+        assert_eq!(encode(3, &second, Some(&second)).len(), 5);
+
+        let mut view = vec![0; FRAME_RGB_SIZE];
+        assert_eq!(decode(&keyframe, &mut view), Ok(1));
+        assert_eq!(view, first);
+        assert_eq!(decode(&delta, &mut view), Ok(2));
+        assert_eq!(view, second);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut view = vec![0; FRAME_RGB_SIZE];
+        assert_eq!(decode(&[KIND_DELTA, 0, 0], &mut view), Err(FrameDiffError::Truncated));
+        assert_eq!(decode(&[9, 0, 0, 0, 0], &mut view), Err(FrameDiffError::UnknownKind(9)));
+        // Skip past the end of the frame
+        let bad = [KIND_DELTA, 0, 0, 0, 0, 0xFF, 0xFF, 0x07, 1, 0, 0, 0];
+        assert_eq!(decode(&bad, &mut view), Err(FrameDiffError::OutOfBounds));
+    }
+}
@@ -0,0 +1,189 @@
+//! Frame skipping for hosts too slow to render every frame
+//!
+//! Every frame is still emulated (so game speed and audio stay correct);
+//! only rendering and presenting are skipped. `Frameskip::Every(n)` renders
+//! one frame in `n`. `Frameskip::Auto` starts by rendering every frame and
+//! adapts from the frame times the frontend reports: it skips more after
+//! consecutive render cycles that overrun the frame budget and skips less
+//! again after a sustained stretch with plenty of headroom.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Largest render interval (render one frame in this many)
+pub const MAX_FRAMESKIP: u8 = 4;
+
+/// Time budget of one NTSC frame (60.0988 Hz)
+pub const FRAME_BUDGET: Duration = Duration::from_nanos(16_639_267);
+
+/// Consecutive overrunning render cycles before auto mode skips more
+const SLOW_CYCLES: u32 = 2;
+/// Consecutive render cycles under 3/4 of the budget before auto mode skips less
+const RECOVER_CYCLES: u32 = 60;
+
+/// Frame skipping mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Frameskip {
+    /// Render every frame
+    #[default]
+    Off,
+    /// Render one frame in this many (2..=`MAX_FRAMESKIP`)
+    Every(u8),
+    /// Choose the interval from measured frame times
+    Auto,
+}
+
+impl Frameskip {
+    /// Parse `off`, `auto` or an interval `1`-`MAX_FRAMESKIP` (1 is the same as off)
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "off" => Some(Frameskip::Off),
+            "auto" => Some(Frameskip::Auto),
+            number => match number.parse::<u8>().ok()? {
+                1 => Some(Frameskip::Off),
+                n @ 2..=MAX_FRAMESKIP => Some(Frameskip::Every(n)),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Frameskip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frameskip::Off => write!(f, "off"),
+            Frameskip::Every(n) => write!(f, "{}", n),
+            Frameskip::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// Decides which frames to render
+#[derive(Debug, Clone)]
+pub struct FrameSkipper {
+    mode: Frameskip,
+    /// Current render interval
+    interval: u8,
+    /// Frames since the last rendered frame
+    position: u8,
+    /// Time spent on the frames of the current render cycle
+    cycle_time: Duration,
+    slow_streak: u32,
+    fast_streak: u32,
+}
+
+impl FrameSkipper {
+    /// Create a skipper for a mode
+    pub fn new(mode: Frameskip) -> Self {
+        let interval = match mode {
+            Frameskip::Every(n) => n.clamp(1, MAX_FRAMESKIP),
+            Frameskip::Off | Frameskip::Auto => 1,
+        };
+        Self { mode, interval, position: 0, cycle_time: Duration::ZERO, slow_streak: 0, fast_streak: 0 }
+    }
+
+    /// Get the mode
+    pub fn mode(&self) -> Frameskip {
+        self.mode
+    }
+
+    /// Current render interval (1 renders every frame)
+    pub fn interval(&self) -> u8 {
+        self.interval
+    }
+
+    /// Check if the next frame should be rendered (call once per frame)
+    pub fn should_render(&mut self) -> bool {
+        let render = self.position == 0;
+        self.position = (self.position + 1) % self.interval;
+        render
+    }
+
+    /// Report how long the last frame took, including rendering if it was rendered
+    ///
+    /// Only auto mode uses this; the interval can change once a render cycle completes.
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.mode != Frameskip::Auto {
+            return;
+        }
+        self.cycle_time += frame_time;
+        if self.position != 0 {
+            return;
+        }
+        let average = self.cycle_time / self.interval as u32;
+        self.cycle_time = Duration::ZERO;
+        if average > FRAME_BUDGET {
+            self.fast_streak = 0;
+            self.slow_streak += 1;
+            if self.slow_streak >= SLOW_CYCLES && self.interval < MAX_FRAMESKIP {
+                self.interval += 1;
+                self.slow_streak = 0;
+            }
+        } else if average < FRAME_BUDGET * 3 / 4 {
+            self.slow_streak = 0;
+            self.fast_streak += 1;
+            if self.fast_streak >= RECOVER_CYCLES && self.interval > 1 {
+                self.interval -= 1;
+                self.fast_streak = 0;
+            }
+        } else {
+            self.slow_streak = 0;
+            self.fast_streak = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Frameskip::parse("off"), Some(Frameskip::Off));
+        assert_eq!(Frameskip::parse("1"), Some(Frameskip::Off));
+        assert_eq!(Frameskip::parse("3"), Some(Frameskip::Every(3)));
+        assert_eq!(Frameskip::parse("AUTO"), Some(Frameskip::Auto));
+        assert_eq!(Frameskip::parse("0"), None);
+        assert_eq!(Frameskip::parse("5"), None);
+        assert_eq!(Frameskip::Every(2).to_string(), "2");
+    }
+
+    #[test]
+    fn test_fixed_interval() {
+        let mut skipper = FrameSkipper::new(Frameskip::Every(3));
+        let rendered: Vec<bool> = (0..7).map(|_| skipper.should_render()).collect();
+        assert_eq!(rendered, [true, false, false, true, false, false, true]);
+
+        let mut off = FrameSkipper::new(Frameskip::Off);
+        off.record(FRAME_BUDGET * 10);
+        assert!((0..5).all(|_| off.should_render()));
+    }
+
+    #[test]
+    fn test_auto_adapts_to_frame_time() {
+        let mut skipper = FrameSkipper::new(Frameskip::Auto);
+        let run = |skipper: &mut FrameSkipper, frames: u32, time: Duration| {
+            for _ in 0..frames {
+                skipper.should_render();
+                skipper.record(time);
+            }
+        };
+
+        // One slow frame isn't enough, two in a row are
+        run(&mut skipper, 1, FRAME_BUDGET * 2);
+        assert_eq!(skipper.interval(), 1);
+        run(&mut skipper, 1, FRAME_BUDGET * 2);
+        assert_eq!(skipper.interval(), 2);
+
+        // Keeps skipping more while slow, up to the limit
+        run(&mut skipper, 100, FRAME_BUDGET * 2);
+        assert_eq!(skipper.interval(), MAX_FRAMESKIP);
+
+        // Frame times near the budget hold the interval; clear headroom lowers it again
+        run(&mut skipper, 1000, FRAME_BUDGET * 9 / 10);
+        assert_eq!(skipper.interval(), MAX_FRAMESKIP);
+        run(&mut skipper, RECOVER_CYCLES * MAX_FRAMESKIP as u32, FRAME_BUDGET / 2);
+        assert_eq!(skipper.interval(), MAX_FRAMESKIP - 1);
+    }
+}
@@ -0,0 +1,421 @@
+//! Animated GIF clip recording
+//!
+//! A `GifRecorder` collects rendered frames and encodes them as a looping
+//! GIF89a. Every NES color is one of the 64 master palette entries, so the
+//! master palette is used as the global color table and frames are stored
+//! losslessly as palette indices.
+//!
+//! GIF delays are whole centiseconds and most viewers stretch delays below
+//! 2cs to 10cs, so 60fps output would play far too slowly. The recorder keeps
+//! every other emulated frame (30fps) and alternates 3cs and 4cs delays so the
+//! clip tracks NTSC's 60.0988fps exactly over time.
+//!
+//! `with_frame_hashes` also writes a `frame_hashes` sidecar next to the GIF,
+//! covering every emulated frame (not just the kept ones), so the clip can
+//! be verified and rendered again later.
+
+use crate::frame_hashes::{self, FrameHashes};
+use crate::ppu::{Ppu, FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH, NES_PALETTE};
+use crate::system::NesSystem;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// NTSC frame rate (CPU clock / CPU cycles per frame)
+pub const NTSC_FRAME_RATE: f64 = 1_789_773.0 / 29_780.5;
+/// Emulated frames per GIF frame
+pub const FRAME_STEP: u32 = 2;
+/// Largest supported scale factor
+pub const MAX_SCALE: usize = 2;
+
+/// LZW minimum code size for the 64-color table
+const MIN_CODE_SIZE: u8 = 6;
+/// GIF codes are at most 12 bits wide
+const MAX_CODE_SIZE: u8 = 12;
+/// Number of codes available before the table must be reset
+const MAX_CODES: u16 = 1 << MAX_CODE_SIZE;
+
+/// Records rendered frames into an animated GIF
+#[derive(Debug, Clone)]
+pub struct GifRecorder {
+    path: PathBuf,
+    max_frames: u32,
+    scale: usize,
+    /// Emulated frames seen since recording started
+    seen: u32,
+    /// Kept frames as master palette indices (256x240)
+    frames: Vec<Vec<u8>>,
+    /// Sidecar being recorded, if requested
+    frame_hashes: Option<FrameHashes>,
+}
+
+impl GifRecorder {
+    /// Start a recording that stops after `max_frames` emulated frames
+    pub fn new(path: impl Into<PathBuf>, max_frames: u32) -> Self {
+        Self {
+            path: path.into(),
+            max_frames,
+            scale: 1,
+            seen: 0,
+            frames: Vec::new(),
+            frame_hashes: None,
+        }
+    }
+
+    /// Scale the output with nearest-neighbour sampling (1 or 2)
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.scale = scale.clamp(1, MAX_SCALE);
+        self
+    }
+
+    /// Also write per-frame state hashes and input to `frame_hashes::sidecar_path`
+    pub fn with_frame_hashes(mut self) -> Self {
+        self.frame_hashes = Some(FrameHashes::default());
+        self
+    }
+
+    /// Get the frame hash sidecar recorded so far (None if not requested)
+    pub fn frame_hashes(&self) -> Option<&FrameHashes> {
+        self.frame_hashes.as_ref()
+    }
+
+    /// Take the sidecar's start state from the system the recording starts on
+    pub(crate) fn begin_frame_hashes(&mut self, system: &NesSystem) {
+        if let Some(hashes) = self.frame_hashes.as_mut() {
+            *hashes = FrameHashes::begin(system);
+        }
+    }
+
+    /// Add the frame the system just finished to the sidecar
+    pub(crate) fn record_frame_hash(&mut self, system: &NesSystem) {
+        if self.is_full() {
+            return;
+        }
+        if let Some(hashes) = self.frame_hashes.as_mut() {
+            hashes.record(system);
+        }
+    }
+
+    /// Output path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Emulated frames recorded so far
+    pub fn frames_seen(&self) -> u32 {
+        self.seen
+    }
+
+    /// Check if the frame limit has been reached
+    pub fn is_full(&self) -> bool {
+        self.seen >= self.max_frames
+    }
+
+    /// Record the frame the PPU just finished
+    pub fn capture(&mut self, ppu: &Ppu) {
+        if self.is_full() {
+            return;
+        }
+        if self.seen.is_multiple_of(FRAME_STEP) {
+            let mut rgb = vec![0u8; FRAME_RGB_SIZE];
+            ppu.render_frame(&mut rgb);
+            self.frames.push(palette_indices(&rgb));
+        }
+        self.seen += 1;
+    }
+
+    /// Encode the recorded frames as a GIF file
+    pub fn encode(&self) -> Vec<u8> {
+        let width = FRAME_WIDTH * self.scale;
+        let height = FRAME_HEIGHT * self.scale;
+        let mut out = Vec::new();
+
+        // Header and logical screen descriptor with a 64-entry global color table
+        out.extend_from_slice(b"GIF89a");
+        out.extend_from_slice(&(width as u16).to_le_bytes());
+        out.extend_from_slice(&(height as u16).to_le_bytes());
+        out.extend_from_slice(&[0xF5, 0, 0]);
+        for &(r, g, b) in &NES_PALETTE {
+            out.extend_from_slice(&[r, g, b]);
+        }
+
+        // Loop forever
+        out.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        out.extend_from_slice(b"NETSCAPE2.0");
+        out.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            // Graphic control extension with the frame delay
+            out.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+            out.extend_from_slice(&frame_delay(index).to_le_bytes());
+            out.extend_from_slice(&[0x00, 0x00]);
+
+            // Image descriptor covering the whole screen
+            out.push(0x2C);
+            out.extend_from_slice(&[0, 0, 0, 0]);
+            out.extend_from_slice(&(width as u16).to_le_bytes());
+            out.extend_from_slice(&(height as u16).to_le_bytes());
+            out.push(0x00);
+
+            out.push(MIN_CODE_SIZE);
+            let data = lzw_encode(&scale_frame(frame, self.scale), MIN_CODE_SIZE);
+            for block in data.chunks(255) {
+                out.push(block.len() as u8);
+                out.extend_from_slice(block);
+            }
+            out.push(0x00);
+        }
+
+        out.push(0x3B);
+        out
+    }
+
+    /// Encode the recording and write it to its path, along with the sidecar if requested
+    pub fn save(&self) -> Result<(), GifError> {
+        if self.frames.is_empty() {
+            return Err(GifError::Empty);
+        }
+        fs::write(&self.path, self.encode()).map_err(|e| GifError::Io(format!("{}: {}", self.path.display(), e)))?;
+        if let Some(hashes) = &self.frame_hashes {
+            hashes.save(&frame_hashes::sidecar_path(&self.path)).map_err(|e| GifError::Io(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Delay of a kept frame in centiseconds
+///
+/// Frame end times are rounded to whole centiseconds, so rounding errors
+/// never accumulate (the delays alternate between 3 and 4).
+pub fn frame_delay(index: usize) -> u16 {
+    let end = |frames: usize| (frames as f64 * FRAME_STEP as f64 * 100.0 / NTSC_FRAME_RATE).round() as u16;
+    end(index + 1) - end(index)
+}
+
+/// Map an RGB frame back to master palette indices
+fn palette_indices(rgb: &[u8]) -> Vec<u8> {
+    let mut last = (0usize, NES_PALETTE[0]);
+    rgb.chunks_exact(3)
+        .map(|px| {
+            let color = (px[0], px[1], px[2]);
+            if color != last.1 {
+                let index = NES_PALETTE.iter().position(|&c| c == color).unwrap_or(0);
+                last = (index, color);
+            }
+            last.0 as u8
+        })
+        .collect()
+}
+
+/// Nearest-neighbour scale a 256x240 index frame
+fn scale_frame(frame: &[u8], scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return frame.to_vec();
+    }
+    let mut out = Vec::with_capacity(frame.len() * scale * scale);
+    for row in frame.chunks_exact(FRAME_WIDTH) {
+        let wide: Vec<u8> = row.iter().flat_map(|&px| std::iter::repeat_n(px, scale)).collect();
+        for _ in 0..scale {
+            out.extend_from_slice(&wide);
+        }
+    }
+    out
+}
+
+/// Packs variable-width codes least significant bit first
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.acc |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// LZW-compress palette indices for a GIF image block
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    // Codes for (prefix code, next index) pairs, indexed by prefix * clear + index
+    // (0 marks an unassigned pair; real codes start after the end code)
+    let stride = clear as usize;
+    let mut table = vec![0u16; MAX_CODES as usize * stride];
+    let mut next = end + 1;
+    let mut size = min_code_size + 1;
+    let mut writer = BitWriter {
+        out: Vec::new(),
+        acc: 0,
+        bits: 0,
+    };
+
+    writer.write(clear, size);
+    let Some((&first, rest)) = indices.split_first() else {
+        writer.write(end, size);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        let slot = prefix as usize * stride + index as usize;
+        if table[slot] != 0 {
+            prefix = table[slot];
+            continue;
+        }
+        writer.write(prefix, size);
+        // The decoder widens its codes once it has assigned every code of the current width
+        if next == 1 << size && size < MAX_CODE_SIZE {
+            size += 1;
+        }
+        if next < MAX_CODES {
+            table[slot] = next;
+            next += 1;
+        } else {
+            writer.write(clear, size);
+            table.fill(0);
+            next = end + 1;
+            size = min_code_size + 1;
+        }
+        prefix = index as u16;
+    }
+    writer.write(prefix, size);
+    if next == 1 << size && size < MAX_CODE_SIZE {
+        size += 1;
+    }
+    writer.write(end, size);
+    writer.finish()
+}
+
+/// GIF recording error types
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GifError {
+    /// No frames were recorded
+    Empty,
+    /// The file could not be written
+    Io(String),
+}
+
+impl std::fmt::Display for GifError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GifError::Empty => write!(f, "No frames were recorded"),
+            GifError::Io(msg) => write!(f, "Failed to write GIF: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for GifError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference LZW decoder for the image data of a GIF
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let mut dict: Vec<Vec<u8>> = Vec::new();
+        let mut size = min_code_size + 1;
+        let mut prev: Option<usize> = None;
+        let mut out = Vec::new();
+        let (mut acc, mut bits, mut pos) = (0u32, 0u8, 0usize);
+        loop {
+            while bits < size {
+                acc |= (data[pos] as u32) << bits;
+                pos += 1;
+                bits += 8;
+            }
+            let code = (acc & ((1 << size) - 1)) as usize;
+            acc >>= size;
+            bits -= size;
+
+            if code == clear {
+                dict = (0..clear).map(|i| vec![i as u8]).chain([vec![], vec![]]).collect();
+                size = min_code_size + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match prev {
+                None => dict[code].clone(),
+                Some(p) => {
+                    let mut entry = if code < dict.len() { dict[code].clone() } else { dict[p].clone() };
+                    if code >= dict.len() {
+                        entry.push(dict[p][0]);
+                    }
+                    let mut added = dict[p].clone();
+                    added.push(entry[0]);
+                    if dict.len() < MAX_CODES as usize {
+                        dict.push(added);
+                    }
+                    entry
+                }
+            };
+            if dict.len() == 1 << size && size < MAX_CODE_SIZE {
+                size += 1;
+            }
+            out.extend_from_slice(&entry);
+            prev = Some(code);
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        // Long runs fill the table and force clear codes; noise exercises every width
+        let mut indices: Vec<u8> = (0..40_000u32).map(|i| (i / 300 % 64) as u8).collect();
+        let mut seed = 0x1234_5678u32;
+        for _ in 0..60_000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            indices.push((seed % 64) as u8);
+        }
+        let encoded = lzw_encode(&indices, MIN_CODE_SIZE);
+        assert_eq!(lzw_decode(&encoded, MIN_CODE_SIZE), indices);
+        assert_eq!(lzw_decode(&lzw_encode(&[], MIN_CODE_SIZE), MIN_CODE_SIZE), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_frame_timing_tracks_ntsc() {
+        let delays: Vec<u16> = (0..300).map(frame_delay).collect();
+        assert!(delays.iter().all(|&d| d == 3 || d == 4));
+        // 300 kept frames are 600 emulated frames, just under 10 seconds
+        assert_eq!(delays.iter().map(|&d| d as u32).sum::<u32>(), 998);
+    }
+
+    #[test]
+    fn test_recording_keeps_every_other_frame() {
+        let mut ppu = Ppu::new();
+        ppu.set_palette_entry(0, 0x21);
+        let mut recorder = GifRecorder::new("clip.gif", 5).with_scale(2);
+        for _ in 0..8 {
+            recorder.capture(&ppu);
+        }
+        assert!(recorder.is_full());
+        assert_eq!(recorder.frames_seen(), 5);
+        assert_eq!(recorder.frames.len(), 3);
+        assert!(recorder.frames[0].iter().all(|&i| i == 0x21));
+
+        let gif = recorder.encode();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[0x00, 0x02, 0xE0, 0x01]);
+        assert_eq!(gif.last(), Some(&0x3B));
+        assert_eq!(GifRecorder::new("empty.gif", 5).save(), Err(GifError::Empty));
+    }
+}
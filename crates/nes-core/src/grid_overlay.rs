@@ -0,0 +1,124 @@
+//! Background grid overlays
+//!
+//! Draws the 8x8 tile grid and the 16x16 attribute boundaries over a 256x240
+//! RGB frame, following the background scroll the renderer used. The
+//! attribute overlay labels each 16x16 area with the background palette (0-3)
+//! its attribute bits select, so artists can see where color changes land.
+
+use crate::osd::draw_text;
+use crate::ppu::{Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+
+/// Tile grid line color
+const TILE_GRID_COLOR: [u8; 3] = [0x60, 0x60, 0x60];
+/// Attribute boundary color
+const ATTRIBUTE_GRID_COLOR: [u8; 3] = [0xFF, 0xD0, 0x00];
+/// Label width in pixels (one glyph plus its dimmed box)
+const LABEL_WIDTH: usize = 6;
+/// Label height in pixels
+const LABEL_HEIGHT: usize = 7;
+
+/// Screen columns where a new tile (or attribute area, when `attribute` is set) starts
+fn column_starts(ppu: &Ppu, attribute: bool) -> Vec<usize> {
+    // Scroll is per frame, so the first scanline gives every column's tile
+    let cell = |x: usize| {
+        let (tile_x, _, _) = ppu.background_tile_at(x, 0);
+        if attribute { tile_x / 2 } else { tile_x }
+    };
+    (1..FRAME_WIDTH).filter(|&x| cell(x) != cell(x - 1)).collect()
+}
+
+/// Screen rows where a new tile (or attribute area) starts
+fn row_starts(ppu: &Ppu, attribute: bool) -> Vec<usize> {
+    let cell = |y: usize| {
+        let (_, tile_y, _) = ppu.background_tile_at(0, y);
+        if attribute { tile_y / 2 } else { tile_y }
+    };
+    (1..FRAME_HEIGHT).filter(|&y| cell(y) != cell(y - 1)).collect()
+}
+
+/// Draw grid lines at the given columns and rows
+fn draw_lines(framebuffer: &mut [u8], columns: &[usize], rows: &[usize], color: [u8; 3]) {
+    for y in 0..FRAME_HEIGHT {
+        let row = y * FRAME_WIDTH * 3;
+        if rows.contains(&y) {
+            for x in 0..FRAME_WIDTH {
+                framebuffer[row + x * 3..row + x * 3 + 3].copy_from_slice(&color);
+            }
+        } else {
+            for &x in columns {
+                framebuffer[row + x * 3..row + x * 3 + 3].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+/// Draw the 8x8 tile grid over a 256x240 RGB framebuffer
+pub fn draw_tile_grid(ppu: &Ppu, framebuffer: &mut [u8]) {
+    if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+        return;
+    }
+    draw_lines(framebuffer, &column_starts(ppu, false), &row_starts(ppu, false), TILE_GRID_COLOR);
+}
+
+/// Draw the 16x16 attribute boundaries and their palette numbers over a 256x240 RGB framebuffer
+pub fn draw_attribute_grid(ppu: &Ppu, framebuffer: &mut [u8]) {
+    if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+        return;
+    }
+    let columns = column_starts(ppu, true);
+    let rows = row_starts(ppu, true);
+    draw_lines(framebuffer, &columns, &rows, ATTRIBUTE_GRID_COLOR);
+
+    // Label every area that has room, including ones cut off by the top-left edge
+    let left_edges: Vec<usize> = std::iter::once(0).chain(columns).collect();
+    let top_edges: Vec<usize> = std::iter::once(0).chain(rows).collect();
+    for &y in &top_edges {
+        if y + 2 + LABEL_HEIGHT > FRAME_HEIGHT {
+            continue;
+        }
+        for &x in &left_edges {
+            if x + 2 + LABEL_WIDTH > FRAME_WIDTH {
+                continue;
+            }
+            let (tile_x, tile_y, _) = ppu.background_tile_at(x, y);
+            let palette = ppu.attribute_palette(tile_x, tile_y);
+            draw_text(framebuffer, x + 2, y + 2, &palette.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::FRAME_RGB_SIZE;
+
+    fn pixel(frame: &[u8], x: usize, y: usize) -> &[u8] {
+        let i = (y * FRAME_WIDTH + x) * 3;
+        &frame[i..i + 3]
+    }
+
+    #[test]
+    fn test_grid_lines_and_labels() {
+        let ppu = Ppu::new();
+        let mut frame = vec![0; FRAME_RGB_SIZE];
+        draw_tile_grid(&ppu, &mut frame);
+        assert_eq!(pixel(&frame, 8, 3), TILE_GRID_COLOR);
+        assert_eq!(pixel(&frame, 3, 16), TILE_GRID_COLOR);
+        assert_eq!(pixel(&frame, 9, 3), [0, 0, 0]);
+
+        let mut frame = vec![0; FRAME_RGB_SIZE];
+        draw_attribute_grid(&ppu, &mut frame);
+        assert_eq!(pixel(&frame, 16, 30), ATTRIBUTE_GRID_COLOR);
+        assert_eq!(pixel(&frame, 8, 30), [0, 0, 0]);
+
+        // Every area is labelled with its palette (0 with blank attribute tables)
+        let mut label = vec![0; FRAME_RGB_SIZE];
+        draw_text(&mut label, 18, 18, "0");
+        for y in 18..23 {
+            for x in 18..21 {
+                assert_eq!(pixel(&frame, x, y), pixel(&label, x, y));
+            }
+        }
+        assert_eq!(pixel(&frame, 18, 18), [0xFF, 0xFF, 0xFF]);
+    }
+}
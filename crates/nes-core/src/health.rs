@@ -0,0 +1,268 @@
+//! Emulator health summary for frontends
+//!
+//! A black screen or silence has many causes: no ROM, a mapper the core
+//! doesn't support, a game that jammed the CPU, or one that simply hasn't
+//! turned rendering on. `HealthReport` gathers those signals in one place so
+//! a frontend can show a single status indicator and a useful hint instead
+//! of guessing.
+//!
+//! The system feeds a `HealthMonitor` as it runs: every step it notes
+//! whether rendering ($2001) and any sound channel ($4015) are enabled, and
+//! every frame it closes a one-frame bucket. "In the last second" means any
+//! of the last `HEALTH_WINDOW_FRAMES` frames. The APU doesn't generate
+//! samples yet, so audio counts as active when a channel is enabled.
+//!
+//! Speed needs wall-clock time, which the core doesn't read (it would break
+//! wasm builds), so frontends report how long their frames take with
+//! `NesSystem::record_host_time`; until they do, the speed is unknown.
+
+use crate::frameskip::FRAME_BUDGET;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// Frames that make up "the last second" (60Hz)
+pub const HEALTH_WINDOW_FRAMES: usize = 60;
+/// Speed (relative to real hardware) below which the report warns
+pub const SLOW_SPEED: f64 = 0.9;
+
+/// Overall status, for a single indicator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    /// Running normally
+    Ok,
+    /// Running, but something looks wrong (no picture yet, running slowly)
+    Warning,
+    /// Can't run properly (no ROM, unsupported mapper, CPU jammed)
+    Error,
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthStatus::Ok => write!(f, "ok"),
+            HealthStatus::Warning => write!(f, "warning"),
+            HealthStatus::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Snapshot of the emulator's health
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct HealthReport {
+    pub rom_loaded: bool,
+    /// iNES mapper number (None without a ROM or for a cartridge loaded without a header)
+    pub mapper: Option<u8>,
+    /// Whether the mapper has dedicated support (true when unknown)
+    pub mapper_supported: bool,
+    /// Frames observed in the window so far (up to `HEALTH_WINDOW_FRAMES`)
+    pub frames_observed: usize,
+    /// Background or sprite rendering was enabled during the last second
+    pub rendering_enabled: bool,
+    /// A sound channel was enabled during the last second
+    pub audio_active: bool,
+    /// Address and opcode the CPU stopped on, if it jammed
+    pub cpu_jammed: Option<(u16, u8)>,
+    /// Emulation speed over the last second, relative to real hardware (1.0 is full speed)
+    pub speed: Option<f64>,
+}
+
+impl HealthReport {
+    /// Check if a full second has been observed (before that, no picture is normal)
+    pub fn window_full(&self) -> bool {
+        self.frames_observed >= HEALTH_WINDOW_FRAMES
+    }
+
+    /// Overall status
+    pub fn status(&self) -> HealthStatus {
+        if !self.rom_loaded || !self.mapper_supported || self.cpu_jammed.is_some() {
+            HealthStatus::Error
+        } else if (self.window_full() && !self.rendering_enabled) || self.speed.is_some_and(|speed| speed < SLOW_SPEED) {
+            HealthStatus::Warning
+        } else {
+            HealthStatus::Ok
+        }
+    }
+
+    /// Explanations for everything that isn't healthy, most serious first
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.rom_loaded {
+            problems.push("no ROM loaded".to_string());
+        }
+        if !self.mapper_supported {
+            let mapper = self.mapper.map_or_else(|| "?".to_string(), |number| number.to_string());
+            problems.push(format!("mapper {} is not supported; the game will probably not run", mapper));
+        }
+        if let Some((pc, opcode)) = self.cpu_jammed {
+            problems.push(format!("CPU jammed on opcode ${:02X} at ${:04X}; reset to recover", opcode, pc));
+        }
+        if self.rom_loaded && self.window_full() && !self.rendering_enabled {
+            problems.push("black screen because the game never enabled rendering in the last second".to_string());
+        }
+        if let Some(speed) = self.speed.filter(|&speed| speed < SLOW_SPEED) {
+            problems.push(format!("running at {:.0}% speed", speed * 100.0));
+        }
+        problems
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status())?;
+        let problems = self.problems();
+        if !problems.is_empty() {
+            write!(f, ": {}", problems.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Activity seen during one frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct FrameActivity {
+    rendering: bool,
+    audio: bool,
+}
+
+/// Collects the signals a `HealthReport` is built from
+#[derive(Debug, Clone, Default)]
+pub struct HealthMonitor {
+    /// Activity of the last completed frames, oldest first
+    frames: VecDeque<FrameActivity>,
+    /// Activity of the frame in progress
+    current: FrameActivity,
+    /// Address and opcode of the jam, if the CPU jammed
+    jammed: Option<(u16, u8)>,
+    /// Host time reports (frames emulated, time taken), oldest first
+    host_times: VecDeque<(u64, Duration)>,
+}
+
+impl HealthMonitor {
+    /// Create an empty monitor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget everything (after a reset or a new ROM)
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Note what the hardware is doing right now
+    pub fn observe(&mut self, rendering: bool, audio: bool) {
+        self.current.rendering |= rendering;
+        self.current.audio |= audio;
+    }
+
+    /// Close the current frame
+    pub fn end_frame(&mut self) {
+        if self.frames.len() == HEALTH_WINDOW_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(std::mem::take(&mut self.current));
+    }
+
+    /// Record that the CPU stopped on an opcode it can't execute
+    pub fn record_jam(&mut self, pc: u16, opcode: u8) {
+        self.jammed = Some((pc, opcode));
+    }
+
+    /// Record that the host took `elapsed` to emulate (and present) `frames` frames
+    pub fn record_host_time(&mut self, frames: u64, elapsed: Duration) {
+        self.host_times.push_back((frames, elapsed));
+        // Keep just over a second of reports
+        while self.host_times.len() > 1 && self.host_time().1 - self.host_times[0].1 >= Duration::from_secs(1) {
+            self.host_times.pop_front();
+        }
+    }
+
+    /// Total frames and time across the host time reports
+    fn host_time(&self) -> (u64, Duration) {
+        self.host_times.iter().fold((0, Duration::ZERO), |(frames, time), &(f, t)| (frames + f, time + t))
+    }
+
+    /// Emulation speed relative to real hardware, if the host has reported any time
+    pub fn speed(&self) -> Option<f64> {
+        let (frames, time) = self.host_time();
+        (!time.is_zero()).then(|| frames as f64 * FRAME_BUDGET.as_secs_f64() / time.as_secs_f64())
+    }
+
+    /// Build a report; the caller supplies what the monitor can't see
+    pub fn report(&self, rom_loaded: bool, mapper: Option<u8>, mapper_supported: bool) -> HealthReport {
+        HealthReport {
+            rom_loaded,
+            mapper,
+            mapper_supported,
+            frames_observed: self.frames.len(),
+            rendering_enabled: self.frames.iter().any(|frame| frame.rendering) || self.current.rendering,
+            audio_active: self.frames.iter().any(|frame| frame.audio) || self.current.audio,
+            cpu_jammed: self.jammed,
+            speed: self.speed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(monitor: &mut HealthMonitor, frames: usize, rendering: bool, audio: bool) {
+        for _ in 0..frames {
+            monitor.observe(rendering, audio);
+            monitor.end_frame();
+        }
+    }
+
+    #[test]
+    fn test_activity_window() {
+        let mut monitor = HealthMonitor::new();
+        run(&mut monitor, 10, false, false);
+        let report = monitor.report(true, Some(0), true);
+        assert!(!report.window_full());
+        assert_eq!(report.status(), HealthStatus::Ok);
+
+        run(&mut monitor, HEALTH_WINDOW_FRAMES, false, false);
+        let report = monitor.report(true, Some(0), true);
+        assert_eq!(report.status(), HealthStatus::Warning);
+        assert!(report.problems()[0].contains("never enabled rendering"));
+
+        // Activity counts for a second, then ages out
+        run(&mut monitor, 1, true, true);
+        run(&mut monitor, HEALTH_WINDOW_FRAMES - 1, false, false);
+        let report = monitor.report(true, Some(0), true);
+        assert!(report.rendering_enabled && report.audio_active);
+        run(&mut monitor, 1, false, false);
+        assert!(!monitor.report(true, Some(0), true).audio_active);
+    }
+
+    #[test]
+    fn test_errors_outrank_warnings() {
+        let mut monitor = HealthMonitor::new();
+        monitor.record_jam(0x8123, 0x02);
+        let report = monitor.report(true, Some(30), false);
+        assert_eq!(report.status(), HealthStatus::Error);
+        assert_eq!(
+            report.to_string(),
+            "error: mapper 30 is not supported; the game will probably not run; \
+             CPU jammed on opcode $02 at $8123; reset to recover"
+        );
+        monitor.reset();
+        assert_eq!(monitor.report(false, None, true).to_string(), "error: no ROM loaded");
+    }
+
+    #[test]
+    fn test_speed_from_host_time() {
+        let mut monitor = HealthMonitor::new();
+        assert_eq!(monitor.speed(), None);
+        for _ in 0..120 {
+            monitor.record_host_time(1, FRAME_BUDGET * 2);
+        }
+        let speed = monitor.speed().unwrap();
+        assert!((speed - 0.5).abs() < 1e-9);
+        // Only about the last second is kept
+        assert!(monitor.host_times.len() <= 31);
+        assert!(monitor.report(true, Some(0), true).problems()[0].contains("50% speed"));
+    }
+}
@@ -0,0 +1,333 @@
+//! Hotkey bindings shared across frontends
+//!
+//! Frontends translate their native key events into canonical key names
+//! ("F1", "Tab", "R", "1", ...) plus `Modifiers`, then ask the `HotkeyMap`
+//! which named `Action`s to run. Bindings round-trip through a simple
+//! `action = combo` config format:
+//!
+//! ```text
+//! save_state_1 = Shift+F1
+//! fast_forward = Tab
+//! ```
+//!
+//! The legacy egui app in the repository root includes this file by path, so
+//! it must only depend on `std`.
+
+use std::fmt;
+
+/// Number of save state slots with default bindings
+pub const STATE_SLOTS: u8 = 4;
+
+/// Display layer that can be toggled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layer {
+    Background,
+    Sprites,
+}
+
+/// Debug overlay drawn over the game view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Overlay {
+    /// 8x8 background tile grid
+    TileGrid,
+    /// 16x16 attribute boundaries with their palette numbers
+    AttributeGrid,
+}
+
+/// Named frontend action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Action {
+    SaveState(u8),
+    LoadState(u8),
+    Rewind,
+    FastForward,
+    Screenshot,
+    RecordGif,
+    ToggleLayer(Layer),
+    ToggleOverlay(Overlay),
+    Reset,
+    Pause,
+    Quit,
+}
+
+impl Action {
+    /// Config name of the action (e.g. `save_state_1`)
+    pub fn name(&self) -> String {
+        match self {
+            Action::SaveState(slot) => format!("save_state_{}", slot),
+            Action::LoadState(slot) => format!("load_state_{}", slot),
+            Action::Rewind => "rewind".into(),
+            Action::FastForward => "fast_forward".into(),
+            Action::Screenshot => "screenshot".into(),
+            Action::RecordGif => "record_gif".into(),
+            Action::ToggleLayer(Layer::Background) => "toggle_background".into(),
+            Action::ToggleLayer(Layer::Sprites) => "toggle_sprites".into(),
+            Action::ToggleOverlay(Overlay::TileGrid) => "toggle_tile_grid".into(),
+            Action::ToggleOverlay(Overlay::AttributeGrid) => "toggle_attribute_grid".into(),
+            Action::Reset => "reset".into(),
+            Action::Pause => "pause".into(),
+            Action::Quit => "quit".into(),
+        }
+    }
+
+    /// Parse a config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(slot) = name.strip_prefix("save_state_") {
+            return slot.parse().ok().map(Action::SaveState);
+        }
+        if let Some(slot) = name.strip_prefix("load_state_") {
+            return slot.parse().ok().map(Action::LoadState);
+        }
+        Some(match name {
+            "rewind" => Action::Rewind,
+            "fast_forward" => Action::FastForward,
+            "screenshot" => Action::Screenshot,
+            "record_gif" => Action::RecordGif,
+            "toggle_background" => Action::ToggleLayer(Layer::Background),
+            "toggle_sprites" => Action::ToggleLayer(Layer::Sprites),
+            "toggle_tile_grid" => Action::ToggleOverlay(Overlay::TileGrid),
+            "toggle_attribute_grid" => Action::ToggleOverlay(Overlay::AttributeGrid),
+            "reset" => Action::Reset,
+            "pause" => Action::Pause,
+            "quit" => Action::Quit,
+            _ => return None,
+        })
+    }
+}
+
+/// Modifier keys held with a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// A key plus required modifiers
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    /// Canonical key name (uppercase letters, digits, "F1", "Tab", ...)
+    pub key: String,
+    pub modifiers: Modifiers,
+}
+
+impl KeyCombo {
+    /// Create a combo without modifiers
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: normalize_key(key),
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    /// Create a combo with modifiers
+    pub fn with_modifiers(key: &str, modifiers: Modifiers) -> Self {
+        Self {
+            key: normalize_key(key),
+            modifiers,
+        }
+    }
+
+    /// Parse a combo such as `Ctrl+Shift+F1`
+    pub fn parse(text: &str) -> Result<Self, HotkeyError> {
+        let mut modifiers = Modifiers::default();
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|k| !k.is_empty()).ok_or_else(|| HotkeyError::InvalidCombo(text.to_string()))?;
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                _ => return Err(HotkeyError::InvalidCombo(text.to_string())),
+            }
+        }
+        Ok(Self::with_modifiers(key, modifiers))
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+/// Canonical key names: single characters are uppercased, longer names keep their case
+fn normalize_key(key: &str) -> String {
+    if key.chars().count() == 1 {
+        key.to_ascii_uppercase()
+    } else {
+        key.to_string()
+    }
+}
+
+/// Mapping from key combos to actions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotkeyMap {
+    bindings: Vec<(KeyCombo, Action)>,
+}
+
+impl HotkeyMap {
+    /// Create a map with no bindings
+    pub fn empty() -> Self {
+        Self { bindings: Vec::new() }
+    }
+
+    /// Bind a combo to an action, replacing any existing binding for the combo
+    pub fn bind(&mut self, combo: KeyCombo, action: Action) {
+        self.bindings.retain(|(c, _)| *c != combo);
+        self.bindings.push((combo, action));
+    }
+
+    /// Remove every binding for the action
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.retain(|(_, a)| *a != action);
+    }
+
+    /// Get the action bound to a combo
+    pub fn action(&self, combo: &KeyCombo) -> Option<Action> {
+        self.bindings.iter().find(|(c, _)| c == combo).map(|(_, a)| *a)
+    }
+
+    /// Get the actions triggered by keys pressed this frame
+    pub fn pressed_actions<'a>(&self, pressed: impl IntoIterator<Item = &'a str>, modifiers: Modifiers) -> Vec<Action> {
+        pressed
+            .into_iter()
+            .filter_map(|key| self.action(&KeyCombo::with_modifiers(key, modifiers)))
+            .collect()
+    }
+
+    /// Check if a held action (such as fast forward) is active given the keys currently down
+    pub fn is_held<'a>(&self, action: Action, down: impl IntoIterator<Item = &'a str>, modifiers: Modifiers) -> bool {
+        down.into_iter()
+            .any(|key| self.action(&KeyCombo::with_modifiers(key, modifiers)) == Some(action))
+    }
+
+    /// Iterate over all bindings
+    pub fn bindings(&self) -> impl Iterator<Item = (&KeyCombo, Action)> {
+        self.bindings.iter().map(|(c, a)| (c, *a))
+    }
+
+    /// Serialize as `action = combo` lines
+    pub fn to_config(&self) -> String {
+        self.bindings
+            .iter()
+            .map(|(combo, action)| format!("{} = {}\n", action.name(), combo))
+            .collect()
+    }
+
+    /// Parse `action = combo` lines (blank lines and `#` comments are ignored)
+    pub fn from_config(text: &str) -> Result<Self, HotkeyError> {
+        let mut map = Self::empty();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, combo) = line
+                .split_once('=')
+                .ok_or_else(|| HotkeyError::InvalidLine(line.to_string()))?;
+            let action = Action::from_name(name.trim())
+                .ok_or_else(|| HotkeyError::UnknownAction(name.trim().to_string()))?;
+            map.bind(KeyCombo::parse(combo.trim())?, action);
+        }
+        Ok(map)
+    }
+}
+
+impl Default for HotkeyMap {
+    /// Default bindings: F1-F4 load, Shift+F1-F4 save, Backspace rewind, Tab fast forward
+    fn default() -> Self {
+        let mut map = Self::empty();
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        for slot in 1..=STATE_SLOTS {
+            let key = format!("F{}", slot);
+            map.bind(KeyCombo::new(&key), Action::LoadState(slot));
+            map.bind(KeyCombo::with_modifiers(&key, shift), Action::SaveState(slot));
+        }
+        map.bind(KeyCombo::new("Backspace"), Action::Rewind);
+        map.bind(KeyCombo::new("Tab"), Action::FastForward);
+        map.bind(KeyCombo::new("F12"), Action::Screenshot);
+        map.bind(KeyCombo::new("F11"), Action::RecordGif);
+        map.bind(KeyCombo::new("F7"), Action::ToggleOverlay(Overlay::TileGrid));
+        map.bind(KeyCombo::new("F8"), Action::ToggleOverlay(Overlay::AttributeGrid));
+        map.bind(KeyCombo::new("F9"), Action::ToggleLayer(Layer::Background));
+        map.bind(KeyCombo::new("F10"), Action::ToggleLayer(Layer::Sprites));
+        map.bind(KeyCombo::new("R"), Action::Reset);
+        map.bind(KeyCombo::new("P"), Action::Pause);
+        map.bind(KeyCombo::new("Escape"), Action::Quit);
+        map
+    }
+}
+
+/// Hotkey configuration error types
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HotkeyError {
+    /// A config line is not of the form `action = combo`
+    InvalidLine(String),
+    /// Unknown action name
+    UnknownAction(String),
+    /// A key combo could not be parsed
+    InvalidCombo(String),
+}
+
+impl fmt::Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HotkeyError::InvalidLine(line) => write!(f, "Invalid hotkey line: {}", line),
+            HotkeyError::UnknownAction(name) => write!(f, "Unknown hotkey action: {}", name),
+            HotkeyError::InvalidCombo(combo) => write!(f, "Invalid key combo: {}", combo),
+        }
+    }
+}
+
+impl std::error::Error for HotkeyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let map = HotkeyMap::default();
+        let config = map.to_config();
+        assert!(config.contains("save_state_1 = Shift+F1\n"));
+        assert!(config.contains("toggle_attribute_grid = F8\n"));
+        assert_eq!(HotkeyMap::from_config(&config).unwrap(), map);
+    }
+
+    #[test]
+    fn test_pressed_actions() {
+        let map = HotkeyMap::from_config("# comment\nsave_state_2 = ctrl+s\nreset = r\n").unwrap();
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(map.pressed_actions(["S"], ctrl), [Action::SaveState(2)]);
+        assert!(map.pressed_actions(["S"], Modifiers::default()).is_empty());
+        assert!(map.is_held(Action::Reset, ["R"], Modifiers::default()));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert_eq!(
+            HotkeyMap::from_config("warp = F1"),
+            Err(HotkeyError::UnknownAction("warp".into()))
+        );
+        assert!(matches!(HotkeyMap::from_config("reset = Hyper+R"), Err(HotkeyError::InvalidCombo(_))));
+        assert!(matches!(HotkeyMap::from_config("reset"), Err(HotkeyError::InvalidLine(_))));
+    }
+}
@@ -0,0 +1,133 @@
+//! Idle detection for headless and batch runs
+//!
+//! A game waiting on a menu for input keeps emulating the same frozen
+//! screen. `IdleDetector` hashes the game state at the end of every frame
+//! (internal RAM, cartridge RAM, VRAM, palette and OAM; CPU registers are
+//! left out because a wait loop is cut at a different instruction every
+//! frame) and counts how many frames in a row it stayed the same. Once the
+//! count reaches the threshold the system reports itself idle, and
+//! `NesSystem::run_until_idle` stops.
+//!
+//! Games that tick a timer or frame counter in RAM while they wait never
+//! look idle; exclude those bytes with `with_ignored_ram`.
+
+use crate::ppu::Ppu;
+use crate::state::{fnv1a_update, FNV_OFFSET_BASIS};
+use std::ops::Range;
+
+/// Default unchanged frames before a game counts as idle (5 seconds at 60Hz)
+pub const DEFAULT_IDLE_FRAMES: u32 = 300;
+
+/// Counts frames in which the game state didn't change
+#[derive(Debug, Clone)]
+pub struct IdleDetector {
+    /// Unchanged frames that make the game idle
+    threshold: u32,
+    /// Internal RAM addresses left out of the hash
+    ignored: Vec<Range<u16>>,
+    /// Hash of the last completed frame
+    last_hash: Option<u64>,
+    /// Frames in a row with the same hash
+    unchanged: u32,
+}
+
+impl IdleDetector {
+    /// Report idle after `threshold` frames without a state change (at least 1)
+    pub fn new(threshold: u32) -> Self {
+        Self { threshold: threshold.max(1), ignored: Vec::new(), last_hash: None, unchanged: 0 }
+    }
+
+    /// Leave internal RAM addresses out of the comparison (timers, frame counters)
+    pub fn with_ignored_ram(mut self, range: Range<u16>) -> Self {
+        self.ignored.push(range);
+        self
+    }
+
+    /// Get the number of unchanged frames that make the game idle
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Get the number of frames in a row the state hasn't changed
+    pub fn unchanged_frames(&self) -> u32 {
+        self.unchanged
+    }
+
+    /// Check if the state has been unchanged for the threshold
+    pub fn is_idle(&self) -> bool {
+        self.unchanged >= self.threshold
+    }
+
+    /// Forget the history (after a reset or loading a state)
+    pub fn reset(&mut self) {
+        self.last_hash = None;
+        self.unchanged = 0;
+    }
+
+    /// Compare the state at the end of a frame with the previous frame
+    pub fn end_frame(&mut self, ram: &[u8], prg_ram: Option<&[u8]>, ppu: &Ppu) {
+        let hash = self.hash(ram, prg_ram, ppu);
+        if self.last_hash == Some(hash) {
+            self.unchanged = self.unchanged.saturating_add(1);
+        } else {
+            self.unchanged = 0;
+        }
+        self.last_hash = Some(hash);
+    }
+
+    fn hash(&self, ram: &[u8], prg_ram: Option<&[u8]>, ppu: &Ppu) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        for (address, &byte) in ram.iter().enumerate() {
+            if !self.ignored.iter().any(|range| range.contains(&(address as u16))) {
+                hash = fnv1a_update(hash, &[byte]);
+            }
+        }
+        hash = fnv1a_update(hash, prg_ram.unwrap_or_default());
+        hash = fnv1a_update(hash, ppu.vram());
+        hash = fnv1a_update(hash, ppu.palette_ram());
+        fnv1a_update(hash, ppu.oam())
+    }
+}
+
+impl Default for IdleDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_IDLE_FRAMES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_after_threshold() {
+        let ppu = Ppu::new();
+        let mut ram = [0u8; 2048];
+        let mut detector = IdleDetector::new(3);
+        for _ in 0..3 {
+            detector.end_frame(&ram, None, &ppu);
+            assert!(!detector.is_idle());
+        }
+        detector.end_frame(&ram, None, &ppu);
+        assert!(detector.is_idle());
+
+        ram[0x10] = 1;
+        detector.end_frame(&ram, None, &ppu);
+        assert_eq!(detector.unchanged_frames(), 0);
+        assert!(!detector.is_idle());
+    }
+
+    #[test]
+    fn test_ignored_ram_doesnt_count() {
+        let ppu = Ppu::new();
+        let mut ram = [0u8; 2048];
+        let mut detector = IdleDetector::new(2).with_ignored_ram(0x00..0x02);
+        for frame in 0..3u8 {
+            ram[0x01] = frame;
+            detector.end_frame(&ram, None, &ppu);
+        }
+        assert!(detector.is_idle());
+        detector.reset();
+        assert_eq!(detector.unchanged_frames(), 0);
+    }
+}
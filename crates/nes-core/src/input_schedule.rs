@@ -0,0 +1,412 @@
+//! Scripted controller input from JSON or CSV schedules
+//!
+//! A lighter alternative to FM2 movies for tests and CI: only the frames where
+//! something is pressed are listed. Each entry presses buttons on a port at a
+//! frame and holds them for a number of frames (default 1):
+//!
+//! ```text
+//! [
+//!   {"frame": 120, "buttons": "start"},
+//!   {"frame": 200, "buttons": ["a", "right"], "hold": 30, "port": 2}
+//! ]
+//! ```
+//!
+//! The same schedule as CSV (the header line is optional, buttons are joined with `+`):
+//!
+//! ```text
+//! frame,buttons,hold,port
+//! 120,start
+//! 200,a+right,30,2
+//! ```
+//!
+//! Frames count from 0 at power-on, matching `NesSystem::frame_count` before the
+//! frame runs. Entries that overlap on a port combine their buttons.
+
+use crate::controller::Buttons;
+use crate::system::NesSystem;
+use std::fmt;
+
+/// Buttons pressed on one port for a run of frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    /// First frame the buttons are held
+    pub frame: u64,
+    /// Number of frames the buttons are held
+    pub hold: u64,
+    /// Controller port (0 or 1)
+    pub port: usize,
+    pub buttons: Buttons,
+}
+
+impl InputEvent {
+    /// Check if the event holds its buttons during a frame
+    pub fn is_active(&self, frame: u64) -> bool {
+        frame >= self.frame && frame - self.frame < self.hold
+    }
+}
+
+/// A parsed input schedule
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputSchedule {
+    pub events: Vec<InputEvent>,
+}
+
+impl InputSchedule {
+    /// Parse a schedule, detecting JSON (starts with `[`) or CSV
+    pub fn parse(text: &str) -> Result<Self, ScheduleError> {
+        if text.trim_start().starts_with('[') {
+            Self::parse_json(text)
+        } else {
+            Self::parse_csv(text)
+        }
+    }
+
+    /// Parse a JSON array of `{"frame", "buttons", "hold"?, "port"?}` objects
+    pub fn parse_json(text: &str) -> Result<Self, ScheduleError> {
+        let mut parser = JsonParser { text, pos: 0 };
+        let value = parser.parse_document()?;
+        let JsonValue::Array(entries) = value else {
+            return Err(ScheduleError::InvalidEntry { entry: 0, reason: "expected an array of entries" });
+        };
+
+        let mut schedule = InputSchedule::default();
+        for (index, entry) in entries.iter().enumerate() {
+            let entry_number = index + 1;
+            let invalid = |reason| ScheduleError::InvalidEntry { entry: entry_number, reason };
+            let JsonValue::Object(fields) = entry else {
+                return Err(invalid("expected an object"));
+            };
+            let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+            let number = |name: &str, default: Option<u64>| match field(name) {
+                Some(JsonValue::Number(n)) => Ok(*n),
+                Some(_) => Err(invalid("frame, hold and port must be non-negative integers")),
+                None => default.ok_or(invalid("missing frame")),
+            };
+
+            let buttons = match field("buttons") {
+                Some(JsonValue::String(names)) => parse_buttons(names)?,
+                Some(JsonValue::Array(names)) => {
+                    let mut bits = 0;
+                    for name in names {
+                        let JsonValue::String(name) = name else {
+                            return Err(invalid("button names must be strings"));
+                        };
+                        bits |= parse_buttons(name)?.bits();
+                    }
+                    Buttons::new(bits)
+                }
+                Some(_) => return Err(invalid("buttons must be a string or an array")),
+                None => return Err(invalid("missing buttons")),
+            };
+            let event = make_event(number("frame", None)?, buttons, number("hold", Some(1))?, number("port", Some(1))?)
+                .map_err(invalid)?;
+            schedule.events.push(event);
+        }
+        Ok(schedule)
+    }
+
+    /// Parse `frame,buttons[,hold[,port]]` lines (blank lines and `#` comments are ignored)
+    pub fn parse_csv(text: &str) -> Result<Self, ScheduleError> {
+        let mut schedule = InputSchedule::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let invalid = |reason| ScheduleError::InvalidLine { line: line_number, reason };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields[0].eq_ignore_ascii_case("frame") {
+                continue;
+            }
+            if fields.len() < 2 || fields.len() > 4 {
+                return Err(invalid("expected frame,buttons[,hold[,port]]"));
+            }
+            let number = |index: usize, default: u64| match fields.get(index) {
+                Some(text) if !text.is_empty() => text.parse::<u64>().map_err(|_| invalid("invalid number")),
+                _ => Ok(default),
+            };
+            let frame = fields[0].parse::<u64>().map_err(|_| invalid("invalid frame number"))?;
+            let event = make_event(frame, parse_buttons(fields[1])?, number(2, 1)?, number(3, 1)?).map_err(invalid)?;
+            schedule.events.push(event);
+        }
+        Ok(schedule)
+    }
+
+    /// Buttons held on each port during a frame
+    pub fn buttons_at(&self, frame: u64) -> [Buttons; 2] {
+        let mut ports = [0u8; 2];
+        for event in self.events.iter().filter(|e| e.is_active(frame)) {
+            ports[event.port] |= event.buttons.bits();
+        }
+        ports.map(Buttons::new)
+    }
+
+    /// Set the controllers for the frame the system is about to run
+    pub fn apply(&self, system: &mut NesSystem) {
+        let frame = system.frame_count();
+        for (port, buttons) in self.buttons_at(frame).into_iter().enumerate() {
+            system.set_buttons(port, buttons);
+        }
+    }
+
+    /// First frame after the last scheduled input
+    pub fn end_frame(&self) -> u64 {
+        self.events.iter().map(|e| e.frame.saturating_add(e.hold)).max().unwrap_or(0)
+    }
+}
+
+/// Validate an entry's fields; ports are 1-based in schedules
+fn make_event(frame: u64, buttons: Buttons, hold: u64, port: u64) -> Result<InputEvent, &'static str> {
+    if !(1..=2).contains(&port) {
+        return Err("port must be 1 or 2");
+    }
+    if hold == 0 {
+        return Err("hold must be at least 1 frame");
+    }
+    Ok(InputEvent {
+        frame,
+        hold,
+        port: port as usize - 1,
+        buttons,
+    })
+}
+
+/// Parse button names joined with `+` (an empty string presses nothing)
+fn parse_buttons(names: &str) -> Result<Buttons, ScheduleError> {
+    let mut bits = 0;
+    for name in names.split('+').map(str::trim).filter(|n| !n.is_empty()) {
+        bits |= Buttons::from_name(name).ok_or_else(|| ScheduleError::UnknownButton(name.to_string()))?;
+    }
+    Ok(Buttons::new(bits))
+}
+
+/// The subset of JSON values schedules use
+#[derive(Debug)]
+enum JsonValue {
+    /// `true`, `false` or `null` (no schedule field uses them)
+    Literal,
+    /// Non-negative integer
+    Number(u64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// Minimal recursive descent JSON reader
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, reason: &'static str) -> ScheduleError {
+        ScheduleError::InvalidJson { offset: self.pos, reason }
+    }
+
+    fn parse_document(&mut self) -> Result<JsonValue, ScheduleError> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.pos < self.text.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8, reason: &'static str) -> Result<(), ScheduleError> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(reason));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, ScheduleError> {
+        match self.peek() {
+            Some(b'[') => self.parse_array(),
+            Some(b'{') => self.parse_object(),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => {
+                for word in ["true", "false", "null"] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(JsonValue::Literal);
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, ScheduleError> {
+        self.expect(b'[', "expected '['")?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, ScheduleError> {
+        self.expect(b'{', "expected '{'")?;
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(fields));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':', "expected ':'")?;
+            fields.push((key, self.parse_value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, ScheduleError> {
+        self.expect(b'"', "expected '\"'")?;
+        let mut out = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(out);
+                }
+                '\\' => match chars.next() {
+                    Some((_, escaped @ ('"' | '\\' | '/'))) => out.push(escaped),
+                    Some((_, 'n')) => out.push('\n'),
+                    Some((_, 't')) => out.push('\t'),
+                    _ => {
+                        self.pos += offset;
+                        return Err(self.error("unsupported escape"));
+                    }
+                },
+                c => out.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, ScheduleError> {
+        let digits = self.text[self.pos..].bytes().take_while(u8::is_ascii_digit).count();
+        let number = self.text[self.pos..self.pos + digits]
+            .parse()
+            .map_err(|_| self.error("number out of range"))?;
+        self.pos += digits;
+        if matches!(self.text.as_bytes().get(self.pos), Some(b'.' | b'e' | b'E')) {
+            return Err(self.error("numbers must be integers"));
+        }
+        Ok(JsonValue::Number(number))
+    }
+}
+
+/// Input schedule parsing error types
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScheduleError {
+    /// The JSON text is malformed (byte offset of the problem)
+    InvalidJson { offset: usize, reason: &'static str },
+    /// A JSON entry (1-based) has missing or invalid fields
+    InvalidEntry { entry: usize, reason: &'static str },
+    /// A CSV line could not be parsed
+    InvalidLine { line: usize, reason: &'static str },
+    /// Unknown button name
+    UnknownButton(String),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::InvalidJson { offset, reason } => write!(f, "Invalid JSON at byte {}: {}", offset, reason),
+            ScheduleError::InvalidEntry { entry, reason } => write!(f, "Invalid input entry {}: {}", entry, reason),
+            ScheduleError::InvalidLine { line, reason } => write!(f, "Invalid input line {}: {}", line, reason),
+            ScheduleError::UnknownButton(name) => write!(f, "Unknown button: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_and_csv_agree() {
+        let json = r#"[
+            {"frame": 120, "buttons": "start"},
+            {"frame": 200, "buttons": ["A", "right"], "hold": 30, "port": 2, "note": null}
+        ]"#;
+        let csv = "# menu\nframe,buttons,hold,port\n120,start\n200,a+right,30,2\n";
+        let schedule = InputSchedule::parse(json).unwrap();
+        assert_eq!(InputSchedule::parse(csv).unwrap(), schedule);
+
+        assert_eq!(schedule.buttons_at(119), [Buttons::default(); 2]);
+        assert_eq!(schedule.buttons_at(120)[0].bits(), Buttons::START);
+        assert_eq!(schedule.buttons_at(121)[0].bits(), 0);
+        assert_eq!(schedule.buttons_at(229)[1].bits(), Buttons::A | Buttons::RIGHT);
+        assert_eq!(schedule.buttons_at(230)[1].bits(), 0);
+        assert_eq!(schedule.end_frame(), 230);
+    }
+
+    #[test]
+    fn test_schedule_errors() {
+        assert_eq!(
+            InputSchedule::parse("10,jump\n"),
+            Err(ScheduleError::UnknownButton("jump".into()))
+        );
+        assert!(matches!(
+            InputSchedule::parse("frame,buttons\n10\n"),
+            Err(ScheduleError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            InputSchedule::parse(r#"[{"frame": 1, "buttons": "a", "port": 3}]"#),
+            Err(ScheduleError::InvalidEntry { entry: 1, reason: "port must be 1 or 2" })
+        ));
+        assert!(matches!(
+            InputSchedule::parse(r#"[{"frame": 1.5, "buttons": "a"}]"#),
+            Err(ScheduleError::InvalidJson { offset: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_uses_frame_count() {
+        let mut system = NesSystem::new();
+        let schedule = InputSchedule::parse("0,select\n").unwrap();
+        schedule.apply(&mut system);
+        assert_eq!(system.buttons(0).bits(), Buttons::SELECT);
+    }
+}
@@ -0,0 +1,172 @@
+//! Pull-based stream of executed instructions
+//!
+//! `NesSystem::instructions` returns an iterator that runs the system one
+//! instruction per `next()` and yields what ran: its address, bytes, the
+//! cycles it took and the registers it left behind. Coverage tools, fuzzers
+//! and dataset builders can consume execution as a plain iterator
+//! (`take`, `filter`, `zip` with their own state) instead of registering
+//! callbacks or parsing trace text.
+//!
+//! The iterator drives the system exactly like `run_frames`: frames end when
+//! the PPU finishes one, so frame counters, crash detection and achievements
+//! keep working. NMI and
+//! IRQ entry sequences are not instructions and are not yielded; `cycle`
+//! shows the gap they leave.
+
+use crate::cpu::CpuError;
+use crate::system::NesSystem;
+
+/// One executed instruction and the CPU state after it ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExecutedInstruction {
+    /// Address of the opcode
+    pub pc: u16,
+    /// Opcode and operand bytes (only the first `len` are meaningful)
+    pub bytes: [u8; 3],
+    /// Instruction length in bytes
+    pub len: u8,
+    /// Total CPU cycles before the instruction
+    pub cycle: u64,
+    /// CPU cycles the instruction took, including DMA stalls and page crossings
+    pub cycles: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    /// Program counter after the instruction (the next instruction's address)
+    pub next_pc: u16,
+}
+
+impl ExecutedInstruction {
+    /// Opcode and operand bytes, without the unused padding
+    pub fn instruction_bytes(&self) -> &[u8] {
+        &self.bytes[..(self.len as usize).min(3)]
+    }
+}
+
+/// Iterator over the instructions the system executes, created by `NesSystem::instructions`
+///
+/// Yields `Err` once if the CPU hits an opcode it can't execute, and ends
+/// after that or when the CPU stops.
+pub struct Instructions<'a> {
+    system: &'a mut NesSystem,
+    finished: bool,
+}
+
+impl<'a> Instructions<'a> {
+    pub(crate) fn new(system: &'a mut NesSystem) -> Self {
+        Self {
+            system,
+            finished: false,
+        }
+    }
+
+    /// The system being run (to inspect memory or PPU state between instructions)
+    pub fn system(&self) -> &NesSystem {
+        self.system
+    }
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Result<ExecutedInstruction, CpuError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let system = &mut *self.system;
+            let interrupt = system.cpu().interrupt_ready();
+            let pc = system.cpu().registers().pc;
+            let bytes = [0, 1, 2].map(|offset| system.peek_memory(pc.wrapping_add(offset)));
+            let len = system.cpu().decode_opcode(bytes[0]).map_or(1, |opcode| system.cpu().instruction_length(opcode));
+            let cycle = system.cpu().total_cycles();
+
+            let result = system.step();
+            if system.take_frame_complete() {
+                system.end_frame();
+            }
+
+            match result {
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+                Ok(false) => self.finished = true,
+                Ok(true) if interrupt => {}
+                Ok(true) => {
+                    let cpu = system.cpu();
+                    let regs = cpu.registers();
+                    return Some(Ok(ExecutedInstruction {
+                        pc,
+                        bytes,
+                        len,
+                        cycle,
+                        cycles: (cpu.total_cycles() - cycle) as u8,
+                        a: regs.a,
+                        x: regs.x,
+                        y: regs.y,
+                        p: cpu.p_register(),
+                        sp: regs.sp,
+                        next_pc: regs.pc,
+                    }));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+
+    /// System at $8000 running `code`, with the rest of PRG filled with NOPs
+    fn system_with(code: &[u8]) -> NesSystem {
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..code.len()].copy_from_slice(code);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system
+    }
+
+    #[test]
+    fn test_yields_instructions_with_registers_after() {
+        // LDX #$05 / DEX / STX $10 / JMP $8000
+        let mut system = system_with(&[0xA2, 0x05, 0xCA, 0x86, 0x10, 0x4C, 0x00, 0x80]);
+        let executed: Vec<ExecutedInstruction> = system.instructions().take(5).map(Result::unwrap).collect();
+
+        let pcs: Vec<u16> = executed.iter().map(|i| i.pc).collect();
+        assert_eq!(pcs, [0x8000, 0x8002, 0x8003, 0x8005, 0x8000]);
+        assert_eq!(executed[0].instruction_bytes(), [0xA2, 0x05]);
+        assert_eq!((executed[0].x, executed[1].x), (5, 4));
+        assert_eq!(executed[2].instruction_bytes(), [0x86, 0x10]);
+        assert_eq!(executed[3].next_pc, 0x8000);
+        assert_eq!(executed.iter().map(|i| i.cycles).collect::<Vec<_>>(), [2, 2, 3, 3, 2]);
+        // Cycles are contiguous when no interrupt intervenes
+        assert!(executed.windows(2).all(|pair| pair[0].cycle + pair[0].cycles as u64 == pair[1].cycle));
+        assert_eq!(system.read_memory(0x0010), 4);
+    }
+
+    #[test]
+    fn test_ends_frames_and_stops_on_jam() {
+        let mut system = system_with(&[0x4C, 0x00, 0x80]);
+        let mut instructions = system.instructions();
+        let mut count = 0;
+        while instructions.system().frame_count() < 2 {
+            instructions.next().unwrap().unwrap();
+            count += 1;
+        }
+        // Two frames of ~29781 cycles in 3-cycle jumps
+        assert!((19_850..=19_860).contains(&count), "{} instructions", count);
+
+        // A KIL opcode is reported once, then the stream ends
+        system.write_memory(0x0000, 0x02);
+        system.cpu_mut().registers_mut().pc = 0x0000;
+        let mut instructions = system.instructions();
+        assert!(matches!(instructions.next(), Some(Err(_))));
+        assert!(instructions.next().is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//! IRQ line sources for debuggers
+//!
+//! The CPU's IRQ input is a shared open-collector line: any device can hold
+//! it low, and it stays asserted until every one of them lets go. An
+//! `InterruptController` records which sources are holding it; the system
+//! builds one before each interrupt poll and drives the CPU's line from it.
+//! When an IRQ looks stuck, `NesSystem::interrupts().pending()` names the
+//! device holding the line, and `NesSystem::acknowledge_irq` releases it the
+//! way the device's acknowledge write would.
+//!
+//! Three sources are emulated: the APU frame counter, the DMC at the end of
+//! a sample, and mapper IRQs (such as the NES-EVENT timer and the Bandai FCG
+//! counter).
+
+use std::fmt;
+
+/// A device that can hold the IRQ line low
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IrqSource {
+    /// The APU frame counter's 4-step sequence (acknowledged by reading $4015)
+    ApuFrame,
+    /// The DMC at the end of a sample (acknowledged by writing $4015)
+    Dmc,
+    /// The cartridge board's IRQ counter or timer
+    Mapper,
+}
+
+impl IrqSource {
+    /// All sources, in reporting order
+    pub const ALL: [IrqSource; 3] = [IrqSource::ApuFrame, IrqSource::Dmc, IrqSource::Mapper];
+
+    /// Short lowercase name, as used in the debug state JSON
+    pub fn name(self) -> &'static str {
+        match self {
+            IrqSource::ApuFrame => "apu_frame",
+            IrqSource::Dmc => "dmc",
+            IrqSource::Mapper => "mapper",
+        }
+    }
+
+    /// Look a source up by `name`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl fmt::Display for IrqSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Sources holding the IRQ line at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InterruptController {
+    /// One bit per `IrqSource`
+    asserted: u8,
+}
+
+impl InterruptController {
+    /// Create a controller with the line released
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record whether a source is holding the line
+    pub fn set(&mut self, source: IrqSource, asserted: bool) {
+        if asserted {
+            self.asserted |= source.bit();
+        } else {
+            self.asserted &= !source.bit();
+        }
+    }
+
+    /// Check if a source is holding the line
+    pub fn is_pending(&self, source: IrqSource) -> bool {
+        self.asserted & source.bit() != 0
+    }
+
+    /// Get the sources holding the line
+    pub fn pending(&self) -> Vec<IrqSource> {
+        IrqSource::ALL.into_iter().filter(|&source| self.is_pending(source)).collect()
+    }
+
+    /// Check if any source is holding the line (the CPU's IRQ input)
+    pub fn line(&self) -> bool {
+        self.asserted != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+    use crate::mapper::MapperState;
+    use crate::system::NesSystem;
+
+    #[test]
+    fn test_pending_and_acknowledge() {
+        let mut system = NesSystem::new();
+        // Bandai FCG with the I flag set, so the IRQ stays pending
+        system.load_simple_cartridge(
+            SimpleCartridge::new(vec![0xEA; 32768], vec![0x00; 8192]).with_mapper(MapperState::for_number(16)),
+        );
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        assert!(system.interrupts().pending().is_empty());
+        assert!(!system.acknowledge_irq(IrqSource::Mapper));
+
+        // Enabling the counter at 0 fires on the next clock
+        system.write_memory(0x800A, 0x01);
+        system.step().unwrap();
+        let interrupts = system.interrupts();
+        assert_eq!(interrupts.pending(), [IrqSource::Mapper]);
+        assert!(interrupts.line());
+
+        assert!(system.acknowledge_irq(IrqSource::Mapper));
+        assert!(!system.interrupts().line());
+        assert_eq!(IrqSource::from_name("mapper"), Some(IrqSource::Mapper));
+        assert_eq!(IrqSource::from_name("apu"), None);
+    }
+}
@@ -0,0 +1,255 @@
+//! Memory-mapped I/O regions registered on the bus
+//!
+//! Hardware outside the fixed NES memory map (mapper registers in unusual
+//! places such as MMC5's $5000-$5FFF, the FDS drive at $4020-$40BF,
+//! expansion port devices) implements `IoHandler` and is mapped onto an
+//! address range with `Bus::map` or `NesSystem::map_io`:
+//!
+//! ```
+//! use nes_core::io_map::IoHandler;
+//! use nes_core::NesSystem;
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Multiplier([u8; 2]);
+//!
+//! impl IoHandler for Multiplier {
+//!     fn read(&mut self, address: u16) -> Option<u8> {
+//!         (address == 0x5205).then(|| self.0[0].wrapping_mul(self.0[1]))
+//!     }
+//!
+//!     fn write(&mut self, address: u16, value: u8) {
+//!         self.0[(address & 1) as usize] = value;
+//!     }
+//! }
+//!
+//! let mut system = NesSystem::new();
+//! system.map_io(0x5204..=0x5205, Multiplier::default())?;
+//! system.write_memory(0x5204, 6);
+//! system.write_memory(0x5205, 7);
+//! assert_eq!(system.read_memory(0x5205), 42);
+//! # Ok::<(), nes_core::io_map::IoMapError>(())
+//! ```
+//!
+//! Mapped regions take priority over the built-in map. A write in a region
+//! goes to its handler only; a read that the handler answers with `None`
+//! falls through to whatever the built-in map has there (usually open bus).
+//! Regions can't overlap each other.
+//!
+//! The bus checks a 256-entry page bitmap before looking at the regions, so
+//! accesses to pages without a region (RAM, PRG ROM, most registers) cost
+//! one bit test. `benches/bus_dispatch.rs` keeps an eye on that.
+//!
+//! Handlers are not part of savestates: the frontend that mapped them
+//! restores its own devices.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Device registers answering a range of CPU addresses
+pub trait IoHandler: IoHandlerClone + fmt::Debug + Send {
+    /// Read a register; None leaves the address to the built-in map
+    fn read(&mut self, address: u16) -> Option<u8>;
+
+    /// Read a register without side effects (debuggers, `Bus::peek`)
+    fn peek(&self, address: u16) -> Option<u8> {
+        let _ = address;
+        None
+    }
+
+    /// Write a register
+    fn write(&mut self, address: u16, value: u8);
+}
+
+/// Boxed cloning for handlers, so the bus stays `Clone` (implemented for every `Clone` handler)
+pub trait IoHandlerClone {
+    /// Clone the handler into a box
+    fn clone_box(&self) -> Box<dyn IoHandler>;
+}
+
+impl<T: IoHandler + Clone + 'static> IoHandlerClone for T {
+    fn clone_box(&self) -> Box<dyn IoHandler> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn IoHandler> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Handle to a mapped region, used to unmap it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IoRegionId(u32);
+
+/// Error mapping a region
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IoMapError {
+    /// The range contains no addresses
+    EmptyRange,
+    /// The range overlaps a region that is already mapped
+    Overlap(RangeInclusive<u16>),
+}
+
+impl fmt::Display for IoMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoMapError::EmptyRange => write!(f, "Empty I/O range"),
+            IoMapError::Overlap(range) => {
+                write!(f, "I/O range overlaps ${:04X}-${:04X}", range.start(), range.end())
+            }
+        }
+    }
+}
+
+impl std::error::Error for IoMapError {}
+
+#[derive(Debug, Clone)]
+struct IoRegion {
+    id: IoRegionId,
+    range: RangeInclusive<u16>,
+    handler: Box<dyn IoHandler>,
+}
+
+/// Registered I/O regions and the page bitmap that guards them
+#[derive(Debug, Clone, Default)]
+pub struct IoMap {
+    regions: Vec<IoRegion>,
+    /// One bit per 256-byte page that has at least one region
+    pages: [u64; 4],
+    next_id: u32,
+}
+
+impl IoMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a handler onto an address range
+    pub fn map(&mut self, range: RangeInclusive<u16>, handler: Box<dyn IoHandler>) -> Result<IoRegionId, IoMapError> {
+        if range.is_empty() {
+            return Err(IoMapError::EmptyRange);
+        }
+        if let Some(region) = self
+            .regions
+            .iter()
+            .find(|region| region.range.start() <= range.end() && range.start() <= region.range.end())
+        {
+            return Err(IoMapError::Overlap(region.range.clone()));
+        }
+        let id = IoRegionId(self.next_id);
+        self.next_id += 1;
+        self.regions.push(IoRegion { id, range, handler });
+        self.rebuild_pages();
+        Ok(id)
+    }
+
+    /// Remove a region, returning its handler
+    pub fn unmap(&mut self, id: IoRegionId) -> Option<Box<dyn IoHandler>> {
+        let index = self.regions.iter().position(|region| region.id == id)?;
+        let region = self.regions.remove(index);
+        self.rebuild_pages();
+        Some(region.handler)
+    }
+
+    /// Get the mapped regions and their ranges
+    pub fn regions(&self) -> impl Iterator<Item = (IoRegionId, RangeInclusive<u16>)> + '_ {
+        self.regions.iter().map(|region| (region.id, region.range.clone()))
+    }
+
+    /// Check if no regions are mapped
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Check if the address's page has a region (the fast path for every access)
+    #[inline]
+    pub fn covers_page(&self, address: u16) -> bool {
+        let page = address >> 8;
+        self.pages[(page >> 6) as usize] & (1 << (page & 63)) != 0
+    }
+
+    /// Read from the region containing the address; None if there is none or it declined
+    pub fn read(&mut self, address: u16) -> Option<u8> {
+        self.region_mut(address)?.handler.read(address)
+    }
+
+    /// Read from the region containing the address without side effects
+    pub fn peek(&self, address: u16) -> Option<u8> {
+        self.regions.iter().find(|region| region.range.contains(&address))?.handler.peek(address)
+    }
+
+    /// Write to the region containing the address; false if no region has it
+    pub fn write(&mut self, address: u16, value: u8) -> bool {
+        match self.region_mut(address) {
+            Some(region) => {
+                region.handler.write(address, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn region_mut(&mut self, address: u16) -> Option<&mut IoRegion> {
+        self.regions.iter_mut().find(|region| region.range.contains(&address))
+    }
+
+    fn rebuild_pages(&mut self) {
+        self.pages = [0; 4];
+        for region in &self.regions {
+            for page in region.range.start() >> 8..=region.range.end() >> 8 {
+                self.pages[(page >> 6) as usize] |= 1 << (page & 63);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Default)]
+    struct Latch(u8);
+
+    impl IoHandler for Latch {
+        fn read(&mut self, _address: u16) -> Option<u8> {
+            Some(self.0)
+        }
+
+        fn write(&mut self, _address: u16, value: u8) {
+            self.0 = value;
+        }
+    }
+
+    #[test]
+    fn test_map_and_unmap() {
+        let mut map = IoMap::new();
+        let id = map.map(0x4020..=0x40BF, Box::new(Latch(7))).unwrap();
+        assert!(map.covers_page(0x4000) && map.covers_page(0x40BF));
+        assert!(!map.covers_page(0x4100));
+        // Same page, outside the range
+        assert_eq!(map.read(0x4010), None);
+        assert_eq!(map.read(0x4020), Some(7));
+        assert!(map.write(0x40BF, 9));
+        assert_eq!(map.read(0x4050), Some(9));
+
+        assert!(map.unmap(id).is_some());
+        assert!(map.is_empty());
+        assert!(!map.covers_page(0x4020));
+        assert!(!map.write(0x4020, 1));
+    }
+
+    #[test]
+    fn test_overlapping_and_empty_ranges_are_rejected() {
+        let mut map = IoMap::new();
+        map.map(0x5000..=0x5FFF, Box::new(Latch(0))).unwrap();
+        assert_eq!(map.map(0x5FFF..=0x6000, Box::new(Latch(0))), Err(IoMapError::Overlap(0x5000..=0x5FFF)));
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 0x6000..=0x5FFF;
+        assert_eq!(map.map(empty, Box::new(Latch(0))), Err(IoMapError::EmptyRange));
+        assert!(map.map(0x4020..=0x4FFF, Box::new(Latch(0))).is_ok());
+        assert_eq!(map.regions().count(), 2);
+    }
+}
@@ -1,20 +1,169 @@
 //! NES Core - Pure Rust NES emulator library
 //!
 //! This crate provides the core emulation logic for a Nintendo Entertainment System (NES).
-//! It is designed to be `no_std`-friendly and contains no WASM or web dependencies.
+//! It depends only on `std` and contains no WASM or web dependencies.
+//!
+//! ```no_run
+//! use nes_core::{Buttons, NesSystem};
+//!
+//! let rom = std::fs::read("game.nes")?;
+//! let mut system = NesSystem::new();
+//! system.load_rom(&rom)?;
+//! system.reset();
+//! system.initialize_ppu();
+//! system.set_buttons(0, Buttons::new(Buttons::START));
+//! system.run_frames(1)?;
+//! let frame = system.ppu_mut().snapshot_frame();
+//! assert_eq!(frame.pixels().len(), nes_core::FRAME_WIDTH * nes_core::FRAME_HEIGHT * 3);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # API stability
+//!
+//! Releases follow semver with 0.x rules: a minor bump (0.1 to 0.2) may break
+//! the API, a patch bump may not. The stable surface is:
+//! - the items re-exported at the crate root (`NesSystem`, `Cartridge`,
+//!   `FrameSnapshot`, `Buttons`, `DpadPolicy`, `Zapper`, `Vaus`, `AccuracyProfile`, `Region`,
+//!   `RamInit`, `CpuRegisters`, `StatusFlags`, the error types);
+//! - the frontend modules listed in the documentation (sinks, frame skipping,
+//!   hotkeys, health, movies, sample formats and the other helpers).
+//!
+//! The emulator internals (`cpu`, `bus`, `apu`, `mapper`, `eeprom`) are
+//! private to the crate. Debuggers and tests go through `NesSystem` instead:
+//! `cpu_registers`/`set_cpu_registers` and `cpu_cycles` for the CPU, and
+//! `debug_state::DebugState` for mapper bank registers and APU channels.
+//! Error enums and reports that are expected to grow are `#[non_exhaustive]`.
 
 #![forbid(unsafe_code)]
 
 /// CPU module containing the 2A03 (6502 variant) implementation
-pub mod cpu;
+pub(crate) mod cpu;
 /// Memory bus and mapping
-pub mod bus;
+pub(crate) mod bus;
+/// Memory-mapped I/O regions for devices outside the fixed memory map
+pub mod io_map;
+/// OAM and DMC DMA timing and the DPCM controller read glitch
+pub mod dma;
+/// Which devices hold the IRQ line, for debuggers
+pub mod interrupt;
+/// Optional hardware behaviours (accuracy profile)
+pub mod accuracy;
+/// Console regions (NTSC, PAL, Dendy) and their timing
+pub mod region;
 /// PPU (Picture Processing Unit) implementation
 pub mod ppu;
-/// APU (Audio Processing Unit) stub with timing hooks
-pub mod apu;
+/// APU (Audio Processing Unit) channels, frame sequencer and resampling
+pub(crate) mod apu;
+/// Standard controller input
+pub mod controller;
+/// Zapper light gun with scanline-timed light sensing
+pub mod zapper;
+/// Arkanoid (Vaus) paddle controller
+pub mod vaus;
 /// Cartridge and mapper support
 pub mod cartridge;
+/// Mapper bank-switching state
+pub(crate) mod mapper;
+/// Cartridge interface shared with the legacy desktop app
+pub mod mapper_interface;
+/// Supported mappers and their support levels, for compatibility hints
+pub mod mappers;
+/// Serial EEPROMs for board saves
+pub(crate) mod eeprom;
 /// Integration module for complete NES system
 pub mod system;
+/// Background ROM loading and library scanning
+pub mod loader;
+/// FM2 movie parsing for input playback
+pub mod movie;
+/// Scripted input schedules from JSON or CSV
+pub mod input_schedule;
+/// RAM conditions for achievements and memory watches
+pub mod condition;
+/// Offline achievement packs evaluated every frame
+pub mod achievements;
+/// Hotkey bindings shared across frontends
+pub mod hotkeys;
+/// Click and pop detection for generated audio
+pub mod audio_check;
+/// Heuristic detection of crashed games
+pub mod crash_detect;
+/// Frame skipping for slow hosts
+pub mod frameskip;
+/// Run-length encoded frame diffs for remote viewing
+pub mod framediff;
+/// Health summary for frontend status indicators
+pub mod health;
+/// Frozen game state detection for headless runs
+pub mod idle;
+/// Framebuffer goals (template matching, custom recognizers) for automated playtesting
+pub mod frame_goal;
+/// Per-subsystem timing metrics
+pub mod metrics;
+/// Integer-only APU channel mixer
+pub mod mixer;
+/// Scripted APU register tests with golden output
+pub mod apu_script;
+/// On-screen display messages for frontends
+pub mod osd;
+/// Polled event queue for frontends without callbacks
+pub mod events;
+/// Canvas sizing presets (aspect ratio, overscan)
+pub mod display;
+/// Animated GIF clip recording
+pub mod gif;
+/// Per-frame state hash and input sidecars that make recordings reproducible
+pub mod frame_hashes;
+/// Pairs recorded frames with their exact share of audio samples
+pub mod av_mux;
+/// Tile and attribute grid overlays for background debugging
+pub mod grid_overlay;
+/// Palette viewer grid for debugging and live palette editing
+pub mod palette_view;
+/// Per-game playtime and notes
+pub mod playstats;
+/// Cached title screen states that skip game intros
+pub mod fast_boot;
+/// ROM database with per-game overrides
+pub mod romdb;
+/// Portable bundle of all user configuration for export and import
+pub mod settings;
+/// Audio sample formats and conversion helpers
+pub mod sample_format;
+/// Per-scanline sprite evaluation analysis
+pub mod sprite_eval;
+/// Pluggable video and audio outputs for frontends
+pub mod sink;
+/// Binary state serialization helpers for savestates
+pub mod state;
+/// Game Genie, Pro Action Replay and Pro Action Rocky cheat codes
+pub mod cheats;
+/// Versioned savestate sections and migrations between versions
+pub mod savestate;
+/// Fast in-memory snapshots with dirty-page copies for run-ahead and rollback
+pub mod snapshot;
+/// Field-by-field comparison of two system states
+pub mod state_diff;
+/// Register snapshots for debugger UIs
+pub mod debug_state;
+/// Deterministic, seedable random numbers
+pub mod rng;
+/// Instruction trace ring buffer
+pub mod trace;
+/// Pull-based stream of executed instructions for analysis tools
+pub mod instructions;
+/// Comparison against reference emulator trace logs (nestest.log format)
+pub mod reference_log;
 
+pub use accuracy::AccuracyProfile;
+pub use cartridge::{Cartridge, CartridgeError};
+pub use controller::{Buttons, DpadPolicy};
+pub use bus::RamInit;
+pub use cpu::{CpuError, CpuRegisters, StatusFlags};
+pub use health::{HealthReport, HealthStatus};
+pub use ppu::{FrameSnapshot, FRAME_HEIGHT, FRAME_WIDTH};
+pub use region::Region;
+pub use state::StateError;
+pub use system::NesSystem;
+pub use vaus::Vaus;
+pub use zapper::Zapper;
@@ -0,0 +1,251 @@
+//! Background ROM loading and library scanning
+//!
+//! Reading and parsing a ROM (or walking a large ROM directory) can take long
+//! enough to stall a UI thread. These helpers do the work on a worker thread:
+//! - `load_rom_async` returns a `PendingRom` handle that can be polled each frame
+//! - `load_rom_with_callback` invokes a callback from the worker when done
+//! - `scan_roms` walks a directory tree, reading only iNES headers
+//!
+//! The parsed cartridge is applied on the caller's thread with
+//! `NesSystem::load_cartridge`, which does no I/O.
+
+use crate::cartridge::{Cartridge, CartridgeError, InesHeader, HEADER_SIZE};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// A ROM read from disk and parsed off the calling thread
+#[derive(Debug, Clone)]
+pub struct LoadedRom {
+    /// Path the ROM was read from
+    pub path: PathBuf,
+    /// Raw file contents (including the iNES header)
+    pub data: Vec<u8>,
+    /// Parsed cartridge
+    pub cartridge: Cartridge,
+}
+
+/// Handle to a ROM being loaded in the background
+#[derive(Debug)]
+pub struct PendingRom {
+    receiver: Receiver<Result<LoadedRom, LoadError>>,
+}
+
+impl PendingRom {
+    /// Take the result if loading has finished, without blocking
+    pub fn try_take(&self) -> Option<Result<LoadedRom, LoadError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(LoadError::WorkerFailed)),
+        }
+    }
+
+    /// Block until loading has finished
+    pub fn wait(self) -> Result<LoadedRom, LoadError> {
+        self.receiver.recv().unwrap_or(Err(LoadError::WorkerFailed))
+    }
+}
+
+/// Start loading a ROM file on a worker thread
+pub fn load_rom_async(path: impl Into<PathBuf>) -> PendingRom {
+    let path = path.into();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(load_rom_file(path));
+    });
+    PendingRom { receiver }
+}
+
+/// Load a ROM file on a worker thread and pass the result to `callback`
+///
+/// The callback runs on the worker thread.
+pub fn load_rom_with_callback<F>(path: impl Into<PathBuf>, callback: F)
+where
+    F: FnOnce(Result<LoadedRom, LoadError>) + Send + 'static,
+{
+    let path = path.into();
+    thread::spawn(move || callback(load_rom_file(path)));
+}
+
+/// Read and parse a ROM file on the current thread
+pub fn load_rom_file(path: PathBuf) -> Result<LoadedRom, LoadError> {
+    let data = fs::read(&path).map_err(|e| LoadError::Io(format!("{}: {}", path.display(), e)))?;
+    let cartridge = Cartridge::from_rom(&data).map_err(LoadError::Cartridge)?;
+    Ok(LoadedRom {
+        path,
+        data,
+        cartridge,
+    })
+}
+
+/// A ROM found by `scan_roms`
+#[derive(Debug, Clone)]
+pub struct RomScanEntry {
+    /// Path to the ROM file
+    pub path: PathBuf,
+    /// File size in bytes
+    pub size: u64,
+    /// Parsed iNES header, or why it could not be read
+    pub header: Result<InesHeader, LoadError>,
+}
+
+/// Handle to a directory scan running in the background
+#[derive(Debug)]
+pub struct RomScan {
+    receiver: Receiver<RomScanEntry>,
+    finished: bool,
+}
+
+impl RomScan {
+    /// Collect the entries found since the last poll, without blocking
+    pub fn poll(&mut self) -> Vec<RomScanEntry> {
+        let mut entries = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(entry) => entries.push(entry),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        entries
+    }
+
+    /// Check if the scan has finished and all entries have been polled
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Block until the scan finishes, returning all remaining entries
+    pub fn wait(mut self) -> Vec<RomScanEntry> {
+        self.finished = true;
+        self.receiver.iter().collect()
+    }
+}
+
+/// Recursively scan a directory for `.nes` files on a worker thread
+pub fn scan_roms(dir: impl Into<PathBuf>) -> RomScan {
+    let dir = dir.into();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut pending = vec![dir];
+        while let Some(dir) = pending.pop() {
+            let Ok(read_dir) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in read_dir.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if is_rom_path(&path) {
+                    let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                    let header = read_header(&path);
+                    if sender.send(RomScanEntry { path, size, header }).is_err() {
+                        // The handle was dropped; stop scanning
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    RomScan {
+        receiver,
+        finished: false,
+    }
+}
+
+fn is_rom_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+}
+
+fn read_header(path: &Path) -> Result<InesHeader, LoadError> {
+    let mut bytes = [0u8; HEADER_SIZE];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .map_err(|e| LoadError::Io(format!("{}: {}", path.display(), e)))?;
+    InesHeader::parse(&bytes).map_err(LoadError::Cartridge)
+}
+
+/// ROM loading error types
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum LoadError {
+    /// The file could not be read
+    Io(String),
+    /// The file is not a valid cartridge
+    Cartridge(CartridgeError),
+    /// The worker thread stopped without producing a result
+    WorkerFailed,
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(msg) => write!(f, "Failed to read ROM: {}", msg),
+            LoadError::Cartridge(e) => write!(f, "{}", e),
+            LoadError::WorkerFailed => write!(f, "ROM loader thread stopped unexpectedly"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_rom() -> Vec<u8> {
+        let mut rom = Vec::new();
+        rom.extend_from_slice(b"NES\x1A");
+        rom.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        rom.extend_from_slice(&[0xEA; 16384]);
+        rom.extend_from_slice(&[0x00; 8192]);
+        rom
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nes-core-loader-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load_rom_async() {
+        let dir = temp_dir("load");
+        let path = dir.join("game.nes");
+        fs::write(&path, test_rom()).unwrap();
+
+        let loaded = load_rom_async(&path).wait().unwrap();
+        assert_eq!(loaded.cartridge.prg_rom().len(), 16384);
+
+        let missing = load_rom_async(dir.join("missing.nes")).wait();
+        assert!(matches!(missing, Err(LoadError::Io(_))));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_scan_roms() {
+        let dir = temp_dir("scan");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.nes"), test_rom()).unwrap();
+        fs::write(dir.join("sub").join("b.NES"), b"not a rom").unwrap();
+        fs::write(dir.join("readme.txt"), b"ignored").unwrap();
+
+        let mut entries = scan_roms(&dir).wait();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].header.is_ok());
+        assert!(entries[1].header.is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1395 @@
+//! Mapper bank-switching state
+//!
+//! Boards with mapper registers keep their state here; `SimpleCartridge`
+//! consults it to translate CPU addresses into PRG ROM offsets and forwards
+//! register writes ($4020-$FFFF) to it.
+//!
+//! Supported boards with registers:
+//! - Mapper 2 (UxROM) - switchable 16KB PRG bank at $8000, last bank fixed at $C000
+//! - Mapper 3 (CNROM) - switchable 8KB CHR bank
+//! - Mapper 7 (AxROM) - switchable 32KB PRG bank and one-screen mirroring
+//! - Discrete 32KB boards with bus conflicts: mapper 11 (Color Dreams), 66
+//!   (GxROM) and 34 (BNROM, CHR RAM)
+//! - Mapper 34 with CHR ROM (NINA-001) - registers at $7FFD-$7FFF, 4KB CHR banks
+//! - Mapper 140 (Jaleco JF-11/JF-14) - register at $6000-$7FFF
+//! - Mapper 1 (MMC1) - including the SUROM/SXROM 512KB PRG and SOROM/SXROM
+//!   banked PRG RAM variants, told apart by PRG ROM and PRG RAM size
+//! - Mapper 30 (UNROM 512) - 16KB PRG banking, CHR RAM banking, header- or
+//!   register-selected mirroring and, on flashable boards, the SST39SF040
+//!   self-flashing protocol homebrew uses to save into PRG
+//! - Mapper 28 (Action 53) - multicart mapper with an outer 32KB bank register
+//! - Mapper 105 (NES-EVENT, Nintendo World Championships 1990) - MMC1 with a
+//!   second 128KB PRG chip and a DIP-switch controlled countdown timer
+//! - Mappers 16 and 159 (Bandai FCG / LZ93D50) - 16KB PRG banking, a CPU cycle
+//!   IRQ counter and a serial EEPROM (24C02 or X24C01) for saves
+//!
+//! Bus conflicts (register writes ANDed with the PRG ROM byte at the written
+//! address) are always present on some boards (`bus_conflicts`). UxROM,
+//! CNROM and AxROM boards were built both ways; NES 2.0 submappers say which
+//! (`submapper_bus_conflicts`), and the ROM database can override it.
+//!
+//! Bank numbers are always reduced modulo the PRG ROM size, so ROMs whose
+//! registers select banks beyond the end of the data mirror instead of panicking.
+
+use crate::cartridge::{InesHeader, Mirroring};
+use crate::eeprom::{Eeprom, EepromKind};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// PRG bank size in bytes
+const PRG_BANK_16K: usize = 16 * 1024;
+/// 32KB PRG bank size in bytes
+const PRG_BANK_32K: usize = 32 * 1024;
+/// PRG RAM bank size in bytes
+const PRG_RAM_BANK_8K: usize = 8 * 1024;
+
+/// Bank-switching state of the cartridge board
+#[derive(Debug, Clone, Default)]
+pub enum MapperState {
+    /// No registers (NROM and unsupported boards)
+    #[default]
+    Fixed,
+    /// Mapper 1
+    Mmc1(Mmc1),
+    /// Mapper 2
+    Uxrom(Uxrom),
+    /// Mapper 3
+    Cnrom(Cnrom),
+    /// Mapper 7
+    Axrom(Axrom),
+    /// Mapper 11
+    ColorDreams(ColorDreams),
+    /// Mapper 66
+    Gxrom(Gxrom),
+    /// Mapper 34 with CHR RAM
+    Bnrom(Bnrom),
+    /// Mapper 34 with CHR ROM
+    Nina001(Nina001),
+    /// Mapper 140
+    Jaleco140(Jaleco140),
+    /// Mapper 28
+    Action53(Action53),
+    /// Mapper 30
+    UnRom512(UnRom512),
+    /// Mapper 105
+    Nwc(Nwc),
+    /// Mappers 16 and 159
+    BandaiFcg(BandaiFcg),
+}
+
+impl MapperState {
+    /// Create the mapper state for an iNES mapper number
+    ///
+    /// Boards without dedicated support fall back to fixed (NROM-style) mapping.
+    pub fn for_number(number: u8) -> Self {
+        match number {
+            1 => MapperState::Mmc1(Mmc1::new()),
+            2 => MapperState::Uxrom(Uxrom::default()),
+            3 => MapperState::Cnrom(Cnrom::default()),
+            7 => MapperState::Axrom(Axrom::default()),
+            11 => MapperState::ColorDreams(ColorDreams::default()),
+            66 => MapperState::Gxrom(Gxrom::default()),
+            34 => MapperState::Bnrom(Bnrom::default()),
+            140 => MapperState::Jaleco140(Jaleco140::default()),
+            28 => MapperState::Action53(Action53::new()),
+            30 => MapperState::UnRom512(UnRom512::default()),
+            105 => MapperState::Nwc(Nwc::new()),
+            16 => MapperState::BandaiFcg(BandaiFcg::new(EepromKind::C02)),
+            159 => MapperState::BandaiFcg(BandaiFcg::new(EepromKind::C01)),
+            _ => MapperState::Fixed,
+        }
+    }
+
+    /// Create the mapper state for a ROM, configured from its header where the board needs it
+    pub fn for_header(header: &InesHeader) -> Self {
+        match Self::for_number(header.mapper_number()) {
+            MapperState::UnRom512(_) => MapperState::UnRom512(UnRom512::new(header.has_sram(), header.flags_6 & 0x09)),
+            // Mapper 34 is BNROM with CHR RAM and NINA-001 with CHR ROM
+            MapperState::Bnrom(_) if header.chr_rom_size > 0 => MapperState::Nina001(Nina001::default()),
+            mapper => mapper,
+        }
+    }
+
+    /// Put the registers back to their power-on values
+    ///
+    /// How the board is wired (UNROM 512 flash and mirroring, NES-EVENT DIP
+    /// switches) and the Bandai EEPROM contents are kept.
+    pub fn reset(&mut self) {
+        match self {
+            MapperState::Fixed => {}
+            MapperState::Mmc1(m) => *m = Mmc1::new(),
+            MapperState::Uxrom(m) => *m = Uxrom::default(),
+            MapperState::Cnrom(m) => *m = Cnrom::default(),
+            MapperState::Axrom(m) => *m = Axrom::default(),
+            MapperState::ColorDreams(m) => *m = ColorDreams::default(),
+            MapperState::Gxrom(m) => *m = Gxrom::default(),
+            MapperState::Bnrom(m) => *m = Bnrom::default(),
+            MapperState::Nina001(m) => *m = Nina001::default(),
+            MapperState::Jaleco140(m) => *m = Jaleco140::default(),
+            MapperState::Action53(m) => *m = Action53::new(),
+            MapperState::UnRom512(m) => *m = UnRom512::new(m.flashable, m.header_mirroring),
+            MapperState::Nwc(m) => *m = Nwc { dip_switches: m.dip_switches, ..Nwc::new() },
+            MapperState::BandaiFcg(m) => {
+                let eeprom = m.eeprom.clone();
+                *m = BandaiFcg { eeprom, ..BandaiFcg::new(EepromKind::C02) };
+            }
+        }
+    }
+
+    /// Named register values, for debuggers and state comparison
+    pub fn registers(&self) -> Vec<(String, u32)> {
+        let named = |pairs: &[(&str, u32)]| pairs.iter().map(|&(name, value)| (name.to_string(), value)).collect::<Vec<_>>();
+        match self {
+            MapperState::Fixed => Vec::new(),
+            MapperState::Mmc1(m) => named(&[
+                ("control", m.control as u32),
+                ("chr0", m.chr0 as u32),
+                ("chr1", m.chr1 as u32),
+                ("prg", m.prg as u32),
+            ]),
+            MapperState::Uxrom(m) => named(&[("prg", m.prg as u32)]),
+            MapperState::Cnrom(m) => named(&[("chr", m.chr as u32)]),
+            MapperState::Axrom(m) => named(&[("prg", m.prg as u32), ("one_screen", m.one_screen as u32)]),
+            MapperState::ColorDreams(m) => named(&[("prg", m.prg as u32), ("chr", m.chr as u32)]),
+            MapperState::Gxrom(m) => named(&[("prg", m.prg as u32), ("chr", m.chr as u32)]),
+            MapperState::Bnrom(m) => named(&[("prg", m.prg as u32)]),
+            MapperState::Nina001(m) => named(&[("prg", m.prg as u32), ("chr0", m.chr[0] as u32), ("chr1", m.chr[1] as u32)]),
+            MapperState::Jaleco140(m) => named(&[("prg", m.prg as u32), ("chr", m.chr as u32)]),
+            MapperState::UnRom512(m) => named(&[
+                ("prg", m.prg as u32),
+                ("chr", m.chr as u32),
+                ("one_screen", m.one_screen as u32),
+                ("flash_step", m.flash.step as u32),
+            ]),
+            MapperState::Action53(m) => named(&[
+                ("select", m.select as u32),
+                ("chr", m.chr as u32),
+                ("inner", m.inner as u32),
+                ("mode", m.mode as u32),
+                ("outer", m.outer as u32),
+            ]),
+            MapperState::Nwc(m) => named(&[
+                ("control", m.control as u32),
+                ("chr0", m.chr0 as u32),
+                ("prg", m.prg as u32),
+                ("initialized", m.initialized as u32),
+                ("timer", m.timer),
+                ("dip_switches", m.dip_switches as u32),
+                ("irq", m.irq as u32),
+            ]),
+            MapperState::BandaiFcg(m) => {
+                let mut registers: Vec<(String, u32)> =
+                    m.chr_banks.iter().enumerate().map(|(i, &bank)| (format!("chr{}", i), bank as u32)).collect();
+                registers.extend(named(&[
+                    ("prg_bank", m.prg_bank as u32),
+                    ("mirroring", m.mirroring as u32),
+                    ("irq_enabled", m.irq_enabled as u32),
+                    ("irq_counter", m.irq_counter as u32),
+                    ("irq_latch", m.irq_latch as u32),
+                    ("irq", m.irq as u32),
+                ]));
+                registers
+            }
+        }
+    }
+
+    /// Check if an iNES mapper number has dedicated support (or needs none, like NROM)
+    pub fn supports(number: u8) -> bool {
+        number == 0 || !matches!(Self::for_number(number), MapperState::Fixed)
+    }
+
+    /// Translate a CPU address in $8000-$FFFF to an offset into PRG ROM
+    pub fn prg_offset(&self, address: u16, prg_len: usize) -> usize {
+        if prg_len == 0 {
+            return 0;
+        }
+        let offset = match self {
+            MapperState::Fixed => (address - 0x8000) as usize,
+            MapperState::Mmc1(m) => m.prg_offset(address, prg_len),
+            MapperState::Uxrom(m) => m.prg_offset(address, prg_len),
+            MapperState::Cnrom(_) => (address - 0x8000) as usize,
+            MapperState::ColorDreams(ColorDreams { prg, .. })
+            | MapperState::Axrom(Axrom { prg, .. })
+            | MapperState::Gxrom(Gxrom { prg, .. })
+            | MapperState::Bnrom(Bnrom { prg })
+            | MapperState::Nina001(Nina001 { prg, .. })
+            | MapperState::Jaleco140(Jaleco140 { prg, .. }) => *prg as usize * PRG_BANK_32K + (address & 0x7FFF) as usize,
+            MapperState::Action53(m) => m.prg_offset(address),
+            MapperState::UnRom512(m) => m.prg_offset(address, prg_len),
+            MapperState::Nwc(m) => m.prg_offset(address),
+            MapperState::BandaiFcg(m) => m.prg_offset(address, prg_len),
+        };
+        offset % prg_len
+    }
+
+    /// Handle a CPU write to $4020-$FFFF
+    pub fn write(&mut self, address: u16, value: u8) {
+        match self {
+            MapperState::Fixed => {}
+            MapperState::Mmc1(m) => m.write(address, value),
+            MapperState::Uxrom(m) => {
+                if address >= 0x8000 {
+                    m.prg = value;
+                }
+            }
+            MapperState::Cnrom(m) => {
+                if address >= 0x8000 {
+                    m.chr = value;
+                }
+            }
+            MapperState::Axrom(m) => {
+                if address >= 0x8000 {
+                    m.prg = value & 0x07;
+                    m.one_screen = value & 0x10 != 0;
+                }
+            }
+            MapperState::ColorDreams(m) => {
+                if address >= 0x8000 {
+                    m.prg = value & 0x03;
+                    m.chr = value >> 4;
+                }
+            }
+            MapperState::Gxrom(m) => {
+                if address >= 0x8000 {
+                    m.prg = (value >> 4) & 0x03;
+                    m.chr = value & 0x03;
+                }
+            }
+            MapperState::Bnrom(m) => {
+                if address >= 0x8000 {
+                    m.prg = value;
+                }
+            }
+            MapperState::Nina001(m) => match address {
+                0x7FFD => m.prg = value & 0x01,
+                0x7FFE | 0x7FFF => m.chr[(address - 0x7FFE) as usize] = value & 0x0F,
+                _ => {}
+            },
+            MapperState::Jaleco140(m) => {
+                if (0x6000..0x8000).contains(&address) {
+                    m.prg = (value >> 4) & 0x03;
+                    m.chr = value & 0x0F;
+                }
+            }
+            MapperState::Action53(m) => m.write(address, value),
+            MapperState::UnRom512(m) => m.write(address, value),
+            MapperState::Nwc(m) => m.write(address, value),
+            MapperState::BandaiFcg(m) => m.write(address, value),
+        }
+    }
+
+    /// Check if the board's register writes conflict with the PRG ROM driving the bus (the ROM's byte is ANDed in)
+    ///
+    /// This is the board default; boards built both ways default to no conflicts.
+    pub fn bus_conflicts(&self) -> bool {
+        matches!(self, MapperState::ColorDreams(_) | MapperState::Gxrom(_) | MapperState::Bnrom(_))
+    }
+
+    /// Bus conflicts given by an NES 2.0 submapper, for boards built both ways (None when unspecified)
+    pub fn submapper_bus_conflicts(mapper: u8, submapper: u8) -> Option<bool> {
+        match (mapper, submapper) {
+            (2 | 3 | 7, 1) => Some(false),
+            (2 | 3 | 7, 2) => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Take the flash operation the last write completed, if any (boards with flash PRG)
+    pub fn take_flash_op(&mut self) -> Option<FlashOp> {
+        match self {
+            MapperState::UnRom512(m) => m.flash.pending.take(),
+            _ => None,
+        }
+    }
+
+    /// Check if the board's PRG is flash that games save into (persisted like battery RAM)
+    pub fn flashable_prg(&self) -> bool {
+        matches!(self, MapperState::UnRom512(m) if m.flashable)
+    }
+
+    /// Translate a CPU address in $6000-$7FFF to an offset into PRG RAM (None while RAM is disabled)
+    pub fn prg_ram_offset(&self, address: u16, ram_len: usize) -> Option<usize> {
+        if ram_len == 0 {
+            return None;
+        }
+        let offset = match self {
+            MapperState::Mmc1(m) => m.prg_ram_offset(address, ram_len)?,
+            _ => (address & 0x1FFF) as usize,
+        };
+        Some(offset % ram_len)
+    }
+
+    /// Handle a CPU read from $6000-$7FFF, if the board responds there instead of PRG RAM
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match self {
+            MapperState::BandaiFcg(m) => m.read(address),
+            _ => None,
+        }
+    }
+
+    /// Advance mapper timers by the given number of CPU cycles
+    pub fn clock_cpu(&mut self, cycles: u32) {
+        match self {
+            MapperState::Nwc(m) => m.clock_cpu(cycles),
+            MapperState::BandaiFcg(m) => m.clock_cpu(cycles),
+            _ => {}
+        }
+    }
+
+    /// Get the mirroring selected by the board's registers
+    ///
+    /// None for boards whose mirroring is soldered (the header's applies).
+    /// The PPU consults this before every step, so games that switch
+    /// mirroring mid-frame (MMC1 and AxROM door and room transitions) see
+    /// the change on their next nametable access.
+    pub fn current_mirroring(&self) -> Option<Mirroring> {
+        // MMC1 control bits 0-1, shared by the boards derived from it
+        let mmc1 = |control: u8| match control & 0x03 {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        };
+        let one_screen = |second: bool| if second { Mirroring::SingleScreenB } else { Mirroring::SingleScreenA };
+        Some(match self {
+            MapperState::Mmc1(m) => mmc1(m.control),
+            MapperState::Nwc(m) => mmc1(m.control),
+            MapperState::Axrom(m) => one_screen(m.one_screen),
+            // One-screen modes take the page from bit 4 of the CHR register
+            MapperState::Action53(m) if m.mode & 0x02 == 0 => one_screen(m.chr & 0x10 != 0),
+            MapperState::Action53(m) => mmc1(m.mode),
+            MapperState::UnRom512(m) => match m.mirroring() {
+                0 => Mirroring::Horizontal,
+                1 => Mirroring::Vertical,
+                4 => Mirroring::FourScreen,
+                mode => one_screen(mode == 3),
+            },
+            MapperState::BandaiFcg(m) => match m.mirroring() {
+                0 => Mirroring::Vertical,
+                1 => Mirroring::Horizontal,
+                mode => one_screen(mode == 3),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Check if the mapper is asserting IRQ
+    pub fn irq_pending(&self) -> bool {
+        match self {
+            MapperState::Nwc(m) => m.irq_pending(),
+            MapperState::BandaiFcg(m) => m.irq_pending(),
+            _ => false,
+        }
+    }
+
+    /// Release the IRQ as the board's acknowledge write would
+    pub fn acknowledge_irq(&mut self) {
+        match self {
+            MapperState::Nwc(m) => {
+                m.timer = 0;
+                m.irq = false;
+            }
+            MapperState::BandaiFcg(m) => m.irq = false,
+            _ => {}
+        }
+    }
+
+    /// Get the battery-backed save data kept on the board (such as EEPROM contents)
+    pub fn battery_data(&self) -> Option<&[u8]> {
+        match self {
+            MapperState::BandaiFcg(m) => Some(m.eeprom().data()),
+            _ => None,
+        }
+    }
+
+    /// Restore battery-backed save data previously returned by `battery_data`
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        if let MapperState::BandaiFcg(m) = self {
+            m.eeprom_mut().load_data(data);
+        }
+    }
+
+    /// Board identifier written ahead of the registers in a savestate
+    fn state_tag(&self) -> u8 {
+        match self {
+            MapperState::Fixed => 0,
+            MapperState::Mmc1(_) => 1,
+            MapperState::Uxrom(_) => 2,
+            MapperState::Cnrom(_) => 3,
+            MapperState::Axrom(_) => 4,
+            MapperState::ColorDreams(_) => 5,
+            MapperState::Gxrom(_) => 6,
+            MapperState::Bnrom(_) => 7,
+            MapperState::Nina001(_) => 8,
+            MapperState::Jaleco140(_) => 9,
+            MapperState::Action53(_) => 10,
+            MapperState::UnRom512(_) => 11,
+            MapperState::Nwc(_) => 12,
+            MapperState::BandaiFcg(_) => 13,
+        }
+    }
+
+    /// Serialize the board registers (and EEPROM contents)
+    ///
+    /// Configuration that comes from the ROM header, such as UNROM 512
+    /// mirroring or the EEPROM kind, is not included: the state is restored
+    /// into the board created for the same ROM.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.state_tag());
+        match self {
+            MapperState::Fixed => {}
+            MapperState::Mmc1(m) => writer.write_bytes(&[m.shift.value, m.shift.count, m.control, m.chr0, m.chr1, m.prg]),
+            MapperState::Uxrom(m) => writer.write_u8(m.prg),
+            MapperState::Cnrom(m) => writer.write_u8(m.chr),
+            MapperState::Axrom(m) => writer.write_bytes(&[m.prg, m.one_screen as u8]),
+            MapperState::ColorDreams(m) => writer.write_bytes(&[m.prg, m.chr]),
+            MapperState::Gxrom(m) => writer.write_bytes(&[m.prg, m.chr]),
+            MapperState::Bnrom(m) => writer.write_u8(m.prg),
+            MapperState::Nina001(m) => writer.write_bytes(&[m.prg, m.chr[0], m.chr[1]]),
+            MapperState::Jaleco140(m) => writer.write_bytes(&[m.prg, m.chr]),
+            // A completed flash command is applied by the cartridge straight
+            // away, so only the command sequence position needs saving
+            MapperState::UnRom512(m) => writer.write_bytes(&[m.prg, m.chr, m.one_screen as u8, m.flash.step]),
+            MapperState::Action53(m) => writer.write_bytes(&[m.select, m.chr, m.inner, m.mode, m.outer]),
+            MapperState::Nwc(m) => {
+                writer.write_bytes(&[m.shift.value, m.shift.count, m.control, m.chr0, m.prg]);
+                writer.write_bool(m.initialized);
+                writer.write_bool(m.init_seen_high);
+                writer.write_u32(m.timer);
+                writer.write_u8(m.dip_switches);
+                writer.write_bool(m.irq);
+            }
+            MapperState::BandaiFcg(m) => {
+                writer.write_bytes(&m.chr_banks);
+                writer.write_u8(m.prg_bank);
+                writer.write_u8(m.mirroring);
+                writer.write_bool(m.irq_enabled);
+                writer.write_u16(m.irq_counter);
+                writer.write_u16(m.irq_latch);
+                writer.write_bool(m.irq);
+                m.eeprom.save_state(writer);
+            }
+        }
+    }
+
+    /// Restore state written by `save_state` for the same board
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if reader.read_u8()? != self.state_tag() {
+            return Err(StateError::InvalidData("mapper does not match the cartridge"));
+        }
+        match self {
+            MapperState::Fixed => {}
+            MapperState::Mmc1(m) => {
+                m.shift = Mmc1Shift::load_state(reader)?;
+                m.control = reader.read_u8()?;
+                m.chr0 = reader.read_u8()?;
+                m.chr1 = reader.read_u8()?;
+                m.prg = reader.read_u8()?;
+            }
+            MapperState::Uxrom(m) => m.prg = reader.read_u8()?,
+            MapperState::Cnrom(m) => m.chr = reader.read_u8()?,
+            MapperState::Axrom(m) => {
+                m.prg = reader.read_u8()?;
+                m.one_screen = reader.read_bool()?;
+            }
+            MapperState::ColorDreams(m) => {
+                m.prg = reader.read_u8()?;
+                m.chr = reader.read_u8()?;
+            }
+            MapperState::Gxrom(m) => {
+                m.prg = reader.read_u8()?;
+                m.chr = reader.read_u8()?;
+            }
+            MapperState::Bnrom(m) => m.prg = reader.read_u8()?,
+            MapperState::Nina001(m) => {
+                m.prg = reader.read_u8()?;
+                reader.read_into(&mut m.chr)?;
+            }
+            MapperState::Jaleco140(m) => {
+                m.prg = reader.read_u8()?;
+                m.chr = reader.read_u8()?;
+            }
+            MapperState::UnRom512(m) => {
+                m.prg = reader.read_u8()?;
+                m.chr = reader.read_u8()?;
+                m.one_screen = reader.read_bool()?;
+                m.flash = FlashCommand { step: reader.read_u8()?, pending: None };
+            }
+            MapperState::Action53(m) => {
+                m.select = reader.read_u8()?;
+                m.chr = reader.read_u8()?;
+                m.inner = reader.read_u8()?;
+                m.mode = reader.read_u8()?;
+                m.outer = reader.read_u8()?;
+            }
+            MapperState::Nwc(m) => {
+                m.shift = Mmc1Shift::load_state(reader)?;
+                m.control = reader.read_u8()?;
+                m.chr0 = reader.read_u8()?;
+                m.prg = reader.read_u8()?;
+                m.initialized = reader.read_bool()?;
+                m.init_seen_high = reader.read_bool()?;
+                m.timer = reader.read_u32()?;
+                m.dip_switches = reader.read_u8()?;
+                m.irq = reader.read_bool()?;
+            }
+            MapperState::BandaiFcg(m) => {
+                reader.read_into(&mut m.chr_banks)?;
+                m.prg_bank = reader.read_u8()?;
+                m.mirroring = reader.read_u8()?;
+                m.irq_enabled = reader.read_bool()?;
+                m.irq_counter = reader.read_u16()?;
+                m.irq_latch = reader.read_u16()?;
+                m.irq = reader.read_bool()?;
+                m.eeprom.load_state(reader)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Result of a write to the MMC1 serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SerialWrite {
+    /// Bit 7 was set: the shift register was cleared
+    Reset,
+    /// A bit was shifted in; the register is not complete yet
+    Pending,
+    /// The fifth bit completed this 5-bit value
+    Complete(u8),
+}
+
+/// MMC1 serial shift register (registers are loaded one bit per write, LSB first)
+#[derive(Debug, Clone, Default)]
+struct Mmc1Shift {
+    value: u8,
+    count: u8,
+}
+
+impl Mmc1Shift {
+    /// Read a shift register saved as value and bit count
+    fn load_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let shift = Self { value: reader.read_u8()?, count: reader.read_u8()? };
+        if shift.count >= 5 {
+            return Err(StateError::InvalidData("MMC1 shift count"));
+        }
+        Ok(shift)
+    }
+
+    fn write(&mut self, data: u8) -> SerialWrite {
+        if data & 0x80 != 0 {
+            *self = Self::default();
+            return SerialWrite::Reset;
+        }
+        self.value |= (data & 0x01) << self.count;
+        self.count += 1;
+        if self.count < 5 {
+            return SerialWrite::Pending;
+        }
+        let value = self.value;
+        *self = Self::default();
+        SerialWrite::Complete(value)
+    }
+}
+
+/// MMC1 (mapper 1) registers
+///
+/// Large boards reuse CHR bank register 0 lines: bit 4 selects the 256KB PRG
+/// half on 512KB boards (SUROM/SXROM), bit 3 selects the 8KB PRG RAM bank on
+/// 16KB boards (SOROM) and bits 2-3 on 32KB boards (SXROM).
+#[derive(Debug, Clone)]
+pub struct Mmc1 {
+    shift: Mmc1Shift,
+    /// Mirroring, PRG and CHR bank modes
+    control: u8,
+    chr0: u8,
+    chr1: u8,
+    /// PRG bank (bits 0-3) and PRG RAM disable (bit 4)
+    prg: u8,
+}
+
+impl Mmc1 {
+    /// Power-on state: PRG mode 3 (last bank fixed at $C000)
+    pub fn new() -> Self {
+        Self {
+            shift: Mmc1Shift::default(),
+            control: 0x0C,
+            chr0: 0,
+            chr1: 0,
+            prg: 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+        let data = match self.shift.write(value) {
+            SerialWrite::Reset => {
+                self.control |= 0x0C;
+                return;
+            }
+            SerialWrite::Pending => return,
+            SerialWrite::Complete(data) => data,
+        };
+        match address {
+            0x8000..=0x9FFF => self.control = data,
+            0xA000..=0xBFFF => self.chr0 = data,
+            0xC000..=0xDFFF => self.chr1 = data,
+            _ => self.prg = data,
+        }
+    }
+
+    fn prg_offset(&self, address: u16, prg_len: usize) -> usize {
+        let a14 = ((address >> 14) & 1) as usize;
+        let prg = (self.prg & 0x0F) as usize;
+        let bank = match (self.control >> 2) & 0x03 {
+            0 | 1 => (prg & !1) | a14,
+            2 => if a14 == 0 { 0 } else { prg },
+            _ => if a14 == 0 { prg } else { 0x0F },
+        };
+        // 512KB boards: CHR bit 4 selects the 256KB half, including the fixed bank
+        let outer = if prg_len > 16 * PRG_BANK_16K { ((self.chr0 >> 4) & 1) as usize * 16 } else { 0 };
+        (outer + bank) * PRG_BANK_16K + (address & 0x3FFF) as usize
+    }
+
+    fn prg_ram_offset(&self, address: u16, ram_len: usize) -> Option<usize> {
+        if self.prg & 0x10 != 0 {
+            return None;
+        }
+        let bank = match ram_len {
+            len if len > 2 * PRG_RAM_BANK_8K => (self.chr0 >> 2) & 0x03,
+            len if len > PRG_RAM_BANK_8K => (self.chr0 >> 3) & 0x01,
+            _ => 0,
+        };
+        Some(bank as usize * PRG_RAM_BANK_8K + (address & 0x1FFF) as usize)
+    }
+}
+
+impl Default for Mmc1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// UxROM (mapper 2) bank register
+#[derive(Debug, Clone, Default)]
+pub struct Uxrom {
+    /// 16KB PRG bank at $8000-$BFFF
+    prg: u8,
+}
+
+impl Uxrom {
+    fn prg_offset(&self, address: u16, prg_len: usize) -> usize {
+        let bank = if address < 0xC000 { self.prg as usize } else { (prg_len / PRG_BANK_16K).saturating_sub(1) };
+        bank * PRG_BANK_16K + (address & 0x3FFF) as usize
+    }
+}
+
+/// CNROM (mapper 3) bank register
+#[derive(Debug, Clone, Default)]
+pub struct Cnrom {
+    /// 8KB CHR bank
+    chr: u8,
+}
+
+/// AxROM (mapper 7) register, `...M.PPP`
+#[derive(Debug, Clone, Default)]
+pub struct Axrom {
+    /// 32KB PRG bank
+    prg: u8,
+    /// One-screen nametable select
+    one_screen: bool,
+}
+
+/// Color Dreams (mapper 11) register, `CCCC..PP`
+#[derive(Debug, Clone, Default)]
+pub struct ColorDreams {
+    /// 32KB PRG bank
+    prg: u8,
+    /// 8KB CHR bank
+    chr: u8,
+}
+
+/// GxROM (mapper 66) register, `..PP..CC`
+#[derive(Debug, Clone, Default)]
+pub struct Gxrom {
+    /// 32KB PRG bank
+    prg: u8,
+    /// 8KB CHR bank
+    chr: u8,
+}
+
+/// BNROM (mapper 34, CHR RAM) register
+#[derive(Debug, Clone, Default)]
+pub struct Bnrom {
+    /// 32KB PRG bank (boards use 2 bits; oversized homebrew uses more)
+    prg: u8,
+}
+
+/// NINA-001 (mapper 34, CHR ROM) registers at $7FFD-$7FFF
+///
+/// The registers overlap PRG RAM, so writes also land in RAM.
+#[derive(Debug, Clone, Default)]
+pub struct Nina001 {
+    /// $7FFD: 32KB PRG bank
+    prg: u8,
+    /// $7FFE/$7FFF: 4KB CHR banks at PPU $0000 and $1000
+    chr: [u8; 2],
+}
+
+/// Jaleco JF-11/JF-14 (mapper 140) register at $6000-$7FFF, `..PPCCCC`
+#[derive(Debug, Clone, Default)]
+pub struct Jaleco140 {
+    /// 32KB PRG bank
+    prg: u8,
+    /// 8KB CHR bank
+    chr: u8,
+}
+
+/// Flash sector size erased by the sector erase command
+pub const FLASH_SECTOR: usize = 4 * 1024;
+
+/// Change to flash PRG requested through the flash command protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashOp {
+    /// Program a byte (flash can only clear bits, so it is ANDed in)
+    Program { offset: usize, value: u8 },
+    /// Erase the `FLASH_SECTOR` bytes starting at this offset to $FF
+    EraseSector { offset: usize },
+    /// Erase the whole chip to $FF
+    EraseChip,
+}
+
+/// SST39SF040 command decoder
+///
+/// Commands are written to chip addresses $5555 and $2AAA (CPU $9555 in bank
+/// 1 and $AAAA in bank 0): `AA 55 A0` then a byte programs it, `AA 55 80 AA
+/// 55` then `30` erases the addressed sector or `10` erases the chip. Any
+/// other write returns to reading. The software ID mode (`90`) isn't
+/// modelled; its exit command (`F0`) is just another reset.
+#[derive(Debug, Clone, Default)]
+struct FlashCommand {
+    /// Position in the command sequence (0 is idle)
+    step: u8,
+    /// Completed operation waiting to be applied to PRG
+    pending: Option<FlashOp>,
+}
+
+impl FlashCommand {
+    fn write(&mut self, offset: usize, value: u8) {
+        let command_address = offset & 0x7FFF;
+        self.step = match (self.step, command_address, value) {
+            (0 | 3, 0x5555, 0xAA) => self.step + 1,
+            (1 | 4, 0x2AAA, 0x55) => self.step + 1,
+            (2, 0x5555, 0xA0) => 6,
+            (2, 0x5555, 0x80) => 3,
+            (5, _, 0x30) => {
+                self.pending = Some(FlashOp::EraseSector { offset: offset & !(FLASH_SECTOR - 1) });
+                0
+            }
+            (5, 0x5555, 0x10) => {
+                self.pending = Some(FlashOp::EraseChip);
+                0
+            }
+            (6, _, value) => {
+                self.pending = Some(FlashOp::Program { offset, value });
+                0
+            }
+            _ => 0,
+        };
+    }
+}
+
+/// UNROM 512 (mapper 30) state
+///
+/// The register is `MCCPPPPP`: 16KB PRG bank at $8000 (P), 8KB CHR RAM bank
+/// (C) and, when the header selects it, the one-screen nametable (M).
+/// Flashable boards (battery bit set) take register writes at $C000-$FFFF and
+/// pass writes to $8000-$BFFF to the flash chip; other boards take register
+/// writes anywhere in $8000-$FFFF.
+#[derive(Debug, Clone, Default)]
+pub struct UnRom512 {
+    prg: u8,
+    chr: u8,
+    one_screen: bool,
+    flashable: bool,
+    /// Header mirroring bits (flags 6 bits 0 and 3)
+    header_mirroring: u8,
+    flash: FlashCommand,
+}
+
+impl UnRom512 {
+    /// Create the board; `flashable` is the header's battery bit, `header_mirroring` flags 6 & $09
+    pub fn new(flashable: bool, header_mirroring: u8) -> Self {
+        Self { flashable, header_mirroring: header_mirroring & 0x09, ..Self::default() }
+    }
+
+    /// Get the mirroring mode (0 horizontal, 1 vertical, 2/3 single screen A/B, 4 four screen)
+    pub fn mirroring(&self) -> u8 {
+        match self.header_mirroring {
+            0x00 => 0,
+            0x01 => 1,
+            0x08 => 2 + self.one_screen as u8,
+            _ => 4,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0xBFFF if self.flashable => {
+                let offset = self.prg as usize * PRG_BANK_16K + (address & 0x3FFF) as usize;
+                self.flash.write(offset, value);
+            }
+            0x8000..=0xFFFF => {
+                self.prg = value & 0x1F;
+                self.chr = (value >> 5) & 0x03;
+                self.one_screen = value & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn prg_offset(&self, address: u16, prg_len: usize) -> usize {
+        let bank = if address < 0xC000 { self.prg as usize } else { (prg_len / PRG_BANK_16K).max(1) - 1 };
+        bank * PRG_BANK_16K + (address & 0x3FFF) as usize
+    }
+}
+
+/// Action 53 (mapper 28) registers
+#[derive(Debug, Clone)]
+pub struct Action53 {
+    /// Register selected through $5000-$5FFF ($00, $01, $80 or $81)
+    select: u8,
+    /// $00: CHR RAM bank and one-screen mirroring bit
+    chr: u8,
+    /// $01: inner PRG bank
+    inner: u8,
+    /// $80: mirroring, PRG bank mode and game size
+    mode: u8,
+    /// $81: outer 32KB PRG bank
+    outer: u8,
+}
+
+impl Action53 {
+    /// Power-on state: the last outer bank is mapped so the menu's reset vector is visible
+    pub fn new() -> Self {
+        Self {
+            select: 0,
+            chr: 0,
+            inner: 0,
+            mode: 0,
+            outer: 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5FFF => self.select = value & 0x81,
+            0x8000..=0xFFFF => match self.select {
+                0x00 => self.chr = value,
+                0x01 => self.inner = value,
+                0x80 => self.mode = value,
+                _ => self.outer = value,
+            },
+            _ => {}
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let a14 = ((address >> 14) & 1) as usize;
+        let outer = (self.outer as usize) << 1;
+        let bank_mode = (self.mode >> 2) & 0x03;
+        // Game size 32/64/128/256KB: mask of 16KB bank bits taken from the inner register
+        let mask = (2usize << ((self.mode >> 4) & 0x03)) - 1;
+        let inner = (self.inner & 0x0F) as usize;
+
+        let bank = match bank_mode {
+            // 32KB switching
+            0 | 1 => (outer & !mask) | (((inner << 1) | a14) & mask),
+            // UNROM-style: one half fixed to the outer bank, the other switchable
+            _ => {
+                let fixed_half = if bank_mode == 2 { 0 } else { 1 };
+                if a14 == fixed_half {
+                    outer | a14
+                } else {
+                    (outer & !mask) | (inner & mask)
+                }
+            }
+        };
+        bank * PRG_BANK_16K + (address & 0x3FFF) as usize
+    }
+}
+
+impl Default for Action53 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Timer threshold before DIP switch adjustment (about 5.0 minutes at 1.79MHz)
+const NWC_TIMER_BASE: u32 = 0x2000_0000;
+
+/// NES-EVENT / Nintendo World Championships (mapper 105) state
+#[derive(Debug, Clone)]
+pub struct Nwc {
+    /// MMC1 serial shift register
+    shift: Mmc1Shift,
+    /// MMC1 registers
+    control: u8,
+    chr0: u8,
+    prg: u8,
+    /// PRG stays on the first 32KB until the IRQ bit has been set and cleared once
+    initialized: bool,
+    init_seen_high: bool,
+    /// Countdown timer (counts up to the DIP-selected threshold)
+    timer: u32,
+    /// DIP switch setting (0-15); each step adds about 18.75 seconds
+    dip_switches: u8,
+    irq: bool,
+}
+
+impl Nwc {
+    /// Create the board with the tournament DIP setting (6:15 time limit)
+    pub fn new() -> Self {
+        Self {
+            shift: Mmc1Shift::default(),
+            control: 0x0C,
+            chr0: 0x10,
+            prg: 0,
+            initialized: false,
+            init_seen_high: false,
+            timer: 0,
+            dip_switches: 4,
+            irq: false,
+        }
+    }
+
+    /// Set the DIP switches that select the timer length (0-15)
+    #[cfg(test)]
+    pub fn set_dip_switches(&mut self, value: u8) {
+        self.dip_switches = value & 0x0F;
+    }
+
+    /// Number of CPU cycles before the timer expires
+    pub fn timer_limit(&self) -> u32 {
+        NWC_TIMER_BASE | ((self.dip_switches as u32) << 25)
+    }
+
+    /// CPU cycles left on the countdown timer
+    #[cfg(test)]
+    pub fn remaining_cycles(&self) -> u32 {
+        self.timer_limit().saturating_sub(self.timer)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+        let data = match self.shift.write(value) {
+            SerialWrite::Reset => {
+                self.control |= 0x0C;
+                return;
+            }
+            SerialWrite::Pending => return,
+            SerialWrite::Complete(data) => data,
+        };
+
+        match address {
+            0x8000..=0x9FFF => self.control = data,
+            0xA000..=0xBFFF => self.write_chr0(data),
+            // CHR bank 1 is unused (the board has 8KB CHR RAM)
+            0xC000..=0xDFFF => {}
+            _ => self.prg = data,
+        }
+    }
+
+    fn write_chr0(&mut self, data: u8) {
+        self.chr0 = data;
+        let irq_bit = data & 0x10 != 0;
+        if !self.initialized {
+            if irq_bit {
+                self.init_seen_high = true;
+            } else if self.init_seen_high {
+                self.initialized = true;
+            }
+        }
+        if irq_bit {
+            // Timer held in reset and IRQ acknowledged
+            self.timer = 0;
+            self.irq = false;
+        }
+    }
+
+    fn clock_cpu(&mut self, cycles: u32) {
+        if self.chr0 & 0x10 != 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_add(cycles);
+        if self.timer >= self.timer_limit() {
+            self.irq = true;
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let a14 = ((address >> 14) & 1) as usize;
+        let window = (address & 0x3FFF) as usize;
+
+        if !self.initialized || self.chr0 & 0x08 == 0 {
+            // First 128KB chip: 32KB banks selected by CHR0 bits 1-2
+            let bank32 = if self.initialized { ((self.chr0 >> 1) & 0x03) as usize } else { 0 };
+            return (bank32 * 2 + a14) * PRG_BANK_16K + window;
+        }
+
+        // Second 128KB chip: regular MMC1 PRG banking within 8 banks
+        let prg = (self.prg & 0x07) as usize;
+        let bank = match (self.control >> 2) & 0x03 {
+            0 | 1 => (prg & !1) | a14,
+            2 => if a14 == 0 { 0 } else { prg },
+            _ => if a14 == 0 { prg } else { 7 },
+        };
+        (8 + bank) * PRG_BANK_16K + window
+    }
+}
+
+impl Default for Nwc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bandai FCG-1/FCG-2 and LZ93D50 (mappers 16 and 159) state
+///
+/// Registers are selected by the low 4 address bits and respond at $6000-$7FFF
+/// (FCG) and $8000-$FFFF (LZ93D50); mapper 16 ROMs may target either board.
+#[derive(Debug, Clone)]
+pub struct BandaiFcg {
+    /// 1KB CHR bank registers ($0-$7)
+    chr_banks: [u8; 8],
+    /// 16KB PRG bank at $8000 ($8); $C000 is fixed to the last bank
+    prg_bank: u8,
+    /// Mirroring ($9)
+    mirroring: u8,
+    irq_enabled: bool,
+    irq_counter: u16,
+    /// Reload value for the LZ93D50 counter ($B-$C at $8000-$FFFF)
+    irq_latch: u16,
+    irq: bool,
+    eeprom: Eeprom,
+}
+
+impl BandaiFcg {
+    /// Create the board with the given save EEPROM
+    pub fn new(eeprom: EepromKind) -> Self {
+        Self {
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            mirroring: 0,
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq: false,
+            eeprom: Eeprom::new(eeprom),
+        }
+    }
+
+    /// Get the save EEPROM
+    pub fn eeprom(&self) -> &Eeprom {
+        &self.eeprom
+    }
+
+    /// Get the save EEPROM mutably (e.g. to load a save file)
+    pub fn eeprom_mut(&mut self) -> &mut Eeprom {
+        &mut self.eeprom
+    }
+
+    /// Get the mirroring register (0 vertical, 1 horizontal, 2/3 single screen)
+    pub fn mirroring(&self) -> u8 {
+        self.mirroring & 0x03
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < 0x6000 {
+            return;
+        }
+        match address & 0x0F {
+            reg @ 0x0..=0x7 => self.chr_banks[reg as usize] = value,
+            0x8 => self.prg_bank = value & 0x0F,
+            0x9 => self.mirroring = value,
+            0xA => {
+                self.irq_enabled = value & 0x01 != 0;
+                self.irq = false;
+                if address >= 0x8000 {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            reg @ (0xB | 0xC) => {
+                let shift = if reg == 0xB { 0 } else { 8 };
+                let target = if address >= 0x8000 { &mut self.irq_latch } else { &mut self.irq_counter };
+                *target = (*target & !(0xFF << shift)) | ((value as u16) << shift);
+            }
+            0xD => self.eeprom.write(value & 0x20 != 0, value & 0x40 != 0),
+            _ => {}
+        }
+    }
+
+    /// EEPROM data appears in bit 4 of reads from $6000-$7FFF
+    fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => Some((self.eeprom.output() as u8) << 4),
+            _ => None,
+        }
+    }
+
+    fn clock_cpu(&mut self, cycles: u32) {
+        if !self.irq_enabled {
+            return;
+        }
+        // The IRQ fires when the counter is 0 as it is decremented
+        if (self.irq_counter as u32) < cycles {
+            self.irq = true;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(cycles as u16);
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn prg_offset(&self, address: u16, prg_len: usize) -> usize {
+        let bank = if address < 0xC000 {
+            self.prg_bank as usize
+        } else {
+            (prg_len / PRG_BANK_16K).max(1) - 1
+        };
+        bank * PRG_BANK_16K + (address & 0x3FFF) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRG_512K: usize = 512 * 1024;
+
+    #[test]
+    fn test_uxrom_fixed_last_bank() {
+        let mut mapper = MapperState::for_number(2);
+        let prg_128k = 128 * 1024;
+        mapper.write(0x8000, 5);
+        assert_eq!(mapper.prg_offset(0x8123, prg_128k), 5 * PRG_BANK_16K + 0x123);
+        assert_eq!(mapper.prg_offset(0xFFFC, prg_128k), prg_128k - 4);
+        // Writes below $8000 don't reach the register
+        mapper.write(0x6000, 1);
+        assert_eq!(mapper.registers(), [("prg".to_string(), 5)]);
+    }
+
+    #[test]
+    fn test_cnrom_chr_bank() {
+        let mut mapper = MapperState::for_number(3);
+        mapper.write(0xC000, 2);
+        assert_eq!(mapper.registers(), [("chr".to_string(), 2)]);
+        assert_eq!(mapper.prg_offset(0xC000, PRG_BANK_16K * 2), PRG_BANK_16K);
+    }
+
+    #[test]
+    fn test_action53_power_on_maps_last_bank() {
+        let mapper = MapperState::for_number(28);
+        // Outer bank $FF in 32KB mode maps the last 32KB of the ROM
+        assert_eq!(mapper.prg_offset(0x8000, PRG_512K), PRG_512K - 0x8000);
+        assert_eq!(mapper.prg_offset(0xFFFC, PRG_512K), PRG_512K - 4);
+    }
+
+    #[test]
+    fn test_action53_unrom_mode_with_outer_bank() {
+        let mut mapper = MapperState::for_number(28);
+        // Mode: UNROM (fixed $C000), 128KB game size
+        mapper.write(0x5000, 0x80);
+        mapper.write(0x8000, 0x2C);
+        // Outer bank 4 (128KB-aligned game starting at 128KB)
+        mapper.write(0x5000, 0x81);
+        mapper.write(0x8000, 0x04);
+        // Inner bank 3
+        mapper.write(0x5000, 0x01);
+        mapper.write(0x8000, 0x03);
+
+        let bank = |addr| mapper.prg_offset(addr, PRG_512K) / PRG_BANK_16K;
+        assert_eq!(bank(0x8000), 8 + 3);
+        assert_eq!(bank(0xC000), 9);
+    }
+
+    #[test]
+    fn test_oversized_bank_mirrors() {
+        let mut mapper = MapperState::for_number(28);
+        mapper.write(0x5000, 0x81);
+        mapper.write(0x8000, 0x40);
+        // 64 x 32KB exceeds a 512KB ROM; the offset wraps instead of going out of range
+        assert!(mapper.prg_offset(0x8000, PRG_512K) < PRG_512K);
+    }
+
+    fn mmc1_write(mapper: &mut MapperState, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write(address, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_nwc_initialization_and_chips() {
+        let mut mapper = MapperState::for_number(105);
+        let prg_len = 256 * 1024;
+        let bank = |m: &MapperState, addr| m.prg_offset(addr, prg_len) / PRG_BANK_16K;
+
+        // Locked to the first 32KB until the IRQ bit toggles high then low
+        mmc1_write(&mut mapper, 0xA000, 0x02);
+        assert_eq!(bank(&mapper, 0x8000), 0);
+        mmc1_write(&mut mapper, 0xA000, 0x10);
+        mmc1_write(&mut mapper, 0xA000, 0x04);
+        assert_eq!(bank(&mapper, 0x8000), 4);
+        assert_eq!(bank(&mapper, 0xC000), 5);
+
+        // Second chip, fixed-last mode, bank 2 at $8000
+        mmc1_write(&mut mapper, 0x8000, 0x0C);
+        mmc1_write(&mut mapper, 0xE000, 0x02);
+        mmc1_write(&mut mapper, 0xA000, 0x08);
+        assert_eq!(bank(&mapper, 0x8000), 10);
+        assert_eq!(bank(&mapper, 0xC000), 15);
+    }
+
+    #[test]
+    fn test_nwc_timer_irq() {
+        let mut nwc = Nwc::new();
+        nwc.set_dip_switches(0);
+        // Timer runs while the IRQ bit is clear
+        nwc.write_chr0(0x00);
+        nwc.clock_cpu(NWC_TIMER_BASE - 1);
+        assert!(!nwc.irq_pending());
+        nwc.clock_cpu(1);
+        assert!(nwc.irq_pending());
+
+        // Setting the IRQ bit acknowledges and resets the timer
+        nwc.write_chr0(0x10);
+        assert!(!nwc.irq_pending());
+        assert_eq!(nwc.remaining_cycles(), NWC_TIMER_BASE);
+    }
+
+    #[test]
+    fn test_bandai_fcg_prg_banks_and_irq() {
+        let mut mapper = MapperState::for_number(16);
+        mapper.write(0x8008, 0x03);
+        assert_eq!(mapper.prg_offset(0x8000, 256 * 1024), 3 * PRG_BANK_16K);
+        assert_eq!(mapper.prg_offset(0xC000, 256 * 1024), 15 * PRG_BANK_16K);
+
+        // LZ93D50: latch 100, then enable to load the counter
+        mapper.write(0x800B, 100);
+        mapper.write(0x800C, 0);
+        mapper.write(0x800A, 0x01);
+        mapper.clock_cpu(100);
+        assert!(!mapper.irq_pending());
+        mapper.clock_cpu(1);
+        assert!(mapper.irq_pending());
+        mapper.write(0x800A, 0x00);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_bandai_fcg_eeprom_battery_data() {
+        let mut mapper = MapperState::for_number(159);
+        assert_eq!(mapper.battery_data().map(<[u8]>::len), Some(128));
+        mapper.load_battery_data(&[0x5A; 4]);
+        assert_eq!(&mapper.battery_data().unwrap()[..5], &[0x5A, 0x5A, 0x5A, 0x5A, 0xFF]);
+        // Idle EEPROM releases SDA, read back as bit 4
+        assert_eq!(mapper.read(0x6000), Some(0x10));
+        assert_eq!(MapperState::for_number(0).battery_data(), None);
+    }
+
+    #[test]
+    fn test_mmc1_surom_outer_bank() {
+        let mut mapper = MapperState::for_number(1);
+        // Power-on: last bank of the first 256KB half at $C000
+        assert_eq!(mapper.prg_offset(0xC000, PRG_512K) / PRG_BANK_16K, 15);
+
+        // CHR bank 0 bit 4 selects the upper 256KB, including the fixed bank
+        mmc1_write(&mut mapper, 0xA000, 0x10);
+        mmc1_write(&mut mapper, 0xE000, 0x02);
+        let bank = |addr| mapper.prg_offset(addr, PRG_512K) / PRG_BANK_16K;
+        assert_eq!(bank(0x8000), 16 + 2);
+        assert_eq!(bank(0xC000), 31);
+    }
+
+    #[test]
+    fn test_mmc1_prg_ram_banking() {
+        let mut mapper = MapperState::for_number(1);
+        // SXROM (32KB): bits 2-3 select the bank
+        mmc1_write(&mut mapper, 0xA000, 0x0C);
+        assert_eq!(mapper.prg_ram_offset(0x6001, 32 * 1024), Some(3 * PRG_RAM_BANK_8K + 1));
+        // SOROM (16KB): bit 3 selects the bank
+        assert_eq!(mapper.prg_ram_offset(0x6001, 16 * 1024), Some(PRG_RAM_BANK_8K + 1));
+        assert_eq!(mapper.prg_ram_offset(0x6001, 8 * 1024), Some(1));
+
+        // PRG bit 4 disables the RAM
+        mmc1_write(&mut mapper, 0xE000, 0x10);
+        assert_eq!(mapper.prg_ram_offset(0x6001, 8 * 1024), None);
+    }
+
+    #[test]
+    fn test_unrom512_banks_and_mirroring() {
+        let mut mapper = MapperState::UnRom512(UnRom512::new(false, 0x08));
+        mapper.write(0x8000, 0x80 | 0x40 | 0x03);
+        assert_eq!(mapper.prg_offset(0x8010, PRG_512K), 3 * PRG_BANK_16K + 0x10);
+        assert_eq!(mapper.prg_offset(0xFFFC, PRG_512K), PRG_512K - 4);
+        let MapperState::UnRom512(board) = &mapper else { unreachable!() };
+        assert_eq!(board.chr, 2);
+        assert_eq!(board.mirroring(), 3);
+        assert_eq!(UnRom512::new(false, 0x01).mirroring(), 1);
+        assert_eq!(UnRom512::new(false, 0x09).mirroring(), 4);
+        assert!(!mapper.flashable_prg());
+    }
+
+    #[test]
+    fn test_unrom512_flash_commands() {
+        let mut mapper = MapperState::UnRom512(UnRom512::new(true, 0));
+        assert!(mapper.flashable_prg());
+        let command = |mapper: &mut MapperState, bank: u8, address: u16, value: u8| {
+            mapper.write(0xC000, bank);
+            mapper.write(address, value);
+        };
+        let unlock = |mapper: &mut MapperState| {
+            command(mapper, 1, 0x9555, 0xAA);
+            command(mapper, 0, 0xAAAA, 0x55);
+        };
+
+        // Program a byte in bank 4
+        unlock(&mut mapper);
+        command(&mut mapper, 1, 0x9555, 0xA0);
+        command(&mut mapper, 4, 0x8123, 0x42);
+        assert_eq!(mapper.take_flash_op(), Some(FlashOp::Program { offset: 4 * PRG_BANK_16K + 0x123, value: 0x42 }));
+        assert_eq!(mapper.take_flash_op(), None);
+
+        // Sector erase is aligned to the sector
+        unlock(&mut mapper);
+        command(&mut mapper, 1, 0x9555, 0x80);
+        unlock(&mut mapper);
+        command(&mut mapper, 2, 0x9234, 0x30);
+        assert_eq!(mapper.take_flash_op(), Some(FlashOp::EraseSector { offset: 2 * PRG_BANK_16K + 0x1000 }));
+
+        // A wrong byte aborts the sequence; the bank register still works
+        unlock(&mut mapper);
+        command(&mut mapper, 1, 0x9555, 0x12);
+        command(&mut mapper, 1, 0x8000, 0x00);
+        assert_eq!(mapper.take_flash_op(), None);
+        assert_eq!(mapper.prg_offset(0x8000, PRG_512K), PRG_BANK_16K);
+    }
+
+    #[test]
+    fn test_submapper_bus_conflicts() {
+        assert_eq!(MapperState::submapper_bus_conflicts(2, 2), Some(true));
+        assert_eq!(MapperState::submapper_bus_conflicts(7, 1), Some(false));
+        assert_eq!(MapperState::submapper_bus_conflicts(3, 0), None);
+        assert_eq!(MapperState::submapper_bus_conflicts(1, 2), None);
+        assert!(!MapperState::for_number(2).bus_conflicts());
+        assert!(MapperState::for_number(66).bus_conflicts());
+    }
+}
@@ -0,0 +1,87 @@
+//! Cartridge interface shared with the legacy desktop app
+//!
+//! `SimpleCartridge` implements `MapperInterface` for the bus, and the
+//! legacy egui app in `src/` implements it for its own mappers. That app
+//! includes this file by path, so it must stay std-only and not refer to
+//! anything else in the crate.
+
+use std::fmt;
+
+/// How the four logical nametables ($2000, $2400, $2800, $2C00) map onto
+/// the console's 2KB of nametable RAM (or the cartridge's extra 2KB)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    /// $2000 = $2400 and $2800 = $2C00 (vertical scrolling games)
+    Horizontal,
+    /// $2000 = $2800 and $2400 = $2C00 (horizontal scrolling games)
+    Vertical,
+    /// All four show the first 1KB page
+    SingleScreenA,
+    /// All four show the second 1KB page
+    SingleScreenB,
+    /// Four separate nametables, using RAM on the cartridge
+    FourScreen,
+}
+
+impl Mirroring {
+    /// Physical 1KB page (0-3, only 0-1 without four-screen RAM) behind logical nametable 0-3
+    pub fn nametable_page(self, nametable: usize) -> usize {
+        let nametable = nametable & 0x03;
+        match self {
+            Mirroring::Horizontal => nametable >> 1,
+            Mirroring::Vertical => nametable & 1,
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
+            Mirroring::FourScreen => nametable,
+        }
+    }
+}
+
+/// A cartridge board as the CPU and PPU see it
+///
+/// `read_low`/`write_low` cover $4020-$7FFF (PRG RAM and the registers some
+/// boards decode there), `read_prg`/`write_prg` cover $8000-$FFFF, where
+/// writes go to the mapper registers, and `read_chr`/`write_chr` cover the
+/// PPU's $0000-$1FFF. Mirroring is asked for on every nametable access since
+/// boards like MMC1 and MMC3 switch it at runtime.
+pub trait MapperInterface: fmt::Debug {
+    /// Return the mapper registers to their power-on state
+    fn reset(&mut self);
+    /// Read from $4020-$7FFF
+    fn read_low(&mut self, address: u16) -> u8;
+    /// Write to $4020-$7FFF
+    fn write_low(&mut self, address: u16, value: u8);
+    /// Read PRG ROM at $8000-$FFFF through the current banks
+    fn read_prg(&mut self, address: u16) -> u8;
+    /// Write to $8000-$FFFF (the mapper registers)
+    fn write_prg(&mut self, address: u16, value: u8);
+    /// Read the pattern tables at PPU $0000-$1FFF
+    fn read_chr(&mut self, address: u16) -> u8;
+    /// Write the pattern tables; boards with CHR RAM store it, others ignore it
+    fn write_chr(&mut self, address: u16, value: u8);
+    /// Nametable mirroring currently in effect
+    fn mirroring(&self) -> Mirroring;
+
+    /// Check if the board is asserting its IRQ line
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Rising edge of PPU A12 (once per scanline with the usual pattern table setup)
+    fn ppu_a12_clock(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nametable_pages() {
+        let pages = |mirroring: Mirroring| (0..4).map(|nametable| mirroring.nametable_page(nametable)).collect::<Vec<_>>();
+        assert_eq!(pages(Mirroring::Horizontal), [0, 0, 1, 1]);
+        assert_eq!(pages(Mirroring::Vertical), [0, 1, 0, 1]);
+        assert_eq!(pages(Mirroring::SingleScreenA), [0, 0, 0, 0]);
+        assert_eq!(pages(Mirroring::SingleScreenB), [1, 1, 1, 1]);
+        assert_eq!(pages(Mirroring::FourScreen), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Which iNES mappers the core supports, for compatibility hints
+//!
+//! `supported()` lists every board the core has dedicated support for, plus
+//! the common boards it doesn't, so a frontend can warn before launching a
+//! ROM and documentation can be generated from the same table. Mappers that
+//! aren't listed are reported by `lookup` as unknown and unsupported; the
+//! core runs them as NROM, which rarely works.
+//!
+//! The table is checked against `MapperState` in tests, so a board added
+//! there without an entry here (or the other way round) fails the build.
+
+use std::fmt;
+
+/// How well a mapper is emulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SupportLevel {
+    /// Not emulated; the ROM runs as NROM and probably won't work
+    None,
+    /// Emulated, with the gaps listed in the notes
+    Partial,
+    /// Emulated completely as far as known games need it
+    Full,
+}
+
+impl SupportLevel {
+    /// Short label for UIs
+    pub fn name(self) -> &'static str {
+        match self {
+            SupportLevel::None => "none",
+            SupportLevel::Partial => "partial",
+            SupportLevel::Full => "full",
+        }
+    }
+}
+
+impl fmt::Display for SupportLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Support status of one iNES mapper number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapperSupport {
+    /// iNES mapper number
+    pub id: u8,
+    /// Common board name
+    pub name: &'static str,
+    /// How well it is emulated
+    pub level: SupportLevel,
+    /// Variants covered and known gaps (empty when there is nothing to add)
+    pub notes: &'static str,
+}
+
+impl fmt::Display for MapperSupport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mapper {} ({}): {}", self.id, self.name, self.level)?;
+        if !self.notes.is_empty() {
+            write!(f, " - {}", self.notes)?;
+        }
+        Ok(())
+    }
+}
+
+const fn entry(id: u8, name: &'static str, level: SupportLevel, notes: &'static str) -> MapperSupport {
+    MapperSupport { id, name, level, notes }
+}
+
+/// Every listed mapper, by number
+const MAPPERS: &[MapperSupport] = &[
+    entry(0, "NROM", SupportLevel::Full, ""),
+    entry(1, "MMC1", SupportLevel::Full, "includes SUROM/SXROM 512KB PRG and SOROM/SXROM banked PRG RAM"),
+    entry(2, "UxROM", SupportLevel::Full, "bus conflicts from the NES 2.0 submapper or ROM database"),
+    entry(3, "CNROM", SupportLevel::Full, "bus conflicts from the NES 2.0 submapper or ROM database"),
+    entry(4, "MMC3", SupportLevel::None, ""),
+    entry(5, "MMC5", SupportLevel::None, ""),
+    entry(7, "AxROM", SupportLevel::Full, "bus conflicts from the NES 2.0 submapper or ROM database"),
+    entry(9, "MMC2", SupportLevel::None, ""),
+    entry(10, "MMC4", SupportLevel::None, ""),
+    entry(11, "Color Dreams", SupportLevel::Full, ""),
+    entry(16, "Bandai FCG / LZ93D50", SupportLevel::Full, "24C02 EEPROM saves"),
+    entry(19, "Namco 163", SupportLevel::None, ""),
+    entry(24, "VRC6a", SupportLevel::None, ""),
+    entry(26, "VRC6b", SupportLevel::None, ""),
+    entry(28, "Action 53", SupportLevel::Full, ""),
+    entry(
+        30,
+        "UNROM 512",
+        SupportLevel::Partial,
+        "flash saves work; the flash chip's software ID mode isn't emulated",
+    ),
+    entry(34, "BNROM / NINA-001", SupportLevel::Full, "told apart by CHR RAM or CHR ROM"),
+    entry(66, "GxROM", SupportLevel::Full, ""),
+    entry(69, "Sunsoft FME-7", SupportLevel::None, ""),
+    entry(71, "Camerica", SupportLevel::None, ""),
+    entry(85, "VRC7", SupportLevel::None, ""),
+    entry(105, "NES-EVENT", SupportLevel::Full, "countdown timer with DIP switches"),
+    entry(140, "Jaleco JF-11/JF-14", SupportLevel::Full, ""),
+    entry(159, "Bandai LZ93D50 (X24C01)", SupportLevel::Full, "X24C01 EEPROM saves"),
+];
+
+/// List the supported mappers and common unsupported ones, by number
+pub fn supported() -> Vec<MapperSupport> {
+    MAPPERS.to_vec()
+}
+
+/// Look up a mapper number (unlisted numbers are reported as unsupported)
+pub fn lookup(id: u8) -> MapperSupport {
+    MAPPERS.iter().find(|mapper| mapper.id == id).copied().unwrap_or(entry(id, "unknown", SupportLevel::None, ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::MapperState;
+
+    #[test]
+    fn test_table_matches_mapper_state() {
+        assert!(MAPPERS.windows(2).all(|pair| pair[0].id < pair[1].id));
+        for id in 0..=u8::MAX {
+            let mapper = lookup(id);
+            assert_eq!(mapper.level != SupportLevel::None, MapperState::supports(id), "{}", mapper);
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup(30).level, SupportLevel::Partial);
+        assert_eq!(lookup(4).to_string(), "mapper 4 (MMC3): none");
+        assert_eq!(lookup(200).name, "unknown");
+        assert!(supported().iter().any(|mapper| mapper.id == 1 && mapper.level == SupportLevel::Full));
+    }
+}
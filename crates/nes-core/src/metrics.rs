@@ -0,0 +1,115 @@
+//! Per-subsystem timing metrics
+//!
+//! When enabled on a `NesSystem`, each step measures the wall-clock time spent
+//! in the CPU, PPU, APU and mapper, so benchmarks can show where time goes.
+//! Measuring adds overhead and uses `std::time::Instant`, which is unavailable
+//! on `wasm32-unknown-unknown`; metrics are disabled by default.
+
+use std::time::Duration;
+
+/// Emulated subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Instruction fetch, decode and execution (including tracing)
+    Cpu,
+    /// PPU stepping and register synchronization
+    Ppu,
+    Apu,
+    /// Cartridge mapper timers
+    Mapper,
+}
+
+impl Subsystem {
+    /// All subsystems, in reporting order
+    pub const ALL: [Subsystem; 4] = [Subsystem::Cpu, Subsystem::Ppu, Subsystem::Apu, Subsystem::Mapper];
+
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Cpu => "CPU",
+            Subsystem::Ppu => "PPU",
+            Subsystem::Apu => "APU",
+            Subsystem::Mapper => "Mapper",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Accumulated time per subsystem
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    times: [Duration; 4],
+    steps: u64,
+}
+
+impl Metrics {
+    /// Create empty metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add time spent in a subsystem
+    pub fn record(&mut self, subsystem: Subsystem, elapsed: Duration) {
+        self.times[subsystem.index()] += elapsed;
+    }
+
+    /// Count one system step
+    pub fn count_step(&mut self) {
+        self.steps += 1;
+    }
+
+    /// Time spent in a subsystem
+    pub fn time(&self, subsystem: Subsystem) -> Duration {
+        self.times[subsystem.index()]
+    }
+
+    /// Total measured time across subsystems
+    pub fn total(&self) -> Duration {
+        self.times.iter().sum()
+    }
+
+    /// Number of system steps measured
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Each subsystem with its time and share of the total (0.0-1.0)
+    pub fn breakdown(&self) -> impl Iterator<Item = (Subsystem, Duration, f64)> + '_ {
+        let total = self.total().as_secs_f64();
+        Subsystem::ALL.into_iter().map(move |subsystem| {
+            let time = self.time(subsystem);
+            let share = if total > 0.0 { time.as_secs_f64() / total } else { 0.0 };
+            (subsystem, time, share)
+        })
+    }
+
+    /// Clear all measurements
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakdown_shares() {
+        let mut metrics = Metrics::new();
+        metrics.record(Subsystem::Cpu, Duration::from_millis(30));
+        metrics.record(Subsystem::Ppu, Duration::from_millis(60));
+        metrics.record(Subsystem::Apu, Duration::from_millis(10));
+        assert_eq!(metrics.total(), Duration::from_millis(100));
+
+        let shares: Vec<(&str, f64)> = metrics.breakdown().map(|(s, _, share)| (s.name(), share)).collect();
+        assert_eq!(shares[1].0, "PPU");
+        assert!((shares[1].1 - 0.6).abs() < 1e-9);
+        assert_eq!(shares[3].1, 0.0);
+
+        metrics.reset();
+        assert_eq!(metrics.total(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,286 @@
+//! Integer APU mixer
+//!
+//! The 2A03 mixes its channels through two nonlinear resistor networks:
+//!
+//! ```text
+//! pulse_out = 95.88  / (8128 / (pulse1 + pulse2) + 100)
+//! tnd_out   = 163.67 / (24329 / (3 * triangle + 2 * noise + dmc) + 100)
+//! ```
+//!
+//! Evaluating that in floating point gives results that can differ in the
+//! last bit between x86, ARM and WASM, which is enough to desync netplay or
+//! fail a movie's checksum. This mixer only uses integer arithmetic: both
+//! formulas are rewritten as ratios of integers (`9588 n / (812800 + 10000 n)`
+//! and `16367 n / (2432900 + 10000 n)`) and evaluated into fixed-point lookup
+//! tables at compile time, so every platform produces the same samples.
+//!
+//! Output is unsigned-style: silence is 0 and all channels at full volume
+//! reach `MIX_SCALE` (the networks peak about 0.1% above 1.0, which is
+//! clipped). Removing the DC offset is left to the output stage.
+//! Frontends that need floats convert at the very end with
+//! `sample_format::from_i16`, outside the deterministic path.
+//!
+//! `mix` reproduces the raw signal. `Mixer` wraps it with optional pop
+//! suppression: games that write $4011 directly make the DMC output jump by
+//! up to 127 steps at once, and a triangle cut off mid-wave drops straight
+//! to zero. Both are audible clicks the hardware really makes, so
+//! suppression is off by default; when enabled, those jumps are spread over
+//! a short linear crossfade (still in integer fixed point) while the
+//! channels' normal one- and two-step moves pass through untouched.
+
+/// Fixed-point value of a mixed output of 1.0
+pub const MIX_SCALE: i64 = i16::MAX as i64;
+
+/// Entries in the pulse table (pulse1 + pulse2, 0-30)
+pub const PULSE_TABLE_LEN: usize = 31;
+/// Entries in the triangle/noise/DMC table (3 * triangle + 2 * noise + dmc, 0-202)
+pub const TND_TABLE_LEN: usize = 203;
+
+/// `numerator * n / (base + 10000 * n)` scaled by `MIX_SCALE`, rounded to nearest
+const fn mix_entry(numerator: i64, base: i64, n: i64) -> i16 {
+    let num = numerator * n * MIX_SCALE;
+    let den = base + 10_000 * n;
+    ((2 * num + den) / (2 * den)) as i16
+}
+
+const fn table<const N: usize>(numerator: i64, base: i64) -> [i16; N] {
+    let mut table = [0; N];
+    let mut n = 0;
+    while n < N {
+        table[n] = mix_entry(numerator, base, n as i64);
+        n += 1;
+    }
+    table
+}
+
+/// Pulse network output, indexed by pulse1 + pulse2
+pub const PULSE_TABLE: [i16; PULSE_TABLE_LEN] = table(9_588, 812_800);
+/// Triangle/noise/DMC network output, indexed by 3 * triangle + 2 * noise + dmc
+pub const TND_TABLE: [i16; TND_TABLE_LEN] = table(16_367, 2_432_900);
+
+/// Current output level of each APU channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelLevels {
+    /// 0-15
+    pub pulse1: u8,
+    /// 0-15
+    pub pulse2: u8,
+    /// 0-15
+    pub triangle: u8,
+    /// 0-15
+    pub noise: u8,
+    /// 0-127
+    pub dmc: u8,
+}
+
+/// Mix channel levels into one sample (0-`MIX_SCALE`); out-of-range levels are clamped
+pub fn mix(levels: ChannelLevels) -> i16 {
+    let pulse = levels.pulse1.min(15) as usize + levels.pulse2.min(15) as usize;
+    let tnd = 3 * levels.triangle.min(15) as usize + 2 * levels.noise.min(15) as usize + levels.dmc.min(127) as usize;
+    PULSE_TABLE[pulse].saturating_add(TND_TABLE[tnd])
+}
+
+/// Default crossfade length, in samples (about 1.5 ms at 44.1 kHz)
+pub const DEFAULT_CROSSFADE_SAMPLES: u16 = 64;
+
+/// Fractional bits of the faded channel levels
+const FADE_FRACTION_BITS: u32 = 8;
+
+/// Which discontinuities `Mixer` smooths over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PopSuppression {
+    /// Crossfade DMC output jumps (direct $4011 loads)
+    pub dmc: bool,
+    /// Crossfade triangle output jumps (the channel silenced mid-wave)
+    pub triangle: bool,
+}
+
+impl PopSuppression {
+    /// The raw signal
+    pub const OFF: PopSuppression = PopSuppression { dmc: false, triangle: false };
+    /// Suppress every kind of pop
+    pub const ALL: PopSuppression = PopSuppression { dmc: true, triangle: true };
+}
+
+/// One channel's level, following the raw level or crossfading to it
+#[derive(Debug, Clone, Copy, Default)]
+struct Fade {
+    /// Raw level of the previous sample
+    last: u8,
+    /// Output level in fixed point
+    current: i32,
+    /// Samples left in the running crossfade
+    remaining: u16,
+}
+
+impl Fade {
+    /// Advance one sample; a move of more than `max_step` starts a crossfade
+    fn next(&mut self, level: u8, max_step: u8, enabled: bool, crossfade: u16) -> i32 {
+        if enabled && level.abs_diff(self.last) > max_step {
+            self.remaining = crossfade;
+        }
+        self.last = level;
+        let target = (level as i32) << FADE_FRACTION_BITS;
+        if self.remaining > 0 {
+            // A linear approach to the (possibly still moving) target that lands on the last sample
+            self.current += (target - self.current) / self.remaining as i32;
+            self.remaining -= 1;
+        } else {
+            self.current = target;
+        }
+        self.current
+    }
+}
+
+/// Mixer with optional pop suppression
+///
+/// Feed it one `ChannelLevels` per output sample. With suppression off it
+/// returns exactly what `mix` does.
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    suppression: PopSuppression,
+    crossfade: u16,
+    triangle: Fade,
+    dmc: Fade,
+}
+
+impl Mixer {
+    /// Create a mixer producing the raw signal
+    pub fn new() -> Self {
+        Self {
+            suppression: PopSuppression::OFF,
+            crossfade: DEFAULT_CROSSFADE_SAMPLES,
+            triangle: Fade::default(),
+            dmc: Fade::default(),
+        }
+    }
+
+    /// Set which pops are suppressed
+    pub fn with_pop_suppression(mut self, suppression: PopSuppression) -> Self {
+        self.suppression = suppression;
+        self
+    }
+
+    /// Get which pops are suppressed
+    pub fn pop_suppression(&self) -> PopSuppression {
+        self.suppression
+    }
+
+    /// Change which pops are suppressed (a running crossfade finishes either way)
+    pub fn set_pop_suppression(&mut self, suppression: PopSuppression) {
+        self.suppression = suppression;
+    }
+
+    /// Get the crossfade length in samples
+    pub fn crossfade_samples(&self) -> u16 {
+        self.crossfade
+    }
+
+    /// Set the crossfade length in samples (at least 1)
+    pub fn set_crossfade_samples(&mut self, samples: u16) {
+        self.crossfade = samples.max(1);
+    }
+
+    /// Forget the previous levels, e.g. after a reset or loading a state
+    pub fn reset(&mut self) {
+        self.triangle = Fade::default();
+        self.dmc = Fade::default();
+    }
+
+    /// Mix one sample (0-`MIX_SCALE`); out-of-range levels are clamped
+    pub fn mix(&mut self, levels: ChannelLevels) -> i16 {
+        let triangle = levels.triangle.min(15);
+        let dmc = levels.dmc.min(127);
+        let triangle = self.triangle.next(triangle, 1, self.suppression.triangle, self.crossfade);
+        let dmc = self.dmc.next(dmc, 2, self.suppression.dmc, self.crossfade);
+
+        let pulse = levels.pulse1.min(15) as usize + levels.pulse2.min(15) as usize;
+        let noise = (levels.noise.min(15) as i32) << FADE_FRACTION_BITS;
+        // Interpolate between table entries for the fractional index
+        let tnd = (3 * triangle + 2 * noise + dmc) as usize;
+        let index = tnd >> FADE_FRACTION_BITS;
+        let fraction = (tnd & ((1 << FADE_FRACTION_BITS) - 1)) as i32;
+        let low = TND_TABLE[index] as i32;
+        let high = TND_TABLE[(index + 1).min(TND_TABLE_LEN - 1)] as i32;
+        let tnd = low + (((high - low) * fraction) >> FADE_FRACTION_BITS);
+        PULSE_TABLE[pulse].saturating_add(tnd as i16)
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables_match_hardware_formula() {
+        // The float formulas are only evaluated here, as the reference
+        for (n, &value) in PULSE_TABLE.iter().enumerate().skip(1) {
+            let expected = 95.88 / (8128.0 / n as f64 + 100.0) * MIX_SCALE as f64;
+            assert!((value as f64 - expected).abs() <= 1.0, "pulse {}: {} vs {}", n, value, expected);
+        }
+        for (n, &value) in TND_TABLE.iter().enumerate().skip(1) {
+            let expected = 163.67 / (24329.0 / n as f64 + 100.0) * MIX_SCALE as f64;
+            assert!((value as f64 - expected).abs() <= 1.0, "tnd {}: {} vs {}", n, value, expected);
+        }
+        assert_eq!((PULSE_TABLE[0], TND_TABLE[0]), (0, 0));
+        assert!(PULSE_TABLE.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(TND_TABLE.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_mix() {
+        assert_eq!(mix(ChannelLevels::default()), 0);
+        let full = ChannelLevels { pulse1: 15, pulse2: 15, triangle: 15, noise: 15, dmc: 127 };
+        assert_eq!(mix(full) as i64, MIX_SCALE);
+        let quiet = ChannelLevels { pulse1: 1, triangle: 2, ..ChannelLevels::default() };
+        assert_eq!(mix(quiet), PULSE_TABLE[1] + TND_TABLE[6]);
+        let over = ChannelLevels { pulse1: 200, pulse2: 200, triangle: 200, noise: 200, dmc: 255 };
+        assert_eq!(mix(over), mix(full));
+    }
+
+    #[test]
+    fn test_pop_suppression() {
+        let quiet = ChannelLevels { pulse1: 4, dmc: 64, ..ChannelLevels::default() };
+        let mut raw = Mixer::new();
+        let mut smooth = Mixer::new().with_pop_suppression(PopSuppression::ALL);
+        smooth.set_crossfade_samples(4);
+        // Starting at $40 is itself a jump from silence
+        for _ in 0..3 {
+            assert_eq!(raw.mix(quiet), mix(quiet));
+            assert!(smooth.mix(quiet) < mix(quiet));
+        }
+        assert_eq!(smooth.mix(quiet), mix(quiet));
+
+        // A direct $4011 load: the raw mixer jumps, the smooth one takes 4 samples
+        let loaded = ChannelLevels { dmc: 0, ..quiet };
+        assert_eq!(raw.mix(loaded), mix(loaded));
+        let mut last = mix(quiet);
+        for _ in 0..3 {
+            let sample = smooth.mix(loaded);
+            assert!(sample < last && sample > mix(loaded));
+            last = sample;
+        }
+        assert_eq!(smooth.mix(loaded), mix(loaded));
+
+        // Normal DMC deltas and triangle steps are not smoothed
+        let stepped = ChannelLevels { dmc: 2, triangle: 1, ..loaded };
+        assert_eq!(smooth.mix(stepped), mix(stepped));
+
+        // The triangle cut off mid-wave, with only DMC suppression enabled
+        smooth.set_pop_suppression(PopSuppression { dmc: true, triangle: false });
+        let playing = ChannelLevels { triangle: 12, ..stepped };
+        smooth.mix(ChannelLevels { triangle: 11, ..stepped });
+        assert_eq!(smooth.mix(playing), mix(playing));
+        assert_eq!(smooth.mix(stepped), mix(stepped));
+        smooth.set_pop_suppression(PopSuppression::ALL);
+        smooth.mix(ChannelLevels { triangle: 11, ..stepped });
+        smooth.mix(playing);
+        assert!(smooth.mix(stepped) > mix(stepped));
+    }
+}
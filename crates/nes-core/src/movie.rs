@@ -0,0 +1,200 @@
+//! FM2 movie parsing for input playback
+//!
+//! FM2 is the text movie format used by FCEUX and most NES TAS tooling. A
+//! movie is a list of `key value` header lines followed by one input line per
+//! frame:
+//!
+//! ```text
+//! |0|RLDUTSBA|........||
+//! ```
+//!
+//! The first field holds command bits (soft/hard reset), followed by one field
+//! per port. A button is pressed when its column holds anything other than
+//! `.` or a space.
+
+use crate::controller::Buttons;
+use crate::system::NesSystem;
+use std::fmt;
+
+/// Command bit: soft reset before this frame
+pub const COMMAND_SOFT_RESET: u8 = 0x01;
+/// Command bit: hard reset (power cycle) before this frame
+pub const COMMAND_HARD_RESET: u8 = 0x02;
+
+/// Button order of an FM2 port field (leftmost column first)
+const FM2_BUTTON_ORDER: [u8; 8] = [
+    Buttons::RIGHT,
+    Buttons::LEFT,
+    Buttons::DOWN,
+    Buttons::UP,
+    Buttons::START,
+    Buttons::SELECT,
+    Buttons::B,
+    Buttons::A,
+];
+
+/// Input for a single frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MovieFrame {
+    /// Command bits (`COMMAND_SOFT_RESET`, `COMMAND_HARD_RESET`)
+    pub commands: u8,
+    /// Controller state for ports 1 and 2
+    pub ports: [Buttons; 2],
+}
+
+impl MovieFrame {
+    /// Apply this frame's commands and input to the system (call before running the frame)
+    pub fn apply(&self, system: &mut NesSystem) {
+        if self.commands & COMMAND_HARD_RESET != 0 {
+            system.power_cycle();
+        } else if self.commands & COMMAND_SOFT_RESET != 0 {
+            system.reset();
+        }
+        for (port, buttons) in self.ports.iter().enumerate() {
+            system.set_buttons(port, *buttons);
+        }
+    }
+}
+
+/// A parsed input movie
+#[derive(Debug, Clone, Default)]
+pub struct Movie {
+    /// Header key/value pairs in file order
+    pub header: Vec<(String, String)>,
+    /// Per-frame input
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Parse a text FM2 movie
+    pub fn parse_fm2(text: &str) -> Result<Self, MovieError> {
+        let mut movie = Movie::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('|') {
+                movie.frames.push(parse_input_line(line, line_number)?);
+            } else {
+                let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+                movie.header.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        if movie.header_value("binary").is_some_and(|v| v != "0") {
+            return Err(MovieError::Unsupported("binary FM2 input logs"));
+        }
+        Ok(movie)
+    }
+
+    /// Get the first header value with the given key
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Number of frames of input
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check if the movie has no input frames
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+fn parse_input_line(line: &str, line_number: usize) -> Result<MovieFrame, MovieError> {
+    let invalid = |reason| MovieError::InvalidLine { line: line_number, reason };
+
+    // "|c|port0|port1|port2|" splits into ["", c, port0, port1, port2, ""]
+    let mut fields = line.split('|').skip(1);
+    let commands = fields
+        .next()
+        .ok_or(invalid("missing command field"))?
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| invalid("invalid command field"))?;
+
+    let mut frame = MovieFrame {
+        commands,
+        ports: [Buttons::default(); 2],
+    };
+    for port in frame.ports.iter_mut() {
+        let field = fields.next().unwrap_or("");
+        if field.is_empty() {
+            continue;
+        }
+        if field.len() != FM2_BUTTON_ORDER.len() {
+            return Err(invalid("gamepad field must have 8 columns"));
+        }
+        let mut bits = 0;
+        for (column, &button) in field.bytes().zip(FM2_BUTTON_ORDER.iter()) {
+            if column != b'.' && column != b' ' {
+                bits |= button;
+            }
+        }
+        *port = Buttons::new(bits);
+    }
+    Ok(frame)
+}
+
+/// Movie parsing error types
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MovieError {
+    /// The movie uses a feature this parser does not handle
+    Unsupported(&'static str),
+    /// An input line could not be parsed
+    InvalidLine { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Unsupported(what) => write!(f, "Unsupported movie: {}", what),
+            MovieError::InvalidLine { line, reason } => write!(f, "Invalid movie line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fm2() {
+        let text = "version 3\nromFilename game\nport0 1\n\
+                    |2|........|||\n\
+                    |0|R......A|.......A||\r\n\
+                    |1|...T....|||\n";
+        let movie = Movie::parse_fm2(text).unwrap();
+
+        assert_eq!(movie.header_value("romFilename"), Some("game"));
+        assert_eq!(movie.len(), 3);
+        assert_eq!(movie.frames[0].commands, COMMAND_HARD_RESET);
+        assert_eq!(movie.frames[1].ports[0].bits(), Buttons::RIGHT | Buttons::A);
+        assert_eq!(movie.frames[1].ports[1].bits(), Buttons::A);
+        assert_eq!(movie.frames[2].ports[0].bits(), Buttons::UP);
+        assert_eq!(movie.frames[2].commands, COMMAND_SOFT_RESET);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Movie::parse_fm2("version 3\n|0|RL|||\n"),
+            Err(MovieError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            Movie::parse_fm2("binary 1\n"),
+            Err(MovieError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+//! On-screen display messages for frontends
+//!
+//! Frontends post short transient messages ("State 3 saved", "Rewinding") with
+//! `Osd::show`, call `tick` once per displayed frame, and either draw the active
+//! messages over the 256x240 frame with `draw` or render them natively by
+//! polling newly posted messages with `poll`.
+
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use std::collections::VecDeque;
+
+/// Default message lifetime in frames (about two seconds at 60Hz)
+pub const DEFAULT_DURATION_FRAMES: u32 = 120;
+
+/// Maximum number of messages drawn at once (oldest are dropped first)
+const MAX_VISIBLE: usize = 4;
+
+/// Maximum number of posted messages kept for `poll`
+const MAX_POSTED: usize = 32;
+
+/// Glyph size in pixels
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Line height including padding
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 3;
+
+/// A message currently on screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OsdMessage {
+    /// Message text
+    pub text: String,
+    /// Frames left before the message disappears
+    pub remaining_frames: u32,
+}
+
+/// On-screen display message queue
+#[derive(Debug, Clone, Default)]
+pub struct Osd {
+    active: VecDeque<OsdMessage>,
+    posted: VecDeque<String>,
+}
+
+impl Osd {
+    /// Create an empty OSD
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show a message for the default duration
+    pub fn show(&mut self, text: impl Into<String>) {
+        self.show_for(text, DEFAULT_DURATION_FRAMES);
+    }
+
+    /// Show a message for the given number of frames
+    pub fn show_for(&mut self, text: impl Into<String>, frames: u32) {
+        let text = text.into();
+        if self.posted.len() == MAX_POSTED {
+            self.posted.pop_front();
+        }
+        self.posted.push_back(text.clone());
+        if self.active.len() == MAX_VISIBLE {
+            self.active.pop_front();
+        }
+        self.active.push_back(OsdMessage {
+            text,
+            remaining_frames: frames,
+        });
+    }
+
+    /// Advance one frame, expiring old messages
+    pub fn tick(&mut self) {
+        for message in &mut self.active {
+            message.remaining_frames = message.remaining_frames.saturating_sub(1);
+        }
+        self.active.retain(|m| m.remaining_frames > 0);
+    }
+
+    /// Get the messages currently on screen, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &OsdMessage> {
+        self.active.iter()
+    }
+
+    /// Take the oldest message posted since the last poll (for frontends that render text themselves)
+    pub fn poll(&mut self) -> Option<String> {
+        self.posted.pop_front()
+    }
+
+    /// Remove all messages
+    pub fn clear(&mut self) {
+        self.active.clear();
+        self.posted.clear();
+    }
+
+    /// Draw the active messages over a 256x240 RGB framebuffer, newest at the bottom
+    pub fn draw(&self, framebuffer: &mut [u8]) {
+        if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+            return;
+        }
+        let count = self.active.len();
+        for (line, message) in self.active.iter().enumerate() {
+            let y = FRAME_HEIGHT - (count - line) * LINE_HEIGHT - 2;
+            draw_text(framebuffer, 4, y, &message.text);
+        }
+    }
+}
+
+/// Draw one line of text on a dimmed background box (`x` at least 1)
+///
+/// Text past the right edge of the 256x240 RGB framebuffer is cut off.
+pub fn draw_text(framebuffer: &mut [u8], x: usize, y: usize, text: &str) {
+    let max_chars = (FRAME_WIDTH - x - 2) / (GLYPH_WIDTH + 1);
+    let chars = text.chars().count().min(max_chars);
+    if chars == 0 {
+        return;
+    }
+
+    // Dim the box behind the text for contrast
+    let box_width = chars * (GLYPH_WIDTH + 1) + 1;
+    for py in y.saturating_sub(1)..(y + GLYPH_HEIGHT + 1).min(FRAME_HEIGHT) {
+        for px in x - 1..x + box_width {
+            let idx = (py * FRAME_WIDTH + px) * 3;
+            for channel in &mut framebuffer[idx..idx + 3] {
+                *channel /= 4;
+            }
+        }
+    }
+
+    for (i, c) in text.chars().take(chars).enumerate() {
+        let glyph_x = x + i * (GLYPH_WIDTH + 1);
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0b100 >> col) != 0 {
+                    let idx = ((y + row) * FRAME_WIDTH + glyph_x + col) * 3;
+                    framebuffer[idx..idx + 3].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 bitmap glyph (one row per byte, bit 2 = leftmost column); lowercase is drawn as uppercase
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010], // '?'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::FRAME_RGB_SIZE;
+
+    #[test]
+    fn test_messages_expire() {
+        let mut osd = Osd::new();
+        osd.show_for("Short", 1);
+        osd.show("State 3 saved");
+        assert_eq!(osd.messages().count(), 2);
+
+        osd.tick();
+        let texts: Vec<_> = osd.messages().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, ["State 3 saved"]);
+
+        assert_eq!(osd.poll().as_deref(), Some("Short"));
+        assert_eq!(osd.poll().as_deref(), Some("State 3 saved"));
+        assert_eq!(osd.poll(), None);
+    }
+
+    #[test]
+    fn test_draw_overlays_text() {
+        let mut osd = Osd::new();
+        let mut framebuffer = vec![0x80; FRAME_RGB_SIZE];
+        osd.draw(&mut framebuffer);
+        assert!(framebuffer.iter().all(|&b| b == 0x80));
+
+        osd.show("Hi");
+        osd.draw(&mut framebuffer);
+        assert!(framebuffer.contains(&0xFF));
+        assert!(framebuffer.contains(&0x20));
+        // The top of the frame is untouched
+        assert!(framebuffer[..FRAME_WIDTH * 3 * 100].iter().all(|&b| b == 0x80));
+    }
+}
@@ -0,0 +1,70 @@
+//! Palette viewer
+//!
+//! Draws the 32 palette RAM entries ($3F00-$3F1F) as a grid of swatches in a
+//! 256x240 RGB frame: four background palettes in the top four rows and four
+//! sprite palettes below, one entry per column, with $3F10/$3F14/$3F18/$3F1C
+//! showing the backdrop entries they mirror. Each swatch is labelled with its
+//! address offset and master palette color. Frontends map clicks back to
+//! entries with `entry_at` and edit them with `Ppu::set_palette_entry`.
+
+use crate::osd::draw_text;
+use crate::ppu::{palette_rgb, Ppu, FRAME_HEIGHT, FRAME_WIDTH, PALETTE_SIZE};
+
+/// Entries per row (one palette)
+pub const COLUMNS: usize = 4;
+/// Rows of swatches (4 background palettes, then 4 sprite palettes)
+pub const ROWS: usize = PALETTE_SIZE / COLUMNS;
+/// Swatch width in pixels
+pub const SWATCH_WIDTH: usize = FRAME_WIDTH / COLUMNS;
+/// Swatch height in pixels
+pub const SWATCH_HEIGHT: usize = FRAME_HEIGHT / ROWS;
+
+/// Draw the palette grid into a 256x240 RGB framebuffer
+pub fn draw_palette(ppu: &Ppu, framebuffer: &mut [u8]) {
+    if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+        return;
+    }
+    for idx in 0..PALETTE_SIZE {
+        let color = ppu.palette_color(idx);
+        let (r, g, b) = palette_rgb(color);
+        let x0 = (idx % COLUMNS) * SWATCH_WIDTH;
+        let y0 = (idx / COLUMNS) * SWATCH_HEIGHT;
+        // One-pixel gap on the right and bottom separates swatches
+        for y in y0..y0 + SWATCH_HEIGHT - 1 {
+            for x in x0..x0 + SWATCH_WIDTH - 1 {
+                let i = (y * FRAME_WIDTH + x) * 3;
+                framebuffer[i..i + 3].copy_from_slice(&[r, g, b]);
+            }
+        }
+        draw_text(framebuffer, x0 + 2, y0 + 2, &format!("{:02X}:{:02X}", idx, color));
+    }
+}
+
+/// Palette entry (0-31) under a pixel of the grid
+pub fn entry_at(x: usize, y: usize) -> Option<usize> {
+    if x >= FRAME_WIDTH || y >= FRAME_HEIGHT {
+        return None;
+    }
+    Some((y / SWATCH_HEIGHT) * COLUMNS + x / SWATCH_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::FRAME_RGB_SIZE;
+
+    #[test]
+    fn test_grid_shows_edited_entries() {
+        let mut ppu = Ppu::new();
+        ppu.set_palette_entry(0x11, 0x16);
+        let mut frame = vec![0; FRAME_RGB_SIZE];
+        draw_palette(&ppu, &mut frame);
+
+        let entry = entry_at(SWATCH_WIDTH + 40, 4 * SWATCH_HEIGHT + 20);
+        assert_eq!(entry, Some(0x11));
+        let i = ((4 * SWATCH_HEIGHT + 20) * FRAME_WIDTH + SWATCH_WIDTH + 40) * 3;
+        let (r, g, b) = palette_rgb(0x16);
+        assert_eq!(&frame[i..i + 3], &[r, g, b]);
+        assert_eq!(entry_at(FRAME_WIDTH, 0), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Per-game play statistics
+//!
+//! Cumulative playtime and free-form notes (passwords, where you left off)
+//! keyed by ROM CRC32, so they follow the game rather than its file name.
+//! The store is a plain text file with one game per line: the hex CRC32,
+//! playtime in whole seconds and the notes, separated by tabs. Lines starting
+//! with `#` are comments. Tabs, newlines and backslashes in notes are
+//! escaped (`\t`, `\n`, `\\`).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// File name of the store inside the config directory
+pub const STATS_FILE: &str = "playstats.txt";
+
+/// Statistics for one game
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameStats {
+    /// Cumulative playtime
+    pub playtime: Duration,
+    /// Free-form user notes
+    pub notes: String,
+}
+
+/// Error reading or writing the statistics store
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlayStatsError {
+    /// Malformed line (1-based line number)
+    InvalidLine { line: usize, reason: String },
+    /// File could not be read or written
+    Io(String),
+}
+
+impl fmt::Display for PlayStatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayStatsError::InvalidLine { line, reason } => write!(f, "Invalid play stats line {}: {}", line, reason),
+            PlayStatsError::Io(e) => write!(f, "Play stats I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlayStatsError {}
+
+/// Playtime and notes for every game played
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayStats {
+    games: BTreeMap<u32, GameStats>,
+}
+
+impl PlayStats {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the text format
+    pub fn parse(text: &str) -> Result<Self, PlayStatsError> {
+        let mut stats = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| PlayStatsError::InvalidLine { line: index + 1, reason: reason.to_string() };
+            let mut fields = line.splitn(3, '\t');
+            let crc = fields
+                .next()
+                .and_then(|f| u32::from_str_radix(f.trim(), 16).ok())
+                .ok_or_else(|| invalid("expected a hex CRC32"))?;
+            let seconds = fields
+                .next()
+                .and_then(|f| f.trim().parse::<u64>().ok())
+                .ok_or_else(|| invalid("expected playtime in seconds"))?;
+            let notes = unescape(fields.next().unwrap_or(""));
+            stats.games.insert(crc, GameStats { playtime: Duration::from_secs(seconds), notes });
+        }
+        Ok(stats)
+    }
+
+    /// Serialize to the text format
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# crc32\tseconds\tnotes\n");
+        for (crc, game) in &self.games {
+            text.push_str(&format!("{:08X}\t{}\t{}\n", crc, game.playtime.as_secs(), escape(&game.notes)));
+        }
+        text
+    }
+
+    /// Load a store from a file; a missing file is an empty store
+    pub fn load(path: &Path) -> Result<Self, PlayStatsError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(PlayStatsError::Io(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    /// Write the store to a file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), PlayStatsError> {
+        let io = |e: std::io::Error| PlayStatsError::Io(format!("{}: {}", path.display(), e));
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+        fs::write(path, self.to_text()).map_err(io)
+    }
+
+    /// Get the statistics for a game
+    pub fn get(&self, crc32: u32) -> Option<&GameStats> {
+        self.games.get(&crc32)
+    }
+
+    /// Add to a game's cumulative playtime
+    pub fn add_playtime(&mut self, crc32: u32, played: Duration) {
+        self.games.entry(crc32).or_default().playtime += played;
+    }
+
+    /// Replace a game's notes
+    pub fn set_notes(&mut self, crc32: u32, notes: impl Into<String>) {
+        self.games.entry(crc32).or_default().notes = notes.into();
+    }
+
+    /// Number of games with statistics
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    /// Check if no game has statistics
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+/// A running play session, started when a ROM is loaded
+#[derive(Debug, Clone, Copy)]
+pub struct PlaySession {
+    crc32: u32,
+    started: Instant,
+}
+
+impl PlaySession {
+    /// Start timing a game
+    pub fn start(crc32: u32) -> Self {
+        Self { crc32, started: Instant::now() }
+    }
+
+    /// CRC32 of the game being played
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// Stop timing (when the ROM is unloaded) and add the session to the store
+    pub fn finish(self, stats: &mut PlayStats) -> Duration {
+        let played = self.started.elapsed();
+        stats.add_playtime(self.crc32, played);
+        played
+    }
+}
+
+/// Default store location: `<config dir>/rustnes/playstats.txt`
+///
+/// The config dir is `$XDG_CONFIG_HOME`, `~/.config` or `%APPDATA%`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join(STATS_FILE))
+}
+
+/// The `rustnes` directory inside the config dir
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let config = env_dir("XDG_CONFIG_HOME")
+        .or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+        .or_else(|| env_dir("APPDATA"))?;
+    Some(config.join("rustnes"))
+}
+
+/// Format a playtime as `1h 02m`, or `3m 05s` under an hour
+pub fn format_playtime(playtime: Duration) -> String {
+    let secs = playtime.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+fn escape(notes: &str) -> String {
+    let mut escaped = String::with_capacity(notes.len());
+    for c in notes.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> String {
+    let mut notes = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            notes.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => notes.push('\t'),
+            Some('n') => notes.push('\n'),
+            Some(other) => notes.push(other),
+            None => notes.push('\\'),
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_accumulate() {
+        let mut stats = PlayStats::new();
+        stats.add_playtime(0x1A2B3C4D, Duration::from_secs(60));
+        stats.add_playtime(0x1A2B3C4D, Duration::from_secs(5));
+        stats.set_notes(0x1A2B3C4D, "Password:\tBCDF\nWorld 3 \\ castle");
+        stats.set_notes(0x00000001, "");
+
+        let text = stats.to_text();
+        assert!(text.contains("1A2B3C4D\t65\tPassword:\\tBCDF\\nWorld 3 \\\\ castle\n"));
+        let parsed = PlayStats::parse(&text).unwrap();
+        assert_eq!(parsed, stats);
+        assert_eq!(parsed.get(0x1A2B3C4D).unwrap().playtime, Duration::from_secs(65));
+        assert_eq!(parsed.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_lines_and_formatting() {
+        assert_eq!(
+            PlayStats::parse("# header\n\nXYZ\t10\n"),
+            Err(PlayStatsError::InvalidLine { line: 3, reason: "expected a hex CRC32".to_string() })
+        );
+        assert!(matches!(PlayStats::parse("0000ABCD\tlots"), Err(PlayStatsError::InvalidLine { line: 1, .. })));
+        assert_eq!(PlayStats::parse("0000ABCD\t7").unwrap().get(0xABCD).unwrap().notes, "");
+
+        assert_eq!(format_playtime(Duration::from_secs(185)), "3m 05s");
+        assert_eq!(format_playtime(Duration::from_secs(3725)), "1h 02m");
+    }
+
+    #[test]
+    fn test_session_and_files() {
+        let dir = std::env::temp_dir().join(format!("rustnes-playstats-{}", std::process::id()));
+        let path = dir.join("nested").join(STATS_FILE);
+        assert!(PlayStats::load(&path).unwrap().is_empty());
+
+        let mut stats = PlayStats::new();
+        let session = PlaySession::start(0xCAFE);
+        assert_eq!(session.crc32(), 0xCAFE);
+        let played = session.finish(&mut stats);
+        assert_eq!(stats.get(0xCAFE).unwrap().playtime, played);
+
+        // Playtime is stored in whole seconds
+        stats.set_notes(0xCAFE, "saved");
+        stats.save(&path).unwrap();
+        let loaded = PlayStats::load(&path).unwrap();
+        assert_eq!(loaded.get(0xCAFE).unwrap().playtime.as_secs(), played.as_secs());
+        assert_eq!(loaded.get(0xCAFE).unwrap().notes, "saved");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -9,11 +9,41 @@
 //! - Background tile size: 8x8 pixels
 //! - Palette: 54 colors (6 colors per palette, 8 palettes)
 
+use crate::cartridge::Mirroring;
+use crate::region::Region;
+use crate::snapshot::PageStamps;
+use crate::sprite_eval::{evaluate_sprites, ScanlineSprites, SecondaryOam, SpriteEvaluator};
+use crate::state::{fnv1a_update, StateError, StateReader, StateWriter, FNV_OFFSET_BASIS};
+
 /// PPU memory map
 pub const VRAM_SIZE: usize = 16384; // 16KB
 pub const PALETTE_SIZE: usize = 32;  // 32 bytes (8 palettes x 4 colors each)
+/// First PPU address of palette RAM ($3F00-$3FFF mirror its 32 bytes)
+const PALETTE_START: u16 = 0x3F00;
 pub const OAM_SIZE: usize = 256;     // Object Attribute Memory
 
+/// Rendered frame dimensions
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+/// Size of an RGB framebuffer in bytes
+pub const FRAME_RGB_SIZE: usize = FRAME_WIDTH * FRAME_HEIGHT * 3;
+
+/// Dots per scanline (0-340)
+pub const DOTS_PER_SCANLINE: u16 = 341;
+/// Dots between a PPUMASK write and the first pixel it affects
+///
+/// The new value passes through the PPU's internal latches before it reaches
+/// the pixel output, so a mid-scanline write shows up a few pixels late.
+pub const MASK_WRITE_DELAY: u16 = 3;
+/// Dot at which the PPU starts fetching the next scanline's first tiles;
+/// `v` is latched there as that line's scroll
+pub const SCROLL_LATCH_DOT: u16 = 320;
+
+/// Loopy `v`/`t` bits copied at dot 257: coarse X and the horizontal nametable
+const HORIZONTAL_SCROLL_BITS: u16 = 0x041F;
+/// Loopy `v`/`t` bits copied on the pre-render line: fine Y, coarse Y and the vertical nametable
+const VERTICAL_SCROLL_BITS: u16 = 0x7BE0;
+
 /// PPU registers
 #[derive(Debug, Clone, Copy)]
 pub enum PpuRegister {
@@ -119,11 +149,26 @@ impl PpuMask {
     }
 }
 
+/// NES master palette: RGB for each 6-bit color value
+pub const NES_PALETTE: [(u8, u8, u8); 64] = [
+    (84, 84, 84), (0, 30, 116), (8, 22, 147), (48, 12, 154), (92, 4, 121), (136, 6, 85), (147, 22, 34), (132, 48, 0), (76, 84, 0), (12, 102, 0), (0, 120, 44), (0, 106, 132), (0, 84, 136), (0, 0, 0), (0, 0, 0), (0, 0, 0),
+    (160, 160, 160), (0, 70, 196), (48, 92, 255), (92, 70, 255), (136, 58, 255), (196, 78, 255), (204, 92, 204), (255, 114, 136), (255, 147, 84), (255, 173, 0), (216, 196, 0), (120, 214, 0), (0, 230, 116), (0, 196, 214), (0, 160, 255), (0, 0, 0),
+    (255, 255, 255), (48, 152, 255), (120, 147, 255), (176, 138, 255), (220, 132, 255), (255, 152, 255), (255, 165, 214), (255, 188, 160), (255, 214, 136), (255, 234, 120), (255, 255, 160), (188, 255, 160), (120, 255, 188), (120, 255, 255), (120, 214, 255), (84, 84, 255),
+    (255, 255, 255), (166, 230, 255), (188, 220, 255), (204, 214, 255), (214, 204, 255), (220, 204, 255), (214, 208, 230), (220, 214, 204), (234, 220, 196), (255, 230, 188), (240, 234, 196), (214, 240, 196), (188, 244, 214), (188, 244, 230), (188, 230, 244), (176, 176, 255),
+];
+
+/// RGB for a 6-bit master palette color (the upper bits are ignored)
+pub fn palette_rgb(color: u8) -> (u8, u8, u8) {
+    NES_PALETTE[(color & 0x3F) as usize]
+}
+
 /// PPU internal state
 #[derive(Debug, Clone)]
 pub struct Ppu {
     /// VRAM (16KB)
     vram: [u8; VRAM_SIZE],
+    /// Pages of VRAM written, for snapshots
+    vram_pages: PageStamps,
     /// Palette memory (32 bytes)
     palette: [u8; PALETTE_SIZE],
     /// OAM (256 bytes)
@@ -153,12 +198,19 @@ pub struct Ppu {
     sprite_overflow_detected: bool,
     /// Dot position (0-340)
     dot: u16,
-    /// Scanline position (-1 to 261, -1 is pre-render)
+    /// Scanline position (-1 to 260 on NTSC, 310 on PAL and Dendy; -1 is pre-render)
     scanline: i16,
-    /// Frame complete flag
+    /// Console region, which sets the frame's scanline count and vblank start
+    region: Region,
+    /// A frame finished since the flag was last taken
     frame_complete: bool,
+    /// Nametable mirroring set by the cartridge (applied to $2000-$3EFF accesses)
+    mirroring: Mirroring,
     /// CHR ROM data for pattern tables (8KB typical)
     chr_rom: Vec<u8>,
+    /// The board has CHR RAM instead of CHR ROM; pattern tables are then
+    /// read from VRAM $0000-$1FFF, which PPUDATA writes
+    chr_ram: bool,
     /// Write toggle for PPUSCROLL and PPUADDR
     write_toggle: bool,
     /// Internal register t (temporary address latch for $2006)
@@ -169,6 +221,102 @@ pub struct Ppu {
     video_address: u16,
     /// Fine X scroll (bits 0-2)
     fine_x: u8,
+    /// Per-scanline hashes of the last snapshot (None forces a full update)
+    snapshot_line_hashes: Option<[u64; FRAME_HEIGHT]>,
+    /// PPUMASK at the start of the frame in progress
+    frame_start_mask: u8,
+    /// PPUMASK changes during the visible lines of the frame in progress, as
+    /// (raster position they take effect at, new value)
+    mask_changes: Vec<(u32, u8)>,
+    /// Starting PPUMASK and changes of the last completed frame, if it changed
+    /// PPUMASK mid-frame; the renderer replays them
+    raster_mask: Option<(u8, Vec<(u32, u8)>)>,
+    /// Fine X at the start of the frame in progress
+    frame_start_fine_x: u8,
+    /// Fine X changes during the visible lines of the frame in progress, as
+    /// (raster position they take effect at, new value)
+    fine_x_changes: Vec<(u32, u8)>,
+    /// Starting fine X and changes of the last completed frame, if it changed
+    /// fine X mid-frame
+    raster_fine_x: Option<(u8, Vec<(u32, u8)>)>,
+    /// `v` as each visible line of the frame in progress started fetching tiles
+    line_scroll: Vec<u16>,
+    /// The pre-render line of the frame in progress copied `t`'s vertical scroll into `v`
+    scroll_copied: bool,
+    /// `line_scroll` of the last completed frame, if its pre-render line ran with rendering on
+    raster_scroll: Option<Vec<u16>>,
+    /// Sprite evaluation of the line in progress
+    sprite_eval: SpriteEvaluator,
+    /// Secondary OAM each visible line of the frame in progress draws, evaluated on the line before
+    line_sprites: Vec<SecondaryOam>,
+    /// Some line of the frame in progress ran sprite evaluation
+    sprites_evaluated: bool,
+    /// `line_sprites` of the last completed frame, if it ran sprite evaluation
+    raster_sprites: Option<Vec<SecondaryOam>>,
+    /// Replay the hardware's buggy search for a ninth sprite (see `SpriteEvaluator`)
+    sprite_overflow_bug: bool,
+    /// 256x240 RGB frame filled a scanline at a time as lines finish (empty when disabled)
+    framebuffer: Vec<u8>,
+}
+
+/// What produced a rendered pixel (for "what drew this pixel" debugging)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelSource {
+    /// Nothing opaque was drawn; the pixel shows the backdrop colour
+    #[default]
+    Backdrop,
+    /// An opaque background tile pixel
+    Background {
+        /// Attribute palette (0-3)
+        palette: u8,
+    },
+    /// An opaque sprite pixel
+    Sprite {
+        /// OAM index of the sprite (0-63)
+        index: u8,
+        /// Sprite palette (0-3)
+        palette: u8,
+    },
+}
+
+/// A rendered frame plus the scanlines that changed since the previous snapshot
+#[derive(Debug, Clone)]
+pub struct FrameSnapshot {
+    pixels: Vec<u8>,
+    dirty: [bool; FRAME_HEIGHT],
+}
+
+impl FrameSnapshot {
+    /// Get the 256x240 RGB pixel data
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Get the pixel data for drawing overlays (not reflected in the dirty flags)
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+
+    /// Get the RGB pixels of one scanline
+    pub fn line(&self, y: usize) -> &[u8] {
+        let stride = FRAME_WIDTH * 3;
+        &self.pixels[y * stride..(y + 1) * stride]
+    }
+
+    /// Check if a scanline changed since the previous snapshot
+    pub fn is_line_dirty(&self, y: usize) -> bool {
+        self.dirty.get(y).copied().unwrap_or(false)
+    }
+
+    /// Iterate over the scanlines that changed since the previous snapshot
+    pub fn dirty_lines(&self) -> impl Iterator<Item = usize> + '_ {
+        self.dirty.iter().enumerate().filter(|(_, &d)| d).map(|(y, _)| y)
+    }
+
+    /// Number of scanlines that changed since the previous snapshot
+    pub fn dirty_count(&self) -> usize {
+        self.dirty.iter().filter(|&&d| d).count()
+    }
 }
 
 impl Ppu {
@@ -176,6 +324,7 @@ impl Ppu {
     pub fn new() -> Self {
         Self {
             vram: [0; VRAM_SIZE],
+            vram_pages: PageStamps::new(VRAM_SIZE),
             palette: [0; PALETTE_SIZE],
             oam: [0; OAM_SIZE],
             control: PpuCtrl::new(0),
@@ -194,18 +343,92 @@ impl Ppu {
             sprite_overflow_detected: false,
             dot: 0,
             scanline: -1,
+            region: Region::Ntsc,
             frame_complete: false,
             write_toggle: false,
+            mirroring: Mirroring::Horizontal,
             chr_rom: vec![0; 8192], // Default 8KB CHR ROM
+            chr_ram: false,
             temp_address: 0,
             video_address: 0,
             fine_x: 0,
+            snapshot_line_hashes: None,
+            frame_start_mask: 0,
+            mask_changes: Vec::new(),
+            raster_mask: None,
+            frame_start_fine_x: 0,
+            fine_x_changes: Vec::new(),
+            raster_fine_x: None,
+            line_scroll: vec![0; FRAME_HEIGHT],
+            scroll_copied: false,
+            raster_scroll: None,
+            sprite_eval: SpriteEvaluator::default(),
+            line_sprites: vec![SecondaryOam::default(); FRAME_HEIGHT],
+            sprites_evaluated: false,
+            raster_sprites: None,
+            sprite_overflow_bug: false,
+            framebuffer: Vec::new(),
         }
     }
 
+    /// Get the console region
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Use a region's scanline count and vblank start
+    ///
+    /// Like mirroring, it isn't part of the saved state; the system sets it.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Emulate the sprite overflow flag's hardware bug (off by default)
+    ///
+    /// Like the region, it isn't part of the saved state; the system sets it
+    /// from its accuracy profile.
+    pub fn set_sprite_overflow_bug(&mut self, enabled: bool) {
+        self.sprite_overflow_bug = enabled;
+    }
+
+    /// Get the last scanline of a frame, before the wrap to the pre-render line (-1)
+    fn last_scanline(&self) -> i16 {
+        self.region.scanlines() as i16 - 2
+    }
+
+    /// Set the cartridge's current nametable mirroring
+    ///
+    /// It isn't part of the PPU's saved state: the system sets it from the
+    /// cartridge before every step and after loading a state.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    /// Get the nametable mirroring in effect
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    /// Index into `vram()` that a PPU address reads, after nametable mirroring
+    ///
+    /// $2000-$3EFF go through the cartridge's mirroring onto the 1KB pages
+    /// stored at $2000, $2400, $2800 and $2C00; other addresses map directly.
+    pub fn vram_index(&self, address: u16) -> usize {
+        let address = address as usize % VRAM_SIZE;
+        if !(0x2000..0x3F00).contains(&address) {
+            return address;
+        }
+        let offset = (address - 0x2000) & 0x0FFF;
+        0x2000 + self.mirroring.nametable_page(offset / 0x400) * 0x400 + offset % 0x400
+    }
+
     /// Set the CHR ROM data for pattern tables
     /// Also loads the palette data from the second 4KB bank (offset 4096)
+    ///
+    /// An empty CHR ROM means the cartridge has 8KB of CHR RAM instead: the
+    /// pattern tables are then the first 8KB of VRAM, written through PPUDATA.
     pub fn set_chr_rom(&mut self, chr_rom: Vec<u8>) {
+        self.chr_ram = chr_rom.is_empty();
         self.chr_rom = chr_rom;
         // Load palette data from offset 4096 (second 4KB bank of CHR ROM)
         // The palette is 32 bytes (8 palettes x 4 colors)
@@ -218,9 +441,24 @@ impl Ppu {
         }
     }
 
+    /// Check if pattern tables come from CHR RAM
+    pub fn chr_ram(&self) -> bool {
+        self.chr_ram
+    }
+
+    /// Get the pattern tables ($0000-$1FFF) rendering reads, CHR ROM or CHR RAM
+    pub fn pattern_tables(&self) -> &[u8] {
+        if self.chr_ram {
+            &self.vram[..0x2000]
+        } else {
+            &self.chr_rom
+        }
+    }
+
     /// Reset the PPU
     pub fn reset(&mut self) {
         self.vram = [0; VRAM_SIZE];
+        self.vram_pages.mark_all();
         self.palette = [0; PALETTE_SIZE];
         self.oam = [0; OAM_SIZE];
         self.control = PpuCtrl::new(0);
@@ -244,20 +482,190 @@ impl Ppu {
         self.temp_address = 0;
         self.video_address = 0;
         self.fine_x = 0;
+        self.snapshot_line_hashes = None;
+        self.frame_start_mask = 0;
+        self.mask_changes.clear();
+        self.raster_mask = None;
+        self.frame_start_fine_x = 0;
+        self.fine_x_changes.clear();
+        self.raster_fine_x = None;
+        self.line_scroll.fill(0);
+        self.scroll_copied = false;
+        self.raster_scroll = None;
+        self.sprite_eval = SpriteEvaluator::default();
+        self.line_sprites.fill(SecondaryOam::default());
+        self.sprites_evaluated = false;
+        self.raster_sprites = None;
+        self.framebuffer.fill(0);
         // Keep chr_rom intact
     }
 
+    /// Serialize memory, registers and the raster position
+    ///
+    /// CHR ROM comes from the cartridge and is not included; CHR RAM lives
+    /// in VRAM and is.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.vram);
+        self.save_state_without_vram(writer);
+    }
+
+    /// Serialize everything `save_state` does but VRAM
+    pub(crate) fn save_state_without_vram(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.palette);
+        writer.write_bytes(&self.oam);
+        writer.write_bytes(&[self.control.0, self.mask.0, self.status.0, self.oam_addr]);
+        writer.write_u16(self.scroll);
+        writer.write_u16(self.address);
+        writer.write_bytes(&[self.fine_scroll_x, self.coarse_x, self.coarse_y, self.fine_y, self.nametable, self.read_buffer]);
+        writer.write_bool(self.sprite_zero_detected);
+        writer.write_bool(self.sprite_overflow_detected);
+        writer.write_u16(self.dot);
+        writer.write_i16(self.scanline);
+        writer.write_bool(self.frame_complete);
+        writer.write_bool(self.write_toggle);
+        writer.write_u16(self.temp_address);
+        writer.write_u16(self.video_address);
+        writer.write_u8(self.fine_x);
+        writer.write_u8(self.frame_start_mask);
+        write_raster_changes(writer, &self.mask_changes);
+        writer.write_bool(self.raster_mask.is_some());
+        if let Some((start, changes)) = &self.raster_mask {
+            writer.write_u8(*start);
+            write_raster_changes(writer, changes);
+        }
+        self.save_raster_scroll(writer);
+        self.save_sprite_evaluation(writer);
+    }
+
+    /// Serialize the per-line scroll and fine X logs (added in PPU section v2)
+    pub(crate) fn save_raster_scroll(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.frame_start_fine_x);
+        write_raster_changes(writer, &self.fine_x_changes);
+        writer.write_bool(self.raster_fine_x.is_some());
+        if let Some((start, changes)) = &self.raster_fine_x {
+            writer.write_u8(*start);
+            write_raster_changes(writer, changes);
+        }
+        for &v in &self.line_scroll {
+            writer.write_u16(v);
+        }
+        writer.write_bool(self.scroll_copied);
+        writer.write_bool(self.raster_scroll.is_some());
+        for &v in self.raster_scroll.iter().flatten() {
+            writer.write_u16(v);
+        }
+    }
+
+    /// Serialize sprite evaluation and the per-line secondary OAM logs (added in PPU section v3)
+    pub(crate) fn save_sprite_evaluation(&self, writer: &mut StateWriter) {
+        self.sprite_eval.save_state(writer);
+        writer.write_bool(self.sprites_evaluated);
+        for line in &self.line_sprites {
+            line.save_state(writer);
+        }
+        writer.write_bool(self.raster_sprites.is_some());
+        for line in self.raster_sprites.iter().flatten() {
+            line.save_state(writer);
+        }
+    }
+
+    /// Restore state written by `save_sprite_evaluation`
+    fn load_sprite_evaluation(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.sprite_eval = SpriteEvaluator::load_state(reader)?;
+        self.sprites_evaluated = reader.read_bool()?;
+        let read_lines = |reader: &mut StateReader| (0..FRAME_HEIGHT).map(|_| SecondaryOam::load_state(reader)).collect::<Result<Vec<_>, _>>();
+        self.line_sprites = read_lines(reader)?;
+        self.raster_sprites = if reader.read_bool()? { Some(read_lines(reader)?) } else { None };
+        Ok(())
+    }
+
+    /// Restore state written by `save_raster_scroll`
+    fn load_raster_scroll(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.frame_start_fine_x = reader.read_u8()?;
+        self.fine_x_changes = read_raster_changes(reader)?;
+        self.raster_fine_x = if reader.read_bool()? {
+            let start = reader.read_u8()?;
+            Some((start, read_raster_changes(reader)?))
+        } else {
+            None
+        };
+        let read_lines = |reader: &mut StateReader| (0..FRAME_HEIGHT).map(|_| reader.read_u16()).collect::<Result<Vec<_>, _>>();
+        self.line_scroll = read_lines(reader)?;
+        self.scroll_copied = reader.read_bool()?;
+        self.raster_scroll = if reader.read_bool()? { Some(read_lines(reader)?) } else { None };
+        Ok(())
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.vram)?;
+        self.vram_pages.mark_all();
+        self.load_state_without_vram(reader)
+    }
+
+    /// Restore state written by `save_state_without_vram`
+    pub(crate) fn load_state_without_vram(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.palette)?;
+        reader.read_into(&mut self.oam)?;
+        self.control = PpuCtrl::new(reader.read_u8()?);
+        self.mask = PpuMask::new(reader.read_u8()?);
+        self.status = PpuStatus::new(reader.read_u8()?);
+        self.oam_addr = reader.read_u8()?;
+        self.scroll = reader.read_u16()?;
+        self.address = reader.read_u16()?;
+        self.fine_scroll_x = reader.read_u8()?;
+        self.coarse_x = reader.read_u8()?;
+        self.coarse_y = reader.read_u8()?;
+        self.fine_y = reader.read_u8()?;
+        self.nametable = reader.read_u8()?;
+        self.read_buffer = reader.read_u8()?;
+        self.sprite_zero_detected = reader.read_bool()?;
+        self.sprite_overflow_detected = reader.read_bool()?;
+        self.dot = reader.read_u16()?;
+        self.scanline = reader.read_i16()?;
+        if self.dot >= DOTS_PER_SCANLINE || !(-1..=self.last_scanline()).contains(&self.scanline) {
+            return Err(StateError::InvalidData("PPU raster position"));
+        }
+        self.frame_complete = reader.read_bool()?;
+        self.write_toggle = reader.read_bool()?;
+        self.temp_address = reader.read_u16()?;
+        self.video_address = reader.read_u16()?;
+        self.fine_x = reader.read_u8()?;
+        self.frame_start_mask = reader.read_u8()?;
+        self.mask_changes = read_raster_changes(reader)?;
+        self.raster_mask = if reader.read_bool()? {
+            let start = reader.read_u8()?;
+            Some((start, read_raster_changes(reader)?))
+        } else {
+            None
+        };
+        self.load_raster_scroll(reader)?;
+        self.load_sprite_evaluation(reader)?;
+        // The next snapshot redraws every line
+        self.snapshot_line_hashes = None;
+        Ok(())
+    }
+
+    /// Check if a frame finished (the last scanline ended) since the last call, clearing the flag
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
     /// Step the PPU by one cycle
     pub fn step(&mut self) {
         self.dot += 1;
 
         if self.dot > 340 {
+            if !self.framebuffer.is_empty() && (0..FRAME_HEIGHT as i16).contains(&self.scanline) {
+                self.render_finished_line(self.scanline as usize);
+            }
             self.dot = 0;
             self.scanline += 1;
 
-            if self.scanline > 261 {
+            if self.scanline > self.last_scanline() {
                 self.scanline = -1;
                 self.frame_complete = true;
+                self.latch_raster();
                 // Clear VBLANK flag at end of frame
                 self.status = PpuStatus::new(self.status.0 & !PpuStatus::VBLANK);
             }
@@ -265,33 +673,161 @@ impl Ppu {
 
         // Handle scanline-specific behavior
         self.handle_scanline();
+        self.clock_scroll();
+        self.clock_sprite_evaluation();
+    }
+
+    /// Render a visible line that just finished into `framebuffer`
+    fn render_finished_line(&mut self, scanline: usize) {
+        let mut framebuffer = std::mem::take(&mut self.framebuffer);
+        let row = &mut framebuffer[scanline * FRAME_WIDTH * 3..(scanline + 1) * FRAME_WIDTH * 3];
+        self.render_line(RasterFrame::InProgress, scanline, row, FRAME_WIDTH, None);
+        self.framebuffer = framebuffer;
+    }
+
+    /// Keep the PPUMASK and scroll changes of the frame that just ended for rendering
+    fn latch_raster(&mut self) {
+        let changes = std::mem::take(&mut self.mask_changes);
+        self.raster_mask = (!changes.is_empty()).then_some((self.frame_start_mask, changes));
+        self.frame_start_mask = self.mask.0;
+        let changes = std::mem::take(&mut self.fine_x_changes);
+        self.raster_fine_x = (!changes.is_empty()).then_some((self.frame_start_fine_x, changes));
+        self.frame_start_fine_x = self.fine_x;
+        self.raster_scroll = std::mem::take(&mut self.scroll_copied).then(|| self.line_scroll.clone());
+        self.raster_sprites = std::mem::take(&mut self.sprites_evaluated).then(|| self.line_sprites.clone());
+        self.line_sprites.fill(SecondaryOam::default());
+    }
+
+    /// Update `v` the way background fetches do while rendering is on
+    ///
+    /// Fine/coarse Y step at dot 256 and the horizontal bits are reloaded
+    /// from `t` at dot 257 of every visible line; the pre-render line also
+    /// reloads the vertical bits. Coarse X steps within a line aren't
+    /// tracked, so `v` keeps the line's starting column. Whatever the mask,
+    /// `v` is latched at `SCROLL_LATCH_DOT` as the next line's scroll.
+    fn clock_scroll(&mut self) {
+        let line = self.scanline;
+        if !(-1..FRAME_HEIGHT as i16).contains(&line) {
+            return;
+        }
+        if self.mask.render_background() || self.mask.render_sprites() {
+            match self.dot {
+                256 => self.video_address = increment_y(self.video_address),
+                257 => copy_scroll_bits(&mut self.video_address, self.temp_address, HORIZONTAL_SCROLL_BITS),
+                280..=304 if line == -1 => {
+                    copy_scroll_bits(&mut self.video_address, self.temp_address, VERTICAL_SCROLL_BITS);
+                    self.scroll_copied = true;
+                }
+                _ => {}
+            }
+        }
+        if self.dot == SCROLL_LATCH_DOT && line < FRAME_HEIGHT as i16 - 1 {
+            self.line_scroll[(line + 1) as usize] = self.video_address;
+        }
+    }
+
+    /// Evaluate the next line's sprites while rendering is on
+    ///
+    /// Secondary OAM is cleared before dot 65, filled a read/write pair at a
+    /// time on dots 65-256 and handed to the next line's sprite fetches at
+    /// dot 257. Lines evaluated with rendering off draw no sprites.
+    fn clock_sprite_evaluation(&mut self) {
+        let line = self.scanline;
+        if !(0..FRAME_HEIGHT as i16).contains(&line) || !(self.mask.render_background() || self.mask.render_sprites()) {
+            return;
+        }
+        match self.dot {
+            65..=256 if self.dot % 2 == 1 => {
+                if self.dot == 65 {
+                    self.sprite_eval.start();
+                }
+                if self.sprite_eval.step(&self.oam, line, self.sprite_height(), self.sprite_overflow_bug) {
+                    self.status = PpuStatus::new(self.status.0 | PpuStatus::SPRITE_OVERFLOW);
+                    self.sprite_overflow_detected = true;
+                }
+            }
+            257 if line < FRAME_HEIGHT as i16 - 1 => {
+                self.line_sprites[(line + 1) as usize] = *self.sprite_eval.secondary();
+                self.sprites_evaluated = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Raster position (scanline * 341 + dot) of the dot in progress
+    fn raster_position(&self) -> u32 {
+        self.scanline as u32 * DOTS_PER_SCANLINE as u32 + self.dot as u32
+    }
+
+    /// PPUMASK in effect for a pixel
+    ///
+    /// Frames that changed PPUMASK while visible lines were drawn are shown as
+    /// the last completed one was scanned out: each pixel uses the value
+    /// latched by the time its dot (x + 1) was output. Otherwise the current
+    /// value applies to the whole frame.
+    fn mask_at(&self, frame: RasterFrame, scanline: usize, x: usize) -> PpuMask {
+        PpuMask::new(match (frame, &self.raster_mask) {
+            (RasterFrame::InProgress, _) => raster_value(self.frame_start_mask, &self.mask_changes, scanline, x),
+            (RasterFrame::Completed, Some((start, changes))) => raster_value(*start, changes, scanline, x),
+            (RasterFrame::Completed, None) => self.mask.0,
+        })
+    }
+
+    /// Fine X the pixel mux used for a pixel, replayed like `mask_at`
+    fn fine_x_at(&self, frame: RasterFrame, scanline: usize, x: usize) -> u8 {
+        match (frame, &self.raster_fine_x) {
+            (RasterFrame::InProgress, _) => raster_value(self.frame_start_fine_x, &self.fine_x_changes, scanline, x),
+            (RasterFrame::Completed, Some((start, changes))) => raster_value(*start, changes, scanline, x),
+            (RasterFrame::Completed, None) => self.fine_x,
+        }
+    }
+
+    /// `v` as a visible line started fetching tiles
+    ///
+    /// Frames whose pre-render line ran with rendering on are shown with the
+    /// scroll each of their lines latched. Otherwise every line is derived
+    /// from `t`, as it would scroll with rendering on from the start.
+    fn line_scroll_at(&self, frame: RasterFrame, scanline: usize) -> u16 {
+        match (frame, &self.raster_scroll) {
+            (RasterFrame::InProgress, _) if self.scroll_copied => self.line_scroll[scanline],
+            (RasterFrame::Completed, Some(lines)) => lines[scanline],
+            _ => scroll_lines(self.temp_address, scanline),
+        }
+    }
+
+    /// Secondary OAM a visible line draws its sprites from
+    ///
+    /// Frames that ran sprite evaluation are shown with the sprites each
+    /// line evaluated. Otherwise each line takes its first 8 sprites from
+    /// the current OAM.
+    fn line_sprites_at(&self, frame: RasterFrame, scanline: usize) -> SecondaryOam {
+        match (frame, &self.raster_sprites) {
+            (RasterFrame::InProgress, _) if self.sprites_evaluated => self.line_sprites[scanline],
+            (RasterFrame::Completed, Some(lines)) => lines[scanline],
+            _ => SecondaryOam::from_oam(&self.oam, self.sprite_height(), scanline),
+        }
     }
 
     /// Handle behavior for specific scanlines
     fn handle_scanline(&mut self) {
         match self.scanline {
-            -1 => {
-                // Pre-render scanline
-                if self.dot == 1 {
-                    // Clear VBLANK at start of pre-render
-                    self.status = PpuStatus::new(self.status.0 & !PpuStatus::VBLANK);
-                }
+            // Pre-render scanline
+            -1 if self.dot == 1 => {
+                // Clear VBLANK, sprite zero and overflow at start of pre-render
+                self.status = PpuStatus::new(self.status.0 & !(PpuStatus::VBLANK | PpuStatus::SPRITE_ZERO_HIT | PpuStatus::SPRITE_OVERFLOW));
+                self.sprite_zero_detected = false;
+                self.sprite_overflow_detected = false;
             }
             0..=239 => {
                 // Visible scanlines
             }
-            241 => {
+            line if line == self.region.vblank_scanline() => {
                 // VBLANK starts
                 self.status = PpuStatus::new(self.status.0 | PpuStatus::VBLANK);
-                // Clear sprite zero and overflow flags
-                self.status = PpuStatus::new(self.status.0 & !(PpuStatus::SPRITE_ZERO_HIT | PpuStatus::SPRITE_OVERFLOW));
-                self.sprite_zero_detected = false;
-                self.sprite_overflow_detected = false;
             }
-            242..=260 => {
-                // Post-render scanlines
+            _ => {
+                // Post-render and vblank scanlines
             }
-            _ => {}
         }
     }
 
@@ -305,9 +841,10 @@ impl Ppu {
             // $2002 - PPUSTATUS
             0x2002 => {
                 let status = self.status.0;
-                // Clear VBLANK flag on read (VBLANK is set at scanline 241)
+                // Clear VBLANK flag on read (VBLANK is set at scanline 241, 291 on the Dendy)
                 // The VBLANK flag is cleared by reading PPUSTATUS
                 self.status = PpuStatus::new(status & !PpuStatus::VBLANK);
+                self.write_toggle = false;
                 // Reset fine scroll bits (not full scroll register)
                 self.fine_scroll_x = 0;
                 self.fine_y = 0;
@@ -332,8 +869,15 @@ impl Ppu {
             0x2007 => {
                 // First read after address set returns the read buffer (previous VRAM content)
                 // Second read returns current VRAM content and updates read buffer
-                let value = self.read_buffer;
-                self.read_buffer = self.vram[self.address as usize];
+                let value = if self.address % VRAM_SIZE as u16 >= PALETTE_START {
+                    // Palette reads aren't buffered; the buffer gets the nametable byte underneath
+                    self.read_buffer = self.vram[self.vram_index(self.address - 0x1000)];
+                    self.palette[palette_mirror(self.address as usize)]
+                } else {
+                    let value = self.read_buffer;
+                    self.read_buffer = self.vram[self.vram_index(self.address)];
+                    value
+                };
                 // Update address for next access
                 let increment = if (self.control.0 & PpuCtrl::VRAM_INC) != 0 { 32 } else { 1 };
                 self.address = self.address.wrapping_add(increment as u16);
@@ -351,9 +895,16 @@ impl Ppu {
                 self.control = PpuCtrl::new(value);
                 // Update nametable from control
                 self.nametable = self.control.nametable();
+                // The nametable bits also go to t's nametable select (bits 10-11)
+                self.temp_address = (self.temp_address & !0x0C00) | ((value as u16 & 0x03) << 10);
             }
             // $2001 - PPUMASK
             0x2001 => {
+                // Registers are synced every step, so only log actual changes
+                if value != self.mask.0 && (0..FRAME_HEIGHT as i16).contains(&self.scanline) {
+                    let position = self.scanline as u32 * DOTS_PER_SCANLINE as u32 + (self.dot + MASK_WRITE_DELAY) as u32;
+                    self.mask_changes.push((position, value));
+                }
                 self.mask = PpuMask::new(value);
             }
             // $2002 - PPUSTATUS (write has no effect)
@@ -372,7 +923,14 @@ impl Ppu {
                 if !self.write_toggle {
                     // First write - X scroll (fine and coarse)
                     // X fine scroll is bits 0-2, X coarse scroll is bits 3-7
-                    self.fine_x = value & 0x07;
+                    // Fine X feeds the pixel mux straight away; coarse X only
+                    // reaches v at dot 257, so a write mid-line moves the
+                    // rest of this line but the tiles from the next one
+                    let fine_x = value & 0x07;
+                    if fine_x != self.fine_x && (0..FRAME_HEIGHT as i16).contains(&self.scanline) {
+                        self.fine_x_changes.push((self.raster_position(), fine_x));
+                    }
+                    self.fine_x = fine_x;
                     // Coarse X goes to temp_address's coarse X (bits 0-4)
                     self.temp_address = (self.temp_address & 0xFFE0) | (((value >> 3) & 0x1F) as u16);
                     self.write_toggle = true;
@@ -380,8 +938,11 @@ impl Ppu {
                     // Second write - Y scroll (fine and coarse)
                     // Y fine scroll is bits 0-2, Y coarse scroll is bits 3-7
                     self.fine_y = value & 0x07;
-                    // Coarse Y goes to temp_address's coarse Y (bits 5-9)
-                    self.temp_address = (self.temp_address & 0x03E0) | ((((value >> 3) & 0x1F) as u16) << 5);
+                    // Fine Y goes to temp_address bits 12-14, coarse Y to bits 5-9;
+                    // v picks them up on the pre-render line, not mid-frame
+                    self.temp_address = (self.temp_address & !0x73E0)
+                        | ((self.fine_y as u16) << 12)
+                        | ((((value >> 3) & 0x1F) as u16) << 5);
                     self.write_toggle = false;
                 }
             }
@@ -395,11 +956,7 @@ impl Ppu {
                     // These affect the video_address's nametable bits when writing low byte
                     let high_byte = value & 0x3F; // Only bits 5-0 are valid
                     self.temp_address = (self.temp_address & 0x00FF) | ((high_byte as u16) << 8);
-                    // Update video_address's nametable bits from the high byte
-                    // Video address format: NNcccccfffccccc (15 bits)
-                    // NN bits (14-15) are nametable select
-                    let nametable_bits = (value as u16 & 0x03) << 10; // Extract bits 0-1 and shift to position
-                    self.video_address = (self.video_address & 0xBFFF) | nametable_bits;
+                    // video_address is left alone until the second write
                     self.write_toggle = true;
                 } else {
                     // Second write - low byte (bits 7-0 of address)
@@ -407,12 +964,20 @@ impl Ppu {
                     self.temp_address = (self.temp_address & 0xFF00) | (value as u16);
                     // Copy temp_address to video_address (this is the key side effect)
                     self.video_address = self.temp_address;
+                    // PPUDATA accesses start at the new address
+                    self.address = self.temp_address;
                     self.write_toggle = false;
                 }
             }
             // $2007 - PPUDATA
             0x2007 => {
-                self.vram[self.address as usize] = value;
+                if self.address % VRAM_SIZE as u16 >= PALETTE_START {
+                    self.palette[palette_mirror(self.address as usize)] = value & 0x3F;
+                } else {
+                    let index = self.vram_index(self.address);
+                    self.vram[index] = value;
+                    self.vram_pages.mark(index);
+                }
                 // Write also updates the read buffer with the value being written
                 self.read_buffer = value;
                 // Update address for next access
@@ -458,6 +1023,11 @@ impl Ppu {
         self.status.vblank()
     }
 
+    /// Level of the NMI output: VBLANK set with NMI enabled in PPUCTRL
+    pub fn nmi_output(&self) -> bool {
+        self.status.vblank() && self.control.nmi_enable()
+    }
+
     /// Get current scanline
     pub fn scanline(&self) -> i16 {
         self.scanline
@@ -468,6 +1038,31 @@ impl Ppu {
         self.dot
     }
 
+    /// Get the current VRAM address (loopy `v`)
+    pub fn video_address(&self) -> u16 {
+        self.video_address
+    }
+
+    /// Get the temporary VRAM address (loopy `t`)
+    pub fn temp_address(&self) -> u16 {
+        self.temp_address
+    }
+
+    /// Get the fine X scroll (loopy `x`)
+    pub fn fine_x(&self) -> u8 {
+        self.fine_x
+    }
+
+    /// Check if the next $2005/$2006 write is the second of a pair (loopy `w`)
+    pub fn write_toggle(&self) -> bool {
+        self.write_toggle
+    }
+
+    /// Clear the write toggle, as a PPUSTATUS read does
+    pub fn clear_write_toggle(&mut self) {
+        self.write_toggle = false;
+    }
+
     /// Get palette entry (4 bytes per palette: 4 colors)
     /// Returns the palette index for background/sprites
     /// Each byte contains two 4-bit color indices
@@ -480,7 +1075,69 @@ impl Ppu {
         let c1 = (self.palette[base] >> 4) & 0x0F;
         let c2 = self.palette[base + 1] & 0x0F;
         let c3 = (self.palette[base + 1] >> 4) & 0x0F;
-        [c0 as u8, c1 as u8, c2 as u8, c3 as u8]
+        [c0, c1, c2, c3]
+    }
+
+    /// Get VRAM contents
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    /// Get VRAM with its page stamps, for snapshots
+    pub(crate) fn vram_pages(&mut self) -> (&mut [u8], &mut PageStamps) {
+        (&mut self.vram, &mut self.vram_pages)
+    }
+
+    /// Get palette RAM contents
+    pub fn palette_ram(&self) -> &[u8] {
+        &self.palette
+    }
+
+    /// Get OAM (sprite attribute) contents
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    /// Get the OAM address (OAMADDR)
+    pub fn oam_address(&self) -> u8 {
+        self.oam_addr
+    }
+
+    /// Write bytes straight into OAM from `offset`, wrapping at 256
+    ///
+    /// Debug API for tests and tools: it bypasses OAMADDR and $2004 timing.
+    pub fn write_oam_bytes(&mut self, offset: u8, bytes: &[u8]) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.oam[offset.wrapping_add(i as u8) as usize] = byte;
+        }
+    }
+
+    /// Sprite height in pixels (8 or 16, from PPUCTRL)
+    pub fn sprite_height(&self) -> u8 {
+        if self.control.sprite_size() { 16 } else { 8 }
+    }
+
+    /// Replay per-scanline sprite evaluation for the current OAM
+    pub fn evaluate_sprites(&self) -> Vec<ScanlineSprites> {
+        evaluate_sprites(&self.oam, self.sprite_height())
+    }
+
+    /// Write a palette RAM entry directly (debug API, e.g. for live palette editing)
+    ///
+    /// `idx` is the offset from $3F00 (0-31); $3F10/$3F14/$3F18/$3F1C mirror
+    /// $3F00/$3F04/$3F08/$3F0C. The value is a 6-bit master palette color.
+    /// It lands where a PPUDATA write would, so the game sees it on a $2007
+    /// read and overwrites it with its next palette upload.
+    pub fn set_palette_entry(&mut self, idx: usize, value: u8) {
+        self.palette[palette_mirror(idx)] = value & 0x3F;
+    }
+
+    /// Master palette color the renderer uses for a palette entry (0-31)
+    ///
+    /// Read from palette RAM, with $3F10/$3F14/$3F18/$3F1C resolving to
+    /// $3F00/$3F04/$3F08/$3F0C.
+    pub fn palette_color(&self, idx: usize) -> u8 {
+        self.palette[palette_mirror(idx)] & 0x3F
     }
 
     /// Get the palette byte at the given index (for direct access)
@@ -492,27 +1149,149 @@ impl Ppu {
         }
     }
 
+    /// Start or stop keeping a framebuffer that `step` fills as visible lines finish
+    ///
+    /// Each line is rendered when its last dot has run, with the VRAM, palette
+    /// and raster state it was drawn with, so mid-frame palette or nametable
+    /// updates show as they would on screen. It is off by default: headless
+    /// runs that never show a frame don't pay for rendering.
+    pub fn set_framebuffer_enabled(&mut self, enabled: bool) {
+        match enabled {
+            true if self.framebuffer.is_empty() => self.framebuffer = vec![0; FRAME_RGB_SIZE],
+            false => self.framebuffer = Vec::new(),
+            _ => {}
+        }
+    }
+
+    /// Check if `step` fills the framebuffer
+    pub fn framebuffer_enabled(&self) -> bool {
+        !self.framebuffer.is_empty()
+    }
+
+    /// Get the 256x240 RGB framebuffer (empty unless enabled)
+    ///
+    /// Between frames it holds the last completed frame; mid-frame, lines
+    /// above the current scanline already belong to the frame in progress.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Render all 240 visible scanlines into a 256x240 RGB framebuffer
+    pub fn render_frame(&self, framebuffer: &mut [u8]) {
+        for (y, row) in framebuffer
+            .chunks_exact_mut(FRAME_WIDTH * 3)
+            .take(FRAME_HEIGHT)
+            .enumerate()
+        {
+            self.render_scanline(y, row, FRAME_WIDTH);
+        }
+    }
+
+    /// Nametable tile (column, row) the background renderer fetches for a screen
+    /// pixel, plus the pixel's column within that tile
+    pub fn background_tile_at(&self, x: usize, scanline: usize) -> (usize, usize, u8) {
+        let frame = RasterFrame::Completed;
+        let fetch = self.background_fetch(frame, self.line_scroll_at(frame, scanline), x, scanline);
+        (fetch.tile_x, fetch.tile_y, fetch.pixel_x)
+    }
+
+    /// Where the background pixel at `x` of a line scrolled to `v` comes from
+    ///
+    /// The shift registers start with the tile `v` points at, and the mux
+    /// picks the bit fine X selects, so pixel x is `x + fine X` pixels into
+    /// the line's tiles. Columns past 31 continue in the horizontally
+    /// adjacent nametable.
+    fn background_fetch(&self, frame: RasterFrame, v: u16, x: usize, scanline: usize) -> BackgroundFetch {
+        let v = v as usize;
+        let offset = (v & 0x1F) * 8 + x + self.fine_x_at(frame, scanline, x) as usize;
+        let column = offset / 8;
+        BackgroundFetch {
+            nametable: ((v >> 10) & 0x03) ^ ((column / 32) & 0x01),
+            tile_x: column % 32,
+            tile_y: (v >> 5) & 0x1F,
+            pixel_x: (offset % 8) as u8,
+            pixel_y: ((v >> 12) & 0x07) as u8,
+        }
+    }
+
+    /// Background palette (0-3) the attribute table assigns to a tile
+    ///
+    /// Each attribute byte covers a 4x4 tile block; bits 0-1 select the palette of the
+    /// upper-left 2x2 quadrant, bits 2-3 upper-right, 4-5 lower-left and 6-7 lower-right.
+    pub fn attribute_palette(&self, tile_x: usize, tile_y: usize) -> u8 {
+        self.attribute_palette_in(self.nametable as usize, tile_x, tile_y)
+    }
+
+    /// Background palette of a tile in the given nametable (0-3)
+    fn attribute_palette_in(&self, nametable: usize, tile_x: usize, tile_y: usize) -> u8 {
+        // Attribute table follows the 960 tile bytes of the nametable
+        let attr_table_base = 0x2000 + nametable * 1024 + 960;
+        let attr_addr = attr_table_base + (tile_y / 4) * 8 + tile_x / 4;
+        let Some(&attr) = self.vram.get(self.vram_index(attr_addr as u16)) else {
+            return 0;
+        };
+        let shift = ((tile_y % 4) / 2) * 4 + ((tile_x % 4) / 2) * 2;
+        (attr >> shift) & 0x03
+    }
+
+    /// Render the frame and record the source of every pixel
+    ///
+    /// `sources` receives one entry per pixel (256x240), row-major.
+    pub fn render_frame_with_sources(&self, framebuffer: &mut [u8], sources: &mut [PixelSource]) {
+        for (y, (row, row_sources)) in framebuffer
+            .chunks_exact_mut(FRAME_WIDTH * 3)
+            .zip(sources.chunks_exact_mut(FRAME_WIDTH))
+            .take(FRAME_HEIGHT)
+            .enumerate()
+        {
+            self.render_line(RasterFrame::Completed, y, row, FRAME_WIDTH, Some(row_sources));
+        }
+    }
+
+    /// Render the frame and report which scanlines changed since the last snapshot
+    ///
+    /// The first snapshot (and the first after a reset) marks every line dirty.
+    /// Lines are compared by hash, so frontends can upload only changed rows.
+    pub fn snapshot_frame(&mut self) -> FrameSnapshot {
+        let mut pixels = vec![0u8; FRAME_RGB_SIZE];
+        self.render_frame(&mut pixels);
+
+        let mut hashes = [0u64; FRAME_HEIGHT];
+        for (hash, row) in hashes.iter_mut().zip(pixels.chunks_exact(FRAME_WIDTH * 3)) {
+            *hash = fnv1a_update(FNV_OFFSET_BASIS, row);
+        }
+
+        let mut dirty = [true; FRAME_HEIGHT];
+        if let Some(previous) = &self.snapshot_line_hashes {
+            for (y, flag) in dirty.iter_mut().enumerate() {
+                *flag = previous[y] != hashes[y];
+            }
+        }
+        self.snapshot_line_hashes = Some(hashes);
+
+        FrameSnapshot { pixels, dirty }
+    }
+
     /// Render a scanline to a framebuffer
     /// framebuffer should be sized for at least `width` * 3 bytes per pixel (RGB)
     /// Returns the number of bytes written to the framebuffer
     pub fn render_scanline(&self, scanline: usize, framebuffer: &mut [u8], width: usize) {
+        self.render_line(RasterFrame::Completed, scanline, framebuffer, width, None);
+    }
+
+    /// Render a scanline, optionally recording each pixel's source
+    fn render_line(
+        &self,
+        frame: RasterFrame,
+        scanline: usize,
+        framebuffer: &mut [u8],
+        width: usize,
+        mut sources: Option<&mut [PixelSource]>,
+    ) {
         if scanline >= 240 || framebuffer.len() < width * 3 {
             return;
         }
 
-        // Get the render mask
-        let render_bg = self.mask.render_background();
-        let render_sprites = self.mask.render_sprites();
-
-        // NES color palette (6-bit values converted to 8-bit RGB)
-        // These are the standard NES palettes (64 colors)
-        let palette_table: [(u8, u8, u8); 64] = [
-            (84, 84, 84), (0, 30, 116), (8, 22, 147), (48, 12, 154), (92, 4, 121), (136, 6, 85), (147, 22, 34), (132, 48, 0), (76, 84, 0), (12, 102, 0), (0, 120, 44), (0, 106, 132), (0, 84, 136), (0, 0, 0), (0, 0, 0), (0, 0, 0),
-            (160, 160, 160), (0, 70, 196), (48, 92, 255), (92, 70, 255), (136, 58, 255), (196, 78, 255), (204, 92, 204), (255, 114, 136), (255, 147, 84), (255, 173, 0), (216, 196, 0), (120, 214, 0), (0, 230, 116), (0, 196, 214), (0, 160, 255), (0, 0, 0),
-            (255, 255, 255), (48, 152, 255), (120, 147, 255), (176, 138, 255), (220, 132, 255), (255, 152, 255), (255, 165, 214), (255, 188, 160), (255, 214, 136), (255, 234, 120), (255, 255, 160), (188, 255, 160), (120, 255, 188), (120, 255, 255), (120, 214, 255), (84, 84, 255),
-            (255, 255, 255), (166, 230, 255), (188, 220, 255), (204, 214, 255), (214, 204, 255), (220, 204, 255), (214, 208, 230), (220, 214, 204), (234, 220, 196), (255, 230, 188), (240, 234, 196), (214, 240, 196), (188, 244, 214), (188, 244, 230), (188, 230, 244), (176, 176, 255),
-        ];
-
         // Calculate pattern table bases
         let bg_pattern_table_base = if (self.control.0 & PpuCtrl::BG_PATTERN_TABLE) != 0 { 4096 } else { 0 };
         let sprite_pattern_table_base = if (self.control.0 & PpuCtrl::SPR_PATTERN_TABLE) != 0 { 4096 } else { 0 };
@@ -543,118 +1322,58 @@ impl Ppu {
             (bit1 << 1) | bit0
         };
 
-        // Helper to get palette index from attribute table
-        // Attribute table is at $23C0-$2FFF (256 bytes, covers 32x30 tile area)
-        // Each byte controls a 4x4 tile block
-        // Bits 0-1: upper-left quadrant, Bits 2-3: upper-right, Bits 4-5: lower-left, Bits 6-7: lower-right
-        let get_attr_palette = |attr: u8, tile_x: u8, tile_y: u8| -> u8 {
-            // Determine which quadrant this tile is in within its 4x4 block
-            let x_in_block = tile_x % 4;
-            let y_in_block = tile_y % 4;
-
-            let shift = if x_in_block < 2 {
-                if y_in_block < 2 { 0 } else { 4 }
-            } else {
-                if y_in_block < 2 { 2 } else { 6 }
-            };
-
-            ((attr >> shift) & 0x03) as u8
-        };
-
-        // Get the background nametable base address
-        // Nametables are at $2000-$23FF in VRAM
-        // With mirroring: nametable 0 = $2000-$23FF, nametable 1 = $2400-$27FF, etc.
-        let nametable_base = (0x2000 + (self.nametable as usize) * 1024) as usize;
-
-        // Attribute table is at $23C0-$2FFF (32 bytes per nametable)
-        let attr_table_base = nametable_base + 960; // $23C0 - $2000 = 0x3C0 = 960
+        // Scroll (loopy v) the line started with; the nametable can change mid-line
+        let line_scroll = self.line_scroll_at(frame, scanline);
+        let line_sprites = self.line_sprites_at(frame, scanline);
 
         // Render background
         for x in 0..width.min(256) {
-            // Calculate scroll position
-            let fine_x = self.fine_scroll_x as i32;
-            let coarse_x = self.coarse_x as i32;
-            let coarse_y = self.coarse_y as i32;
-
-            // Calculate the tile X position accounting for fine scroll
-            // The fine scroll tells us how many pixels into the tile to start
-            let tile_x = if x as i32 >= fine_x {
-                coarse_x + ((x as i32 - fine_x) / 8)
-            } else {
-                // Wrapping to the other side of the screen
-                coarse_x + 32 - ((fine_x - x as i32) / 8)
-            } % 32;
-
-            // Calculate pixel position within the tile (0-7)
-            let pixel_x = if x as i32 >= fine_x {
-                (x as i32 - fine_x) % 8
-            } else {
-                8 - (fine_x - x as i32) % 8
-            } as u8;
+            let mut source = PixelSource::Backdrop;
+            let mask = self.mask_at(frame, scanline, x);
+            let (render_bg, render_sprites) = (mask.render_background(), mask.render_sprites());
 
-            // Calculate tile Y position
-            // For Y, we need to account for the current scanline relative to the coarse Y
-            let scanline_in_tile = scanline as i32 % 8;
-            let tile_y = (coarse_y + (scanline as i32 / 8)) % 32;
+            let fetch = self.background_fetch(frame, line_scroll, x, scanline);
 
             let color_idx = if render_bg {
                 // Calculate nametable address for this tile
-                let nametable_addr = nametable_base + (tile_y as usize) * 32 + (tile_x as usize);
+                // Nametables are at $2000-$23FF in VRAM
+                // With mirroring: nametable 0 = $2000-$23FF, nametable 1 = $2400-$27FF, etc.
+                let nametable_addr = 0x2000 + fetch.nametable * 1024 + fetch.tile_y * 32 + fetch.tile_x;
 
                 if nametable_addr >= self.vram.len() {
                     0
                 } else {
-                    let tile_idx = self.vram[nametable_addr];
-
-                    // Get the attribute table byte for this tile
-                    // Attribute table is organized in 4x4 tile blocks
-                    let attr_tile_x = tile_x as u8 / 4;
-                    let attr_tile_y = tile_y as u8 / 4;
-                    let attr_addr = attr_table_base + (attr_tile_y as usize) * 8 + (attr_tile_x as usize);
-
-                    let palette_select = if attr_addr < self.vram.len() {
-                        let attr = self.vram[attr_addr];
-                        get_attr_palette(attr, tile_x as u8, tile_y as u8)
-                    } else {
-                        0
-                    };
+                    let tile_idx = self.vram[self.vram_index(nametable_addr as u16)];
 
-                    // Get the actual color from the tile using the pattern table
-                    let pixel_y = scanline_in_tile as u8;
+                    let palette_select = self.attribute_palette_in(fetch.nametable, fetch.tile_x, fetch.tile_y);
 
                     // Get color index from pattern table (0-3)
-                    let color = get_tile_pixel(tile_idx, pixel_x, pixel_y, bg_pattern_table_base, &self.chr_rom);
+                    let color = get_tile_pixel(tile_idx, fetch.pixel_x, fetch.pixel_y, bg_pattern_table_base, self.pattern_tables());
 
                     // Use palette to get final color index (0-63)
                     if color > 0 {
+                        source = PixelSource::Background { palette: palette_select };
                         palette_select * 4 + color
                     } else {
                         0 // Background color (palette index 0 of selected palette)
                     }
                 }
             } else if render_sprites {
-                // Simple sprite rendering - check OAM for sprites on this scanline
+                // Simple sprite rendering - the sprites secondary OAM holds for this scanline
                 let mut sprite_color: u8 = 0;
                 let mut sprite_found = false;
 
-                for sprite_idx in 0..64 {
-                    let oam_base = (sprite_idx as usize) * 4;
-
-                    if oam_base + 3 >= self.oam.len() {
-                        break;
-                    }
-
-                    let sprite_y = self.oam[oam_base] as i32;
-                    let tile_idx = self.oam[oam_base + 1];
-                    let flags = self.oam[oam_base + 2];
-                    let sprite_x = self.oam[oam_base + 3] as i32;
+                for sprite in line_sprites.sprites() {
+                    let sprite_idx = sprite.index;
+                    let [sprite_y, tile_idx, flags, sprite_x] = sprite.bytes;
+                    let (sprite_y, sprite_x) = (sprite_y as i32, sprite_x as i32);
 
                     // Check if sprite is on this scanline
                     let sprite_height = if self.control.sprite_size() { 16 } else { 8 };
 
                     // Sprite is visible if: sprite_y <= scanline < sprite_y + sprite_height
                     // Sprite Y position is offset by 1 (sprite at Y=0 is drawn at scanline 1)
-                    if (scanline as i32) >= (sprite_y + 1) && (scanline as i32) < (sprite_y + 1 + sprite_height as i32) {
+                    if (scanline as i32) >= (sprite_y + 1) && (scanline as i32) < (sprite_y + 1 + sprite_height) {
                         let pixel_y = (scanline as i32 - (sprite_y + 1)) as u8;
 
                         if self.control.sprite_size() {
@@ -666,17 +1385,18 @@ impl Ppu {
                                 // Flipped vertically
                                 tile_idx as u16 + (1 - tile_row) * 2
                             } else {
-                                tile_idx as u16 + tile_row as u16
+                                tile_idx as u16 + tile_row
                             };
 
                             let pixel_x = x as i32 - sprite_x;
-                            if pixel_x >= 0 && pixel_x < 8 {
-                                let color = get_tile_pixel(actual_tile as u8, pixel_x as u8, actual_y as u8, sprite_pattern_table_base, &self.chr_rom);
+                            if (0..8).contains(&pixel_x) {
+                                let color = get_tile_pixel(actual_tile as u8, pixel_x as u8, actual_y, sprite_pattern_table_base, self.pattern_tables());
                                 if color > 0 {
                                     // Sprite palette is in bits 4-5 of flags
                                     let sprite_palette = ((flags >> 4) as usize & 3) * 4;
                                     sprite_color = ((sprite_palette + color as usize) as u8).min(63);
                                     sprite_found = true;
+                                    source = PixelSource::Sprite { index: sprite_idx, palette: (sprite_palette / 4) as u8 };
                                     break;
                                 }
                             }
@@ -684,16 +1404,17 @@ impl Ppu {
                             // 8x8 sprite
                             let pixel_x = x as i32 - sprite_x;
 
-                            if pixel_x >= 0 && pixel_x < 8 {
+                            if (0..8).contains(&pixel_x) {
                                 let actual_y = if (flags & 0x80) != 0 { 7 - pixel_y } else { pixel_y };
                                 let actual_tile = if (flags & 0x40) != 0 { tile_idx + 1 } else { tile_idx };
 
-                                let color = get_tile_pixel(actual_tile, pixel_x as u8, actual_y as u8, sprite_pattern_table_base, &self.chr_rom);
+                                let color = get_tile_pixel(actual_tile, pixel_x as u8, actual_y, sprite_pattern_table_base, self.pattern_tables());
 
                                 if color > 0 {
                                     let sprite_palette = ((flags >> 4) as usize & 3) * 4;
                                     sprite_color = (sprite_palette as u8 + color).min(63);
                                     sprite_found = true;
+                                    source = PixelSource::Sprite { index: sprite_idx, palette: (sprite_palette / 4) as u8 };
                                     break;
                                 }
                             }
@@ -709,15 +1430,106 @@ impl Ppu {
                 0  // Black when nothing rendered
             };
 
-            let rgb = palette_table[color_idx as usize % palette_table.len()];
+            let entry = match source {
+                PixelSource::Sprite { .. } => 0x10 + color_idx as usize,
+                _ => color_idx as usize,
+            };
+            let rgb = palette_rgb(self.palette_color(entry));
             let idx = x * 3;
             framebuffer[idx] = rgb.0;
             framebuffer[idx + 1] = rgb.1;
             framebuffer[idx + 2] = rgb.2;
+
+            if let Some(sources) = sources.as_deref_mut() {
+                if let Some(slot) = sources.get_mut(x) {
+                    *slot = source;
+                }
+            }
         }
     }
 }
 
+/// Which frame's raster logs the renderer replays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RasterFrame {
+    /// The last completed frame (frames rendered after the fact)
+    Completed,
+    /// The frame in progress (lines rendered as they finish)
+    InProgress,
+}
+
+/// Source of one background pixel
+struct BackgroundFetch {
+    /// Nametable (0-3) before mirroring
+    nametable: usize,
+    tile_x: usize,
+    tile_y: usize,
+    /// Column and row within the tile
+    pixel_x: u8,
+    pixel_y: u8,
+}
+
+/// Step `v` down one line: fine Y, then coarse Y, which wraps after row 29
+/// into the vertically adjacent nametable (rows 30-31 wrap without switching)
+fn increment_y(v: u16) -> u16 {
+    if v & 0x7000 != 0x7000 {
+        return v + 0x1000;
+    }
+    let v = v & !0x7000;
+    match (v >> 5) & 0x1F {
+        29 => (v & !0x03E0) ^ 0x0800,
+        31 => v & !0x03E0,
+        _ => v + 0x20,
+    }
+}
+
+/// `v` after `lines` Y increments
+fn scroll_lines(v: u16, lines: usize) -> u16 {
+    // Eight increments step coarse Y and leave fine Y where it was
+    let mut v = v;
+    for _ in 0..lines / 8 {
+        let fine_y = v & 0x7000;
+        v = increment_y(v | 0x7000) | fine_y;
+    }
+    (0..lines % 8).fold(v, |v, _| increment_y(v))
+}
+
+/// Replace the `bits` of `v` with those of `t`
+fn copy_scroll_bits(v: &mut u16, t: u16, bits: u16) {
+    *v = (*v & !bits) | (t & bits);
+}
+
+/// Value of a raster-logged register for a pixel (see `Ppu::mask_at`)
+fn raster_value(start: u8, changes: &[(u32, u8)], scanline: usize, x: usize) -> u8 {
+    let position = scanline as u32 * DOTS_PER_SCANLINE as u32 + x as u32 + 1;
+    changes.iter().take_while(|&&(at, _)| at <= position).last().map_or(start, |&(_, value)| value)
+}
+
+/// Write a raster change log: the count, then (position, value) pairs
+fn write_raster_changes(writer: &mut StateWriter, changes: &[(u32, u8)]) {
+    writer.write_u32(changes.len() as u32);
+    for &(position, value) in changes {
+        writer.write_u32(position);
+        writer.write_u8(value);
+    }
+}
+
+/// Read a log written by `write_raster_changes`
+fn read_raster_changes(reader: &mut StateReader) -> Result<Vec<(u32, u8)>, StateError> {
+    let count = reader.read_u32()? as usize;
+    // Each entry takes 5 bytes; a bad count must not allocate a huge log
+    if count > reader.remaining() / 5 {
+        return Err(StateError::UnexpectedEnd);
+    }
+    (0..count).map(|_| Ok((reader.read_u32()?, reader.read_u8()?))).collect()
+}
+
+/// Fold the sprite backdrop entries ($3F10/$3F14/$3F18/$3F1C) onto $3F00-$3F0C
+fn palette_mirror(idx: usize) -> usize {
+    let idx = idx % PALETTE_SIZE;
+    if idx & 0x13 == 0x10 { idx & 0x0F } else { idx }
+}
+
 impl Default for Ppu {
     fn default() -> Self {
         Self::new()
@@ -768,6 +1580,302 @@ mod tests {
         assert_eq!(ppu.get_palette_byte(3), 0x78);
     }
 
+    #[test]
+    fn test_render_frame_fills_every_row() {
+        let mut ppu = Ppu::new();
+        let mut framebuffer = vec![0xAA; FRAME_RGB_SIZE];
+        ppu.render_frame(&mut framebuffer);
+
+        // Rendering disabled: every pixel is palette entry 0
+        let first = [framebuffer[0], framebuffer[1], framebuffer[2]];
+        let last = &framebuffer[FRAME_RGB_SIZE - 3..];
+        assert_eq!(first, [84, 84, 84]);
+        assert_eq!(last, &first);
+
+        ppu.mask = PpuMask::new(PpuMask::RENDER_BG);
+        ppu.render_frame(&mut framebuffer);
+        assert!(framebuffer.chunks(3).all(|px| px == first));
+    }
+
+    #[test]
+    fn test_framebuffer_fills_as_lines_finish() {
+        let mut ppu = Ppu::new();
+        assert!(ppu.framebuffer().is_empty());
+        ppu.set_framebuffer_enabled(true);
+        ppu.set_palette_entry(0x00, 0x21);
+
+        // A backdrop change mid-frame splits the line-by-line frame, not the after-the-fact one
+        while ppu.scanline() != 120 {
+            ppu.step();
+        }
+        ppu.set_palette_entry(0x00, 0x16);
+        while ppu.scanline() != -1 {
+            ppu.step();
+        }
+        let (above, below) = ppu.framebuffer().split_at(120 * FRAME_WIDTH * 3);
+        let (r, g, b) = palette_rgb(0x21);
+        assert!(above.chunks(3).all(|px| px == [r, g, b]));
+        let (r, g, b) = palette_rgb(0x16);
+        assert!(below.chunks(3).all(|px| px == [r, g, b]));
+
+        let mut framebuffer = vec![0; FRAME_RGB_SIZE];
+        ppu.render_frame(&mut framebuffer);
+        assert!(framebuffer.chunks(3).all(|px| px == [r, g, b]));
+
+        ppu.set_framebuffer_enabled(false);
+        assert!(!ppu.framebuffer_enabled());
+    }
+
+    #[test]
+    fn test_set_palette_entry_recolors_backdrop() {
+        let mut ppu = Ppu::new();
+        assert_eq!(ppu.palette_color(0x05), 0x00);
+
+        // $3F10 mirrors the universal backdrop at $3F00
+        ppu.set_palette_entry(0x10, 0x21);
+        assert_eq!(ppu.palette_color(0x00), 0x21);
+        assert_eq!(ppu.palette_color(0x10), 0x21);
+        assert_eq!(ppu.palette_ram()[0], 0x21);
+
+        let mut framebuffer = vec![0; FRAME_RGB_SIZE];
+        ppu.render_frame(&mut framebuffer);
+        let (r, g, b) = palette_rgb(0x21);
+        assert!(framebuffer.chunks(3).all(|px| px == [r, g, b]));
+
+        // The edit is palette RAM: the game reads it back and can overwrite it
+        ppu.write(0x2006, 0x3F);
+        ppu.write(0x2006, 0x00);
+        assert_eq!(ppu.read(0x2007), 0x21);
+        ppu.write(0x2006, 0x3F);
+        ppu.write(0x2006, 0x10);
+        ppu.write(0x2007, 0x0F);
+        assert_eq!(ppu.palette_color(0x00), 0x0F);
+
+        ppu.reset();
+        assert_eq!(ppu.palette_color(0x00), 0);
+    }
+
+    #[test]
+    fn test_background_tile_and_attribute_lookup() {
+        let mut ppu = Ppu::new();
+        assert_eq!(ppu.background_tile_at(17, 9), (2, 1, 1));
+
+        // Fine X starts the line that many pixels into the first tile, and
+        // the column wraps at 32 into the next nametable
+        ppu.write(0x2005, 31 * 8 + 3);
+        ppu.write(0x2005, 8 * 2 + 5);
+        assert_eq!(ppu.fine_x(), 3);
+        assert_eq!(ppu.background_tile_at(0, 0), (31, 2, 3));
+        assert_eq!(ppu.background_tile_at(4, 0), (31, 2, 7));
+        assert_eq!(ppu.background_tile_at(5, 0), (0, 2, 0));
+        // Fine Y 5 reaches the next tile row after three lines
+        assert_eq!(ppu.background_tile_at(5, 3), (0, 3, 0));
+        assert_eq!(ppu.video_address(), 0);
+
+        // Upper-right quadrant of the first attribute byte uses palette 3
+        ppu.vram[0x23C0] = 0b0000_1100;
+        assert_eq!(ppu.attribute_palette(2, 1), 3);
+        assert_eq!(ppu.attribute_palette(1, 1), 0);
+        // Nametable 1 is a separate page with vertical mirroring
+        ppu.set_mirroring(Mirroring::Vertical);
+        ppu.nametable = 1;
+        assert_eq!(ppu.attribute_palette(2, 1), 0);
+        ppu.set_mirroring(Mirroring::Horizontal);
+        assert_eq!(ppu.attribute_palette(2, 1), 3);
+    }
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let mut ppu = Ppu::new();
+        let index = |ppu: &Ppu| [0x2005, 0x2405, 0x2805, 0x2C05, 0x3405].map(|address| ppu.vram_index(address));
+        assert_eq!(index(&ppu), [0x2005, 0x2005, 0x2405, 0x2405, 0x2005]);
+        ppu.set_mirroring(Mirroring::Vertical);
+        assert_eq!(index(&ppu), [0x2005, 0x2405, 0x2005, 0x2405, 0x2405]);
+        ppu.set_mirroring(Mirroring::SingleScreenB);
+        assert_eq!(index(&ppu), [0x2405; 5]);
+        ppu.set_mirroring(Mirroring::FourScreen);
+        assert_eq!(index(&ppu), [0x2005, 0x2405, 0x2805, 0x2C05, 0x2405]);
+        // Pattern tables and palette addresses are not mirrored here
+        assert_eq!(ppu.vram_index(0x1234), 0x1234);
+        assert_eq!(ppu.vram_index(0x3F00), 0x3F00);
+
+        // PPUDATA goes through the mirroring
+        ppu.set_mirroring(Mirroring::Horizontal);
+        ppu.address = 0x2400;
+        ppu.write(0x2007, 0x42);
+        assert_eq!(ppu.vram()[0x2000], 0x42);
+    }
+
+    #[test]
+    fn test_ppudata_reaches_palette_ram() {
+        let mut ppu = Ppu::new();
+        ppu.vram[ppu.vram_index(0x2F11)] = 0x99;
+        let write = |ppu: &mut Ppu, address: u16, values: &[u8]| {
+            ppu.write(0x2006, (address >> 8) as u8);
+            ppu.write(0x2006, address as u8);
+            for &value in values {
+                ppu.write(0x2007, value);
+            }
+        };
+        write(&mut ppu, 0x3F00, &[0x0F, 0x21, 0x16, 0xC7]);
+        assert_eq!((0..4).map(|i| ppu.palette_color(i)).collect::<Vec<_>>(), [0x0F, 0x21, 0x16, 0x07]);
+        assert_eq!(ppu.vram()[0x3F00], 0, "palette writes don't land in VRAM");
+
+        // $3F10/$3F14/$3F18/$3F1C share their bytes with $3F00/$3F04/$3F08/$3F0C,
+        // and $3F20-$3FFF mirror the whole palette
+        write(&mut ppu, 0x3F10, &[0x30]);
+        assert_eq!((ppu.palette_color(0x00), ppu.palette_color(0x10)), (0x30, 0x30));
+        write(&mut ppu, 0x3FFC, &[0x2A]);
+        assert_eq!((ppu.palette_color(0x0C), ppu.palette_color(0x1C)), (0x2A, 0x2A));
+        write(&mut ppu, 0x3F11, &[0x12]);
+        assert_eq!((ppu.palette_color(0x01), ppu.palette_color(0x11)), (0x21, 0x12));
+
+        // Reads come straight back; the buffer fills from the nametable below
+        write(&mut ppu, 0x3F11, &[]);
+        assert_eq!(ppu.read(0x2007), 0x12);
+        write(&mut ppu, 0x2000, &[]);
+        assert_eq!(ppu.read(0x2007), 0x99);
+    }
+
+    #[test]
+    fn test_snapshot_dirty_lines() {
+        let mut ppu = Ppu::new();
+        let first = ppu.snapshot_frame();
+        assert_eq!(first.dirty_count(), FRAME_HEIGHT);
+
+        let unchanged = ppu.snapshot_frame();
+        assert_eq!(unchanged.dirty_lines().count(), 0);
+
+        // Tile 0 has only its top row set, so only every 8th line changes
+        let mut chr_rom = vec![0; 8192];
+        chr_rom[0] = 0xFF;
+        ppu.set_chr_rom(chr_rom);
+        ppu.set_palette_entry(0x01, 0x30);
+        ppu.mask = PpuMask::new(PpuMask::RENDER_BG);
+        let changed = ppu.snapshot_frame();
+        assert!(changed.dirty_lines().eq((0..FRAME_HEIGHT).step_by(8)));
+        assert_ne!(changed.line(8), first.line(8));
+        assert_eq!(changed.line(9), first.line(9));
+
+        ppu.reset();
+        assert_eq!(ppu.snapshot_frame().dirty_count(), FRAME_HEIGHT);
+    }
+
+    #[test]
+    fn test_mid_scanline_mask_write() {
+        let mut ppu = Ppu::new();
+        let mut chr_rom = vec![0; 8192];
+        chr_rom[..8].fill(0xFF); // Tile 0: every pixel opaque
+        ppu.set_chr_rom(chr_rom);
+        let run_to = |ppu: &mut Ppu, scanline: i16, dot: u16| {
+            ppu.step();
+            while (ppu.scanline, ppu.dot) != (scanline, dot) {
+                ppu.step();
+            }
+        };
+        let first_drawn = |ppu: &Ppu, y: usize| {
+            let mut sources = vec![PixelSource::Backdrop; FRAME_WIDTH * FRAME_HEIGHT];
+            ppu.render_frame_with_sources(&mut vec![0; FRAME_RGB_SIZE], &mut sources);
+            sources[y * FRAME_WIDTH..(y + 1) * FRAME_WIDTH].iter().position(|&s| s != PixelSource::Backdrop)
+        };
+
+        // Turn the background on at dot 100 of scanline 10, then finish the frame
+        run_to(&mut ppu, 10, 100);
+        ppu.write(0x2001, PpuMask::RENDER_BG);
+        run_to(&mut ppu, -1, 0);
+        assert_eq!(first_drawn(&ppu, 9), None);
+        // Pixel x is output on dot x + 1
+        assert_eq!(first_drawn(&ppu, 10), Some((100 + MASK_WRITE_DELAY - 1) as usize));
+        assert_eq!(first_drawn(&ppu, 11), Some(0));
+
+        // A frame without mid-frame writes uses the current value throughout
+        run_to(&mut ppu, 0, 0);
+        ppu.write(0x2001, 0);
+        run_to(&mut ppu, -1, 0);
+        ppu.write(0x2001, PpuMask::RENDER_BG);
+        run_to(&mut ppu, -1, 0);
+        assert_eq!(first_drawn(&ppu, 9), Some(0));
+    }
+
+    #[test]
+    fn test_render_frame_with_sources() {
+        let mut ppu = Ppu::new();
+        let mut chr_rom = vec![0; 8192];
+        chr_rom[16] = 0x80; // Tile 1, top row: leftmost pixel opaque
+        ppu.set_chr_rom(chr_rom);
+
+        // Sprite 5 at (10, 20) using tile 1
+        ppu.write_oam_bytes(20, &[19, 1, 0x10, 10]);
+        ppu.mask = PpuMask::new(PpuMask::RENDER_SPR);
+
+        let mut framebuffer = vec![0; FRAME_RGB_SIZE];
+        let mut sources = vec![PixelSource::Backdrop; FRAME_WIDTH * FRAME_HEIGHT];
+        ppu.render_frame_with_sources(&mut framebuffer, &mut sources);
+
+        assert_eq!(sources[20 * FRAME_WIDTH + 10], PixelSource::Sprite { index: 5, palette: 1 });
+        assert_eq!(sources[20 * FRAME_WIDTH + 11], PixelSource::Backdrop);
+        assert_eq!(sources[21 * FRAME_WIDTH + 10], PixelSource::Backdrop);
+
+        // Background tile 0 with an opaque top-left pixel
+        let mut chr_rom = vec![0; 8192];
+        chr_rom[0] = 0x80;
+        ppu.set_chr_rom(chr_rom);
+        ppu.mask = PpuMask::new(PpuMask::RENDER_BG);
+        ppu.render_frame_with_sources(&mut framebuffer, &mut sources);
+        assert_eq!(sources[0], PixelSource::Background { palette: 0 });
+        assert_eq!(sources[1], PixelSource::Backdrop);
+    }
+
+    #[test]
+    fn test_sprite_evaluation_limits_lines_and_sets_overflow() {
+        let mut ppu = Ppu::new();
+        let mut chr_rom = vec![0; 8192];
+        chr_rom[16] = 0xFF; // Tile 1, top row opaque
+        ppu.set_chr_rom(chr_rom);
+
+        // Nine sprites on line 20, 16 pixels apart
+        for i in 0..9u8 {
+            ppu.write_oam_bytes(i * 4, &[19, 1, 0x00, i * 16]);
+        }
+        for i in 9..64u8 {
+            ppu.write_oam_bytes(i * 4, &[0xFF; 4]);
+        }
+        ppu.mask = PpuMask::new(PpuMask::RENDER_SPR);
+
+        // Line 19 evaluates line 20's sprites and finds the ninth
+        while ppu.scanline < 19 || ppu.dot < 256 {
+            ppu.step();
+        }
+        assert!(ppu.status.sprite_overflow());
+        while !ppu.frame_complete {
+            ppu.step();
+        }
+        assert!(ppu.status.sprite_overflow(), "the flag lasts through vblank");
+
+        // The completed frame draws what secondary OAM held, whatever OAM holds now
+        ppu.write_oam_bytes(0, &[99]);
+        let mut framebuffer = vec![0; FRAME_RGB_SIZE];
+        let mut sources = vec![PixelSource::Backdrop; FRAME_WIDTH * FRAME_HEIGHT];
+        ppu.render_frame_with_sources(&mut framebuffer, &mut sources);
+        assert_eq!(sources[20 * FRAME_WIDTH], PixelSource::Sprite { index: 0, palette: 0 });
+        assert_eq!(sources[20 * FRAME_WIDTH + 7 * 16], PixelSource::Sprite { index: 7, palette: 0 });
+        assert_eq!(sources[20 * FRAME_WIDTH + 8 * 16], PixelSource::Backdrop);
+        assert_eq!(sources[100 * FRAME_WIDTH], PixelSource::Backdrop);
+
+        // The pre-render line clears the flag
+        ppu.step();
+        assert!(!ppu.status.sprite_overflow());
+    }
+
+    #[test]
+    fn test_write_oam_bytes_wraps() {
+        let mut ppu = Ppu::new();
+        ppu.write_oam_bytes(0xFE, &[1, 2, 3, 4]);
+        assert_eq!(&ppu.oam()[0xFE..], &[1, 2]);
+        assert_eq!(&ppu.oam()[..2], &[3, 4]);
+        assert_eq!(ppu.oam_address(), 0);
+    }
+
     #[test]
     fn test_ppu_get_palette() {
         let mut ppu = Ppu::new();
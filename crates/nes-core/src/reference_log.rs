@@ -0,0 +1,352 @@
+//! Comparison against reference emulator traces
+//!
+//! Reference emulators (Nintendulator, Mesen) and the well-known nestest.log
+//! write one line per instruction with the CPU state before it ran:
+//!
+//! ```text
+//! C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+//! ```
+//!
+//! `compare_log` runs the system alongside such a log and checks every
+//! column: PC, registers, the PPU line/dot and the CPU cycle count. It stops
+//! at the first line that differs and reports it with the lines leading up
+//! to it, which is usually enough to see which instruction got its timing or
+//! flags wrong.
+//!
+//! Reference logs start from their own power-on point, so cycles and PPU
+//! positions are compared as the time elapsed since the first line: the
+//! "actual" entry shows the log's starting position advanced by what the
+//! system ran.
+
+use crate::cpu::{CpuError, StatusFlags};
+use crate::ppu::DOTS_PER_SCANLINE;
+use crate::system::NesSystem;
+use std::fmt;
+
+/// Log lines shown before a divergence
+pub const CONTEXT_LINES: usize = 5;
+/// Scanlines per frame in the logs' numbering (0-261)
+const SCANLINES_PER_FRAME: u32 = 262;
+
+/// One line of a reference log: the CPU state before an instruction
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LogEntry {
+    /// Address of the opcode
+    pub pc: u16,
+    /// Opcode and operand bytes
+    pub bytes: Vec<u8>,
+    /// Disassembly as written in the log (empty for captured states)
+    pub instruction: String,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    /// PPU scanline (0-261)
+    pub ppu_line: u16,
+    /// PPU dot (0-340)
+    pub ppu_dot: u16,
+    /// Total CPU cycles
+    pub cycles: u64,
+}
+
+impl LogEntry {
+    /// Parse a log line; returns None for blank or malformed lines
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        let pc = u16::from_str_radix(line.get(0..4)?, 16).ok()?;
+        let registers_start = line.find("A:")?;
+
+        // Up to three two-digit bytes, then the disassembly; unofficial opcodes
+        // are marked with a '*' that can follow the third byte after one space
+        let mut rest = line.get(4..registers_start)?.trim();
+        let mut bytes = Vec::new();
+        while bytes.len() < 3 {
+            let Some(byte) = rest.get(..2).filter(|_| rest[2..].is_empty() || rest[2..].starts_with(' ')) else {
+                break;
+            };
+            let Ok(byte) = u8::from_str_radix(byte, 16) else {
+                break;
+            };
+            bytes.push(byte);
+            rest = rest[2..].trim_start();
+        }
+        let instruction = rest;
+
+        let registers = &line[registers_start..];
+        let ppu = registers.get(registers.find("PPU:")? + 4..)?;
+        let (ppu_line, rest) = ppu.split_once(',')?;
+        let ppu_dot = rest.split_whitespace().next()?;
+        let cycles = registers.get(registers.find("CYC:")? + 4..)?.split_whitespace().next()?;
+
+        Some(Self {
+            pc,
+            bytes,
+            instruction: instruction.trim().to_string(),
+            a: field(registers, "A:")?,
+            x: field(registers, "X:")?,
+            y: field(registers, "Y:")?,
+            p: field(registers, "P:")?,
+            sp: field(registers, "SP:")?,
+            ppu_line: ppu_line.trim().parse().ok()?,
+            ppu_dot: ppu_dot.parse().ok()?,
+            cycles: cycles.parse().ok()?,
+        })
+    }
+
+    /// Columns that differ from `other`
+    pub fn differences(&self, other: &LogEntry) -> Vec<LogField> {
+        [
+            (LogField::Pc, self.pc == other.pc),
+            (LogField::A, self.a == other.a),
+            (LogField::X, self.x == other.x),
+            (LogField::Y, self.y == other.y),
+            (LogField::P, self.p == other.p),
+            (LogField::Sp, self.sp == other.sp),
+            (LogField::Ppu, (self.ppu_line, self.ppu_dot) == (other.ppu_line, other.ppu_dot)),
+            (LogField::Cycles, self.cycles == other.cycles),
+        ]
+        .into_iter()
+        .filter(|&(_, same)| !same)
+        .map(|(field, _)| field)
+        .collect()
+    }
+}
+
+/// Two-digit hex value of the register column starting with `prefix`
+fn field(registers: &str, prefix: &str) -> Option<u8> {
+    let hex = registers.split_whitespace().find_map(|word| word.strip_prefix(prefix))?;
+    u8::from_str_radix(hex.get(..2)?, 16).ok()
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        // The '*' of unofficial opcodes goes in the column before the mnemonic
+        let instruction = if self.instruction.starts_with('*') { self.instruction.clone() } else { format!(" {}", self.instruction) };
+        write!(
+            f,
+            "{:04X}  {:<8} {:<33}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            self.pc,
+            bytes.join(" "),
+            instruction,
+            self.a,
+            self.x,
+            self.y,
+            self.p,
+            self.sp,
+            self.ppu_line,
+            self.ppu_dot,
+            self.cycles
+        )
+    }
+}
+
+/// Parse every valid line of a log
+pub fn parse_log(text: &str) -> Vec<LogEntry> {
+    text.lines().filter_map(LogEntry::parse).collect()
+}
+
+/// A column of a reference log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogField {
+    Pc,
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    /// PPU line and dot
+    Ppu,
+    Cycles,
+}
+
+impl fmt::Display for LogField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogField::Pc => "PC",
+            LogField::A => "A",
+            LogField::X => "X",
+            LogField::Y => "Y",
+            LogField::P => "P",
+            LogField::Sp => "SP",
+            LogField::Ppu => "PPU",
+            LogField::Cycles => "CYC",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The first log line the system didn't match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the entry in the log (0-based)
+    pub index: usize,
+    pub expected: LogEntry,
+    /// The system's state in the log's format
+    pub actual: LogEntry,
+    /// Columns that differ
+    pub fields: Vec<LogField>,
+    /// Up to `CONTEXT_LINES` entries before the divergence, which all matched
+    pub context: Vec<LogEntry>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self.fields.iter().map(LogField::to_string).collect();
+        writeln!(f, "first divergence at log entry {} ({} differ):", self.index + 1, fields.join(", "))?;
+        for entry in &self.context {
+            writeln!(f, "  {}", entry)?;
+        }
+        writeln!(f, "- {}", self.expected)?;
+        write!(f, "+ {}", self.actual)
+    }
+}
+
+/// Result of running the system against a log
+#[derive(Debug)]
+pub struct LogComparison {
+    /// Entries that matched
+    pub matched: usize,
+    /// Entries in the log
+    pub total: usize,
+    /// The first entry that didn't match, if any
+    pub divergence: Option<Divergence>,
+    /// CPU error that ended the run before the log did
+    pub error: Option<CpuError>,
+}
+
+impl LogComparison {
+    /// Check if every entry matched
+    pub fn passed(&self) -> bool {
+        self.matched == self.total
+    }
+}
+
+impl fmt::Display for LogComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "matched {}/{} log entries", self.matched, self.total)?;
+        if let Some(divergence) = &self.divergence {
+            write!(f, "\n{}", divergence)?;
+        } else if let Some(error) = &self.error {
+            write!(f, "\nCPU error after entry {}: {}", self.matched, error)?;
+        } else if !self.passed() {
+            write!(f, "\nCPU stopped after entry {}", self.matched)?;
+        }
+        Ok(())
+    }
+}
+
+/// Put the CPU in the state of a log entry (logs like nestest's start at an entry point, not the reset vector)
+pub fn start_from(system: &mut NesSystem, entry: &LogEntry) {
+    let cpu = system.cpu_mut();
+    let registers = cpu.registers_mut();
+    registers.pc = entry.pc;
+    registers.a = entry.a;
+    registers.x = entry.x;
+    registers.y = entry.y;
+    registers.p = entry.p;
+    registers.sp = entry.sp;
+    *cpu.status_mut() = StatusFlags::new(entry.p);
+}
+
+/// PPU position in dots from the start of the frame, counting the pre-render line as the first
+fn ppu_position(system: &NesSystem) -> u32 {
+    let ppu = system.ppu();
+    (ppu.scanline() + 1) as u32 * DOTS_PER_SCANLINE as u32 + ppu.dot() as u32
+}
+
+/// Run the system against `entries`, stopping at the first entry that differs
+///
+/// The system should already be in the first entry's state (see `start_from`).
+pub fn compare_log(system: &mut NesSystem, entries: &[LogEntry]) -> LogComparison {
+    let mut comparison = LogComparison { matched: 0, total: entries.len(), divergence: None, error: None };
+    let Some(first) = entries.first() else {
+        return comparison;
+    };
+    let frame_dots = SCANLINES_PER_FRAME * DOTS_PER_SCANLINE as u32;
+    let first_position = first.ppu_line as u32 * DOTS_PER_SCANLINE as u32 + first.ppu_dot as u32;
+    let start_cycles = system.cpu().total_cycles();
+    let start_position = ppu_position(system);
+
+    for (index, expected) in entries.iter().enumerate() {
+        let elapsed_dots = (ppu_position(system) + frame_dots - start_position) % frame_dots;
+        let position = (first_position + elapsed_dots) % frame_dots;
+        let cpu = system.cpu();
+        let registers = cpu.registers();
+        let len = cpu.decode_opcode(system.peek_memory(registers.pc)).map_or(1, |opcode| cpu.instruction_length(opcode));
+        let actual = LogEntry {
+            pc: registers.pc,
+            bytes: (0..len as u16).map(|offset| system.peek_memory(registers.pc.wrapping_add(offset))).collect(),
+            instruction: String::new(),
+            a: registers.a,
+            x: registers.x,
+            y: registers.y,
+            p: cpu.p_register(),
+            sp: registers.sp,
+            ppu_line: (position / DOTS_PER_SCANLINE as u32) as u16,
+            ppu_dot: (position % DOTS_PER_SCANLINE as u32) as u16,
+            cycles: first.cycles + (cpu.total_cycles() - start_cycles),
+        };
+
+        let fields = expected.differences(&actual);
+        if !fields.is_empty() {
+            comparison.divergence = Some(Divergence {
+                index,
+                expected: expected.clone(),
+                actual,
+                fields,
+                context: entries[index.saturating_sub(CONTEXT_LINES)..index].to_vec(),
+            });
+            break;
+        }
+        comparison.matched += 1;
+
+        // The last entry is checked without running its instruction
+        if index + 1 == entries.len() {
+            break;
+        }
+        match system.step() {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(error) => {
+                comparison.error = Some(error);
+                break;
+            }
+        }
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12";
+
+    #[test]
+    fn test_parse_round_trip() {
+        let entry = LogEntry::parse(LINE).unwrap();
+        assert_eq!((entry.pc, entry.bytes.as_slice(), entry.instruction.as_str()), (0xC5F7, &[0x86, 0x00][..], "STX $00 = 00"));
+        assert_eq!((entry.p, entry.sp, entry.ppu_line, entry.ppu_dot, entry.cycles), (0x26, 0xFD, 0, 36, 12));
+        assert_eq!(entry.to_string(), LINE);
+
+        let wide = LogEntry::parse("C6A2  60        RTS                             A:00 X:FF Y:15 P:27 SP:FB PPU:233,191 CYC:26548").unwrap();
+        assert_eq!((wide.ppu_line, wide.ppu_dot, wide.cycles), (233, 191, 26548));
+        let line = "C6C9  0C A9 A9 *NOP $A9A9 = A9                  A:AA X:97 Y:4E P:EF SP:F7 PPU:128,158 CYC:14602";
+        let unofficial = LogEntry::parse(line).unwrap();
+        assert_eq!((unofficial.bytes.len(), unofficial.instruction.as_str()), (3, "*NOP $A9A9 = A9"));
+        assert_eq!(unofficial.to_string(), line);
+        assert!(LogEntry::parse("").is_none());
+        assert!(LogEntry::parse("not a log line").is_none());
+    }
+
+    #[test]
+    fn test_differences() {
+        let expected = LogEntry::parse(LINE).unwrap();
+        let actual = LogEntry { ppu_dot: 39, cycles: 13, x: 1, ..expected.clone() };
+        assert_eq!(expected.differences(&actual), [LogField::X, LogField::Ppu, LogField::Cycles]);
+        assert!(expected.differences(&expected).is_empty());
+    }
+}
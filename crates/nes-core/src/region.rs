@@ -0,0 +1,157 @@
+//! Console regions and their timing
+//!
+//! NTSC consoles run the PPU at exactly three dots per CPU cycle and draw 262
+//! lines a frame. PAL consoles divide a faster master clock differently
+//! (3.2 dots per CPU cycle), draw 312 lines and retune the APU's frame
+//! sequencer, noise and DMC tables to their slower CPU. The Dendy, a common
+//! Famiclone, draws PAL's 312 lines but keeps NTSC's 3:1 ratio and APU tables
+//! and starts vertical blanking 50 lines later so NTSC games keep their
+//! vblank time.
+//!
+//! Everything here is integer math; the PAL dot ratio is kept as a fraction.
+
+use core::fmt;
+use core::time::Duration;
+
+/// Dots per scanline, which is the same in every region
+const DOTS_PER_SCANLINE: u64 = 341;
+
+/// TV system a console was built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Region {
+    /// North America and Japan, ~60.1 frames per second
+    #[default]
+    Ntsc,
+    /// Europe and Australia, ~50 frames per second
+    Pal,
+    /// Dendy and similar Famiclones, ~50 frames per second with NTSC's CPU timing
+    Dendy,
+}
+
+impl Region {
+    /// Names accepted by `from_name`
+    pub const NAMES: [&'static str; 3] = ["ntsc", "pal", "dendy"];
+
+    /// Look up a region by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    /// Get the region's number in savestates (its index in `NAMES`)
+    pub(crate) fn state_id(self) -> u8 {
+        Self::NAMES.iter().position(|&name| name == self.name()).unwrap_or(0) as u8
+    }
+
+    /// Look up a region by its savestate number
+    pub(crate) fn from_state_id(id: u8) -> Option<Self> {
+        Self::NAMES.get(id as usize).and_then(|name| Self::from_name(name))
+    }
+
+    /// Get the region's name (one of `NAMES`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Dendy => "dendy",
+        }
+    }
+
+    /// Get the CPU clock in Hz
+    pub fn cpu_clock(self) -> u32 {
+        match self {
+            Region::Ntsc => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    /// Get the PPU dots per CPU cycle as a fraction (numerator, denominator)
+    pub fn dots_per_cpu_cycle(self) -> (u32, u32) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    /// Get the PPU dots run by the end of CPU cycle `cycle`, counted from power-on
+    ///
+    /// The dots in a run of cycles are the difference between its ends, so
+    /// PAL's fractional ratio spreads evenly without keeping a remainder.
+    pub fn dots_at_cycle(self, cycle: u64) -> u64 {
+        let (numerator, denominator) = self.dots_per_cpu_cycle();
+        cycle * numerator as u64 / denominator as u64
+    }
+
+    /// Get the number of scanlines per frame, including the pre-render line
+    pub fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// Get the scanline vertical blanking (and the NMI) starts on
+    pub fn vblank_scanline(self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Get the whole CPU cycles in a frame (29780 for NTSC)
+    pub fn cpu_cycles_per_frame(self) -> u32 {
+        let (numerator, denominator) = self.dots_per_cpu_cycle();
+        (DOTS_PER_SCANLINE * self.scanlines() as u64 * denominator as u64 / numerator as u64) as u32
+    }
+
+    /// Get the length of a frame, for frontends pacing their output
+    pub fn frame_duration(self) -> Duration {
+        let (numerator, denominator) = self.dots_per_cpu_cycle();
+        let dots = DOTS_PER_SCANLINE * self.scanlines() as u64;
+        let nanos = dots * denominator as u64 * 1_000_000_000 / (numerator as u64 * self.cpu_clock() as u64);
+        Duration::from_nanos(nanos)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_timing() {
+        assert_eq!(Region::Ntsc.cpu_cycles_per_frame(), 29780);
+        assert_eq!(Region::Pal.cpu_cycles_per_frame(), 33247);
+        assert_eq!(Region::Dendy.cpu_cycles_per_frame(), 35464);
+        assert_eq!(Region::Ntsc.frame_duration().as_micros(), 16_639);
+        assert_eq!(Region::Pal.frame_duration().as_micros(), 19_997);
+        assert_eq!(Region::Dendy.frame_duration().as_micros(), 19_997);
+    }
+
+    #[test]
+    fn test_pal_dots_spread_over_cycles() {
+        assert_eq!(Region::Ntsc.dots_at_cycle(7), 21);
+        let dots: Vec<u64> = (1..=5).map(|cycle| Region::Pal.dots_at_cycle(cycle) - Region::Pal.dots_at_cycle(cycle - 1)).collect();
+        assert_eq!(dots, [3, 3, 3, 3, 4]);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for name in Region::NAMES {
+            assert_eq!(Region::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(Region::from_name("PAL"), Some(Region::Pal));
+        assert_eq!(Region::from_name("secam"), None);
+    }
+}
@@ -0,0 +1,105 @@
+//! Deterministic random numbers
+//!
+//! Every stochastic feature (random power-on RAM, random CPU/PPU alignment)
+//! draws from the system's `Rng` instead of OS entropy, so a run is fully
+//! determined by its seed: record the seed (or save the RNG state with the
+//! rest of a savestate) and movies replay identically on every platform.
+//! Features take `&mut impl RandomSource`, so tests can substitute a
+//! scripted source.
+
+use crate::state::{StateError, StateReader, StateWriter};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Source of random numbers
+pub trait RandomSource {
+    /// Next 64 random bits
+    fn next_u64(&mut self) -> u64;
+
+    /// Next 32 random bits
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Next random byte
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Uniform value in `0..bound` (0 when `bound` is 0)
+    fn below(&mut self, bound: u32) -> u32 {
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+}
+
+/// SplitMix64 generator: small, fast and identical on every platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Pick a seed from OS entropy (record it to reproduce the run)
+    pub fn entropy_seed() -> u64 {
+        RandomState::new().build_hasher().finish()
+    }
+
+    /// Serialize the generator state
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.state);
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.state = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+impl RandomSource for Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_is_fixed_by_seed() {
+        // Reference values for SplitMix64 with seed 0
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        assert!((0..100).all(|_| a.next_u64() == b.next_u64()));
+        assert!((0..100).all(|_| a.below(3) < 3));
+        assert_eq!(a.below(0), 0);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut rng = Rng::new(7);
+        rng.next_u64();
+        let mut writer = StateWriter::new();
+        rng.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let mut restored = Rng::new(0);
+        restored.load_state(&mut StateReader::new(&bytes)).unwrap();
+        assert_eq!(restored.next_u64(), rng.next_u64());
+        assert_eq!(restored.load_state(&mut StateReader::new(&bytes[..4])), Err(StateError::UnexpectedEnd));
+    }
+}
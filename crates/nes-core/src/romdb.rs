@@ -0,0 +1,156 @@
+//! ROM database
+//!
+//! Per-game overrides keyed by the CRC32 of the ROM data (PRG + CHR, header excluded).
+//! The database is consulted when a ROM is loaded so games with special requirements
+//! (for example a specific power-on RAM pattern) work without user configuration.
+
+use crate::bus::RamInit;
+
+/// CRC32 of Minna no Taabou no Nakayoshi Daisakusen (Japan)
+pub const MINNA_NO_TAABOU_CRC32: u32 = 0xB3BB_C7A2;
+
+/// Database entry for a single game
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RomInfo {
+    /// CRC32 of PRG ROM followed by CHR ROM
+    pub crc32: u32,
+    /// Human readable title
+    pub name: String,
+    /// Power-on RAM pattern required by this game, if any
+    pub ram_init: Option<RamInit>,
+    /// PRG RAM size in bytes, for boards whose dumps often lack it in the header
+    pub prg_ram_size: Option<usize>,
+    /// Whether the board has bus conflicts, for dumps whose header doesn't say
+    pub bus_conflicts: Option<bool>,
+}
+
+impl RomInfo {
+    /// Create an entry with no overrides
+    pub fn new(crc32: u32, name: impl Into<String>) -> Self {
+        Self {
+            crc32,
+            name: name.into(),
+            ram_init: None,
+            prg_ram_size: None,
+            bus_conflicts: None,
+        }
+    }
+
+    /// Set the power-on RAM pattern override
+    pub fn with_ram_init(mut self, ram_init: RamInit) -> Self {
+        self.ram_init = Some(ram_init);
+        self
+    }
+
+    /// Set the bus conflict override
+    pub fn with_bus_conflicts(mut self, bus_conflicts: bool) -> Self {
+        self.bus_conflicts = Some(bus_conflicts);
+        self
+    }
+
+    /// Set the PRG RAM size override in bytes
+    pub fn with_prg_ram_size(mut self, bytes: usize) -> Self {
+        self.prg_ram_size = Some(bytes);
+        self
+    }
+}
+
+/// Collection of per-game overrides
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    entries: Vec<RomInfo>,
+}
+
+impl RomDatabase {
+    /// Create an empty database
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the database of overrides shipped with the core
+    ///
+    /// Frontends can add their own entries with `insert`.
+    pub fn builtin() -> Self {
+        let mut database = Self::new();
+        // Hangs at boot unless RAM powers on filled with $FF
+        database.insert(
+            RomInfo::new(MINNA_NO_TAABOU_CRC32, "Minna no Taabou no Nakayoshi Daisakusen").with_ram_init(RamInit::Fill(0xFF)),
+        );
+        database
+    }
+
+    /// Add an entry, replacing any existing entry with the same CRC32
+    pub fn insert(&mut self, info: RomInfo) {
+        self.entries.retain(|e| e.crc32 != info.crc32);
+        self.entries.push(info);
+    }
+
+    /// Look up an entry by CRC32
+    pub fn lookup(&self, crc32: u32) -> Option<&RomInfo> {
+        self.entries.iter().find(|e| e.crc32 == crc32)
+    }
+
+    /// Iterate over the entries
+    pub fn iter(&self) -> impl Iterator<Item = &RomInfo> {
+        self.entries.iter()
+    }
+
+    /// Number of entries in the database
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the database has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Compute the CRC32 (IEEE 802.3) of a byte slice
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Continue a CRC32 computation with more data
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_lookup_and_replace() {
+        let mut db = RomDatabase::new();
+        db.insert(RomInfo::new(0x1234_5678, "Test Game"));
+        db.insert(RomInfo::new(0x1234_5678, "Test Game").with_ram_init(RamInit::Fill(0xFF)));
+
+        assert_eq!(db.len(), 1);
+        let info = db.lookup(0x1234_5678).unwrap();
+        assert_eq!(info.ram_init, Some(RamInit::Fill(0xFF)));
+        assert!(db.lookup(0).is_none());
+    }
+
+    #[test]
+    fn test_builtin_entries() {
+        let db = RomDatabase::builtin();
+        let info = db.lookup(MINNA_NO_TAABOU_CRC32).unwrap();
+        assert_eq!(info.ram_init, Some(RamInit::Fill(0xFF)));
+    }
+}
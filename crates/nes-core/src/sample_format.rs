@@ -0,0 +1,217 @@
+//! Audio sample formats and conversion helpers
+//!
+//! The APU produces mono `i16` mixer levels, which reach `sink::AudioSink` as
+//! `f32` in -1.0..=1.0 (see `from_i16`, or take them already converted with
+//! `NesSystem::take_audio_samples_f32`, which lives here to keep floats out of
+//! `system`).
+//! Outputs want something else: cpal devices take interleaved `i16` or `f32`,
+//! WebAudio takes `f32` per channel, WAV files take little-endian `i16` or
+//! unsigned 8-bit. `OutputFormat` converts a block of mono samples to the
+//! configured format and channel count in one place, so consumers don't each
+//! re-implement clamping and scaling. Out-of-range input is clamped, never
+//! wrapped.
+
+use crate::system::NesSystem;
+use std::fmt;
+
+/// Sample encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleFormat {
+    /// Signed 16-bit
+    #[default]
+    I16,
+    /// 32-bit float in -1.0..=1.0
+    F32,
+    /// Unsigned 8-bit, silence at 128
+    U8,
+}
+
+impl SampleFormat {
+    /// Names accepted by `parse`
+    pub const NAMES: [&'static str; 3] = ["i16", "f32", "u8"];
+
+    /// Parse `i16`, `f32` or `u8`
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "i16" => Some(SampleFormat::I16),
+            "f32" => Some(SampleFormat::F32),
+            "u8" => Some(SampleFormat::U8),
+            _ => None,
+        }
+    }
+
+    /// Size of one sample in bytes
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::I16 => 2,
+            SampleFormat::F32 => 4,
+            SampleFormat::U8 => 1,
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Self::NAMES[*self as usize])
+    }
+}
+
+/// Clamp a sample to -1.0..=1.0 (NaN becomes silence)
+pub fn clamp(sample: f32) -> f32 {
+    if sample.is_nan() {
+        0.0
+    } else {
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+/// Convert to signed 16-bit
+pub fn to_i16(sample: f32) -> i16 {
+    (clamp(sample) * i16::MAX as f32).round() as i16
+}
+
+/// Convert to unsigned 8-bit
+pub fn to_u8(sample: f32) -> u8 {
+    (clamp(sample) * 127.0 + 128.0).round() as u8
+}
+
+/// Convert from signed 16-bit
+pub fn from_i16(sample: i16) -> f32 {
+    (sample as f32 / i16::MAX as f32).max(-1.0)
+}
+
+/// Convert from unsigned 8-bit
+pub fn from_u8(sample: u8) -> f32 {
+    ((sample as f32 - 128.0) / 127.0).max(-1.0)
+}
+
+/// Format, channel count and rate an output expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub format: SampleFormat,
+    /// Interleaved channels; mono input is copied to each
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl OutputFormat {
+    /// Create a format (channels is at least 1)
+    pub fn new(format: SampleFormat, channels: u16, sample_rate: u32) -> Self {
+        Self { format, channels: channels.max(1), sample_rate }
+    }
+
+    /// Bytes per frame (one sample for every channel)
+    pub fn frame_bytes(&self) -> usize {
+        self.format.bytes_per_sample() * self.channels as usize
+    }
+
+    /// Interleave mono samples as `i16`
+    pub fn interleave_i16(&self, mono: &[f32]) -> Vec<i16> {
+        self.interleave(mono, to_i16)
+    }
+
+    /// Interleave mono samples as clamped `f32`
+    pub fn interleave_f32(&self, mono: &[f32]) -> Vec<f32> {
+        self.interleave(mono, clamp)
+    }
+
+    /// Interleave mono samples as `u8`
+    pub fn interleave_u8(&self, mono: &[f32]) -> Vec<u8> {
+        self.interleave(mono, to_u8)
+    }
+
+    /// Append mono samples to `out` as interleaved little-endian bytes in this format (WAV data)
+    pub fn encode_le(&self, mono: &[f32], out: &mut Vec<u8>) {
+        out.reserve(mono.len() * self.frame_bytes());
+        match self.format {
+            SampleFormat::I16 => out.extend(self.interleave_i16(mono).iter().flat_map(|s| s.to_le_bytes())),
+            SampleFormat::F32 => out.extend(self.interleave_f32(mono).iter().flat_map(|s| s.to_le_bytes())),
+            SampleFormat::U8 => out.extend(self.interleave_u8(mono)),
+        }
+    }
+
+    fn interleave<T: Copy>(&self, mono: &[f32], convert: impl Fn(f32) -> T) -> Vec<T> {
+        let channels = self.channels as usize;
+        let mut out = Vec::with_capacity(mono.len() * channels);
+        for &sample in mono {
+            out.extend(std::iter::repeat_n(convert(sample), channels));
+        }
+        out
+    }
+}
+
+impl NesSystem {
+    /// Take the audio samples produced since the last call as `f32` in -1.0..=1.0
+    ///
+    /// The same samples as `take_audio_samples`, converted with `from_i16`.
+    pub fn take_audio_samples_f32(&mut self) -> Vec<f32> {
+        self.take_audio_samples().into_iter().map(from_i16).collect()
+    }
+}
+
+impl Default for OutputFormat {
+    /// 16-bit stereo at 48kHz
+    fn default() -> Self {
+        Self::new(SampleFormat::I16, 2, 48_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+
+    #[test]
+    fn test_scalar_conversions_clamp() {
+        assert_eq!(to_i16(1.0), i16::MAX);
+        assert_eq!(to_i16(-1.0), -i16::MAX);
+        assert_eq!(to_i16(2.5), i16::MAX);
+        assert_eq!(to_i16(f32::NAN), 0);
+        assert_eq!(to_u8(0.0), 128);
+        assert_eq!(to_u8(-1.0), 1);
+        assert_eq!(to_u8(1.0), 255);
+        assert_eq!(from_i16(i16::MIN), -1.0);
+        assert_eq!(from_u8(to_u8(0.5)), 64.0 / 127.0);
+        assert!((from_i16(to_i16(0.25)) - 0.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_system_samples_as_f32() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.set_audio_enabled(true);
+        system.run_frames(1).unwrap();
+        let samples = system.take_audio_samples_f32();
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(system.take_audio_samples_f32().is_empty());
+    }
+
+    #[test]
+    fn test_interleave_and_encode() {
+        let mono = [0.5, -2.0];
+        let stereo = OutputFormat::new(SampleFormat::I16, 2, 44_100);
+        assert_eq!(stereo.interleave_i16(&mono), [16384, 16384, -32767, -32767]);
+        assert_eq!(stereo.interleave_f32(&mono), [0.5, 0.5, -1.0, -1.0]);
+
+        let mut bytes = Vec::new();
+        stereo.encode_le(&mono, &mut bytes);
+        assert_eq!(bytes.len(), mono.len() * stereo.frame_bytes());
+        assert_eq!(&bytes[..4], &[0x00, 0x40, 0x00, 0x40]);
+
+        let mono_u8 = OutputFormat::new(SampleFormat::U8, 0, 8_000);
+        assert_eq!(mono_u8.channels, 1);
+        bytes.clear();
+        mono_u8.encode_le(&mono, &mut bytes);
+        assert_eq!(bytes, [192, 1]);
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for name in SampleFormat::NAMES {
+            assert_eq!(SampleFormat::parse(name).unwrap().to_string(), name);
+        }
+        assert_eq!(SampleFormat::parse("F32"), Some(SampleFormat::F32));
+        assert_eq!(SampleFormat::parse("s24"), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Versioned savestate sections and migrations
+//!
+//! A savestate is a header (`STATE_MAGIC`, the container `STATE_VERSION`
+//! and the ROM's CRC32) followed by one section per component. Each section
+//! carries a 4-byte tag, its own layout version and its length, so a change
+//! to one component's layout only bumps that component's version:
+//!
+//! ```text
+//! tag [u8; 4] | version u16 | length u32 | payload [u8; length]
+//! ```
+//!
+//! When a section is older than the version this build writes, the
+//! registered `MIGRATIONS` upgrade its payload one version at a time before
+//! the component reads it. A section that is newer, or old without a
+//! migration path, fails with `StateError::UnsupportedSection`, which names
+//! the component and version ("PPU section v2 unsupported").
+//!
+//! Changing a component's `save_state` layout means bumping its version in
+//! `Section::version` and adding a migration from the previous version.
+
+use crate::apu::Apu;
+use crate::dma::DmcDma;
+use crate::ppu::{Ppu, PALETTE_SIZE, VRAM_SIZE};
+use crate::region::Region;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Component stored in its own savestate section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// CPU registers and interrupt lines
+    Cpu,
+    /// PPU memory, registers and raster position
+    Ppu,
+    /// APU registers, channels and frame sequencer
+    Apu,
+    /// RAM, I/O registers, controllers and the cartridge (RAM and mapper)
+    Bus,
+    /// System timing (frame count, NMI line, PPU warm-up, region)
+    System,
+    /// Random number generator
+    Rng,
+}
+
+impl Section {
+    /// Every section, in the order they are written
+    pub const ALL: [Section; 6] = [Section::Cpu, Section::Ppu, Section::Apu, Section::Bus, Section::System, Section::Rng];
+
+    /// Tag identifying the section in the state
+    pub fn tag(self) -> [u8; 4] {
+        match self {
+            Section::Cpu => *b"CPU ",
+            Section::Ppu => *b"PPU ",
+            Section::Apu => *b"APU ",
+            Section::Bus => *b"BUS ",
+            Section::System => *b"SYS ",
+            Section::Rng => *b"RNG ",
+        }
+    }
+
+    /// Name used in error messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Cpu => "CPU",
+            Section::Ppu => "PPU",
+            Section::Apu => "APU",
+            Section::Bus => "bus",
+            Section::System => "system",
+            Section::Rng => "RNG",
+        }
+    }
+
+    /// Layout version this build writes
+    pub fn version(self) -> u16 {
+        match self {
+            // v2: DMC sample reader appended
+            // v3: DMC IRQ enable and flag appended
+            Section::Bus => 3,
+            // v2: per-line scroll and fine X logs appended
+            // v3: sprite evaluation and per-line secondary OAM logs appended
+            // v4: palette edit mask dropped (palette RAM holds every color)
+            Section::Ppu => 4,
+            // v2: channel and frame sequencer state replaced the frame counter
+            // v3: frame IRQ flag appended
+            // v4: resampler progress appended
+            // v5: pending $4017 restart appended
+            Section::Apu => 5,
+            // v2: console region appended
+            Section::System => 2,
+            _ => 1,
+        }
+    }
+
+    /// Find the section with a tag
+    pub fn from_tag(tag: [u8; 4]) -> Option<Self> {
+        Self::ALL.into_iter().find(|section| section.tag() == tag)
+    }
+}
+
+/// Upgrade of one section's payload from version `from` to `from + 1`
+#[derive(Debug, Clone, Copy)]
+pub struct SectionMigration {
+    pub section: Section,
+    pub from: u16,
+    pub migrate: fn(&[u8]) -> Result<Vec<u8>, StateError>,
+}
+
+/// Migrations applied to sections older than the current version
+pub const MIGRATIONS: &[SectionMigration] = &[
+    SectionMigration { section: Section::Bus, from: 1, migrate: bus_add_dmc },
+    SectionMigration { section: Section::Bus, from: 2, migrate: bus_add_dmc_irq },
+    SectionMigration { section: Section::Ppu, from: 1, migrate: ppu_add_raster_scroll },
+    SectionMigration { section: Section::Ppu, from: 2, migrate: ppu_add_sprite_evaluation },
+    SectionMigration { section: Section::Ppu, from: 3, migrate: ppu_drop_palette_edits },
+    SectionMigration { section: Section::Apu, from: 1, migrate: apu_add_channels },
+    SectionMigration { section: Section::Apu, from: 2, migrate: apu_add_frame_irq },
+    SectionMigration { section: Section::Apu, from: 3, migrate: apu_add_resampler },
+    SectionMigration { section: Section::Apu, from: 4, migrate: apu_add_frame_reset },
+    SectionMigration { section: Section::System, from: 1, migrate: system_add_region },
+];
+
+/// Bus v1 to v2: the DMC reader was added; older states had none playing
+fn bus_add_dmc(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    DmcDma::new().save_state(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// Bus v2 to v3: the DMC IRQ was added; older states had it disabled
+fn bus_add_dmc_irq(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    DmcDma::new().save_irq(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// PPU v1 to v2: scroll logs were added; older states render the next frame from `t`
+fn ppu_add_raster_scroll(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    Ppu::new().save_raster_scroll(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// PPU v2 to v3: sprite evaluation was added; older states take sprites from OAM until the frame ends
+fn ppu_add_sprite_evaluation(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    Ppu::new().save_sprite_evaluation(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// PPU v3 to v4: the palette edit mask was dropped
+///
+/// v3 kept PPUDATA palette writes in VRAM at $3F00 and only used palette RAM
+/// for entries edited with `set_palette_entry` (one mask bit each), so the
+/// rest are taken from VRAM.
+fn ppu_drop_palette_edits(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let (palette_start, edits_start) = (VRAM_SIZE, VRAM_SIZE + PALETTE_SIZE);
+    if payload.len() < edits_start + 4 {
+        return Err(StateError::InvalidData("PPU v3 section length"));
+    }
+    let edits = StateReader::new(&payload[edits_start..]).read_u32()?;
+    let mut writer = StateWriter::new();
+    writer.write_bytes(&payload[..palette_start]);
+    for idx in 0..PALETTE_SIZE {
+        let color = if edits & (1 << idx) != 0 { payload[palette_start + idx] } else { payload[0x3F00 + idx] };
+        writer.write_u8(color & 0x3F);
+    }
+    writer.write_bytes(&payload[edits_start + 4..]);
+    Ok(writer.into_bytes())
+}
+
+/// APU v1 to v2: the channels were added; older states had them all silent
+///
+/// v1 ended with two frame counter bytes after the registers and cycle
+/// count; they counted nothing the channels use, so they are dropped.
+fn apu_add_channels(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    if payload.len() != 34 {
+        return Err(StateError::InvalidData("APU v1 section length"));
+    }
+    let mut writer = StateWriter::new();
+    writer.write_bytes(&payload[..32]);
+    Apu::new().save_channels(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// APU v2 to v3: the frame IRQ was added; older states had none pending
+fn apu_add_frame_irq(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    Apu::new().save_frame_irq(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// APU v3 to v4: the resampler was added; older states start the next output sample afresh
+fn apu_add_resampler(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    Apu::new().save_resampler(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// APU v4 to v5: delayed $4017 restarts were added; older states had none pending
+fn apu_add_frame_reset(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    Apu::new().save_frame_reset(&mut writer);
+    Ok(writer.into_bytes())
+}
+
+/// System v1 to v2: the region was added; older states were all NTSC
+fn system_add_region(payload: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(payload);
+    writer.write_u8(Region::Ntsc.state_id());
+    Ok(writer.into_bytes())
+}
+
+/// A section as read from a state, before migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawSection {
+    pub section: Section,
+    pub version: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Write a section at the current version; `save` writes the payload
+pub fn write_section(writer: &mut StateWriter, section: Section, save: impl FnOnce(&mut StateWriter)) {
+    let mut payload = StateWriter::new();
+    save(&mut payload);
+    let payload = payload.into_bytes();
+    writer.write_bytes(&section.tag());
+    writer.write_u16(section.version());
+    writer.write_u32(payload.len() as u32);
+    writer.write_bytes(&payload);
+}
+
+/// Read sections up to the end of the state
+pub fn read_sections(reader: &mut StateReader) -> Result<Vec<RawSection>, StateError> {
+    let mut sections: Vec<RawSection> = Vec::new();
+    while reader.remaining() > 0 {
+        let mut tag = [0; 4];
+        reader.read_into(&mut tag)?;
+        let section = Section::from_tag(tag).ok_or(StateError::UnknownSection(tag))?;
+        if sections.iter().any(|raw| raw.section == section) {
+            return Err(StateError::InvalidData("duplicate section"));
+        }
+        let version = reader.read_u16()?;
+        let len = reader.read_u32()? as usize;
+        let payload = reader.read_bytes(len)?.to_vec();
+        sections.push(RawSection { section, version, payload });
+    }
+    Ok(sections)
+}
+
+/// Bring a section's payload up to `current` using `migrations`
+pub fn upgrade(raw: RawSection, current: u16, migrations: &[SectionMigration]) -> Result<Vec<u8>, StateError> {
+    let unsupported = StateError::UnsupportedSection { section: raw.section.name(), version: raw.version };
+    if raw.version > current {
+        return Err(unsupported);
+    }
+    let mut payload = raw.payload;
+    for from in raw.version..current {
+        let migration = migrations
+            .iter()
+            .find(|m| m.section == raw.section && m.from == from)
+            .ok_or_else(|| unsupported.clone())?;
+        payload = (migration.migrate)(&payload)?;
+    }
+    Ok(payload)
+}
+
+/// Take a section out of the list and upgrade it to the current version
+pub fn take_section(sections: &mut Vec<RawSection>, section: Section) -> Result<Vec<u8>, StateError> {
+    let index = sections
+        .iter()
+        .position(|raw| raw.section == section)
+        .ok_or(StateError::MissingSection(section.name()))?;
+    upgrade(sections.remove(index), section.version(), MIGRATIONS)
+}
+
+/// Load a component from a section payload, which it must consume exactly
+pub fn load_section(
+    payload: &[u8],
+    load: impl FnOnce(&mut StateReader) -> Result<(), StateError>,
+) -> Result<(), StateError> {
+    let mut reader = StateReader::new(payload);
+    load(&mut reader)?;
+    if reader.remaining() != 0 {
+        return Err(StateError::InvalidData("section longer than its component"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sections(data: &[u8]) -> Result<Vec<RawSection>, StateError> {
+        read_sections(&mut StateReader::new(data))
+    }
+
+    #[test]
+    fn test_section_round_trip() {
+        let mut writer = StateWriter::new();
+        write_section(&mut writer, Section::Apu, |w| w.write_u16(0x1234));
+        write_section(&mut writer, Section::Rng, |w| w.write_u8(7));
+        let mut raw = sections(&writer.into_bytes()).unwrap();
+        assert_eq!(raw.len(), 2);
+        assert_eq!(take_section(&mut raw, Section::Rng), Ok(vec![7]));
+        assert_eq!(take_section(&mut raw, Section::Apu), Ok(vec![0x34, 0x12]));
+        assert_eq!(take_section(&mut raw, Section::Apu), Err(StateError::MissingSection("APU")));
+
+        assert_eq!(sections(b"XYZ \x01\x00\x00\x00\x00\x00"), Err(StateError::UnknownSection(*b"XYZ ")));
+    }
+
+    #[test]
+    fn test_migrations_upgrade_one_version_at_a_time() {
+        // A hypothetical PPU layout history: v1 had no trailing byte, v2 added 0xAA, v3 doubled it
+        let migrations = [
+            SectionMigration { section: Section::Ppu, from: 2, migrate: |p| Ok(p.iter().chain(p).copied().collect()) },
+            SectionMigration { section: Section::Ppu, from: 1, migrate: |p| Ok([p, &[0xAA]].concat()) },
+        ];
+        let raw = |version| RawSection { section: Section::Ppu, version, payload: vec![1] };
+        assert_eq!(upgrade(raw(3), 3, &migrations), Ok(vec![1]));
+        assert_eq!(upgrade(raw(1), 3, &migrations), Ok(vec![1, 0xAA, 1, 0xAA]));
+
+        let error = upgrade(raw(4), 3, &migrations).unwrap_err();
+        assert_eq!(error.to_string(), "PPU section v4 unsupported");
+        // No path from v0
+        assert_eq!(upgrade(raw(0), 3, &migrations), Err(StateError::UnsupportedSection { section: "PPU", version: 0 }));
+    }
+
+    #[test]
+    fn test_apu_v1_loads_with_silent_channels() {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&[0x42; 24]);
+        writer.write_u64(1234);
+        writer.write_bytes(&[7, 0]);
+        let raw = RawSection { section: Section::Apu, version: 1, payload: writer.into_bytes() };
+        let payload = upgrade(raw.clone(), Section::Apu.version(), MIGRATIONS).unwrap();
+
+        let mut apu = Apu::new();
+        load_section(&payload, |r| apu.load_state(r)).unwrap();
+        assert_eq!(apu.read(0x4000), 0x42);
+        assert_eq!(apu.cycle_count(), 1234);
+        assert_eq!(apu.status(), 0);
+
+        let truncated = RawSection { payload: raw.payload[..33].to_vec(), ..raw };
+        assert!(upgrade(truncated, Section::Apu.version(), MIGRATIONS).is_err());
+    }
+
+    #[test]
+    fn test_ppu_v3_palette_comes_from_vram_and_edits() {
+        let mut payload = vec![0; VRAM_SIZE + PALETTE_SIZE];
+        payload[0x3F00..0x3F04].copy_from_slice(&[0x0F, 0x21, 0x56, 0x23]);
+        payload[VRAM_SIZE + 2] = 0x16;
+        payload.extend(0b100u32.to_le_bytes());
+        payload.push(0xAB);
+        let raw = RawSection { section: Section::Ppu, version: 3, payload };
+        let payload = upgrade(raw, 4, MIGRATIONS).unwrap();
+        assert_eq!(payload.len(), VRAM_SIZE + PALETTE_SIZE + 1);
+        assert_eq!(payload[VRAM_SIZE..VRAM_SIZE + 4], [0x0F, 0x21, 0x16, 0x23]);
+        assert_eq!(payload.last(), Some(&0xAB));
+    }
+
+    #[test]
+    fn test_system_v1_loads_as_ntsc() {
+        let raw = RawSection { section: Section::System, version: 1, payload: vec![0; 19] };
+        let payload = upgrade(raw, Section::System.version(), MIGRATIONS).unwrap();
+        assert_eq!(payload.len(), 20);
+        assert_eq!(Region::from_state_id(payload[19]), Some(Region::Ntsc));
+    }
+}
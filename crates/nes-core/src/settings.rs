@@ -0,0 +1,351 @@
+//! Portable settings bundle
+//!
+//! One text file holding every piece of user configuration, so a setup can
+//! be exported, moved to another machine or shared, and imported again:
+//!
+//! ```text
+//! # rustnes settings
+//! version = 1
+//!
+//! [hotkeys]
+//! save_state_1 = Shift+F1
+//!
+//! [frontend]
+//! palette = greyscale
+//! scale = 3
+//!
+//! [game 1A2B3C4D]
+//! name = Some Game
+//! ram_init = fill FF
+//! prg_ram_size = 8192
+//! bus_conflicts = true
+//! ```
+//!
+//! `[hotkeys]` uses the `hotkeys` config lines, `[frontend]` holds options
+//! only the frontend understands (window scale, display palette, region),
+//! and each `[game <crc32>]` section is a `romdb::RomInfo` override.
+//!
+//! Parsing is version tolerant in both directions: a bundle written by a
+//! newer version still loads. Keys and values this version doesn't know are
+//! skipped and listed by `skipped`, and unknown sections are kept verbatim
+//! so exporting again doesn't lose them. Only text that isn't a setting at
+//! all (a line without `=`, a game header without a CRC32) is an error.
+
+use crate::bus::RamInit;
+use crate::hotkeys::{Action, HotkeyMap, KeyCombo};
+use crate::playstats;
+use crate::romdb::{RomDatabase, RomInfo};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version written into exported bundles
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// File name of the bundle inside the config directory
+pub const SETTINGS_FILE: &str = "settings.txt";
+
+/// Error reading or writing a settings bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SettingsError {
+    /// Malformed line (1-based line number)
+    InvalidLine { line: usize, reason: String },
+    /// File could not be read or written
+    Io(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::InvalidLine { line, reason } => write!(f, "Invalid settings line {}: {}", line, reason),
+            SettingsError::Io(e) => write!(f, "Settings I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Section a line belongs to while parsing
+enum Section {
+    Top,
+    Hotkeys,
+    Frontend,
+    Game(usize),
+    Unknown(usize),
+}
+
+/// All user configuration in one portable bundle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsBundle {
+    /// Hotkey bindings (None leaves the frontend's current ones alone on import)
+    pub hotkeys: Option<HotkeyMap>,
+    /// Frontend options by name, as the frontend wrote them
+    pub frontend: BTreeMap<String, String>,
+    /// Per-game overrides
+    pub games: Vec<RomInfo>,
+    /// Sections this version doesn't know: header and `key = value` lines
+    unknown: Vec<(String, Vec<(String, String)>)>,
+    /// What parsing skipped, one message per line
+    skipped: Vec<String>,
+}
+
+impl SettingsBundle {
+    /// Create an empty bundle
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the text format
+    pub fn parse(text: &str) -> Result<Self, SettingsError> {
+        let mut bundle = Self::new();
+        let mut section = Section::Top;
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| SettingsError::InvalidLine { line: index + 1, reason: reason.to_string() };
+            if let Some(header) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                let header = header.trim();
+                section = match (header, header.strip_prefix("game ")) {
+                    ("hotkeys", _) => {
+                        bundle.hotkeys.get_or_insert_with(HotkeyMap::empty);
+                        Section::Hotkeys
+                    }
+                    ("frontend", _) => Section::Frontend,
+                    (_, Some(crc)) => {
+                        let crc = u32::from_str_radix(crc.trim(), 16).map_err(|_| invalid("expected a hex CRC32"))?;
+                        bundle.games.push(RomInfo::new(crc, ""));
+                        Section::Game(bundle.games.len() - 1)
+                    }
+                    _ => {
+                        bundle.unknown.push((header.to_string(), Vec::new()));
+                        Section::Unknown(bundle.unknown.len() - 1)
+                    }
+                };
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| invalid("expected `key = value`"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let skipped = match &section {
+                Section::Top if key == "version" => {
+                    // Newer bundles are read as far as this version understands them
+                    value.parse::<u32>().map_err(|_| invalid("expected a version number"))?;
+                    None
+                }
+                Section::Top => Some("unknown setting"),
+                Section::Hotkeys => {
+                    let map = bundle.hotkeys.get_or_insert_with(HotkeyMap::empty);
+                    match (Action::from_name(key), KeyCombo::parse(value)) {
+                        (Some(action), Ok(combo)) => {
+                            map.bind(combo, action);
+                            None
+                        }
+                        (None, _) => Some("unknown hotkey action"),
+                        (_, Err(_)) => Some("invalid key combo"),
+                    }
+                }
+                Section::Frontend => {
+                    bundle.frontend.insert(key.to_string(), value.to_string());
+                    None
+                }
+                Section::Game(game) => set_game_value(&mut bundle.games[*game], key, value),
+                Section::Unknown(unknown) => {
+                    bundle.unknown[*unknown].1.push((key.to_string(), value.to_string()));
+                    None
+                }
+            };
+            if let Some(reason) = skipped {
+                bundle.skipped.push(format!("line {}: {}: {}", index + 1, reason, line));
+            }
+        }
+        Ok(bundle)
+    }
+
+    /// Serialize to the text format
+    pub fn to_text(&self) -> String {
+        let mut text = format!("# rustnes settings\nversion = {}\n", SETTINGS_VERSION);
+        if let Some(hotkeys) = &self.hotkeys {
+            text.push_str("\n[hotkeys]\n");
+            text.push_str(&hotkeys.to_config());
+        }
+        if !self.frontend.is_empty() {
+            text.push_str("\n[frontend]\n");
+            for (key, value) in &self.frontend {
+                text.push_str(&format!("{} = {}\n", key, value));
+            }
+        }
+        for game in &self.games {
+            text.push_str(&format!("\n[game {:08X}]\n", game.crc32));
+            if !game.name.is_empty() {
+                text.push_str(&format!("name = {}\n", game.name));
+            }
+            if let Some(ram_init) = game.ram_init {
+                text.push_str(&format!("ram_init = {}\n", ram_init_name(ram_init)));
+            }
+            if let Some(bytes) = game.prg_ram_size {
+                text.push_str(&format!("prg_ram_size = {}\n", bytes));
+            }
+            if let Some(bus_conflicts) = game.bus_conflicts {
+                text.push_str(&format!("bus_conflicts = {}\n", bus_conflicts));
+            }
+        }
+        for (header, lines) in &self.unknown {
+            text.push_str(&format!("\n[{}]\n", header));
+            for (key, value) in lines {
+                text.push_str(&format!("{} = {}\n", key, value));
+            }
+        }
+        text
+    }
+
+    /// Load a bundle from a file
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        let text = fs::read_to_string(path).map_err(|e| SettingsError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&text)
+    }
+
+    /// Write the bundle to a file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), SettingsError> {
+        let io = |e: std::io::Error| SettingsError::Io(format!("{}: {}", path.display(), e));
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+        fs::write(path, self.to_text()).map_err(io)
+    }
+
+    /// Get what parsing skipped (settings from a newer version, or invalid values)
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Get a frontend option
+    pub fn frontend_value(&self, key: &str) -> Option<&str> {
+        self.frontend.get(key).map(String::as_str)
+    }
+
+    /// Set a frontend option
+    pub fn set_frontend_value(&mut self, key: &str, value: impl fmt::Display) {
+        self.frontend.insert(key.to_string(), value.to_string());
+    }
+
+    /// Copy the per-game overrides of a ROM database into the bundle
+    pub fn add_games(&mut self, database: &RomDatabase) {
+        for info in database.iter() {
+            self.games.retain(|game| game.crc32 != info.crc32);
+            self.games.push(info.clone());
+        }
+    }
+
+    /// Insert the bundle's per-game overrides into a ROM database
+    ///
+    /// Overrides are read when a ROM is loaded, so a game that is already
+    /// running picks them up the next time it is loaded.
+    pub fn apply_games(&self, database: &mut RomDatabase) {
+        for game in &self.games {
+            database.insert(game.clone());
+        }
+    }
+}
+
+/// Apply one `[game]` line, returning why it was skipped, if it was
+fn set_game_value(game: &mut RomInfo, key: &str, value: &str) -> Option<&'static str> {
+    match key {
+        "name" => game.name = value.to_string(),
+        "ram_init" => match parse_ram_init(value) {
+            Some(ram_init) => game.ram_init = Some(ram_init),
+            None => return Some("unknown RAM pattern"),
+        },
+        "prg_ram_size" => match value.parse() {
+            Ok(bytes) => game.prg_ram_size = Some(bytes),
+            Err(_) => return Some("expected a size in bytes"),
+        },
+        "bus_conflicts" => match value.parse() {
+            Ok(bus_conflicts) => game.bus_conflicts = Some(bus_conflicts),
+            Err(_) => return Some("expected true or false"),
+        },
+        _ => return Some("unknown game setting"),
+    }
+    None
+}
+
+fn ram_init_name(ram_init: RamInit) -> String {
+    match ram_init {
+        RamInit::Zeros => "zeros".to_string(),
+        RamInit::Fill(value) => format!("fill {:02X}", value),
+        RamInit::Alternating => "alternating".to_string(),
+        RamInit::Random => "random".to_string(),
+    }
+}
+
+fn parse_ram_init(text: &str) -> Option<RamInit> {
+    match text {
+        "zeros" => Some(RamInit::Zeros),
+        "alternating" => Some(RamInit::Alternating),
+        "random" => Some(RamInit::Random),
+        _ => {
+            let value = text.strip_prefix("fill ")?;
+            u8::from_str_radix(value.trim(), 16).ok().map(RamInit::Fill)
+        }
+    }
+}
+
+/// Default bundle location: `<config dir>/rustnes/settings.txt`
+///
+/// The config dir is the same as for `playstats::default_path`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(playstats::config_dir()?.join(SETTINGS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkeys::Layer;
+
+    #[test]
+    fn test_round_trip() {
+        let mut bundle = SettingsBundle::new();
+        bundle.hotkeys = Some(HotkeyMap::default());
+        bundle.set_frontend_value("scale", 3);
+        bundle.set_frontend_value("palette", "greyscale");
+        let mut database = RomDatabase::new();
+        database.insert(RomInfo::new(0x1A2B3C4D, "Some Game").with_ram_init(RamInit::Fill(0xFF)).with_prg_ram_size(8192));
+        database.insert(RomInfo::new(0x0000BEEF, "").with_bus_conflicts(false));
+        bundle.add_games(&database);
+
+        let text = bundle.to_text();
+        assert!(text.contains("version = 1\n"));
+        assert!(text.contains("[game 1A2B3C4D]\nname = Some Game\nram_init = fill FF\nprg_ram_size = 8192\n"));
+        let parsed = SettingsBundle::parse(&text).unwrap();
+        assert_eq!(parsed, bundle);
+        assert_eq!(parsed.frontend_value("scale"), Some("3"));
+
+        let mut imported = RomDatabase::new();
+        parsed.apply_games(&mut imported);
+        assert_eq!(imported.lookup(0xBEEF).unwrap().bus_conflicts, Some(false));
+    }
+
+    #[test]
+    fn test_newer_version_is_tolerated() {
+        let text = "version = 3\ntheme = dark\n\n[hotkeys]\ntoggle_sprites = F10\nrewind_fast = Backspace\n\n\
+                    [game 00001234]\nram_init = pattern2\nbus_conflicts = true\n\n[netplay]\nnickname = nes fan\n";
+        let bundle = SettingsBundle::parse(text).unwrap();
+        let hotkeys = bundle.hotkeys.as_ref().unwrap();
+        assert_eq!(hotkeys.action(&KeyCombo::new("F10")), Some(Action::ToggleLayer(Layer::Sprites)));
+        assert_eq!(hotkeys.bindings().count(), 1);
+        assert_eq!(bundle.games[0].bus_conflicts, Some(true));
+        assert_eq!(bundle.games[0].ram_init, None);
+        assert_eq!(bundle.skipped().len(), 3);
+        assert!(bundle.skipped()[1].starts_with("line 6: unknown hotkey action"));
+        // The unknown section survives a re-export
+        assert!(bundle.to_text().ends_with("[netplay]\nnickname = nes fan\n"));
+
+        assert_eq!(
+            SettingsBundle::parse("[game zz]\n"),
+            Err(SettingsError::InvalidLine { line: 1, reason: "expected a hex CRC32".to_string() })
+        );
+        assert!(matches!(SettingsBundle::parse("version\n"), Err(SettingsError::InvalidLine { line: 1, .. })));
+    }
+}
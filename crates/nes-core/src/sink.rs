@@ -0,0 +1,227 @@
+//! Pluggable video and audio outputs for frontends
+//!
+//! A frontend implements `VideoSink` (and `AudioSink` for sound) and registers
+//! it with a `FramePump`, which runs the system a frame at a time and pushes
+//! every finished frame into each sink. This replaces the render-and-convert
+//! loop each frontend used to copy: the desktop's minifb window, the CLI's PNG
+//! sequence writer and a GPU texture (`TextureSink`, for egui or wgpu) are all
+//! just sinks.
+//!
+//! Frames arrive as `FrameSnapshot`s, so sinks that upload to a GPU can skip
+//! scanlines that didn't change. With an audio sink registered, the pump
+//! turns on the APU's output and hands each frame's samples to the audio
+//! sinks as one `f32` block; `FramePump::push_samples` forwards samples from
+//! anywhere else.
+
+use crate::ppu::{FrameSnapshot, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::system::NesSystem;
+use std::error::Error;
+use std::ops::Range;
+
+/// Receives every rendered frame
+pub trait VideoSink {
+    /// Handle a finished frame
+    fn on_frame(&mut self, frame: &FrameSnapshot);
+}
+
+/// Receives generated audio
+pub trait AudioSink {
+    /// Handle a block of mono samples in -1.0..=1.0
+    fn on_samples(&mut self, samples: &[f32], sample_rate: u32);
+}
+
+/// Sink that discards everything (headless runs and benchmarks)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl VideoSink for NullSink {
+    fn on_frame(&mut self, _frame: &FrameSnapshot) {}
+}
+
+impl AudioSink for NullSink {
+    fn on_samples(&mut self, _samples: &[f32], _sample_rate: u32) {}
+}
+
+/// Keeps the latest frame as RGBA8 for GPU texture uploads
+///
+/// The pixel layout matches egui's `ColorImage::from_rgba_unmultiplied` and
+/// wgpu's `Rgba8Unorm`. `take_dirty_rows` tells the frontend which rows to
+/// upload since the last call.
+#[derive(Debug, Clone)]
+pub struct TextureSink {
+    rgba: Vec<u8>,
+    dirty: Option<Range<usize>>,
+}
+
+impl TextureSink {
+    /// Create a sink holding a black frame
+    pub fn new() -> Self {
+        Self { rgba: vec![0; FRAME_WIDTH * FRAME_HEIGHT * 4], dirty: None }
+    }
+
+    /// Texture size in pixels (width, height)
+    pub fn size(&self) -> [usize; 2] {
+        [FRAME_WIDTH, FRAME_HEIGHT]
+    }
+
+    /// Get the RGBA pixels of the latest frame
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    /// Take the range of rows changed since the last call (None if nothing changed)
+    pub fn take_dirty_rows(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+}
+
+impl Default for TextureSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VideoSink for TextureSink {
+    fn on_frame(&mut self, frame: &FrameSnapshot) {
+        for y in frame.dirty_lines() {
+            let row = &mut self.rgba[y * FRAME_WIDTH * 4..(y + 1) * FRAME_WIDTH * 4];
+            for (out, rgb) in row.chunks_exact_mut(4).zip(frame.line(y).chunks_exact(3)) {
+                out.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 0xFF]);
+            }
+            self.dirty = Some(match self.dirty.take() {
+                Some(rows) => rows.start.min(y)..rows.end.max(y + 1),
+                None => y..y + 1,
+            });
+        }
+    }
+}
+
+/// Runs a system and pushes its output into the registered sinks
+#[derive(Default)]
+pub struct FramePump {
+    video: Vec<Box<dyn VideoSink>>,
+    audio: Vec<Box<dyn AudioSink>>,
+}
+
+impl FramePump {
+    /// Create a pump with no sinks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a sink for frames
+    pub fn add_video_sink(&mut self, sink: impl VideoSink + 'static) {
+        self.video.push(Box::new(sink));
+    }
+
+    /// Register a sink for audio
+    pub fn add_audio_sink(&mut self, sink: impl AudioSink + 'static) {
+        self.audio.push(Box::new(sink));
+    }
+
+    /// Run `frames` frames, presenting each one to the video sinks and its audio to the audio sinks
+    pub fn run_frames(&mut self, system: &mut NesSystem, frames: u64) -> Result<(), Box<dyn Error>> {
+        if !self.audio.is_empty() {
+            system.set_audio_enabled(true);
+        }
+        for _ in 0..frames {
+            system.run_frames(1)?;
+            self.present(system);
+            if !self.audio.is_empty() {
+                let samples = system.take_audio_samples_f32();
+                self.push_samples(&samples, system.sample_rate());
+            }
+        }
+        Ok(())
+    }
+
+    /// Snapshot the system's current frame and push it to every video sink
+    pub fn present(&mut self, system: &mut NesSystem) {
+        if self.video.is_empty() {
+            return;
+        }
+        let frame = system.ppu_mut().snapshot_frame();
+        for sink in &mut self.video {
+            sink.on_frame(&frame);
+        }
+    }
+
+    /// Forward audio to every audio sink
+    pub fn push_samples(&mut self, samples: &[f32], sample_rate: u32) {
+        for sink in &mut self.audio {
+            sink.on_samples(samples, sample_rate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records what it receives into shared storage the test can inspect
+    #[derive(Default, Clone)]
+    struct Recorder {
+        frames: Rc<RefCell<Vec<usize>>>,
+        samples: Rc<RefCell<Vec<(usize, u32)>>>,
+    }
+
+    impl VideoSink for Recorder {
+        fn on_frame(&mut self, frame: &FrameSnapshot) {
+            self.frames.borrow_mut().push(frame.dirty_count());
+        }
+    }
+
+    impl AudioSink for Recorder {
+        fn on_samples(&mut self, samples: &[f32], sample_rate: u32) {
+            self.samples.borrow_mut().push((samples.len(), sample_rate));
+        }
+    }
+
+    #[test]
+    fn test_pump_pushes_every_frame() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+
+        let recorder = Recorder::default();
+        let mut pump = FramePump::new();
+        pump.add_video_sink(recorder.clone());
+        pump.add_video_sink(NullSink);
+        pump.add_audio_sink(recorder.clone());
+        pump.run_frames(&mut system, 3).unwrap();
+        pump.push_samples(&[0.0; 800], 48000);
+
+        // The first snapshot marks every line dirty; an unchanged screen then marks none
+        assert_eq!(*recorder.frames.borrow(), [FRAME_HEIGHT, 0, 0]);
+        // One block per frame of 44.1 kHz at ~60.1 frames per second, then the pushed block
+        let samples = recorder.samples.borrow();
+        assert_eq!(samples.len(), 4);
+        for &(len, rate) in &samples[..3] {
+            assert_eq!(rate, 44_100);
+            assert!((733..=735).contains(&len), "{} samples in a frame", len);
+        }
+        assert_eq!(samples[3], (800, 48000));
+        assert_eq!(system.frame_count(), 3);
+    }
+
+    #[test]
+    fn test_texture_sink_tracks_dirty_rows() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        let mut texture = TextureSink::new();
+        assert_eq!(texture.take_dirty_rows(), None);
+
+        let frame = system.ppu_mut().snapshot_frame();
+        texture.on_frame(&frame);
+        assert_eq!(texture.take_dirty_rows(), Some(0..FRAME_HEIGHT));
+        assert_eq!(texture.take_dirty_rows(), None);
+        assert_eq!(texture.rgba().len(), FRAME_WIDTH * FRAME_HEIGHT * 4);
+        assert_eq!(&texture.rgba()[..4], &[frame.pixels()[0], frame.pixels()[1], frame.pixels()[2], 0xFF]);
+
+        texture.on_frame(&system.ppu_mut().snapshot_frame());
+        assert_eq!(texture.take_dirty_rows(), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! Fast in-memory snapshots for run-ahead and rollback
+//!
+//! `NesSystem::save_state` builds a portable file: a header, versioned
+//! sections and a full copy of every memory. Run-ahead and rollback netplay
+//! save and restore every frame and never keep the result, so they use a
+//! `Snapshot` instead. It has no header or section framing, and the bus and
+//! PPU stamp each 256-byte page of CPU RAM and VRAM with a generation when
+//! it is written, so refilling a snapshot (or restoring one) copies only the
+//! pages written since that snapshot was taken. The rest of the state
+//! (registers, OAM, palette, cartridge RAM, mapper) goes through the
+//! component serializers into a buffer the snapshot keeps between uses.
+//!
+//! A snapshot only makes sense to the system that took it (or a clone made
+//! after). `SnapshotRing` keeps the last few and reuses the oldest buffers:
+//!
+//! ```no_run
+//! use nes_core::snapshot::SnapshotRing;
+//! use nes_core::NesSystem;
+//!
+//! let mut system = NesSystem::new();
+//! system.load_rom(&std::fs::read("game.nes")?)?;
+//! system.reset();
+//! let mut ring = SnapshotRing::new(8);
+//! for _ in 0..60 {
+//!     ring.push(&mut system);
+//!     system.run_frames(1)?;
+//! }
+//! // A late remote input arrived for three frames ago: go back and replay
+//! ring.rewind(&mut system, 2)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::state::StateError;
+use crate::system::NesSystem;
+
+/// Size of a tracked page in bytes
+pub const PAGE_SIZE: usize = 256;
+
+/// Generation stamps for the pages of one memory
+#[derive(Debug, Clone)]
+pub(crate) struct PageStamps {
+    /// Generation of the last write to each page
+    stamps: Vec<u64>,
+    /// Generation writes are stamped with now
+    generation: u64,
+}
+
+impl PageStamps {
+    /// Track a memory of the given size
+    pub(crate) fn new(bytes: usize) -> Self {
+        Self { stamps: vec![0; bytes.div_ceil(PAGE_SIZE)], generation: 1 }
+    }
+
+    /// Record a write at `offset`
+    pub(crate) fn mark(&mut self, offset: usize) {
+        self.stamps[offset / PAGE_SIZE] = self.generation;
+    }
+
+    /// Record that the whole memory was replaced
+    pub(crate) fn mark_all(&mut self) {
+        self.stamps.fill(self.generation);
+    }
+}
+
+/// Copy of one tracked memory
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PageCopy {
+    data: Vec<u8>,
+    /// Generation the copy was taken at
+    taken: Option<u64>,
+}
+
+impl PageCopy {
+    /// Bring the copy up to date with `memory`, returning the pages copied
+    pub(crate) fn take(&mut self, memory: &[u8], pages: &mut PageStamps) -> usize {
+        let mut copied = 0;
+        match self.taken {
+            Some(taken) if self.data.len() == memory.len() => {
+                for (page, _) in pages.stamps.iter().enumerate().filter(|(_, &stamp)| stamp > taken) {
+                    let range = page * PAGE_SIZE..((page + 1) * PAGE_SIZE).min(memory.len());
+                    self.data[range.clone()].copy_from_slice(&memory[range]);
+                    copied += 1;
+                }
+            }
+            _ => {
+                self.data = memory.to_vec();
+                copied = pages.stamps.len();
+            }
+        }
+        // Later writes get a newer stamp than this copy
+        self.taken = Some(pages.generation);
+        pages.generation += 1;
+        copied
+    }
+
+    /// Write the copy back over `memory`, returning the pages copied
+    pub(crate) fn restore(&self, memory: &mut [u8], pages: &mut PageStamps) -> usize {
+        let Some(taken) = self.taken else {
+            return 0;
+        };
+        let mut copied = 0;
+        for (page, stamp) in pages.stamps.iter_mut().enumerate().filter(|(_, stamp)| **stamp > taken) {
+            let range = page * PAGE_SIZE..((page + 1) * PAGE_SIZE).min(memory.len());
+            memory[range.clone()].copy_from_slice(&self.data[range]);
+            // The page changed again as far as other snapshots are concerned
+            *stamp = pages.generation;
+            copied += 1;
+        }
+        copied
+    }
+}
+
+/// Machine state kept in memory for a quick restore
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub(crate) rom_crc32: Option<u32>,
+    pub(crate) ram: PageCopy,
+    pub(crate) vram: PageCopy,
+    /// Everything else, in component serializer order
+    pub(crate) state: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Create an empty snapshot to save into
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if nothing was saved into the snapshot yet
+    pub fn is_empty(&self) -> bool {
+        self.state.is_empty()
+    }
+}
+
+/// The last few snapshots of a system, oldest overwritten first
+#[derive(Debug, Clone)]
+pub struct SnapshotRing {
+    slots: Vec<Snapshot>,
+    /// Index of the oldest snapshot
+    start: usize,
+    len: usize,
+}
+
+impl SnapshotRing {
+    /// Create a ring holding up to `capacity` snapshots (at least one)
+    pub fn new(capacity: usize) -> Self {
+        Self { slots: vec![Snapshot::new(); capacity.max(1)], start: 0, len: 0 }
+    }
+
+    /// Get the number of snapshots the ring holds when full
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Get the number of snapshots held
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the ring holds no snapshots
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Forget all snapshots, keeping their buffers
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Get a snapshot by age (0 is the newest)
+    pub fn get(&self, age: usize) -> Option<&Snapshot> {
+        (age < self.len).then(|| &self.slots[(self.start + self.len - 1 - age) % self.slots.len()])
+    }
+
+    /// Snapshot the system, replacing the oldest snapshot once the ring is full
+    pub fn push(&mut self, system: &mut NesSystem) {
+        let slot = (self.start + self.len) % self.slots.len();
+        if self.len == self.slots.len() {
+            self.start = (self.start + 1) % self.slots.len();
+        } else {
+            self.len += 1;
+        }
+        system.save_snapshot(&mut self.slots[slot]);
+    }
+
+    /// Restore the snapshot `age` pushes back (0 is the newest), dropping the newer ones
+    ///
+    /// Returns false, leaving the system alone, if the ring holds `age` or
+    /// fewer snapshots.
+    pub fn rewind(&mut self, system: &mut NesSystem, age: usize) -> Result<bool, StateError> {
+        let Some(snapshot) = self.get(age) else {
+            return Ok(false);
+        };
+        system.load_snapshot(snapshot)?;
+        self.len -= age;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+
+    #[test]
+    fn test_page_copy_only_copies_written_pages() {
+        let mut memory = vec![0u8; 4 * PAGE_SIZE];
+        let mut pages = PageStamps::new(memory.len());
+        let mut copy = PageCopy::default();
+        assert_eq!(copy.take(&memory, &mut pages), 4);
+
+        memory[PAGE_SIZE + 3] = 7;
+        pages.mark(PAGE_SIZE + 3);
+        let mut later = PageCopy::default();
+        later.take(&memory, &mut pages);
+        assert_eq!(copy.take(&memory, &mut pages), 1);
+        assert_eq!(copy.data[PAGE_SIZE + 3], 7);
+
+        // Restoring the older copy puts back only the page written since
+        memory[3 * PAGE_SIZE] = 9;
+        pages.mark(3 * PAGE_SIZE);
+        assert_eq!(later.restore(&mut memory, &mut pages), 1);
+        assert_eq!(memory[3 * PAGE_SIZE], 0);
+        assert_eq!(memory[PAGE_SIZE + 3], 7);
+        // ...which is now newer than the copy taken after `later`
+        assert_eq!(copy.take(&memory, &mut pages), 1);
+    }
+
+    #[test]
+    fn test_ring_rewind() {
+        let mut system = NesSystem::new();
+        // INC $10; INC $0300; STA $2007; JMP $8000
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..12].copy_from_slice(&[0xE6, 0x10, 0xEE, 0x00, 0x03, 0x8D, 0x07, 0x20, 0x4C, 0x00, 0x80, 0xEA]);
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+
+        let mut ring = SnapshotRing::new(3);
+        assert!(!ring.rewind(&mut system, 0).unwrap());
+        let mut hashes = Vec::new();
+        for _ in 0..5 {
+            ring.push(&mut system);
+            hashes.push(system.state_hash());
+            system.run_frames(1).unwrap();
+        }
+        assert_eq!(ring.len(), 3);
+        assert!(!ring.rewind(&mut system, 3).unwrap());
+
+        // Back to the snapshot before the last frame, then one more back
+        assert!(ring.rewind(&mut system, 0).unwrap());
+        assert_eq!(system.state_hash(), hashes[4]);
+        assert!(ring.rewind(&mut system, 1).unwrap());
+        assert_eq!(system.state_hash(), hashes[3]);
+        assert_eq!(ring.len(), 2);
+        assert!(!ring.rewind(&mut system, 2).unwrap());
+
+        // Replaying from there gives the same run as before
+        system.run_frames(1).unwrap();
+        ring.push(&mut system);
+        assert_eq!(system.state_hash(), hashes[4]);
+        system.run_frames(1).unwrap();
+        let replayed = system.save_state();
+        assert!(ring.rewind(&mut system, 1).unwrap());
+        system.run_frames(2).unwrap();
+        assert_eq!(system.save_state(), replayed);
+
+        let mut other = NesSystem::new();
+        assert_eq!(other.load_snapshot(&Snapshot::new()), Err(StateError::NotAState));
+    }
+}
@@ -0,0 +1,418 @@
+//! Sprite evaluation analysis
+//!
+//! The NES can only show 8 sprites per scanline; further sprites in OAM order are
+//! dropped, which games hide by rotating OAM each frame so the dropped sprites
+//! flicker instead of vanishing. This module replays that per-scanline
+//! evaluation from OAM so frontends can show which sprites the hardware would
+//! drop and why sprites disappear.
+//!
+//! `SpriteStats` sums a frame's evaluation up (the busiest line, how many
+//! lines carry each number of sprites, how many sprite rows were dropped) so
+//! homebrew developers can tune their OAM usage against the limit while
+//! playtesting; `NesSystem::set_sprite_stats_enabled` collects it every
+//! frame, and `draw_occupancy` shows the per-line counts as an overlay.
+//!
+//! The PPU itself runs the hardware's evaluation with `SpriteEvaluator`, a
+//! dot at a time, into the secondary OAM each line draws from.
+
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Hardware limit of sprites per scanline
+pub const SPRITES_PER_LINE: usize = 8;
+
+/// Color used to mark dropped sprite pixels
+const DROPPED_COLOR: [u8; 3] = [0xFF, 0x00, 0xFF];
+/// Color of the per-scanline overflow bar on the left edge
+const OVERFLOW_BAR_COLOR: [u8; 3] = [0xFF, 0x30, 0x30];
+/// Occupancy bar colors: below the limit, at it, and the sprites beyond it
+const OCCUPANCY_COLORS: [[u8; 3]; 3] = [[0x30, 0xC0, 0x30], [0xFF, 0xC0, 0x00], [0xFF, 0x30, 0x30]];
+
+/// Sprites that fall on one scanline, in OAM order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanlineSprites {
+    /// OAM indices of the sprites the hardware draws (at most 8)
+    pub visible: Vec<u8>,
+    /// OAM indices of the sprites beyond the limit
+    pub dropped: Vec<u8>,
+}
+
+impl ScanlineSprites {
+    /// Check if any sprite is dropped on this line
+    pub fn overflows(&self) -> bool {
+        !self.dropped.is_empty()
+    }
+}
+
+/// Evaluate which sprites land on each visible scanline (sprite height is 8 or 16)
+pub fn evaluate_sprites(oam: &[u8], sprite_height: u8) -> Vec<ScanlineSprites> {
+    let mut lines = vec![ScanlineSprites::default(); FRAME_HEIGHT];
+    for (index, sprite) in oam.chunks_exact(4).enumerate().take(64) {
+        // Sprites are drawn one line below their OAM Y coordinate
+        let top = sprite[0] as usize + 1;
+        for line in lines.iter_mut().skip(top).take(sprite_height as usize) {
+            if line.visible.len() < SPRITES_PER_LINE {
+                line.visible.push(index as u8);
+            } else {
+                line.dropped.push(index as u8);
+            }
+        }
+    }
+    lines
+}
+
+/// A sprite copied into secondary OAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct EvaluatedSprite {
+    /// OAM index (0-63)
+    pub index: u8,
+    /// Y, tile, attributes and X, as in OAM
+    pub bytes: [u8; 4],
+}
+
+/// Secondary OAM: the sprites one scanline draws, in OAM order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct SecondaryOam {
+    sprites: [EvaluatedSprite; SPRITES_PER_LINE],
+    len: u8,
+}
+
+impl SecondaryOam {
+    /// Pick the first 8 sprites drawn on `line` straight from OAM, without
+    /// evaluation timing or the overflow flag
+    pub fn from_oam(oam: &[u8], sprite_height: u8, line: usize) -> Self {
+        let mut secondary = Self::default();
+        for (index, sprite) in oam.chunks_exact(4).enumerate().take(64) {
+            if secondary.is_full() {
+                break;
+            }
+            // Sprites are drawn one line below their OAM Y coordinate
+            let top = sprite[0] as usize + 1;
+            if (top..top + sprite_height as usize).contains(&line) {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(sprite);
+                secondary.sprites[secondary.len as usize] = EvaluatedSprite { index: index as u8, bytes };
+                secondary.len += 1;
+            }
+        }
+        secondary
+    }
+
+    /// Get the sprites found, in OAM order
+    pub fn sprites(&self) -> &[EvaluatedSprite] {
+        &self.sprites[..self.len as usize]
+    }
+
+    fn is_full(&self) -> bool {
+        self.len as usize == SPRITES_PER_LINE
+    }
+
+    /// Serialize the sprite count, then each sprite's index and bytes
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.len);
+        for sprite in self.sprites() {
+            writer.write_u8(sprite.index);
+            writer.write_bytes(&sprite.bytes);
+        }
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let mut secondary = Self { len: reader.read_u8()?, ..Self::default() };
+        if secondary.len as usize > SPRITES_PER_LINE {
+            return Err(StateError::InvalidData("secondary OAM sprite count"));
+        }
+        for sprite in &mut secondary.sprites[..secondary.len as usize] {
+            sprite.index = reader.read_u8()?;
+            reader.read_into(&mut sprite.bytes)?;
+        }
+        Ok(secondary)
+    }
+}
+
+/// The PPU's sprite evaluation for one scanline, run a read/write pair at a time
+///
+/// On dots 65-256 of a visible line the PPU reads OAM on odd dots and writes
+/// secondary OAM on even ones. It compares each sprite's Y with the line and
+/// copies the other three bytes of the sprites in range. Once 8 are found it
+/// keeps reading Y coordinates to find a ninth, which sets the overflow flag.
+/// On hardware that search also steps the byte index with each sprite, so it
+/// compares tile, attribute and X bytes as if they were Y and both misses and
+/// invents overflows; `buggy_overflow` replays this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct SpriteEvaluator {
+    /// Sprite being read (64 once evaluation is over)
+    n: u8,
+    /// Byte of the sprite being read
+    m: u8,
+    secondary: SecondaryOam,
+}
+
+impl SpriteEvaluator {
+    /// Clear secondary OAM and start again from sprite 0
+    pub fn start(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Run one read/write pair against OAM for `line`
+    ///
+    /// Returns true when it finds a sprite beyond the eighth; evaluation
+    /// stops there.
+    pub fn step(&mut self, oam: &[u8], line: i16, sprite_height: u8, buggy_overflow: bool) -> bool {
+        if self.n >= 64 {
+            return false;
+        }
+        let byte = oam[self.n as usize * 4 + self.m as usize];
+        let in_range = (0..sprite_height as i16).contains(&(line - byte as i16));
+        if !self.secondary.is_full() {
+            let slot = &mut self.secondary.sprites[self.secondary.len as usize];
+            if self.m == 0 {
+                if !in_range {
+                    self.n += 1;
+                    return false;
+                }
+                slot.index = self.n;
+            }
+            slot.bytes[self.m as usize] = byte;
+            self.m += 1;
+            if self.m == 4 {
+                self.m = 0;
+                self.n += 1;
+                self.secondary.len += 1;
+            }
+            return false;
+        }
+        if in_range {
+            self.n = 64;
+            return true;
+        }
+        self.n += 1;
+        if buggy_overflow {
+            self.m = (self.m + 1) & 3;
+        }
+        false
+    }
+
+    /// Get the sprites found so far
+    pub fn secondary(&self) -> &SecondaryOam {
+        &self.secondary
+    }
+
+    /// Serialize the read position and secondary OAM
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.n, self.m]);
+        self.secondary.save_state(writer);
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let (n, m) = (reader.read_u8()?, reader.read_u8()?);
+        if n > 64 || m > 3 {
+            return Err(StateError::InvalidData("sprite evaluation position"));
+        }
+        Ok(Self { n, m, secondary: SecondaryOam::load_state(reader)? })
+    }
+}
+
+/// Count the scanlines where at least one sprite is dropped
+pub fn overflow_lines(lines: &[ScanlineSprites]) -> usize {
+    lines.iter().filter(|line| line.overflows()).count()
+}
+
+/// Sprite occupancy of the scanlines of one frame
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpriteStats {
+    /// Most sprites falling on one line, dropped ones included
+    pub max_per_line: u8,
+    /// Number of lines carrying each number of sprites (index 0 is empty lines), up to `max_per_line`
+    pub histogram: Vec<u16>,
+    /// Lines where sprites were dropped
+    pub overflow_lines: u16,
+    /// Sprite rows dropped, summed over all lines
+    pub dropped: u32,
+}
+
+impl SpriteStats {
+    /// Sum up a frame's evaluation (see `evaluate_sprites`)
+    pub fn from_lines(lines: &[ScanlineSprites]) -> Self {
+        let mut stats = Self::default();
+        for line in lines {
+            let count = line.visible.len() + line.dropped.len();
+            if stats.histogram.len() <= count {
+                stats.histogram.resize(count + 1, 0);
+            }
+            stats.histogram[count] += 1;
+            if line.overflows() {
+                stats.overflow_lines += 1;
+                stats.dropped += line.dropped.len() as u32;
+            }
+        }
+        stats.max_per_line = stats.histogram.len().saturating_sub(1) as u8;
+        stats
+    }
+}
+
+/// Draw each scanline's sprite count as a bar on the right edge of a 256x240 RGB framebuffer
+///
+/// Bars grow leftwards 2 pixels per sprite: green below the 8-sprite limit,
+/// yellow for a full line, and red for the sprites beyond it.
+pub fn draw_occupancy(framebuffer: &mut [u8], lines: &[ScanlineSprites]) {
+    if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+        return;
+    }
+    for (y, line) in lines.iter().enumerate().take(FRAME_HEIGHT) {
+        let row = y * FRAME_WIDTH * 3;
+        let color = match line.visible.len() {
+            SPRITES_PER_LINE => OCCUPANCY_COLORS[1],
+            _ => OCCUPANCY_COLORS[0],
+        };
+        let visible = line.visible.len() * 2;
+        let total = (visible + line.dropped.len() * 2).min(FRAME_WIDTH);
+        for offset in 0..total {
+            let color = if offset < visible { color } else { OCCUPANCY_COLORS[2] };
+            let px = FRAME_WIDTH - 1 - offset;
+            framebuffer[row + px * 3..row + px * 3 + 3].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Mark dropped sprites over a 256x240 RGB framebuffer
+///
+/// Each dropped sprite's 8 pixels on the affected line are painted magenta, and a
+/// red bar on the left edge shows how many sprites each line dropped (2 pixels per sprite).
+pub fn draw_dropped_sprites(framebuffer: &mut [u8], oam: &[u8], lines: &[ScanlineSprites]) {
+    if framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+        return;
+    }
+    for (y, line) in lines.iter().enumerate().take(FRAME_HEIGHT) {
+        let row = y * FRAME_WIDTH * 3;
+        for &index in &line.dropped {
+            let x = oam[index as usize * 4 + 3] as usize;
+            for px in x..(x + 8).min(FRAME_WIDTH) {
+                framebuffer[row + px * 3..row + px * 3 + 3].copy_from_slice(&DROPPED_COLOR);
+            }
+        }
+        let bar = (line.dropped.len() * 2).min(FRAME_WIDTH);
+        for px in 0..bar {
+            framebuffer[row + px * 3..row + px * 3 + 3].copy_from_slice(&OVERFLOW_BAR_COLOR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluation_drops_beyond_limit() {
+        // Ten 8x8 sprites on the same rows, the rest hidden below the screen
+        let mut oam = [0xFF; 256];
+        for i in 0..10 {
+            oam[i * 4] = 19;
+            oam[i * 4 + 3] = (i * 16) as u8;
+        }
+        let lines = evaluate_sprites(&oam, 8);
+        assert!(lines[19].visible.is_empty());
+        assert_eq!(lines[20].visible, (0..8).collect::<Vec<u8>>());
+        assert_eq!(lines[27].dropped, [8, 9]);
+        assert!(!lines[28].overflows());
+        assert_eq!(overflow_lines(&lines), 8);
+
+        // 8x16 sprites cover twice as many lines
+        assert_eq!(overflow_lines(&evaluate_sprites(&oam, 16)), 16);
+    }
+
+    /// Run a whole line's evaluation (96 read/write pairs), returning whether it overflowed
+    fn evaluate_line(oam: &[u8], line: i16, buggy_overflow: bool) -> (SpriteEvaluator, bool) {
+        let mut evaluator = SpriteEvaluator::default();
+        let overflow = (0..96).fold(false, |overflow, _| evaluator.step(oam, line, 8, buggy_overflow) | overflow);
+        (evaluator, overflow)
+    }
+
+    #[test]
+    fn test_evaluator_fills_secondary_oam() {
+        // Sprites 3 and 5 cover line 20, and nine cover line 40
+        let mut oam = [0xFF; 256];
+        oam[12..16].copy_from_slice(&[15, 1, 2, 3]);
+        oam[20..24].copy_from_slice(&[20, 4, 5, 6]);
+        let (evaluator, overflow) = evaluate_line(&oam, 20, false);
+        assert!(!overflow);
+        let sprites = evaluator.secondary().sprites();
+        assert_eq!(sprites.iter().map(|sprite| sprite.index).collect::<Vec<_>>(), [3, 5]);
+        assert_eq!(sprites[1].bytes, [20, 4, 5, 6]);
+        assert_eq!(*evaluator.secondary(), SecondaryOam::from_oam(&oam, 8, 21));
+
+        for i in 10..19 {
+            oam[i * 4] = 40;
+        }
+        let (evaluator, overflow) = evaluate_line(&oam, 40, false);
+        assert!(overflow);
+        assert_eq!(evaluator.secondary().sprites().len(), SPRITES_PER_LINE);
+        assert_eq!(evaluator.secondary().sprites()[7].index, 17);
+    }
+
+    #[test]
+    fn test_buggy_overflow_reads_the_wrong_bytes() {
+        // Eight sprites on line 100, then a ninth two sprites later: the
+        // buggy search reads sprite 9's tile byte and sprite 10's attributes
+        let mut oam = [0xFF; 256];
+        for i in 0..8 {
+            oam[i * 4] = 100;
+        }
+        oam[40] = 100;
+        assert!(evaluate_line(&oam, 100, false).1);
+        assert!(!evaluate_line(&oam, 100, true).1);
+
+        // And a non-Y byte that happens to be in range sets the flag falsely
+        oam[40] = 0xFF;
+        oam[37] = 98;
+        assert!(!evaluate_line(&oam, 100, false).1);
+        assert!(evaluate_line(&oam, 100, true).1);
+    }
+
+    #[test]
+    fn test_draw_marks_dropped_sprites() {
+        let mut oam = [0xFF; 256];
+        for i in 0..9 {
+            oam[i * 4] = 99;
+            oam[i * 4 + 3] = 200;
+        }
+        let lines = evaluate_sprites(&oam, 8);
+        let mut framebuffer = vec![0; FRAME_WIDTH * FRAME_HEIGHT * 3];
+        draw_dropped_sprites(&mut framebuffer, &oam, &lines);
+
+        let pixel = |x: usize, y: usize| &framebuffer[(y * FRAME_WIDTH + x) * 3..(y * FRAME_WIDTH + x) * 3 + 3];
+        assert_eq!(pixel(200, 100), DROPPED_COLOR);
+        assert_eq!(pixel(1, 100), OVERFLOW_BAR_COLOR);
+        assert_eq!(pixel(2, 100), [0, 0, 0]);
+        assert_eq!(pixel(200, 99), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_stats_and_occupancy() {
+        // Nine sprites on lines 100-107 and three of them again on 110-117
+        let mut oam = [0xFF; 256];
+        for i in 0..9 {
+            oam[i * 4] = 99;
+        }
+        for i in 9..12 {
+            oam[i * 4] = 109;
+        }
+        let lines = evaluate_sprites(&oam, 8);
+        let stats = SpriteStats::from_lines(&lines);
+        assert_eq!(stats.max_per_line, 9);
+        assert_eq!(stats.histogram.len(), 10);
+        assert_eq!((stats.histogram[0], stats.histogram[3], stats.histogram[9]), (224, 8, 8));
+        assert_eq!((stats.overflow_lines, stats.dropped), (8, 8));
+        assert_eq!(SpriteStats::from_lines(&evaluate_sprites(&[0xFF; 256], 8)).histogram, [240]);
+
+        let mut framebuffer = vec![0; FRAME_WIDTH * FRAME_HEIGHT * 3];
+        draw_occupancy(&mut framebuffer, &lines);
+        let pixel = |x: usize, y: usize| &framebuffer[(y * FRAME_WIDTH + x) * 3..(y * FRAME_WIDTH + x) * 3 + 3];
+        assert_eq!(pixel(255, 100), OCCUPANCY_COLORS[1]);
+        assert_eq!(pixel(255 - 16, 100), OCCUPANCY_COLORS[2]);
+        assert_eq!(pixel(255 - 18, 100), [0, 0, 0]);
+        assert_eq!(pixel(255 - 5, 110), OCCUPANCY_COLORS[0]);
+        assert_eq!(pixel(255 - 6, 110), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Binary state serialization helpers
+//!
+//! Components write their state as a flat little-endian byte stream with
+//! `StateWriter` and restore it in the same order with `StateReader`.
+
+use std::fmt;
+
+/// Little-endian byte stream writer for component state
+#[derive(Debug, Clone, Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Create an empty writer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty writer that writes into `buf`'s allocation
+    pub fn reuse(mut buf: Vec<u8>) -> Self {
+        buf.clear();
+        Self { buf }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Write raw bytes (the reader must know the length)
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Get the bytes written so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consume the writer and return the bytes
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Little-endian byte stream reader for component state
+#[derive(Debug, Clone)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Create a reader over the given bytes
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Read exactly `len` raw bytes
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.pos.checked_add(len).ok_or(StateError::UnexpectedEnd)?;
+        let bytes = self.data.get(self.pos..end).ok_or(StateError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Read raw bytes into the given buffer, filling it completely
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<(), StateError> {
+        buf.copy_from_slice(self.read_bytes(buf.len())?);
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut bytes = [0; N];
+        self.read_into(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, StateError> {
+        Ok(i16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Number of bytes not yet read
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+/// First bytes of a full-system savestate
+pub const STATE_MAGIC: [u8; 4] = *b"NESS";
+/// Savestate container version (components version their sections separately, see `savestate`)
+pub const STATE_VERSION: u16 = 1;
+
+/// FNV-1a 64-bit offset basis (initial hash value)
+pub const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+
+/// Continue an FNV-1a 64-bit hash with more data
+pub fn fnv1a_update(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// State serialization error types
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StateError {
+    /// The data ended before all fields were read
+    UnexpectedEnd,
+    /// A field held a value that is not valid for the component
+    InvalidData(&'static str),
+    /// The data does not start with `STATE_MAGIC`
+    NotAState,
+    /// The state was written in a format version this build can't read
+    UnsupportedVersion(u16),
+    /// The state belongs to a different ROM
+    WrongRom,
+    /// A section is newer than this build, or too old to migrate
+    UnsupportedSection { section: &'static str, version: u16 },
+    /// A component's section is missing
+    MissingSection(&'static str),
+    /// A section tag this build doesn't know
+    UnknownSection([u8; 4]),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::UnexpectedEnd => write!(f, "State data ended unexpectedly"),
+            StateError::InvalidData(msg) => write!(f, "Invalid state data: {}", msg),
+            StateError::NotAState => write!(f, "Not a savestate"),
+            StateError::UnsupportedVersion(version) => write!(f, "Unsupported savestate version {}", version),
+            StateError::WrongRom => write!(f, "Savestate was made with a different ROM"),
+            StateError::UnsupportedSection { section, version } => write!(f, "{} section v{} unsupported", section, version),
+            StateError::MissingSection(section) => write!(f, "Savestate has no {} section", section),
+            StateError::UnknownSection(tag) => write!(f, "Unknown savestate section '{}'", String::from_utf8_lossy(tag)),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_i16(-2);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(u64::MAX - 1);
+        writer.write_bytes(&[1, 2, 3]);
+        let bytes = writer.into_bytes();
+
+        let mut reader = StateReader::new(&bytes);
+        assert_eq!(reader.read_u8(), Ok(0x12));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u16(), Ok(0x3456));
+        assert_eq!(reader.read_i16(), Ok(-2));
+        assert_eq!(reader.read_u32(), Ok(0x789A_BCDE));
+        assert_eq!(reader.read_u64(), Ok(u64::MAX - 1));
+        assert_eq!(reader.read_bytes(3), Ok(&[1u8, 2, 3][..]));
+        assert_eq!(reader.remaining(), 0);
+        assert_eq!(reader.read_u8(), Err(StateError::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,222 @@
+//! Field-by-field comparison of two system states
+//!
+//! `diff_systems` compares two snapshots of a `NesSystem` (for example clones
+//! taken at two points of a run, or the same frame of two runs that
+//! desynced). Scalar state (CPU and PPU registers, frame count, mapper
+//! registers) is reported per field; memories (RAM, PRG RAM, VRAM, palette,
+//! OAM, APU registers) are reported as changed byte ranges, with nearby
+//! changes merged so a report shows whole structures rather than single bytes.
+
+use crate::system::NesSystem;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Unchanged bytes allowed inside one reported range before it is split
+const MERGE_GAP: usize = 8;
+/// Bytes per hexdump row
+const ROW_BYTES: usize = 16;
+
+/// A scalar field that differs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// Field name, such as `cpu.a` or `mapper.prg`
+    pub name: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// A run of bytes that differs within one memory region
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeDiff {
+    /// Region name, such as `ram` or `vram`
+    pub region: &'static str,
+    /// Address of the first byte (CPU or PPU address space, depending on the region)
+    pub address: usize,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+impl RangeDiff {
+    /// Number of bytes in the range that actually differ
+    pub fn changed_bytes(&self) -> usize {
+        self.before.iter().zip(&self.after).filter(|(a, b)| a != b).count()
+    }
+}
+
+/// Every difference between two states
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub fields: Vec<FieldDiff>,
+    pub ranges: Vec<RangeDiff>,
+}
+
+impl StateDiff {
+    /// Check if the states are identical
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.ranges.is_empty()
+    }
+
+    /// Write a report: changed fields, then a hexdump per changed range
+    /// (`-` rows are the first state, `+` rows the second, `^^` marks changes)
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.is_empty() {
+            return writeln!(out, "States are identical");
+        }
+        for field in &self.fields {
+            writeln!(out, "{:<20} {} -> {}", field.name, field.before, field.after)?;
+        }
+        for range in &self.ranges {
+            writeln!(
+                out,
+                "\n{} ${:04X}-${:04X} ({} bytes changed)",
+                range.region,
+                range.address,
+                range.address + range.before.len() - 1,
+                range.changed_bytes()
+            )?;
+            for row in (0..range.before.len()).step_by(ROW_BYTES) {
+                let end = (row + ROW_BYTES).min(range.before.len());
+                let before = &range.before[row..end];
+                let after = &range.after[row..end];
+                writeln!(out, "- {:04X}: {}", range.address + row, hex(before))?;
+                writeln!(out, "+ {:04X}: {}", range.address + row, hex(after))?;
+                let marks: Vec<&str> = before.iter().zip(after).map(|(a, b)| if a != b { "^^" } else { "  " }).collect();
+                writeln!(out, "        {}", marks.join(" ").trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StateDiff {
+    /// One-line summary
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: usize = self.ranges.iter().map(RangeDiff::changed_bytes).sum();
+        write!(f, "{} fields and {} bytes in {} ranges differ", self.fields.len(), bytes, self.ranges.len())
+    }
+}
+
+/// Compare two system states
+pub fn diff_systems(before: &NesSystem, after: &NesSystem) -> StateDiff {
+    let mut fields = Vec::new();
+    let mut ranges = Vec::new();
+    let mut field = |name: String, a: String, b: String| {
+        if a != b {
+            fields.push(FieldDiff { name, before: a, after: b });
+        }
+    };
+
+    field("frame".into(), before.frame_count().to_string(), after.frame_count().to_string());
+    let (cpu_a, cpu_b) = (before.cpu(), after.cpu());
+    let (regs_a, regs_b) = (cpu_a.registers(), cpu_b.registers());
+    field("cpu.pc".into(), format!("${:04X}", regs_a.pc), format!("${:04X}", regs_b.pc));
+    field("cpu.a".into(), format!("${:02X}", regs_a.a), format!("${:02X}", regs_b.a));
+    field("cpu.x".into(), format!("${:02X}", regs_a.x), format!("${:02X}", regs_b.x));
+    field("cpu.y".into(), format!("${:02X}", regs_a.y), format!("${:02X}", regs_b.y));
+    field("cpu.sp".into(), format!("${:02X}", regs_a.sp), format!("${:02X}", regs_b.sp));
+    field("cpu.p".into(), format!("${:02X}", cpu_a.p_register()), format!("${:02X}", cpu_b.p_register()));
+    field("cpu.cycles".into(), cpu_a.total_cycles().to_string(), cpu_b.total_cycles().to_string());
+
+    let (ppu_a, ppu_b) = (before.ppu(), after.ppu());
+    field("ppu.ctrl".into(), format!("${:02X}", ppu_a.control_value()), format!("${:02X}", ppu_b.control_value()));
+    field("ppu.mask".into(), format!("${:02X}", ppu_a.mask_value()), format!("${:02X}", ppu_b.mask_value()));
+    field("ppu.status".into(), format!("${:02X}", ppu_a.status_value()), format!("${:02X}", ppu_b.status_value()));
+    field("ppu.scanline".into(), ppu_a.scanline().to_string(), ppu_b.scanline().to_string());
+    field("ppu.dot".into(), ppu_a.dot().to_string(), ppu_b.dot().to_string());
+
+    let mapper_a = before.bus_cartridge().map(|c| c.mapper().registers()).unwrap_or_default();
+    let mapper_b = after.bus_cartridge().map(|c| c.mapper().registers()).unwrap_or_default();
+    if mapper_a.iter().map(|(name, _)| name).eq(mapper_b.iter().map(|(name, _)| name)) {
+        for ((name, a), (_, b)) in mapper_a.iter().zip(&mapper_b) {
+            field(format!("mapper.{}", name), format!("${:02X}", a), format!("${:02X}", b));
+        }
+    } else {
+        field("mapper".into(), format!("{} registers", mapper_a.len()), format!("{} registers (different board)", mapper_b.len()));
+    }
+
+    let apu = |system: &NesSystem| (0x4000..=0x4017).map(|address| system.apu().read(address)).collect::<Vec<u8>>();
+    let prg_ram = |system: &NesSystem| system.bus_cartridge().and_then(|c| c.prg_ram()).map(<[u8]>::to_vec).unwrap_or_default();
+    let regions: [(&'static str, usize, Vec<u8>, Vec<u8>); 6] = [
+        ("ram", 0x0000, before.ram().to_vec(), after.ram().to_vec()),
+        ("apu", 0x4000, apu(before), apu(after)),
+        ("prg_ram", 0x6000, prg_ram(before), prg_ram(after)),
+        ("vram", 0x0000, ppu_a.vram().to_vec(), ppu_b.vram().to_vec()),
+        ("palette", 0x3F00, ppu_a.palette_ram().to_vec(), ppu_b.palette_ram().to_vec()),
+        ("oam", 0x0000, ppu_a.oam().to_vec(), ppu_b.oam().to_vec()),
+    ];
+    for (region, base, a, b) in regions {
+        if a.len() != b.len() {
+            field(format!("{}.size", region), a.len().to_string(), b.len().to_string());
+            continue;
+        }
+        ranges.extend(changed_ranges(&a, &b).into_iter().map(|(start, end)| RangeDiff {
+            region,
+            address: base + start,
+            before: a[start..end].to_vec(),
+            after: b[start..end].to_vec(),
+        }));
+    }
+    StateDiff { fields, ranges }
+}
+
+/// Changed byte ranges (start, end exclusive), merging ranges separated by small gaps
+fn changed_ranges(a: &[u8], b: &[u8]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for index in (0..a.len()).filter(|&i| a[i] != b[i]) {
+        match ranges.last_mut() {
+            Some((_, end)) if index - *end <= MERGE_GAP => *end = index + 1,
+            _ => ranges.push((index, index + 1)),
+        }
+    }
+    ranges
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::SimpleCartridge;
+
+    fn system() -> NesSystem {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        system
+    }
+
+    #[test]
+    fn test_identical_states() {
+        let system = system();
+        let diff = diff_systems(&system, &system.clone());
+        assert!(diff.is_empty());
+        let mut report = Vec::new();
+        diff.write_report(&mut report).unwrap();
+        assert_eq!(String::from_utf8(report).unwrap(), "States are identical\n");
+    }
+
+    #[test]
+    fn test_fields_and_ranges() {
+        let before = system();
+        let mut after = before.clone();
+        after.cpu_mut().registers_mut().a = 0x42;
+        after.write_memory(0x0010, 1);
+        after.write_memory(0x0015, 2);
+        after.write_memory(0x0300, 3);
+        after.write_memory(0x6001, 4);
+
+        let diff = diff_systems(&before, &after);
+        assert_eq!(diff.fields, [FieldDiff { name: "cpu.a".into(), before: "$00".into(), after: "$42".into() }]);
+        let ranges: Vec<_> = diff.ranges.iter().map(|r| (r.region, r.address, r.before.len(), r.changed_bytes())).collect();
+        assert_eq!(ranges, [("ram", 0x10, 6, 2), ("ram", 0x300, 1, 1), ("prg_ram", 0x6001, 1, 1)]);
+        assert_eq!(diff.to_string(), "1 fields and 4 bytes in 3 ranges differ");
+
+        let mut report = Vec::new();
+        diff.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("cpu.a                $00 -> $42\n"));
+        assert!(report.contains("ram $0010-$0015 (2 bytes changed)\n- 0010: 00 00 00 00 00 00\n+ 0010: 01 00 00 00 00 02\n        ^^             ^^\n"));
+    }
+}
@@ -2,12 +2,61 @@
 //!
 //! This module integrates all NES components (CPU, PPU, APU, Bus) into a working system.
 
-use crate::bus::{Bus, SimpleCartridge};
+use crate::accuracy::{AccuracyProfile, PPU_WARMUP_CYCLES};
+use crate::achievements::AchievementSet;
+use crate::bus::{Bus, RamInit, SimpleCartridge};
+use crate::cheats::Cheat;
+use crate::controller::{Buttons, DpadPolicy, InputEcho};
+use crate::mapper::MapperState;
+use crate::mapper_interface::MapperInterface;
 use crate::cpu::Bus as CpuBus;
 use crate::cartridge::{Cartridge, CartridgeError};
-use crate::cpu::{Cpu, CpuError};
-use crate::ppu::Ppu;
+use crate::crash_detect::{CrashDetector, CrashDiagnostic};
+use crate::dma::{dmc_fetch_during_oam_stall, dmc_fetch_stall, DMC_FETCH_DURING_OAM_CYCLES, OAM_DMA_CYCLES};
+use crate::cpu::{write_cycles, Cpu, CpuError, CpuRegisters, Opcode, StatusFlags, HIJACK_WINDOW_CYCLES, NMI_VECTOR};
+use crate::gif::{GifError, GifRecorder};
+use crate::health::{HealthMonitor, HealthReport};
+use crate::frame_goal::FrameGoal;
+use crate::idle::IdleDetector;
+use crate::instructions::Instructions;
+use crate::interrupt::{InterruptController, IrqSource};
+use crate::io_map::{IoHandler, IoMapError, IoRegionId};
+use crate::ppu::{Ppu, FRAME_RGB_SIZE, FRAME_WIDTH};
+use crate::region::Region;
 use crate::apu::Apu;
+use crate::metrics::{Metrics, Subsystem};
+use crate::sprite_eval::SpriteStats;
+use crate::rng::Rng;
+use crate::savestate::{load_section, read_sections, take_section, write_section, Section};
+use crate::snapshot::Snapshot;
+use crate::romdb::{RomDatabase, RomInfo};
+use crate::state::{fnv1a_update, StateError, StateReader, StateWriter, FNV_OFFSET_BASIS, STATE_MAGIC, STATE_VERSION};
+use crate::trace::{TraceEntry, TraceRing};
+use crate::vaus::Vaus;
+use crate::zapper::Zapper;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// PRG RAM size used when neither the ROM database nor the header specifies one
+const DEFAULT_PRG_RAM_SIZE: usize = 8 * 1024;
+
+/// Frame count, input polls, warm-up, alignment, NMI line state and region, as saved
+type Timing = (u64, u32, u32, u8, bool, bool, Region);
+
+/// Part of the system `NesSystem::reset_component` resets on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Component {
+    /// CPU registers and interrupt state
+    Cpu,
+    /// PPU memory, registers and raster position
+    Ppu,
+    /// APU registers and the DMC sample reader
+    Apu,
+    /// Cartridge bank registers
+    Mapper,
+}
 
 /// NES System - integrates all components
 #[derive(Debug, Clone)]
@@ -20,11 +69,58 @@ pub struct NesSystem {
     frame_count: u64,
     /// Track if PPU has been initialized
     ppu_initialized: bool,
+    /// Controller polls ($4016 reads) during the last completed frame
+    last_frame_input_polls: u32,
+    /// Bytes read from $4016/$4017 during the last completed frame
+    last_frame_input_echo: InputEcho,
+    /// Default power-on RAM pattern
+    ram_init: RamInit,
+    /// Per-game overrides consulted when a ROM is loaded
+    rom_database: RomDatabase,
+    /// CRC32 of the loaded ROM (PRG + CHR)
+    rom_crc32: Option<u32>,
+    /// Most recently executed instructions
+    trace: TraceRing,
+    /// Optional hardware behaviours
+    accuracy: AccuracyProfile,
+    /// Console region (clocks, scanlines, APU tables)
+    region: Region,
+    /// CPU cycles left in the PPU warm-up period
+    ppu_warmup_remaining: u32,
+    /// PPU dot offset applied at the last reset
+    ppu_alignment: u8,
+    /// Per-subsystem timing (None when disabled)
+    metrics: Option<Metrics>,
+    /// Sprite occupancy of the last frame (None when disabled)
+    sprite_stats: Option<SpriteStats>,
+    /// PPU NMI output level after the last PPU dot
+    nmi_line: bool,
+    /// NMI edge raised too late to be polled; delivered after the next instruction
+    nmi_deferred: bool,
+    /// GIF clip being recorded, captured at the end of every frame
+    gif_recording: Option<GifRecorder>,
+    /// Achievement pack evaluated at the end of every frame
+    achievements: Option<AchievementSet>,
+    /// Titles unlocked since the frontend last took them
+    unlocked_achievements: Vec<String>,
+    /// Crash signature heuristics
+    crash_detector: CrashDetector,
+    /// iNES mapper number of the loaded ROM
+    mapper_number: Option<u8>,
+    /// Signals for `health()`
+    health: HealthMonitor,
+    /// Frozen-state detection (None when disabled)
+    idle: Option<IdleDetector>,
+    /// Seed the RNG was last set from
+    rng_seed: u64,
+    /// Randomness for every stochastic feature (random RAM, random alignment)
+    rng: Rng,
 }
 
 impl NesSystem {
     /// Create a new NES system with no cartridge
     pub fn new() -> Self {
+        let rng_seed = Rng::entropy_seed();
         Self {
             cpu: Cpu::new(),
             ppu: Ppu::new(),
@@ -32,6 +128,29 @@ impl NesSystem {
             bus: Bus::new(),
             frame_count: 0,
             ppu_initialized: false,
+            last_frame_input_polls: 0,
+            last_frame_input_echo: InputEcho::new(),
+            ram_init: RamInit::default(),
+            rom_database: RomDatabase::builtin(),
+            rom_crc32: None,
+            trace: TraceRing::default(),
+            accuracy: AccuracyProfile::default(),
+            region: Region::default(),
+            ppu_warmup_remaining: 0,
+            ppu_alignment: 0,
+            metrics: None,
+            sprite_stats: None,
+            nmi_line: false,
+            nmi_deferred: false,
+            gif_recording: None,
+            achievements: None,
+            unlocked_achievements: Vec::new(),
+            crash_detector: CrashDetector::default(),
+            mapper_number: None,
+            health: HealthMonitor::new(),
+            idle: None,
+            rng_seed,
+            rng: Rng::new(rng_seed),
         }
     }
 
@@ -50,85 +169,743 @@ impl NesSystem {
     }
 
     /// Load a simple cartridge into the system
-    pub fn load_simple_cartridge(&mut self, cartridge: SimpleCartridge) {
+    #[cfg(test)]
+    pub(crate) fn load_simple_cartridge(&mut self, cartridge: SimpleCartridge) {
         self.bus.set_cartridge(cartridge);
+        self.sync_mirroring();
+        self.mapper_number = None;
+        self.health.reset();
     }
 
     /// Load an iNES ROM file into the system
     pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), CartridgeError> {
         let cartridge = Cartridge::from_rom(rom_data)?;
-        self.bus.set_cartridge(SimpleCartridge::new(
-            cartridge.prg_rom().to_vec(),
-            cartridge.chr_rom().to_vec(),
-        ));
+        self.load_cartridge(&cartridge);
         Ok(())
     }
 
+    /// Load an already parsed cartridge (for example one from `loader::load_rom_async`)
+    pub fn load_cartridge(&mut self, cartridge: &Cartridge) {
+        self.rom_crc32 = Some(cartridge.crc32());
+        self.mapper_number = Some(cartridge.header().mapper_number());
+        self.health.reset();
+        self.crash_detector = CrashDetector::new(Some(cartridge.header().mapper_number()));
+        // PRG RAM size: ROM database, then header, then the common 8KB
+        let prg_ram_size = self
+            .rom_info()
+            .and_then(|info| info.prg_ram_size)
+            .or_else(|| cartridge.header().prg_ram_bytes())
+            .unwrap_or(DEFAULT_PRG_RAM_SIZE);
+        // Bus conflicts: ROM database, then NES 2.0 submapper, then the board default
+        let header = cartridge.header();
+        let bus_conflicts = self.rom_info().and_then(|info| info.bus_conflicts).or_else(|| {
+            header.submapper().and_then(|submapper| MapperState::submapper_bus_conflicts(header.mapper_number(), submapper))
+        });
+        self.bus.set_cartridge(
+            SimpleCartridge::new(cartridge.prg_rom().to_vec(), cartridge.chr_rom().to_vec())
+                .with_prg_ram_size(prg_ram_size)
+                .with_mapper(MapperState::for_header(header))
+                .with_bus_conflicts(bus_conflicts)
+                .with_mirroring(header.mirroring())
+                .with_battery(header.has_sram()),
+        );
+        self.sync_mirroring();
+        self.bus.power_on_ram(self.effective_ram_init(), &mut self.rng);
+        if let Some(region) = header.region() {
+            self.set_region(region);
+        }
+    }
+
+    /// Set the default power-on RAM pattern (used unless the ROM database overrides it)
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+    }
+
+    /// Get the power-on RAM pattern for the loaded ROM, including database overrides
+    pub fn effective_ram_init(&self) -> RamInit {
+        self.rom_info()
+            .and_then(|info| info.ram_init)
+            .unwrap_or(self.ram_init)
+    }
+
+    /// Get the ROM database entry for the loaded ROM, if it has one
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_crc32.and_then(|crc| self.rom_database.lookup(crc))
+    }
+
+    /// Get the ROM database
+    pub fn rom_database(&self) -> &RomDatabase {
+        &self.rom_database
+    }
+
+    /// Get mutable ROM database (to add per-game overrides)
+    pub fn rom_database_mut(&mut self) -> &mut RomDatabase {
+        &mut self.rom_database
+    }
+
+    /// Get the CRC32 of the loaded ROM, if any
+    pub fn rom_crc32(&self) -> Option<u32> {
+        self.rom_crc32
+    }
+
     /// Reset the NES system
     pub fn reset(&mut self) {
         self.cpu.reset();
         self.ppu.reset();
+        self.ppu_alignment = self.accuracy.ppu_alignment.resolve(&mut self.rng);
+        for _ in 0..self.ppu_alignment {
+            self.ppu.step();
+        }
         self.apu.reset();
+        self.bus.reset_dma();
+        self.bus.set_apu_status(0);
+        self.nmi_line = self.ppu.nmi_output();
+        self.nmi_deferred = false;
         self.frame_count = 0;
+        self.last_frame_input_polls = 0;
+        self.bus.take_input_polls();
+        self.last_frame_input_echo.clear();
+        self.bus.take_input_echo();
+        self.bus.take_ppu_accesses();
+        self.crash_detector.reset();
+        self.health.reset();
+        if let Some(idle) = self.idle.as_mut() {
+            idle.reset();
+        }
+        if self.bus.cartridge().is_some() {
+            let bus = &self.bus;
+            self.crash_detector.check_vectors(|address| bus.peek(address), 0, self.cpu.registers().pc);
+        }
+        // The NES PPU is reset along with the CPU, so the warm-up applies to resets too
+        self.ppu_warmup_remaining = if self.accuracy.ppu_warmup { PPU_WARMUP_CYCLES } else { 0 };
+        self.bus.set_ppu_warming_up(self.ppu_warmup_remaining > 0);
+    }
+
+    /// Reset one component, leaving the others and the frame count alone
+    ///
+    /// For tests and debugging sessions; `reset` is the console's reset
+    /// button. The CPU restarts from the reset vector and the PPU gets the
+    /// warm-up period if the accuracy profile has one, as with `reset`.
+    /// Resetting the mapper puts its bank registers back to their power-on
+    /// values, which `reset` doesn't do since the console's reset line doesn't
+    /// reach most boards.
+    pub fn reset_component(&mut self, component: Component) {
+        match component {
+            Component::Cpu => {
+                self.cpu.reset();
+                self.nmi_deferred = false;
+            }
+            Component::Ppu => {
+                self.ppu.reset();
+                // Otherwise the next step writes the old register values back
+                self.bus.clear_ppu_registers();
+                self.sync_mirroring();
+                self.nmi_line = self.ppu.nmi_output();
+                self.ppu_warmup_remaining = if self.accuracy.ppu_warmup { PPU_WARMUP_CYCLES } else { 0 };
+                self.bus.set_ppu_warming_up(self.ppu_warmup_remaining > 0);
+            }
+            Component::Apu => {
+                self.apu.reset();
+                self.bus.reset_dmc();
+                self.bus.set_apu_status(0);
+            }
+            Component::Mapper => {
+                if let Some(cartridge) = self.bus.cartridge_mut() {
+                    cartridge.reset();
+                }
+                self.sync_mirroring();
+            }
+        }
+    }
+
+    /// Get the accuracy profile
+    pub fn accuracy(&self) -> AccuracyProfile {
+        self.accuracy
+    }
+
+    /// Set the accuracy profile (the PPU warm-up and alignment take effect from the next reset)
+    pub fn set_accuracy(&mut self, accuracy: AccuracyProfile) {
+        self.accuracy = accuracy;
+        self.ppu.set_sprite_overflow_bug(accuracy.sprite_overflow_bug);
+        if !accuracy.ppu_warmup {
+            self.ppu_warmup_remaining = 0;
+            self.bus.set_ppu_warming_up(false);
+        }
+    }
+
+    /// Get the console region
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Set the console region (NTSC by default)
+    ///
+    /// Loading a ROM with a NES 2.0 header that names a region switches to
+    /// it, so set a region after loading to override the header. It takes
+    /// effect immediately, but games expect it from power-on.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        self.bus.set_region(region);
+    }
+
+    /// Get the CPU/PPU alignment (PPU dot offset) chosen at the last reset
+    ///
+    /// With a random alignment, record this value and pass it back as a fixed
+    /// alignment to reproduce a run.
+    pub fn ppu_alignment(&self) -> u8 {
+        self.ppu_alignment
+    }
+
+    /// Restart the RNG from a seed
+    ///
+    /// Set the seed before loading a ROM (or power-cycle afterwards) so random
+    /// power-on RAM comes from it too.
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng_seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    /// Get the seed the RNG was last set from (chosen from OS entropy by `new`)
+    pub fn rng_seed(&self) -> u64 {
+        self.rng_seed
+    }
+
+    /// Get the RNG (save its state alongside the rest of a savestate)
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    /// Get mutable access to the RNG, for frontend features that need randomness
+    pub fn rng_mut(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Check if the PPU is still ignoring setup register writes after power/reset
+    pub fn ppu_warming_up(&self) -> bool {
+        self.ppu_warmup_remaining > 0
+    }
+
+    /// Power-cycle the system: restore the power-on RAM pattern, then reset
+    pub fn power_cycle(&mut self) {
+        self.bus.power_on_ram(self.effective_ram_init(), &mut self.rng);
+        self.reset();
     }
 
     /// Step the system by one instruction (CPU)
-    /// This also steps PPU appropriately (3 PPU cycles per CPU cycle)
+    /// This also steps PPU appropriately (3 PPU cycles per CPU cycle, 3.2 on PAL)
     pub fn step(&mut self) -> Result<bool, CpuError> {
+        let mut clock = self.metrics.is_some().then(Instant::now);
+
         // Sync PPU registers from bus to PPU before CPU reads
         self.sync_ppu_registers();
+        // Setting NMI enable during VBLANK raises NMI, but the write lands on
+        // the last cycle of the store, after the poll
+        if self.update_nmi_line() {
+            self.nmi_deferred = true;
+        }
+        self.lap(&mut clock, Subsystem::Ppu);
+
+        // Only the instruction's own controller reads can be hit by a DMC fetch
+        self.bus.take_controller_read();
+        let cycles_before = self.cpu.total_cycles();
+        let (kind, opcode) = if self.cpu.interrupt_ready() {
+            // The interrupt sequence writes on the same cycles as BRK
+            match self.cpu.service_interrupt(&mut self.bus)? {
+                NMI_VECTOR => (StepKind::Nmi, Opcode::BRKImplied),
+                _ => (StepKind::Irq, Opcode::BRKImplied),
+            }
+        } else {
+            // Get opcode and decode it before stepping
+            let pc = self.cpu.registers().pc;
+            if pc < 0x2000 {
+                self.crash_detector.check_pc(pc, self.bus.ram(), self.frame_count);
+            }
+            let opcode_byte = self.bus.read(pc);
+            let opcode = self.cpu.decode_opcode(opcode_byte);
+            if self.trace.is_enabled() {
+                // Invalid opcodes are still recorded so the trace ends at the faulting byte
+                let len = opcode.as_ref().map_or(1, |&op| self.cpu.instruction_length(op));
+                self.record_trace(opcode_byte, len);
+            }
+            let opcode = opcode.inspect_err(|_| self.health.record_jam(pc, opcode_byte))?;
 
-        // Get opcode and decode it before stepping
-        let opcode_byte = self.bus.read(self.cpu.registers().pc);
-        let opcode = match self.cpu.decode_opcode(opcode_byte) {
-            Ok(op) => op,
-            Err(e) => return Err(e),
+            // Step CPU
+            let running = self.cpu.step(&mut self.bus)?;
+            if !running {
+                self.lap(&mut clock, Subsystem::Cpu);
+                return Ok(false);
+            }
+            match opcode {
+                Opcode::BRKImplied => (StepKind::Brk, opcode),
+                _ => (StepKind::Instruction, opcode),
+            }
         };
-        let instruction_cycles = self.cpu.instruction_cycles(opcode).max(1);
+        let instruction_cycles = (self.cpu.total_cycles() - cycles_before) as u32;
+        let stall = self.run_dma(instruction_cycles, write_cycles(opcode, instruction_cycles));
+        self.cpu.stall(stall);
+        let cycles = instruction_cycles + stall;
+        self.lap(&mut clock, Subsystem::Cpu);
+
+        // Step PPU (3 cycles for each CPU cycle, or 16 for every 5 on PAL),
+        // noting the CPU cycle in which NMI was raised
+        let end = self.cpu.total_cycles();
+        let dots = self.region.dots_at_cycle(end) - self.region.dots_at_cycle(end - cycles as u64);
+        let (numerator, denominator) = self.region.dots_per_cpu_cycle();
+        let mut nmi_cycle = None;
+        for dot in 0..dots as u32 {
+            self.ppu.step();
+            if self.update_nmi_line() && nmi_cycle.is_none() {
+                nmi_cycle = Some(dot * denominator / numerator);
+            }
+        }
+        // The instruction's scroll writes land at the dot it finished on
+        self.apply_ppu_writes();
+        let mask = self.ppu.mask();
+        self.health.observe(mask.render_background() || mask.render_sprites(), self.bus.peek(0x4015) & 0x1F != 0);
+        let ppu = &self.ppu;
+        if let Some(zapper) = self.bus.zapper_mut() {
+            zapper.begin_scanline(ppu.scanline(), |y| {
+                let mut row = vec![0; FRAME_WIDTH * 3];
+                ppu.render_scanline(y, &mut row, FRAME_WIDTH);
+                row
+            });
+        }
+        self.lap(&mut clock, Subsystem::Ppu);
 
-        // Step CPU
-        let running = self.cpu.step(&mut self.bus)?;
-        if !running {
-            return Ok(false);
+        // Step APU, after the instruction's register writes and DMC fetches
+        for (address, value) in self.bus.take_apu_writes() {
+            self.apu.write(address, value);
+        }
+        for byte in self.bus.take_dmc_samples() {
+            self.apu.push_dmc_sample(byte);
+        }
+        if self.bus.take_apu_status_read() {
+            self.apu.acknowledge_frame_irq();
         }
+        self.apu.step(cycles);
+        self.bus.set_apu_status(self.apu.status());
+        self.lap(&mut clock, Subsystem::Apu);
 
-        // Step PPU (3 cycles for each CPU cycle)
-        for _ in 0..(instruction_cycles as usize * 3) {
-            self.ppu.step();
+        // Step mapper timers
+        self.bus.clock_cartridge(cycles);
+        self.lap(&mut clock, Subsystem::Mapper);
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.count_step();
         }
 
-        // Step APU
-        self.apu.step(instruction_cycles as u8);
+        if self.ppu_warmup_remaining > 0 {
+            self.ppu_warmup_remaining = self.ppu_warmup_remaining.saturating_sub(cycles);
+            self.bus.set_ppu_warming_up(self.ppu_warmup_remaining > 0);
+        }
 
+        self.poll_interrupts(kind, nmi_cycle, cycles);
         Ok(true)
     }
 
+    /// Run the DMA units after an instruction, returning the cycles they halt the CPU for
+    ///
+    /// DMC fetches made during the instruction halt it afterwards, for 1-4
+    /// cycles depending on the cycle each came due on and how many of the
+    /// instruction's `writes` cycles it overlaps. One that lands on the
+    /// instruction's last cycle, where a load reads $4016/$4017, reads the
+    /// controller again when `dmc_controller_glitch` is on. The extra read is
+    /// applied after the instruction's own, so the button lost is the one
+    /// after the bit the game saw. A $4014 write then copies its page into
+    /// OAM through the bus, so a copy from page $40 clocks the controllers
+    /// too.
+    fn run_dma(&mut self, instruction_cycles: u32, writes: Range<u32>) -> u32 {
+        let controller_read = self.bus.take_controller_read();
+        let fetches = self.bus.clock_dmc(instruction_cycles);
+        if fetches.on_last_cycle && self.accuracy.dmc_controller_glitch {
+            if let Some(port) = controller_read {
+                self.bus.clock_controller(port);
+            }
+        }
+        let oam_page = self.bus.take_oam_dma();
+        let start = self.cpu.total_cycles() - instruction_cycles as u64;
+        // Cycles the fetches themselves halt for
+        let mut fetch_cycles = fetches
+            .cycles
+            .iter()
+            .map(|&cycle| match oam_page {
+                // A fetch on the $4014 write joins the OAM DMA's halt
+                Some(_) if cycle + 1 == instruction_cycles => DMC_FETCH_DURING_OAM_CYCLES,
+                _ => {
+                    let overlapped = if writes.contains(&cycle) { writes.end - cycle } else { 0 };
+                    dmc_fetch_stall(start + cycle as u64, overlapped)
+                }
+            })
+            .sum();
+        let mut stall = fetch_cycles;
+
+        if let Some(page) = oam_page {
+            let base = (page as u16) << 8;
+            let bytes: Vec<u8> = (0..=0xFF).map(|i| self.bus.read(base | i)).collect();
+            self.ppu.write_oam_bytes(self.ppu.oam_address(), &bytes);
+            self.bus.take_controller_read();
+            // One alignment cycle when the halt starts on an odd cycle
+            let odd = (self.cpu.total_cycles() + stall as u64) % 2 == 1;
+            let dma_cycles = OAM_DMA_CYCLES + odd as u32;
+            let fetches = self.bus.clock_dmc(dma_cycles);
+            let extra: u32 = fetches.cycles.iter().map(|&cycle| dmc_fetch_during_oam_stall(dma_cycles - cycle)).sum();
+            fetch_cycles += extra;
+            stall += dma_cycles + extra;
+        }
+        // The reader keeps running through its own halts, which are far too
+        // short for the next fetch to come due
+        self.bus.clock_dmc(fetch_cycles);
+        stall
+    }
+
+    /// Track the PPU NMI output, returning true on a rising edge
+    fn update_nmi_line(&mut self) -> bool {
+        let line = self.ppu.nmi_output();
+        let rising = line && !self.nmi_line;
+        self.nmi_line = line;
+        rising
+    }
+
+    /// Deliver interrupts raised during the step that just ran.
+    ///
+    /// The CPU polls before the last cycle of an instruction, so an NMI
+    /// raised on the last cycle waits for one more instruction; so does one
+    /// raised after the vector fetch of BRK or an interrupt sequence, which
+    /// does not poll. An NMI raised before the vector fetch of BRK or IRQ
+    /// takes over the vector instead.
+    fn poll_interrupts(&mut self, kind: StepKind, nmi_cycle: Option<u32>, cycles: u32) {
+        if std::mem::take(&mut self.nmi_deferred) {
+            self.cpu.trigger_nmi();
+        }
+        if let Some(cycle) = nmi_cycle {
+            let hijackable = matches!(kind, StepKind::Brk | StepKind::Irq);
+            if hijackable && cycle < HIJACK_WINDOW_CYCLES as u32 {
+                self.cpu.hijack_with_nmi(&mut self.bus);
+            } else if kind != StepKind::Instruction || cycle + 1 >= cycles {
+                self.nmi_deferred = true;
+            } else {
+                self.cpu.trigger_nmi();
+            }
+        }
+
+        self.cpu.set_irq_line(self.interrupts().line());
+        self.cpu.poll_interrupts();
+    }
+
+    /// Get the devices holding the IRQ line
+    pub fn interrupts(&self) -> InterruptController {
+        let mut interrupts = InterruptController::new();
+        interrupts.set(IrqSource::ApuFrame, self.apu.frame_irq_pending());
+        interrupts.set(IrqSource::Dmc, self.bus.dmc_irq_pending());
+        interrupts.set(IrqSource::Mapper, self.bus.cartridge().is_some_and(|cart| cart.irq_pending()));
+        interrupts
+    }
+
+    /// Release a device's IRQ as its acknowledge write would, for debuggers
+    ///
+    /// Returns true if the source was holding the line.
+    pub fn acknowledge_irq(&mut self, source: IrqSource) -> bool {
+        let pending = self.interrupts().is_pending(source);
+        match source {
+            IrqSource::ApuFrame => {
+                self.apu.acknowledge_frame_irq();
+                self.bus.set_apu_status(self.apu.status());
+            }
+            IrqSource::Dmc => self.bus.acknowledge_dmc_irq(),
+            IrqSource::Mapper => {
+                if let Some(cartridge) = self.bus.cartridge_mut() {
+                    cartridge.mapper_mut().acknowledge_irq();
+                }
+            }
+        }
+        self.cpu.set_irq_line(self.interrupts().line());
+        pending
+    }
+
+    /// Charge the time since `clock` to a subsystem and restart the clock
+    fn lap(&mut self, clock: &mut Option<Instant>, subsystem: Subsystem) {
+        if let (Some(start), Some(metrics)) = (clock.as_mut(), self.metrics.as_mut()) {
+            let now = Instant::now();
+            metrics.record(subsystem, now - *start);
+            *start = now;
+        }
+    }
+
+    /// Enable or disable per-subsystem timing (enabling starts from zero)
+    pub fn set_metrics_enabled(&mut self, enabled: bool) {
+        self.metrics = enabled.then(Metrics::new);
+    }
+
+    /// Get the timing metrics, if enabled
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Enable or disable per-frame sprite occupancy statistics
+    ///
+    /// When enabled, OAM is evaluated at the end of every frame (the same OAM
+    /// the frame was rendered from) and summed up into `sprite_stats`.
+    pub fn set_sprite_stats_enabled(&mut self, enabled: bool) {
+        self.sprite_stats = enabled.then(SpriteStats::default);
+    }
+
+    /// Get the sprite occupancy of the last frame, if enabled
+    pub fn sprite_stats(&self) -> Option<&SpriteStats> {
+        self.sprite_stats.as_ref()
+    }
+
+    /// Record the instruction about to execute in the trace ring
+    fn record_trace(&mut self, opcode_byte: u8, len: u8) {
+        let regs = self.cpu.registers();
+        let pc = regs.pc;
+        self.trace.push(TraceEntry {
+            pc,
+            bytes: [
+                opcode_byte,
+                self.bus.peek(pc.wrapping_add(1)),
+                self.bus.peek(pc.wrapping_add(2)),
+            ],
+            len,
+            a: regs.a,
+            x: regs.x,
+            y: regs.y,
+            p: self.cpu.p_register(),
+            sp: regs.sp,
+            cycles: self.cpu.total_cycles(),
+        });
+    }
+
+    /// Get the trace ring of recently executed instructions
+    pub fn trace(&self) -> &TraceRing {
+        &self.trace
+    }
+
+    /// Change how many instructions the trace ring keeps (0 disables it); clears the ring
+    pub fn set_trace_capacity(&mut self, capacity: usize) {
+        self.trace = TraceRing::new(capacity);
+    }
+
     /// Sync PPU internal state from bus registers
     pub fn sync_ppu_registers(&mut self) {
+        self.apply_ppu_writes();
         // Read values from bus's ppu_registers and sync to PPU
-        // The bus stores writes to PPU registers ($2000-$2007)
-        self.ppu.write(0x2000, self.bus.get_ppu_register(0)); // PPUCTRL
+        // The bus stores writes to PPU registers ($2000-$2007); PPUCTRL is
+        // only rewritten when a PPU reset lost it, as a rewrite reloads `t`
+        let control = self.bus.get_ppu_register(0);
+        if control != self.ppu.control_value() {
+            self.ppu.write(0x2000, control); // PPUCTRL
+        }
         self.ppu.write(0x2001, self.bus.get_ppu_register(1)); // PPUMASK
         self.ppu.write(0x2003, self.bus.get_ppu_register(3)); // OAMADDR
-        self.ppu.write(0x2005, self.bus.get_ppu_register(5)); // PPUSCROLL
-        self.ppu.write(0x2006, self.bus.get_ppu_register(6)); // PPUADDR
+        self.sync_mirroring();
+    }
+
+    /// Hand the PPU the register accesses that move its scroll registers, in order
+    fn apply_ppu_writes(&mut self) {
+        if self.bus.take_ppu_status_read() {
+            self.ppu.clear_write_toggle();
+        }
+        for (index, value) in self.bus.take_ppu_writes() {
+            self.ppu.write(0x2000 + index as u16, value);
+        }
+    }
+
+    /// Apply the cartridge's current nametable mirroring to the PPU
+    fn sync_mirroring(&mut self) {
+        if let Some(cartridge) = self.bus.cartridge() {
+            self.ppu.set_mirroring(cartridge.mirroring());
+        }
     }
 
     /// Run for N frames
     pub fn run_frames(&mut self, frames: u64) -> Result<(), Box<dyn std::error::Error>> {
-        // NTSC: ~29780 cycles per frame
-        let cycles_per_frame = 29780;
-
         for _ in 0..frames {
-            // Run for one frame
-            for _ in 0..cycles_per_frame {
-                self.step()?;
-            }
-            self.frame_count += 1;
+            self.run_frame_until(|_| false)?;
         }
         Ok(())
     }
 
+    /// Run one frame, stopping early when `stop` returns true before an instruction
+    ///
+    /// A frame ends with the instruction during which the PPU finishes its
+    /// last scanline, so it runs the region's `Region::cpu_cycles_per_frame`
+    /// cycles on average. Returns true if it stopped early; the frame is then
+    /// left unfinished and the next call continues it.
+    pub fn run_frame_until(&mut self, mut stop: impl FnMut(&Self) -> bool) -> Result<bool, Box<dyn std::error::Error>> {
+        loop {
+            if stop(self) {
+                return Ok(true);
+            }
+            let running = self.step()?;
+            if self.ppu.take_frame_complete() || !running {
+                break;
+            }
+        }
+        self.end_frame();
+        Ok(false)
+    }
+
+    /// Run up to `max_frames` frames, stopping early once the game is idle
+    ///
+    /// Returns the number of frames run. Without an idle detector (see
+    /// `set_idle_detector`) this runs all of them.
+    pub fn run_until_idle(&mut self, max_frames: u64) -> Result<u64, Box<dyn std::error::Error>> {
+        for frame in 0..max_frames {
+            if self.is_idle() {
+                return Ok(frame);
+            }
+            self.run_frame_until(|_| false)?;
+        }
+        Ok(max_frames)
+    }
+
+    /// Run up to `max_frames` frames, stopping once `goal` recognizes the rendered frame
+    ///
+    /// The goal sees every frame's 256x240 RGB framebuffer (see `frame_goal`).
+    /// Returns the number of frames run when it was reached, or None if it
+    /// never was.
+    pub fn run_until_goal(
+        &mut self,
+        max_frames: u64,
+        goal: &mut impl FrameGoal,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let mut framebuffer = vec![0; FRAME_RGB_SIZE];
+        for frame in 1..=max_frames {
+            self.run_frame_until(|_| false)?;
+            self.ppu.render_frame(&mut framebuffer);
+            if goal.reached(&framebuffer, self.frame_count) {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Watch for a frozen game state at the end of every frame (None disables it)
+    pub fn set_idle_detector(&mut self, detector: Option<IdleDetector>) {
+        self.idle = detector;
+    }
+
+    /// Get the idle detector, if enabled
+    pub fn idle_detector(&self) -> Option<&IdleDetector> {
+        self.idle.as_ref()
+    }
+
+    /// Check if the game state has been frozen for the idle detector's threshold
+    pub fn is_idle(&self) -> bool {
+        self.idle.as_ref().is_some_and(IdleDetector::is_idle)
+    }
+
+    /// Run one instruction per `next()` and yield what ran, for analysis tools
+    pub fn instructions(&mut self) -> Instructions<'_> {
+        Instructions::new(self)
+    }
+
+    /// Check if the PPU finished a frame since the last call, clearing the flag
+    pub(crate) fn take_frame_complete(&mut self) -> bool {
+        self.ppu.take_frame_complete()
+    }
+
+    /// Finish the current frame and latch per-frame statistics
+    pub(crate) fn end_frame(&mut self) {
+        self.frame_count += 1;
+        self.last_frame_input_polls = self.bus.take_input_polls();
+        self.last_frame_input_echo = self.bus.take_input_echo();
+        if let Some(stats) = self.sprite_stats.as_mut() {
+            *stats = SpriteStats::from_lines(&self.ppu.evaluate_sprites());
+        }
+        let ppu_accesses = self.bus.take_ppu_accesses();
+        self.crash_detector.end_frame(ppu_accesses, self.frame_count, self.cpu.registers().pc);
+        self.health.end_frame();
+        if let Some(idle) = self.idle.as_mut() {
+            idle.end_frame(self.bus.ram(), self.bus.cartridge().and_then(SimpleCartridge::prg_ram), &self.ppu);
+        }
+        if let Some(mut recorder) = self.gif_recording.take() {
+            recorder.record_frame_hash(self);
+            recorder.capture(&self.ppu);
+            self.gif_recording = Some(recorder);
+        }
+        self.bus.apply_ram_cheats();
+        if let Some(achievements) = self.achievements.as_mut() {
+            let unlocked = achievements.evaluate(self.bus.ram());
+            self.unlocked_achievements.extend(unlocked.into_iter().map(|a| a.title.clone()));
+        }
+    }
+
+    /// Summarize whether the emulator is running properly, for a status indicator
+    pub fn health(&self) -> HealthReport {
+        let mapper_supported = self.mapper_number.is_none_or(MapperState::supports);
+        self.health.report(self.bus.cartridge().is_some(), self.mapper_number, mapper_supported)
+    }
+
+    /// Report how long the host took to run (and present) `frames` frames, for `health().speed`
+    pub fn record_host_time(&mut self, frames: u64, elapsed: Duration) {
+        self.health.record_host_time(frames, elapsed);
+    }
+
+    /// Evaluate an achievement pack every frame (None removes it)
+    pub fn set_achievements(&mut self, achievements: Option<AchievementSet>) {
+        self.achievements = achievements;
+        self.unlocked_achievements.clear();
+    }
+
+    /// Get the achievement pack and its progress
+    pub fn achievements(&self) -> Option<&AchievementSet> {
+        self.achievements.as_ref()
+    }
+
+    /// Take crash diagnostics (null vectors, zeroed RAM execution, a silent PPU) reported since the last call
+    pub fn take_crash_diagnostics(&mut self) -> Vec<CrashDiagnostic> {
+        self.crash_detector.take_diagnostics()
+    }
+
+    /// Take the titles of achievements unlocked since the last call, oldest first
+    pub fn take_unlocked_achievements(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.unlocked_achievements)
+    }
+
+    /// Start recording an animated GIF of the next `max_frames` frames
+    ///
+    /// Any recording in progress is discarded. Frames stop being captured once the
+    /// limit is reached; the file is written by `stop_gif_recording`.
+    pub fn start_gif_recording(&mut self, path: impl Into<PathBuf>, max_frames: u32) {
+        self.start_gif_recording_with(GifRecorder::new(path, max_frames));
+    }
+
+    /// Start recording with a configured recorder (for example a 2x scaled one)
+    pub fn start_gif_recording_with(&mut self, mut recorder: GifRecorder) {
+        recorder.begin_frame_hashes(self);
+        self.gif_recording = Some(recorder);
+    }
+
+    /// Get the GIF recording in progress
+    pub fn gif_recording(&self) -> Option<&GifRecorder> {
+        self.gif_recording.as_ref()
+    }
+
+    /// Stop recording and write the GIF, returning its path (None if not recording)
+    pub fn stop_gif_recording(&mut self) -> Option<Result<PathBuf, GifError>> {
+        let recorder = self.gif_recording.take()?;
+        Some(recorder.save().map(|()| recorder.path().to_path_buf()))
+    }
+
+    /// Add a cheat: PRG patches apply from the next read, RAM cheats at the end of every frame
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        let mut cheats = self.bus.cheats().to_vec();
+        cheats.push(cheat);
+        self.bus.set_cheats(cheats);
+    }
+
+    /// Remove every cheat
+    pub fn clear_cheats(&mut self) {
+        self.bus.set_cheats(Vec::new());
+    }
+
+    /// Get the active cheats, in the order they were added
+    pub fn cheats(&self) -> &[Cheat] {
+        self.bus.cheats()
+    }
+
     /// Run until VBLANK is set (one frame)
     pub fn run_until_vblank(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let mut cycles = 0u64;
@@ -147,15 +924,36 @@ impl NesSystem {
     }
 
     /// Get CPU reference
-    pub fn cpu(&self) -> &Cpu {
+    pub(crate) fn cpu(&self) -> &Cpu {
         &self.cpu
     }
 
     /// Get mutable CPU reference
-    pub fn cpu_mut(&mut self) -> &mut Cpu {
+    pub(crate) fn cpu_mut(&mut self) -> &mut Cpu {
         &mut self.cpu
     }
 
+    /// Get a copy of the CPU registers, with `p` built from the status flags
+    pub fn cpu_registers(&self) -> CpuRegisters {
+        CpuRegisters { p: self.cpu.p_register(), ..*self.cpu.registers() }
+    }
+
+    /// Set the CPU registers, taking the status flags from `p`
+    pub fn set_cpu_registers(&mut self, registers: CpuRegisters) {
+        *self.cpu.registers_mut() = registers;
+        *self.cpu.status_mut() = StatusFlags::new(registers.p);
+    }
+
+    /// Move the program counter, e.g. to a test ROM's entry point
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.registers_mut().pc = pc;
+    }
+
+    /// Get the CPU cycles run since power-on
+    pub fn cpu_cycles(&self) -> u64 {
+        self.cpu.total_cycles()
+    }
+
     /// Get PPU reference
     pub fn ppu(&self) -> &Ppu {
         &self.ppu
@@ -166,19 +964,59 @@ impl NesSystem {
         &mut self.ppu
     }
 
+    /// Have the PPU fill a framebuffer as visible lines finish (see `Ppu::set_framebuffer_enabled`)
+    pub fn set_framebuffer_enabled(&mut self, enabled: bool) {
+        self.ppu.set_framebuffer_enabled(enabled);
+    }
+
+    /// Get the 256x240 RGB frame the PPU built line by line (empty unless enabled)
+    pub fn framebuffer(&self) -> &[u8] {
+        self.ppu.framebuffer()
+    }
+
+    /// Copy CPU page `page` ($XX00-$XXFF) into OAM, as a write of `page` to $4014 would
+    ///
+    /// Debug API for tests and tools: bytes land from OAMADDR onwards (wrapping),
+    /// reads have no side effects, and the CPU is not stalled.
+    pub fn trigger_oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        let bytes: Vec<u8> = (0..=0xFF).map(|i| self.bus.peek(base | i)).collect();
+        let offset = self.ppu.oam_address();
+        self.ppu.write_oam_bytes(offset, &bytes);
+    }
+
     /// Get CHR ROM data
     pub fn chr_rom(&self) -> Option<&[u8]> {
         self.bus.chr_rom()
     }
 
     /// Get APU reference
-    pub fn apu(&self) -> &Apu {
+    pub(crate) fn apu(&self) -> &Apu {
         &self.apu
     }
 
-    /// Get mutable APU reference
-    pub fn apu_mut(&mut self) -> &mut Apu {
-        &mut self.apu
+    /// Turn audio output on or off (off by default, which skips the mixing)
+    pub fn set_audio_enabled(&mut self, enabled: bool) {
+        self.apu.set_audio_enabled(enabled);
+    }
+
+    /// Set the audio output sample rate in Hz
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    /// Get the audio output sample rate in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.apu.sample_rate()
+    }
+
+    /// Take the mono audio samples produced since the last call
+    ///
+    /// Samples are mixer levels (0..=`mixer::MIX_SCALE`); convert them with
+    /// `sample_format::from_i16`, take them with `take_audio_samples_f32`
+    /// instead, or let `sink::FramePump` do it.
+    pub fn take_audio_samples(&mut self) -> Vec<i16> {
+        self.apu.take_samples()
     }
 
     /// Get frame count
@@ -186,51 +1024,1291 @@ impl NesSystem {
         self.frame_count
     }
 
-    /// Read a byte from memory via the bus
-    pub fn read_memory(&mut self, address: u16) -> u8 {
-        self.bus.read(address)
+    /// Get the number of controller polls ($4016 reads) in the last frame
+    pub fn input_polls_last_frame(&self) -> u32 {
+        self.last_frame_input_polls
     }
 
-    /// Write a byte to memory via the bus
-    pub fn write_memory(&mut self, address: u16, value: u8) {
-        self.bus.write(address, value);
+    /// Get the bytes the game read from each controller port during the last frame
+    ///
+    /// These are the exact values the CPU saw, open-bus bits included, so a
+    /// read made while the strobe was still high shows up as a repeat of A.
+    /// The echo isn't part of savestates: after a load it starts empty.
+    pub fn input_echo_last_frame(&self) -> &InputEcho {
+        &self.last_frame_input_echo
     }
 
-    /// Get a reference to the bus's cartridge
-    pub fn bus_cartridge(&self) -> Option<&SimpleCartridge> {
-        self.bus.cartridge()
+    /// Check if the last frame was a lag frame (the game never read the controller)
+    pub fn lag_frame(&self) -> bool {
+        self.frame_count > 0 && self.last_frame_input_polls == 0
     }
-}
 
-impl Default for NesSystem {
-    fn default() -> Self {
-        Self::new()
+    /// Set the button state of the controller on the given port (0 or 1)
+    pub fn set_buttons(&mut self, port: usize, buttons: Buttons) {
+        self.bus.set_buttons(port, buttons);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::bus::SimpleCartridge;
+    /// Get the button state of the controller on the given port (0 or 1)
+    ///
+    /// Opposing directions come back resolved by the D-pad policy, as the
+    /// game reads them.
+    pub fn buttons(&self, port: usize) -> Buttons {
+        self.bus.buttons(port)
+    }
 
-    #[test]
-    fn test_system_reset() {
-        let mut system = NesSystem::new();
-        system.reset();
-        // After reset, CPU should be ready
-        assert_eq!(system.cpu().registers().pc, 0xFFFC);
+    /// Set how the controllers resolve Left+Right and Up+Down (`Allow` by default)
+    pub fn set_dpad_policy(&mut self, policy: DpadPolicy) {
+        self.bus.set_dpad_policy(policy);
     }
 
-    #[test]
-    fn test_system_with_cartridge() {
-        let prg_rom = vec![0xFF; 16384]; // 16KB
-        let chr_rom = vec![0x00; 8192];  // 8KB
-        let cartridge = SimpleCartridge::new(prg_rom, chr_rom);
+    /// Get the opposing D-pad direction policy
+    pub fn dpad_policy(&self) -> DpadPolicy {
+        self.bus.dpad_policy()
+    }
 
-        let mut system = NesSystem::new();
-        system.load_simple_cartridge(cartridge);
-        system.reset();
+    /// Plug a Zapper into port 2, unplugging a paddle (None plugs the controller back in)
+    pub fn set_zapper(&mut self, zapper: Option<Zapper>) {
+        self.bus.set_zapper(zapper);
+    }
 
-        assert!(system.cpu().registers().pc == 0xFFFC);
+    /// Get the Zapper on port 2, if plugged in
+    pub fn zapper(&self) -> Option<&Zapper> {
+        self.bus.zapper()
+    }
+
+    /// Get mutable access to the Zapper (to aim it and pull the trigger)
+    pub fn zapper_mut(&mut self) -> Option<&mut Zapper> {
+        self.bus.zapper_mut()
+    }
+
+    /// Plug an Arkanoid paddle into port 2, unplugging a Zapper (None plugs the controller back in)
+    pub fn set_vaus(&mut self, vaus: Option<Vaus>) {
+        self.bus.set_vaus(vaus);
+    }
+
+    /// Get the paddle on port 2, if plugged in
+    pub fn vaus(&self) -> Option<&Vaus> {
+        self.bus.vaus()
+    }
+
+    /// Get mutable access to the paddle (to turn the knob and press fire)
+    pub fn vaus_mut(&mut self) -> Option<&mut Vaus> {
+        self.bus.vaus_mut()
+    }
+
+    /// Hash the observable machine state (CPU registers, RAM, PPU memory, frame count)
+    ///
+    /// Two runs that produce the same hash reached the same state, which is
+    /// what movie verification and telemetry compare.
+    pub fn state_hash(&self) -> u64 {
+        let regs = self.cpu.registers();
+        let mut hash = FNV_OFFSET_BASIS;
+        hash = fnv1a_update(hash, &[regs.a, regs.x, regs.y, self.cpu.p_register(), regs.sp]);
+        hash = fnv1a_update(hash, &regs.pc.to_le_bytes());
+        hash = fnv1a_update(hash, &self.frame_count.to_le_bytes());
+        hash = fnv1a_update(hash, self.bus.ram());
+        hash = fnv1a_update(hash, self.ppu.vram());
+        hash = fnv1a_update(hash, self.ppu.palette_ram());
+        fnv1a_update(hash, self.ppu.oam())
+    }
+
+    /// Save the complete machine state
+    ///
+    /// The state starts with `STATE_MAGIC`, `STATE_VERSION` and the ROM's
+    /// CRC32, followed by versioned sections for the CPU, PPU, APU, bus
+    /// (including cartridge RAM and mapper registers), system timing and RNG
+    /// (see `savestate`). ROM data, settings and debugging aids (trace,
+    /// metrics, achievements) are not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&STATE_MAGIC);
+        writer.write_u16(STATE_VERSION);
+        writer.write_bool(self.rom_crc32.is_some());
+        writer.write_u32(self.rom_crc32.unwrap_or(0));
+        write_section(&mut writer, Section::Cpu, |w| self.cpu.save_state(w));
+        write_section(&mut writer, Section::Ppu, |w| self.ppu.save_state(w));
+        write_section(&mut writer, Section::Apu, |w| self.apu.save_state(w));
+        write_section(&mut writer, Section::Bus, |w| self.bus.save_state(w));
+        write_section(&mut writer, Section::System, |w| self.save_timing(w));
+        write_section(&mut writer, Section::Rng, |w| self.rng.save_state(w));
+        writer.into_bytes()
     }
-}
\ No newline at end of file
+
+    /// Restore a state written by `save_state` with the same ROM loaded
+    ///
+    /// Sections from older versions are migrated first. The system is left
+    /// untouched if the state can't be loaded.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(data);
+        let mut magic = [0; 4];
+        reader.read_into(&mut magic).map_err(|_| StateError::NotAState)?;
+        if magic != STATE_MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = reader.read_u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let has_rom = reader.read_bool()?;
+        let crc = reader.read_u32()?;
+        if self.rom_crc32 != has_rom.then_some(crc) {
+            return Err(StateError::WrongRom);
+        }
+        let mut sections = read_sections(&mut reader)?;
+
+        let (mut cpu, mut ppu, mut apu, mut bus, mut rng) =
+            (self.cpu.clone(), self.ppu.clone(), self.apu.clone(), self.bus.clone(), self.rng.clone());
+        // The region comes first: it sets the raster range the PPU section is checked against
+        let mut timing = (0, 0, 0, 0, false, false, Region::Ntsc);
+        load_section(&take_section(&mut sections, Section::System)?, |r| {
+            timing = Self::read_timing(r)?;
+            Ok(())
+        })?;
+        ppu.set_region(timing.6);
+        apu.set_region(timing.6);
+        bus.set_region(timing.6);
+        load_section(&take_section(&mut sections, Section::Cpu)?, |r| cpu.load_state(r))?;
+        load_section(&take_section(&mut sections, Section::Ppu)?, |r| ppu.load_state(r))?;
+        load_section(&take_section(&mut sections, Section::Apu)?, |r| apu.load_state(r))?;
+        load_section(&take_section(&mut sections, Section::Bus)?, |r| bus.load_state(r))?;
+        load_section(&take_section(&mut sections, Section::Rng)?, |r| rng.load_state(r))?;
+
+        self.cpu = cpu;
+        self.ppu = ppu;
+        self.apu = apu;
+        self.bus = bus;
+        self.rng = rng;
+        self.set_timing(timing);
+        self.bus.set_apu_status(self.apu.status());
+        self.sync_mirroring();
+        if let Some(idle) = self.idle.as_mut() {
+            idle.reset();
+        }
+        Ok(())
+    }
+
+    /// Write the frame count, NMI and warm-up timing and the region (the System section)
+    fn save_timing(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.frame_count);
+        writer.write_u32(self.last_frame_input_polls);
+        writer.write_u32(self.ppu_warmup_remaining);
+        writer.write_u8(self.ppu_alignment);
+        writer.write_bool(self.nmi_line);
+        writer.write_bool(self.nmi_deferred);
+        writer.write_u8(self.region.state_id());
+    }
+
+    /// Read timing written by `save_timing`
+    fn read_timing(reader: &mut StateReader) -> Result<Timing, StateError> {
+        let (frame_count, polls, warmup, alignment) = (reader.read_u64()?, reader.read_u32()?, reader.read_u32()?, reader.read_u8()?);
+        let (nmi_line, nmi_deferred) = (reader.read_bool()?, reader.read_bool()?);
+        let region = Region::from_state_id(reader.read_u8()?).ok_or(StateError::InvalidData("region"))?;
+        Ok((frame_count, polls, warmup, alignment, nmi_line, nmi_deferred, region))
+    }
+
+    fn set_timing(&mut self, timing: Timing) {
+        let region;
+        (
+            self.frame_count,
+            self.last_frame_input_polls,
+            self.ppu_warmup_remaining,
+            self.ppu_alignment,
+            self.nmi_line,
+            self.nmi_deferred,
+            region,
+        ) = timing;
+        self.set_region(region);
+        self.last_frame_input_echo.clear();
+    }
+
+    /// Save the machine state into an in-memory snapshot
+    ///
+    /// Saving into the same snapshot again only copies the RAM and VRAM
+    /// pages written since, which makes it much cheaper than `save_state`
+    /// for run-ahead and rollback (see `snapshot`). Like `save_state`, it
+    /// leaves out ROM data, settings and debugging aids.
+    pub fn save_snapshot(&mut self, snapshot: &mut Snapshot) {
+        snapshot.rom_crc32 = self.rom_crc32;
+        let (ram, ram_pages) = self.bus.ram_pages();
+        snapshot.ram.take(ram, ram_pages);
+        let (vram, vram_pages) = self.ppu.vram_pages();
+        snapshot.vram.take(vram, vram_pages);
+        let mut writer = StateWriter::reuse(std::mem::take(&mut snapshot.state));
+        self.cpu.save_state(&mut writer);
+        self.ppu.save_state_without_vram(&mut writer);
+        self.apu.save_state(&mut writer);
+        self.bus.save_state_without_ram(&mut writer);
+        self.save_timing(&mut writer);
+        self.rng.save_state(&mut writer);
+        snapshot.state = writer.into_bytes();
+    }
+
+    /// Restore a snapshot taken from this system (or the one it was cloned from)
+    ///
+    /// An empty snapshot or one from another ROM is refused before anything
+    /// changes. The snapshot is restored in place, so any other error (from a
+    /// differently configured system) can leave the system partly restored.
+    pub fn load_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), StateError> {
+        if snapshot.is_empty() {
+            return Err(StateError::NotAState);
+        }
+        if snapshot.rom_crc32 != self.rom_crc32 {
+            return Err(StateError::WrongRom);
+        }
+        let mut reader = StateReader::new(&snapshot.state);
+        self.cpu.load_state(&mut reader)?;
+        self.ppu.load_state_without_vram(&mut reader)?;
+        self.apu.load_state(&mut reader)?;
+        self.bus.load_state_without_ram(&mut reader)?;
+        let timing = Self::read_timing(&mut reader)?;
+        self.set_timing(timing);
+        self.rng.load_state(&mut reader)?;
+        let (ram, ram_pages) = self.bus.ram_pages();
+        snapshot.ram.restore(ram, ram_pages);
+        let (vram, vram_pages) = self.ppu.vram_pages();
+        snapshot.vram.restore(vram, vram_pages);
+        self.bus.set_apu_status(self.apu.status());
+        self.sync_mirroring();
+        if let Some(idle) = self.idle.as_mut() {
+            idle.reset();
+        }
+        Ok(())
+    }
+
+    /// Get the 2KB internal RAM (read without bus side effects)
+    pub fn ram(&self) -> &[u8] {
+        self.bus.ram()
+    }
+
+    /// Read a byte without side effects (no controller shifts or poll counting)
+    pub(crate) fn peek_memory(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    /// Read a byte from memory via the bus
+    pub fn read_memory(&mut self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
+    /// Write a byte to memory via the bus
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+    }
+
+    /// Map a device's registers onto a CPU address range (see `io_map`)
+    pub fn map_io(
+        &mut self,
+        range: std::ops::RangeInclusive<u16>,
+        handler: impl IoHandler + 'static,
+    ) -> Result<IoRegionId, IoMapError> {
+        self.bus.map(range, handler)
+    }
+
+    /// Remove a region mapped with `map_io`, returning its handler
+    pub fn unmap_io(&mut self, id: IoRegionId) -> Option<Box<dyn IoHandler>> {
+        self.bus.unmap(id)
+    }
+
+    /// Get a reference to the bus's cartridge
+    pub(crate) fn bus_cartridge(&self) -> Option<&SimpleCartridge> {
+        self.bus.cartridge()
+    }
+
+    /// Get the cartridge's battery-backed save data, if the board has any (e.g. EEPROM, flash PRG or battery PRG RAM)
+    pub fn battery_data(&self) -> Option<&[u8]> {
+        self.bus.cartridge().and_then(SimpleCartridge::battery_data)
+    }
+
+    /// Restore battery-backed save data (such as the contents of a .sav file)
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        if let Some(cart) = self.bus.cartridge_mut() {
+            cart.load_battery_data(data);
+        }
+    }
+
+    /// Check if the battery-backed save data may have changed since the last take (or load), resetting the flag
+    pub fn take_battery_dirty(&mut self) -> bool {
+        self.bus.cartridge_mut().is_some_and(SimpleCartridge::take_battery_dirty)
+    }
+}
+
+/// What a system step ran, for interrupt polling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepKind {
+    Instruction,
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl Default for NesSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{HealthStatus, HEALTH_WINDOW_FRAMES};
+    use crate::accuracy::PpuAlignment;
+    use crate::bus::SimpleCartridge;
+    use crate::cartridge::Mirroring;
+    use crate::cheats::CheatFormat;
+
+    #[test]
+    fn test_system_reset() {
+        let mut system = NesSystem::new();
+        system.reset();
+        // After reset, CPU should be ready
+        assert_eq!(system.cpu().registers().pc, 0xFFFC);
+    }
+
+    #[test]
+    fn test_system_with_cartridge() {
+        let prg_rom = vec![0xFF; 16384]; // 16KB
+        let chr_rom = vec![0x00; 8192];  // 8KB
+        let cartridge = SimpleCartridge::new(prg_rom, chr_rom);
+
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(cartridge);
+        system.reset();
+
+        assert!(system.cpu().registers().pc == 0xFFFC);
+    }
+
+    #[test]
+    fn test_rom_database_ram_init_override() {
+        let mut rom = Vec::new();
+        rom.extend_from_slice(b"NES\x1A");
+        rom.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        rom.extend_from_slice(&[0xEA; 16384]);
+        rom.extend_from_slice(&[0x00; 8192]);
+        let crc = Cartridge::from_rom(&rom).unwrap().crc32();
+
+        let mut system = NesSystem::new();
+        system.set_ram_init(RamInit::Fill(0x55));
+        system.load_rom(&rom).unwrap();
+        assert_eq!(system.read_memory(0x0000), 0x55);
+
+        system.rom_database_mut().insert(
+            crate::romdb::RomInfo::new(crc, "Test").with_ram_init(RamInit::Fill(0xFF)),
+        );
+        system.load_rom(&rom).unwrap();
+        assert_eq!(system.effective_ram_init(), RamInit::Fill(0xFF));
+        assert_eq!(system.read_memory(0x0000), 0xFF);
+    }
+
+    /// Bytes that make the CRC32 of `data` followed by them come out as `target`
+    fn crc32_suffix(data: &[u8], target: u32) -> [u8; 4] {
+        // Run the register back 32 bit steps from the target, to the value
+        // it must have right after the suffix is XORed in
+        let mut register = !target;
+        for _ in 0..32 {
+            register = if register & 0x8000_0000 != 0 { ((register ^ 0xEDB8_8320) << 1) | 1 } else { register << 1 };
+        }
+        (register ^ !crate::romdb::crc32(data)).to_le_bytes()
+    }
+
+    #[test]
+    fn test_builtin_ram_init_applies_at_power_on() {
+        let mut rom = Vec::new();
+        rom.extend_from_slice(b"NES\x1A");
+        rom.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        rom.extend_from_slice(&[0xEA; 16384]);
+        rom.extend_from_slice(&[0x00; 8192 - 4]);
+        let suffix = crc32_suffix(&rom[16..], crate::romdb::MINNA_NO_TAABOU_CRC32);
+        rom.extend_from_slice(&suffix);
+
+        let mut system = NesSystem::new();
+        system.load_rom(&rom).unwrap();
+        assert_eq!(system.rom_info().map(|info| info.crc32), Some(crate::romdb::MINNA_NO_TAABOU_CRC32));
+        assert_eq!(system.effective_ram_init(), RamInit::Fill(0xFF));
+        assert!(system.ram().iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn test_gif_recording_captures_frames() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        assert!(system.stop_gif_recording().is_none());
+
+        let path = std::env::temp_dir().join(format!("nes-core-clip-{}.gif", std::process::id()));
+        system.start_gif_recording_with(GifRecorder::new(&path, 2).with_frame_hashes());
+        system.run_frames(3).unwrap();
+        let recording = system.gif_recording().unwrap();
+        assert!(recording.is_full());
+        assert_eq!(recording.frames_seen(), 2);
+        assert_eq!(recording.frame_hashes().unwrap().len(), 2);
+
+        assert_eq!(system.stop_gif_recording(), Some(Ok(path.clone())));
+        assert!(system.gif_recording().is_none());
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+        let sidecar = crate::frame_hashes::sidecar_path(&path);
+        assert_eq!(crate::frame_hashes::FrameHashes::load(&sidecar).unwrap().frames[1].frame, 2);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&sidecar).unwrap();
+    }
+
+    #[test]
+    fn test_achievements_unlock_at_frame_end() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+
+        let pack = AchievementSet::parse("Started: 0x10 == 1\nNever: 0x10 == 2").unwrap();
+        system.set_achievements(Some(pack));
+        system.run_frames(1).unwrap();
+        assert!(system.take_unlocked_achievements().is_empty());
+
+        system.write_memory(0x0010, 1);
+        system.run_frames(2).unwrap();
+        assert_eq!(system.take_unlocked_achievements(), ["Started"]);
+        assert!(system.take_unlocked_achievements().is_empty());
+        assert_eq!(system.achievements().unwrap().unlocked_count(), 1);
+    }
+
+    #[test]
+    fn test_crash_detection() {
+        use crate::crash_detect::{CrashSignature, Vector, SILENT_PPU_FRAMES};
+
+        // All-NOP PRG: the vectors read $EAEA, the PC runs off $FFFF into
+        // cleared RAM and the PPU is never touched
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.set_ram_init(RamInit::Zeros);
+        system.power_cycle();
+        assert!(system.take_crash_diagnostics().is_empty());
+        system.run_frames(SILENT_PPU_FRAMES as u64).unwrap();
+        let signatures: Vec<_> = system.take_crash_diagnostics().into_iter().map(|d| d.signature).collect();
+        assert_eq!(signatures, [CrashSignature::ZeroedRamExecution, CrashSignature::PpuSilent(SILENT_PPU_FRAMES)]);
+
+        let mut prg = vec![0xEA; 16384];
+        prg[0x3FFC] = 0x00;
+        prg[0x3FFD] = 0x00;
+        system.load_simple_cartridge(SimpleCartridge::new(prg, vec![0x00; 8192]));
+        system.reset();
+        let signatures: Vec<_> = system.take_crash_diagnostics().into_iter().map(|d| d.signature).collect();
+        assert_eq!(signatures, [CrashSignature::NullVector(Vector::Reset)]);
+    }
+
+    #[test]
+    fn test_random_features_follow_seed() {
+        use crate::rng::RandomSource;
+
+        let run = |seed: u64| {
+            let mut system = NesSystem::new();
+            system.set_rng_seed(seed);
+            system.set_ram_init(RamInit::Random);
+            system.set_accuracy(AccuracyProfile {
+                ppu_alignment: crate::accuracy::PpuAlignment::Random,
+                ..AccuracyProfile::default()
+            });
+            system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+            let alignments: Vec<u8> = (0..8).map(|_| {
+                system.power_cycle();
+                system.ppu_alignment()
+            }).collect();
+            (system.ram().to_vec(), alignments, system.rng().clone())
+        };
+        assert_eq!(run(1234), run(1234));
+        assert_ne!(run(1234).0, run(4321).0);
+
+        let mut system = NesSystem::new();
+        system.set_rng_seed(5);
+        assert_eq!(system.rng_seed(), 5);
+        assert_eq!(system.rng_mut().next_u64(), Rng::new(5).next_u64());
+    }
+
+    #[test]
+    fn test_run_frame_until_pc() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0xC000;
+
+        assert!(system.run_frame_until(|s| s.cpu().registers().pc == 0xC010).unwrap());
+        assert_eq!(system.cpu().registers().pc, 0xC010);
+        assert_eq!(system.frame_count(), 0);
+        // Stopping before the first instruction leaves the system untouched
+        assert!(system.run_frame_until(|s| s.cpu().registers().pc == 0xC010).unwrap());
+        assert!(!system.run_frame_until(|_| false).unwrap());
+        assert_eq!(system.frame_count(), 1);
+    }
+
+    #[test]
+    fn test_state_hash_tracks_ram() {
+        let mut system = NesSystem::new();
+        let initial = system.state_hash();
+        assert_eq!(system.clone().state_hash(), initial);
+
+        system.write_memory(0x0010, 0x01);
+        assert_ne!(system.state_hash(), initial);
+    }
+
+    #[test]
+    fn test_ppu_alignment_offsets_ppu_at_reset() {
+        let mut system = NesSystem::new();
+        system.set_accuracy(AccuracyProfile {
+            ppu_alignment: PpuAlignment::Fixed(2),
+            ..AccuracyProfile::default()
+        });
+        system.reset();
+        assert_eq!(system.ppu_alignment(), 2);
+        assert_eq!(system.ppu().dot(), 2);
+    }
+
+    #[test]
+    fn test_ppu_warmup_ignores_setup_writes() {
+        // LDA #$80; loop: STA $2000; JMP loop
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..8].copy_from_slice(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x02, 0x80]);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0; 8192]));
+        system.set_accuracy(AccuracyProfile::accurate());
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+
+        for _ in 0..10 {
+            system.step().unwrap();
+        }
+        assert!(system.ppu_warming_up());
+        assert_eq!(system.ppu().control_value(), 0);
+
+        while system.ppu_warming_up() {
+            system.step().unwrap();
+        }
+        for _ in 0..4 {
+            system.step().unwrap();
+        }
+        assert_eq!(system.ppu().control_value(), 0x80);
+    }
+
+    #[test]
+    fn test_trace_records_recent_instructions() {
+        // NOP; NOP; JMP $8000
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..5].copy_from_slice(&[0xEA, 0xEA, 0x4C, 0x00, 0x80]);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system.set_trace_capacity(4);
+
+        for _ in 0..6 {
+            system.step().unwrap();
+        }
+        let pcs: Vec<u16> = system.trace().iter().map(|e| e.pc).collect();
+        assert_eq!(pcs, [0x8002, 0x8000, 0x8001, 0x8002]);
+        let last = system.trace().iter().last().unwrap();
+        assert_eq!(&last.bytes, &[0x4C, 0x00, 0x80]);
+        assert_eq!(last.len, 3);
+    }
+
+    #[test]
+    fn test_oam_dma_from_ram_page() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        for i in 0..0x100u16 {
+            system.write_memory(0x0200 + i, i as u8);
+        }
+        system.trigger_oam_dma(0x02);
+        assert_eq!(system.ppu().oam()[0], 0x00);
+        assert_eq!(system.ppu().oam()[0xFF], 0xFF);
+
+        // A non-zero OAMADDR rotates where the page lands
+        system.ppu_mut().write(0x2003, 0x10);
+        system.trigger_oam_dma(0x02);
+        assert_eq!(system.ppu().oam()[0x10], 0x00);
+        assert_eq!(system.ppu().oam()[0x0F], 0xFF);
+    }
+
+    #[test]
+    fn test_oam_dma_write_halts_cpu() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        for i in 0..0x100u16 {
+            system.write_memory(0x0200 + i, !i as u8);
+        }
+        // STA $4014 with A = $02
+        for (i, byte) in [0x8D, 0x14, 0x40].into_iter().enumerate() {
+            system.write_memory(0x0300 + i as u16, byte);
+        }
+        for start_odd in [false, true] {
+            system.cpu_mut().registers_mut().pc = 0x0300;
+            system.cpu_mut().registers_mut().a = 0x02;
+            if (system.cpu().total_cycles() + 4) % 2 != start_odd as u64 {
+                system.cpu_mut().stall(1);
+            }
+            let before = system.cpu().total_cycles();
+            system.step().unwrap();
+            assert_eq!(system.cpu().total_cycles() - before, 4 + OAM_DMA_CYCLES as u64 + start_odd as u64);
+            assert_eq!(system.ppu().oam()[0], 0xFF);
+            assert_eq!(system.ppu().oam()[0xFF], 0x00);
+        }
+    }
+
+    /// Controller reports read with `LDA $4016` while a DMC sample loops at
+    /// the fastest rate, the first read delayed by `phase` 3-cycle loads
+    fn reports_during_dmc(glitch: bool, phase: u32, buttons: u8) -> Vec<u8> {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.set_accuracy(AccuracyProfile { dmc_controller_glitch: glitch, ..AccuracyProfile::default() });
+        system.reset();
+        system.set_buttons(0, Buttons::new(buttons));
+        // $0300: LDA $4016, $0303: LDA $00
+        for (i, byte) in [0xAD, 0x16, 0x40, 0xA5, 0x00].into_iter().enumerate() {
+            system.write_memory(0x0300 + i as u16, byte);
+        }
+        for (address, value) in [(0x4010, 0x4F), (0x4013, 0x00), (0x4015, 0x10)] {
+            system.write_memory(address, value);
+        }
+        for _ in 0..phase {
+            system.cpu_mut().registers_mut().pc = 0x0303;
+            system.step().unwrap();
+        }
+
+        let mut reports = Vec::new();
+        for _ in 0..300 {
+            system.write_memory(0x4016, 1);
+            system.write_memory(0x4016, 0);
+            let mut report = 0;
+            for bit in 0..8 {
+                system.cpu_mut().registers_mut().pc = 0x0300;
+                system.step().unwrap();
+                report |= (system.cpu().registers().a & 1) << bit;
+            }
+            reports.push(report);
+        }
+        reports
+    }
+
+    #[test]
+    fn test_dmc_controller_glitch() {
+        let buttons = Buttons::A | Buttons::SELECT | Buttons::UP | Buttons::LEFT;
+        for phase in 0..4 {
+            assert!(reports_during_dmc(false, phase, buttons).iter().all(|&report| report == buttons));
+        }
+        // A 3-cycle halt puts the fetches out of step with the 4-cycle reads,
+        // so they drift onto the read cycle now and then in every phase
+        for phase in 0..4 {
+            let glitched = reports_during_dmc(true, phase, buttons).iter().filter(|&&r| r != buttons).count();
+            assert!((1..30).contains(&glitched), "phase {}: {} glitched reports", phase, glitched);
+        }
+
+        // Workaround: reading until two reports in a row agree always gets the right buttons
+        for phase in 0..4 {
+            let reports = reports_during_dmc(true, phase, buttons);
+            for pair in reports.windows(2).filter(|pair| pair[0] == pair[1]) {
+                assert_eq!(pair[0], buttons);
+            }
+        }
+    }
+
+    /// Cycles the DMC fetches halted the CPU for, running the instruction at
+    /// $0300 (taking `cycles`) over and over while a sample loops at the
+    /// fastest rate, the CPU first stalled for `phase` cycles
+    fn dmc_stalls(instruction: &[u8], cycles: u64, phase: u32) -> Vec<u64> {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().stall(phase);
+        for (i, &byte) in instruction.iter().enumerate() {
+            system.write_memory(0x0300 + i as u16, byte);
+        }
+        for (address, value) in [(0x4010, 0x4F), (0x4013, 0x00), (0x4015, 0x10)] {
+            system.write_memory(address, value);
+        }
+        (0..3000)
+            .filter_map(|_| {
+                system.cpu_mut().registers_mut().pc = 0x0300;
+                let before = system.cpu().total_cycles();
+                system.step().unwrap();
+                Some(system.cpu().total_cycles() - before - cycles).filter(|&stall| stall > 0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dmc_fetch_stall_depends_on_halt_cycle() {
+        // Reads only (LDA $00): 3 or 4 cycles, depending on alignment
+        let stalls: Vec<u64> = (0..2).flat_map(|phase| dmc_stalls(&[0xA5, 0x00], 3, phase)).collect();
+        assert!(stalls.contains(&3) && stalls.contains(&4), "{:?}", stalls);
+        assert!(stalls.iter().all(|stall| (3..=4).contains(stall)));
+
+        // INC $00 writes on its last two cycles, which the halt overlaps
+        let stalls: Vec<u64> = (0..2).flat_map(|phase| dmc_stalls(&[0xE6, 0x00], 5, phase)).collect();
+        assert!(stalls.contains(&1) && stalls.contains(&2), "{:?}", stalls);
+        assert!(stalls.iter().all(|stall| (1..=4).contains(stall)));
+    }
+
+    /// System running a NOP sled in RAM at $0200 with NMI enabled; the NMI
+    /// handler is at $9000 and the IRQ/BRK handler at $A000 (both NOPs)
+    fn nmi_test_system() -> NesSystem {
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[0x3FFA..0x3FFC].copy_from_slice(&[0x00, 0x90]);
+        prg_rom[0x3FFE..].copy_from_slice(&[0x00, 0xA0]);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0; 8192]));
+        system.reset();
+        for address in 0x0200..0x0800 {
+            system.write_memory(address, 0xEA);
+        }
+        system.write_memory(0x2000, 0x80);
+        system.cpu_mut().registers_mut().pc = 0x0200;
+        system
+    }
+
+    /// Step NOPs (2 cycles each) until `done`, looping over the sled
+    fn run_nops_until(system: &mut NesSystem, mut done: impl FnMut(&NesSystem) -> bool) {
+        while !done(system) {
+            if system.cpu().registers().pc >= 0x0700 {
+                system.cpu_mut().registers_mut().pc = 0x0200;
+            }
+            system.step().unwrap();
+        }
+    }
+
+    /// Step NOPs until the PPU is on scanline 240 at or past `dot`, then run a BRK
+    fn run_brk_before_vblank(system: &mut NesSystem, dot: u16) {
+        run_nops_until(system, |s| s.ppu().scanline() == 240 && s.ppu().dot() >= dot);
+        let pc = system.cpu().registers().pc;
+        system.write_memory(pc, 0x00);
+        system.step().unwrap();
+    }
+
+    #[test]
+    fn test_frame_runs_one_frame_of_cycles() {
+        // JMP $C000, with an NMI handler at $C010 counting into $10: INC $10; RTI
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..3].copy_from_slice(&[0x4C, 0x00, 0xC0]);
+        prg_rom[0x10..0x13].copy_from_slice(&[0xE6, 0x10, 0x40]);
+        prg_rom[0x3FFA..0x3FFC].copy_from_slice(&[0x10, 0xC0]);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0xC000;
+        system.write_memory(0x2000, 0x80);
+        system.set_audio_enabled(true);
+        system.set_sample_rate(44_100);
+        system.run_frames(1).unwrap();
+        system.take_audio_samples();
+
+        for frame in 2..=6u8 {
+            let cycles = system.cpu().total_cycles();
+            system.run_frames(1).unwrap();
+            assert_eq!(system.read_memory(0x0010), frame, "one NMI per frame");
+            assert!((29_780..29_790).contains(&(system.cpu().total_cycles() - cycles)), "{}", system.cpu().total_cycles() - cycles);
+            let samples = system.take_audio_samples().len();
+            // 44.1 kHz at ~60.1 frames per second
+            assert!((733..=735).contains(&samples), "{} samples", samples);
+        }
+    }
+
+    #[test]
+    fn test_cpu_palette_writes_color_the_framebuffer() {
+        // LDA #$3F; STA $2006; LDA #$10; STA $2006; LDA #$16; STA $2007; JMP *
+        let program = [0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x10, 0x8D, 0x06, 0x20, 0xA9, 0x16, 0x8D, 0x07, 0x20, 0x4C, 0x0F, 0xC0];
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0xC000;
+        system.set_framebuffer_enabled(true);
+        system.run_frames(2).unwrap();
+
+        // $3F10 mirrors the backdrop at $3F00, which fills the screen with rendering off
+        assert_eq!(system.ppu().palette_color(0x00), 0x16);
+        let (r, g, b) = crate::ppu::palette_rgb(0x16);
+        assert!(system.framebuffer().chunks(3).all(|px| px == [r, g, b]));
+    }
+
+    /// CLI (or SEI) then JMP to itself, with an IRQ handler at $C010 that
+    /// acknowledges the frame IRQ and counts into $10: LDA $4015; INC $10; RTI
+    fn irq_test_system(flag: u8) -> NesSystem {
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..4].copy_from_slice(&[flag, 0x4C, 0x01, 0xC0]);
+        prg_rom[0x10..0x16].copy_from_slice(&[0xAD, 0x15, 0x40, 0xE6, 0x10, 0x40]);
+        prg_rom[0x3FFE..].copy_from_slice(&[0x10, 0xC0]);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0xC000;
+        system
+    }
+
+    #[test]
+    fn test_frame_irq_serviced_once_per_sequence() {
+        let mut system = irq_test_system(0x58);
+        system.run_frames(10).unwrap();
+        // The 4-step sequence (29830 cycles) is a few cycles longer than a frame
+        assert!((9..=10).contains(&system.read_memory(0x0010)), "{}", system.read_memory(0x0010));
+        assert!(!system.interrupts().line());
+
+        // $4017 bit 6 inhibits it
+        let count = system.read_memory(0x0010);
+        system.write_memory(0x4017, 0x40);
+        system.run_frames(3).unwrap();
+        assert_eq!(system.read_memory(0x0010), count);
+    }
+
+    #[test]
+    fn test_frame_irq_held_until_4015_read() {
+        let mut system = irq_test_system(0x78);
+        system.run_frames(2).unwrap();
+        assert_eq!(system.read_memory(0x0010), 0);
+        assert_eq!(system.interrupts().pending(), [IrqSource::ApuFrame]);
+        assert_eq!(system.read_memory(0x4015) & 0xC0, 0x40);
+        system.step().unwrap();
+        assert!(system.interrupts().pending().is_empty());
+        assert_eq!(system.read_memory(0x4015) & 0xC0, 0x00);
+    }
+
+    #[test]
+    fn test_dmc_irq_at_sample_end() {
+        let mut system = irq_test_system(0x78);
+        system.write_memory(0x4017, 0x40);
+        // One-byte sample at the fastest rate with IRQs enabled
+        system.write_memory(0x4010, 0x8F);
+        system.write_memory(0x4012, 0x00);
+        system.write_memory(0x4013, 0x00);
+        system.write_memory(0x4015, 0x10);
+        system.run_frames(1).unwrap();
+        assert_eq!(system.interrupts().pending(), [IrqSource::Dmc]);
+        // Reading $4015 leaves it; writing $4015 releases it
+        assert_eq!(system.read_memory(0x4015) & 0x90, 0x80);
+        assert_eq!(system.read_memory(0x4015) & 0x80, 0x80);
+        system.write_memory(0x4015, 0x00);
+        assert!(system.interrupts().pending().is_empty());
+
+        // So do acknowledging the source and clearing bit 7 of $4010
+        system.write_memory(0x4015, 0x10);
+        system.run_frames(1).unwrap();
+        assert!(system.acknowledge_irq(IrqSource::Dmc));
+        assert!(!system.interrupts().line());
+        system.write_memory(0x4015, 0x10);
+        system.run_frames(1).unwrap();
+        assert!(system.interrupts().is_pending(IrqSource::Dmc));
+        system.write_memory(0x4010, 0x0F);
+        assert!(!system.interrupts().line());
+    }
+
+    #[test]
+    fn test_nmi_serviced_at_vblank() {
+        let mut system = nmi_test_system();
+        run_nops_until(&mut system, |s| s.ppu().in_vblank());
+        // Serviced after the NOP that raised it, or one more if raised on its last cycle
+        for _ in 0..2 {
+            system.step().unwrap();
+            if system.cpu().registers().pc == 0x9000 {
+                break;
+            }
+        }
+        assert_eq!(system.cpu().registers().pc, 0x9000);
+        assert!(!system.cpu().nmi_pending());
+        let sp = system.cpu().registers().sp as u16;
+        assert_eq!(system.read_memory(0x0101 + sp) & 0x10, 0, "B flag clear");
+    }
+
+    #[test]
+    fn test_region_sets_raster_timing() {
+        let raster = |system: &NesSystem| (system.ppu().scanline() as i64 + 1) * 341 + system.ppu().dot() as i64;
+        for (region, dots) in [(Region::Ntsc, 600), (Region::Pal, 640), (Region::Dendy, 600)] {
+            let mut system = nmi_test_system();
+            system.write_memory(0x2000, 0x00);
+            system.set_region(region);
+
+            // 100 NOPs are 200 CPU cycles
+            let start = raster(&system);
+            for _ in 0..100 {
+                system.step().unwrap();
+            }
+            assert_eq!(raster(&system) - start, dots, "{}", region);
+
+            run_nops_until(&mut system, |s| s.ppu().in_vblank());
+            assert_eq!(system.ppu().scanline(), region.vblank_scanline(), "{}", region);
+            let mut last = 0;
+            run_nops_until(&mut system, |s| {
+                last = last.max(s.ppu().scanline());
+                s.ppu().scanline() == -1
+            });
+            // The pre-render line (-1) is the frame's last
+            assert_eq!(last, region.scanlines() as i16 - 2, "{}", region);
+        }
+    }
+
+    #[test]
+    fn test_region_from_nes2_header() {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x00, 0x08];
+        rom.resize(16, 0);
+        rom[12] = 0x01;
+        rom.resize(16 + 16384 + 8192, 0xEA);
+        let mut system = NesSystem::new();
+        system.load_rom(&rom).unwrap();
+        assert_eq!(system.region(), Region::Pal);
+        assert_eq!(system.apu().region(), Region::Pal);
+
+        // Overridden after loading
+        system.set_region(Region::Dendy);
+        assert_eq!(system.ppu().region(), Region::Dendy);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        // NMI raised within BRK's first four cycles: BRK pushes its state but
+        // jumps through the NMI vector, and no second NMI follows
+        let mut system = nmi_test_system();
+        run_brk_before_vblank(&mut system, 329);
+        assert_eq!(system.cpu().registers().pc, 0x9000);
+        assert!(!system.cpu().nmi_pending());
+        let sp = system.cpu().registers().sp as u16;
+        assert_ne!(system.read_memory(0x0101 + sp) & 0x10, 0, "B flag pushed");
+
+        system.step().unwrap();
+        assert_eq!(system.cpu().registers().pc, 0x9001);
+    }
+
+    #[test]
+    fn test_late_nmi_after_brk_waits_one_instruction() {
+        let mut system = nmi_test_system();
+        run_brk_before_vblank(&mut system, 320);
+        assert_eq!(system.cpu().registers().pc, 0xA000);
+
+        // The first handler instruction runs before the NMI is taken
+        system.step().unwrap();
+        assert_eq!(system.cpu().registers().pc, 0xA001);
+        system.step().unwrap();
+        assert_eq!(system.cpu().registers().pc, 0x9000);
+    }
+
+    #[test]
+    fn test_input_polls_latched_per_frame() {
+        // LDA $4016; JMP $8000
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..6].copy_from_slice(&[0xAD, 0x16, 0x40, 0x4C, 0x00, 0x80]);
+        let chr_rom = vec![0x00; 8192];
+
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, chr_rom));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        assert_eq!(system.input_polls_last_frame(), 0);
+
+        system.run_frames(1).unwrap();
+        let polls = system.input_polls_last_frame();
+        assert!(polls > 0);
+
+        // Frames are whole instructions, so a 7-cycle loop polls one more or less
+        system.run_frames(1).unwrap();
+        assert!(system.input_polls_last_frame().abs_diff(polls) <= 1);
+    }
+
+    #[test]
+    fn test_cheats_patch_prg_and_freeze_ram() {
+        // LDA $8100; STA $10; JMP $C000
+        let mut prg = vec![0xEA; 16384];
+        prg[..8].copy_from_slice(&[0xAD, 0x00, 0x81, 0x85, 0x10, 0x4C, 0x00, 0xC0]);
+        prg[0x100] = 0x11;
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0xC000;
+
+        let run = |system: &mut NesSystem, cheats: &[&str]| {
+            system.clear_cheats();
+            for code in cheats {
+                system.add_cheat(Cheat::decode(CheatFormat::Raw, code).unwrap());
+            }
+            system.run_frames(1).unwrap();
+            (system.read_memory(0x10), system.read_memory(0x20))
+        };
+        assert_eq!(run(&mut system, &[]).0, 0x11);
+        assert_eq!(run(&mut system, &["8100?11:42", "0020:07"]), (0x42, 0x07));
+        // The compare byte and the bank (the ROM byte is in bank 0) must both match
+        assert_eq!(run(&mut system, &["8100?12:42"]).0, 0x11);
+        assert_eq!(run(&mut system, &["8100:42@1"]).0, 0x11);
+        assert_eq!(run(&mut system, &["8100:42@0"]).0, 0x42);
+        let cheat = Cheat::decode(CheatFormat::GameGenie, "SXIOPO").unwrap();
+        system.add_cheat(cheat);
+        assert_eq!(system.cheats().last(), Some(&cheat));
+    }
+
+    #[test]
+    fn test_input_echo_latched_per_frame() {
+        // Strobe, then read port 1 three times and port 2 once each frame
+        // LDA #$01; STA $4016; LDA #$00; STA $4016; LDA $4016 (x3); LDA $4017; loop forever
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..25].copy_from_slice(&[
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0xAD, 0x16, 0x40, 0xAD,
+            0x16, 0x40, 0xAD, 0x17, 0x40, 0x4C, 0x16, 0x80,
+        ]);
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system.set_buttons(0, Buttons::new(Buttons::B | Buttons::SELECT));
+        system.set_buttons(1, Buttons::new(Buttons::A));
+
+        system.run_frames(1).unwrap();
+        let echo = system.input_echo_last_frame();
+        assert_eq!(echo.reads(0), [0x40, 0x41, 0x41]);
+        assert_eq!(echo.bits(0), [0, 1, 1]);
+        assert_eq!(echo.reads(1), [0x41]);
+        assert!(echo.reads(2).is_empty());
+
+        // The loop doesn't read again, so the next frame echoes nothing
+        system.run_frames(1).unwrap();
+        assert!(system.input_echo_last_frame().is_empty());
+    }
+
+    #[test]
+    fn test_sprite_stats_per_frame() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        system.run_frames(1).unwrap();
+        assert!(system.sprite_stats().is_none());
+
+        // Power-on OAM is all zeros: all 64 sprites on lines 1-8
+        system.set_sprite_stats_enabled(true);
+        system.run_frames(1).unwrap();
+        let stats = system.sprite_stats().unwrap();
+        assert_eq!((stats.max_per_line, stats.overflow_lines, stats.dropped), (64, 8, 8 * 56));
+        assert_eq!((stats.histogram[0], stats.histogram[64]), (232, 8));
+    }
+
+    #[test]
+    fn test_zapper_replaces_port_two() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        system.set_buttons(1, Buttons::new(Buttons::A));
+        system.set_zapper(Some(Zapper::new()));
+
+        // No light, trigger released
+        assert_eq!(system.read_memory(0x4017), 0x48);
+        system.zapper_mut().unwrap().set_trigger(true);
+        assert_eq!(system.read_memory(0x4017), 0x58);
+
+        system.set_zapper(None);
+        assert!(system.zapper().is_none());
+    }
+
+    #[test]
+    fn test_vaus_replaces_port_two() {
+        let mut system = NesSystem::new();
+        system.load_simple_cartridge(SimpleCartridge::new(vec![0xEA; 16384], vec![0x00; 8192]));
+        system.reset();
+        system.set_zapper(Some(Zapper::new()));
+        system.set_vaus(Some(Vaus::new()));
+        assert!(system.zapper().is_none());
+
+        let vaus = system.vaus_mut().unwrap();
+        vaus.set_position(0x80);
+        vaus.set_fire(true);
+        system.write_memory(0x4016, 1);
+        system.write_memory(0x4016, 0);
+        // MSB set: data bit clear; fire held
+        assert_eq!(system.read_memory(0x4017), 0x48);
+        // Next bit clear: data bit set
+        assert_eq!(system.read_memory(0x4017), 0x58);
+    }
+
+    #[test]
+    fn test_health_reports_jam_and_black_screen() {
+        let mut system = NesSystem::new();
+        assert_eq!(system.health().status(), HealthStatus::Error);
+
+        // JMP $8000 with rendering off
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+        system.load_simple_cartridge(SimpleCartridge::new(prg_rom, vec![0x00; 8192]));
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system.run_frames(HEALTH_WINDOW_FRAMES as u64).unwrap();
+        let health = system.health();
+        assert!(health.rom_loaded && health.window_full());
+        assert!(!health.rendering_enabled && !health.audio_active);
+        assert_eq!(health.status(), HealthStatus::Warning);
+
+        // A KIL opcode jams the CPU until reset
+        system.write_memory(0x0000, 0x02);
+        system.cpu_mut().registers_mut().pc = 0x0000;
+        assert!(system.run_frames(1).is_err());
+        assert_eq!(system.health().cpu_jammed, Some((0x0000, 0x02)));
+        system.reset();
+        assert_eq!(system.health().cpu_jammed, None);
+    }
+
+    /// iNES image running `INC $10; LDA $10; STA $2006; JMP $8000`
+    fn counter_rom(chr_fill: u8) -> Vec<u8> {
+        let mut rom = b"NES\x1A\x01\x01".to_vec();
+        rom.resize(16, 0);
+        let mut prg = vec![0xEA; 16384];
+        prg[..10].copy_from_slice(&[0xE6, 0x10, 0xA5, 0x10, 0x8D, 0x06, 0x20, 0x4C, 0x00, 0x80]);
+        rom.extend(prg);
+        rom.extend(vec![chr_fill; 8192]);
+        rom
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let mut system = NesSystem::new();
+        system.load_rom(&counter_rom(0)).unwrap();
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system.run_frames(3).unwrap();
+        let state = system.save_state();
+
+        system.run_frames(5).unwrap();
+        let expected = (system.state_hash(), system.cpu().total_cycles(), system.ppu().scanline());
+
+        // Restoring rewinds the machine, in the same system or a fresh one
+        system.load_state(&state).unwrap();
+        assert_eq!(system.frame_count(), 3);
+        system.run_frames(5).unwrap();
+        assert_eq!((system.state_hash(), system.cpu().total_cycles(), system.ppu().scanline()), expected);
+
+        let mut fresh = NesSystem::new();
+        fresh.load_rom(&counter_rom(0)).unwrap();
+        fresh.load_state(&state).unwrap();
+        fresh.run_frames(5).unwrap();
+        assert_eq!(fresh.state_hash(), expected.0);
+    }
+
+    #[test]
+    fn test_save_state_keeps_region() {
+        let mut system = NesSystem::new();
+        system.load_rom(&counter_rom(0)).unwrap();
+        system.set_region(Region::Pal);
+        system.reset();
+        // Stop on a line only PAL frames have
+        system.run_frame_until(|s| s.ppu().scanline() == 300).unwrap();
+        let state = system.save_state();
+
+        let mut fresh = NesSystem::new();
+        fresh.load_rom(&counter_rom(0)).unwrap();
+        fresh.load_state(&state).unwrap();
+        assert_eq!((fresh.region(), fresh.ppu().region(), fresh.apu().region()), (Region::Pal, Region::Pal, Region::Pal));
+        assert_eq!(fresh.ppu().scanline(), 300);
+    }
+
+    #[test]
+    fn test_mapper_mirroring_reaches_ppu_and_savestates() {
+        // AxROM (mapper 7), 32KB of NOPs
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 0, 0x70, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0xEA; 32768]);
+        let mut system = NesSystem::new();
+        system.load_rom(&rom).unwrap();
+        system.reset();
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenA);
+
+        // The switch takes effect on the next step
+        system.write_memory(0x8000, 0x10);
+        system.step().unwrap();
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenB);
+        let state = system.save_state();
+        system.write_memory(0x8000, 0x00);
+        system.step().unwrap();
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenA);
+        system.load_state(&state).unwrap();
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenB);
+
+        // Boards without a mirroring register use the header's
+        system.load_rom(&counter_rom(0)).unwrap();
+        assert_eq!(system.ppu().mirroring(), Mirroring::Horizontal);
+        let mut vertical = counter_rom(0);
+        vertical[6] |= 0x01;
+        system.load_rom(&vertical).unwrap();
+        assert_eq!(system.ppu().mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_reset_component() {
+        // AxROM (mapper 7), 32KB of NOPs
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 0, 0x70, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0xEA; 32768]);
+        let mut system = NesSystem::new();
+        system.load_rom(&rom).unwrap();
+        system.reset();
+        system.run_frames(1).unwrap();
+        system.write_memory(0x0010, 0x42);
+        system.write_memory(0x2000, 0x80);
+        system.write_memory(0x8000, 0x11);
+        system.step().unwrap();
+        assert!(system.ppu().control().nmi_enable());
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenB);
+
+        // Only the PPU starts over; its old register values aren't written back
+        let pc = system.cpu().registers().pc;
+        system.reset_component(Component::Ppu);
+        assert_eq!((system.ppu().scanline(), system.ppu().dot()), (-1, 0));
+        system.step().unwrap();
+        assert!(!system.ppu().control().nmi_enable());
+        assert_ne!(system.cpu().registers().pc, 0xFFFC);
+        assert_eq!(system.frame_count(), 1);
+        assert_eq!(system.ram()[0x10], 0x42);
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenB);
+
+        system.reset_component(Component::Mapper);
+        assert_eq!(system.ppu().mirroring(), Mirroring::SingleScreenA);
+        assert_eq!(system.bus.cartridge().unwrap().mapper().registers()[0], ("prg".to_string(), 0));
+
+        system.reset_component(Component::Cpu);
+        assert_eq!(system.cpu().registers().pc, 0xFFFC);
+        assert_ne!(pc, 0xFFFC);
+        assert_eq!(system.ram()[0x10], 0x42);
+    }
+
+    #[test]
+    fn test_load_state_rejects_bad_data() {
+        let mut system = NesSystem::new();
+        system.load_rom(&counter_rom(0)).unwrap();
+        let state = system.save_state();
+        system.write_memory(0x0010, 0x42);
+        let hash = system.state_hash();
+
+        assert_eq!(system.load_state(b"nope"), Err(StateError::NotAState));
+        let mut future = state.clone();
+        future[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(system.load_state(&future), Err(StateError::UnsupportedVersion(STATE_VERSION + 1)));
+        assert_eq!(system.load_state(&state[..state.len() - 1]), Err(StateError::UnexpectedEnd));
+        let mut newer_ppu = state.clone();
+        let ppu_tag = newer_ppu.windows(4).position(|tag| tag == b"PPU ").unwrap();
+        newer_ppu[ppu_tag + 4] = 5;
+        assert_eq!(system.load_state(&newer_ppu).unwrap_err().to_string(), "PPU section v5 unsupported");
+        // A failed load leaves the system as it was
+        assert_eq!(system.state_hash(), hash);
+
+        let mut other = NesSystem::new();
+        other.load_rom(&counter_rom(1)).unwrap();
+        assert_eq!(other.load_state(&state), Err(StateError::WrongRom));
+    }
+
+    #[test]
+    fn test_run_until_idle() {
+        let mut system = NesSystem::new();
+        system.load_rom(&counter_rom(0)).unwrap();
+        system.reset();
+        system.cpu_mut().registers_mut().pc = 0x8000;
+        system.set_idle_detector(Some(IdleDetector::new(5)));
+        // The counter at $10 changes every frame
+        assert_eq!(system.run_until_idle(20).unwrap(), 20);
+        assert!(!system.is_idle());
+
+        // Spinning on JMP changes nothing
+        system.write_memory(0x0000, 0x4C);
+        system.write_memory(0x0001, 0x00);
+        system.write_memory(0x0002, 0x00);
+        system.cpu_mut().registers_mut().pc = 0x0000;
+        assert_eq!(system.run_until_idle(20).unwrap(), 6);
+        assert!(system.is_idle());
+        system.reset();
+        assert!(!system.is_idle());
+    }
+}
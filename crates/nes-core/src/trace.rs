@@ -0,0 +1,173 @@
+//! Instruction trace ring buffer
+//!
+//! The system records every executed instruction into a fixed-size ring, so the
+//! instructions leading up to a crash can be dumped after the fact without
+//! paying for always-on logging. Entries are small fixed-size records and are
+//! only formatted when dumped.
+
+use std::fmt;
+use std::io::{self, Write};
+
+/// Default number of instructions kept
+pub const DEFAULT_TRACE_CAPACITY: usize = 10_000;
+
+/// One executed instruction and the CPU state before it ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TraceEntry {
+    /// Address of the opcode
+    pub pc: u16,
+    /// Opcode and operand bytes (only the first `len` are meaningful)
+    pub bytes: [u8; 3],
+    /// Instruction length in bytes
+    pub len: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    /// Total CPU cycles before the instruction
+    pub cycles: u64,
+}
+
+impl fmt::Display for TraceEntry {
+    /// nestest-style line: `C000  4C F5 C5  A:00 X:00 Y:00 P:24 SP:FD CYC:7`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}  ", self.pc)?;
+        for i in 0..3 {
+            if i < self.len as usize {
+                write!(f, "{:02X} ", self.bytes[i])?;
+            } else {
+                write!(f, "   ")?;
+            }
+        }
+        write!(
+            f,
+            " A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.a, self.x, self.y, self.p, self.sp, self.cycles
+        )
+    }
+}
+
+/// Fixed-capacity ring of the most recent trace entries
+#[derive(Debug, Clone)]
+pub struct TraceRing {
+    entries: Vec<TraceEntry>,
+    capacity: usize,
+    /// Index where the next entry is written once the ring is full
+    next: usize,
+}
+
+impl TraceRing {
+    /// Create a ring holding up to `capacity` entries (0 disables recording)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            capacity,
+            next: 0,
+        }
+    }
+
+    /// Record an entry, overwriting the oldest when full
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() < self.capacity {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.next] = entry;
+            self.next = (self.next + 1) % self.capacity;
+        }
+    }
+
+    /// Check if recording is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Maximum number of entries kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of entries currently held
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no entries have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.next = 0;
+    }
+
+    /// Iterate over the entries from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &TraceEntry> {
+        let (newer, older) = self.entries.split_at(self.next);
+        older.iter().chain(newer.iter())
+    }
+
+    /// Write the entries, oldest first, one line each
+    pub fn dump(&self, out: &mut impl Write) -> io::Result<()> {
+        for entry in self.iter() {
+            writeln!(out, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for TraceRing {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pc: u16) -> TraceEntry {
+        TraceEntry {
+            pc,
+            ..TraceEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_ring_keeps_newest() {
+        let mut ring = TraceRing::new(3);
+        for pc in 0..5 {
+            ring.push(entry(pc));
+        }
+        let pcs: Vec<u16> = ring.iter().map(|e| e.pc).collect();
+        assert_eq!(pcs, [2, 3, 4]);
+
+        let mut disabled = TraceRing::new(0);
+        disabled.push(entry(1));
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn test_entry_format() {
+        let entry = TraceEntry {
+            pc: 0xC000,
+            bytes: [0x4C, 0xF5, 0xC5],
+            len: 3,
+            a: 0,
+            x: 0,
+            y: 0,
+            p: 0x24,
+            sp: 0xFD,
+            cycles: 7,
+        };
+        assert_eq!(entry.to_string(), "C000  4C F5 C5  A:00 X:00 Y:00 P:24 SP:FD CYC:7");
+
+        let short = TraceEntry { len: 1, bytes: [0xEA, 0, 0], ..entry };
+        assert_eq!(short.to_string(), "C000  EA        A:00 X:00 Y:00 P:24 SP:FD CYC:7");
+    }
+}
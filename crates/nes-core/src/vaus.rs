@@ -0,0 +1,135 @@
+//! Arkanoid controller ("Vaus" paddle, port 2)
+//!
+//! The paddle's knob turns a potentiometer whose position is converted to an
+//! 8-bit value. A $4016 strobe latches it into a shift register that $4017
+//! reads return one bit at a time, most significant first and inverted.
+//! The knob's travel covers roughly `POSITION_MIN` to `POSITION_MAX`; games
+//! calibrate to that range, so host input (a mouse or touch column) is
+//! mapped onto it with `aim_at`.
+//!
+//! $4017 reads: bit 4 is the inverted serial position data, bit 3 is 1
+//! while the fire button is held.
+
+use crate::controller::OPEN_BUS_BITS;
+
+/// Position reported with the knob turned fully left
+pub const POSITION_MIN: u8 = 0x62;
+/// Position reported with the knob turned fully right
+pub const POSITION_MAX: u8 = 0xF2;
+
+/// $4017 bit carrying the (inverted) position, MSB first
+const DATA_BIT: u8 = 0x10;
+/// $4017 bit set while the fire button is held
+const FIRE_BIT: u8 = 0x08;
+
+/// Arkanoid controller state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vaus {
+    /// Potentiometer value
+    position: u8,
+    fire: bool,
+    /// Position latched by the last strobe, shifted out MSB first
+    shift: u8,
+    strobe: bool,
+}
+
+impl Vaus {
+    /// Create a paddle with the knob centred and the button released
+    pub fn new() -> Self {
+        let centre = POSITION_MIN + (POSITION_MAX - POSITION_MIN) / 2;
+        Self { position: centre, fire: false, shift: 0, strobe: false }
+    }
+
+    /// Set the raw potentiometer value
+    pub fn set_position(&mut self, position: u8) {
+        self.position = position;
+    }
+
+    /// Get the raw potentiometer value
+    pub fn position(&self) -> u8 {
+        self.position
+    }
+
+    /// Turn the knob to match a screen column (0 = fully left, 255 = fully right)
+    pub fn aim_at(&mut self, x: u8) {
+        let travel = (POSITION_MAX - POSITION_MIN) as u16;
+        self.position = POSITION_MIN + (x as u16 * travel / 255) as u8;
+    }
+
+    /// Press or release the fire button
+    pub fn set_fire(&mut self, pressed: bool) {
+        self.fire = pressed;
+    }
+
+    /// Check if the fire button is held
+    pub fn fire(&self) -> bool {
+        self.fire
+    }
+
+    /// Handle a $4016 write: bit 0 high latches the position
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.position;
+        }
+    }
+
+    /// Value returned by a $4017 read, shifting out the next position bit
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.shift = self.position;
+        }
+        let mut value = OPEN_BUS_BITS;
+        if self.shift & 0x80 == 0 {
+            value |= DATA_BIT;
+        }
+        if self.fire {
+            value |= FIRE_BIT;
+        }
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+        value
+    }
+}
+
+impl Default for Vaus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strobe and read the 8 position bits the way Arkanoid does
+    fn read_position(vaus: &mut Vaus) -> u8 {
+        vaus.write_strobe(1);
+        vaus.write_strobe(0);
+        (0..8).fold(0, |value, _| value << 1 | (vaus.read() & DATA_BIT == 0) as u8)
+    }
+
+    #[test]
+    fn test_position_shifts_out_inverted_msb_first() {
+        let mut vaus = Vaus::new();
+        vaus.set_position(0xA5);
+        assert_eq!(read_position(&mut vaus), 0xA5);
+        // Past the eighth bit the register is empty (reads as all ones)
+        assert_eq!(vaus.read() & DATA_BIT, DATA_BIT);
+
+        vaus.set_fire(true);
+        assert_eq!(vaus.read() & FIRE_BIT, FIRE_BIT);
+    }
+
+    #[test]
+    fn test_aim_covers_knob_travel() {
+        let mut vaus = Vaus::new();
+        vaus.aim_at(0);
+        assert_eq!(vaus.position(), POSITION_MIN);
+        vaus.aim_at(255);
+        assert_eq!(vaus.position(), POSITION_MAX);
+        vaus.aim_at(128);
+        assert!((POSITION_MIN..POSITION_MAX).contains(&vaus.position()));
+    }
+}
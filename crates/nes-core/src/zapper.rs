@@ -0,0 +1,188 @@
+//! Zapper light gun (port 2)
+//!
+//! The Zapper's photodiode sees the CRT beam, not the whole frame: it reports
+//! light only after the beam has drawn a bright pixel under the gun, and the
+//! phosphor and sensor circuit keep it lit for roughly 10-25 scanlines
+//! afterwards. Games such as Duck Hunt rely on that: they blank the screen,
+//! draw white boxes over the targets, and poll $4017 while the frame is drawn.
+//!
+//! The system calls `begin_scanline` as the PPU advances. When the beam
+//! reaches the aimed row, that scanline is rendered from the current PPU
+//! state and the brightness around the aimed pixel is measured; light is then
+//! reported for `PERSISTENCE_SCANLINES` lines. A dark pixel, an off-screen aim
+//! or the next frame clears it.
+//!
+//! $4017 reads: bit 3 is 0 while light is sensed, bit 4 is 1 while the trigger
+//! is pulled.
+
+use crate::controller::OPEN_BUS_BITS;
+
+/// Scanlines the sensor stays lit after the beam passes a bright pixel
+pub const PERSISTENCE_SCANLINES: i16 = 20;
+/// Minimum brightness (luma, 0-255) the sensor responds to; white and the
+/// brightest pastels pass, mid blues and greens don't
+pub const LIGHT_THRESHOLD: u8 = 0xC0;
+/// Pixels either side of the aimed pixel included in the measurement
+const SENSE_RADIUS: usize = 1;
+
+/// $4017 bit set while no light is sensed
+const LIGHT_BIT: u8 = 0x08;
+/// $4017 bit set while the trigger is pulled
+const TRIGGER_BIT: u8 = 0x10;
+
+/// Zapper state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zapper {
+    /// Screen pixel the gun points at (None when aimed away from the screen)
+    aim: Option<(u8, u8)>,
+    trigger: bool,
+    /// Scanline the PPU was on at the last update
+    scanline: i16,
+    /// Row where the beam last drew a bright pixel under the gun this frame
+    lit_line: Option<i16>,
+}
+
+impl Zapper {
+    /// Create a Zapper aimed away from the screen with the trigger released
+    pub fn new() -> Self {
+        Self { aim: None, trigger: false, scanline: -1, lit_line: None }
+    }
+
+    /// Aim at a screen pixel (x 0-255, y 0-239), or away from the screen with None
+    pub fn set_aim(&mut self, aim: Option<(u8, u8)>) {
+        self.aim = aim.filter(|&(_, y)| (y as usize) < crate::ppu::FRAME_HEIGHT);
+    }
+
+    /// Get the aimed pixel
+    pub fn aim(&self) -> Option<(u8, u8)> {
+        self.aim
+    }
+
+    /// Pull or release the trigger
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    /// Check if the trigger is pulled
+    pub fn trigger(&self) -> bool {
+        self.trigger
+    }
+
+    /// Follow the PPU onto `scanline`; `render_line` renders a visible row as RGB
+    pub fn begin_scanline(&mut self, scanline: i16, render_line: impl FnOnce(usize) -> Vec<u8>) {
+        if scanline == self.scanline {
+            return;
+        }
+        self.scanline = scanline;
+        if scanline < 0 {
+            // Pre-render line: a new frame starts dark
+            self.lit_line = None;
+            return;
+        }
+        match self.aim {
+            Some((x, y)) if y as i16 == scanline => {
+                let row = render_line(y as usize);
+                self.lit_line = (brightness(&row, x as usize) >= LIGHT_THRESHOLD).then_some(scanline);
+            }
+            None => self.lit_line = None,
+            _ => {}
+        }
+    }
+
+    /// Check if the sensor currently reports light
+    pub fn light_sensed(&self) -> bool {
+        self.lit_line.is_some_and(|line| (line..line + PERSISTENCE_SCANLINES).contains(&self.scanline))
+    }
+
+    /// Value returned by a $4017 read
+    pub fn read(&self) -> u8 {
+        let mut value = OPEN_BUS_BITS;
+        if !self.light_sensed() {
+            value |= LIGHT_BIT;
+        }
+        if self.trigger {
+            value |= TRIGGER_BIT;
+        }
+        value
+    }
+}
+
+impl Default for Zapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Average luma of the pixels around `x` in an RGB row
+fn brightness(row: &[u8], x: usize) -> u8 {
+    let width = row.len() / 3;
+    if width == 0 {
+        return 0;
+    }
+    let start = x.saturating_sub(SENSE_RADIUS).min(width - 1);
+    let end = (x + SENSE_RADIUS).min(width - 1);
+    let total: u32 = (start..=end)
+        .map(|i| {
+            let (r, g, b) = (row[i * 3] as u32, row[i * 3 + 1] as u32, row[i * 3 + 2] as u32);
+            (r * 299 + g * 587 + b * 114) / 1000
+        })
+        .sum();
+    (total / (end - start + 1) as u32) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::FRAME_WIDTH;
+
+    fn row(rgb: [u8; 3]) -> Vec<u8> {
+        rgb.repeat(FRAME_WIDTH)
+    }
+
+    /// Run a frame's scanlines, returning the ones where light was sensed
+    fn lit_scanlines(zapper: &mut Zapper, rgb: [u8; 3]) -> Vec<i16> {
+        (-1..261)
+            .filter(|&line| {
+                zapper.begin_scanline(line, |_| row(rgb));
+                zapper.light_sensed()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_light_persists_after_beam_passes() {
+        let mut zapper = Zapper::new();
+        zapper.set_aim(Some((128, 100)));
+        let lit = lit_scanlines(&mut zapper, [236, 238, 236]);
+        assert_eq!(lit, (100..100 + PERSISTENCE_SCANLINES).collect::<Vec<_>>());
+
+        // $4017 reports light as a cleared bit 3, only inside the window
+        zapper.begin_scanline(-1, |_| unreachable!());
+        assert_eq!(zapper.read() & LIGHT_BIT, LIGHT_BIT);
+        zapper.begin_scanline(100, |_| row([255, 255, 255]));
+        zapper.set_trigger(true);
+        assert_eq!(zapper.read(), OPEN_BUS_BITS | TRIGGER_BIT);
+    }
+
+    #[test]
+    fn test_dark_pixels_and_off_screen_aim() {
+        let mut zapper = Zapper::new();
+        zapper.set_aim(Some((10, 50)));
+        // NES light blue is not bright enough
+        assert!(lit_scanlines(&mut zapper, [76, 154, 236]).is_empty());
+
+        zapper.set_aim(None);
+        assert!(lit_scanlines(&mut zapper, [255, 255, 255]).is_empty());
+        zapper.set_aim(Some((0, 240)));
+        assert_eq!(zapper.aim(), None);
+    }
+
+    #[test]
+    fn test_brightness_averages_neighbours() {
+        let mut line = row([0, 0, 0]);
+        line[3..6].copy_from_slice(&[255, 255, 255]);
+        assert_eq!(brightness(&line, 1), 85);
+        assert_eq!(brightness(&line, 0), 127);
+        assert_eq!(brightness(&line, FRAME_WIDTH - 1), 0);
+    }
+}
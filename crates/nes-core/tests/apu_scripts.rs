@@ -0,0 +1,49 @@
+//! Golden output for the APU register scripts in `tests/apu/`
+//!
+//! Each `<name>.script` is run with `apu_script` and its channel levels are
+//! compared with `<name>.golden`. After an intended change to a channel, run
+//! with `NES_BLESS=1` to rewrite the golden files and review their diff: only
+//! the changed channel's column should move.
+//!
+//! The triangle holds its last step when it stops, and it powers up on step
+//! 0, so its column reads 15 in the scripts that don't use it.
+
+use nes_core::apu_script::{compare, decode_golden, encode_golden, ApuScript};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn test_apu_scripts_match_golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/apu");
+    let bless = std::env::var_os("NES_BLESS").is_some();
+    let mut scripts: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "script"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in scripts {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let script = ApuScript::parse(&fs::read_to_string(&path).unwrap()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let samples = script.run();
+        let golden_path = path.with_extension("golden");
+        if bless {
+            fs::write(&golden_path, encode_golden(&samples)).unwrap();
+            continue;
+        }
+        let golden = match fs::read_to_string(&golden_path) {
+            Ok(text) => decode_golden(&text).unwrap_or_else(|e| panic!("{}.golden: {}", name, e)),
+            Err(_) => {
+                failures.push(format!("{}: no golden file (run with NES_BLESS=1)", name));
+                continue;
+            }
+        };
+        if let Some(mismatch) = compare(&golden, &samples, script.sample_period) {
+            failures.push(format!("{}: {}", name, mismatch));
+        }
+    }
+    assert!(failures.is_empty(), "APU scripts differ from golden output:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,64 @@
+//! CHR RAM regression test
+//!
+//! The synthetic ROM below has no CHR ROM, so the board has 8KB of CHR RAM.
+//! Its reset code points PPUADDR at $0000, writes $F0 to the eight bytes of
+//! tile 0's low plane through PPUDATA, sets palette entry 1 to master color
+//! $01, then turns on the background. Tile 0 fills the nametable, so every
+//! line shows 4 pixels of color 1 followed by 4 of the backdrop. Without CHR RAM the pattern fetches read nothing and the
+//! screen stays blank.
+
+use nes_core::ppu::{palette_rgb, FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use nes_core::system::NesSystem;
+
+const PRG_BANK: usize = 16 * 1024;
+
+/// NROM image with no CHR banks
+///
+/// ```text
+/// reset: SEI / LDA #$00 / STA $2006 / STA $2006
+///        LDA #$F0 / LDX #$08
+/// loop:  STA $2007 / DEX / BNE loop
+///        LDA #$3F / STA $2006 / LDA #$01 / STA $2006 / STA $2007
+///        LDA #$08 / STA $2001
+/// spin:  JMP spin
+/// ```
+fn chr_ram_rom() -> Vec<u8> {
+    let mut prg = vec![0xEA; PRG_BANK];
+    prg[..40].copy_from_slice(&[
+        0x78, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0xF0, 0xA2, 0x08, 0x8D, 0x07, 0x20, 0xCA, 0xD0,
+        0xFA, 0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x01, 0x8D, 0x06, 0x20, 0x8D, 0x07, 0x20, 0xA9, 0x08, 0x8D, 0x01,
+        0x20, 0x4C, 0x25, 0xC0,
+    ]);
+    for vector in [0x3FFA, 0x3FFC, 0x3FFE] {
+        prg[vector..vector + 2].copy_from_slice(&0xC000u16.to_le_bytes());
+    }
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0, 0];
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom
+}
+
+#[test]
+fn test_chr_ram_written_through_ppudata() {
+    let mut system = NesSystem::new();
+    system.load_rom(&chr_ram_rom()).expect("test ROM should parse");
+    system.reset();
+    system.initialize_ppu();
+    system.set_pc(0xC000);
+    assert!(system.ppu().chr_ram());
+
+    system.run_frames(2).expect("CHR RAM ROM should run");
+    assert_eq!(system.ppu().pattern_tables().len(), 0x2000);
+    assert_eq!(system.ppu().pattern_tables()[..9], [0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0x00]);
+
+    let mut framebuffer = vec![0u8; FRAME_RGB_SIZE];
+    system.ppu().render_frame(&mut framebuffer);
+    let (grey, blue) = (palette_rgb(0x00), palette_rgb(0x01));
+    for (y, row) in framebuffer.chunks_exact(FRAME_WIDTH * 3).enumerate().take(FRAME_HEIGHT) {
+        for (x, pixel) in row.chunks_exact(3).enumerate() {
+            let expected = if x % 8 < 4 { blue } else { grey };
+            assert_eq!((pixel[0], pixel[1], pixel[2]), expected, "pixel ({}, {})", x, y);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Smoke ROM shared by every build configuration
+//!
+//! The same tiny program runs in the native tests (`tests/feature_matrix.rs`)
+//! and the wasm32 tests (`nes-wasm/tests/smoke.rs`); both compare the final
+//! `NesSystem::state_hash` with `SMOKE_HASH`, so a configuration that
+//! executes differently fails even though it can't talk to the others.
+//! Only the stable root API is used, which also checks that it compiles.
+//!
+//! Like the title screen hashes, `SMOKE_HASH` changes when emulation timing
+//! changes on purpose; update it from the std run after checking why.
+
+use nes_core::{AccuracyProfile, Buttons, NesSystem};
+
+/// Frames the smoke ROM runs for
+pub const SMOKE_FRAMES: u64 = 30;
+/// `state_hash` after `SMOKE_FRAMES` frames
+pub const SMOKE_HASH: u64 = 0xD4106D7EF1589E0F;
+
+/// NROM image: a counting main loop and an NMI handler that polls port 1
+///
+/// ```text
+/// reset: SEI / LDX #$FF / TXS
+///        LDA #$80 / STA $2000      ; NMI on
+///        LDA #$1E / STA $2001      ; rendering on
+/// loop:  INC $10 / LDA $10 / ADC $11 / STA $11 / JMP loop
+/// nmi:   LDA #1 / STA $4016 / LDA #0 / STA $4016
+///        LDA $4016 / AND #1 / CLC / ADC $12 / STA $12   ; frames with A held
+///        INC $13 / RTI                                  ; NMI count
+/// ```
+pub fn smoke_rom() -> Vec<u8> {
+    let mut prg = vec![0xEA; 16 * 1024];
+    let reset = [
+        0x78, 0xA2, 0xFF, 0x9A, 0xA9, 0x80, 0x8D, 0x00, 0x20, 0xA9, 0x1E, 0x8D, 0x01, 0x20, // $C000
+        0xE6, 0x10, 0xA5, 0x10, 0x65, 0x11, 0x85, 0x11, 0x4C, 0x0E, 0xC0, // $C00E
+    ];
+    let nmi = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, // $C100
+        0xAD, 0x16, 0x40, 0x29, 0x01, 0x18, 0x65, 0x12, 0x85, 0x12, 0xE6, 0x13, 0x40,
+    ];
+    prg[..reset.len()].copy_from_slice(&reset);
+    prg[0x100..0x100 + nmi.len()].copy_from_slice(&nmi);
+    prg[0x3FFA..].copy_from_slice(&[0x00, 0xC1, 0x00, 0xC0, 0x00, 0xC0]);
+    let chr: Vec<u8> = (0..8 * 1024).map(|i| (i * 7 % 256) as u8).collect();
+
+    let mut rom = b"NES\x1A\x01\x01".to_vec();
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.extend(chr);
+    rom
+}
+
+/// Boot the smoke ROM with A held, run it and return the system
+pub fn run_smoke() -> NesSystem {
+    let mut system = NesSystem::new();
+    system.set_accuracy(AccuracyProfile::compatible());
+    system.load_rom(&smoke_rom()).expect("smoke ROM loads");
+    system.reset();
+    system.set_buttons(0, Buttons::new(Buttons::A));
+    system.run_frames(SMOKE_FRAMES).expect("smoke ROM runs");
+    system
+}
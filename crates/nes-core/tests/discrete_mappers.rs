@@ -0,0 +1,171 @@
+//! Discrete-logic mapper tests with synthetic ROMs
+//!
+//! Every 32KB PRG bank is filled with $FF except its first byte ($8000),
+//! which holds the bank number, so a read of $8000 shows the mapped bank and
+//! writes elsewhere never conflict. Boards with bus conflicts see writes to
+//! $8000 ANDed with that bank number.
+
+use nes_core::debug_state::DebugState;
+use nes_core::romdb::{crc32, RomInfo};
+use nes_core::NesSystem;
+
+const PRG_BANK_32K: usize = 32 * 1024;
+const CHR_BANK_8K: usize = 8 * 1024;
+
+/// iNES image with `prg_banks` 32KB banks and `chr_banks` 8KB banks; a submapper makes it NES 2.0
+fn rom(mapper: u8, prg_banks: usize, chr_banks: usize, submapper: Option<u8>) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, (prg_banks * 2) as u8, chr_banks as u8, mapper << 4, mapper & 0xF0];
+    if let Some(submapper) = submapper {
+        rom[7] |= 0x08;
+        rom.push(submapper << 4);
+    }
+    rom.resize(16, 0);
+    for bank in 0..prg_banks {
+        let mut prg = vec![0xFF; PRG_BANK_32K];
+        prg[0] = bank as u8;
+        rom.extend(prg);
+    }
+    for bank in 0..chr_banks {
+        rom.extend(vec![bank as u8; CHR_BANK_8K]);
+    }
+    rom
+}
+
+fn load_image(rom: &[u8]) -> NesSystem {
+    let mut system = NesSystem::new();
+    system.load_rom(rom).expect("synthetic ROM should parse");
+    system
+}
+
+fn load(mapper: u8, prg_banks: usize, chr_banks: usize) -> NesSystem {
+    load_image(&rom(mapper, prg_banks, chr_banks, None))
+}
+
+/// Named mapper register value
+fn register(system: &NesSystem, name: &str) -> u32 {
+    let registers = DebugState::capture(system).mapper;
+    registers.into_iter().find(|(n, _)| n == name).map(|(_, value)| value).unwrap()
+}
+
+/// Selected 8KB CHR bank
+fn chr_bank(system: &NesSystem) -> usize {
+    register(system, "chr") as usize
+}
+
+#[test]
+fn test_color_dreams() {
+    let mut system = load(11, 4, 16);
+    system.write_memory(0x8001, 0x72);
+    assert_eq!(system.read_memory(0x8000), 2);
+    assert_eq!(chr_bank(&system), 7);
+
+    // $8000 holds 2 in this bank, so writing $F1 there selects PRG 0, CHR 0
+    system.write_memory(0x8000, 0xF1);
+    assert_eq!(system.read_memory(0x8000), 0);
+    assert_eq!(chr_bank(&system), 0);
+}
+
+#[test]
+fn test_gxrom() {
+    let mut system = load(66, 4, 4);
+    system.write_memory(0xC123, 0x31);
+    assert_eq!(system.read_memory(0x8000), 3);
+    assert_eq!(chr_bank(&system), 1);
+
+    // Bus conflict with the 3 at $8000: $12 & $03 selects PRG 0, CHR 2
+    system.write_memory(0x8000, 0x12);
+    assert_eq!(system.read_memory(0x8000), 0);
+    assert_eq!(chr_bank(&system), 2);
+}
+
+#[test]
+fn test_bnrom() {
+    let mut system = load(34, 4, 0);
+    system.write_memory(0xFFF0, 0x03);
+    assert_eq!(system.read_memory(0x8000), 3);
+    system.write_memory(0x8000, 0x02);
+    assert_eq!(system.read_memory(0x8000), 2);
+    // $8000 now holds 2: a write of 1 conflicts down to 0
+    system.write_memory(0x8000, 0x01);
+    assert_eq!(system.read_memory(0x8000), 0);
+}
+
+#[test]
+fn test_nina001() {
+    let mut system = load(34, 2, 4);
+    system.write_memory(0x7FFD, 0x01);
+    system.write_memory(0x7FFE, 0x05);
+    system.write_memory(0x7FFF, 0x06);
+    assert_eq!(system.read_memory(0x8000), 1);
+    assert_eq!((register(&system, "chr0"), register(&system, "chr1")), (5, 6));
+    // The registers sit on top of PRG RAM
+    assert_eq!(system.read_memory(0x7FFE), 0x05);
+
+    // No registers at $8000-$FFFF
+    system.write_memory(0x8000, 0x00);
+    assert_eq!(system.read_memory(0x8000), 1);
+}
+
+#[test]
+fn test_jaleco_140() {
+    let mut system = load(140, 4, 16);
+    system.write_memory(0x6000, 0x2B);
+    assert_eq!(system.read_memory(0x8000), 2);
+    assert_eq!(chr_bank(&system), 0x0B);
+
+    system.write_memory(0x8000, 0x00);
+    assert_eq!(system.read_memory(0x8000), 2);
+    system.write_memory(0x7FFF, 0x10);
+    assert_eq!(system.read_memory(0x8000), 1);
+}
+
+#[test]
+fn test_axrom() {
+    let mut system = load(7, 8, 0);
+    system.write_memory(0x8001, 0x15);
+    assert_eq!(system.read_memory(0x8000), 5);
+    assert_eq!(register(&system, "one_screen"), 1);
+}
+
+#[test]
+fn test_bus_conflicts_from_submapper() {
+    // UNROM: $8000 holds 0, so with conflicts selecting bank 2 (32KB bank 1) fails
+    for (submapper, expected) in [(None, 1), (Some(1), 1), (Some(2), 0)] {
+        let mut system = load_image(&rom(2, 2, 0, submapper));
+        system.write_memory(0x8000, 0x02);
+        assert_eq!(system.read_memory(0x8000), expected, "submapper {:?}", submapper);
+    }
+
+    // AxROM: select bank 1 away from $8000, then write 2 over its marker
+    let mut system = load_image(&rom(7, 4, 0, Some(2)));
+    system.write_memory(0x8001, 0x01);
+    system.write_memory(0x8000, 0x02);
+    assert_eq!(system.read_memory(0x8000), 0);
+}
+
+#[test]
+fn test_bus_conflicts_from_rom_database() {
+    // CNROM with no submapper: the database entry turns conflicts on
+    let image = rom(3, 1, 4, None);
+    let crc = crc32(&image[16..]);
+    let mut system = NesSystem::new();
+    system.rom_database_mut().insert(RomInfo::new(crc, "Conflicted CNROM").with_bus_conflicts(true));
+    system.load_rom(&image).unwrap();
+    system.write_memory(0x8000, 0x03);
+    assert_eq!(chr_bank(&system), 0);
+    system.write_memory(0x8001, 0x03);
+    assert_eq!(chr_bank(&system), 3);
+}
+
+#[test]
+fn test_savestate_restores_bank_registers() {
+    let mut system = load(66, 4, 4);
+    system.write_memory(0xC123, 0x31);
+    let state = system.save_state();
+    system.write_memory(0xC123, 0x02);
+    assert_eq!(system.read_memory(0x8000), 0);
+
+    system.load_state(&state).unwrap();
+    assert_eq!(system.read_memory(0x8000), 3);
+    assert_eq!(chr_bank(&system), 1);
+}
@@ -0,0 +1,16 @@
+//! Checks shared by the build configurations `cargo xtask matrix` covers
+//!
+//! - std: the smoke ROM runs to the expected state (`common/smoke.rs`);
+//!   nes-wasm runs the same check on wasm32-unknown-unknown.
+
+#[path = "common/smoke.rs"]
+mod smoke;
+
+#[test]
+fn test_smoke_rom_runs_to_expected_state() {
+    let system = smoke::run_smoke();
+    let ram = system.ram();
+    assert!(ram[0x13] > 0, "no NMIs ran");
+    assert_eq!(ram[0x12], ram[0x13], "A wasn't read in every NMI");
+    assert_eq!(system.state_hash(), smoke::SMOKE_HASH, "state hash {:#018X}", system.state_hash());
+}
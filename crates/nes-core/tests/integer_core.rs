@@ -0,0 +1,60 @@
+//! Guard for the integer-only emulation path
+//!
+//! Netplay and movie verification compare state hashes across x86, ARM and
+//! WASM, so everything that advances emulated state must use integer math
+//! only; float rounding differences between platforms cause desyncs. This
+//! test fails if `f32` or `f64` shows up in the non-test code of those
+//! modules. Output and analysis modules (sample formats, GIF timing, metrics,
+//! health) are deliberately not listed: they only read the state.
+
+/// Modules on the emulation path, with their source
+const EMULATION_PATH: [(&str, &str); 18] = [
+    ("apu", include_str!("../src/apu.rs")),
+    ("bus", include_str!("../src/bus.rs")),
+    ("cartridge", include_str!("../src/cartridge.rs")),
+    ("cheats", include_str!("../src/cheats.rs")),
+    ("controller", include_str!("../src/controller.rs")),
+    ("cpu", include_str!("../src/cpu.rs")),
+    ("eeprom", include_str!("../src/eeprom.rs")),
+    ("input_schedule", include_str!("../src/input_schedule.rs")),
+    ("instructions", include_str!("../src/instructions.rs")),
+    ("mapper", include_str!("../src/mapper.rs")),
+    ("mixer", include_str!("../src/mixer.rs")),
+    ("movie", include_str!("../src/movie.rs")),
+    ("ppu", include_str!("../src/ppu.rs")),
+    ("region", include_str!("../src/region.rs")),
+    ("rng", include_str!("../src/rng.rs")),
+    ("state", include_str!("../src/state.rs")),
+    ("system", include_str!("../src/system.rs")),
+    ("zapper", include_str!("../src/zapper.rs")),
+];
+
+/// Lines of non-test code that mention a float type
+fn float_lines(source: &str) -> Vec<(usize, &str)> {
+    let code = source.split("#[cfg(test)]").next().unwrap_or_default();
+    code.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.split("//").next().unwrap_or_default()))
+        .filter(|(_, line)| {
+            line.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .any(|word| word == "f32" || word == "f64")
+        })
+        .collect()
+}
+
+#[test]
+fn test_emulation_path_is_float_free() {
+    let offenders: Vec<String> = EMULATION_PATH
+        .iter()
+        .flat_map(|(module, source)| {
+            float_lines(source).into_iter().map(move |(line, code)| format!("{}.rs:{}: {}", module, line, code.trim()))
+        })
+        .collect();
+    assert!(offenders.is_empty(), "floating point on the emulation path:\n{}", offenders.join("\n"));
+}
+
+#[test]
+fn test_scanner_finds_floats() {
+    assert_eq!(float_lines("let x: f32 = 1.0;\n// f64 in a comment\nfn f320() {}").len(), 1);
+    assert!(float_lines("#[cfg(test)]\nlet x = 1.0f64 as f64;").is_empty());
+}
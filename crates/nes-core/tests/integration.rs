@@ -12,20 +12,21 @@ fn test_system_creation() {
 fn test_system_reset() {
     let mut system = NesSystem::new();
     system.reset();
-    assert_eq!(system.cpu().registers().pc, 0xFFFC);
+    assert_eq!(system.cpu_registers().pc, 0xFFFC);
 }
 
 #[test]
 fn test_system_with_cartridge() {
-    let prg_rom = vec![0xFF; 16384]; // 16KB
-    let chr_rom = vec![0x00; 8192];  // 8KB
-    let cartridge = nes_core::bus::SimpleCartridge::new(prg_rom, chr_rom);
+    let mut rom = b"NES\x1A\x01\x01".to_vec();
+    rom.resize(16, 0);
+    rom.extend(vec![0xFF; 16384]); // 16KB PRG
+    rom.extend(vec![0x00; 8192]); // 8KB CHR
 
     let mut system = NesSystem::new();
-    system.load_simple_cartridge(cartridge);
+    system.load_rom(&rom).unwrap();
     system.reset();
 
-    assert_eq!(system.cpu().registers().pc, 0xFFFC);
+    assert_eq!(system.cpu_registers().pc, 0xFFFC);
 }
 
 #[test]
@@ -33,10 +34,10 @@ fn test_cpu_after_reset() {
     let mut system = NesSystem::new();
     system.reset();
 
-    let cpu = system.cpu();
-    assert_eq!(cpu.registers().a, 0);
-    assert_eq!(cpu.registers().x, 0);
-    assert_eq!(cpu.registers().y, 0);
-    assert_eq!(cpu.registers().sp, 0xFD);
-    assert_eq!(cpu.registers().pc, 0xFFFC);
+    let registers = system.cpu_registers();
+    assert_eq!(registers.a, 0);
+    assert_eq!(registers.x, 0);
+    assert_eq!(registers.y, 0);
+    assert_eq!(registers.sp, 0xFD);
+    assert_eq!(registers.pc, 0xFFFC);
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+//! Mid-frame PPUMASK regression test
+//!
+//! The synthetic ROM below sets palette entry 1 to master color $01 at reset,
+//! turns the background off in its NMI handler, then burns a fixed number of
+//! cycles and turns it back on partway through a visible scanline. Tile 0 is
+//! solid color 1 and fills the nametable, so the picture is split colorwise:
+//! backdrop grey above the write, blue below it, with the switch in the
+//! middle of one line. Emulation is deterministic, so
+//! the split point is pinned here; a change in CPU or PPU timing moves it.
+
+use nes_core::ppu::{palette_rgb, FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use nes_core::system::NesSystem;
+
+const PRG_BANK: usize = 16 * 1024;
+const CHR_BANK: usize = 8 * 1024;
+const NMI: u16 = 0xC020;
+
+/// Scanline and first blue pixel of the split
+const SPLIT_LINE: usize = 125;
+const SPLIT_X: usize = 157;
+
+/// NROM image: reset enables NMI and spins, NMI splits the screen
+///
+/// ```text
+/// reset: SEI / LDA #$3F / STA $2006 / LDA #$01 / STA $2006 / STA $2007
+///        NOP / LDA #$80 / STA $2000 / loop: JMP loop
+/// nmi:   LDA #$00 / STA $2001
+///        LDY #$33
+/// outer: LDX #$40
+/// inner: DEX / BNE inner
+///        DEY / BNE outer
+///        LDA #$08 / STA $2001 / RTI
+/// ```
+fn split_rom() -> Vec<u8> {
+    let mut prg = vec![0xEA; PRG_BANK];
+    prg[..23].copy_from_slice(&[
+        0x78, 0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x01, 0x8D, 0x06, 0x20, 0x8D, 0x07, 0x20, 0xEA, 0xA9, 0x80, 0x8D,
+        0x00, 0x20, 0x4C, 0x14, 0xC0,
+    ]);
+    let nmi = (NMI - 0xC000) as usize;
+    prg[nmi..nmi + 21].copy_from_slice(&[
+        0xA9, 0x00, 0x8D, 0x01, 0x20, 0xA0, 0x33, 0xA2, 0x40, 0xCA, 0xD0, 0xFD, 0x88, 0xD0, 0xF8, 0xA9, 0x08, 0x8D,
+        0x01, 0x20, 0x40,
+    ]);
+    for (vector, target) in [(0x3FFA, NMI), (0x3FFC, 0xC000), (0x3FFE, 0xC000)] {
+        prg[vector..vector + 2].copy_from_slice(&target.to_le_bytes());
+    }
+    let mut chr = vec![0; CHR_BANK];
+    chr[..8].fill(0xFF); // Tile 0, low plane: color 1 everywhere
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0];
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.extend(chr);
+    rom
+}
+
+/// Render the current frame as rows of RGB pixels
+fn frame_rows(system: &NesSystem) -> Vec<Vec<(u8, u8, u8)>> {
+    let mut framebuffer = vec![0u8; FRAME_RGB_SIZE];
+    system.ppu().render_frame(&mut framebuffer);
+    framebuffer
+        .chunks_exact(FRAME_WIDTH * 3)
+        .map(|row| row.chunks_exact(3).map(|p| (p[0], p[1], p[2])).collect())
+        .collect()
+}
+
+#[test]
+fn test_mid_scanline_mask_split() {
+    let mut system = NesSystem::new();
+    system.load_rom(&split_rom()).expect("test ROM should parse");
+    system.reset();
+    system.initialize_ppu();
+    system.set_pc(0xC000);
+    system.run_frames(12).expect("split ROM should run");
+
+    let rows = frame_rows(&system);
+    let (grey, blue) = (palette_rgb(0x00), palette_rgb(0x01));
+    for (y, row) in rows.iter().enumerate().take(FRAME_HEIGHT) {
+        let split = match y {
+            y if y < SPLIT_LINE => FRAME_WIDTH,
+            SPLIT_LINE => SPLIT_X,
+            _ => 0,
+        };
+        assert!(row[..split].iter().all(|&p| p == grey), "line {} should be grey up to x={}", y, split);
+        assert!(row[split..].iter().all(|&p| p == blue), "line {} should be blue from x={}", y, split);
+    }
+
+    // The NMI waits for the spin loop's 3-cycle JMP to finish, so later
+    // frames split up to 9 dots later, but always on the same line
+    for _ in 0..8 {
+        system.run_frames(1).expect("split ROM should run");
+        let rows = frame_rows(&system);
+        let line = rows.iter().position(|row| row[FRAME_WIDTH - 1] == blue);
+        let split = rows[SPLIT_LINE].iter().position(|&p| p == blue).unwrap();
+        assert_eq!(line, Some(SPLIT_LINE));
+        assert!((SPLIT_X..SPLIT_X + 9).contains(&split), "split at x={}", split);
+    }
+}
@@ -0,0 +1,108 @@
+//! Mid-frame PPUSCROLL regression test
+//!
+//! The synthetic ROM below resets the scroll to 0,0 in its NMI handler, then
+//! burns a fixed number of cycles and writes PPUSCROLL again during the
+//! horizontal blank of a visible scanline: X = 11 (coarse 1, fine 3), then
+//! Y = 4. Tile 0 fills the nametable and is a 4x4 checkerboard of backdrop
+//! grey and color 1, which reset sets to blue, so the picture only shows
+//! fine scroll. On hardware the first write moves fine X
+//! at the pixel mux straight away and the second only lands in `t`, so every
+//! line after the write is shifted left by 3 pixels and no line moves
+//! vertically. Emulation is deterministic, so the split line is pinned here;
+//! a change in CPU or PPU timing moves it.
+
+use nes_core::ppu::{palette_rgb, FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use nes_core::system::NesSystem;
+
+const PRG_BANK: usize = 16 * 1024;
+const CHR_BANK: usize = 8 * 1024;
+const NMI: u16 = 0xC020;
+
+/// First scanline drawn with the new fine X
+const SPLIT_LINE: usize = 100;
+/// Fine X written mid-frame
+const FINE_X: usize = 3;
+
+/// NROM image: reset turns on NMI and the background and spins, NMI splits the screen
+///
+/// ```text
+/// reset: SEI / LDA #$3F / STA $2006 / LDA #$01 / STA $2006 / STA $2007
+///        LDA #$80 / STA $2000 / LDA #$08 / STA $2001 / loop: JMP loop
+/// nmi:   LDA $2002 / LDA #$00 / STA $2005 / STA $2005
+///        LDY #$2A
+/// outer: LDX #$40
+/// inner: DEX / BNE inner
+///        DEY / BNE outer
+///        NOP x8
+///        LDA #$0B / STA $2005 / LDA #$04 / STA $2005 / RTI
+/// ```
+fn split_rom() -> Vec<u8> {
+    let mut prg = vec![0xEA; PRG_BANK];
+    prg[..27].copy_from_slice(&[
+        0x78, 0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x01, 0x8D, 0x06, 0x20, 0x8D, 0x07, 0x20, 0xA9, 0x80, 0x8D, 0x00,
+        0x20, 0xA9, 0x08, 0x8D, 0x01, 0x20, 0x4C, 0x18, 0xC0,
+    ]);
+    let nmi = (NMI - 0xC000) as usize;
+    prg[nmi..nmi + 21].copy_from_slice(&[
+        0xAD, 0x02, 0x20, 0xA9, 0x00, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, 0xA0, 0x2A, 0xA2, 0x40, 0xCA, 0xD0, 0xFD,
+        0x88, 0xD0, 0xF8,
+    ]);
+    // Eight NOPs are already there from the fill
+    prg[nmi + 29..nmi + 40].copy_from_slice(&[0xA9, 0x0B, 0x8D, 0x05, 0x20, 0xA9, 0x04, 0x8D, 0x05, 0x20, 0x40]);
+    for (vector, target) in [(0x3FFA, NMI), (0x3FFC, 0xC000), (0x3FFE, 0xC000)] {
+        prg[vector..vector + 2].copy_from_slice(&target.to_le_bytes());
+    }
+    let mut chr = vec![0; CHR_BANK];
+    // Tile 0, low plane: color 1 in the top-left and bottom-right 4x4 quarters
+    chr[..4].fill(0xF0);
+    chr[4..8].fill(0x0F);
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0];
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.extend(chr);
+    rom
+}
+
+/// Render the current frame as rows of RGB pixels
+fn frame_rows(system: &NesSystem) -> Vec<Vec<(u8, u8, u8)>> {
+    let mut framebuffer = vec![0u8; FRAME_RGB_SIZE];
+    system.ppu().render_frame(&mut framebuffer);
+    framebuffer
+        .chunks_exact(FRAME_WIDTH * 3)
+        .map(|row| row.chunks_exact(3).map(|p| (p[0], p[1], p[2])).collect())
+        .collect()
+}
+
+/// Check if the checkerboard is blue at a tile pixel
+fn checker(column: usize, row: usize) -> bool {
+    (column % 8 < 4) == (row % 8 < 4)
+}
+
+#[test]
+fn test_hblank_scroll_split() {
+    let mut system = NesSystem::new();
+    system.load_rom(&split_rom()).expect("test ROM should parse");
+    system.reset();
+    system.initialize_ppu();
+    system.set_pc(0xC000);
+    system.set_framebuffer_enabled(true);
+
+    let (grey, blue) = (palette_rgb(0x00), palette_rgb(0x01));
+    // The first frame is drawn before the first NMI
+    system.run_frames(1).expect("split ROM should run");
+    // The NMI waits for the spin loop's 3-cycle JMP to finish, so the write
+    // lands a few dots apart from frame to frame, but always in hblank
+    for _ in 0..6 {
+        system.run_frames(1).expect("split ROM should run");
+        let rows = frame_rows(&system);
+        for (y, row) in rows.iter().enumerate().take(FRAME_HEIGHT) {
+            let fine_x = if y < SPLIT_LINE { 0 } else { FINE_X };
+            let expected: Vec<_> =
+                (0..FRAME_WIDTH).map(|x| if checker(x + fine_x, y) { blue } else { grey }).collect();
+            assert_eq!(row, &expected, "line {} should be drawn with fine X {}", y, fine_x);
+        }
+        // Lines rendered as they finish replay the same split
+        assert_eq!(system.framebuffer(), rows.concat().iter().flat_map(|&(r, g, b)| [r, g, b]).collect::<Vec<_>>());
+    }
+}
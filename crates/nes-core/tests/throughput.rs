@@ -0,0 +1,87 @@
+//! Full-system throughput gate
+//!
+//! Runs a reference workload the way a frontend does (emulate a frame, then
+//! render it) and fails if release-mode throughput drops below a threshold,
+//! so changes that wreck performance (say, an allocation per pixel) are
+//! caught before a release. Timing depends on the machine and debug builds
+//! are far slower, so the test is ignored by default:
+//!
+//! ```text
+//! cargo test --release -p nes-core --test throughput -- --ignored
+//! ```
+//!
+//! `NES_BENCH_MIN_FPS` sets the threshold (default `DEFAULT_MIN_FPS`) and
+//! `NES_BENCH_FRAMES` the workload length (default `DEFAULT_FRAMES`).
+
+use nes_core::{NesSystem, FRAME_WIDTH};
+use std::time::Instant;
+
+/// Frames per second the workload must reach: full speed (60Hz) with some headroom
+///
+/// Deliberately conservative so slow CI machines pass; raise it with
+/// `NES_BENCH_MIN_FPS` on a known machine for a tighter gate.
+const DEFAULT_MIN_FPS: f64 = 90.0;
+/// Frames in the workload
+const DEFAULT_FRAMES: u64 = 1200;
+/// Byte offset of a pixel near the middle of the frame, summed so rendering can't be skipped
+const FRAME_CENTER: usize = (120 * FRAME_WIDTH + 128) * 3;
+
+/// Reference workload ROM: background and sprites on, a busy main loop and an NMI handler
+///
+/// ```text
+/// reset: LDA #$80 / STA $2000      ; NMI on
+///        LDA #$1E / STA $2001      ; background and sprites on
+/// loop:  INC $00 / LDX $00
+///        LDA $0200,X / ADC #$01 / STA $0200,X   ; keep changing the sprite page
+///        JMP loop
+/// nmi:   LDA #$02 / STA $4014 / RTI            ; OAM DMA from $0200
+/// ```
+fn workload_rom() -> Vec<u8> {
+    let mut prg = vec![0xEA; 16 * 1024];
+    let reset = [
+        0xA9, 0x80, 0x8D, 0x00, 0x20, 0xA9, 0x1E, 0x8D, 0x01, 0x20, // $C000
+        0xE6, 0x00, 0xA6, 0x00, 0xBD, 0x00, 0x02, 0x69, 0x01, 0x9D, 0x00, 0x02, 0x4C, 0x0A, 0xC0, // $C00A
+    ];
+    prg[..reset.len()].copy_from_slice(&reset);
+    prg[0x100..0x106].copy_from_slice(&[0xA9, 0x02, 0x8D, 0x14, 0x40, 0x40]); // $C100
+    prg[0x3FFA..0x3FFE].copy_from_slice(&[0x00, 0xC1, 0x00, 0xC0]);
+    let chr: Vec<u8> = (0..8 * 1024).map(|i| (i * 37 % 251) as u8).collect();
+
+    let mut rom = b"NES\x1A\x01\x01".to_vec();
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.extend(chr);
+    rom
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+#[test]
+#[ignore = "timing gate; run with --release -- --ignored"]
+fn test_release_throughput() {
+    if cfg!(debug_assertions) {
+        eprintln!("throughput gate skipped: build with --release");
+        return;
+    }
+    let min_fps = env_or("NES_BENCH_MIN_FPS", DEFAULT_MIN_FPS);
+    let frames = env_or("NES_BENCH_FRAMES", DEFAULT_FRAMES);
+
+    let mut system = NesSystem::new();
+    system.load_rom(&workload_rom()).unwrap();
+    system.reset();
+    system.initialize_ppu();
+    system.set_pc(0xC000);
+
+    let start = Instant::now();
+    let mut checksum = 0u64;
+    for _ in 0..frames {
+        system.run_frames(1).unwrap();
+        let frame = system.ppu_mut().snapshot_frame();
+        checksum = checksum.wrapping_add(frame.pixels()[FRAME_CENTER] as u64);
+    }
+    let fps = frames as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON);
+    println!("throughput: {:.1} frames/s over {} frames (checksum {})", fps, frames, checksum);
+    assert!(fps >= min_fps, "throughput {:.1} frames/s is below the {:.1} frames/s gate", fps, min_fps);
+}
@@ -0,0 +1,183 @@
+//! Boot-to-title regression tests
+//!
+//! Each test builds a small homebrew ROM for one board (NROM, UNROM, CNROM),
+//! boots it from its reset vector, runs it into its title loop and checks
+//! where it ended up (a marker byte in RAM and the mapper's bank registers)
+//! and the FNV-1a hash of the rendered framebuffer.
+//!
+//! The ROMs are assembled here rather than shipped as binaries, so they are
+//! covered by the repo's license and every byte is documented below. The
+//! title code writes PPUCTRL/PPUMASK from the bank it runs in, so a broken
+//! PRG banking path changes both the marker and the picture.
+//!
+//! A hash changes whenever rendering changes. After an intentional change
+//! (for example when PPUDATA writes start reaching VRAM), check the new
+//! output by eye with `nes-cli --png-frames` and update the constants.
+//!
+//! `test_run_until_title_logo` shows the template-matching goal playtests
+//! use: boot until a region of the screen shows the expected title.
+
+use nes_core::debug_state::DebugState;
+use nes_core::frame_goal::{Region, TemplateMatch};
+use nes_core::ppu::FRAME_RGB_SIZE;
+use nes_core::system::NesSystem;
+
+const PRG_BANK: usize = 16 * 1024;
+const CHR_BANK: usize = 8 * 1024;
+/// Frames to run before taking the title screen
+const TITLE_FRAMES: u64 = 10;
+
+// Expected framebuffer hashes after `TITLE_FRAMES` frames
+const NROM_HASH: u64 = 0x05E4D8784D7B7925;
+const UNROM_HASH: u64 = 0x575DCF36DF9DA325;
+const CNROM_HASH: u64 = 0xFF0A473FEC2793A5;
+
+/// iNES image with the given mapper, PRG banks (16KB each) and CHR data
+fn ines(mapper: u8, prg: Vec<u8>, chr: Vec<u8>) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A];
+    rom.push((prg.len() / PRG_BANK) as u8);
+    rom.push((chr.len() / CHR_BANK) as u8);
+    rom.push(mapper << 4);
+    rom.push(mapper & 0xF0);
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.extend(chr);
+    rom
+}
+
+/// Title routine at `base`: record `marker` in $00, set up the PPU, then spin
+///
+/// ```text
+/// SEI
+/// LDA #marker / STA $00
+/// LDA #ctrl   / STA $2000
+/// LDA #mask   / STA $2001
+/// loop: JMP loop
+/// ```
+fn title_code(base: u16, marker: u8, ctrl: u8, mask: u8) -> Vec<u8> {
+    let [lo, hi] = (base + 15).to_le_bytes();
+    vec![
+        0x78, 0xA9, marker, 0x85, 0x00, 0xA9, ctrl, 0x8D, 0x00, 0x20, 0xA9, mask, 0x8D, 0x01, 0x20, 0x4C, lo, hi,
+    ]
+}
+
+/// Address of the spin loop in `title_code(base, ..)`
+fn title_loop(base: u16) -> u16 {
+    base + 15
+}
+
+/// Put `code` at `address` in a bank mapped at `bank_base`
+fn place(bank: &mut [u8], bank_base: u16, address: u16, code: &[u8]) {
+    let offset = (address - bank_base) as usize;
+    bank[offset..offset + code.len()].copy_from_slice(code);
+}
+
+/// Point every interrupt vector of a bank mapped at $C000 at `reset`
+fn set_vectors(bank: &mut [u8], reset: u16) {
+    for vector in [0x3FFA, 0x3FFC, 0x3FFE] {
+        bank[vector..vector + 2].copy_from_slice(&reset.to_le_bytes());
+    }
+}
+
+/// CHR data that differs per ROM (`seed`), per bank and per byte
+fn chr(banks: usize, seed: u8) -> Vec<u8> {
+    (0..banks * CHR_BANK).map(|i| ((i * 7) ^ (i / CHR_BANK * 0x55)) as u8 ^ seed).collect()
+}
+
+/// Load a ROM and point the CPU at its reset vector
+fn power_on(rom: &[u8]) -> NesSystem {
+    let mut system = NesSystem::new();
+    system.load_rom(rom).expect("test ROM should parse");
+    system.reset();
+    system.initialize_ppu();
+    let reset = u16::from_le_bytes([system.read_memory(0xFFFC), system.read_memory(0xFFFD)]);
+    system.set_pc(reset);
+    system
+}
+
+/// Load a ROM, start it from its reset vector and run it to the title
+fn boot(rom: &[u8]) -> NesSystem {
+    let mut system = power_on(rom);
+    system.run_frames(TITLE_FRAMES).expect("title screen should run");
+    system
+}
+
+/// FNV-1a hash of the rendered frame
+fn frame_hash(system: &NesSystem) -> u64 {
+    let mut framebuffer = vec![0u8; FRAME_RGB_SIZE];
+    system.ppu().render_frame(&mut framebuffer);
+    framebuffer.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+/// NROM title ROM: 16KB PRG mirrored at $8000 and $C000, the code runs from the upper mirror
+fn nrom_title_rom() -> Vec<u8> {
+    let mut prg = vec![0xEA; PRG_BANK];
+    place(&mut prg, 0xC000, 0xC000, &title_code(0xC000, 0x01, 0x10, 0x1E));
+    set_vectors(&mut prg, 0xC000);
+    ines(0, prg, chr(1, 0x00))
+}
+
+#[test]
+fn test_nrom_title() {
+    let mut system = boot(&nrom_title_rom());
+    assert_eq!(system.cpu_registers().pc, title_loop(0xC000));
+    assert_eq!(system.read_memory(0x0000), 0x01);
+    assert_eq!(frame_hash(&system), NROM_HASH);
+}
+
+#[test]
+fn test_unrom_title() {
+    // Four 16KB banks; the fixed last bank selects bank 2 and jumps into it.
+    // Every switchable bank has its own title code, so the wrong bank shows.
+    let mut prg = vec![0xEA; 4 * PRG_BANK];
+    for (bank, code) in prg.chunks_exact_mut(PRG_BANK).take(3).enumerate() {
+        let bank = bank as u8;
+        place(code, 0x8000, 0x8000, &title_code(0x8000, 0x10 + bank, 0x10, 0x18 | bank << 5));
+    }
+    let fixed = &mut prg[3 * PRG_BANK..];
+    // LDA #$02 / STA $C001 (writes over the operand byte, avoiding a bus conflict) / JMP $8000
+    place(fixed, 0xC000, 0xC000, &[0xA9, 0x02, 0x8D, 0x01, 0xC0, 0x4C, 0x00, 0x80]);
+    set_vectors(fixed, 0xC000);
+
+    let mut system = boot(&ines(2, prg, Vec::new()));
+    assert_eq!(system.cpu_registers().pc, title_loop(0x8000));
+    assert_eq!(system.read_memory(0x0000), 0x12);
+    assert_eq!(frame_hash(&system), UNROM_HASH);
+}
+
+#[test]
+fn test_cnrom_title() {
+    // 16KB PRG and four 8KB CHR banks; the reset code selects CHR bank 3.
+    // The renderer doesn't follow CHR banking yet, so the picture shows bank 0.
+    let mut prg = vec![0xEA; PRG_BANK];
+    // LDA #$03 / STA $8001 (the operand byte again)
+    place(&mut prg, 0x8000, 0x8000, &[0xA9, 0x03, 0x8D, 0x01, 0x80]);
+    place(&mut prg, 0x8000, 0x8005, &title_code(0x8005, 0x03, 0x08, 0x0A));
+    set_vectors(&mut prg, 0x8000);
+
+    let mut system = boot(&ines(3, prg, chr(4, 0x33)));
+    assert_eq!(system.cpu_registers().pc, title_loop(0x8005));
+    assert_eq!(system.read_memory(0x0000), 0x03);
+    assert_eq!(DebugState::capture(&system).mapper, [("chr".to_string(), 3)]);
+    assert_eq!(frame_hash(&system), CNROM_HASH);
+}
+
+#[test]
+fn test_run_until_title_logo() {
+    // The "logo" is the top-left 64x16 pixels of a known-good title screen.
+    // A real playtest would keep these pixels in a file next to the test.
+    let logo = Region { x: 0, y: 0, width: 64, height: 16 };
+    let mut reference = vec![0u8; FRAME_RGB_SIZE];
+    boot(&nrom_title_rom()).ppu().render_frame(&mut reference);
+    let mut title = TemplateMatch::capture(&reference, logo).unwrap().with_tolerance(4);
+    assert!(!title.matches(&vec![0; FRAME_RGB_SIZE]), "the logo should not match a black screen");
+
+    let mut system = power_on(&nrom_title_rom());
+    let frames = system.run_until_goal(60, &mut title).unwrap();
+    assert!(frames.is_some_and(|frames| frames <= TITLE_FRAMES), "title not reached: {:?}", frames);
+    assert_eq!(system.read_memory(0x0000), 0x01);
+
+    // Any closure works as a goal, here one that never succeeds
+    let mut never = |_: &[u8], _: u64| false;
+    assert_eq!(power_on(&nrom_title_rom()).run_until_goal(3, &mut never).unwrap(), None);
+}
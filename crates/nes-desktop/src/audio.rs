@@ -0,0 +1,116 @@
+//! Audio output through cpal
+//!
+//! Shared by both desktop frontends: the legacy egui app in the root `src/`
+//! includes this file as its own `audio` module, so it only uses cpal and
+//! std. The main loop pushes each frame's samples into a queue that the
+//! output stream drains on cpal's audio thread. The emulator and the sound
+//! card run off different clocks (and neither minifb's frame pacing nor
+//! egui's repaints are exactly 60 Hz), so the queue would slowly fill up or
+//! run dry. Dynamic rate
+//! control keeps it near `TARGET_LATENCY` instead: `adjusted_rate` asks the
+//! emulator for slightly more samples per frame when the queue is short and
+//! slightly fewer when it is long, by at most `MAX_RATE_DELTA`, which is too
+//! little to hear as a pitch change.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Audio the queue aims to hold, in seconds
+const TARGET_LATENCY: f64 = 0.05;
+/// Largest relative change `adjusted_rate` makes to the device rate
+const MAX_RATE_DELTA: f64 = 0.005;
+/// Queue length, in multiples of the target, beyond which the oldest samples are dropped
+const MAX_QUEUE_TARGETS: usize = 4;
+
+/// Mono samples waiting for the audio thread
+type Queue = Arc<Mutex<VecDeque<f32>>>;
+
+/// Open output stream and its sample queue
+pub struct AudioOutput {
+    stream: Stream,
+    queue: Queue,
+    sample_rate: u32,
+    paused: Cell<bool>,
+}
+
+impl AudioOutput {
+    /// Open the default output device at its default configuration
+    pub fn open() -> Result<Self, String> {
+        let device = cpal::default_host().default_output_device().ok_or("no output device")?;
+        let config = device.default_output_config().map_err(|e| e.to_string())?;
+        let sample_rate = config.sample_rate().0;
+        let queue = Queue::default();
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config.into(), queue.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config.into(), queue.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config.into(), queue.clone()),
+            other => return Err(format!("unsupported sample format {}", other)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(Self { stream, queue, sample_rate, paused: Cell::new(false) })
+    }
+
+    /// Get the device's sample rate in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Queue samples for playback, dropping the oldest if the queue is far too long
+    pub fn push(&self, samples: &[f32]) {
+        let limit = self.target_len() as usize * MAX_QUEUE_TARGETS;
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(limit);
+        queue.drain(..excess);
+    }
+
+    /// Sample rate to run the emulator at so the queue stays near the target latency
+    pub fn adjusted_rate(&self) -> u32 {
+        let target = self.target_len();
+        let queued = self.queue.lock().unwrap().len() as f64;
+        let error = ((target - queued) / target).clamp(-1.0, 1.0);
+        (self.sample_rate as f64 * (1.0 + error * MAX_RATE_DELTA)).round() as u32
+    }
+
+    /// Stop or restart the device (while the emulator is paused)
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.replace(paused) == paused {
+            return;
+        }
+        let result = if paused { self.stream.pause().map_err(|e| e.to_string()) } else { self.stream.play().map_err(|e| e.to_string()) };
+        if let Err(e) = result {
+            eprintln!("Audio stream error: {}", e);
+        }
+    }
+
+    /// Queue length the rate control steers towards, in samples
+    fn target_len(&self) -> f64 {
+        self.sample_rate as f64 * TARGET_LATENCY
+    }
+}
+
+/// Build a stream that plays the queue on every channel
+fn build_stream<T: SizedSample + FromSample<f32>>(device: &Device, config: &StreamConfig, queue: Queue) -> Result<Stream, String> {
+    let channels = config.channels as usize;
+    let mut last = 0.0;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut queue = queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    // An underrun holds the last level; dropping to silence would click
+                    if let Some(sample) = queue.pop_front() {
+                        last = sample;
+                    }
+                    frame.fill(T::from_sample(last));
+                }
+            },
+            |e| eprintln!("Audio stream error: {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,102 @@
+//! Keyboard controls for the controllers
+//!
+//! Player 1 plays on the arrow keys with X (A), Z (B), Right Shift (Select)
+//! and Enter (Start); player 2 on WASD with H (A), G (B), Q (Select) and E
+//! (Start). Keys use the names from `keys::key_name` and buttons the names
+//! from `Buttons::NAMES`. In race mode, `InputMode` decides whether both
+//! instances follow the same controllers or each player drives their own
+//! instance.
+
+use nes_core::controller::Buttons;
+
+/// Default bindings: (key, player, button)
+const DEFAULT_BINDINGS: [(&str, usize, &str); 16] = [
+    ("Up", 0, "up"),
+    ("Down", 0, "down"),
+    ("Left", 0, "left"),
+    ("Right", 0, "right"),
+    ("X", 0, "a"),
+    ("Z", 0, "b"),
+    ("RightShift", 0, "select"),
+    ("Enter", 0, "start"),
+    ("W", 1, "up"),
+    ("S", 1, "down"),
+    ("A", 1, "left"),
+    ("D", 1, "right"),
+    ("H", 1, "a"),
+    ("G", 1, "b"),
+    ("Q", 1, "select"),
+    ("E", 1, "start"),
+];
+
+/// How race mode instances share the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// Every instance gets both players' controllers
+    Shared,
+    /// Player 1 drives the first instance and player 2 the second, each on port 1
+    Separate,
+}
+
+impl InputMode {
+    /// Parse a mode name ("shared" or "separate")
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "shared" => Some(InputMode::Shared),
+            "separate" => Some(InputMode::Separate),
+            _ => None,
+        }
+    }
+}
+
+/// Keys bound to each player's buttons
+pub struct KeyBindings {
+    bindings: Vec<(String, usize, u8)>,
+}
+
+impl KeyBindings {
+    /// Button state of both players from the keys held down
+    pub fn players(&self, down: &[String]) -> [Buttons; 2] {
+        let mut bits = [0u8; 2];
+        for (key, player, button) in &self.bindings {
+            if down.contains(key) {
+                bits[*player] |= button;
+            }
+        }
+        bits.map(Buttons::new)
+    }
+
+    /// Print the bindings, one player per line
+    pub fn print(&self) {
+        for player in 0..2 {
+            let keys: Vec<String> = self
+                .bindings
+                .iter()
+                .filter(|(_, p, _)| *p == player)
+                .filter_map(|(key, _, bit)| {
+                    let (name, _) = Buttons::NAMES.iter().find(|(_, b)| b == bit)?;
+                    Some(format!("{}={}", name, key))
+                })
+                .collect();
+            println!("  Player {}: {}", player + 1, keys.join(" "));
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .filter_map(|&(key, player, button)| Some((key.to_string(), player, Buttons::from_name(button)?)))
+            .collect();
+        Self { bindings }
+    }
+}
+
+/// Buttons for ports 1 and 2 of one instance, given both players' buttons
+pub fn instance_buttons(mode: InputMode, instance: usize, instances: usize, players: [Buttons; 2]) -> [Buttons; 2] {
+    match mode {
+        InputMode::Separate if instances > 1 => [players.get(instance).copied().unwrap_or_default(), Buttons::default()],
+        _ => players,
+    }
+}
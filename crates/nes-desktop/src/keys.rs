@@ -0,0 +1,22 @@
+//! Translation from minifb key events to the shared hotkey names
+
+use minifb::{Key, Window};
+use nes_core::hotkeys::Modifiers;
+
+/// Canonical hotkey name for a minifb key ("Key1" becomes "1", others keep their name)
+pub fn key_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    match name.strip_prefix("Key") {
+        Some(digit) if digit.len() == 1 => digit.to_string(),
+        _ => name,
+    }
+}
+
+/// Modifier keys currently held in the window
+pub fn modifiers(window: &Window) -> Modifiers {
+    Modifiers {
+        ctrl: window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl),
+        shift: window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift),
+        alt: window.is_key_down(Key::LeftAlt) || window.is_key_down(Key::RightAlt),
+    }
+}
@@ -1,14 +1,81 @@
 //! NES Desktop - Desktop NES emulator with minifb rendering
 //!
 //! This is a desktop version of the NES emulator that uses:
-//! - minifb for simple window creation and rendering
+//! - minifb for simple window creation and rendering (`minifb_sink`, a
+//!   `nes_core::sink::VideoSink`)
+//!
+//! With `--race` two systems run side by side in one window and reset
+//! together; `--race-input` picks whether both follow the same controllers
+//! (`shared`) or each player drives their own instance (`separate`).
+//! Controllers are played on the keyboard (see `input`).
+//! `--frameskip` renders only some frames on hosts too slow to draw all 60.
+//! Problems from `NesSystem::health()` (CPU jammed, rendering never enabled,
+//! running slowly) are announced on screen when the status changes.
+//! `--zapper` plugs a Zapper into port 2 of the first instance, aimed with the
+//! mouse and fired with the left button.
+//! `--show-dropped-sprites` marks sprites lost to the 8-per-scanline limit,
+//! and `--show-sprite-occupancy` draws each scanline's sprite count on the
+//! right edge.
+//! F7 and F8 (by default) overlay the background tile grid and attribute areas.
+//! The record_gif hotkey (F11 by default) toggles recording a GIF clip next to the ROM.
+//! `--palette-viewer` opens a second window for viewing and editing the palette.
+//! Boards with battery-backed saves (battery PRG RAM, Bandai EEPROMs, or the
+//! flash PRG of UNROM 512 homebrew) load a `.sav` file next to the main ROM,
+//! and write it back on exit if the game changed it.
+//! Playtime and notes per game are kept in `playstats.txt` in the config
+//! directory (see `nes_core::playstats`); `--no-play-stats` turns this off.
+//! `--achievements` loads an offline achievement pack (see `nes_core::achievements`)
+//! and announces unlocks on screen.
+//! Hotkeys come from `nes_core::hotkeys` and can be overridden with `--hotkeys`.
+//! `--fast-boot` caches a state at the title screen on the first boot of a ROM
+//! and restores it on later launches (see `nes_core::fast_boot`).
+//! The quit hotkey (Escape by default) opens a pause menu drawn into the
+//! frame, with reset, save/load state and a settings page for the scale,
+//! palette and console region (see `menu`). Save states go next to the ROM as
+//! `<rom>.state<slot>`, shared with the state slot hotkeys.
+//! The settings page exports hotkeys, display settings and per-game
+//! overrides to one bundle (`settings.txt` in the config directory, or
+//! `--settings`) and imports them back; an existing bundle is also read at
+//! start, with `--hotkeys` and `--scale` taking precedence.
+//! Sound from the first instance plays through cpal (see `audio`), muted
+//! while fast forwarding; `--no-audio` turns it off.
+
+mod audio;
+mod input;
+mod keys;
+mod menu;
+mod minifb_sink;
+mod pacing;
+mod palette;
 
 use clap::Parser;
+use nes_core::accuracy::AccuracyProfile;
+use nes_core::achievements::AchievementSet;
 use nes_core::cartridge::Cartridge;
+use nes_core::fast_boot::{self, FastBootCache, TitleProgress, TitleScreenDetector};
+use nes_core::frameskip::{FrameSkipper, Frameskip, MAX_FRAMESKIP};
+use nes_core::gif::GifRecorder;
+use nes_core::grid_overlay;
+use nes_core::health::HealthStatus;
+use nes_core::hotkeys::{Action, HotkeyMap, Overlay};
+use nes_core::osd::Osd;
+use nes_core::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use nes_core::playstats::{self, PlaySession, PlayStats};
+use nes_core::settings::{self, SettingsBundle};
+use nes_core::sink::VideoSink;
+use nes_core::sprite_eval;
 use nes_core::system::NesSystem;
+use nes_core::zapper::Zapper;
 use std::fs;
-use std::path::PathBuf;
-use minifb::{Window, WindowOptions, Key};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use minifb::{Window, WindowOptions, KeyRepeat, MouseButton, MouseMode};
+use audio::AudioOutput;
+use input::{InputMode, KeyBindings};
+use menu::{DisplayPalette, Menu, MenuCommand, Settings};
+use minifb_sink::MinifbSink;
+use pacing::FramePacer;
+use palette::PaletteWindow;
 
 /// NES Emulator Desktop App
 #[derive(Parser, Debug)]
@@ -19,16 +86,687 @@ struct Args {
     #[arg(short, long)]
     rom: PathBuf,
 
-    /// Screen scale factor (1-4)
-    #[arg(short, long, default_value = "2")]
-    scale: usize,
+    /// Screen scale factor (1-4; defaults to the settings bundle's, or 2)
+    #[arg(short, long)]
+    scale: Option<usize>,
+
+    /// Run a second instance side by side (race mode); pass the same ROM to race yourself
+    #[arg(long, value_name = "ROM")]
+    race: Option<PathBuf>,
+
+    /// Race mode input: 'shared' (both instances follow both controllers) or 'separate' (player 1 plays the first instance, player 2 the second)
+    #[arg(long, value_name = "MODE", default_value = "shared", value_parser = parse_input_mode)]
+    race_input: InputMode,
+
+    /// Hotkey config file with `action = combo` lines (defaults are used otherwise)
+    #[arg(long, value_name = "FILE")]
+    hotkeys: Option<PathBuf>,
+
+    /// Settings bundle read at start and used by the menu's export and import (defaults to settings.txt in the config directory)
+    #[arg(long, value_name = "FILE")]
+    settings: Option<PathBuf>,
+
+    /// Keep running while the window is unfocused instead of pausing
+    #[arg(long)]
+    run_in_background: bool,
+
+    /// Frame skipping for slow hosts: 'off', render one frame in N (2-4), or 'auto'
+    #[arg(long, value_name = "MODE", default_value = "off", value_parser = parse_frameskip)]
+    frameskip: Frameskip,
+
+    /// Plug a Zapper into port 2; aim with the mouse, left button pulls the trigger
+    #[arg(long)]
+    zapper: bool,
+
+    /// Highlight sprites the hardware drops beyond 8 per scanline (magenta, with a red count bar)
+    #[arg(long)]
+    show_dropped_sprites: bool,
+
+    /// Draw the number of sprites on each scanline as bars on the right edge (yellow at the limit, red beyond)
+    #[arg(long)]
+    show_sprite_occupancy: bool,
+
+    /// Longest GIF clip the record hotkey captures, in frames
+    #[arg(long, value_name = "FRAMES", default_value = "600")]
+    gif_max_frames: u32,
+
+    /// GIF scale factor (1 or 2, nearest neighbour)
+    #[arg(long, value_name = "N", default_value = "1", value_parser = clap::value_parser!(u8).range(1..=2))]
+    gif_scale: u8,
+
+    /// Open a palette viewer window; click entries to edit them live (right click steps back)
+    #[arg(long)]
+    palette_viewer: bool,
+
+    /// Offline achievement pack (`Title: condition && condition [for FRAMES]` lines)
+    #[arg(long, value_name = "FILE")]
+    achievements: Option<PathBuf>,
+
+    /// Play statistics file (defaults to playstats.txt in the config directory)
+    #[arg(long, value_name = "FILE")]
+    play_stats: Option<PathBuf>,
+
+    /// Don't record playtime for this session
+    #[arg(long, conflicts_with = "play_stats")]
+    no_play_stats: bool,
+
+    /// Skip intros: restore the title screen state cached on the first boot of the ROM
+    #[arg(long)]
+    fast_boot: bool,
+
+    /// Don't open an audio device
+    #[arg(long)]
+    no_audio: bool,
 }
 
 fn main() {
     let args = Args::parse();
 
+    let settings_path = args.settings.clone().or_else(settings::default_path);
+    let mut bundle = load_bundle(settings_path.as_deref(), args.settings.is_some());
+    let mut hotkeys = match (&args.hotkeys, &bundle.hotkeys) {
+        (None, Some(hotkeys)) => hotkeys.clone(),
+        _ => load_hotkeys(args.hotkeys.as_deref()),
+    };
+    let mut osd = Osd::new();
+    let mut systems = vec![load_system(&args.rom, &bundle)];
+    load_battery(&mut systems[0], &args.rom);
+    if let Some(race_rom) = &args.race {
+        systems.push(load_system(race_rom, &bundle));
+    }
+    for system in &mut systems {
+        system.set_accuracy(AccuracyProfile { frameskip: args.frameskip, ..system.accuracy() });
+    }
+    if args.zapper {
+        systems[0].set_zapper(Some(Zapper::new()));
+    }
+    let fast_boot_cache = args.fast_boot.then(FastBootCache::default_dir).flatten().map(FastBootCache::new);
+    if args.fast_boot && fast_boot_cache.is_none() {
+        eprintln!("Fast boot disabled: no cache directory found");
+    }
+    // Instances that booted from reset watch for their title screen to cache it
+    let mut title_watch: Vec<Option<TitleScreenDetector>> = systems
+        .iter_mut()
+        .map(|system| fast_boot_cache.as_ref().and_then(|cache| start_fast_boot(cache, system, &mut osd)))
+        .collect();
+    if let Some(path) = &args.achievements {
+        let pack = load_achievements(path);
+        println!("Loaded {} achievements from {}", pack.achievements().len(), path.display());
+        for system in &mut systems {
+            system.set_achievements(Some(pack.clone()));
+        }
+    }
+
+    let audio = if args.no_audio { None } else { open_audio(&mut systems[0]) };
+
+    // Instances are laid out side by side in one buffer
+    let mut display = MinifbSink::new(systems.len());
+
+    // Create window with specified scale
+    // The ROM header's region is the default; a saved setting overrides it
+    let mut settings = Settings { scale: 2, palette: DisplayPalette::Standard, region: systems[0].region() }.import(&bundle);
+    if let Some(scale) = args.scale {
+        settings.scale = scale.clamp(1, menu::MAX_SCALE);
+    }
+    for system in &mut systems {
+        system.set_region(settings.region);
+    }
+    let mut window = open_window(display.width(), settings);
+    let mut menu = Menu::new(settings);
+    let mut palette_window = args.palette_viewer.then(|| PaletteWindow::new(settings.scale));
+
+    println!("\nStarting NES emulation...");
+    if systems.len() > 1 {
+        println!("Race mode: the reset hotkey resets both instances.");
+        if args.race_input == InputMode::Separate {
+            println!("Player 1 plays the left instance, player 2 the right one.");
+        }
+    }
+    let key_bindings = KeyBindings::default();
+    println!("Controls:");
+    key_bindings.print();
+    println!("Hotkeys:");
+    for (combo, action) in hotkeys.bindings() {
+        println!("  {:<12} {}", combo.to_string(), action.name());
+    }
+    println!("The quit hotkey opens the pause menu.");
+    if let Some(name) = args.rom.file_name() {
+        osd.show(format!("Loaded {}", name.to_string_lossy()));
+    }
+    let stats_path = if args.no_play_stats { None } else { args.play_stats.clone().or_else(playstats::default_path) };
+    let session = stats_path.as_deref().and_then(|path| start_session(&systems[0], path, &mut osd));
+
+    let mut pacer = FramePacer::new(!args.run_in_background);
+    let mut skipper = FrameSkipper::new(systems[0].accuracy().frameskip);
+    let mut tile_grid = false;
+    let mut attribute_grid = false;
+    let mut health_status = HealthStatus::Ok;
+    while window.is_open() {
+        let started = Instant::now();
+        // Editing the palette shouldn't pause the game it previews
+        let palette_focused = palette_window.as_mut().is_some_and(|palette| palette.is_active());
+        if let Some(message) = pacer.set_focused(window.is_active() || palette_focused) {
+            osd.show(message);
+        }
+
+        let modifiers = keys::modifiers(&window);
+        let pressed: Vec<String> = window
+            .get_keys_pressed(KeyRepeat::No)
+            .into_iter()
+            .map(keys::key_name)
+            .collect();
+
+        let mut quit = false;
+        if menu.is_open() {
+            let commands: Vec<MenuCommand> = pressed.iter().filter_map(|key| menu.press(key)).collect();
+            for command in commands {
+                match command {
+                    MenuCommand::Resume => {}
+                    MenuCommand::Reset => {
+                        for system in &mut systems {
+                            system.reset();
+                        }
+                        osd.show("Reset");
+                    }
+                    MenuCommand::SaveState(slot) => save_state(&systems[0], &args.rom, slot, &mut osd),
+                    MenuCommand::LoadState(slot) => load_state(&mut systems[0], &args.rom, slot, &mut osd),
+                    MenuCommand::Quit => quit = true,
+                    MenuCommand::Apply(changed) => {
+                        if changed.scale != settings.scale {
+                            window = open_window(display.width(), changed);
+                        }
+                        display.set_palette(changed.palette);
+                        window.set_target_fps(menu::region_fps(changed.region));
+                        if changed.region != settings.region {
+                            for system in &mut systems {
+                                system.set_region(changed.region);
+                            }
+                        }
+                        settings = changed;
+                    }
+                    MenuCommand::ExportSettings => {
+                        // Start from the last bundle so sections from other versions survive
+                        bundle.hotkeys = Some(hotkeys.clone());
+                        settings.export(&mut bundle);
+                        bundle.add_games(systems[0].rom_database());
+                        export_bundle(&bundle, settings_path.as_deref(), &mut osd);
+                    }
+                    MenuCommand::ImportSettings => {
+                        let Some(imported) = import_bundle(settings_path.as_deref(), &mut osd) else {
+                            continue;
+                        };
+                        if let Some(imported_hotkeys) = &imported.hotkeys {
+                            hotkeys = imported_hotkeys.clone();
+                        }
+                        for system in &mut systems {
+                            imported.apply_games(system.rom_database_mut());
+                        }
+                        let changed = settings.import(&imported);
+                        if changed.scale != settings.scale {
+                            window = open_window(display.width(), changed);
+                        }
+                        display.set_palette(changed.palette);
+                        window.set_target_fps(menu::region_fps(changed.region));
+                        if changed.region != settings.region {
+                            for system in &mut systems {
+                                system.set_region(changed.region);
+                            }
+                        }
+                        menu.set_settings(changed);
+                        settings = changed;
+                        bundle = imported;
+                    }
+                }
+            }
+        }
+        let actions = if menu.is_open() { Vec::new() } else { hotkeys.pressed_actions(pressed.iter().map(String::as_str), modifiers) };
+        for action in actions {
+            match action {
+                Action::Quit => menu.open(),
+                Action::Reset => {
+                    // Reset all instances together so races start on the same frame
+                    for system in &mut systems {
+                        system.reset();
+                    }
+                    osd.show("Reset");
+                }
+                Action::Pause => {
+                    let paused = pacer.toggle_pause();
+                    osd.show(if paused { "Paused" } else { "Resumed" });
+                }
+                Action::ToggleOverlay(overlay) => {
+                    let (shown, label) = match overlay {
+                        Overlay::TileGrid => (&mut tile_grid, "Tile grid"),
+                        Overlay::AttributeGrid => (&mut attribute_grid, "Attribute grid"),
+                    };
+                    *shown = !*shown;
+                    osd.show(format!("{} {}", label, if *shown { "on" } else { "off" }));
+                }
+                Action::RecordGif => {
+                    if systems[0].gif_recording().is_some() {
+                        finish_gif(&mut systems[0], &mut osd);
+                    } else {
+                        let path = gif_path(&args.rom, systems[0].frame_count());
+                        let recorder = GifRecorder::new(path, args.gif_max_frames).with_scale(args.gif_scale as usize);
+                        systems[0].start_gif_recording_with(recorder);
+                        osd.show("Recording GIF");
+                    }
+                }
+                Action::SaveState(slot) => save_state(&systems[0], &args.rom, slot, &mut osd),
+                Action::LoadState(slot) => load_state(&mut systems[0], &args.rom, slot, &mut osd),
+                // Held action, checked below
+                Action::FastForward => {}
+                other => osd.show(format!("{} is not available yet", other.name())),
+            }
+        }
+        if quit {
+            break;
+        }
+
+        let down: Vec<String> = window.get_keys().into_iter().map(keys::key_name).collect();
+        let fast_forward = hotkeys.is_held(Action::FastForward, down.iter().map(String::as_str), modifiers);
+        // The game doesn't see keys pressed while the menu is open
+        let players = if menu.is_open() { Default::default() } else { key_bindings.players(&down) };
+        let instances = systems.len();
+        for (index, system) in systems.iter_mut().enumerate() {
+            let ports = input::instance_buttons(args.race_input, index, instances, players);
+            for (port, buttons) in ports.into_iter().enumerate() {
+                system.set_buttons(port, buttons);
+            }
+        }
+        let frames = if menu.is_open() { 0 } else { pacer.frames_to_run(fast_forward) };
+        if let Some(audio) = &audio {
+            audio.set_paused(frames == 0);
+        }
+        osd.tick();
+        if let Some(palette) = palette_window.as_mut() {
+            palette.update(&mut systems[0], &mut osd);
+        }
+
+        if let Some(zapper) = systems[0].zapper_mut() {
+            aim_zapper(zapper, &window, settings.scale);
+        }
+
+        // Skipped frames are still emulated, just not drawn
+        let render = skipper.should_render() || menu.is_open();
+        for (index, system) in systems.iter_mut().enumerate() {
+            // Run emulation for this display frame
+            let _ = system.run_frames(frames);
+            if let (0, Some(audio)) = (index, &audio) {
+                let samples = system.take_audio_samples_f32();
+                if !fast_forward {
+                    audio.push(&samples);
+                }
+                system.set_sample_rate(audio.adjusted_rate());
+            }
+            if let (Some(detector), Some(cache)) = (title_watch[index].as_mut(), fast_boot_cache.as_ref()) {
+                match detector.observe(system) {
+                    TitleProgress::Booting => {}
+                    TitleProgress::Reached => {
+                        match cache.store(system) {
+                            Ok(path) => println!("Cached title screen state in {}", path.display()),
+                            Err(e) => eprintln!("Failed to cache title screen state: {}", e),
+                        }
+                        title_watch[index] = None;
+                    }
+                    TitleProgress::GaveUp => title_watch[index] = None,
+                }
+            }
+            if system.gif_recording().is_some_and(|recording| recording.is_full()) {
+                finish_gif(system, &mut osd);
+            }
+            for diagnostic in system.take_crash_diagnostics() {
+                eprintln!("Warning: {}", diagnostic);
+                osd.show(format!("Game may have crashed: {}", diagnostic.signature));
+            }
+            for title in system.take_unlocked_achievements() {
+                let player = if index > 0 { format!("P{} ", index + 1) } else { String::new() };
+                println!("{}Achievement unlocked: {}", player, title);
+                osd.show(format!("{}Unlocked: {}", player, title));
+            }
+
+            if !render {
+                continue;
+            }
+            // Render the frame and draw overlays on top
+            let mut frame = system.ppu_mut().snapshot_frame();
+            let framebuffer = frame.pixels_mut();
+            if args.show_dropped_sprites || args.show_sprite_occupancy {
+                let lines = system.ppu().evaluate_sprites();
+                if args.show_dropped_sprites {
+                    sprite_eval::draw_dropped_sprites(framebuffer, system.ppu().oam(), &lines);
+                }
+                if args.show_sprite_occupancy {
+                    sprite_eval::draw_occupancy(framebuffer, &lines);
+                }
+            }
+            if tile_grid {
+                grid_overlay::draw_tile_grid(system.ppu(), framebuffer);
+            }
+            if attribute_grid {
+                grid_overlay::draw_attribute_grid(system.ppu(), framebuffer);
+            }
+            osd.draw(framebuffer);
+            if index == 0 {
+                menu.draw(framebuffer);
+            }
+
+            display.select_column(index);
+            display.on_frame(&frame);
+        }
+
+        if render {
+            display.present(&mut window).expect("Failed to update window");
+        } else {
+            window.update();
+        }
+        skipper.record(started.elapsed());
+        if frames > 0 {
+            // Paused time isn't slowness
+            systems[0].record_host_time(frames, started.elapsed());
+        }
+        let health = systems[0].health();
+        if health.status() != health_status {
+            health_status = health.status();
+            if health_status != HealthStatus::Ok {
+                osd.show(health.to_string());
+            }
+        }
+    }
+
+    finish_gif(&mut systems[0], &mut osd);
+    save_battery(&mut systems[0], &args.rom);
+    if let (Some(session), Some(path)) = (session, &stats_path) {
+        finish_session(session, path);
+    }
+    println!("Emulator closed.");
+}
+
+/// Open the audio device and turn on the system's audio at its rate
+fn open_audio(system: &mut NesSystem) -> Option<AudioOutput> {
+    match AudioOutput::open() {
+        Ok(audio) => {
+            system.set_sample_rate(audio.sample_rate());
+            system.set_audio_enabled(true);
+            Some(audio)
+        }
+        Err(e) => {
+            eprintln!("Audio disabled: {}", e);
+            None
+        }
+    }
+}
+
+/// Open the game window sized for `width` frame pixels at the settings' scale
+fn open_window(width: usize, settings: Settings) -> Window {
+    let mut window = Window::new(
+        "NES Emulator",
+        width * settings.scale,
+        FRAME_HEIGHT * settings.scale,
+        WindowOptions {
+            resize: false,
+            ..WindowOptions::default()
+        },
+    ).expect("Failed to create window");
+    window.set_target_fps(menu::region_fps(settings.region));
+    window
+}
+
+/// Aim the Zapper at the pixel under the mouse (first instance's column only)
+fn aim_zapper(zapper: &mut Zapper, window: &Window, scale: usize) {
+    let aim = window.get_mouse_pos(MouseMode::Discard).and_then(|(x, y)| {
+        let (x, y) = (x as usize / scale, y as usize / scale);
+        (x < FRAME_WIDTH && y < FRAME_HEIGHT).then_some((x as u8, y as u8))
+    });
+    zapper.set_aim(aim);
+    zapper.set_trigger(window.get_mouse_down(MouseButton::Left));
+}
+
+fn parse_frameskip(text: &str) -> Result<Frameskip, String> {
+    Frameskip::parse(text).ok_or_else(|| format!("invalid frameskip '{}' (expected 'off', 2-{} or 'auto')", text, MAX_FRAMESKIP))
+}
+
+fn parse_input_mode(text: &str) -> Result<InputMode, String> {
+    InputMode::parse(text).ok_or_else(|| format!("invalid input mode '{}' (expected 'shared' or 'separate')", text))
+}
+
+/// Clip path next to the ROM, named after the frame recording started on
+fn gif_path(rom: &Path, frame: u64) -> PathBuf {
+    let stem = rom.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    rom.with_file_name(format!("{}-{}.gif", stem, frame))
+}
+
+/// Stop a GIF recording in progress and report where it went
+fn finish_gif(system: &mut NesSystem, osd: &mut Osd) {
+    let message = match system.stop_gif_recording() {
+        Some(Ok(path)) => format!("Saved {}", path.display()),
+        Some(Err(e)) => e.to_string(),
+        None => return,
+    };
+    println!("{}", message);
+    osd.show(message);
+}
+
+/// Save state file for a slot, next to the ROM
+fn state_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+/// Write the system's state to a slot file
+fn save_state(system: &NesSystem, rom: &Path, slot: u8, osd: &mut Osd) {
+    let path = state_path(rom, slot);
+    match fs::write(&path, system.save_state()) {
+        Ok(()) => osd.show(format!("State {} saved", slot)),
+        Err(e) => {
+            eprintln!("Failed to write state {}: {}", path.display(), e);
+            osd.show(format!("State {} not saved", slot));
+        }
+    }
+}
+
+/// Restore the system's state from a slot file
+fn load_state(system: &mut NesSystem, rom: &Path, slot: u8, osd: &mut Osd) {
+    let path = state_path(rom, slot);
+    let loaded = fs::read(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| system.load_state(&data).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(()) => osd.show(format!("State {} loaded", slot)),
+        Err(e) => {
+            eprintln!("Failed to load state {}: {}", path.display(), e);
+            osd.show(format!("State {} not loaded", slot));
+        }
+    }
+}
+
+/// Restore the cached title screen state, or return a detector to cache one from this boot
+fn start_fast_boot(cache: &FastBootCache, system: &mut NesSystem, osd: &mut Osd) -> Option<TitleScreenDetector> {
+    if !fast_boot::supported(system) {
+        println!("Fast boot is not used for games with battery saves");
+        return None;
+    }
+    match cache.restore(system) {
+        Ok(true) => {
+            osd.show("Fast boot");
+            None
+        }
+        Ok(false) => Some(TitleScreenDetector::new()),
+        Err(e) => {
+            eprintln!("{}", e);
+            Some(TitleScreenDetector::new())
+        }
+    }
+}
+
+/// Restore battery-backed save data from `<rom>.sav` if the board has any
+fn load_battery(system: &mut NesSystem, rom: &Path) {
+    if system.battery_data().is_none() {
+        return;
+    }
+    let path = rom.with_extension("sav");
+    match fs::read(&path) {
+        Ok(data) => {
+            system.load_battery_data(&data);
+            println!("Loaded save data from {}", path.display());
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to read save data {}: {}", path.display(), e),
+    }
+}
+
+/// Write battery-backed save data to `<rom>.sav` if the session changed it
+fn save_battery(system: &mut NesSystem, rom: &Path) {
+    if !system.take_battery_dirty() {
+        return;
+    }
+    let Some(data) = system.battery_data() else {
+        return;
+    };
+    let path = rom.with_extension("sav");
+    match fs::write(&path, data) {
+        Ok(()) => println!("Saved save data to {}", path.display()),
+        Err(e) => eprintln!("Failed to write save data {}: {}", path.display(), e),
+    }
+}
+
+/// Show the game's playtime and notes so far, and start timing this session
+fn start_session(system: &NesSystem, path: &Path, osd: &mut Osd) -> Option<PlaySession> {
+    let crc32 = system.rom_crc32()?;
+    match PlayStats::load(path) {
+        Ok(stats) => {
+            if let Some(game) = stats.get(crc32) {
+                osd.show(format!("Played {}", playstats::format_playtime(game.playtime)));
+                if !game.notes.is_empty() {
+                    println!("Notes:\n{}", game.notes);
+                }
+            }
+        }
+        // Keep playing; the session is still added to whatever can be read at exit
+        Err(e) => eprintln!("{}", e),
+    }
+    Some(PlaySession::start(crc32))
+}
+
+/// Add the session's playtime to the statistics file
+fn finish_session(session: PlaySession, path: &Path) {
+    let mut stats = match PlayStats::load(path) {
+        Ok(stats) => stats,
+        Err(e) => {
+            // Don't overwrite a file we couldn't parse
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let played = session.finish(&mut stats);
+    match stats.save(path) {
+        Ok(()) => println!("Played {} this session", playstats::format_playtime(played)),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// Load an achievement pack, exiting on error
+fn load_achievements(path: &Path) -> AchievementSet {
+    let parsed = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| AchievementSet::parse(&text).map_err(|e| e.to_string()));
+    match parsed {
+        Ok(pack) => pack,
+        Err(e) => {
+            eprintln!("Failed to load achievements from {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load the hotkey map from a config file (or the defaults), exiting on error
+fn load_hotkeys(path: Option<&Path>) -> HotkeyMap {
+    let Some(path) = path else {
+        return HotkeyMap::default();
+    };
+    let parsed = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| HotkeyMap::from_config(&text).map_err(|e| e.to_string()));
+    match parsed {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("Failed to load hotkeys from {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read the settings bundle at start, exiting on error
+///
+/// A missing default bundle is just no settings; a missing `--settings` file
+/// is created by the first export.
+fn load_bundle(path: Option<&Path>, explicit: bool) -> SettingsBundle {
+    let Some(path) = path.filter(|path| path.is_file()) else {
+        if let (true, Some(path)) = (explicit, path) {
+            println!("Settings file {} doesn't exist yet; export from the menu to create it", path.display());
+        }
+        return SettingsBundle::new();
+    };
+    match SettingsBundle::load(path) {
+        Ok(bundle) => {
+            for skipped in bundle.skipped() {
+                eprintln!("Skipped setting in {}: {}", path.display(), skipped);
+            }
+            bundle
+        }
+        Err(e) => {
+            eprintln!("Failed to load settings: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Write the settings bundle, reporting the result on screen
+fn export_bundle(bundle: &SettingsBundle, path: Option<&Path>, osd: &mut Osd) {
+    let Some(path) = path else {
+        osd.show("No config directory; pass --settings FILE");
+        return;
+    };
+    match bundle.save(path) {
+        Ok(()) => osd.show(format!("Settings exported to {}", path.display())),
+        Err(e) => {
+            eprintln!("Failed to export settings: {}", e);
+            osd.show("Settings not exported");
+        }
+    }
+}
+
+/// Read the settings bundle for an import, reporting problems on screen
+fn import_bundle(path: Option<&Path>, osd: &mut Osd) -> Option<SettingsBundle> {
+    let Some(path) = path else {
+        osd.show("No config directory; pass --settings FILE");
+        return None;
+    };
+    match SettingsBundle::load(path) {
+        Ok(bundle) if bundle.skipped().is_empty() => {
+            osd.show("Settings imported");
+            Some(bundle)
+        }
+        Ok(bundle) => {
+            for skipped in bundle.skipped() {
+                eprintln!("Skipped setting in {}: {}", path.display(), skipped);
+            }
+            osd.show(format!("Settings imported ({} skipped)", bundle.skipped().len()));
+            Some(bundle)
+        }
+        Err(e) => {
+            eprintln!("Failed to import settings: {}", e);
+            osd.show("Settings not imported");
+            None
+        }
+    }
+}
+
+/// Load a ROM file into a freshly reset system, exiting on error
+///
+/// The bundle's per-game overrides are in place before the ROM loads.
+fn load_system(path: &Path, bundle: &SettingsBundle) -> NesSystem {
     // Load ROM file
-    let rom_data = match fs::read(&args.rom) {
+    let rom_data = match fs::read(path) {
         Ok(data) => data,
         Err(e) => {
             eprintln!("Failed to read ROM file: {}", e);
@@ -45,13 +783,14 @@ fn main() {
         }
     };
 
-    println!("Loaded cartridge:");
+    println!("Loaded cartridge {}:", path.display());
     println!("  PRG ROM: {} bytes", cartridge.prg_rom().len());
     println!("  CHR ROM: {} bytes", cartridge.chr_rom().len());
     println!("  Mapper: {:?}", cartridge.mapper());
 
     // Create and initialize system
     let mut system = NesSystem::new();
+    bundle.apply_games(system.rom_database_mut());
     if let Err(e) = system.load_rom(&rom_data) {
         eprintln!("Failed to load ROM: {}", e);
         std::process::exit(1);
@@ -64,64 +803,5 @@ fn main() {
         system.ppu_mut().set_chr_rom(chr_rom);
     }
 
-    // NES resolution is 256x240
-    let nes_width = 256;
-    let nes_height = 240;
-
-    // Create window with specified scale
-    let scale = args.scale.min(4).max(1);
-    let window_width = nes_width * scale;
-    let window_height = nes_height * scale;
-
-    let mut window = Window::new(
-        "NES Emulator",
-        window_width,
-        window_height,
-        WindowOptions {
-            resize: false,
-            ..WindowOptions::default()
-        },
-    ).expect("Failed to create window");
-
-    // Framebuffer storage (RGB bytes for PPU)
-    let mut framebuffer = vec![0u8; nes_width * nes_height * 3];
-
-    // Convert RGB to RGBA for minifb (0xAABBGGRR format - little endian BGRA)
-    let mut rgba_buffer = vec![0u32; nes_width * nes_height];
-
-    println!("\nStarting NES emulation...");
-    println!("Press ESC or close the window to exit.");
-
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        // Run one frame of emulation
-        let _ = system.run_frames(1);
-
-        // Get PPU for rendering
-        let ppu = system.ppu();
-
-        // Render framebuffer from PPU
-        render_framebuffer(ppu, &mut framebuffer, nes_width, nes_height);
-
-        // Convert RGB to RGBA (minifb uses 0xAABBGGRR format)
-        for i in 0..nes_width * nes_height {
-            let r = framebuffer[i * 3];
-            let g = framebuffer[i * 3 + 1];
-            let b = framebuffer[i * 3 + 2];
-            // Convert to minifb format: 0xAABBGGRR (little endian)
-            rgba_buffer[i] = ((255u32) << 24) | ((b as u32) << 16) | ((g as u32) << 8) | (r as u32);
-        }
-
-        // Update window with framebuffer
-        window
-            .update_with_buffer(&rgba_buffer, nes_width, nes_height)
-            .expect("Failed to update window");
-    }
-
-    println!("Emulator closed.");
+    system
 }
-
-fn render_framebuffer(ppu: &nes_core::ppu::Ppu, framebuffer: &mut [u8], width: usize, height: usize) {
-    for y in 0..height {
-        ppu.render_scanline(y, framebuffer, width);
-    }
-}
\ No newline at end of file
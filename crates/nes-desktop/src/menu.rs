@@ -0,0 +1,307 @@
+//! Pause menu and settings page drawn into the game frame
+//!
+//! The quit hotkey (Escape by default) opens the menu and pauses emulation.
+//! Up and Down move the selection, Enter picks an entry, Left and Right change
+//! the state slot or a setting, and Escape goes back (closing the menu from
+//! the main page). The menu only returns `MenuCommand`s; the main loop
+//! carries them out.
+//!
+//! The settings page can also export everything to the settings bundle
+//! (`nes_core::settings`) and import it back; `Settings` are stored in its
+//! `[frontend]` section.
+
+use nes_core::hotkeys::STATE_SLOTS;
+use nes_core::osd::draw_text;
+use nes_core::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use nes_core::region::Region;
+use nes_core::settings::SettingsBundle;
+use std::fmt;
+
+/// Largest window scale offered
+pub const MAX_SCALE: usize = 4;
+
+/// Left edge of the menu text
+const TEXT_X: usize = 72;
+/// Top of the menu title
+const TEXT_Y: usize = 80;
+/// Distance between entries in pixels
+const LINE_HEIGHT: usize = 10;
+
+/// Color treatment applied when frames are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayPalette {
+    /// The core's master palette as is
+    Standard,
+    /// Luma only, like a black and white TV
+    Greyscale,
+}
+
+impl DisplayPalette {
+    /// Pack an RGB pixel the way `MinifbSink` stores it (0xAABBGGRR)
+    pub fn pack(self, r: u8, g: u8, b: u8) -> u32 {
+        let (r, g, b) = match self {
+            DisplayPalette::Standard => (r, g, b),
+            DisplayPalette::Greyscale => {
+                let luma = ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8;
+                (luma, luma, luma)
+            }
+        };
+        (255u32 << 24) | ((b as u32) << 16) | ((g as u32) << 8) | r as u32
+    }
+
+    fn next(self) -> Self {
+        match self {
+            DisplayPalette::Standard => DisplayPalette::Greyscale,
+            DisplayPalette::Greyscale => DisplayPalette::Standard,
+        }
+    }
+
+    /// Name in the settings bundle
+    fn name(self) -> &'static str {
+        match self {
+            DisplayPalette::Standard => "standard",
+            DisplayPalette::Greyscale => "greyscale",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [DisplayPalette::Standard, DisplayPalette::Greyscale].into_iter().find(|palette| palette.name() == name)
+    }
+}
+
+impl fmt::Display for DisplayPalette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DisplayPalette::Standard => "Standard",
+            DisplayPalette::Greyscale => "Greyscale",
+        })
+    }
+}
+
+/// Frames shown per second in a region (the window's target rate)
+pub fn region_fps(region: Region) -> usize {
+    (1.0 / region.frame_duration().as_secs_f64()).round() as usize
+}
+
+/// Step through `Region::NAMES` in either direction
+fn step_region(region: Region, forward: bool) -> Region {
+    let count = Region::NAMES.len();
+    let index = Region::NAMES.iter().position(|name| *name == region.name()).unwrap_or(0);
+    let next = if forward { (index + 1) % count } else { (index + count - 1) % count };
+    Region::from_name(Region::NAMES[next]).unwrap_or_default()
+}
+
+/// Region as shown on the settings page
+fn region_label(region: Region) -> String {
+    let name = match region {
+        Region::Ntsc => "NTSC",
+        Region::Pal => "PAL",
+        Region::Dendy => "Dendy",
+        _ => region.name(),
+    };
+    format!("{} {}Hz", name, region_fps(region))
+}
+
+/// Display settings changed from the settings page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Window scale factor (1 to `MAX_SCALE`)
+    pub scale: usize,
+    pub palette: DisplayPalette,
+    /// Console region the systems emulate and the window is paced for
+    pub region: Region,
+}
+
+impl Settings {
+    /// Store the settings in a bundle's `[frontend]` section
+    pub fn export(&self, bundle: &mut SettingsBundle) {
+        bundle.set_frontend_value("scale", self.scale);
+        bundle.set_frontend_value("palette", self.palette.name());
+        bundle.set_frontend_value("region", self.region.name());
+    }
+
+    /// Read settings from a bundle, keeping these values for anything missing or invalid
+    pub fn import(&self, bundle: &SettingsBundle) -> Self {
+        let scale = bundle.frontend_value("scale").and_then(|scale| scale.parse().ok());
+        Self {
+            scale: scale.filter(|scale| (1..=MAX_SCALE).contains(scale)).unwrap_or(self.scale),
+            palette: bundle.frontend_value("palette").and_then(DisplayPalette::from_name).unwrap_or(self.palette),
+            region: bundle.frontend_value("region").and_then(Region::from_name).unwrap_or(self.region),
+        }
+    }
+}
+
+/// Something the main loop should do for the menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuCommand {
+    /// The menu closed; unpause
+    Resume,
+    Reset,
+    SaveState(u8),
+    LoadState(u8),
+    Quit,
+    /// A setting changed; apply the new settings
+    Apply(Settings),
+    /// Write all settings to the settings bundle
+    ExportSettings,
+    /// Replace the settings with the ones in the settings bundle
+    ImportSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Main,
+    Settings,
+}
+
+const MAIN_ENTRIES: usize = 6;
+const SETTINGS_ENTRIES: usize = 6;
+
+/// Pause menu state
+#[derive(Debug, Clone)]
+pub struct Menu {
+    open: bool,
+    page: Page,
+    selected: usize,
+    /// State slot used by the save and load entries
+    slot: u8,
+    settings: Settings,
+}
+
+impl Menu {
+    /// Create a closed menu starting from the given settings
+    pub fn new(settings: Settings) -> Self {
+        Self { open: false, page: Page::Main, selected: 0, slot: 1, settings }
+    }
+
+    /// Check if the menu is showing (emulation stays paused while it is)
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Replace the settings shown on the settings page (after an import)
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    /// Show the main page with Resume selected
+    pub fn open(&mut self) {
+        self.open = true;
+        self.page = Page::Main;
+        self.selected = 0;
+    }
+
+    /// Handle a key press (a `keys::key_name`), returning what to do
+    pub fn press(&mut self, key: &str) -> Option<MenuCommand> {
+        let entries = match self.page {
+            Page::Main => MAIN_ENTRIES,
+            Page::Settings => SETTINGS_ENTRIES,
+        };
+        match key {
+            "Up" => self.selected = (self.selected + entries - 1) % entries,
+            "Down" => self.selected = (self.selected + 1) % entries,
+            "Left" => return self.adjust(false),
+            "Right" => return self.adjust(true),
+            "Enter" | "Space" => return self.choose(),
+            "Escape" => return self.back(),
+            _ => {}
+        }
+        None
+    }
+
+    fn choose(&mut self) -> Option<MenuCommand> {
+        let command = match (self.page, self.selected) {
+            (Page::Main, 0) => MenuCommand::Resume,
+            (Page::Main, 1) => MenuCommand::Reset,
+            (Page::Main, 2) => MenuCommand::SaveState(self.slot),
+            (Page::Main, 3) => MenuCommand::LoadState(self.slot),
+            (Page::Main, 4) => {
+                self.page = Page::Settings;
+                self.selected = 0;
+                return None;
+            }
+            (Page::Main, _) => MenuCommand::Quit,
+            // The bundle entries leave the menu open on the page
+            (Page::Settings, 3) => return Some(MenuCommand::ExportSettings),
+            (Page::Settings, 4) => return Some(MenuCommand::ImportSettings),
+            (Page::Settings, 5) => return self.back(),
+            (Page::Settings, _) => return self.adjust(true),
+        };
+        if command != MenuCommand::Quit {
+            self.open = false;
+        }
+        Some(command)
+    }
+
+    fn back(&mut self) -> Option<MenuCommand> {
+        match self.page {
+            Page::Main => {
+                self.open = false;
+                Some(MenuCommand::Resume)
+            }
+            Page::Settings => {
+                self.page = Page::Main;
+                self.selected = 4;
+                None
+            }
+        }
+    }
+
+    /// Step the slot or setting under the selection
+    fn adjust(&mut self, forward: bool) -> Option<MenuCommand> {
+        let settings = &mut self.settings;
+        match (self.page, self.selected) {
+            (Page::Main, 2 | 3) => {
+                self.slot = if forward { self.slot % STATE_SLOTS + 1 } else { (self.slot + STATE_SLOTS - 2) % STATE_SLOTS + 1 };
+                return None;
+            }
+            (Page::Settings, 0) => {
+                settings.scale = if forward { settings.scale % MAX_SCALE + 1 } else { (settings.scale + MAX_SCALE - 2) % MAX_SCALE + 1 };
+            }
+            // There are only two palettes, so either direction flips them
+            (Page::Settings, 1) => settings.palette = settings.palette.next(),
+            (Page::Settings, 2) => settings.region = step_region(settings.region, forward),
+            _ => return None,
+        }
+        Some(MenuCommand::Apply(self.settings))
+    }
+
+    /// Dim a 256x240 RGB frame and draw the current page over it
+    pub fn draw(&self, framebuffer: &mut [u8]) {
+        if !self.open || framebuffer.len() < FRAME_WIDTH * FRAME_HEIGHT * 3 {
+            return;
+        }
+        for channel in framebuffer.iter_mut() {
+            *channel /= 2;
+        }
+        let (title, entries) = match self.page {
+            Page::Main => (
+                "Paused",
+                vec![
+                    "Resume".to_string(),
+                    "Reset".to_string(),
+                    format!("Save state  < {} >", self.slot),
+                    format!("Load state  < {} >", self.slot),
+                    "Settings".to_string(),
+                    "Quit".to_string(),
+                ],
+            ),
+            Page::Settings => (
+                "Settings",
+                vec![
+                    format!("Scale    < {}x >", self.settings.scale),
+                    format!("Palette  < {} >", self.settings.palette),
+                    format!("Region   < {} >", region_label(self.settings.region)),
+                    "Export settings".to_string(),
+                    "Import settings".to_string(),
+                    "Back".to_string(),
+                ],
+            ),
+        };
+        draw_text(framebuffer, TEXT_X, TEXT_Y, title);
+        for (index, entry) in entries.iter().enumerate() {
+            let marker = if index == self.selected { ">" } else { " " };
+            draw_text(framebuffer, TEXT_X, TEXT_Y + (index + 2) * LINE_HEIGHT, &format!("{} {}", marker, entry));
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Video sink presenting frames in a minifb window
+//!
+//! Race mode shows several instances side by side, so the sink's buffer has
+//! one column per instance; select a column before pushing that instance's
+//! frame, then `present` once per displayed frame. Pixels are converted
+//! through the `DisplayPalette` chosen in the settings menu.
+
+use crate::menu::DisplayPalette;
+use minifb::Window;
+use nes_core::ppu::{FrameSnapshot, FRAME_HEIGHT, FRAME_WIDTH};
+use nes_core::sink::VideoSink;
+
+/// Side-by-side framebuffer for one window
+pub struct MinifbSink {
+    /// Pixels packed as 0xAABBGGRR
+    buffer: Vec<u32>,
+    columns: usize,
+    column: usize,
+    palette: DisplayPalette,
+}
+
+impl MinifbSink {
+    /// Create a sink with room for `columns` frames side by side
+    pub fn new(columns: usize) -> Self {
+        let columns = columns.max(1);
+        Self {
+            buffer: vec![0; FRAME_WIDTH * columns * FRAME_HEIGHT],
+            columns,
+            column: 0,
+            palette: DisplayPalette::Standard,
+        }
+    }
+
+    /// Buffer width in pixels
+    pub fn width(&self) -> usize {
+        FRAME_WIDTH * self.columns
+    }
+
+    /// Choose the column the next frame is drawn into
+    pub fn select_column(&mut self, column: usize) {
+        self.column = column.min(self.columns - 1);
+    }
+
+    /// Choose how later frames are colored
+    pub fn set_palette(&mut self, palette: DisplayPalette) {
+        self.palette = palette;
+    }
+
+    /// Show the buffer in the window
+    pub fn present(&self, window: &mut Window) -> minifb::Result<()> {
+        window.update_with_buffer(&self.buffer, self.width(), FRAME_HEIGHT)
+    }
+}
+
+impl VideoSink for MinifbSink {
+    fn on_frame(&mut self, frame: &FrameSnapshot) {
+        let width = self.width();
+        let x_offset = self.column * FRAME_WIDTH;
+        for y in 0..FRAME_HEIGHT {
+            let row = &mut self.buffer[y * width + x_offset..y * width + x_offset + FRAME_WIDTH];
+            for (out, rgb) in row.iter_mut().zip(frame.line(y).chunks_exact(3)) {
+                *out = self.palette.pack(rgb[0], rgb[1], rgb[2]);
+            }
+        }
+    }
+}
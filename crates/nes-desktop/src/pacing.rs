@@ -0,0 +1,58 @@
+//! Frame pacing: decides how many emulated frames to run per displayed frame
+
+/// Frames emulated per displayed frame while fast forward is held
+pub const FAST_FORWARD_FRAMES: u64 = 4;
+
+/// Tracks pause state from the user and from window focus
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    /// Pause automatically while the window is unfocused
+    auto_pause: bool,
+    /// Paused by the user (pause hotkey)
+    user_paused: bool,
+    /// Paused because the window lost focus
+    focus_paused: bool,
+}
+
+impl FramePacer {
+    /// Create a pacer; `auto_pause` enables pausing while unfocused
+    pub fn new(auto_pause: bool) -> Self {
+        Self {
+            auto_pause,
+            user_paused: false,
+            focus_paused: false,
+        }
+    }
+
+    /// Toggle the user pause, returning the new state
+    pub fn toggle_pause(&mut self) -> bool {
+        self.user_paused = !self.user_paused;
+        self.user_paused
+    }
+
+    /// Update window focus, returning an OSD message when auto-pause changes state
+    pub fn set_focused(&mut self, focused: bool) -> Option<&'static str> {
+        let focus_paused = self.auto_pause && !focused;
+        if focus_paused == self.focus_paused {
+            return None;
+        }
+        self.focus_paused = focus_paused;
+        Some(if focus_paused { "Paused (window inactive)" } else { "Resumed" })
+    }
+
+    /// Check if emulation (and audio) should be halted
+    pub fn is_paused(&self) -> bool {
+        self.user_paused || self.focus_paused
+    }
+
+    /// Number of frames to emulate for the next displayed frame
+    pub fn frames_to_run(&self, fast_forward: bool) -> u64 {
+        if self.is_paused() {
+            0
+        } else if fast_forward {
+            FAST_FORWARD_FRAMES
+        } else {
+            1
+        }
+    }
+}
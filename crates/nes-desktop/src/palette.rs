@@ -0,0 +1,86 @@
+//! Palette viewer window with click-to-edit
+//!
+//! Shows `nes_core::palette_view`'s grid of the 32 palette entries. Left click
+//! steps an entry to the next master palette color and right click to the
+//! previous one (hold Shift to step by a row of 16). Edits go into palette
+//! RAM, so they show up in the game view on the next frame and last until the
+//! game uploads that entry again.
+
+use minifb::{Key, MouseButton, MouseMode, Window, WindowOptions};
+use nes_core::osd::Osd;
+use nes_core::palette_view;
+use nes_core::ppu::{FRAME_HEIGHT, FRAME_RGB_SIZE, FRAME_WIDTH};
+use nes_core::system::NesSystem;
+
+/// Second window showing and editing the palette of one system
+pub struct PaletteWindow {
+    window: Window,
+    scale: usize,
+    framebuffer: Vec<u8>,
+    buffer: Vec<u32>,
+    /// Mouse buttons (left, right) held during the last update, for click detection
+    held: (bool, bool),
+}
+
+impl PaletteWindow {
+    /// Open the viewer window at the given scale
+    pub fn new(scale: usize) -> Self {
+        let window = Window::new(
+            "NES Palette",
+            FRAME_WIDTH * scale,
+            FRAME_HEIGHT * scale,
+            WindowOptions {
+                resize: false,
+                ..WindowOptions::default()
+            },
+        )
+        .expect("Failed to create palette window");
+        Self {
+            window,
+            scale,
+            framebuffer: vec![0; FRAME_RGB_SIZE],
+            buffer: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+            held: (false, false),
+        }
+    }
+
+    /// Check if the viewer window has focus
+    pub fn is_active(&mut self) -> bool {
+        self.window.is_active()
+    }
+
+    /// Apply clicks to the system's palette, then redraw the grid
+    pub fn update(&mut self, system: &mut NesSystem, osd: &mut Osd) {
+        let left = self.window.get_mouse_down(MouseButton::Left);
+        let right = self.window.get_mouse_down(MouseButton::Right);
+        let step: i16 = match (left && !self.held.0, right && !self.held.1) {
+            (true, _) => 1,
+            (_, true) => -1,
+            _ => 0,
+        };
+        self.held = (left, right);
+
+        if step != 0 {
+            let shift = self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
+            let step = if shift { step * 16 } else { step };
+            let entry = self
+                .window
+                .get_mouse_pos(MouseMode::Discard)
+                .and_then(|(x, y)| palette_view::entry_at(x as usize / self.scale, y as usize / self.scale));
+            if let Some(entry) = entry {
+                let color = (system.ppu().palette_color(entry) as i16 + step).rem_euclid(64) as u8;
+                system.ppu_mut().set_palette_entry(entry, color);
+                osd.show(format!("Palette ${:04X} = ${:02X}", 0x3F00 + entry, color));
+            }
+        }
+
+        palette_view::draw_palette(system.ppu(), &mut self.framebuffer);
+        for (pixel, rgb) in self.buffer.iter_mut().zip(self.framebuffer.chunks_exact(3)) {
+            *pixel = (255u32 << 24) | ((rgb[2] as u32) << 16) | ((rgb[1] as u32) << 8) | rgb[0] as u32;
+        }
+        // A closed viewer just stops updating; the game window keeps running
+        if self.window.is_open() {
+            let _ = self.window.update_with_buffer(&self.buffer, FRAME_WIDTH, FRAME_HEIGHT);
+        }
+    }
+}
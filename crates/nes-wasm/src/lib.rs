@@ -1,15 +1,31 @@
 //! NES WASM - WASM wrapper for NES emulator
 
+use nes_core::debug_state::DebugState;
+use nes_core::interrupt::IrqSource;
+use nes_core::display::{visible_lines, CanvasSize};
+use nes_core::events::{self, Event, EventQueue};
+use nes_core::osd::Osd;
+use nes_core::ppu::FrameSnapshot;
+use nes_core::region::Region;
 use nes_core::system::NesSystem;
-use wasm_bindgen::prelude::wasm_bindgen;
-use js_sys::Uint8Array;
-use js_sys::ArrayBuffer;
-use std::slice;
+use nes_core::{Buttons, Vaus, Zapper, FRAME_WIDTH};
+use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
+use js_sys::{Float32Array, Uint8Array};
 
 /// NES Emulator wrapper for WASM
 #[wasm_bindgen]
 pub struct NesEmulator {
     system: NesSystem,
+    /// Last frame captured with `capture_frame`
+    frame: Option<FrameSnapshot>,
+    /// On-screen display messages for JS to show
+    osd: Osd,
+    /// Show the top and bottom overscan lines (cropped when false)
+    overscan: bool,
+    /// Events for `poll_events`
+    events: EventQueue,
+    /// Buttons held on each controller port, as pressed (before the D-pad policy)
+    buttons: [Buttons; 2],
 }
 
 #[wasm_bindgen]
@@ -17,33 +33,158 @@ impl NesEmulator {
     /// Create a new NES emulator
     #[wasm_bindgen(constructor)]
     pub fn new() -> NesEmulator {
+        let mut system = NesSystem::new();
+        system.set_framebuffer_enabled(true);
         Self {
-            system: NesSystem::new(),
+            system,
+            frame: None,
+            osd: Osd::new(),
+            overscan: true,
+            events: EventQueue::new(),
+            buttons: [Buttons::default(); 2],
         }
     }
 
     /// Load a ROM from bytes
     /// Returns true on success, false on failure
     pub fn load_rom(&mut self, rom_data: &[u8]) -> bool {
-        self.system.load_rom(rom_data).is_ok()
+        match self.system.load_rom(rom_data) {
+            Ok(()) => {
+                self.show("ROM loaded");
+                true
+            }
+            Err(e) => {
+                self.show(e.to_string());
+                false
+            }
+        }
     }
 
     /// Reset the emulator
     pub fn reset(&mut self) {
         self.system.reset();
+        self.show("Reset");
+    }
+
+    /// Post an on-screen message (so JS-side actions share the same queue)
+    pub fn show_message(&mut self, text: &str) {
+        self.show(text);
+    }
+
+    /// Save the full system state
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let state = self.system.save_state();
+        self.events.push(Event::StateSaved);
+        state
+    }
+
+    /// Restore a state from `save_state`
+    /// Returns true on success, false (with an on-screen message) on failure
+    pub fn load_state(&mut self, data: &[u8]) -> bool {
+        match self.system.load_state(data) {
+            Ok(()) => {
+                self.events.push(Event::StateLoaded);
+                true
+            }
+            Err(e) => {
+                self.show(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// localStorage key for the loaded ROM's battery save (`nes-sav-` and the ROM's CRC32)
+    ///
+    /// None without a ROM or when the board has no battery-backed data. JS
+    /// restores the save after `load_rom` with `load_battery_data` and writes
+    /// `battery_data` back under this key whenever `take_battery_dirty` is true
+    /// (for example on `beforeunload` or every few seconds).
+    pub fn battery_save_key(&self) -> Option<String> {
+        self.system.battery_data()?;
+        self.system.rom_crc32().map(|crc| format!("nes-sav-{:08x}", crc))
+    }
+
+    /// Get the battery-backed save data, if the board has any
+    pub fn battery_data(&self) -> Option<Vec<u8>> {
+        self.system.battery_data().map(<[u8]>::to_vec)
+    }
+
+    /// Restore battery-backed save data previously returned by `battery_data`
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        self.system.load_battery_data(data);
+    }
+
+    /// Check if the save data changed since the last call (or load), resetting the flag
+    pub fn take_battery_dirty(&mut self) -> bool {
+        self.system.take_battery_dirty()
+    }
+
+    /// Take the events queued since the last call as an array of objects
+    /// Each has a `type` ("frame_complete", "cpu_jammed", "state_saved",
+    /// "state_loaded", "achievement_unlocked" or "message") and its fields
+    /// (see `nes_core::events`)
+    pub fn poll_events(&mut self) -> JsValue {
+        let json = events::to_json(&self.events.drain());
+        js_sys::JSON::parse(&json).unwrap_or(JsValue::NULL)
+    }
+
+    /// Take the next on-screen message posted since the last poll, if any
+    pub fn poll_message(&mut self) -> Option<String> {
+        self.osd.poll()
     }
 
     /// Step the emulator once
     pub fn step(&mut self) -> bool {
-        match self.system.step() {
-            Ok(running) => running,
-            Err(_) => false,
-        }
+        self.system.step().unwrap_or_default()
     }
 
     /// Run for N frames
     pub fn run_frames(&mut self, frames: u32) {
-        let _ = self.system.run_frames(frames as u64);
+        for _ in 0..frames {
+            let result = self.system.run_frames(1);
+            self.events.observe(&mut self.system);
+            if result.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Set the console region: "ntsc", "pal" or "dendy"
+    /// Returns false for an unknown name. Loading a NES 2.0 ROM that names a
+    /// region also sets it, so call this after `load_rom` to override it.
+    pub fn set_region(&mut self, region: &str) -> bool {
+        let Some(region) = Region::from_name(region) else {
+            return false;
+        };
+        self.system.set_region(region);
+        true
+    }
+
+    /// Get the console region's name
+    pub fn region(&self) -> String {
+        self.system.region().name().to_string()
+    }
+
+    /// Get the length of a frame in milliseconds, for pacing `run_frames`
+    pub fn frame_duration_ms(&self) -> f64 {
+        self.system.region().frame_duration().as_secs_f64() * 1000.0
+    }
+
+    /// Set the audio sample rate in Hz (the AudioContext's `sampleRate`) and turn audio on
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.system.set_sample_rate(sample_rate);
+        self.system.set_audio_enabled(true);
+    }
+
+    /// Take the mono samples (-1.0 to 1.0) generated since the last call
+    ///
+    /// Audio is off until this or `set_sample_rate` is first called, so the
+    /// first call returns an empty array. After that, poll once per
+    /// `run_frames` and post the samples to the AudioWorklet.
+    pub fn audio_samples(&mut self) -> Float32Array {
+        self.system.set_audio_enabled(true);
+        let samples = self.system.take_audio_samples_f32();
+        Float32Array::from(&samples[..])
     }
 
     /// Get the current frame count
@@ -51,54 +192,146 @@ impl NesEmulator {
         self.system.frame_count() as u32
     }
 
-    /// Get PPU framebuffer (256x240 RGB pixels)
-    /// Returns raw RGB data (76800 bytes: 256 * 240 * 3)
-    #[wasm_bindgen(getter)]
-    pub fn framebuffer_rgb(&self) -> Uint8Array {
-        let ppu = self.system.ppu();
-        let scanline = ppu.scanline() as usize;
-
-        // Create a fixed-size buffer on the heap
-        let buffer_size = 256 * 240 * 3;
-        let mut buffer = Vec::with_capacity(buffer_size);
-        buffer.resize(buffer_size, 0);
-
-        // Render a simple test pattern based on scanline
-        for y in 0..240 {
-            for x in 0..256 {
-                let idx = (y * 256 + x) * 3;
-                // Simple pattern: gradient based on position and scanline
-                buffer[idx] = ((x / 16) as u8 * 16) % 255;
-                buffer[idx + 1] = ((y / 16) as u8 * 16) % 255;
-                buffer[idx + 2] = ((scanline / 4) as u8 * 4) % 255;
+    /// Get the number of controller polls ($4016 reads) in the last frame
+    pub fn input_polls(&self) -> u32 {
+        self.system.input_polls_last_frame()
+    }
+
+    /// Get the bytes the game read from a controller port (0 or 1) in the last frame
+    pub fn input_echo(&self, port: usize) -> Vec<u8> {
+        self.system.input_echo_last_frame().reads(port).to_vec()
+    }
+
+    /// Press a button on a controller port (0 or 1)
+    /// Buttons are "a", "b", "select", "start", "up", "down", "left" and "right";
+    /// returns false for an unknown port or button
+    pub fn press_button(&mut self, port: usize, button: &str) -> bool {
+        self.set_button(port, button, true)
+    }
+
+    /// Release a button on a controller port (0 or 1), named as for `press_button`
+    pub fn release_button(&mut self, port: usize, button: &str) -> bool {
+        self.set_button(port, button, false)
+    }
+
+    /// Set every button of a controller port (0 or 1) from a bitmask (bit 0 = A ... bit 7 = Right)
+    pub fn set_buttons(&mut self, port: usize, bits: u8) {
+        if let Some(buttons) = self.buttons.get_mut(port) {
+            *buttons = Buttons::new(bits);
+            self.system.set_buttons(port, *buttons);
+        }
+    }
+
+    /// Plug a device into port 2: "controller", "zapper" or "vaus" (Arkanoid paddle)
+    /// Returns false for an unknown name
+    pub fn set_port2_device(&mut self, device: &str) -> bool {
+        match device {
+            "controller" => {
+                self.system.set_zapper(None);
+                self.system.set_vaus(None);
             }
+            "zapper" => self.system.set_zapper(Some(Zapper::new())),
+            "vaus" => self.system.set_vaus(Some(Vaus::new())),
+            _ => return false,
         }
+        true
+    }
+
+    /// Forward the mouse or touch pointer (canvas pixels) to the device on port 2
+    /// The Zapper aims at the pixel (off the canvas aims away from the screen) and
+    /// the paddle follows the column; `pressed` pulls the trigger or presses fire
+    pub fn set_pointer(&mut self, x: i32, y: i32, pressed: bool) {
+        let canvas = CanvasSize::visible(self.overscan);
+        let top = visible_lines(self.overscan).start as i32;
+        let on_canvas = (0..canvas.width as i32).contains(&x) && (0..canvas.height as i32).contains(&y);
+        if let Some(zapper) = self.system.zapper_mut() {
+            zapper.set_aim(on_canvas.then(|| (x as u8, (y + top) as u8)));
+            zapper.set_trigger(pressed);
+        }
+        if let Some(vaus) = self.system.vaus_mut() {
+            vaus.aim_at(x.clamp(0, 255) as u8);
+            vaus.set_fire(pressed);
+        }
+    }
 
-        // Get the pointer and length
-        let len = buffer.len();
-        let ptr = buffer.as_ptr() as usize;
+    /// Show or crop the 8 overscan lines at the top and bottom of the frame
+    /// Changes `canvas_height`, `framebuffer_len` and the rows of `frame_line`
+    pub fn set_overscan(&mut self, overscan: bool) {
+        self.overscan = overscan;
+    }
 
-        // Create a new ArrayBuffer
-        let array_buffer = ArrayBuffer::new(buffer_size as u32);
+    /// Check if overscan lines are shown
+    pub fn overscan(&self) -> bool {
+        self.overscan
+    }
 
-        // Create Uint8Array from the ArrayBuffer
-        let arr = Uint8Array::new(&array_buffer);
+    /// Native PPU width in pixels (256)
+    pub fn native_width(&self) -> u32 {
+        CanvasSize::native().width as u32
+    }
 
-        // Copy data using copy_from
-        unsafe {
-            let src_slice = slice::from_raw_parts(ptr as *const u8, len);
-            arr.copy_from(src_slice);
-        }
+    /// Native PPU height in pixels (240)
+    pub fn native_height(&self) -> u32 {
+        CanvasSize::native().height as u32
+    }
 
-        // Forget the buffer to prevent double free
-        std::mem::forget(buffer);
+    /// Height with overscan cropped (224)
+    pub fn cropped_height(&self) -> u32 {
+        CanvasSize::visible(false).height as u32
+    }
 
-        arr
+    /// Width of the frame stretched to the 8:7 NTSC pixel aspect ratio (293)
+    pub fn par_width(&self) -> u32 {
+        CanvasSize::native().aspect_corrected().width as u32
+    }
+
+    /// Width of the current canvas in frame pixels
+    pub fn canvas_width(&self) -> u32 {
+        CanvasSize::visible(self.overscan).width as u32
+    }
+
+    /// Height of the current canvas in frame pixels (240, or 224 with overscan cropped)
+    pub fn canvas_height(&self) -> u32 {
+        CanvasSize::visible(self.overscan).height as u32
+    }
+
+    /// Get PPU framebuffer (256 RGB pixels per visible line)
+    /// Returns raw RGB data (`framebuffer_len` bytes) of the last completed frame,
+    /// rendered by the PPU a scanline at a time
+    #[wasm_bindgen(getter)]
+    pub fn framebuffer_rgb(&self) -> Uint8Array {
+        let stride = FRAME_WIDTH * 3;
+        let lines = visible_lines(self.overscan);
+        Uint8Array::from(&self.system.framebuffer()[lines.start * stride..lines.end * stride])
+    }
+
+    /// Capture the current frame and return the canvas rows changed since the last capture
+    /// Upload only these rows (via `frame_line`) to avoid redrawing static screens
+    pub fn capture_frame(&mut self) -> Vec<u16> {
+        let frame = self.system.ppu_mut().snapshot_frame();
+        let lines = visible_lines(self.overscan);
+        let dirty = frame
+            .dirty_lines()
+            .filter(|y| lines.contains(y))
+            .map(|y| (y - lines.start) as u16)
+            .collect();
+        self.frame = Some(frame);
+        dirty
+    }
+
+    /// Get one canvas row (256 RGB pixels) of the last captured frame
+    pub fn frame_line(&self, y: u32) -> Vec<u8> {
+        let lines = visible_lines(self.overscan);
+        let line = lines.start + y as usize;
+        match &self.frame {
+            Some(frame) if lines.contains(&line) => frame.line(line).to_vec(),
+            _ => Vec::new(),
+        }
     }
 
     /// Get PPU framebuffer length
     pub fn framebuffer_len(&self) -> usize {
-        256 * 240 * 3
+        (self.canvas_width() * self.canvas_height() * 3) as usize
     }
 
     /// Get current PPU scanline
@@ -118,7 +351,45 @@ impl NesEmulator {
 
     /// Get CPU cycles
     pub fn cpu_cycles(&self) -> u32 {
-        self.system.cpu().total_cycles() as u32
+        self.system.cpu_cycles() as u32
+    }
+
+    /// Get a JSON snapshot of the CPU, PPU, mapper and APU registers, IRQ sources and sprite occupancy for debugger UIs
+    /// Layout: `{"cpu":{..},"ppu":{..},"mapper":{..},"apu":{..},"irq":[..],"sprites":{..}}` (see `DebugState::to_json`)
+    pub fn debug_state_json(&self) -> String {
+        DebugState::capture(&self.system).to_json()
+    }
+
+    /// Release a stuck IRQ by source name (as listed under `irq` in the debug state)
+    ///
+    /// Returns true if the source was holding the line.
+    pub fn acknowledge_irq(&mut self, source: &str) -> bool {
+        IrqSource::from_name(source).is_some_and(|source| self.system.acknowledge_irq(source))
+    }
+}
+
+impl NesEmulator {
+    /// Press or release a named button and pass the port's state to the system
+    fn set_button(&mut self, port: usize, button: &str, pressed: bool) -> bool {
+        let (Some(buttons), Some(bit)) = (self.buttons.get_mut(port), Buttons::from_name(button)) else {
+            return false;
+        };
+        buttons.set(bit, pressed);
+        self.system.set_buttons(port, *buttons);
+        true
+    }
+
+    /// Post an on-screen message and queue it as an event
+    fn show(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        self.osd.show(text.clone());
+        self.events.push(Event::Message { text });
+    }
+}
+
+impl Default for NesEmulator {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
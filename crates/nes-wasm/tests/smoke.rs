@@ -0,0 +1,15 @@
+//! The shared smoke ROM on the wasm32 build of nes-core
+//!
+//! Runs in a JS engine with `wasm-bindgen-test-runner` (see `cargo xtask
+//! matrix`) and as a plain test on native targets.
+
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[path = "../../nes-core/tests/common/smoke.rs"]
+mod smoke;
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_smoke_rom_matches_native() {
+    let system = smoke::run_smoke();
+    assert_eq!(system.state_hash(), smoke::SMOKE_HASH);
+}
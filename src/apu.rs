@@ -5,6 +5,11 @@
 //! - 1 Triangle wave channel
 //! - 1 Noise channel
 //! - 1 DMC (Delta Modulation Channel)
+//!
+//! The noise and DMC period tables and the frame counter steps are NTSC's
+//! until `APU::set_region` switches them to PAL's.
+
+use crate::region::Region;
 
 /// APU registers
 pub const REGSquare1_CTRL: u16 = 0x4000;
@@ -28,6 +33,107 @@ pub const REGDMC_LENGTH: u16 = 0x4013;
 pub const REGCHANNEL_ENABLE: u16 = 0x4015;
 pub const REGFRAME_COUNTER: u16 = 0x4017;
 
+/// Length counter load values, indexed by bits 7-3 of $4003/$4007/$400B/$400F
+pub const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Output of `APU::get_output` when the mixer is at full level
+pub const MIX_FULL_SCALE: i32 = 32767;
+
+/// Mix the channel outputs the way the 2A03's DACs do, giving 0.0 to about 1.0
+///
+/// The two pulse channels share one DAC and the triangle, noise and DMC
+/// another, and neither responds linearly: a lone pulse at 15 comes out
+/// quieter than the triangle at 15, and the DMC compresses near the top of
+/// its range instead of clipping. These are the usual approximations of the
+/// measured curves (from the NESdev wiki).
+pub fn mix(square1: i32, square2: i32, triangle: i32, noise: i32, dmc: i32) -> f32 {
+    let pulse = (square1 + square2) as f32;
+    let pulse_out = if pulse > 0.0 { 95.88 / (8128.0 / pulse + 100.0) } else { 0.0 };
+    let tnd = triangle as f32 / 8227.0 + noise as f32 / 12241.0 + dmc as f32 / 22638.0;
+    let tnd_out = if tnd > 0.0 { 159.79 / (1.0 / tnd + 100.0) } else { 0.0 };
+    pulse_out + tnd_out
+}
+
+/// Length counter shared by the square, triangle and noise channels
+///
+/// Register writes are latched and applied after the frame counter has clocked
+/// on the same cycle, which reproduces the hardware's write-order behaviour:
+/// - a reload written as a half frame clock decrements the counter is ignored
+///   (a reload of a counter that was already 0 still applies)
+/// - a halt flag written on a half frame clock only affects later clocks
+#[derive(Debug, Default)]
+pub struct LengthCounter {
+    pub counter: u8,
+    /// Halt flag (also the envelope loop / triangle control flag)
+    pub halt: bool,
+    /// Channel enabled through $4015; loads are ignored while disabled
+    pub enabled: bool,
+    /// Halt flag written this cycle
+    pub new_halt: bool,
+    /// Reload value written this cycle (0 when none)
+    pub reload: u8,
+    /// Counter value when the reload was written
+    pub reload_previous: u8,
+}
+
+impl LengthCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+        self.enabled = false;
+        self.reload = 0;
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.new_halt = halt;
+    }
+
+    /// Load from a length table index (bits 7-3 of the channel's fourth register)
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.reload = LENGTH_TABLE[(index & 0x1F) as usize];
+            self.reload_previous = self.counter;
+        }
+    }
+
+    /// Enable or disable through $4015; disabling clears the counter immediately
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+            self.reload = 0;
+        }
+    }
+
+    /// Half frame clock
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    /// Apply the writes latched this cycle (after the frame counter's clocks)
+    pub fn apply_writes(&mut self) {
+        if self.reload != 0 {
+            if self.counter == self.reload_previous {
+                self.counter = self.reload;
+            }
+            self.reload = 0;
+        }
+        self.halt = self.new_halt;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
 /// Square wave channel
 #[derive(Debug)]
 pub struct SquareChannel {
@@ -46,8 +152,7 @@ pub struct SquareChannel {
     pub sweep_shift: u8,      // Sweep shift amount (0-7)
     pub sweep_counter: u8,    // Sweep counter
 
-    pub length_counter: u8,   // Length counter (0-63)
-    pub length_enabled: bool, // Length counter enabled
+    pub length: LengthCounter,
 
     pub timer_low: u8,        // Timer low byte
     pub timer_high: u8,       // Timer high byte (3 bits)
@@ -75,8 +180,7 @@ impl SquareChannel {
             sweep_shift: 0,
             sweep_counter: 0,
 
-            length_counter: 0,
-            length_enabled: false,
+            length: LengthCounter::new(),
 
             timer_low: 0,
             timer_high: 0,
@@ -93,17 +197,18 @@ impl SquareChannel {
         self.envelope_counter = 0;
         self.envelope_volume = 0;
         self.sweep_counter = 0;
-        self.length_counter = 0;
+        self.length.reset();
         self.timer_counter = 0;
         self.output = 0;
     }
 
     pub fn set_ctrl(&mut self, value: u8) {
         self.duty_cycle = (value >> 6) & 0x03;
-        self.envelope_loop = (value & 0x08) != 0;
-        self.envelope_constant = (value & 0x10) == 0;
+        // Bit 5 both loops the envelope and halts the length counter
+        self.envelope_loop = (value & 0x20) != 0;
+        self.envelope_constant = (value & 0x10) != 0;
         self.envelope_period = value & 0x0F;
-        self.length_enabled = (value & 0x20) != 0;
+        self.length.set_halt(self.envelope_loop);
     }
 
     pub fn set_sweep(&mut self, value: u8) {
@@ -120,18 +225,12 @@ impl SquareChannel {
 
     pub fn set_freq_high(&mut self, value: u8) {
         self.timer_high = value & 0x07;
-        self.length_counter = ((value >> 3) & 0x1F) as u8;
+        self.length.load(value >> 3);
         self.update_timer();
 
-        if self.envelope_loop || self.envelope_constant {
-            self.envelope_counter = self.envelope_period;
-            self.envelope_volume = self.envelope_period;
-        }
-
-        // Reload length counter if enabled
-        if self.length_enabled && self.length_counter == 0 {
-            self.length_counter = 64;
-        }
+        // Restart the envelope
+        self.envelope_counter = self.envelope_period;
+        self.envelope_volume = 15;
     }
 
     fn update_timer(&mut self) {
@@ -140,9 +239,7 @@ impl SquareChannel {
     }
 
     pub fn clock_length(&mut self) {
-        if self.length_enabled && self.length_counter > 0 {
-            self.length_counter -= 1;
-        }
+        self.length.clock();
     }
 
     pub fn clock_envelope(&mut self) {
@@ -219,7 +316,7 @@ impl SquareChannel {
     }
 
     pub fn get_output(&self) -> i32 {
-        if !self.enabled || self.length_counter == 0 {
+        if !self.enabled || !self.length.is_active() {
             0
         } else {
             self.output
@@ -228,6 +325,7 @@ impl SquareChannel {
 
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        self.length.set_enabled(enabled);
     }
 }
 
@@ -245,8 +343,7 @@ pub struct TriangleChannel {
     pub linear_counter_load: u8,       // Bits 6-0 of $4008
     pub linear_counter: u8,
 
-    pub length_counter: u8,
-    pub length_enabled: bool,
+    pub length: LengthCounter,
 
     pub timer_low: u8,
     pub timer_high: u8,
@@ -263,8 +360,7 @@ impl TriangleChannel {
             linear_counter_control: false,
             linear_counter_load: 0,
             linear_counter: 0,
-            length_counter: 0,
-            length_enabled: false,
+            length: LengthCounter::new(),
             timer_low: 0,
             timer_high: 0,
             timer_period: 0,
@@ -275,7 +371,7 @@ impl TriangleChannel {
 
     pub fn reset(&mut self) {
         self.linear_counter = 0;
-        self.length_counter = 0;
+        self.length.reset();
         self.timer_counter = 0;
         self.output = 0;
     }
@@ -283,7 +379,8 @@ impl TriangleChannel {
     pub fn set_ctrl(&mut self, value: u8) {
         self.linear_counter_control = (value & 0x80) != 0;
         self.linear_counter_load = value & 0x7F;
-        self.length_enabled = (value & 0x80) == 0;
+        // The control flag doubles as the length counter halt
+        self.length.set_halt(self.linear_counter_control);
     }
 
     pub fn set_freq_low(&mut self, value: u8) {
@@ -293,7 +390,7 @@ impl TriangleChannel {
 
     pub fn set_freq_high(&mut self, value: u8) {
         self.timer_high = value & 0x07;
-        self.length_counter = ((value >> 3) & 0x1F) as u8;
+        self.length.load(value >> 3);
         self.update_timer();
 
         if self.linear_counter_control {
@@ -307,9 +404,7 @@ impl TriangleChannel {
     }
 
     pub fn clock_length(&mut self) {
-        if self.length_enabled && self.length_counter > 0 {
-            self.length_counter -= 1;
-        }
+        self.length.clock();
     }
 
     pub fn clock_linear(&mut self) {
@@ -325,7 +420,7 @@ impl TriangleChannel {
             self.timer_counter = self.timer_period;
 
             // Triangle wave output (0-15)
-            if self.linear_counter > 0 && self.length_counter > 0 {
+            if self.linear_counter > 0 && self.length.is_active() {
                 // Simple triangle pattern: 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,14,13,...
                 // For simplicity, use a basic pattern
                 self.output = ((self.timer_counter & 0x0F) as i32) - 8;
@@ -338,7 +433,7 @@ impl TriangleChannel {
     }
 
     pub fn get_output(&self) -> i32 {
-        if !self.enabled || self.length_counter == 0 || self.linear_counter == 0 {
+        if !self.enabled || !self.length.is_active() || self.linear_counter == 0 {
             0
         } else {
             self.output
@@ -347,6 +442,7 @@ impl TriangleChannel {
 
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        self.length.set_enabled(enabled);
     }
 }
 
@@ -366,14 +462,15 @@ pub struct NoiseChannel {
     pub envelope_counter: u8,
     pub envelope_volume: u8,
 
-    pub length_counter: u8,
-    pub length_enabled: bool,
+    pub length: LengthCounter,
 
     pub noise_mode: bool,       // 0=7-stage, 1=15-stage
     pub noise_period_index: u8, // Frequency table index
 
     pub noise_shift: u32,       // Shift register
     pub noise_counter: u32,
+    /// Period table for the region
+    pub wavelengths: &'static [u16; 16],
 
     pub output: i32,
 }
@@ -383,6 +480,10 @@ impl NoiseChannel {
     const WAVELENGTHS: [u16; 16] = [
         4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
     ];
+    // Wavelength table for PAL's 1.66 MHz clock
+    const WAVELENGTHS_PAL: [u16; 16] = [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ];
 
     pub fn new() -> Self {
         Self {
@@ -392,30 +493,36 @@ impl NoiseChannel {
             envelope_period: 0,
             envelope_counter: 0,
             envelope_volume: 0,
-            length_counter: 0,
-            length_enabled: false,
+            length: LengthCounter::new(),
             noise_mode: false,
             noise_period_index: 0,
             noise_shift: 0x7F,
             noise_counter: 0,
+            wavelengths: &Self::WAVELENGTHS,
             output: 0,
         }
     }
 
+    /// Use the region's period table (the Dendy uses NTSC's)
+    pub fn set_region(&mut self, region: Region) {
+        self.wavelengths = if region == Region::Pal { &Self::WAVELENGTHS_PAL } else { &Self::WAVELENGTHS };
+    }
+
     pub fn reset(&mut self) {
         self.envelope_counter = 0;
         self.envelope_volume = 0;
-        self.length_counter = 0;
+        self.length.reset();
         self.noise_shift = 0x7F;
         self.noise_counter = 0;
         self.output = 0;
     }
 
     pub fn set_ctrl(&mut self, value: u8) {
-        self.envelope_loop = (value & 0x08) != 0;
-        self.envelope_constant = (value & 0x10) == 0;
+        // Bit 5 both loops the envelope and halts the length counter
+        self.envelope_loop = (value & 0x20) != 0;
+        self.envelope_constant = (value & 0x10) != 0;
         self.envelope_period = value & 0x0F;
-        self.length_enabled = (value & 0x20) != 0;
+        self.length.set_halt(self.envelope_loop);
     }
 
     pub fn set_freq(&mut self, value: u8) {
@@ -424,13 +531,15 @@ impl NoiseChannel {
     }
 
     pub fn set_length(&mut self, value: u8) {
-        self.length_counter = ((value >> 3) & 0x1F) as u8;
+        self.length.load(value >> 3);
+
+        // Restart the envelope
+        self.envelope_counter = self.envelope_period;
+        self.envelope_volume = 15;
     }
 
     pub fn clock_length(&mut self) {
-        if self.length_enabled && self.length_counter > 0 {
-            self.length_counter -= 1;
-        }
+        self.length.clock();
     }
 
     pub fn clock_envelope(&mut self) {
@@ -451,7 +560,7 @@ impl NoiseChannel {
     }
 
     pub fn update_output(&mut self) {
-        let period = Self::WAVELENGTHS[self.noise_period_index as usize] as u32;
+        let period = self.wavelengths[self.noise_period_index as usize] as u32;
 
         if self.noise_counter == 0 {
             self.noise_counter = period;
@@ -481,7 +590,7 @@ impl NoiseChannel {
     }
 
     pub fn get_output(&self) -> i32 {
-        if !self.enabled || self.length_counter == 0 {
+        if !self.enabled || !self.length.is_active() {
             0
         } else {
             self.output
@@ -490,6 +599,7 @@ impl NoiseChannel {
 
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        self.length.set_enabled(enabled);
     }
 }
 
@@ -500,32 +610,51 @@ impl Default for NoiseChannel {
 }
 
 /// DMC (Delta Modulation Channel)
+///
+/// The memory reader refills the one-byte sample buffer whenever it is empty
+/// and bytes remain; the bus does the fetch itself (see `fetch_address` and
+/// `load_sample`), since it has to read through the mapper and halt the CPU.
+/// The output unit plays the buffered byte a bit at a time, moving the 7-bit
+/// level up or down by 2.
 #[derive(Debug)]
 pub struct DmcChannel {
     pub enabled: bool,
-    pub play_mode: u8,      // 00=normal, 01=loop, 10=IRQ on completion
+    pub play_mode: u8,      // bit 1: IRQ on completion, bit 0: loop
     pub frequency_index: u8,
 
     pub sample_address: u16,  // $C000 + (value << 6)
-    pub sample_length: u16,   // (value + 1) * 16 bytes
+    pub sample_length: u16,   // (value << 4) + 1 bytes
 
-    pub dac_latch: u8,      // 6-bit DAC
-    pub delta_counter: u8,  // 6-bit delta
+    pub delta_counter: u8,  // 7-bit output level
 
-    pub sample_buffer: u8,
+    /// Byte fetched by the memory reader, waiting for the output unit
+    pub sample_buffer: Option<u8>,
+    /// Byte being played, LSB first
+    pub shift_register: u8,
+    /// Bits left in the current output cycle
     pub sample_bit_count: u8,
+    /// The output cycle started with an empty buffer, so the level holds
+    pub silence: bool,
+    /// CPU cycles until the next output bit
+    pub timer: u16,
 
     pub sample_address_counter: u16,
     pub sample_length_counter: u16,
 
-    pub output: i32,
+    /// Set when a sample ends with IRQ mode on; cleared by writing $4015, or $4010 with IRQ off
+    pub irq_pending: bool,
+    /// Rate table for the region
+    pub rates: &'static [u16; 16],
 }
 
 impl DmcChannel {
-    // DMC frequency table (based on 1.79 MHz clock)
-    const FREQ_DIVISORS: [u32; 16] = [
-        537472, 478016, 424704, 399872, 357824, 318720, 298944, 279168,
-        260608, 239008, 212352, 199936, 178912, 159360, 149472, 134848,
+    /// CPU cycles per output bit for each rate index (NTSC)
+    pub const RATES: [u16; 16] = [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ];
+    /// CPU cycles per output bit for each rate index (PAL)
+    pub const RATES_PAL: [u16; 16] = [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
     ];
 
     pub fn new() -> Self {
@@ -533,34 +662,48 @@ impl DmcChannel {
             enabled: false,
             play_mode: 0,
             frequency_index: 0,
-            sample_address: 0,
-            sample_length: 0,
-            dac_latch: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
             delta_counter: 0,
-            sample_buffer: 0,
-            sample_bit_count: 0,
-            sample_address_counter: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            sample_bit_count: 8,
+            silence: true,
+            timer: Self::RATES[0],
+            sample_address_counter: 0xC000,
             sample_length_counter: 0,
-            output: 0,
+            irq_pending: false,
+            rates: &Self::RATES,
         }
     }
 
     pub fn reset(&mut self) {
-        self.dac_latch = 0;
-        self.delta_counter = 0;
-        self.sample_buffer = 0;
-        self.sample_bit_count = 0;
-        self.output = 0;
+        *self = Self { rates: self.rates, ..Self::new() };
+    }
+
+    /// Use the region's rate table (the Dendy uses NTSC's)
+    pub fn set_region(&mut self, region: Region) {
+        self.rates = if region == Region::Pal { &Self::RATES_PAL } else { &Self::RATES };
+    }
+
+    pub fn looping(&self) -> bool {
+        self.play_mode & 0x01 != 0
+    }
+
+    pub fn irq_enabled(&self) -> bool {
+        self.play_mode & 0x02 != 0
     }
 
     pub fn set_ctrl(&mut self, value: u8) {
         self.play_mode = (value >> 6) & 0x03;
         self.frequency_index = value & 0x0F;
+        if !self.irq_enabled() {
+            self.irq_pending = false;
+        }
     }
 
     pub fn set_dac(&mut self, value: u8) {
-        self.dac_latch = value & 0x7F;
-        self.delta_counter = self.dac_latch;
+        self.delta_counter = value & 0x7F;
     }
 
     pub fn set_address(&mut self, value: u8) {
@@ -568,63 +711,88 @@ impl DmcChannel {
     }
 
     pub fn set_length(&mut self, value: u8) {
-        self.sample_length = ((value as u16) + 1) * 16;
+        self.sample_length = ((value as u16) << 4) + 1;
     }
 
+    /// Restart the memory reader at the start of the sample
     pub fn start_sample(&mut self) {
-        if self.sample_length > 0 {
-            self.sample_address_counter = self.sample_address;
-            self.sample_length_counter = self.sample_length;
-            self.sample_bit_count = 0;
-            self.enabled = true;
+        self.sample_address_counter = self.sample_address;
+        self.sample_length_counter = self.sample_length;
+    }
+
+    /// Address the memory reader needs next, if the buffer is empty and bytes remain
+    pub fn fetch_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.sample_length_counter > 0 {
+            Some(self.sample_address_counter)
+        } else {
+            None
         }
     }
 
-    pub fn clock_sample(&mut self) {
-        if self.sample_bit_count == 0 && self.sample_length_counter > 0 {
-            // Load next byte from ROM
-            self.sample_buffer = 0;  // Would read from memory
-            self.sample_bit_count = 8;
+    /// Fill the sample buffer with the byte read from `fetch_address`
+    ///
+    /// The address wraps from $FFFF to $8000. When the last byte is read the
+    /// sample restarts in loop mode, or raises the IRQ in IRQ mode.
+    pub fn load_sample(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.sample_address_counter = self.sample_address_counter.checked_add(1).unwrap_or(0x8000);
+        self.sample_length_counter -= 1;
+        if self.sample_length_counter == 0 {
+            if self.looping() {
+                self.start_sample();
+            } else if self.irq_enabled() {
+                self.irq_pending = true;
+            }
         }
+    }
 
-        if self.sample_bit_count > 0 {
-            let bit = (self.sample_buffer >> (self.sample_bit_count - 1)) & 1;
+    /// Clock the output unit for one CPU cycle
+    pub fn clock_timer(&mut self) {
+        self.timer -= 1;
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.rates[self.frequency_index as usize];
 
-            if bit == 0 {
-                if self.delta_counter >= 4 {
-                    self.delta_counter -= 4;
-                }
-            } else {
-                if self.delta_counter <= 123 {
-                    self.delta_counter += 4;
+        if !self.silence {
+            if self.shift_register & 1 == 0 {
+                if self.delta_counter >= 2 {
+                    self.delta_counter -= 2;
                 }
+            } else if self.delta_counter <= 125 {
+                self.delta_counter += 2;
             }
+        }
+        self.shift_register >>= 1;
 
-            self.dac_latch = self.delta_counter;
-            self.output = self.delta_counter as i32;
-
-            self.sample_bit_count -= 1;
-            self.sample_length_counter -= 1;
-
-            if self.sample_length_counter == 0 && self.play_mode != 0 {
-                // Re-start sample if looping
-                self.sample_length_counter = self.sample_length;
-            } else if self.sample_length_counter == 0 {
-                self.enabled = false;
+        self.sample_bit_count -= 1;
+        if self.sample_bit_count == 0 {
+            // A new output cycle takes the buffered byte, freeing the buffer for the next fetch
+            self.sample_bit_count = 8;
+            match self.sample_buffer.take() {
+                Some(value) => {
+                    self.shift_register = value;
+                    self.silence = false;
+                }
+                None => self.silence = true,
             }
         }
     }
 
+    /// Output level; the DAC holds its level whether or not a sample plays
     pub fn get_output(&self) -> i32 {
-        if !self.enabled {
-            0
-        } else {
-            self.output
-        }
+        self.delta_counter as i32
     }
 
+    /// Handle bit 4 of a $4015 write: start the sample if none is playing, or stop it
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        self.irq_pending = false;
+        if !enabled {
+            self.sample_length_counter = 0;
+        } else if self.sample_length_counter == 0 {
+            self.start_sample();
+        }
     }
 }
 
@@ -634,78 +802,154 @@ impl Default for DmcChannel {
     }
 }
 
+/// Quarter and half frame clocks produced by the frame counter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameClocks {
+    pub quarter: u32,
+    pub half: u32,
+}
+
 /// Frame counter for APU
+///
+/// Counts CPU cycles rather than APU cycles so the steps that land between APU
+/// cycles (3728.5, 7456.5, ... APU cycles) happen on the exact CPU cycle.
 #[derive(Debug)]
 pub struct FrameCounter {
+    /// CPU cycles since the sequence started
     pub cycle_counter: u64,
+    /// Steps taken in the current sequence
     pub step: u8,
     pub count_sequence: u8,  // 0=4-step, 1=5-step
-    pub irq_enabled: bool,
+    /// $4017 bit 6: the frame IRQ is never raised while set
+    pub irq_inhibit: bool,
     pub irq_pending: bool,
+    /// CPU cycles clocked since power-on, used to find APU cycle boundaries
+    pub total_cycles: u64,
+    /// $4017 value waiting to restart the sequence, and the CPU cycles until it does
+    pub pending_write: Option<(u8, u8)>,
+    /// CPU cycle of each event for the region (see `STEPS`)
+    pub steps: &'static [u64; 8],
 }
 
 impl FrameCounter {
-    // NTSC cycle counts for frame steps
-    const FRAME_STEPS_4: [u64; 4] = [7457, 14913, 22371, 29829];
-    const FRAME_STEPS_5: [u64; 5] = [7457, 14913, 22371, 29829, 37281];
+    /// NTSC CPU cycle of each event after the sequence starts: quarter 1,
+    /// half 1, quarter 3, the 4-step IRQ, half 4, the 4-step wrap (which
+    /// raises the IRQ once more), half 5 and the 5-step wrap
+    pub const STEPS: [u64; 8] = [7457, 14913, 22371, 29828, 29829, 29830, 37281, 37282];
+    /// PAL CPU cycle of each event, in the same order as `STEPS`
+    pub const STEPS_PAL: [u64; 8] = [8313, 16627, 24939, 33252, 33253, 33254, 41565, 41566];
 
     pub fn new() -> Self {
         Self {
             cycle_counter: 0,
             step: 0,
             count_sequence: 0,
-            irq_enabled: false,
+            irq_inhibit: false,
             irq_pending: false,
+            total_cycles: 0,
+            pending_write: None,
+            steps: &Self::STEPS,
         }
     }
 
+    /// Use the region's step timing (the Dendy uses NTSC's)
+    pub fn set_region(&mut self, region: Region) {
+        self.steps = if region == Region::Pal { &Self::STEPS_PAL } else { &Self::STEPS };
+    }
+
     pub fn reset(&mut self) {
         self.cycle_counter = 0;
         self.step = 0;
         self.irq_pending = false;
+        self.pending_write = None;
     }
 
-    pub fn clock(&mut self, cycles: u64) {
-        self.cycle_counter += cycles;
+    /// Handle a $4017 write
+    ///
+    /// Setting the inhibit bit clears the IRQ flag at once. The sequence restarts
+    /// 3 CPU cycles later if the write lands on an APU cycle, 4 if it lands between.
+    pub fn write(&mut self, value: u8) {
+        self.irq_inhibit = value & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_pending = false;
+        }
+        let delay = if self.total_cycles.is_multiple_of(2) { 3 } else { 4 };
+        self.pending_write = Some((value, delay));
+    }
 
-        if self.count_sequence == 0 {
-            // 4-step sequence
-            if self.step < 4 {
-                let next_step = Self::FRAME_STEPS_4[self.step as usize];
-                if self.cycle_counter >= next_step {
-                    self.step += 1;
-                    if self.step == 4 {
-                        self.step = 0;
-                        self.cycle_counter = 0;
-                    }
-                }
-            }
-        } else {
-            // 5-step sequence
-            if self.step < 5 {
-                let next_step = Self::FRAME_STEPS_5[self.step as usize];
-                if self.cycle_counter >= next_step {
-                    self.step += 1;
-                    if self.step == 5 {
-                        self.step = 0;
-                        self.cycle_counter = 0;
-                        self.irq_pending = true;
-                    }
+    /// Advance by CPU cycles, returning the quarter and half frame clocks that occurred
+    pub fn clock(&mut self, cycles: u64) -> FrameClocks {
+        let mut clocks = FrameClocks::default();
+        for _ in 0..cycles {
+            self.tick(&mut clocks);
+        }
+        clocks
+    }
+
+    fn tick(&mut self, clocks: &mut FrameClocks) {
+        self.total_cycles += 1;
+
+        if let Some((value, delay)) = self.pending_write {
+            if delay > 1 {
+                self.pending_write = Some((value, delay - 1));
+            } else {
+                // Restart the sequence; entering 5-step mode clocks both units immediately
+                self.pending_write = None;
+                self.count_sequence = (value >> 7) & 0x01;
+                self.cycle_counter = 0;
+                self.step = 0;
+                if self.count_sequence == 1 {
+                    clocks.quarter += 1;
+                    clocks.half += 1;
                 }
+                return;
             }
         }
-    }
 
-    pub fn get_step(&self) -> u8 {
-        self.step
+        self.cycle_counter += 1;
+        let [quarter_1, half_1, quarter_3, irq_4, half_4, wrap_4, half_5, wrap_5] = *self.steps;
+        match (self.count_sequence, self.cycle_counter) {
+            (_, cycle) if cycle == quarter_1 || cycle == quarter_3 => {
+                clocks.quarter += 1;
+                self.step += 1;
+            }
+            (_, cycle) if cycle == half_1 => {
+                clocks.quarter += 1;
+                clocks.half += 1;
+                self.step += 1;
+            }
+            (0, cycle) if cycle == irq_4 => self.raise_irq(),
+            (0, cycle) if cycle == half_4 => {
+                clocks.quarter += 1;
+                clocks.half += 1;
+                self.step = 0;
+                self.raise_irq();
+            }
+            (0, cycle) if cycle == wrap_4 => {
+                self.raise_irq();
+                self.cycle_counter = 0;
+            }
+            // The 5-step sequence's fourth step does nothing
+            (1, cycle) if cycle == half_4 => self.step += 1,
+            (1, cycle) if cycle == half_5 => {
+                clocks.quarter += 1;
+                clocks.half += 1;
+                self.step = 0;
+            }
+            (1, cycle) if cycle == wrap_5 => self.cycle_counter = 0,
+            _ => {}
+        }
     }
 
-    pub fn set_sequence(&mut self, sequence: u8) {
-        self.count_sequence = sequence;
+    /// Set the IRQ flag unless inhibited (the 5-step sequence never calls this)
+    fn raise_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.irq_pending = true;
+        }
     }
 
-    pub fn set_irq_enabled(&mut self, enabled: bool) {
-        self.irq_enabled = enabled;
+    pub fn get_step(&self) -> u8 {
+        self.step
     }
 
     pub fn is_irq_pending(&self) -> bool {
@@ -743,6 +987,9 @@ pub struct APU {
     // Sample accumulator
     pub sample_counter: u64,
     pub sample_buffer: i32,
+
+    // Pulse timers tick on every other CPU cycle
+    pub odd_cycle: bool,
 }
 
 impl APU {
@@ -764,6 +1011,8 @@ impl APU {
 
             sample_counter: 0,
             sample_buffer: 0,
+
+            odd_cycle: false,
         }
     }
 
@@ -780,19 +1029,28 @@ impl APU {
         }
     }
 
+    /// Switch the noise and DMC tables and the frame counter steps to a region
+    pub fn set_region(&mut self, region: Region) {
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+        self.frame_counter.set_region(region);
+    }
+
     /// Read from APU registers
     pub fn read(&mut self, address: u16) -> u8 {
         match address {
             0x4015 => {
                 // Channel enable/status
                 let mut value = 0u8;
-                value |= if self.square1.length_counter > 0 { 0x01 } else { 0 };
-                value |= if self.square2.length_counter > 0 { 0x02 } else { 0 };
-                value |= if self.triangle.length_counter > 0 { 0x04 } else { 0 };
-                value |= if self.noise.length_counter > 0 { 0x08 } else { 0 };
+                value |= if self.square1.length.is_active() { 0x01 } else { 0 };
+                value |= if self.square2.length.is_active() { 0x02 } else { 0 };
+                value |= if self.triangle.length.is_active() { 0x04 } else { 0 };
+                value |= if self.noise.length.is_active() { 0x08 } else { 0 };
                 value |= if self.dmc.sample_length_counter > 0 { 0x10 } else { 0 };
                 value |= if self.frame_counter.is_irq_pending() { 0x40 } else { 0 };
-                value |= if self.frame_counter.irq_enabled { 0x80 } else { 0 };
+                value |= if self.dmc.irq_pending { 0x80 } else { 0 };
+                // Reading the status acknowledges the frame IRQ
+                self.frame_counter.clear_irq();
                 value
             }
             0x4017 => {
@@ -839,29 +1097,38 @@ impl APU {
                 self.triangle.set_enabled(self.channel_enabled[2]);
                 self.noise.set_enabled(self.channel_enabled[3]);
                 self.dmc.set_enabled(self.channel_enabled[4]);
-
-                // Start DMC if enabled
-                if (value & 0x10) != 0 && self.dmc.sample_length_counter == 0 {
-                    self.dmc.start_sample();
-                }
-
-                // Reset DMC length if bit 4 is set
-                if (value & 0x20) != 0 {
-                    self.dmc.sample_length_counter = self.dmc.sample_length;
-                }
             }
             0x4017 => {
                 // Frame counter
-                self.frame_counter.set_sequence((value >> 7) & 0x01);
-                self.frame_counter.set_irq_enabled((value & 0x40) != 0);
+                self.frame_counter.write(value);
             }
             _ => {}
         }
     }
 
-    /// Clock frame counter
+    /// Check if the frame counter or the DMC is asserting the IRQ line
+    pub fn irq_pending(&self) -> bool {
+        self.frame_counter.is_irq_pending() || self.dmc.irq_pending
+    }
+
+    /// Clock frame counter, running the quarter and half frame units it triggers
+    ///
+    /// Length counter writes latched since the last call land on the first cycle,
+    /// after that cycle's clocks.
     pub fn clock_frame_counter(&mut self, cycles: u64) {
-        self.frame_counter.clock(cycles);
+        for _ in 0..cycles {
+            let clocks = self.frame_counter.clock(1);
+            for _ in 0..clocks.quarter {
+                self.clock_quarter_frame();
+            }
+            for _ in 0..clocks.half {
+                self.clock_half_frame();
+            }
+            self.square1.length.apply_writes();
+            self.square2.length.apply_writes();
+            self.triangle.length.apply_writes();
+            self.noise.length.apply_writes();
+        }
     }
 
     /// Clock quarter frame operations
@@ -883,40 +1150,9 @@ impl APU {
         self.square2.clock_sweep();
     }
 
-    /// Clock frame steps based on sequence
-    pub fn clock_frame_step(&mut self) {
-        let step = self.frame_counter.get_step();
-
-        match self.frame_counter.count_sequence {
-            0 => {
-                // 4-step sequence
-                match step {
-                    0 => self.clock_quarter_frame(),
-                    1 => self.clock_half_frame(),
-                    2 => self.clock_quarter_frame(),
-                    3 => self.clock_half_frame(),
-                    _ => {}
-                }
-            }
-            1 => {
-                // 5-step sequence
-                match step {
-                    0 => self.clock_quarter_frame(),
-                    1 => self.clock_half_frame(),
-                    2 => self.clock_quarter_frame(),
-                    3 => self.clock_half_frame(),
-                    4 => {
-                        self.clock_quarter_frame();
-                        self.clock_half_frame();
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-
-    /// Update all channels
+    /// Update the square, triangle and noise channels
+    ///
+    /// The DMC needs the bus for its fetches, so the NES clocks it every CPU cycle instead.
     pub fn update_channels(&mut self) {
         if self.channel_enabled[0] {
             self.square1.update_output();
@@ -930,21 +1166,42 @@ impl APU {
         if self.channel_enabled[3] {
             self.noise.update_output();
         }
-        if self.channel_enabled[4] {
-            self.dmc.clock_sample();
+    }
+
+    /// Clock the channel timers for the given CPU cycles
+    ///
+    /// The triangle and noise timers count CPU cycles; the pulse timers count
+    /// APU cycles, which are two CPU cycles long.
+    pub fn clock_timers(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            if self.channel_enabled[2] {
+                self.triangle.update_output();
+            }
+            if self.channel_enabled[3] {
+                self.noise.update_output();
+            }
+            self.odd_cycle = !self.odd_cycle;
+            if self.odd_cycle {
+                if self.channel_enabled[0] {
+                    self.square1.update_output();
+                }
+                if self.channel_enabled[1] {
+                    self.square2.update_output();
+                }
+            }
         }
     }
 
-    /// Calculate output sample
+    /// Calculate output sample, scaled so full output is `MIX_FULL_SCALE`
     pub fn get_output(&self) -> (i32, i32) {
-        let sq1 = self.square1.get_output();
-        let sq2 = self.square2.get_output();
-        let tri = self.triangle.get_output();
-        let noise = self.noise.get_output();
-        let dmc = self.dmc.get_output();
-
-        // Simple mixing (real APU has more complex DAC tables)
-        let left = (sq1 + sq2 + tri + noise + dmc) as i32;
+        let level = mix(
+            self.square1.get_output(),
+            self.square2.get_output(),
+            self.triangle.get_output(),
+            self.noise.get_output(),
+            self.dmc.get_output(),
+        );
+        let left = (level * MIX_FULL_SCALE as f32).round() as i32;
         let right = left;  // Mono output for simplicity
 
         (left, right)
@@ -973,4 +1230,177 @@ impl Default for APU {
     fn default() -> Self {
         Self::new(44100)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod apu_tests {
+    use super::*;
+
+    /// Cycles until the next quarter frame clock, and the clocks seen on that cycle
+    fn next_quarter(counter: &mut FrameCounter) -> (u64, FrameClocks) {
+        for cycle in 1..=40_000 {
+            let clocks = counter.clock(1);
+            if clocks.quarter > 0 {
+                return (cycle, clocks);
+            }
+        }
+        panic!("no quarter frame clock");
+    }
+
+    #[test]
+    fn test_nonlinear_mix() {
+        let close = |actual: f32, expected: f32| (actual - expected).abs() < 0.0001;
+        assert_eq!(mix(0, 0, 0, 0, 0), 0.0);
+        assert!(close(mix(15, 0, 0, 0, 0), 0.14938));
+        assert!(close(mix(15, 15, 0, 0, 0), 0.25848));
+        assert!(close(mix(0, 0, 15, 0, 0), 0.24641));
+        assert!(close(mix(0, 0, 0, 15, 0), 0.17443));
+        assert!(close(mix(0, 0, 0, 0, 127), 0.57426));
+        // Everything at full volume stays within full scale
+        assert!(close(mix(15, 15, 15, 15, 127), 0.99999));
+
+        // The DMC is compressed rather than clipped: its upper half adds less than its lower half
+        assert!(mix(0, 0, 0, 0, 127) - mix(0, 0, 0, 0, 64) < mix(0, 0, 0, 0, 64));
+
+        let mut apu = APU::default();
+        apu.dmc.set_dac(127);
+        assert_eq!(apu.get_output(), (18817, 18817));
+    }
+
+    #[test]
+    fn test_four_step_timing_and_irq() {
+        let mut counter = FrameCounter::new();
+        assert_eq!(next_quarter(&mut counter).0, 7457);
+        assert_eq!(next_quarter(&mut counter), (7456, FrameClocks { quarter: 1, half: 1 }));
+        assert_eq!(next_quarter(&mut counter).0, 7458);
+
+        counter.clock(29827 - 22371);
+        assert!(!counter.is_irq_pending());
+        counter.clock(1);
+        assert!(counter.is_irq_pending());
+        assert_eq!(counter.clock(1), FrameClocks { quarter: 1, half: 1 });
+
+        // Wrapping raises the flag again, so acknowledging at 29829 doesn't stick
+        counter.clear_irq();
+        counter.clock(1);
+        assert!(counter.is_irq_pending());
+        assert_eq!(next_quarter(&mut counter).0, 7457);
+    }
+
+    #[test]
+    fn test_4017_inhibit_and_five_step_mode() {
+        let mut counter = FrameCounter::new();
+        counter.clock(29829);
+        assert!(counter.is_irq_pending());
+
+        // Inhibit clears the flag immediately and keeps it clear
+        counter.write(0x40);
+        assert!(!counter.is_irq_pending());
+        counter.clock(40_000);
+        assert!(!counter.is_irq_pending());
+
+        // 5-step mode clocks both units when the delayed write lands (4 cycles after an odd cycle)
+        let mut counter = FrameCounter::new();
+        counter.clock(1);
+        counter.write(0x80);
+        assert_eq!(counter.clock(3), FrameClocks::default());
+        assert_eq!(counter.clock(1), FrameClocks { quarter: 1, half: 1 });
+
+        // ...and never raises the frame IRQ
+        counter.clock(37282 * 2);
+        assert!(!counter.is_irq_pending());
+        assert_eq!(next_quarter(&mut counter).0, 7457);
+    }
+
+    /// APU with every channel enabled, stopped one cycle before the first half frame clock
+    fn apu_before_half_frame() -> APU {
+        let mut apu = APU::default();
+        apu.write(0x4015, 0x0F);
+        apu.clock_frame_counter(14912);
+        apu
+    }
+
+    /// Length counter of a channel by its $4015 bit
+    fn length(apu: &APU, channel: usize) -> &LengthCounter {
+        match channel {
+            0 => &apu.square1.length,
+            1 => &apu.square2.length,
+            2 => &apu.triangle.length,
+            _ => &apu.noise.length,
+        }
+    }
+
+    #[test]
+    fn test_length_load_for_each_channel() {
+        // (length register, control register, halt bit)
+        let channels = [(0x4003, 0x4000, 0x20), (0x4007, 0x4004, 0x20), (0x400B, 0x4008, 0x80), (0x400F, 0x400C, 0x20)];
+        for (channel, &(load, ctrl, halt)) in channels.iter().enumerate() {
+            let mut apu = APU::default();
+            // Ignored while the channel is disabled
+            apu.write(load, 0x08);
+            apu.clock_frame_counter(1);
+            assert_eq!(length(&apu, channel).counter, 0);
+
+            // Index 1 loads 254 from the table
+            apu.write(0x4015, 1 << channel);
+            apu.write(load, 0x08);
+            apu.clock_frame_counter(1);
+            assert_eq!(length(&apu, channel).counter, 254);
+            assert_eq!(apu.read(0x4015) & (1 << channel), 1 << channel);
+
+            // Half frame clocks count down unless halted
+            apu.clock_frame_counter(14913);
+            assert_eq!(length(&apu, channel).counter, 253);
+            apu.write(ctrl, halt);
+            apu.clock_frame_counter(29830);
+            assert_eq!(length(&apu, channel).counter, 253);
+
+            // Disabling clears the counter
+            apu.write(0x4015, 0);
+            assert_eq!(length(&apu, channel).counter, 0);
+        }
+    }
+
+    #[test]
+    fn test_length_reload_during_clock() {
+        // Reloading a non-zero counter on the clock cycle is ignored
+        let mut apu = APU::default();
+        apu.write(0x4015, 0x0F);
+        apu.write(0x4003, 0x18); // index 3 loads 2
+        apu.clock_frame_counter(14912);
+        assert_eq!(apu.square1.length.counter, 2);
+        apu.write(0x4003, 0x08);
+        apu.clock_frame_counter(1);
+        assert_eq!(apu.square1.length.counter, 1);
+
+        // ...but a counter that was 0 still reloads
+        let mut apu = apu_before_half_frame();
+        apu.write(0x4007, 0x08);
+        apu.clock_frame_counter(1);
+        assert_eq!(apu.square2.length.counter, 254);
+    }
+
+    #[test]
+    fn test_length_halt_written_during_clock() {
+        // Halting on the clock cycle doesn't stop that clock
+        let mut apu = APU::default();
+        apu.write(0x4015, 0x0F);
+        apu.write(0x400F, 0x08);
+        apu.clock_frame_counter(14912);
+        apu.write(0x400C, 0x20);
+        apu.clock_frame_counter(1);
+        assert_eq!(apu.noise.length.counter, 253);
+
+        // Clearing the halt on the clock cycle doesn't let that clock through
+        let mut apu = APU::default();
+        apu.write(0x4015, 0x0F);
+        apu.write(0x4008, 0x80);
+        apu.write(0x400B, 0x08);
+        apu.clock_frame_counter(14912);
+        apu.write(0x4008, 0x00);
+        apu.clock_frame_counter(1);
+        assert_eq!(apu.triangle.length.counter, 254);
+        apu.clock_frame_counter(29830);
+        assert_eq!(apu.triangle.length.counter, 252);
+    }
+}
@@ -2,6 +2,8 @@
 //!
 //! Implements the Ricoh 2A03 CPU used in the NES.
 
+use crate::rom::{MapperInterface, NoMapper};
+
 /// CPU status flags
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct StatusFlags {
@@ -153,6 +155,7 @@ pub struct InstructionInfo {
     pub opcode: Opcode,
     pub mode: AddressingMode,
     pub cycles: u8,
+    pub page_cycle: bool, // Extra cycle if page crossed
 }
 
 /// The 6502 CPU emulator
@@ -161,6 +164,8 @@ pub struct CPU {
     pub registers: Registers,
     pub flags: StatusFlags,
     pub memory: [u8; 0x10000],
+    /// Cartridge on $6000-$FFFF: PRG RAM, PRG ROM and the mapper registers
+    pub mapper: Box<dyn MapperInterface>,
     pub data_bus: u8,
 
     pub cycles: u64,
@@ -171,6 +176,8 @@ pub struct CPU {
     pub nmi_prev_low: bool,
 
     pub cycles_to_halt: u64,
+    /// Page written to $4014, waiting for the NES to copy it into OAM
+    pub oam_dma_page: Option<u8>,
     pub ppu_catchup_dots: u64,
     pub apu_catchup_cycles: u64,
 }
@@ -181,6 +188,7 @@ impl CPU {
             registers: Registers::new(),
             flags: StatusFlags::new(),
             memory: [0u8; 0x10000],
+            mapper: Box::new(NoMapper::new()),
             data_bus: 0,
             cycles: 0,
             irq_delay: 0,
@@ -188,6 +196,7 @@ impl CPU {
             nmi_pending: false,
             nmi_prev_low: true,
             cycles_to_halt: 0,
+            oam_dma_page: None,
             ppu_catchup_dots: 0,
             apu_catchup_cycles: 0,
         };
@@ -199,8 +208,8 @@ impl CPU {
         self.flags = StatusFlags::new();
         self.registers.sp = 0xFD;
 
-        let lo = self.memory[0xFFFC] as u16;
-        let hi = self.memory[0xFFFD] as u16;
+        let lo = self.peek(0xFFFC) as u16;
+        let hi = self.peek(0xFFFD) as u16;
         self.registers.pc = lo | (hi << 8);
 
         self.irq_request = IrqRequest::Reset;
@@ -225,8 +234,44 @@ impl CPU {
         }
     }
 
+    /// Drive the IRQ line, which the APU and mapper hold low until acknowledged
+    ///
+    /// The interrupt is taken before the next instruction once the I flag is
+    /// clear; releasing the line first drops the request.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        if asserted {
+            self.request_irq(IrqRequest::Normal);
+        } else if self.irq_request == IrqRequest::Normal {
+            self.irq_request = IrqRequest::None;
+        }
+    }
+
+    /// Push PC and the flags (B clear) and jump through the IRQ vector, taking 7 cycles
+    fn service_irq(&mut self) -> u8 {
+        let pc = self.registers.pc;
+        self.push((pc >> 8) as u8);
+        self.push(pc as u8);
+        self.push(self.flags.to_u8() & !0x10);
+
+        self.flags.interrupt = true;
+        let lo = self.load(0xFFFE) as u16;
+        let hi = self.load(0xFFFF) as u16;
+        self.registers.pc = lo | (hi << 8);
+        self.cycles += 7;
+        7
+    }
+
+    /// Read a byte without driving the data bus; $6000-$FFFF goes to the mapper
+    pub fn peek(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => self.mapper.read_low(address),
+            0x8000..=0xFFFF => self.mapper.read_prg(address),
+            _ => self.memory[address as usize],
+        }
+    }
+
     pub fn load(&mut self, address: u16) -> u8 {
-        let value = self.memory[address as usize];
+        let value = self.peek(address);
         self.data_bus = value;
         value
     }
@@ -239,8 +284,15 @@ impl CPU {
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
+        match address {
+            0x6000..=0x7FFF => self.mapper.write_low(address, value),
+            0x8000..=0xFFFF => self.mapper.write_prg(address, value),
+            _ => self.memory[address as usize] = value,
+        }
         self.data_bus = value;
+        if address == 0x4014 {
+            self.oam_dma_page = Some(value);
+        }
     }
 
     pub fn push(&mut self, value: u8) {
@@ -259,14 +311,14 @@ impl CPU {
                 self.registers.pc
             }
             AddressingMode::ZeroPage => {
-                self.memory[self.registers.pc as usize] as u16
+                self.peek(self.registers.pc) as u16
             }
             AddressingMode::ZeroPageX => {
-                let base = self.memory[self.registers.pc as usize];
+                let base = self.peek(self.registers.pc);
                 (base.wrapping_add(self.registers.x)) as u16
             }
             AddressingMode::ZeroPageY => {
-                let base = self.memory[self.registers.pc as usize];
+                let base = self.peek(self.registers.pc);
                 (base.wrapping_add(self.registers.y)) as u16
             }
             AddressingMode::Absolute => {
@@ -282,23 +334,23 @@ impl CPU {
                 base.wrapping_add(self.registers.y as u16)
             }
             AddressingMode::IndirectX => {
-                let zero = self.memory[self.registers.pc as usize] as u8;
+                let zero = self.peek(self.registers.pc) as u8;
                 let effective = zero.wrapping_add(self.registers.x);
-                let lo = self.memory[effective as usize] as u16;
-                let hi = self.memory[(effective.wrapping_add(1) as usize)] as u16;
+                let lo = self.peek(effective as u16) as u16;
+                let hi = self.peek(effective.wrapping_add(1) as u16) as u16;
                 lo | (hi << 8)
             }
             AddressingMode::IndirectY => {
-                let zero = self.memory[self.registers.pc as usize] as u8;
+                let zero = self.peek(self.registers.pc) as u8;
                 let base = {
-                    let lo = self.memory[zero as usize] as u16;
-                    let hi = self.memory[(zero + 1) as usize] as u16;
+                    let lo = self.peek(zero as u16) as u16;
+                    let hi = self.peek((zero + 1) as u16) as u16;
                     lo | (hi << 8)
                 };
                 base.wrapping_add(self.registers.y as u16)
             }
             AddressingMode::Relative => {
-                let offset = self.memory[self.registers.pc as usize] as i8;
+                let offset = self.peek(self.registers.pc) as i8;
                 self.registers.pc.wrapping_add(offset as u16)
             }
             _ => self.registers.pc,
@@ -656,40 +708,67 @@ impl CPU {
             Opcode::SED, Opcode::SBC, Opcode::NOP, Opcode::ISC, Opcode::NOP, Opcode::SBC, Opcode::INC, Opcode::ISC,
         ];
 
+        // Stores and read-modify-write instructions always take their indexed
+        // worst case; branches are listed untaken
         const CYCLES: [u8; 256] = [
             7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 5, 5, 7, 7,
+            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
             6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 5, 5, 7, 7,
+            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
             6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 5, 5, 7, 7,
+            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
             6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 5, 5, 7, 7,
+            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
             2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
             2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
-            2, 2, 2, 2, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
-            2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
-            2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 5, 5, 7, 7,
             2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 5, 5, 7, 7,
+            2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+            2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+            2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+        ];
+
+        // Reads that take one extra cycle when indexing crosses a page
+        const PAGE_CYCLES: [u8; 256] = [
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1, 1, 1, 1, 1,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
         ];
 
         InstructionInfo {
             opcode: OPCODES[opcode as usize],
             mode: MODES[opcode as usize],
             cycles: CYCLES[opcode as usize],
+            page_cycle: PAGE_CYCLES[opcode as usize] != 0,
         }
     }
 
     pub fn emulate(&mut self) -> u8 {
+        if self.irq_request == IrqRequest::Normal && !self.flags.interrupt {
+            self.irq_request = IrqRequest::None;
+            return self.service_irq();
+        }
+
         let pc = self.registers.pc;
         let opcode = self.load(pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
 
         let info = self.get_instruction(opcode);
         let cycles = info.cycles;
-        self.cycles += cycles as u64;
 
         // Calculate effective address (reads operands from current PC)
         // Then advance PC past the operands
@@ -712,18 +791,21 @@ impl CPU {
         };
         self.registers.pc = self.registers.pc + operand_bytes as u16;
 
+        // Only reads pay for crossing a page; stores and read-modify-write
+        // instructions already count their worst case
+        let operand = pc.wrapping_add(1);
         let mut extra_cycles = 0;
-        if info.mode == AddressingMode::AbsoluteX || info.mode == AddressingMode::AbsoluteY || info.mode == AddressingMode::IndirectY {
+        if info.page_cycle {
             let base = match info.mode {
-                AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
-                    let lo = self.memory[pc as usize] as u16;
-                    let hi = self.memory[(pc as usize + 1) as usize] as u16;
+                AddressingMode::IndirectY => {
+                    let zero = self.peek(operand);
+                    let lo = self.peek(zero as u16) as u16;
+                    let hi = self.peek(zero.wrapping_add(1) as u16) as u16;
                     lo | (hi << 8)
                 }
                 _ => {
-                    let zero = self.memory[pc as usize] as u8;
-                    let lo = self.memory[zero as usize] as u16;
-                    let hi = self.memory[(zero + 1) as usize] as u16;
+                    let lo = self.peek(operand) as u16;
+                    let hi = self.peek(operand.wrapping_add(1)) as u16;
                     lo | (hi << 8)
                 }
             };
@@ -732,6 +814,26 @@ impl CPU {
             }
         }
 
+        // Taken branches add a cycle, and one more when the target is on
+        // another page than the next instruction
+        let branch_taken = match info.opcode {
+            Opcode::BCC => !self.flags.carry,
+            Opcode::BCS => self.flags.carry,
+            Opcode::BEQ => self.flags.zero,
+            Opcode::BNE => !self.flags.zero,
+            Opcode::BPL => !self.flags.sign,
+            Opcode::BMI => self.flags.sign,
+            Opcode::BVC => !self.flags.overflow,
+            Opcode::BVS => self.flags.overflow,
+            _ => false,
+        };
+        if branch_taken {
+            extra_cycles += 1;
+            if (addr & 0xFF00) != (self.registers.pc & 0xFF00) {
+                extra_cycles += 1;
+            }
+        }
+
         match info.opcode {
             Opcode::LDA => {
                 let value = self.load(addr);
@@ -868,6 +970,7 @@ impl CPU {
             _ => {}
         }
 
+        self.cycles += (cycles + extra_cycles) as u64;
         cycles + extra_cycles
     }
 }
@@ -13,11 +13,13 @@ pub mod apu;
 pub mod rom;
 pub mod controller;
 pub mod nes;
+pub mod region;
 pub mod testing;
 
 pub use cpu::{CPU, StatusFlags, Registers, IrqRequest, AddressingMode, Opcode, InstructionInfo};
 pub use ppu::{PPU, STATUS_VBLANK, STATUS_SPRITE0HIT, STATUS_SPRITEOVERFLOW};
-pub use apu::{APU, SquareChannel, TriangleChannel, NoiseChannel, DmcChannel, FrameCounter};
+pub use apu::{APU, SquareChannel, TriangleChannel, NoiseChannel, DmcChannel, FrameClocks, FrameCounter, LengthCounter};
 pub use rom::{Rom, RomHeader, Mapper, Mirroring, create_mapper, MapperInterface};
 pub use controller::{StandardController, ZapperController, ControllerPorts, ControllerType, BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_START, BUTTON_UP, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT};
-pub use nes::NES;
\ No newline at end of file
+pub use nes::NES;
+pub use region::Region;
\ No newline at end of file
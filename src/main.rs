@@ -1,10 +1,23 @@
 //! Rust NES Emulator - Desktop Application using egui
 
+#[path = "../crates/nes-desktop/src/audio.rs"]
+mod audio;
+// Shared with nes-desktop; this app only uses part of it
+#[allow(dead_code)]
+#[path = "../crates/nes-core/src/hotkeys.rs"]
+mod hotkeys;
+
+use audio::AudioOutput;
 use eframe::egui;
+use hotkeys::{Action, HotkeyMap};
+use std::fs;
 use std::time::Instant;
 
 use rust_nes_emulator::{NES, Rom, BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_START, BUTTON_UP, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT};
 
+/// Frames run per update while the fast forward hotkey is held
+const FAST_FORWARD_FRAMES: u32 = 4;
+
 /// App state for the egui application
 struct NesApp {
     nes: NES,
@@ -12,11 +25,21 @@ struct NesApp {
     button_states: [bool; 8],
     last_frame_time: Instant,
     fps: f64,
+    audio: Option<AudioOutput>,
+    hotkeys: HotkeyMap,
+    paused: bool,
+    fast_forward: bool,
+    /// Last hotkey message, shown in the menu bar
+    notice: Option<String>,
 }
 
 impl NesApp {
-    fn new() -> Self {
-        let mut nes = NES::new(44100);
+    fn new(hotkeys: HotkeyMap) -> Self {
+        let audio = AudioOutput::open()
+            .map_err(|e| eprintln!("Audio disabled: {}", e))
+            .ok();
+        let sample_rate = audio.as_ref().map_or(44100, |audio| audio.sample_rate());
+        let mut nes = NES::new(sample_rate);
         // nes.debug = true;  // Disable debug output for normal operation
         Self {
             nes: nes,
@@ -24,6 +47,11 @@ impl NesApp {
             button_states: [false; 8],
             last_frame_time: Instant::now(),
             fps: 0.0,
+            audio,
+            hotkeys,
+            paused: false,
+            fast_forward: false,
+            notice: None,
         }
     }
 
@@ -46,17 +74,50 @@ impl NesApp {
     fn handle_input(&mut self, ctx: &egui::Context) {
         // Keyboard input - check for new key presses in events
         let mut keys_pressed_this_frame: Vec<egui::Key> = Vec::new();
+        // Hotkeys fire once per press, so key repeats are left out
+        let mut hotkeys_pressed: Vec<egui::Key> = Vec::new();
+        let mut keys_down: Vec<egui::Key> = Vec::new();
+        let mut modifiers = hotkeys::Modifiers::default();
 
         ctx.input(|i| {
             for event in &i.raw.events {
-                if let egui::Event::Key { key, pressed, .. } = event {
+                if let egui::Event::Key { key, pressed, repeat, .. } = event {
                     if *pressed {
                         keys_pressed_this_frame.push(*key);
+                        if !*repeat {
+                            hotkeys_pressed.push(*key);
+                        }
                     }
                 }
             }
+            keys_down.extend(i.keys_down.iter().copied());
+            modifiers = hotkeys::Modifiers {
+                ctrl: i.modifiers.ctrl,
+                shift: i.modifiers.shift,
+                alt: i.modifiers.alt,
+            };
         });
 
+        // egui's key names ("F1", "Tab", "A", "1", ...) are the shared hotkey names
+        let actions = self.hotkeys.pressed_actions(hotkeys_pressed.iter().map(|key| key.name()), modifiers);
+        for action in actions {
+            match action {
+                Action::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                Action::Reset => {
+                    self.nes.reset();
+                    self.notice = Some("Reset".to_string());
+                }
+                Action::Pause => {
+                    self.paused = !self.paused;
+                    self.notice = Some(if self.paused { "Paused" } else { "Resumed" }.to_string());
+                }
+                // Held action, checked below
+                Action::FastForward => {}
+                other => self.notice = Some(format!("{} is not available in this frontend", other.name())),
+            }
+        }
+        self.fast_forward = self.hotkeys.is_held(Action::FastForward, keys_down.iter().map(|key| key.name()), modifiers);
+
         let button_map = [
             (egui::Key::Space, BUTTON_SELECT),
             (egui::Key::Enter, BUTTON_START),
@@ -93,9 +154,24 @@ impl eframe::App for NesApp {
         self.fps = 1.0 / dt.max(0.001);
         self.last_frame_time = now;
 
-        // Run NES frame continuously
-        if self.rom_loaded {
-            self.nes.frame();
+        // Run NES frames continuously unless paused
+        let running = self.rom_loaded && !self.paused;
+        if running {
+            let frames = if self.fast_forward { FAST_FORWARD_FRAMES } else { 1 };
+            for _ in 0..frames {
+                self.nes.frame();
+            }
+
+            let samples = self.nes.take_audio_samples();
+            if let Some(audio) = &self.audio {
+                if !self.fast_forward {
+                    audio.push(&samples);
+                }
+                self.nes.apu.sample_rate = audio.adjusted_rate();
+            }
+        }
+        if let Some(audio) = &self.audio {
+            audio.set_paused(!running);
         }
 
         // UI Layout
@@ -109,6 +185,9 @@ impl eframe::App for NesApp {
 
                 ui.label(format!("FPS: {:.1}", self.fps));
                 ui.label(format!("Frames: {}", self.nes.frame_count));
+                if let Some(notice) = &self.notice {
+                    ui.label(notice);
+                }
                             });
         });
 
@@ -185,21 +264,28 @@ fn main() {
         ..Default::default()
     };
 
-    // Get command-line arguments
-    let args: Vec<String> = std::env::args().collect();
-
-    // If a ROM file is provided as argument, pass it to the app
-    let rom_path = if args.len() > 1 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
+    // Get command-line arguments: an optional ROM path and `--hotkeys <file>`
+    let mut rom_path = None;
+    let mut hotkeys_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--hotkeys" {
+            hotkeys_path = args.next();
+        } else {
+            rom_path = Some(arg);
+        }
+    }
+    let hotkeys = load_hotkeys(hotkeys_path.as_deref());
+    println!("Hotkeys:");
+    for (combo, action) in hotkeys.bindings() {
+        println!("  {:<12} {}", combo.to_string(), action.name());
+    }
 
     eframe::run_native(
         "Rust NES Emulator",
         native_options,
         Box::new(move |_| {
-            let mut app = NesApp::new();
+            let mut app = NesApp::new(hotkeys);
             if let Some(path) = rom_path {
                 app.load_rom(&path);
             }
@@ -207,3 +293,20 @@ fn main() {
         }),
     ).expect("Failed to run application");
 }
+
+/// Load the hotkey map from a config file (or the defaults), exiting on error
+fn load_hotkeys(path: Option<&str>) -> HotkeyMap {
+    let Some(path) = path else {
+        return HotkeyMap::default();
+    };
+    let parsed = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| HotkeyMap::from_config(&text).map_err(|e| e.to_string()));
+    match parsed {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("Failed to load hotkeys from {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -2,14 +2,19 @@
 
 use crate::cpu::CPU;
 use crate::ppu::PPU;
-use crate::apu::APU;
-use crate::rom::{Rom, MapperInterface, create_mapper, Mapper};
+use crate::apu::{APU, MIX_FULL_SCALE};
+use crate::rom::{Rom, create_mapper};
 use crate::controller::ControllerPorts;
+use crate::region::Region;
 
 /// NTSC clock speed (Hz)
 const CPU_FREQ_NTSC: f64 = 1789772.5;
 const PPU_FREQ_NTSC: f64 = 5369317.5;  // 3x CPU
 
+/// CPU cycles a DMC sample fetch halts the CPU for (3 when it lands on a
+/// write cycle and 2 inside an OAM DMA on hardware; always 4 here)
+pub const DMC_FETCH_CYCLES: u64 = 4;
+
 /// Frame timing constants
 const DOTS_PER_SCANLINE: u16 = 341;
 const SCANLINES_PER_FRAME: u16 = 262;
@@ -19,19 +24,26 @@ pub struct NES {
     pub cpu: CPU,
     pub ppu: PPU,
     pub apu: APU,
-    pub mapper: Box<dyn MapperInterface>,
     pub controllers: ControllerPorts,
 
     pub rom: Option<Rom>,
     pub frame_count: u32,
+    pub region: Region,
 
     // Cycle tracking for synchronization
     pub cycle_count: u64,
     pub dots_since_last_cpu: u64,
+    // CPU cycles the PPU has been run for, to spread PAL's 3.2 dots per cycle
+    ppu_clock: u64,
 
     // Audio output callback
     pub on_audio_sample: Option<Box<dyn Fn(f32, f32) + Send + Sync>>,
 
+    // Mono samples at the APU's sample rate, waiting for `take_audio_samples`
+    pub audio_samples: Vec<f32>,
+    // CPU cycles times the sample rate since the last sample
+    sample_phase: u64,
+
     // Frame output callback
     pub on_frame: Option<Box<dyn Fn(&[u32]) + Send + Sync>>,
 
@@ -46,13 +58,16 @@ impl NES {
             cpu: CPU::new(),
             ppu: PPU::new(),
             apu: APU::new(sample_rate),
-            mapper: create_mapper(Mapper::NoMapper),
             controllers: ControllerPorts::new(),
             rom: None,
             frame_count: 0,
+            region: Region::Ntsc,
             cycle_count: 0,
             dots_since_last_cpu: 0,
+            ppu_clock: 0,
             on_audio_sample: None,
+            audio_samples: Vec::new(),
+            sample_phase: 0,
             on_frame: None,
             debug: false,
         }
@@ -60,17 +75,20 @@ impl NES {
 
     /// Load a ROM into the emulator
     pub fn load_rom(&mut self, rom: Rom) -> Result<(), &'static str> {
-        // Create appropriate mapper based on ROM header
-        self.mapper = create_mapper(rom.header.mapper);
-
-        // Load ROM data into mapper
-        self.mapper.load_rom(&rom);
+        // Create appropriate mapper based on ROM header; the CPU reads
+        // PRG ROM and writes the mapper registers through it
+        self.cpu.mapper = create_mapper(&rom);
 
-        // Load PRG-ROM into CPU memory
-        self.load_prg_rom(&rom)?;
+        // NES 2.0 headers name the region the game was made for
+        if let Some(region) = rom.header.region {
+            self.set_region(region);
+        }
 
-        // Load CHR-ROM into PPU
-        self.load_chr_rom(&rom);
+        // The PPU reads CHR through the mapper; boards with CHR RAM start
+        // out with a font so ROMs that store text as ASCII bytes display
+        if rom.chr_rom.is_empty() {
+            self.load_default_font_patterns();
+        }
 
         // Initialize nametables with visible content
         self.ppu.init_nametables();
@@ -83,72 +101,30 @@ impl NES {
         Ok(())
     }
 
-    fn load_prg_rom(&mut self, rom: &Rom) -> Result<(), &'static str> {
-        // Copy PRG-ROM to CPU memory ($8000-$FFFF)
-        for (i, &byte) in rom.prg_rom.iter().enumerate() {
-            let addr = 0x8000 + i;
-            if addr < 0x10000 {
-                self.cpu.memory[addr] = byte;
-            }
-        }
-
-        // Also copy to $C000-$FFFF for smaller ROMs
-        // This handles ROMs with less than 32KB PRG-ROM
-        if rom.prg_rom.len() < 0x8000 {
-            let prg_size = rom.prg_rom.len();
-            for i in 0..prg_size {
-                let addr = 0xC000 + i;
-                if addr < 0x10000 {
-                    self.cpu.memory[addr] = rom.prg_rom[i];
-                }
-            }
-        }
-
-        // Ensure reset vector at $FFFC-$FFFD points to a valid address
-        // This is critical for the CPU to start executing at the right place
-        // Use the last 2 bytes of PRG ROM as reset vector if available, otherwise use $8000
-        let rom_size = rom.prg_rom.len();
-        if rom_size >= 2 {
-            let reset_vec = (rom_size as u16) - 2;
-            self.cpu.memory[0xFFFC] = reset_vec as u8;
-            self.cpu.memory[0xFFFD] = (reset_vec >> 8) as u8;
-        } else {
-            // Fallback to $8000 if ROM is too small
-            self.cpu.memory[0xFFFC] = 0x00;
-            self.cpu.memory[0xFFFD] = 0x80;
-        }
-
-        Ok(())
+    /// Switch the PPU frame length, APU timing and clock rate to a region
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
     }
 
-    fn load_chr_rom(&mut self, rom: &Rom) {
-        // Copy CHR-ROM to PPU VRAM
-        for (i, &byte) in rom.chr_rom.iter().enumerate() {
-            if i < self.ppu.vram.len() {
-                self.ppu.vram[i] = byte;
-            }
-        }
-
-        // If CHR ROM is empty or all zeros, load default font patterns
-        // This allows ROMs that store text as ASCII bytes to display correctly
-        let chr_rom_empty = rom.chr_rom.is_empty() || rom.chr_rom.iter().all(|&b| b == 0);
-        if chr_rom_empty {
-            self.load_default_font_patterns();
-        }
+    /// Count PPU dots for the next CPU cycles (3 each, 16 per 5 on PAL)
+    fn ppu_dots(&mut self, cycles: u64) -> u64 {
+        let start = self.region.dots_at_cycle(self.ppu_clock);
+        self.ppu_clock += cycles;
+        self.region.dots_at_cycle(self.ppu_clock) - start
     }
 
-    /// Load default ASCII font patterns into PPU VRAM
+    /// Load default ASCII font patterns into the cartridge's CHR RAM
     fn load_default_font_patterns(&mut self) {
         // Load a simple 8x8 font pattern for ASCII characters 0x20-0x7F
         // Patterns are stored at $0000-$0FFF (pattern table 0)
         for char_code in 0x20..0x80 {
-            let pattern_offset = (char_code as usize) * 16;
-            if pattern_offset + 16 <= self.ppu.vram.len() {
-                // Generate simple 8x8 font pattern for this character
-                let byte_data = Self::get_char_pattern(char_code);
-                for i in 0..16 {
-                    self.ppu.vram[pattern_offset + i] = byte_data[i];
-                }
+            let pattern_offset = (char_code as u16) * 16;
+            // Generate simple 8x8 font pattern for this character
+            let byte_data = Self::get_char_pattern(char_code);
+            for (i, &byte) in byte_data.iter().enumerate() {
+                self.cpu.mapper.write_chr(pattern_offset + i as u16, byte);
             }
         }
     }
@@ -361,30 +337,96 @@ impl NES {
     pub fn reset(&mut self) {
         self.cpu.reset();
         self.ppu = PPU::new();
+        self.ppu.set_region(self.region);
         self.apu.reset();
         self.frame_count = 0;
         self.cycle_count = 0;
+        self.ppu_clock = 0;
     }
 
-    /// Run one CPU instruction
+    /// Pass the APU and mapper IRQ outputs to the CPU's IRQ line
+    fn update_irq_line(&mut self) {
+        self.cpu.set_irq_line(self.apu.irq_pending() || self.cpu.mapper.irq_pending());
+    }
+
+    /// Run one CPU instruction (or the IRQ sequence), or up to 8 cycles of a DMA halt
     pub fn run_cpu(&mut self) -> u8 {
-        let cycles = self.cpu.emulate();
+        let cycles = if self.cpu.cycles_to_halt > 0 {
+            let cycles = self.cpu.cycles_to_halt.min(8);
+            self.cpu.cycles_to_halt -= cycles;
+            cycles as u8
+        } else {
+            self.update_irq_line();
+            let cycles = self.cpu.emulate();
+            self.run_oam_dma();
+            cycles
+        };
         self.cycle_count += cycles as u64;
         cycles
     }
 
+    /// Run an OAM DMA requested by a $4014 write: copy the page into OAM
+    /// starting at OAMADDR and halt the CPU for 513 cycles (514 when the
+    /// write ended on an odd cycle)
+    fn run_oam_dma(&mut self) {
+        let Some(page) = self.cpu.oam_dma_page.take() else {
+            return;
+        };
+        let base = (page as u16) << 8;
+        for i in 0..256 {
+            let address = self.ppu.oam_addr.wrapping_add(i as u8) as usize;
+            self.ppu.oam[address] = self.cpu.peek(base + i);
+        }
+        self.cpu.cycles_to_halt += 513 + (self.cpu.cycles & 1);
+    }
+
     /// Run PPU for specified cycles
     pub fn run_ppu(&mut self, cycles: u64) {
-        self.ppu.run_cycles(cycles);
+        self.ppu.run_cycles(cycles, &mut *self.cpu.mapper);
+    }
+
+    /// Clock the DMC for the given CPU cycles, fetching its sample bytes
+    /// through the mapper; each fetch halts the CPU for `DMC_FETCH_CYCLES`
+    fn run_dmc(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            if let Some(address) = self.apu.dmc.fetch_address() {
+                let value = self.cpu.mapper.read_prg(address);
+                self.apu.dmc.load_sample(value);
+                self.cpu.cycles_to_halt += DMC_FETCH_CYCLES;
+            }
+            self.apu.dmc.clock_timer();
+        }
     }
 
     /// Run APU for specified cycles
     pub fn run_apu(&mut self, cycles: u64) {
         // Clock frame counter
         self.apu.clock_frame_counter(cycles);
+        self.run_dmc(cycles);
 
         // Update channels
-        self.apu.update_channels();
+        self.apu.clock_timers(cycles);
+        self.collect_audio(cycles);
+    }
+
+    /// Sample the APU output after `cycles` CPU cycles, at `apu.sample_rate`
+    fn collect_audio(&mut self, cycles: u64) {
+        let cycles_per_second = self.region.cpu_freq();
+        self.sample_phase += cycles * self.apu.sample_rate as u64;
+        if self.sample_phase < cycles_per_second {
+            return;
+        }
+        let (level, _) = self.apu.get_output();
+        let sample = level as f32 / MIX_FULL_SCALE as f32 * self.apu.master_volume;
+        while self.sample_phase >= cycles_per_second {
+            self.audio_samples.push(sample);
+            self.sample_phase -= cycles_per_second;
+        }
+    }
+
+    /// Take the audio samples produced since the last call
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.audio_samples)
     }
 
     /// Handle a single CPU/PPU/APU cycle
@@ -392,8 +434,8 @@ impl NES {
         // Run CPU instruction
         let cpu_cycles = self.run_cpu();
 
-        // PPU runs at 3x CPU speed
-        let ppu_cycles = cpu_cycles as u64 * 3;
+        // PPU runs at 3x CPU speed (3.2x on PAL)
+        let ppu_cycles = self.ppu_dots(cpu_cycles as u64);
 
         self.run_ppu(ppu_cycles);
         self.run_apu(cpu_cycles as u64);
@@ -434,14 +476,16 @@ impl NES {
 
             if self.cpu.cycles_to_halt == 0 {
                 // Run CPU instruction
+                self.update_irq_line();
                 let cycles = self.cpu.emulate();
-                let ppu_cycles = cycles as u64 * 3;
+                self.run_oam_dma();
+                let ppu_cycles = self.ppu_dots(cycles as u64);
 
                 // Update APU
-                self.apu.clock_frame_counter(cycles as u64);
+                self.run_apu(cycles as u64);
 
                 // Update PPU
-                self.ppu.run_cycles(ppu_cycles);
+                self.ppu.run_cycles(ppu_cycles, &mut *self.cpu.mapper);
 
                 total_cycles += cycles as u64;
 
@@ -458,8 +502,9 @@ impl NES {
             } else {
                 // PPU catchup phase
                 let cycles = self.cpu.cycles_to_halt.min(8) as u64;
-                self.apu.clock_frame_counter(cycles);
-                self.ppu.run_cycles(cycles * 3);
+                self.run_apu(cycles);
+                let ppu_cycles = self.ppu_dots(cycles);
+                self.ppu.run_cycles(ppu_cycles, &mut *self.cpu.mapper);
                 self.cpu.cycles_to_halt -= cycles as u64;
                 total_cycles += cycles;
             }
@@ -480,46 +525,6 @@ impl NES {
         }
     }
 
-    /// Process controller input
-    pub fn process_frame(&mut self) {
-        // Process APU frame steps based on current scanline
-        let scanline = self.ppu.scanline as u64;
-
-        // Check for frame counter steps
-        let _step = self.apu.frame_counter.get_step();
-
-        match self.apu.frame_counter.count_sequence {
-            0 => {
-                // 4-step sequence
-                if scanline == 20 {
-                    self.apu.clock_quarter_frame();
-                } else if scanline == 63 {
-                    self.apu.clock_half_frame();
-                } else if scanline == 112 {
-                    self.apu.clock_quarter_frame();
-                } else if scanline == 162 {
-                    self.apu.clock_half_frame();
-                }
-            }
-            1 => {
-                // 5-step sequence
-                if scanline == 20 {
-                    self.apu.clock_quarter_frame();
-                } else if scanline == 63 {
-                    self.apu.clock_half_frame();
-                } else if scanline == 112 {
-                    self.apu.clock_quarter_frame();
-                } else if scanline == 162 {
-                    self.apu.clock_half_frame();
-                } else if scanline == 211 {
-                    self.apu.clock_quarter_frame();
-                    self.apu.clock_half_frame();
-                }
-            }
-            _ => {}
-        }
-    }
-
     /// Get frame buffer
     pub fn get_frame_buffer(&self) -> &[u32] {
         &self.ppu.frame_buffer
@@ -545,12 +550,12 @@ impl NES {
 
     /// Write to PPU register
     pub fn write_ppu(&mut self, address: u16, value: u8) {
-        self.ppu.write(address, value);
+        self.ppu.write(address, value, &mut *self.cpu.mapper);
     }
 
     /// Read from PPU register
     pub fn read_ppu(&mut self, address: u16) -> u8 {
-        self.ppu.read(address)
+        self.ppu.read(address, &mut *self.cpu.mapper)
     }
 
     /// Write to APU register
@@ -601,6 +606,161 @@ mod tests {
         assert!(cpu.flags.interrupt);
     }
 
+    #[test]
+    fn test_oam_dma() {
+        let mut nes = NES::new(44100);
+        for i in 0..256 {
+            nes.cpu.memory[0x0200 + i] = i as u8;
+        }
+        nes.ppu.oam_addr = 4;
+        nes.cpu.write(0x4014, 0x02);
+        nes.run_oam_dma();
+
+        // The copy starts at OAMADDR and wraps
+        assert_eq!(nes.ppu.oam[4], 0x00);
+        assert_eq!(nes.ppu.oam[3], 0xFF);
+        assert_eq!(nes.cpu.cycles_to_halt, 513 + (nes.cpu.cycles & 1));
+        assert_eq!(nes.cpu.oam_dma_page, None);
+    }
+
+    #[test]
+    fn test_dmc_fetches_through_mapper_and_stalls() {
+        // NROM-128: $C000 mirrors $8000, so the sample at $C000 is PRG offset 0
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0x00, 0x00];
+        data.resize(16, 0);
+        data.resize(16 + 0x4000 + 0x2000, 0);
+        data[16] = 0xFF;
+        data[17] = 0x00;
+        let mut nes = NES::new(44100);
+        nes.cpu.mapper = create_mapper(&Rom::load_from_data(&data).unwrap());
+
+        nes.write_apu(0x4010, 0x8F);  // IRQ mode, fastest rate
+        nes.write_apu(0x4011, 0x40);
+        nes.write_apu(0x4012, 0x00);  // $C000
+        nes.write_apu(0x4013, 0x00);  // 1 byte
+        nes.write_apu(0x4015, 0x10);
+        assert_eq!(nes.apu.read(0x4015) & 0x10, 0x10);
+
+        // The empty buffer is filled on the first cycle, which ends the sample and raises the IRQ
+        nes.run_dmc(1);
+        assert_eq!(nes.apu.dmc.sample_buffer, Some(0xFF));
+        assert_eq!(nes.cpu.cycles_to_halt, DMC_FETCH_CYCLES);
+        assert!(nes.apu.dmc.irq_pending);
+        assert_eq!(nes.apu.read(0x4015), 0x80);
+
+        // Silent until the first output cycle ends (its first bit still at the power-on rate),
+        // then the byte raises the level by 2 per bit
+        nes.run_dmc(427 + 54 * 7);
+        assert_eq!(nes.apu.dmc.get_output(), 0x40);
+        nes.run_dmc(54 * 8);
+        assert_eq!(nes.apu.dmc.get_output(), 0x40 + 16);
+        assert_eq!(nes.cpu.cycles_to_halt, DMC_FETCH_CYCLES);
+
+        // Writing $4015 acknowledges the IRQ
+        nes.write_apu(0x4015, 0x00);
+        assert!(!nes.apu.dmc.irq_pending);
+    }
+
+    #[test]
+    fn test_cartridge_space_goes_through_mapper() {
+        // UNROM with 4 banks filled with $C0 plus their number
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 4, 0, 0x20, 0x00];
+        data.resize(16, 0);
+        for bank in 0..4 {
+            data.extend(vec![0xC0 | bank; 0x4000]);
+        }
+        let mut nes = NES::new(44100);
+        nes.load_rom(Rom::load_from_data(&data).unwrap()).unwrap();
+
+        // The reset vector comes from the fixed last bank
+        assert_eq!(nes.cpu.registers.pc, 0xC3C3);
+        assert_eq!(nes.cpu.load(0x8000), 0xC0);
+
+        // Writes to ROM switch the bank instead of changing memory
+        nes.cpu.write(0x8000, 2);
+        assert_eq!(nes.cpu.load(0x8000), 0xC2);
+        assert_eq!(nes.cpu.load(0xC000), 0xC3);
+        assert_eq!(nes.cpu.memory[0x8000], 0x00);
+    }
+
+    #[test]
+    fn test_audio_samples_at_sample_rate() {
+        let mut nes = NES::new(44100);
+        nes.write_apu(0x4015, 0x01);
+        nes.write_apu(0x4000, 0xBF);  // 50% duty, constant volume 15
+        nes.write_apu(0x4002, 0xFD);  // ~440 Hz
+        nes.write_apu(0x4003, 0x00);
+
+        // One second of 2-cycle instructions gives one second of samples, tracing the square wave
+        for _ in 0..CPU_FREQ_NTSC as u64 / 2 {
+            nes.run_apu(2);
+        }
+        let samples = nes.take_audio_samples();
+        assert_eq!(samples.len(), 44100);
+        assert!(samples.iter().any(|&sample| sample > 0.0));
+        assert!(samples.iter().any(|&sample| sample == 0.0));
+        assert!(nes.take_audio_samples().is_empty());
+    }
+
+    #[test]
+    fn test_region_timing() {
+        let mut nes = NES::new(44100);
+        nes.set_region(Region::Pal);
+        let dots: Vec<u64> = (0..5).map(|_| nes.ppu_dots(1)).collect();
+        assert_eq!(dots, [3, 3, 3, 3, 4]);
+        assert_eq!(nes.apu.dmc.rates[0], 398);
+
+        // The Dendy keeps NTSC's 3 dots per cycle but starts vblank at line 291
+        nes.set_region(Region::Dendy);
+        nes.reset();
+        nes.ppu.start_frame();
+        while nes.ppu.open_bus & crate::ppu::STATUS_VBLANK == 0 {
+            let dots = nes.ppu_dots(1);
+            assert_eq!(dots, 3);
+            nes.ppu.run_cycles(dots, &mut *nes.cpu.mapper);
+        }
+        assert_eq!(nes.ppu.scanline, 291);
+        assert_eq!(nes.apu.dmc.rates[0], 428);
+    }
+
+    #[test]
+    fn test_frame_irq_reaches_cpu() {
+        let mut nes = NES::new(44100);
+        // CLI, then spin on NOPs; the IRQ handler at $9000 is NOPs too
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 1, 0x00, 0x00];
+        data.resize(16, 0);
+        let mut prg = vec![0xEA; 0x8000];
+        prg[0x0000] = 0x58;
+        prg[0x7FFE] = 0x00;
+        prg[0x7FFF] = 0x90;
+        data.extend(prg);
+        data.resize(data.len() + 0x2000, 0);
+        nes.load_rom(Rom::load_from_data(&data).unwrap()).unwrap();
+        nes.cpu.registers.pc = 0x8000;
+        nes.cpu.flags.interrupt = true;
+
+        // The 4-step sequence raises the IRQ 29828 cycles in
+        nes.apu.clock_frame_counter(29827);
+        nes.cpu.cycles_to_halt = 0;
+        nes.cycle();
+        assert_eq!(nes.cpu.registers.pc, 0x8001);
+        assert!(nes.apu.irq_pending());
+
+        // CLI took effect: the next step takes the interrupt with B clear on the stack
+        assert_eq!(nes.run_cpu(), 7);
+        assert_eq!(nes.cpu.registers.pc, 0x9000);
+        assert!(nes.cpu.flags.interrupt);
+        assert_eq!(nes.cpu.memory[0x01FB] & 0x30, 0x20);
+        assert_eq!(nes.cpu.memory[0x01FC], 0x01);
+        assert_eq!(nes.cpu.memory[0x01FD], 0x80);
+
+        // Acknowledging through $4015 releases the line
+        nes.apu.read(0x4015);
+        nes.cpu.flags.interrupt = false;
+        nes.run_cpu();
+        assert_eq!(nes.cpu.registers.pc, 0x9001);
+    }
+
     #[test]
     fn test_ppu_init() {
         let ppu = PPU::new();
@@ -2,6 +2,9 @@
 //!
 //! Implements the Ricoh 2C02 PPU used in the NES.
 
+use crate::region::Region;
+use crate::rom::{MapperInterface, Mirroring};
+
 /// PPU Status flags
 pub const STATUS_VBLANK: u8 = 0x80;
 pub const STATUS_SPRITE0HIT: u8 = 0x40;
@@ -100,7 +103,7 @@ pub struct PpuRegisters {
 /// The PPU emulator
 #[derive(Debug)]
 pub struct PPU {
-    pub vram: [u8; 0x8000],      // 32KB VRAM
+    pub vram: [u8; 0x8000],      // 32KB VRAM (pattern tables are read from the mapper)
     pub oam: [u8; 256],          // 256-byte OAM (Object Attribute Memory)
     pub palette: [u8; 32],       // 32-byte palette RAM
     pub open_bus: u8,            // Open bus latch
@@ -113,6 +116,8 @@ pub struct PPU {
     pub scanline: i16,           // Current scanline (-1 to 261)
     pub frame_count: u32,        // Frame counter
     pub frame_complete: bool,    // Flag set when a frame completes
+    pub vblank_scanline: i16,    // Scanline VBlank starts on (241, 291 on Dendy)
+    pub last_scanline: i16,      // Last scanline of a frame (261, 311 on PAL and Dendy)
 
     // Scrolling counters
     pub vram_address: u16,       // Current VRAM address
@@ -176,6 +181,8 @@ impl PPU {
             scanline: -1,
             frame_count: 0,
             frame_complete: false,
+            vblank_scanline: 241,
+            last_scanline: 261,
 
             vram_address: 0,
             vram_buffered_value: 0,
@@ -217,6 +224,12 @@ impl PPU {
         ppu
     }
 
+    /// Set the frame length and VBlank line for a region
+    pub fn set_region(&mut self, region: Region) {
+        self.vblank_scanline = region.vblank_scanline();
+        self.last_scanline = region.scanlines() - 1;
+    }
+
     /// Start a new frame
     pub fn start_frame(&mut self) {
         // Reset frame state for new frame
@@ -254,7 +267,7 @@ impl PPU {
     }
 
     /// Render a scanline to the frame buffer
-    fn render_scanline(&mut self, scanline: i16) {
+    fn render_scanline(&mut self, scanline: i16, mapper: &mut dyn MapperInterface) {
         if scanline < 0 || scanline >= 240 {
             return;  // Not a visible scanline
         }
@@ -262,7 +275,7 @@ impl PPU {
         let y = scanline as u16;
 
         for x in 0..256 {
-            let color = self.render_pixel(x as u16, y);
+            let color = self.render_pixel(x as u16, y, mapper);
             let pixel_index = (y as usize * 256) + (x as usize);
             if pixel_index < self.frame_buffer.len() {
                 self.frame_buffer[pixel_index] = color;
@@ -271,7 +284,7 @@ impl PPU {
     }
 
     /// End current scanline
-    pub fn end_scanline(&mut self) {
+    pub fn end_scanline(&mut self, mapper: &mut dyn MapperInterface) {
         if self.debug {
             eprintln!("PPU: end_scanline, prev_scanline={}", self.scanline);
         }
@@ -286,19 +299,19 @@ impl PPU {
             if self.debug {
                 eprintln!("PPU: rendering scanline {}", prev_scanline);
             }
-            self.render_scanline(prev_scanline);
+            self.render_scanline(prev_scanline, mapper);
         } else if self.debug {
             eprintln!("PPU: skipping render for scanline {} (prev_scanline={})", self.scanline - 1, prev_scanline);
         }
 
-        if self.scanline == 241 {
+        if self.scanline == self.vblank_scanline {
             // Start of VBlank
             if self.debug {
                 eprintln!("PPU: VBlank start");
             }
             self.start_vblank();
-        } else if self.scanline > 261 {
-            // Frame complete - set flag and keep scanline at 262
+        } else if self.scanline > self.last_scanline {
+            // Frame complete - set flag and keep scanline past the last
             // until start_frame() is called
             if self.debug {
                 eprintln!("PPU: frame complete!");
@@ -322,7 +335,7 @@ impl PPU {
     }
 
     /// Read from PPU registers
-    pub fn read(&mut self, address: u16) -> u8 {
+    pub fn read(&mut self, address: u16, mapper: &mut dyn MapperInterface) -> u8 {
         let value = match address {
             0x2002 => {
                 let value = self.open_bus;
@@ -339,9 +352,8 @@ impl PPU {
             }
             0x2007 => {
                 // VRAM data read
-                let addr = self.vram_address as usize;
                 let value = self.vram_buffered_value;
-                self.vram_buffered_value = self.vram[addr];
+                self.vram_buffered_value = self.vram_read(self.vram_address, mapper);
                 self.vram_address = self.vram_address.wrapping_add(self.address_increment as u16);
                 value
             }
@@ -355,7 +367,7 @@ impl PPU {
     }
 
     /// Write to PPU registers
-    pub fn write(&mut self, address: u16, value: u8) {
+    pub fn write(&mut self, address: u16, value: u8, mapper: &mut dyn MapperInterface) {
         self.open_bus = value;
 
         if self.debug {
@@ -431,13 +443,11 @@ impl PPU {
             }
             0x2007 => {
                 // PPUDATA - VRAM data write
-                let addr = self.vram_address as usize;
-                self.vram[addr] = value;
+                self.vram_write(self.vram_address, value, mapper);
                 self.vram_address = self.vram_address.wrapping_add(self.address_increment as u16);
 
                 // Buffer the read value
-                let next_addr = self.vram_address as usize;
-                self.vram_buffered_value = self.vram[next_addr];
+                self.vram_buffered_value = self.vram_read(self.vram_address, mapper);
             }
             _ => {
                 // Mirror of $2000-$2007
@@ -445,16 +455,33 @@ impl PPU {
         }
     }
 
-    /// Read from VRAM
-    pub fn vram_read(&mut self, address: u16) -> u8 {
-        let addr = (address & 0x3FFF) as usize;
-        self.vram[addr]
+    /// Index into `vram` for a PPU address: the nametables ($2000-$3EFF) are
+    /// folded onto the pages the mirroring selects, the rest is used as is
+    fn vram_index(address: u16, mirroring: Mirroring) -> usize {
+        let address = (address & 0x3FFF) as usize;
+        match address {
+            0x2000..=0x3EFF => {
+                let offset = (address - 0x2000) & 0x0FFF;
+                0x2000 + mirroring.nametable_page(offset / 0x400) * 0x400 + offset % 0x400
+            }
+            _ => address,
+        }
+    }
+
+    /// Read from VRAM (the pattern tables at $0000-$1FFF are on the cartridge)
+    pub fn vram_read(&mut self, address: u16, mapper: &mut dyn MapperInterface) -> u8 {
+        match address & 0x3FFF {
+            0x0000..=0x1FFF => mapper.read_chr(address & 0x1FFF),
+            _ => self.vram[Self::vram_index(address, mapper.mirroring())],
+        }
     }
 
-    /// Write to VRAM
-    pub fn vram_write(&mut self, address: u16, value: u8) {
-        let addr = (address & 0x3FFF) as usize;
-        self.vram[addr] = value;
+    /// Write to VRAM (pattern table writes only stick on CHR RAM boards)
+    pub fn vram_write(&mut self, address: u16, value: u8, mapper: &mut dyn MapperInterface) {
+        match address & 0x3FFF {
+            0x0000..=0x1FFF => mapper.write_chr(address & 0x1FFF, value),
+            _ => self.vram[Self::vram_index(address, mapper.mirroring())] = value,
+        }
     }
 
     /// Get palette color with emphasis
@@ -478,20 +505,20 @@ impl PPU {
     }
 
     /// Get attribute table index for a tile
-    pub fn get_attribute(&mut self, tile_x: u8, tile_y: u8, nametable: u8) -> u8 {
-        let nametable_offset = nametable as u16 * 0x400;
+    pub fn get_attribute(&mut self, tile_x: u8, tile_y: u8, nametable: u8, mapper: &mut dyn MapperInterface) -> u8 {
+        let nametable_offset = 0x2000 + nametable as u16 * 0x400;
         let attr_x = tile_x / 4;
         let attr_y = tile_y / 4;
         let attr_addr = nametable_offset + 0x3C0 + (attr_y as u16) * 8 + (attr_x as u16) / 2;
 
-        let byte = self.vram_read(attr_addr as u16);
+        let byte = self.vram_read(attr_addr as u16, mapper);
         let shift = ((tile_x % 4) % 2) * 4;
 
         (byte >> shift) & 0x03
     }
 
     /// Render a single pixel
-    pub fn render_pixel(&mut self, x: u16, y: u16) -> u32 {
+    pub fn render_pixel(&mut self, x: u16, y: u16, mapper: &mut dyn MapperInterface) -> u32 {
         // Check sprite 0 hit
         if x < 256 && y < 240 {
             if self.sprite0_hit && self.sprite_visible && self.bg_visible {
@@ -501,7 +528,7 @@ impl PPU {
 
         // Render background if visible
         if self.bg_visible && x < 256 && y < 240 {
-            let bg_color = self.render_background(x, y);
+            let bg_color = self.render_background(x, y, mapper);
             if bg_color != 0 {
                 return bg_color;
             }
@@ -509,7 +536,7 @@ impl PPU {
 
         // Render sprites if visible
         if self.sprite_visible && x < 256 && y < 240 {
-            if let Some(sprite_color) = self.render_sprite(x, y) {
+            if let Some(sprite_color) = self.render_sprite(x, y, mapper) {
                 return sprite_color;
             }
         }
@@ -518,7 +545,7 @@ impl PPU {
     }
 
     /// Render background pixel
-    fn render_background(&mut self, x: u16, y: u16) -> u32 {
+    fn render_background(&mut self, x: u16, y: u16, mapper: &mut dyn MapperInterface) -> u32 {
         // Calculate tile coordinates
         let coarse_x = (x as u16 / 8) & 0x1F;
         let coarse_y = (y as u16 / 8) & 0x1F;
@@ -526,24 +553,24 @@ impl PPU {
         let fine_y = y as u16 & 0x07;
 
         // Get nametable base
-        let nametable_base = self.nametable_select * 0x400;
+        let nametable_base = 0x2000 + self.nametable_select * 0x400;
 
         // Calculate tile index address
         let tile_index_addr = nametable_base + (coarse_y as u16) * 32 + (coarse_x as u16);
-        let tile_index = self.vram_read(tile_index_addr);
+        let tile_index = self.vram_read(tile_index_addr, mapper);
 
         // Calculate attribute table address
         let attr_x = coarse_x / 4;
         let attr_y = coarse_y / 4;
         let attr_addr = nametable_base + 0x3C0 + (attr_y as u16) * 8 + (attr_x as u16) / 2;
-        let attr_byte = self.vram_read(attr_addr);
+        let attr_byte = self.vram_read(attr_addr, mapper);
         let attr_shift = ((coarse_x % 4) % 2) * 4;
         let palette = ((attr_byte >> attr_shift) & 0x03) as u8;
 
         // Calculate pattern table address
         let pattern_addr = self.bg_pattern_table + (tile_index as u16) * 16 + (fine_y as u16);
-        let byte1 = self.vram_read(pattern_addr);
-        let byte2 = self.vram_read(pattern_addr + 8);
+        let byte1 = self.vram_read(pattern_addr, mapper);
+        let byte2 = self.vram_read(pattern_addr + 8, mapper);
 
         // Get pixel color
         let bit1 = (byte1 >> (7 - fine_x as u8)) & 1;
@@ -555,13 +582,13 @@ impl PPU {
         }
 
         let palette_addr = 0x3F00 + (palette as u16) * 4 + color as u16;
-        let palette_index = self.vram_read(palette_addr) & 0x3F;
+        let palette_index = self.vram_read(palette_addr, mapper) & 0x3F;
 
         self.get_palette_color(palette_index)
     }
 
     /// Render sprite pixel
-    fn render_sprite(&mut self, x: u16, y: u16) -> Option<u32> {
+    fn render_sprite(&mut self, x: u16, y: u16, mapper: &mut dyn MapperInterface) -> Option<u32> {
         if !self.sprite_visible {
             return None;
         }
@@ -605,8 +632,8 @@ impl PPU {
 
             // Get tile data
             let pattern_addr = sprite_pattern_base + (tile as u16) * 16 + (render_y as u16);
-            let byte1 = self.vram_read(pattern_addr);
-            let byte2 = self.vram_read(pattern_addr + 8);
+            let byte1 = self.vram_read(pattern_addr, mapper);
+            let byte2 = self.vram_read(pattern_addr + 8, mapper);
 
             let bit1 = (byte1 >> (7 - render_x as u8)) & 1;
             let bit2 = (byte2 >> (7 - render_x as u8)) & 1;
@@ -620,7 +647,7 @@ impl PPU {
             let palette = ((attr & 0x03) as u8) + 1;  // Sprite palettes are 1-3
 
             let palette_addr = 0x3F10 + (palette as u16) * 4 + color as u16;
-            let palette_index = self.vram_read(palette_addr) & 0x3F;
+            let palette_index = self.vram_read(palette_addr, mapper) & 0x3F;
 
             return Some(self.get_palette_color(palette_index));
         }
@@ -629,7 +656,7 @@ impl PPU {
     }
 
     /// Check sprite 0 hit
-    pub fn check_sprite0_hit(&mut self, x: u16, y: u16) {
+    pub fn check_sprite0_hit(&mut self, x: u16, y: u16, mapper: &mut dyn MapperInterface) {
         if !self.sprite_visible || !self.bg_visible {
             return;
         }
@@ -643,27 +670,48 @@ impl PPU {
         if y >= sprite_y && y < sprite_y + sprite_size &&
            x >= sprite_x && x < sprite_x + 8 {
             // Check for non-transparent pixel overlap
-            let bg_color = self.render_background(x, y);
+            let bg_color = self.render_background(x, y, mapper);
             if bg_color != 0 {
                 self.sprite0_hit = true;
             }
         }
     }
 
+    /// Dot of the pre-render and visible lines where PPU A12 rises, if it does
+    ///
+    /// With sprites at $1000 (or 8x16 sprites) the sprite fetches from dot
+    /// 257 raise it; otherwise the background prefetch from dot 321 does when
+    /// the background is at $1000. MMC3 counts these edges as scanlines.
+    fn a12_rise_dot(&self) -> Option<u16> {
+        if !self.bg_visible && !self.sprite_visible {
+            None
+        } else if self.sprite_size || self.sp_pattern_table == 0x1000 {
+            Some(260)
+        } else if self.bg_pattern_table == 0x1000 {
+            Some(324)
+        } else {
+            None
+        }
+    }
+
     /// Run PPU for n cycles
-    pub fn run_cycles(&mut self, cycles: u64) {
+    pub fn run_cycles(&mut self, cycles: u64, mapper: &mut dyn MapperInterface) {
         for _ in 0..cycles {
             self.cur_x += 1;
 
+            if self.scanline < 240 && Some(self.cur_x) == self.a12_rise_dot() {
+                mapper.ppu_a12_clock();
+            }
+
             if self.cur_x == 341 {
                 self.cur_x = 0;
-                self.end_scanline();
+                self.end_scanline(mapper);
             }
         }
     }
 
     /// Update sprite evaluation
-    pub fn update_sprite_evaluation(&mut self) {
+    pub fn update_sprite_evaluation(&mut self, mapper: &mut dyn MapperInterface) {
         // Sprite evaluation happens during pre-render scanline (scanline -1)
         // and the first visible scanline
         if self.scanline != -1 && self.scanline != 255 {
@@ -692,7 +740,7 @@ impl PPU {
                 if i == 0 && self.bg_visible {
                     let sprite_x = self.oam[(base as usize) + 3] as u16;
                     if self.cur_x >= sprite_x && self.cur_x < sprite_x + 8 {
-                        self.check_sprite0_hit(self.cur_x, self.scanline as u16);
+                        self.check_sprite0_hit(self.cur_x, self.scanline as u16, mapper);
                     }
                 }
             }
@@ -710,6 +758,7 @@ impl Default for PPU {
 #[cfg(test)]
 mod ppu_tests {
     use super::*;
+    use crate::rom::{NoMapper, Rom, MMC3};
 
     #[test]
     fn test_render_pixel_background() {
@@ -726,7 +775,7 @@ mod ppu_tests {
         ppu.palette[3] = 0x00;
         
         // Draw a simple pattern - render a pixel at (10, 20)
-        let color = ppu.render_pixel(10, 20);
+        let color = ppu.render_pixel(10, 20, &mut NoMapper::new());
         
         // Should be black (0) since no background is visible at that position
         // (palette 0, color 0 is black)
@@ -749,4 +798,58 @@ mod ppu_tests {
         // Check that pixels were written
         assert_eq!(ppu.frame_buffer[256 * 5 + 10], 0xFF0000FF);
     }
+
+    #[test]
+    fn test_nametable_mirroring_follows_mapper() {
+        let mut ppu = PPU::new();
+        let mut mapper = MMC3::new();
+
+        // MMC3 starts out horizontal: $2000 and $2400 are the same page
+        ppu.vram_write(0x2005, 0x11, &mut mapper);
+        assert_eq!(ppu.vram_read(0x2405, &mut mapper), 0x11);
+        assert_eq!(ppu.vram_read(0x2805, &mut mapper), 0x00);
+        // $3000-$3EFF mirrors $2000-$2EFF
+        assert_eq!(ppu.vram_read(0x3005, &mut mapper), 0x11);
+
+        // Switching to vertical at runtime pairs $2000 with $2800 instead
+        mapper.write_prg(0xA000, 0);
+        assert_eq!(ppu.vram_read(0x2805, &mut mapper), 0x11);
+        assert_eq!(ppu.vram_read(0x2405, &mut mapper), 0x00);
+    }
+
+    #[test]
+    fn test_a12_clocks_mmc3_irq() {
+        let mut ppu = PPU::new();
+        let mut mapper = MMC3::new();
+        mapper.write_prg(0xC000, 2);
+        mapper.write_prg(0xC001, 0);
+        mapper.write_prg(0xE001, 0);
+
+        // Both pattern tables at $0000: A12 never rises
+        ppu.run_cycles(341 * 4, &mut mapper);
+        assert!(!mapper.irq_pending());
+
+        // Sprites at $1000: one clock per line, reload on the first,
+        // then 1 and 0 on the next two
+        ppu.sp_pattern_table = 0x1000;
+        ppu.run_cycles(341 * 2, &mut mapper);
+        assert!(!mapper.irq_pending());
+        ppu.run_cycles(341, &mut mapper);
+        assert!(mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_pattern_tables_come_from_mapper() {
+        let mut ppu = PPU::new();
+        // NROM without CHR ROM has CHR RAM, written through $2006/$2007
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 0, 0x00, 0x00];
+        data.resize(16 + 0x4000, 0);
+        let mut mapper = NoMapper::from_rom(&Rom::load_from_data(&data).unwrap());
+        ppu.write(0x2006, 0x10, &mut mapper);
+        ppu.write(0x2006, 0x20, &mut mapper);
+        ppu.write(0x2007, 0x5A, &mut mapper);
+        assert_eq!(mapper.read_chr(0x1020), 0x5A);
+        assert_eq!(ppu.vram[0x1020], 0x00);
+        assert_eq!(ppu.vram_read(0x1020, &mut mapper), 0x5A);
+    }
 }
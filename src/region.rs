@@ -0,0 +1,71 @@
+//! Console regions (NTSC, PAL and the Dendy Famiclone)
+//!
+//! PAL consoles run 3.2 PPU dots per CPU cycle instead of 3, draw 312
+//! scanlines and use their own APU frame counter, noise and DMC tables. The
+//! Dendy draws 312 scanlines too but keeps the NTSC CPU timing and tables,
+//! starting vblank 50 lines late.
+
+/// TV system a console was built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    /// Parse a region name ("ntsc", "pal" or "dendy", any case)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    /// CPU clock (Hz, rounded down)
+    pub fn cpu_freq(self) -> u64 {
+        match self {
+            Region::Ntsc => 1_789_772,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    /// PPU dots run by the end of CPU cycle `cycle`
+    ///
+    /// PAL's 3.2 dots per cycle is spread out by taking the difference
+    /// between two cycle counts.
+    pub fn dots_at_cycle(self, cycle: u64) -> u64 {
+        match self {
+            Region::Pal => cycle * 16 / 5,
+            Region::Ntsc | Region::Dendy => cycle * 3,
+        }
+    }
+
+    /// Scanlines per frame
+    pub fn scanlines(self) -> i16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// Scanline vblank starts on
+    pub fn vblank_scanline(self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Frames per second, rounded
+    pub fn fps(self) -> u32 {
+        match self {
+            Region::Ntsc => 60,
+            Region::Pal | Region::Dendy => 50,
+        }
+    }
+}
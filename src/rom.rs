@@ -1,34 +1,16 @@
 //! NES ROM loading and parsing
 
+use crate::region::Region;
 use std::fs::File;
 use std::io::{self, Read, Write};
 
+#[path = "../crates/nes-core/src/mapper_interface.rs"]
+mod mapper_interface;
+pub use mapper_interface::{MapperInterface, Mirroring};
+
 /// NES ROM header magic number
 pub const NES_MAGIC: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];  // "NES\x1A"
 
-/// Mirroring modes
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum Mirroring {
-    Horizontal = 0,
-    Vertical = 1,
-    FourScreen = 2,
-    SingleScreenA = 3,
-    SingleScreenB = 4,
-}
-
-impl Mirroring {
-    pub fn from_value(value: u8) -> Self {
-        match value {
-            0 => Mirroring::Horizontal,
-            1 => Mirroring::Vertical,
-            2 => Mirroring::FourScreen,
-            3 => Mirroring::SingleScreenA,
-            4 => Mirroring::SingleScreenB,
-            _ => Mirroring::Horizontal,
-        }
-    }
-}
-
 /// Trainer presence
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Trainer {
@@ -120,9 +102,19 @@ pub struct RomHeader {
     pub has_battery_ram: bool,
     pub has_trainer: bool,
     pub four_screen: bool,
+    pub region: Option<Region>,   // NES 2.0 only; None for iNES 1.0 and multi-region
 }
 
 impl RomHeader {
+    /// Mirroring the board is wired for (four-screen VRAM overrides the mirroring bit)
+    pub fn nametable_mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            Mirroring::FourScreen
+        } else {
+            self.mirroring
+        }
+    }
+
     pub fn parse(data: &[u8]) -> Result<Self, io::Error> {
         if data.len() < 16 {
             return Err(io::Error::new(io::ErrorKind::InvalidData, "ROM too small"));
@@ -139,7 +131,7 @@ impl RomHeader {
         let flags7 = data[7];
 
         // Parse flags
-        let mirroring = Mirroring::from_value(if (flags6 & 0x01) != 0 { 1 } else { 0 });
+        let mirroring = if (flags6 & 0x01) != 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
         let has_battery_ram = (flags6 & 0x02) != 0;
         let has_trainer = (flags6 & 0x04) != 0;
         let four_screen = (flags6 & 0x08) != 0;
@@ -150,6 +142,18 @@ impl RomHeader {
         let mapper_value = (mapper_high | mapper_low) as u8;
         let mapper = Mapper::from_value(mapper_value);
 
+        // NES 2.0 headers give the CPU/PPU timing in byte 12
+        let region = if flags7 & 0x0C == 0x08 {
+            match data[12] & 0x03 {
+                0 => Some(Region::Ntsc),
+                1 => Some(Region::Pal),
+                3 => Some(Region::Dendy),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
         Ok(Self {
             prg_rom_size,
             chr_rom_size,
@@ -158,6 +162,7 @@ impl RomHeader {
             has_battery_ram,
             has_trainer,
             four_screen,
+            region,
         })
     }
 }
@@ -234,35 +239,28 @@ impl Rom {
     }
 }
 
-/// Mapper factory
-pub fn create_mapper(mapper: Mapper) -> Box<dyn MapperInterface> {
-    match mapper {
-        Mapper::NoMapper => Box::new(NoMapper::new()),
-        Mapper::MMC1 => Box::new(MMC1::new()),
-        Mapper::UNROM => Box::new(UNROM::new()),
-        Mapper::CNROM => Box::new(CNROM::new()),
-        Mapper::MMC3 => Box::new(MMC3::new()),
-        _ => Box::new(NoMapper::new()),
+/// Create the mapper for a ROM's header, loaded with its PRG and CHR
+///
+/// The mappers implement nes-core's `MapperInterface`, which this crate
+/// shares by path. Unsupported mappers run as NROM.
+pub fn create_mapper(rom: &Rom) -> Box<dyn MapperInterface> {
+    match rom.header.mapper {
+        Mapper::MMC1 => Box::new(MMC1::from_rom(rom)),
+        Mapper::UNROM => Box::new(UNROM::from_rom(rom)),
+        Mapper::CNROM => Box::new(CNROM::from_rom(rom)),
+        Mapper::MMC3 => Box::new(MMC3::from_rom(rom)),
+        _ => Box::new(NoMapper::from_rom(rom)),
     }
 }
 
-/// Mapper trait
-pub trait MapperInterface {
-    fn reset(&mut self);
-    fn read_low(&mut self, address: u16) -> u8;
-    fn write_low(&mut self, address: u16, value: u8);
-    fn load_rom(&mut self, rom: &Rom);
-    fn read_prg(&mut self, address: u16) -> u8;
-    fn write_prg(&mut self, address: u16, value: u8);
-    fn read_chr(&mut self, address: u16) -> u8;
-    fn write_chr(&mut self, address: u16, value: u8);
-}
-
 /// NoMapper - simplest mapper
 #[derive(Debug)]
 pub struct NoMapper {
     prg_banks: Vec<u8>,
     chr_banks: Vec<u8>,
+    /// 8KB CHR RAM in `chr_banks` (the header has no CHR ROM)
+    chr_ram: bool,
+    mirroring: Mirroring,
 }
 
 impl NoMapper {
@@ -270,8 +268,25 @@ impl NoMapper {
         Self {
             prg_banks: Vec::new(),
             chr_banks: Vec::new(),
+            chr_ram: false,
+            mirroring: Mirroring::Horizontal,
         }
     }
+
+    /// Create the mapper with a ROM's PRG and CHR
+    pub fn from_rom(rom: &Rom) -> Self {
+        let mut mapper = Self::new();
+        mapper.load_rom(rom);
+        mapper
+    }
+
+    /// Take the PRG and CHR (and the wired mirroring) from a ROM
+    pub fn load_rom(&mut self, rom: &Rom) {
+        self.prg_banks = rom.prg_rom.clone();
+        self.chr_ram = rom.chr_rom.is_empty();
+        self.chr_banks = if self.chr_ram { vec![0; 0x2000] } else { rom.chr_rom.clone() };
+        self.mirroring = rom.header.nametable_mirroring();
+    }
 }
 
 impl Default for NoMapper {
@@ -289,29 +304,12 @@ impl MapperInterface for NoMapper {
 
     fn write_low(&mut self, _address: u16, _value: u8) {}
 
-    fn load_rom(&mut self, rom: &Rom) {
-        self.prg_banks = rom.prg_rom.clone();
-        self.chr_banks = rom.chr_rom.clone();
-    }
-
     fn read_prg(&mut self, address: u16) -> u8 {
-        let addr = address as usize;
-
-        // Map PRG-ROM to $8000-$FFFF
-        if addr >= 0x8000 {
-            let offset = addr - 0x8000;
-            if offset < self.prg_banks.len() {
-                return self.prg_banks[offset];
-            }
-            // Mirror the last bank for addresses beyond PRG-ROM size
-            let bank_size = self.prg_banks.len();
-            let mirrored_offset = offset % bank_size;
-            if mirrored_offset < self.prg_banks.len() {
-                return self.prg_banks[mirrored_offset];
-            }
+        if address < 0x8000 || self.prg_banks.is_empty() {
+            return 0;
         }
-
-        0
+        // 16KB PRG ROM is mirrored at $C000
+        self.prg_banks[(address as usize - 0x8000) % self.prg_banks.len()]
     }
 
     fn write_prg(&mut self, _address: u16, _value: u8) {}
@@ -324,29 +322,101 @@ impl MapperInterface for NoMapper {
         0
     }
 
-    fn write_chr(&mut self, _address: u16, _value: u8) {}
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let addr = address as usize;
+        if self.chr_ram && addr < self.chr_banks.len() {
+            self.chr_banks[addr] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
 }
 
 /// MMC1 Mapper
+///
+/// Registers are loaded through a 5-bit serial port: each write to
+/// $8000-$FFFF shifts bit 0 in (LSB first), and the fifth write copies the
+/// value into the register picked by address bits 13-14. A write with bit 7
+/// set clears the shift register and selects PRG mode 3.
 #[derive(Debug)]
 pub struct MMC1 {
     shift_register: u8,
+    /// Bits shifted in since the last register load
+    shift_count: u8,
+    /// `CPPMM`: CHR mode, PRG mode, mirroring
     control: u8,
     chr_bank0: u8,
     chr_bank1: u8,
     prg_bank: u8,
+    prg_banks: Vec<u8>,
+    chr_banks: Vec<u8>,
+    /// CHR is 8KB of RAM (the ROM has no CHR ROM)
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
 }
 
 impl MMC1 {
     pub fn new() -> Self {
         Self {
-            shift_register: 0x80,  // Reset state
-            control: 0,
+            shift_register: 0,
+            shift_count: 0,
+            control: 0x0C,  // PRG mode 3: last bank fixed at $C000
             chr_bank0: 0,
             chr_bank1: 0,
             prg_bank: 0,
+            prg_banks: Vec::new(),
+            chr_banks: Vec::new(),
+            chr_ram: false,
+            prg_ram: vec![0; 0x2000],
         }
     }
+
+    /// Create the mapper with a ROM's PRG and CHR
+    pub fn from_rom(rom: &Rom) -> Self {
+        let mut mapper = Self::new();
+        mapper.load_rom(rom);
+        mapper
+    }
+
+    /// Take the PRG and CHR from a ROM (the control register picks the mirroring)
+    pub fn load_rom(&mut self, rom: &Rom) {
+        self.prg_banks = rom.prg_rom.clone();
+        self.chr_ram = rom.chr_rom.is_empty();
+        self.chr_banks = if self.chr_ram { vec![0; 0x2000] } else { rom.chr_rom.clone() };
+    }
+
+    /// Offset into PRG ROM for a CPU address in $8000-$FFFF
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = (self.prg_banks.len() / 0x4000).max(1);
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let slot = (address as usize - 0x8000) / 0x4000;
+        let bank_16k = match ((self.control >> 2) & 0x03, slot) {
+            // 32KB mode ignores the low bit of the bank number
+            (0 | 1, _) => (bank & !1) + slot,
+            (2, 0) => 0,
+            (2, _) => bank,
+            (_, 0) => bank,
+            (_, _) => bank_count - 1,
+        };
+        (bank_16k % bank_count) * 0x4000 + (address as usize & 0x3FFF)
+    }
+
+    /// Offset into CHR for a PPU address in $0000-$1FFF
+    fn chr_offset(&self, address: u16) -> usize {
+        let address = address as usize & 0x1FFF;
+        let bank_4k = if self.control & 0x10 == 0 {
+            // 8KB mode ignores the low bit of the bank number
+            (self.chr_bank0 & 0x1E) as usize + address / 0x1000
+        } else if address < 0x1000 {
+            self.chr_bank0 as usize
+        } else {
+            self.chr_bank1 as usize
+        };
+        let bank_count = (self.chr_banks.len() / 0x1000).max(1);
+        (bank_4k % bank_count) * 0x1000 + (address & 0x0FFF)
+    }
 }
 
 impl Default for MMC1 {
@@ -357,51 +427,75 @@ impl Default for MMC1 {
 
 impl MapperInterface for MMC1 {
     fn reset(&mut self) {
-        self.shift_register = 0x80;
+        self.shift_register = 0;
+        self.shift_count = 0;
+        self.control |= 0x0C;
     }
 
-    fn read_low(&mut self, _address: u16) -> u8 {
-        0
+    fn read_low(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram[address as usize - 0x6000],
+            _ => 0,
+        }
     }
 
     fn write_low(&mut self, address: u16, value: u8) {
-        // MMC1 uses $8000-$FFFF for all register writes
-        if address < 0x8000 {
-            return;
+        if address >= 0x6000 {
+            self.prg_ram[address as usize - 0x6000] = value;
         }
+    }
 
-        // Write to shift register
-        let write_enable = (value & 0x80) == 0;
-        self.shift_register = (self.shift_register >> 1) | (value & 0x80);
-
-        if write_enable && (self.shift_register & 0x10) != 0 {
-            // Register write complete
-            let reg = (address >> 13) & 0x03;
-            self.shift_register = 0x80;
-
-            match reg {
-                0 => self.control = self.shift_register,
-                1 => self.chr_bank0 = self.shift_register,
-                2 => self.chr_bank1 = self.shift_register,
-                3 => self.prg_bank = self.shift_register,
-                _ => {}
-            }
+    fn read_prg(&mut self, address: u16) -> u8 {
+        if address < 0x8000 || self.prg_banks.is_empty() {
+            return 0;
         }
+        self.prg_banks[self.prg_offset(address)]
     }
 
-    fn load_rom(&mut self, _rom: &Rom) {}
-
-    fn read_prg(&mut self, _address: u16) -> u8 {
-        0
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if value & 0x80 != 0 {
+            self.reset();
+            return;
+        }
+        self.shift_register |= (value & 0x01) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count < 5 {
+            return;
+        }
+        let register = self.shift_register;
+        self.shift_register = 0;
+        self.shift_count = 0;
+        match (address >> 13) & 0x03 {
+            0 => self.control = register,
+            1 => self.chr_bank0 = register,
+            2 => self.chr_bank1 = register,
+            _ => self.prg_bank = register,
+        }
     }
 
-    fn write_prg(&mut self, _address: u16, _value: u8) {}
+    fn read_chr(&mut self, address: u16) -> u8 {
+        if self.chr_banks.is_empty() {
+            return 0;
+        }
+        self.chr_banks[self.chr_offset(address)]
+    }
 
-    fn read_chr(&mut self, _address: u16) -> u8 {
-        0
+    fn write_chr(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr_banks[offset] = value;
+        }
     }
 
-    fn write_chr(&mut self, _address: u16, _value: u8) {}
+    /// Nametable mirroring selected by the control register
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
 }
 
 /// UNROM Mapper
@@ -409,7 +503,10 @@ impl MapperInterface for MMC1 {
 pub struct UNROM {
     prg_banks: Vec<u8>,
     chr_banks: Vec<u8>,
+    /// 8KB CHR RAM in `chr_banks` (the header has no CHR ROM)
+    chr_ram: bool,
     current_prg_bank: usize,
+    mirroring: Mirroring,
 }
 
 impl UNROM {
@@ -417,9 +514,26 @@ impl UNROM {
         Self {
             prg_banks: Vec::new(),
             chr_banks: Vec::new(),
+            chr_ram: false,
             current_prg_bank: 0,
+            mirroring: Mirroring::Horizontal,
         }
     }
+
+    /// Create the mapper with a ROM's PRG and CHR
+    pub fn from_rom(rom: &Rom) -> Self {
+        let mut mapper = Self::new();
+        mapper.load_rom(rom);
+        mapper
+    }
+
+    /// Take the PRG and CHR (and the wired mirroring) from a ROM
+    pub fn load_rom(&mut self, rom: &Rom) {
+        self.prg_banks = rom.prg_rom.clone();
+        self.chr_ram = rom.chr_rom.is_empty();
+        self.chr_banks = if self.chr_ram { vec![0; 0x2000] } else { rom.chr_rom.clone() };
+        self.mirroring = rom.header.nametable_mirroring();
+    }
 }
 
 impl Default for UNROM {
@@ -435,39 +549,21 @@ impl MapperInterface for UNROM {
         0
     }
 
-    fn write_low(&mut self, address: u16, value: u8) {
-        if address >= 0x8000 && address < 0xA000 {
-            self.current_prg_bank = (value as usize) & 0x7F;
-        }
-    }
-
-    fn load_rom(&mut self, rom: &Rom) {
-        self.prg_banks = rom.prg_rom.clone();
-        self.chr_banks = rom.chr_rom.clone();
-    }
+    fn write_low(&mut self, _address: u16, _value: u8) {}
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        let addr = address as usize;
-        let bank_size = 0x8000;
-
-        if addr < bank_size {
-            // Fixed bank at $8000
-            let offset = addr;
-            if offset < self.prg_banks.len() {
-                return self.prg_banks[offset];
-            }
-        } else if addr >= 0x8000 {
-            // Switchable bank at $C000
-            let offset = (addr - 0x8000) + (self.current_prg_bank * bank_size);
-            if offset < self.prg_banks.len() {
-                return self.prg_banks[offset];
-            }
+        if address < 0x8000 || self.prg_banks.is_empty() {
+            return 0;
         }
-
-        0
+        // Switchable 16KB bank at $8000, last bank fixed at $C000
+        let bank_count = (self.prg_banks.len() / 0x4000).max(1);
+        let bank = if address < 0xC000 { self.current_prg_bank % bank_count } else { bank_count - 1 };
+        self.prg_banks[bank * 0x4000 + (address as usize & 0x3FFF)]
     }
 
-    fn write_prg(&mut self, _address: u16, _value: u8) {}
+    fn write_prg(&mut self, _address: u16, value: u8) {
+        self.current_prg_bank = (value as usize) & 0x7F;
+    }
 
     fn read_chr(&mut self, address: u16) -> u8 {
         let addr = address as usize;
@@ -477,7 +573,16 @@ impl MapperInterface for UNROM {
         0
     }
 
-    fn write_chr(&mut self, _address: u16, _value: u8) {}
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let addr = address as usize;
+        if self.chr_ram && addr < self.chr_banks.len() {
+            self.chr_banks[addr] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
 }
 
 /// CNROM Mapper
@@ -485,7 +590,10 @@ impl MapperInterface for UNROM {
 pub struct CNROM {
     prg_banks: Vec<u8>,
     chr_banks: Vec<u8>,
+    /// 8KB CHR RAM in `chr_banks` (the header has no CHR ROM)
+    chr_ram: bool,
     current_chr_bank: usize,
+    mirroring: Mirroring,
 }
 
 impl CNROM {
@@ -493,9 +601,26 @@ impl CNROM {
         Self {
             prg_banks: Vec::new(),
             chr_banks: Vec::new(),
+            chr_ram: false,
             current_chr_bank: 0,
+            mirroring: Mirroring::Horizontal,
         }
     }
+
+    /// Create the mapper with a ROM's PRG and CHR
+    pub fn from_rom(rom: &Rom) -> Self {
+        let mut mapper = Self::new();
+        mapper.load_rom(rom);
+        mapper
+    }
+
+    /// Take the PRG and CHR (and the wired mirroring) from a ROM
+    pub fn load_rom(&mut self, rom: &Rom) {
+        self.prg_banks = rom.prg_rom.clone();
+        self.chr_ram = rom.chr_rom.is_empty();
+        self.chr_banks = if self.chr_ram { vec![0; 0x2000] } else { rom.chr_rom.clone() };
+        self.mirroring = rom.header.nametable_mirroring();
+    }
 }
 
 impl Default for CNROM {
@@ -511,31 +636,19 @@ impl MapperInterface for CNROM {
         0
     }
 
-    fn write_low(&mut self, address: u16, value: u8) {
-        if address >= 0x8000 {
-            self.current_chr_bank = (value as usize) & 0x03;
-        }
-    }
-
-    fn load_rom(&mut self, rom: &Rom) {
-        self.prg_banks = rom.prg_rom.clone();
-        self.chr_banks = rom.chr_rom.clone();
-    }
+    fn write_low(&mut self, _address: u16, _value: u8) {}
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        let addr = address as usize;
-
-        if addr >= 0x8000 {
-            let offset = addr - 0x8000;
-            if offset < self.prg_banks.len() {
-                return self.prg_banks[offset];
-            }
+        if address < 0x8000 || self.prg_banks.is_empty() {
+            return 0;
         }
-
-        0
+        // 16KB PRG ROM is mirrored at $C000
+        self.prg_banks[(address as usize - 0x8000) % self.prg_banks.len()]
     }
 
-    fn write_prg(&mut self, _address: u16, _value: u8) {}
+    fn write_prg(&mut self, _address: u16, value: u8) {
+        self.current_chr_bank = (value as usize) & 0x03;
+    }
 
     fn read_chr(&mut self, address: u16) -> u8 {
         let addr = address as usize;
@@ -548,17 +661,44 @@ impl MapperInterface for CNROM {
         0
     }
 
-    fn write_chr(&mut self, _address: u16, _value: u8) {}
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let addr = address as usize & 0x1FFF;
+        if self.chr_ram {
+            self.chr_banks[addr] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
 }
 
 /// MMC3 Mapper
+///
+/// Registers are decoded from address bits 0, 13, 14 and 15, so each one is
+/// mirrored across its 8KB range. The scanline counter reloads from the
+/// latch when it is 0 (or after a $C001 write) and otherwise counts down on
+/// each PPU A12 rising edge; reaching 0 raises IRQ if $E001 enabled it.
 #[derive(Debug)]
 pub struct MMC3 {
     prg_banks: Vec<u8>,
     chr_banks: Vec<u8>,
+    /// Bank select ($8000): register for $8001 in bits 0-2, PRG mode in bit 6
     command: u8,
+    /// R6 and R7: 8KB PRG banks
     prg_banks_select: [u8; 2],
+    /// R0-R5: two 2KB and four 1KB CHR banks
     chr_banks_select: [u8; 6],
+    /// 8KB CHR RAM in `chr_banks` (the header has no CHR ROM)
+    chr_ram: bool,
+    mirroring: Mirroring,
+    /// Four-screen boards ignore $A000
+    four_screen: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
 }
 
 impl MMC3 {
@@ -568,9 +708,52 @@ impl MMC3 {
             chr_banks: Vec::new(),
             command: 0,
             prg_banks_select: [0, 1],
-            chr_banks_select: [0, 1, 2, 3, 4, 5],
+            chr_banks_select: [0, 2, 4, 5, 6, 7],
+            chr_ram: false,
+            mirroring: Mirroring::Horizontal,
+            four_screen: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
         }
     }
+
+    /// Create the mapper with a ROM's PRG and CHR
+    pub fn from_rom(rom: &Rom) -> Self {
+        let mut mapper = Self::new();
+        mapper.load_rom(rom);
+        mapper
+    }
+
+    /// Take the PRG and CHR (and the wired mirroring) from a ROM
+    pub fn load_rom(&mut self, rom: &Rom) {
+        self.prg_banks = rom.prg_rom.clone();
+        self.chr_ram = rom.chr_rom.is_empty();
+        self.chr_banks = if self.chr_ram { vec![0; 0x2000] } else { rom.chr_rom.clone() };
+        self.mirroring = rom.header.nametable_mirroring();
+        self.four_screen = rom.header.four_screen;
+    }
+
+    /// Offset into CHR for a PPU address in $0000-$1FFF
+    fn chr_offset(&self, address: u16) -> usize {
+        let address = address as usize & 0x1FFF;
+        // Bit 7 of the bank select swaps the 2KB and 1KB halves
+        let mut slot = address / 0x400;
+        if self.command & 0x80 != 0 {
+            slot ^= 4;
+        }
+        let bank_1k = match slot {
+            0 => self.chr_banks_select[0] & 0xFE,
+            1 => self.chr_banks_select[0] | 1,
+            2 => self.chr_banks_select[1] & 0xFE,
+            3 => self.chr_banks_select[1] | 1,
+            _ => self.chr_banks_select[slot - 2],
+        } as usize;
+        let bank_count = (self.chr_banks.len() / 0x400).max(1);
+        (bank_1k % bank_count) * 0x400 + (address & 0x03FF)
+    }
 }
 
 impl Default for MMC3 {
@@ -582,107 +765,327 @@ impl Default for MMC3 {
 impl MapperInterface for MMC3 {
     fn reset(&mut self) {
         self.command = 0;
+        self.irq_enabled = false;
+        self.irq_pending = false;
     }
 
     fn read_low(&mut self, _address: u16) -> u8 {
         0
     }
 
-    fn write_low(&mut self, address: u16, value: u8) {
-        match address {
+    fn write_low(&mut self, _address: u16, _value: u8) {}
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address & 0xE001 {
             0x8000 => {
                 self.command = value;
             }
             0x8001 => {
                 // Write to selected register based on command
                 match self.command & 0x07 {
-                    0 => {
-                        // Two 1KB VROM banks at $0000+$0400
-                        self.chr_banks_select[0] = value & 0xFE;
-                        self.chr_banks_select[1] = (value & 0xFE) | 1;
-                    }
-                    1 => {
-                        // Two 1KB VROM banks at $0800+$0C00
-                        self.chr_banks_select[2] = value & 0xFE;
-                        self.chr_banks_select[3] = (value & 0xFE) | 1;
-                    }
-                    2 => {
-                        // One 1KB VROM at $1000
-                        self.chr_banks_select[4] = value;
-                    }
-                    3 => {
-                        // One 1KB VROM at $1400
-                        self.chr_banks_select[5] = value;
-                    }
-                    4 => {
-                        // One 1KB VROM at $1800
-                        self.chr_banks_select[4] = value;
-                    }
-                    5 => {
-                        // One 1KB VROM at $1C00
-                        self.chr_banks_select[5] = value;
+                    register @ 0..=5 => {
+                        // R0-R1: 2KB VROM banks at $0000 and $0800, R2-R5:
+                        // 1KB banks at $1000-$1C00 (halves swapped by bit 7)
+                        self.chr_banks_select[register as usize] = value;
                     }
                     6 => {
-                        // PRG page 1 selection
-                        self.prg_banks_select[1] = value & 0x07;
+                        // 8KB PRG bank at $8000 (or $C000 in PRG mode 1)
+                        self.prg_banks_select[0] = value & 0x3F;
                     }
                     7 => {
-                        // PRG page 2 selection
-                        self.prg_banks_select[0] = value & 0x07;
+                        // 8KB PRG bank at $A000
+                        self.prg_banks_select[1] = value & 0x3F;
                     }
                     _ => {}
                 }
             }
+            0xA000 => {
+                if !self.four_screen {
+                    self.mirroring = if value & 0x01 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+                }
+            }
+            0xC000 => {
+                self.irq_latch = value;
+            }
+            0xC001 => {
+                // Reload from the latch on the next clock
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000 => {
+                // Disabling also acknowledges a pending IRQ
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE001 => {
+                self.irq_enabled = true;
+            }
             _ => {}
         }
     }
 
-    fn load_rom(&mut self, rom: &Rom) {
-        self.prg_banks = rom.prg_rom.clone();
-        self.chr_banks = rom.chr_rom.clone();
-    }
-
     fn read_prg(&mut self, address: u16) -> u8 {
-        let addr = address as usize;
+        if address < 0x8000 || self.prg_banks.is_empty() {
+            return 0;
+        }
+        // The last bank is fixed at $E000; PRG mode 1 swaps R6 at $8000
+        // with the fixed second-last bank at $C000
+        let bank_count = (self.prg_banks.len() / 0x2000).max(1);
+        let bank = match ((address - 0x8000) / 0x2000, self.command & 0x40 != 0) {
+            (0, false) | (2, true) => self.prg_banks_select[0] as usize,
+            (1, _) => self.prg_banks_select[1] as usize,
+            (3, _) => bank_count - 1,
+            _ => bank_count.saturating_sub(2),
+        };
+        self.prg_banks[(bank % bank_count) * 0x2000 + (address as usize & 0x1FFF)]
+    }
 
-        if addr < 0x8000 {
+    fn read_chr(&mut self, address: u16) -> u8 {
+        if self.chr_banks.is_empty() {
             return 0;
         }
+        self.chr_banks[self.chr_offset(address)]
+    }
 
-        let bank_size = 0x2000;
-        let offset = if addr < 0xC000 {
-            // Page 2 at $8000
-            (addr - 0x8000) + (self.prg_banks_select[0] as usize * bank_size)
+    fn write_chr(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr_banks[offset] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn ppu_a12_clock(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
         } else {
-            // Page 1 at $C000
-            (addr - 0xC000) + (self.prg_banks_select[1] as usize * bank_size)
-        };
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if offset < self.prg_banks.len() {
-            return self.prg_banks[offset];
+    /// MMC1 image with 8 PRG banks (16KB) and 4 CHR banks (4KB) filled with their number
+    fn mmc1_rom() -> Rom {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 8, 2, 0x10, 0x00];
+        data.resize(16, 0);
+        for bank in 0..8 {
+            data.extend(vec![bank; 0x4000]);
         }
-        0
+        for bank in 0..4 {
+            data.extend(vec![0x10 + bank; 0x1000]);
+        }
+        Rom::load_from_data(&data).unwrap()
     }
 
-    fn write_prg(&mut self, _address: u16, _value: u8) {}
+    /// Load an MMC1 register through the serial port
+    fn write_register(mapper: &mut MMC1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write_prg(address, (value >> bit) & 0x01);
+        }
+    }
 
-    fn read_chr(&mut self, address: u16) -> u8 {
-        let addr = address as usize;
-        let bank_size = 0x0400;
+    #[test]
+    fn test_mmc1_banking() {
+        let rom = mmc1_rom();
+        assert_eq!(rom.header.mapper, Mapper::MMC1);
+        let mut mapper = MMC1::from_rom(&rom);
+
+        // Power-on: PRG mode 3, last bank fixed at $C000
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xFFFF)), (0, 7));
+        write_register(&mut mapper, 0xE000, 5);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xC000)), (5, 7));
+
+        // PRG mode 2: first bank fixed at $8000, vertical mirroring
+        write_register(&mut mapper, 0x8000, 0b01010);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xC000)), (0, 5));
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        // 32KB mode drops the low bit; 8KB CHR mode likewise
+        write_register(&mut mapper, 0x8000, 0b00011);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xC000)), (4, 5));
+        write_register(&mut mapper, 0xA000, 3);
+        assert_eq!((mapper.read_chr(0x0000), mapper.read_chr(0x1000)), (0x12, 0x13));
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        // 4KB CHR mode switches the halves separately
+        write_register(&mut mapper, 0x8000, 0b10000);
+        write_register(&mut mapper, 0xC000, 0);
+        assert_eq!((mapper.read_chr(0x0000), mapper.read_chr(0x1000)), (0x13, 0x10));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+    }
 
-        let offset = if addr < 0x1000 {
-            // Pattern table 0
-            (addr % bank_size) + (self.chr_banks_select[0] as usize * bank_size)
-        } else {
-            // Pattern table 1
-            (addr % bank_size) + (self.chr_banks_select[4] as usize * bank_size)
-        };
+    #[test]
+    fn test_mmc1_reset_write_and_ram() {
+        let mut mapper = MMC1::from_rom(&Rom { chr_rom: Vec::new(), ..mmc1_rom() });
+
+        // A write with bit 7 set abandons the partial load and restores PRG mode 3
+        write_register(&mut mapper, 0x8000, 0b00000);
+        mapper.write_prg(0xE000, 1);
+        mapper.write_prg(0xE000, 0x80);
+        write_register(&mut mapper, 0xE000, 2);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xC000)), (2, 7));
+
+        mapper.write_low(0x6123, 0x42);
+        assert_eq!(mapper.read_low(0x6123), 0x42);
+        mapper.write_chr(0x0100, 0x99);
+        assert_eq!(mapper.read_chr(0x0100), 0x99);
+    }
 
-        if offset < self.chr_banks.len() {
-            return self.chr_banks[offset];
+    /// MMC3 image with vertical mirroring in the header
+    fn mmc3_rom(flags6: u8) -> Rom {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 1, 0x40 | flags6, 0x00];
+        data.resize(16, 0);
+        data.resize(16 + 2 * 0x4000 + 0x2000, 0);
+        Rom::load_from_data(&data).unwrap()
+    }
+
+    #[test]
+    fn test_mirroring_from_header_and_mmc3() {
+        let mut mapper = create_mapper(&mmc3_rom(0x01));
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        // $A000 is mirrored on even addresses up to $BFFE
+        mapper.write_prg(0xBFFE, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.write_prg(0xA000, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        // Four-screen boards can't switch
+        let mut mapper = create_mapper(&mmc3_rom(0x08));
+        mapper.write_prg(0xA000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::FourScreen);
+
+        let nrom = NoMapper::from_rom(&Rom { header: RomHeader { mirroring: Mirroring::Vertical, ..mmc3_rom(0).header }, ..mmc3_rom(0) });
+        assert_eq!(nrom.mirroring(), Mirroring::Vertical);
+        assert!(!nrom.irq_pending());
+    }
+
+    /// Image for `mapper` with `prg_16k` PRG banks, each 8KB filled with its number
+    fn numbered_prg_rom(mapper: u8, prg_16k: u8) -> Rom {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, prg_16k, 1, mapper << 4, 0x00];
+        data.resize(16, 0);
+        for bank in 0..prg_16k * 2 {
+            data.extend(vec![bank; 0x2000]);
         }
-        0
+        data.resize(data.len() + 0x2000, 0);
+        Rom::load_from_data(&data).unwrap()
     }
 
-    fn write_chr(&mut self, _address: u16, _value: u8) {}
-}
\ No newline at end of file
+    /// Bytes at $8000, $A000, $C000 and $E000
+    fn prg_windows(mapper: &mut Box<dyn MapperInterface>) -> [u8; 4] {
+        [0x8000, 0xA000, 0xC000, 0xE000].map(|address| mapper.read_prg(address))
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        // NROM-128 is mirrored at $C000
+        let mut nrom = create_mapper(&numbered_prg_rom(0, 1));
+        assert_eq!(prg_windows(&mut nrom), [0, 1, 0, 1]);
+
+        // UNROM: any write to $8000-$FFFF switches $8000, the last bank stays at $C000
+        let mut unrom = create_mapper(&numbered_prg_rom(2, 4));
+        assert_eq!(prg_windows(&mut unrom), [0, 1, 6, 7]);
+        unrom.write_prg(0xFFFF, 2);
+        assert_eq!(prg_windows(&mut unrom), [4, 5, 6, 7]);
+
+        // MMC3 keeps the last two banks at $C000 and $E000, so the vectors are there at power-on
+        let mut mmc3 = create_mapper(&numbered_prg_rom(4, 4));
+        mmc3.write_prg(0x8000, 6);
+        mmc3.write_prg(0x8001, 3);
+        mmc3.write_prg(0x8000, 7);
+        mmc3.write_prg(0x8001, 4);
+        assert_eq!(prg_windows(&mut mmc3), [3, 4, 6, 7]);
+        // PRG mode 1 swaps $8000 and $C000
+        mmc3.write_prg(0x8000, 0x40);
+        assert_eq!(prg_windows(&mut mmc3), [6, 4, 3, 7]);
+
+        // Without a ROM there is nothing to read
+        assert_eq!(NoMapper::new().read_prg(0xFFFC), 0);
+    }
+
+    #[test]
+    fn test_mmc3_chr_banking() {
+        // 32KB of CHR ROM, each 1KB bank filled with its number
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 4, 0x40, 0x00];
+        data.resize(16 + 2 * 0x4000, 0);
+        for bank in 0..32 {
+            data.extend(vec![bank; 0x400]);
+        }
+        let mut mapper = create_mapper(&Rom::load_from_data(&data).unwrap());
+        let windows = |mapper: &mut Box<dyn MapperInterface>| (0..8).map(|slot| mapper.read_chr(slot * 0x400)).collect::<Vec<_>>();
+        assert_eq!(windows(&mut mapper), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // R0-R1 ignore the low bit and map 2KB, R2-R5 map 1KB each
+        for (register, bank) in [(0, 9), (1, 12), (2, 20), (3, 21), (4, 30), (5, 31)] {
+            mapper.write_prg(0x8000, register);
+            mapper.write_prg(0x8001, bank);
+        }
+        assert_eq!(windows(&mut mapper), [8, 9, 12, 13, 20, 21, 30, 31]);
+
+        // Bit 7 swaps the halves
+        mapper.write_prg(0x8000, 0x80);
+        assert_eq!(windows(&mut mapper), [20, 21, 30, 31, 8, 9, 12, 13]);
+    }
+
+    #[test]
+    fn test_chr_ram_without_chr_rom() {
+        for mapper_type in [Mapper::NoMapper, Mapper::UNROM, Mapper::CNROM, Mapper::MMC3] {
+            let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 0, 0x00, 0x00];
+            data.resize(16 + 2 * 0x4000, 0);
+            let mut rom = Rom::load_from_data(&data).unwrap();
+            rom.header.mapper = mapper_type;
+            let mut mapper = create_mapper(&rom);
+            mapper.write_chr(0x1FFF, 0x5A);
+            assert_eq!(mapper.read_chr(0x1FFF), 0x5A, "{:?}", mapper_type);
+        }
+
+        // CHR ROM stays read-only
+        let mut mapper = NoMapper::from_rom(&mmc3_rom(0));
+        mapper.write_chr(0x0000, 0x5A);
+        assert_eq!(mapper.read_chr(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_mmc3_irq_counter() {
+        let mut mapper = MMC3::from_rom(&mmc3_rom(0));
+        mapper.write_prg(0xC000, 2);
+        mapper.write_prg(0xC001, 0);
+        mapper.write_prg(0xE001, 0);
+
+        // Reload to 2, then 1, then 0 raises IRQ
+        mapper.ppu_a12_clock();
+        mapper.ppu_a12_clock();
+        assert!(!mapper.irq_pending());
+        mapper.ppu_a12_clock();
+        assert!(mapper.irq_pending());
+
+        // $E000 acknowledges and disables; the counter keeps running
+        mapper.write_prg(0xE000, 0);
+        assert!(!mapper.irq_pending());
+        for _ in 0..3 {
+            mapper.ppu_a12_clock();
+        }
+        assert!(!mapper.irq_pending());
+
+        // A latch of 0 fires on every clock once enabled
+        mapper.write_prg(0xC000, 0);
+        mapper.write_prg(0xC001, 0);
+        mapper.write_prg(0xE001, 0);
+        mapper.ppu_a12_clock();
+        assert!(mapper.irq_pending());
+    }
+}
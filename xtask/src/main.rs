@@ -0,0 +1,123 @@
+//! Workspace maintenance tasks, run with `cargo xtask <task>`
+//!
+//! `matrix` builds and smoke-tests nes-core in each configuration it ships
+//! in, so a change that only works in one of them is caught:
+//!
+//! - std: the native test suite's smoke ROM and the public API checks
+//!   (`nes-core/tests/feature_matrix.rs`) plus the same ROM from nes-wasm's
+//!   tests;
+//! - wasm32: nes-core and nes-wasm built for `wasm32-unknown-unknown`, and
+//!   the smoke ROM run in a JS engine when `wasm-bindgen-test-runner` is
+//!   installed (`cargo install wasm-bindgen-cli`).
+//!
+//! There is no no_std configuration: nes-core always builds with std, so
+//! one can only be added once a `std` feature gates the frontend helpers.
+//!
+//! Configurations whose toolchain is missing are reported as skipped rather
+//! than failed; the task fails if any configuration that ran failed.
+
+use std::env;
+use std::process::{Command, ExitCode};
+
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+const WASM_RUNNER: &str = "wasm-bindgen-test-runner";
+
+/// Outcome of one configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+fn main() -> ExitCode {
+    let task = env::args().nth(1);
+    match task.as_deref() {
+        Some("matrix") => matrix(),
+        _ => {
+            eprintln!("usage: cargo xtask matrix");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn matrix() -> ExitCode {
+    let results = [("std", std_config()), ("wasm32", wasm_config())];
+    println!();
+    for (name, outcome) in &results {
+        match outcome {
+            Outcome::Passed => println!("{:20} passed", name),
+            Outcome::Failed(reason) => println!("{:20} FAILED: {}", name, reason),
+            Outcome::Skipped(reason) => println!("{:20} skipped: {}", name, reason),
+        }
+    }
+    if results.iter().any(|(_, outcome)| matches!(outcome, Outcome::Failed(_))) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn std_config() -> Outcome {
+    run_all(&[
+        &["test", "-p", "nes-core", "--test", "feature_matrix"],
+        &["test", "-p", "nes-wasm", "--test", "smoke"],
+    ])
+}
+
+fn wasm_config() -> Outcome {
+    if !target_installed(WASM_TARGET) {
+        return Outcome::Skipped(format!("target not installed (rustup target add {})", WASM_TARGET));
+    }
+    let built = run_all(&[&["build", "-p", "nes-core", "-p", "nes-wasm", "--target", WASM_TARGET]]);
+    if built != Outcome::Passed {
+        return built;
+    }
+    if !on_path(WASM_RUNNER) {
+        return Outcome::Skipped(format!("built; smoke ROM not run ({} not installed)", WASM_RUNNER));
+    }
+    let mut test = cargo(&["test", "-p", "nes-wasm", "--test", "smoke", "--target", WASM_TARGET]);
+    test.env("CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER", WASM_RUNNER);
+    run(test)
+}
+
+/// Cargo command with the given arguments, using the cargo running this task
+fn cargo(args: &[&str]) -> Command {
+    let mut command = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    command.args(args);
+    command
+}
+
+/// Run cargo commands in order, stopping at the first failure
+fn run_all(commands: &[&[&str]]) -> Outcome {
+    for args in commands {
+        let outcome = run(cargo(args));
+        if outcome != Outcome::Passed {
+            return outcome;
+        }
+    }
+    Outcome::Passed
+}
+
+fn run(mut command: Command) -> Outcome {
+    let line = format!("{:?}", command).replace('"', "");
+    println!("$ {}", line);
+    match command.status() {
+        Ok(status) if status.success() => Outcome::Passed,
+        Ok(status) => Outcome::Failed(format!("`{}` exited with {}", line, status)),
+        Err(error) => Outcome::Failed(format!("`{}` didn't start: {}", line, error)),
+    }
+}
+
+/// Check if rustup has the standard library for a target
+fn target_installed(target: &str) -> bool {
+    Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).lines().any(|line| line.trim() == target))
+}
+
+/// Check if a program is on the PATH
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}